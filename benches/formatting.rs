@@ -0,0 +1,75 @@
+//! Compares writing records through the text formatter with the
+//! `to_string` and `join` formatting it replaced, counting heap allocations
+//! along with the time.
+//!
+//! Run with `cargo bench --bench formatting`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use multiple_of_a_and_b::formatter::FormatterRegistry;
+use multiple_of_a_and_b::{generate_record, LineNumbers, ResultRecord};
+
+/// The system allocator, counting allocations.
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn records() -> Vec<ResultRecord> {
+    (0..8).map(|i| generate_record(LineNumbers::new(2 + i, 3 + i, 4_000_000).unwrap())).collect()
+}
+
+fn joined(w: &mut dyn Write, record: &ResultRecord) -> io::Result<()> {
+    let numbers: Vec<String> = record.result.numbers.iter().map(|n| n.to_string()).collect();
+    writeln!(w, "{}:{}", record.result.end, numbers.join(" "))
+}
+
+fn run(name: &str, records: &[ResultRecord], write: &mut dyn FnMut(&mut dyn Write, &ResultRecord)) {
+    let mut out = BufWriter::new(io::sink());
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for record in records {
+        write(&mut out, record);
+    }
+    out.flush().unwrap();
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!("{:<10} {:>8.2?} {:>10} allocations", name, elapsed, allocations);
+}
+
+fn main() {
+    let records = records();
+    let mut formatter = FormatterRegistry::with_builtins().create("text").unwrap();
+    let (mut expected, mut written) = (Vec::new(), Vec::new());
+    for record in &records {
+        joined(&mut expected, record).unwrap();
+        formatter.write_record(&mut written, record).unwrap();
+    }
+    assert!(expected == written);
+
+    for _ in 0..3 {
+        run("join", &records, &mut |w, record| joined(w, record).unwrap());
+        run("formatter", &records, &mut |w, record| formatter.write_record(w, record).unwrap());
+    }
+}
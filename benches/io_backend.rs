@@ -0,0 +1,39 @@
+//! Compares the buffered and memory-mapped output backends.
+//!
+//! Run with `cargo bench --bench io_backend`.
+
+use std::time::Instant;
+
+use multiple_of_a_and_b::formatter::FormatterRegistry;
+use multiple_of_a_and_b::output::{LazyFile, MmapSink};
+use multiple_of_a_and_b::pipeline::{jobs_source, FormatterSink, Pipeline, Sink};
+use multiple_of_a_and_b::LineNumbers;
+
+fn jobs() -> Vec<LineNumbers> {
+    (0..8).map(|i| LineNumbers::new(2 + i, 3 + i, 4_000_000).unwrap()).collect()
+}
+
+fn run(name: &str, sink: &mut dyn Sink) {
+    let start = Instant::now();
+    Pipeline::builder(jobs_source(jobs())).build().run(sink).unwrap();
+    println!("{:<10} {:>8.2?}", name, start.elapsed());
+}
+
+fn main() {
+    let dir = std::env::temp_dir();
+    let buffered_path = dir.join("multiples_bench_buffered.txt");
+    let mmap_path = dir.join("multiples_bench_mmap.txt");
+
+    for _ in 0..3 {
+        let formatter = FormatterRegistry::with_builtins().create("text").unwrap();
+        run("buffered", &mut FormatterSink::new(LazyFile::new(&buffered_path), formatter));
+        run(
+            "mmap",
+            &mut MmapSink::new(&mmap_path, || FormatterRegistry::with_builtins().create("text")),
+        );
+    }
+
+    assert_eq!(std::fs::read(&buffered_path).unwrap(), std::fs::read(&mmap_path).unwrap());
+    std::fs::remove_file(buffered_path).unwrap();
+    std::fs::remove_file(mmap_path).unwrap();
+}
@@ -0,0 +1,25 @@
+//! Passes the git commit the tool is built from to the build as
+//! `MULTIPLES_GIT_HASH`, for the provenance of its outputs: the hash of
+//! `HEAD`, with `-dirty` when tracked files have changes, or nothing
+//! outside of a git checkout.
+
+use std::process::Command;
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn main() {
+    let hash = match git(&["rev-parse", "HEAD"]) {
+        Some(hash) => match git(&["status", "--porcelain", "--untracked-files=no"]) {
+            Some(changes) if !changes.is_empty() => format!("{}-dirty", hash),
+            _ => hash,
+        },
+        None => String::new(),
+    };
+    println!("cargo:rustc-env=MULTIPLES_GIT_HASH={}", hash);
+    for path in [".git/HEAD", ".git/refs", ".git/index", "src"] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+}
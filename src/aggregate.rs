@@ -0,0 +1,250 @@
+//! The `--aggregate` report: one summary of a whole run instead of a
+//! record per line.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::output::RecordOutput;
+use crate::pipeline::Sink;
+use crate::quantity::format_count;
+use crate::{LineNumbers, ResultRecord};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AggregateFormat {
+    /// Aligned, human readable lines.
+    Text,
+    /// A single JSON object.
+    Json,
+}
+
+impl FromStr for AggregateFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "text" => Ok(AggregateFormat::Text),
+            "json" => Ok(AggregateFormat::Json),
+            _ => Err(anyhow!("--aggregate writes text or json, not {}", name)),
+        }
+    }
+}
+
+/// The number of multiples of one line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineCount {
+    pub index: usize,
+    pub job: LineNumbers,
+    pub count: u64,
+}
+
+/// Totals over every record of a run, built up one record at a time.
+///
+/// Counts-only records carry no multiples, so once one is added the
+/// smallest, largest and sum of the multiples and the counts per divisor
+/// are no longer known and reported as missing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aggregate {
+    pub lines: Vec<LineCount>,
+    pub total: u64,
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+    pub sum: u128,
+    /// How many of the listed multiples each divisor divides, over all
+    /// lines with that divisor.
+    pub per_divisor: BTreeMap<u32, u64>,
+    /// Whether every record listed its multiples.
+    pub listed: bool,
+}
+
+impl Default for Aggregate {
+    fn default() -> Self {
+        Aggregate {
+            lines: Vec::new(),
+            total: 0,
+            min: None,
+            max: None,
+            sum: 0,
+            per_divisor: BTreeMap::new(),
+            listed: true,
+        }
+    }
+}
+
+impl Aggregate {
+    pub fn add(&mut self, record: &ResultRecord) {
+        let count = record.result.count();
+        self.lines.push(LineCount {
+            index: record.index,
+            job: record.job,
+            count,
+        });
+        self.total += count;
+        if record.result.count.is_some() {
+            self.listed = false;
+            return;
+        }
+        let numbers = &record.result.numbers;
+        // Descending records list their largest multiple first.
+        let (first, last) = (numbers.first().copied(), numbers.last().copied());
+        for n in first.into_iter().chain(last) {
+            self.min = Some(self.min.map_or(n, |min| min.min(n)));
+            self.max = Some(self.max.map_or(n, |max| max.max(n)));
+        }
+        self.sum += numbers.iter().map(|&n| n as u128).sum::<u128>();
+        let job = record.job;
+        let divisors = if job.a == job.b { vec![job.a] } else { vec![job.a, job.b] };
+        for divisor in divisors.into_iter().filter(|&divisor| divisor != 0) {
+            let divides = numbers.iter().filter(|&&n| n % divisor == 0).count() as u64;
+            *self.per_divisor.entry(divisor).or_default() += divides;
+        }
+    }
+
+    fn listed<T: ToString>(&self, value: Option<T>) -> Option<String> {
+        value.filter(|_| self.listed).map(|value| value.to_string())
+    }
+
+    fn lines_by_index(&self) -> Vec<LineCount> {
+        let mut lines = self.lines.clone();
+        lines.sort_by_key(|line| line.index);
+        lines
+    }
+
+    pub fn to_text(&self) -> String {
+        let missing = || String::from("-");
+        let mut text = String::new();
+        let _ = writeln!(text, "{:<12} {}", "lines", format_count(self.lines.len() as u64));
+        let _ = writeln!(text, "{:<12} {}", "multiples", format_count(self.total));
+        let _ = writeln!(text, "{:<12} {}", "smallest", self.listed(self.min.map(format_count)).unwrap_or_else(missing));
+        let _ = writeln!(text, "{:<12} {}", "largest", self.listed(self.max.map(format_count)).unwrap_or_else(missing));
+        let sum = self.listed(Some(format_count(self.sum)));
+        let _ = writeln!(text, "{:<12} {}", "sum", sum.unwrap_or_else(missing));
+        if self.listed {
+            let _ = writeln!(text, "\n{:<12} {:>13}", "divisor", "multiples");
+            for (divisor, count) in &self.per_divisor {
+                let _ = writeln!(text, "{:<12} {:>13}", divisor, format_count(*count));
+            }
+        }
+        let _ = writeln!(text, "\n{:<8} {:>10} {:>10} {:>10} {:>13}", "line", "a", "b", "end", "count");
+        for line in self.lines_by_index() {
+            let job = line.job;
+            let count = format_count(line.count);
+            let _ = writeln!(text, "{:<8} {:>10} {:>10} {:>10} {:>13}", line.index + 1, job.a, job.b, job.end, count);
+        }
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        let null = || String::from("null");
+        let divisors: Vec<String> = self
+            .per_divisor
+            .iter()
+            .map(|(divisor, count)| format!("\"{}\":{}", divisor, count))
+            .collect();
+        let lines: Vec<String> = self
+            .lines_by_index()
+            .iter()
+            .map(|line| {
+                let job = line.job;
+                format!(
+                    "{{\"line\":{},\"a\":{},\"b\":{},\"end\":{},\"count\":{}}}",
+                    line.index + 1,
+                    job.a,
+                    job.b,
+                    job.end,
+                    line.count
+                )
+            })
+            .collect();
+        format!(
+            "{{\"lines\":{},\"multiples\":{},\"min\":{},\"max\":{},\"sum\":{},\"per_divisor\":{},\"per_line\":[{}]}}\n",
+            self.lines.len(),
+            self.total,
+            self.listed(self.min).unwrap_or_else(null),
+            self.listed(self.max).unwrap_or_else(null),
+            self.listed(Some(self.sum)).unwrap_or_else(null),
+            if self.listed { format!("{{{}}}", divisors.join(",")) } else { null() },
+            lines.join(",")
+        )
+    }
+}
+
+/// Adds up the records of a run and writes the [`Aggregate`] once they are
+/// all in.
+pub struct AggregateSink<W: RecordOutput> {
+    out: W,
+    format: AggregateFormat,
+    aggregate: Aggregate,
+}
+
+impl<W: RecordOutput> AggregateSink<W> {
+    pub fn new(out: W, format: AggregateFormat) -> Self {
+        AggregateSink {
+            out,
+            format,
+            aggregate: Aggregate::default(),
+        }
+    }
+}
+
+impl<W: RecordOutput> Sink for AggregateSink<W> {
+    fn write(&mut self, record: ResultRecord) -> Result<()> {
+        self.aggregate.add(&record);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let report = match self.format {
+            AggregateFormat::Text => self.aggregate.to_text(),
+            AggregateFormat::Json => self.aggregate.to_json(),
+        };
+        self.out.write_all(report.as_bytes()).context("Failed to write the aggregate")?;
+        self.out.close().context("Failed to flush output buffer")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Aggregate, AggregateFormat, AggregateSink};
+    use crate::json;
+    use crate::pipeline::{jobs_source, Pipeline};
+    use crate::{count_record, formula::Mode, LineNumbers};
+
+    fn jobs() -> Vec<LineNumbers> {
+        vec![LineNumbers { a: 3, b: 5, end: 20 }, LineNumbers { a: 2, b: 3, end: 6 }, LineNumbers { a: 4, b: 4, end: 3 }]
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut out = Vec::new();
+        let mut sink = AggregateSink::new(&mut out, AggregateFormat::Json);
+        Pipeline::builder(jobs_source(jobs())).build().run(&mut sink).unwrap();
+        let report = json::parse(std::str::from_utf8(&out).unwrap()).unwrap();
+        let field = |name: &str| report.get(name).and_then(|value| value.as_text()).map(String::from);
+        assert_eq!(field("lines").as_deref(), Some("3"));
+        // 3 5 6 9 10 12 15 18 20 and 2 3 4 6.
+        assert_eq!(field("multiples").as_deref(), Some("13"));
+        assert_eq!(field("min").as_deref(), Some("2"));
+        assert_eq!(field("max").as_deref(), Some("20"));
+        assert_eq!(field("sum").as_deref(), Some("113"));
+        let per_divisor = report.get("per_divisor").unwrap();
+        let divides = |divisor: &str| per_divisor.get(divisor).and_then(|value| value.as_text()).map(String::from);
+        assert_eq!(divides("3").as_deref(), Some("8"));
+        assert_eq!(divides("5").as_deref(), Some("4"));
+        assert_eq!(divides("4").as_deref(), Some("0"));
+        assert_eq!(report.get("per_line").and_then(|lines| lines.as_array()).map(|lines| lines.len()), Some(3));
+
+        let mut counted = Aggregate::default();
+        for job in jobs() {
+            counted.add(&count_record(job, Mode::Or));
+        }
+        assert_eq!(counted.total, 13);
+        let text = counted.to_text();
+        assert!(text.contains("smallest     -\n"), "{}", text);
+        assert!(!text.contains("divisor"), "{}", text);
+        assert!(counted.to_json().contains("\"per_divisor\":null"));
+        assert!("csv".parse::<AggregateFormat>().is_err());
+    }
+}
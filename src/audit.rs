@@ -0,0 +1,135 @@
+//! The I/O audit behind `--pure`: every file a run reads or writes is
+//! opened through this module, which notes the access and, once
+//! [`enforce`] is called, refuses any path that wasn't [allowed](allow) —
+//! so that a pure run touches its input and output and nothing else, even
+//! through a line naming its own output or a fallback that reads
+//! `/proc/meminfo`.
+//!
+//! Paths are compared as given, component by component, so `./out.txt`
+//! and `out.txt` are the same path but a symlink to the output isn't.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::paths;
+
+/// How a file is accessed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+struct Audit {
+    enforced: bool,
+    allowed: Vec<(PathBuf, Access)>,
+    accesses: Vec<(PathBuf, Access)>,
+}
+
+static AUDIT: Mutex<Audit> = Mutex::new(Audit {
+    enforced: false,
+    allowed: Vec::new(),
+    accesses: Vec::new(),
+});
+
+impl Audit {
+    fn check(&mut self, path: &Path, access: Access) -> io::Result<()> {
+        if !self.enforced {
+            return Ok(());
+        }
+        let allowed = |(allowed, granted): &(PathBuf, Access)| {
+            components(allowed).eq(components(path)) && (*granted == access || *granted == Access::Write)
+        };
+        if !self.allowed.iter().any(allowed) {
+            let verb = match access {
+                Access::Read => "read",
+                Access::Write => "write",
+            };
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("--pure refuses to {} {:?}, which isn't the input or output", verb, path),
+            ));
+        }
+        self.accesses.push((path.to_path_buf(), access));
+        Ok(())
+    }
+}
+
+/// The components of `path` but `.`.
+fn components(path: &Path) -> impl Iterator<Item = Component<'_>> {
+    path.components().filter(|component| *component != Component::CurDir)
+}
+
+fn audit() -> std::sync::MutexGuard<'static, Audit> {
+    AUDIT.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Refuses every access from now on but those [allowed](allow).
+pub fn enforce() {
+    audit().enforced = true;
+}
+
+/// Lets `path` be accessed as `access`; writing a file includes reading
+/// it back.
+pub fn allow(path: &Path, access: Access) {
+    audit().allowed.push((path.to_path_buf(), access));
+}
+
+/// Notes that `path` is accessed as `access`, failing if it isn't allowed.
+pub fn check(path: &Path, access: Access) -> io::Result<()> {
+    audit().check(path, access)
+}
+
+/// The accesses noted since [`enforce`], in order, refused ones left out.
+pub fn accesses() -> Vec<(PathBuf, Access)> {
+    audit().accesses.clone()
+}
+
+/// [`File::open`], audited, of the [native](paths::native) form of `path`.
+pub fn open(path: &Path) -> io::Result<File> {
+    check(path, Access::Read)?;
+    File::open(paths::native(path))
+}
+
+/// [`File::create`], audited.
+pub fn create(path: &Path) -> io::Result<File> {
+    check(path, Access::Write)?;
+    File::create(paths::native(path))
+}
+
+/// [`OpenOptions::open`], audited as a write.
+pub fn open_with(options: &OpenOptions, path: &Path) -> io::Result<File> {
+    check(path, Access::Write)?;
+    options.open(paths::native(path))
+}
+
+/// [`std::fs::read_to_string`], audited.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    check(path, Access::Read)?;
+    std::fs::read_to_string(paths::native(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Access, Audit};
+
+    #[test]
+    fn test_enforce() {
+        // Enforcing the global audit would hold for the other tests too.
+        let mut audit = Audit {
+            enforced: true,
+            allowed: vec![(Path::new("in.txt").into(), Access::Read), (Path::new("out/all.txt").into(), Access::Write)],
+            accesses: Vec::new(),
+        };
+        assert!(audit.check(Path::new("./in.txt"), Access::Read).is_ok());
+        let err = audit.check(Path::new("in.txt"), Access::Write).unwrap_err();
+        assert_eq!(err.to_string(), "--pure refuses to write \"in.txt\", which isn't the input or output");
+        assert!(audit.check(Path::new("out/./all.txt"), Access::Read).is_ok());
+        assert!(audit.check(Path::new("/proc/meminfo"), Access::Read).is_err());
+        assert_eq!(audit.accesses, [(Path::new("./in.txt").into(), Access::Read), (Path::new("out/./all.txt").into(), Access::Read)]);
+    }
+}
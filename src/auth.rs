@@ -0,0 +1,277 @@
+//! Authentication of `serve` requests with an API key in the `X-API-Key`
+//! header or an HS256-signed JWT in `Authorization: Bearer`.
+//!
+//! Each request is attributed to an identity: the name of its key, or
+//! `jwt:` followed by the token's subject. Usage metrics are kept per
+//! identity so that teams sharing one instance can tell their traffic apart.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::http::Request;
+use crate::json::{self, Value};
+use crate::sha256::{constant_time_eq, hmac_sha256};
+
+/// Clock skew tolerated when checking `exp` and `nbf`, in seconds.
+pub const LEEWAY: u64 = 60;
+
+/// A named key accepted in `X-API-Key`.
+#[derive(Clone)]
+pub struct ApiKey {
+    pub name: String,
+    secret: String,
+}
+
+/// Reads `name key` lines, skipping blank ones and `#` comments.
+pub fn read_api_keys(path: &Path) -> Result<Vec<ApiKey>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read API keys from {:?}", path))?;
+    let mut keys: Vec<ApiKey> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, secret) = line
+            .split_once(char::is_whitespace)
+            .map(|(name, secret)| (name, secret.trim()))
+            .filter(|(_, secret)| !secret.is_empty())
+            .ok_or_else(|| anyhow!("{:?} line {}: expected a name and a key", path, index + 1))?;
+        if keys.iter().any(|key| key.name == name) {
+            bail!("{:?} line {}: duplicate key name {}", path, index + 1, name);
+        }
+        keys.push(ApiKey {
+            name: name.to_string(),
+            secret: secret.to_string(),
+        });
+    }
+    Ok(keys)
+}
+
+/// How bearer tokens are checked. Tokens must be signed with HS256 by one
+/// of `keys`, so that keys can be rotated by accepting old and new ones
+/// for a while.
+#[derive(Clone)]
+pub struct JwtConfig {
+    pub keys: Vec<Vec<u8>>,
+    /// Required value of the `iss` claim.
+    pub issuer: Option<String>,
+}
+
+/// Why a request was refused.
+#[derive(Debug, PartialEq)]
+pub struct AuthError(pub String);
+
+/// The credentials `serve` accepts. Without any, every request is let in.
+#[derive(Clone, Default)]
+pub struct Authenticator {
+    api_keys: Vec<ApiKey>,
+    jwt: Option<JwtConfig>,
+}
+
+impl fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.api_keys.iter().map(|key| key.name.as_str()).collect();
+        f.debug_struct("Authenticator")
+            .field("api_keys", &names)
+            .field("jwt_issuer", &self.jwt.as_ref().map(|jwt| &jwt.issuer))
+            .finish()
+    }
+}
+
+impl Authenticator {
+    pub fn new(api_keys: Vec<ApiKey>, jwt: Option<JwtConfig>) -> Self {
+        Authenticator { api_keys, jwt }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.accepts_api_keys() || self.accepts_bearer_tokens()
+    }
+
+    pub fn accepts_api_keys(&self) -> bool {
+        !self.api_keys.is_empty()
+    }
+
+    pub fn accepts_bearer_tokens(&self) -> bool {
+        self.jwt.is_some()
+    }
+
+    /// The identity `request` authenticates as, or `anonymous` when no
+    /// credentials are configured.
+    pub fn authenticate(&self, request: &Request) -> Result<String, AuthError> {
+        if !self.is_enabled() {
+            return Ok(String::from("anonymous"));
+        }
+        if let Some(given) = request.header("x-api-key") {
+            let key = self.api_keys.iter().find(|key| constant_time_eq(key.secret.as_bytes(), given.as_bytes()));
+            return key.map(|key| key.name.clone()).ok_or_else(|| AuthError(String::from("Unknown API key")));
+        }
+        let bearer = request.header("authorization").and_then(|value| {
+            let (scheme, token) = value.split_once(' ')?;
+            scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+        });
+        match (bearer, &self.jwt) {
+            (Some(token), Some(jwt)) => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                verify_jwt(token, jwt, now).map_err(|err| AuthError(format!("Invalid bearer token: {:#}", err)))
+            }
+            (Some(_), None) => Err(AuthError(String::from("Bearer tokens aren't accepted; send X-API-Key"))),
+            (None, _) => Err(AuthError(String::from("Missing credentials"))),
+        }
+    }
+}
+
+/// Decodes unpadded base64url, as used by JWTs.
+pub fn base64url_decode(text: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => bail!("Invalid base64url character {:?}", c as char),
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    if count >= 6 {
+        bail!("Truncated base64url");
+    }
+    Ok(out)
+}
+
+fn decode_json(part: &str) -> Result<Value> {
+    let bytes = base64url_decode(part)?;
+    json::parse(std::str::from_utf8(&bytes).context("Not UTF-8")?)
+}
+
+fn seconds(claims: &Value, name: &str) -> Result<Option<u64>> {
+    match claims.get(name) {
+        None => Ok(None),
+        Some(Value::Number(text)) => {
+            let seconds = text.split('.').next().unwrap_or_default().parse();
+            Ok(Some(seconds.map_err(|_| anyhow!("Invalid {} claim: {}", name, text))?))
+        }
+        Some(_) => bail!("Invalid {} claim", name),
+    }
+}
+
+/// Checks the signature and claims of `token` at `now` (Unix seconds),
+/// returning `jwt:` and its subject.
+pub fn verify_jwt(token: &str, config: &JwtConfig, now: u64) -> Result<String> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        bail!("Not a JWT");
+    };
+    let algorithm = decode_json(header)?.get("alg").and_then(Value::as_text).map(String::from);
+    if algorithm.as_deref() != Some("HS256") {
+        bail!("Unsupported algorithm {:?} (only HS256 is accepted)", algorithm.unwrap_or_default());
+    }
+    let signature = base64url_decode(signature)?;
+    let signed = &token[..header.len() + 1 + payload.len()];
+    if !config.keys.iter().any(|key| constant_time_eq(&hmac_sha256(key, signed.as_bytes()), &signature)) {
+        bail!("Bad signature");
+    }
+    let claims = decode_json(payload)?;
+    if seconds(&claims, "exp")?.is_some_and(|exp| now >= exp + LEEWAY) {
+        bail!("Expired");
+    }
+    if seconds(&claims, "nbf")?.is_some_and(|nbf| now + LEEWAY < nbf) {
+        bail!("Not valid yet");
+    }
+    if let Some(issuer) = &config.issuer {
+        let given = claims.get("iss").and_then(Value::as_text);
+        if given != Some(issuer.as_str()) {
+            bail!("Issuer {:?} isn't {:?}", given.unwrap_or_default(), issuer);
+        }
+    }
+    let subject = claims.get("sub").and_then(Value::as_text).ok_or_else(|| anyhow!("Missing sub claim"))?;
+    Ok(format!("jwt:{}", subject))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64url_decode, read_api_keys, verify_jwt, ApiKey, Authenticator, JwtConfig};
+    use crate::http::Request;
+    use crate::sha256::hmac_sha256;
+
+    fn base64url(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+        let mut out = String::new();
+        for chunk in bytes.chunks(3) {
+            let n = chunk.iter().fold(0u32, |n, &byte| n << 8 | byte as u32) << (8 * (3 - chunk.len()));
+            for i in 0..=chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            }
+        }
+        out
+    }
+
+    fn token(key: &[u8], header: &str, claims: &str) -> String {
+        let signed = format!("{}.{}", base64url(header.as_bytes()), base64url(claims.as_bytes()));
+        format!("{}.{}", signed, base64url(&hmac_sha256(key, signed.as_bytes())))
+    }
+
+    #[test]
+    fn test_base64url_decode() {
+        assert_eq!(base64url_decode("aGVsbG8").unwrap(), b"hello");
+        assert_eq!(base64url_decode(&base64url(&[0xfb, 0xff])).unwrap(), [0xfb, 0xff]);
+        assert!(base64url_decode("a+b").is_err());
+        assert!(base64url_decode("a").is_err());
+    }
+
+    #[test]
+    fn test_verify_jwt() {
+        let config = JwtConfig {
+            keys: vec![b"old".to_vec(), b"new".to_vec()],
+            issuer: Some(String::from("auth.example")),
+        };
+        let header = r#"{"alg":"HS256","typ":"JWT"}"#;
+        let claims = r#"{"sub":"team-a","iss":"auth.example","exp":1000,"nbf":100}"#;
+        assert_eq!(verify_jwt(&token(b"new", header, claims), &config, 500).unwrap(), "jwt:team-a");
+        assert!(verify_jwt(&token(b"old", header, claims), &config, 500).is_ok());
+        assert!(verify_jwt(&token(b"other", header, claims), &config, 500).is_err());
+        assert!(verify_jwt(&token(b"new", header, claims), &config, 1100).is_err());
+        assert!(verify_jwt(&token(b"new", header, claims), &config, 10).is_err());
+        let foreign = r#"{"sub":"team-a","iss":"elsewhere"}"#;
+        assert!(verify_jwt(&token(b"new", header, foreign), &config, 500).is_err());
+        assert!(verify_jwt(&token(b"new", r#"{"alg":"none"}"#, claims), &config, 500).is_err());
+        assert!(verify_jwt("a.b", &config, 500).is_err());
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let path = std::env::temp_dir().join(format!("multiples_keys_{}.txt", std::process::id()));
+        std::fs::write(&path, "# team keys\nteam-a s3cret\n\nteam-b  other key\n").unwrap();
+        let keys: Vec<ApiKey> = read_api_keys(&path).unwrap();
+        std::fs::write(&path, "team-a\n").unwrap();
+        assert!(read_api_keys(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(keys[1].secret, "other key");
+
+        let request = |header: &str, value: &str| Request {
+            headers: vec![(header.to_string(), value.to_string())],
+            ..Default::default()
+        };
+        assert_eq!(Authenticator::default().authenticate(&Request::default()).unwrap(), "anonymous");
+        let auth = Authenticator::new(keys, None);
+        assert_eq!(auth.authenticate(&request("X-API-Key", "other key")).unwrap(), "team-b");
+        assert!(auth.authenticate(&request("X-API-Key", "guess")).is_err());
+        assert!(auth.authenticate(&request("Authorization", "Bearer x.y.z")).is_err());
+        assert!(auth.authenticate(&Request::default()).is_err());
+
+        let auth = Authenticator::new(Vec::new(), Some(JwtConfig { keys: vec![b"k".to_vec()], issuer: None }));
+        let bearer = format!("Bearer {}", token(b"k", r#"{"alg":"HS256"}"#, r#"{"sub":"ci"}"#));
+        assert_eq!(auth.authenticate(&request("authorization", &bearer)).unwrap(), "jwt:ci");
+    }
+}
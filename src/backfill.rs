@@ -0,0 +1,302 @@
+//! Filling the gaps of a series of result files: given a directory holding
+//! the results of the same divisors for some ends of a stride, such as
+//! 1e6, 3e6 and 5e6, [`plan`] finds the ends of `stride, 2 * stride, ...,
+//! target` without a file and [`backfill`] generates them, each extending
+//! the record of the nearest end below it by the values past that end with
+//! [`RecordGenerator::generate_range`] rather than starting over from 1.
+//!
+//! Every file of a series holds one record with its divisors. A file's
+//! name gives the names of the new ones: the end it holds, written out in
+//! digits, is replaced by each new end, so `3_5_1000000.ndjson` is
+//! followed by `3_5_2000000.ndjson`. Files named otherwise need a
+//! template, see [`Naming`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::formula::{self, Mode};
+use crate::reader::{self, ResultReaderRegistry};
+use crate::spec::{DivisorSet, RangeSpec};
+use crate::strategy::{GenerateOptions, RecordGenerator};
+use crate::{LineNumbers, ResultRecord};
+
+/// How the files of new ends are named.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Naming {
+    /// After an existing file of the series, as described in the
+    /// [module documentation](self).
+    LikeExisting,
+    /// A template of the file name in the directory, with `{a}`, `{b}` and
+    /// `{end}` standing for the numbers of the record, e.g.
+    /// `{a}x{b}-{end}.csv`. The format is given by its extension.
+    Template(String),
+}
+
+/// A file to generate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fill {
+    pub job: LineNumbers,
+    /// The file of the nearest end below, which is extended, or `None` to
+    /// generate the range from 1. It may be a file filled before this one.
+    pub base: Option<PathBuf>,
+    pub output: PathBuf,
+    pub format: String,
+}
+
+/// What [`plan`] found in a directory.
+#[derive(Debug, Default)]
+pub struct Plan {
+    /// The files to generate, ascending by end within each series, so that
+    /// every base is there before the files extending it.
+    pub fills: Vec<Fill>,
+    /// Files that aren't part of a series, with the reason.
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+/// The ends of `stride, 2 * stride, ..., target` that `existing` lacks.
+pub fn missing_ends(existing: &BTreeSet<u32>, stride: u32, target: u32) -> Vec<u32> {
+    (1..=target / stride).map(|i| i * stride).filter(|end| !existing.contains(end)).collect()
+}
+
+/// Finds the files missing from every series of results in `dir`, the
+/// files with a registered extension whose single record has divisors.
+/// Records that aren't the `mode` multiples of their divisors, as with
+/// digit filters, can't be extended and fail the plan.
+pub fn plan(registry: &ResultReaderRegistry, dir: &Path, stride: u32, target: u32, mode: Mode, naming: &Naming) -> Result<Plan> {
+    if stride == 0 || target < stride {
+        bail!("--target must be at least --stride, which must be positive");
+    }
+    if let Naming::Template(template) = naming {
+        validate_template(template)?;
+    }
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to list {:?}", dir))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<_>>()
+        .with_context(|| format!("Failed to list {:?}", dir))?;
+    entries.sort();
+
+    let mut plan = Plan::default();
+    // The files of every pair of divisors by their end.
+    let mut series: BTreeMap<(u32, u32), BTreeMap<u32, PathBuf>> = BTreeMap::new();
+    for path in entries {
+        let Some(format) = registry.format_of(&path).filter(|_| path.is_file()) else {
+            continue;
+        };
+        let mut records = match reader::read_records(&path, Some(format)) {
+            Ok(records) => records,
+            Err(err) => {
+                plan.skipped.push((path, format!("{:#}", err)));
+                continue;
+            }
+        };
+        let reason = match records.as_slice() {
+            [record] if record.job.a == 0 && record.job.b == 0 => "its record doesn't name its divisors".to_string(),
+            [_] => String::new(),
+            records => format!("it holds {} records rather than one", records.len()),
+        };
+        if !reason.is_empty() {
+            plan.skipped.push((path, reason));
+            continue;
+        }
+        let record = records.remove(0);
+        let job = record.job;
+        let expected = formula::count(&job, mode);
+        if record.result.count() != expected {
+            bail!(
+                "{:?} holds {} multiples of a:{} b:{} end:{} instead of the {} of --mode; only unfiltered results can be extended",
+                path,
+                record.result.count(),
+                job.a,
+                job.b,
+                job.end,
+                expected
+            );
+        }
+        if record.result.numbers.windows(2).any(|pair| pair[0] > pair[1]) {
+            bail!("{:?} lists its multiples in descending order, which backfill can't extend", path);
+        }
+        let members = series.entry((job.a, job.b)).or_default();
+        if let Some(other) = members.get(&job.end) {
+            bail!("{:?} and {:?} both hold a:{} b:{} end:{}", other, path, job.a, job.b, job.end);
+        }
+        members.insert(job.end, path);
+    }
+
+    for ((a, b), mut members) in series {
+        let ends: BTreeSet<u32> = members.keys().copied().collect();
+        let missing = missing_ends(&ends, stride, target);
+        if missing.is_empty() {
+            continue;
+        }
+        let template = match naming {
+            Naming::Template(template) => template.clone(),
+            Naming::LikeExisting => template_of(members.iter()).ok_or_else(|| {
+                anyhow!("None of the files of a:{} b:{} names its end in digits; give a --name template", a, b)
+            })?,
+        };
+        let name = template.replace("{a}", &a.to_string()).replace("{b}", &b.to_string());
+        for end in missing {
+            let output = dir.join(name.replace("{end}", &end.to_string()));
+            let format = registry.format_of(&output).map(str::to_string).ok_or_else(|| {
+                anyhow!("{:?} has no extension of a result format", output)
+            })?;
+            let base = members.range(..end).next_back().map(|(_, path)| path.clone());
+            plan.fills.push(Fill {
+                job: LineNumbers { a, b, end },
+                base,
+                output: output.clone(),
+                format,
+            });
+            members.insert(end, output);
+        }
+    }
+    Ok(plan)
+}
+
+/// Checks that a `--name` template names every file of a series apart.
+fn validate_template(template: &str) -> Result<()> {
+    if !template.contains("{end}") || template.contains(['/', '\\']) {
+        bail!("--name {} must contain {{end}} and name a file in the directory", template);
+    }
+    Ok(())
+}
+
+/// The template of the first file of `members` that names its end once in
+/// digits, with the end replaced by `{end}`.
+fn template_of<'a>(mut members: impl Iterator<Item = (&'a u32, &'a PathBuf)>) -> Option<String> {
+    members.find_map(|(end, path)| {
+        let name = path.file_name()?.to_str()?;
+        let digits = end.to_string();
+        let found: Vec<_> = name.match_indices(&digits).map(|(at, _)| at).collect();
+        let &[at] = found.as_slice() else {
+            return None;
+        };
+        // The end, not part of a longer number.
+        let bytes = name.as_bytes();
+        let after = at + digits.len();
+        if (at > 0 && bytes[at - 1].is_ascii_digit()) || bytes.get(after).is_some_and(u8::is_ascii_digit) {
+            return None;
+        }
+        Some(format!("{}{{end}}{}", &name[..at], &name[after..]))
+    })
+}
+
+/// The record of `job` with `mode`: `base`, the record of the same
+/// divisors for a lower end, extended by the multiples past its end, or
+/// the whole range without a base. Count-only bases are extended by the
+/// count of the rest of the range.
+pub fn extend(base: Option<&ResultRecord>, job: LineNumbers, mode: Mode) -> Result<ResultRecord> {
+    let divisors = DivisorSet::new(job.a, job.b)?;
+    let count_only = base.is_some_and(|base| base.result.count.is_some());
+    let generator = RecordGenerator::new(GenerateOptions { mode, count_only, ..GenerateOptions::default() });
+    let Some(base) = base else {
+        return Ok(generator.generate(job));
+    };
+    if base.job.a != job.a || base.job.b != job.b || base.job.end >= job.end {
+        bail!("a:{} b:{} end:{} can't be extended to end:{}", base.job.a, base.job.b, base.job.end, job.end);
+    }
+    let rest = generator.generate_range(divisors, RangeSpec::new(base.job.end + 1, job.end)?);
+    let mut record = base.clone();
+    record.job = job;
+    record.result.end = job.end;
+    record.result.count = base.result.count.map(|count| count + rest.result.count());
+    record.result.numbers.extend(rest.result.numbers);
+    Ok(record)
+}
+
+/// Generates the files of `plan` in order, handing each record to `write`
+/// with its fill. Bases are read once and the records of earlier fills
+/// kept, so that a series of fills reads no file twice.
+pub fn backfill(plan: &Plan, mode: Mode, mut write: impl FnMut(&Fill, &ResultRecord) -> Result<()>) -> Result<()> {
+    let mut previous: Option<(PathBuf, ResultRecord)> = None;
+    for fill in &plan.fills {
+        let base = match (&fill.base, previous.take()) {
+            (None, _) => None,
+            (Some(path), Some((written, record))) if *path == written => Some(record),
+            (Some(path), _) => Some(reader::read_records(path, None)?.remove(0)),
+        };
+        let record = extend(base.as_ref(), fill.job, mode)
+            .with_context(|| format!("Failed to generate {:?}", fill.output))?;
+        write(fill, &record).with_context(|| format!("Failed to write {:?}", fill.output))?;
+        previous = Some((fill.output.clone(), record));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::{backfill, extend, missing_ends, plan, Naming};
+    use crate::formula::Mode;
+    use crate::reader::{read_records, ResultReaderRegistry};
+    use crate::strategy::{GenerateOptions, RecordGenerator};
+    use crate::LineNumbers;
+
+    #[test]
+    fn test_missing_ends() {
+        let existing = BTreeSet::from([1_000_000, 3_000_000, 5_000_000]);
+        assert_eq!(
+            missing_ends(&existing, 1_000_000, 7_500_000),
+            [2_000_000, 4_000_000, 6_000_000, 7_000_000]
+        );
+        assert!(missing_ends(&existing, 2_000_000, 5_000_000).contains(&2_000_000));
+    }
+
+    /// Extending a record gives the record generated from scratch.
+    #[test]
+    fn test_extend() {
+        for mode in [Mode::Or, Mode::Xor] {
+            let generator = RecordGenerator::new(GenerateOptions { mode, ..GenerateOptions::default() });
+            let base = generator.generate(LineNumbers { a: 3, b: 5, end: 100 });
+            let job = LineNumbers { a: 3, b: 5, end: 250 };
+            assert_eq!(extend(Some(&base), job, mode).unwrap(), generator.generate(job));
+            assert_eq!(extend(None, job, mode).unwrap(), generator.generate(job));
+        }
+        let counted = RecordGenerator::new(GenerateOptions { count_only: true, ..GenerateOptions::default() });
+        let base = counted.generate(LineNumbers { a: 3, b: 5, end: 100 });
+        let extended = extend(Some(&base), LineNumbers { a: 3, b: 5, end: 250 }, Mode::Or).unwrap();
+        assert_eq!(extended.result.count, Some(117));
+        assert!(extend(Some(&base), LineNumbers { a: 3, b: 7, end: 250 }, Mode::Or).is_err());
+    }
+
+    #[test]
+    fn test_backfill_dir() {
+        let dir = std::env::temp_dir().join(format!("multiples_backfill_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let generator = RecordGenerator::new(GenerateOptions::default());
+        for end in [100, 300] {
+            let record = generator.generate(LineNumbers { a: 3, b: 5, end });
+            let line = format!("{{\"a\":3,\"b\":5,\"end\":{},\"numbers\":{:?}}}\n", end, record.result.numbers);
+            std::fs::write(dir.join(format!("r_3_5_{}.ndjson", end)), line.replace(' ', "")).unwrap();
+        }
+        std::fs::write(dir.join("input.txt"), "3 5 100\n").unwrap();
+
+        let registry = ResultReaderRegistry::with_builtins();
+        let planned = plan(&registry, &dir, 100, 500, Mode::Or, &Naming::LikeExisting).unwrap();
+        let outputs: Vec<_> = planned.fills.iter().map(|fill| fill.output.file_name().unwrap().to_owned()).collect();
+        assert_eq!(outputs, ["r_3_5_200.ndjson", "r_3_5_400.ndjson", "r_3_5_500.ndjson"]);
+        assert_eq!(planned.fills[1].base, Some(dir.join("r_3_5_300.ndjson")));
+        assert_eq!(planned.fills[2].base, Some(dir.join("r_3_5_400.ndjson")));
+        assert_eq!(planned.skipped.len(), 1);
+
+        let mut written = Vec::new();
+        backfill(&planned, Mode::Or, |fill, record| {
+            written.push((fill.job.end, record.clone()));
+            Ok(())
+        })
+        .unwrap();
+        for (end, record) in written {
+            assert_eq!(record, generator.generate(LineNumbers { a: 3, b: 5, end }));
+        }
+        assert_eq!(read_records(&dir.join("r_3_5_300.ndjson"), None).unwrap()[0].result.count(), 140);
+
+        let template = Naming::Template("{a}-{b}/{end}.csv".to_string());
+        assert!(plan(&registry, &dir, 100, 500, Mode::Or, &template).is_err());
+        assert!(plan(&registry, &dir, 100, 500, Mode::Xor, &Naming::LikeExisting).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
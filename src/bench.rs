@@ -0,0 +1,149 @@
+//! Timing `generate` runs for `bench`, optionally against another build of
+//! the program given with `--compare-with`, such as the release installed
+//! before an upgrade. Both builds run the same input with the same options
+//! in processes of their own, alternating, and their outputs must be the
+//! same byte for byte.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+
+/// One build of the program being timed.
+pub struct Contender {
+    /// How the build is named in the report.
+    pub name: String,
+    pub exe: PathBuf,
+    /// Where its runs write their output.
+    pub output: PathBuf,
+    pub runs: Vec<Duration>,
+}
+
+impl Contender {
+    pub fn new(name: &str, exe: PathBuf, output: PathBuf) -> Self {
+        Contender {
+            name: name.to_string(),
+            exe,
+            output,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Runs `exe <options> <input> <output>` once, discarding stdout, and
+    /// records how long it took.
+    pub fn run(&mut self, input: &Path, options: &[String]) -> Result<Duration> {
+        let started = Instant::now();
+        let run = Command::new(&self.exe)
+            .args(options)
+            .arg(input)
+            .arg(&self.output)
+            .stdout(Stdio::null())
+            .output()
+            .with_context(|| format!("Failed to run {:?}", self.exe))?;
+        let elapsed = started.elapsed();
+        if !run.status.success() {
+            let stderr = String::from_utf8_lossy(&run.stderr);
+            bail!("The {} build {:?} failed: {}", self.name, self.exe, stderr.trim());
+        }
+        self.runs.push(elapsed);
+        Ok(elapsed)
+    }
+
+    /// The median of the runs so far, which a single slow run caused by the
+    /// rest of the machine doesn't move.
+    pub fn median(&self) -> Duration {
+        let mut runs = self.runs.clone();
+        runs.sort();
+        match runs.len() {
+            0 => Duration::ZERO,
+            n if n % 2 == 1 => runs[n / 2],
+            n => (runs[n / 2 - 1] + runs[n / 2]) / 2,
+        }
+    }
+}
+
+/// Where the files at `a` and `b` first differ, or `None` if they are the
+/// same byte for byte.
+pub fn first_difference(a: &Path, b: &Path) -> Result<Option<String>> {
+    let open = |path: &Path| File::open(path).map(BufReader::new).with_context(|| format!("Failed to open {:?}", path));
+    let (mut a_lines, mut b_lines) = (open(a)?.split(b'\n'), open(b)?.split(b'\n'));
+    let mut line = 0;
+    loop {
+        line += 1;
+        let (a_line, b_line) = match (a_lines.next().transpose()?, b_lines.next().transpose()?) {
+            (None, None) => return Ok(None),
+            (Some(_), None) | (None, Some(_)) => return Ok(Some(format!("one output ends after line {}", line - 1))),
+            (Some(a_line), Some(b_line)) => (a_line, b_line),
+        };
+        if a_line != b_line {
+            let shorten = |line: &[u8]| {
+                let text = String::from_utf8_lossy(line);
+                match text.char_indices().nth(60) {
+                    Some((at, _)) => format!("{}...", &text[..at]),
+                    None => text.into_owned(),
+                }
+            };
+            return Ok(Some(format!("line {} differs: {:?} vs {:?}", line, shorten(&a_line), shorten(&b_line))));
+        }
+    }
+}
+
+/// How much faster `new` is than `old`, such as `1.25x faster (-20.0%)`.
+pub fn describe_delta(new: Duration, old: Duration) -> String {
+    let (new, old) = (new.as_secs_f64(), old.as_secs_f64());
+    if new == 0.0 || old == 0.0 {
+        return "too fast to compare".to_string();
+    }
+    let change = (new - old) / old * 100.0;
+    if new <= old {
+        format!("{:.2}x faster ({:+.1}%)", old / new, change)
+    } else {
+        format!("{:.2}x slower ({:+.1}%)", new / old, change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use super::{describe_delta, first_difference, Contender};
+
+    #[test]
+    fn test_median() {
+        let mut contender = Contender::new("new", PathBuf::from("multiples"), PathBuf::from("out.txt"));
+        assert_eq!(contender.median(), Duration::ZERO);
+        contender.runs = [30, 10, 500].map(Duration::from_millis).to_vec();
+        assert_eq!(contender.median(), Duration::from_millis(30));
+        contender.runs.push(Duration::from_millis(20));
+        assert_eq!(contender.median(), Duration::from_millis(25));
+    }
+
+    #[test]
+    fn test_describe_delta() {
+        assert_eq!(describe_delta(Duration::from_millis(800), Duration::from_secs(1)), "1.25x faster (-20.0%)");
+        assert_eq!(describe_delta(Duration::from_secs(3), Duration::from_secs(2)), "1.50x slower (+50.0%)");
+        assert_eq!(describe_delta(Duration::ZERO, Duration::from_secs(1)), "too fast to compare");
+    }
+
+    #[test]
+    fn test_first_difference() {
+        let dir = std::env::temp_dir();
+        let path = |name: &str| dir.join(format!("multiples_bench_{}_{}", std::process::id(), name));
+        let (a, b, c, d) = (path("a"), path("b"), path("c"), path("d"));
+        fs::write(&a, "10:3 5 6 9 10\n6:2 3 4 6\n").unwrap();
+        fs::write(&b, "10:3 5 6 9 10\n6:2 3 4 6\n").unwrap();
+        fs::write(&c, "10:3 5 6 9 10\n6:2 3 4\n").unwrap();
+        fs::write(&d, "10:3 5 6 9 10\n").unwrap();
+        assert_eq!(first_difference(&a, &b).unwrap(), None);
+        assert_eq!(first_difference(&a, &c).unwrap().unwrap(), "line 2 differs: \"6:2 3 4 6\" vs \"6:2 3 4\"");
+        assert_eq!(first_difference(&a, &d).unwrap().unwrap(), "one output ends after line 1");
+        for file in [a, b, c, d] {
+            fs::remove_file(file).unwrap();
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! A least recently used cache with a size budget and an optional time to
+//! live, used by `serve` to answer repeated identical queries from memory,
+//! and [`SingleFlight`], which answers identical concurrent queries with a
+//! single generation.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+struct Entry<V> {
+    value: V,
+    size: u64,
+    inserted: Instant,
+    /// When it was last used, as a tick of the cache's clock.
+    used: u64,
+}
+
+/// What a cache has done, for `/metrics`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped to make room, not counting expired ones.
+    pub evictions: u64,
+    pub entries: usize,
+    pub size: u64,
+}
+
+/// Values of up to `max_size` bytes in total, dropping the least recently
+/// used first. Values larger than the whole budget aren't kept at all.
+pub struct LruCache<K, V> {
+    max_size: u64,
+    ttl: Option<Duration>,
+    entries: HashMap<K, Entry<V>>,
+    /// Keys by when they were last used, oldest first.
+    order: BTreeMap<u64, K>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> LruCache<K, V> {
+    pub fn new(max_size: u64, ttl: Option<Duration>) -> Self {
+        LruCache {
+            max_size,
+            ttl,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// The value of `key`, unless it is missing or older than the time to
+    /// live, counting a hit or a miss.
+    pub fn get(&mut self, key: &K, now: Instant) -> Option<V> {
+        let expired = match self.entries.get(key) {
+            None => {
+                self.stats.misses += 1;
+                return None;
+            }
+            Some(entry) => self.ttl.is_some_and(|ttl| now.duration_since(entry.inserted) >= ttl),
+        };
+        if expired {
+            self.remove(key);
+            self.stats.misses += 1;
+            return None;
+        }
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.used);
+        entry.used = self.clock;
+        self.order.insert(self.clock, key.clone());
+        self.stats.hits += 1;
+        Some(entry.value.clone())
+    }
+
+    /// Keeps `value`, which takes `size` bytes, dropping the least recently
+    /// used entries until it fits.
+    pub fn insert(&mut self, key: K, value: V, size: u64, now: Instant) {
+        self.remove(&key);
+        if size > self.max_size {
+            return;
+        }
+        while self.stats.size + size > self.max_size {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.stats.size -= entry.size;
+                self.stats.evictions += 1;
+            }
+        }
+        self.clock += 1;
+        self.order.insert(self.clock, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                size,
+                inserted: now,
+                used: self.clock,
+            },
+        );
+        self.stats.size += size;
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.used);
+            self.stats.size -= entry.size;
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.len(),
+            ..self.stats
+        }
+    }
+}
+
+enum FlightState<V> {
+    Running,
+    Done(V),
+    /// The work panicked; whoever waited does it on their own.
+    Abandoned,
+}
+
+struct Flight<V> {
+    state: Mutex<FlightState<V>>,
+    done: Condvar,
+}
+
+/// What [`SingleFlight`] has done, for `/metrics`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlightStats {
+    /// Calls that waited for the same work of another call.
+    pub coalesced: u64,
+    /// Work running right now.
+    pub in_flight: usize,
+}
+
+/// Runs the work for a key once at a time: calls for a key whose work is
+/// already running wait for it and get a copy of its value.
+pub struct SingleFlight<K, V> {
+    flights: Mutex<HashMap<K, Arc<Flight<V>>>>,
+    coalesced: AtomicU64,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        SingleFlight {
+            flights: Mutex::default(),
+            coalesced: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Finishes a flight when the work returns or panics, so that no one waits
+/// for it forever.
+struct Landing<'a, K: Eq + Hash, V> {
+    flights: &'a Mutex<HashMap<K, Arc<Flight<V>>>>,
+    key: &'a K,
+    flight: &'a Flight<V>,
+    value: Option<V>,
+}
+
+impl<K: Eq + Hash, V> Drop for Landing<'_, K, V> {
+    fn drop(&mut self) {
+        self.flights.lock().unwrap_or_else(|err| err.into_inner()).remove(self.key);
+        let mut state = self.flight.state.lock().unwrap_or_else(|err| err.into_inner());
+        *state = match self.value.take() {
+            Some(value) => FlightState::Done(value),
+            None => FlightState::Abandoned,
+        };
+        self.flight.done.notify_all();
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> SingleFlight<K, V> {
+    /// The value of `work`, or of the same work for `key` that another call
+    /// is running already.
+    pub fn run(&self, key: &K, work: impl FnOnce() -> V) -> V {
+        let mut flights = self.flights.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(flight) = flights.get(key).cloned() {
+            drop(flights);
+            self.coalesced.fetch_add(1, Ordering::Relaxed);
+            let mut state = flight.state.lock().unwrap_or_else(|err| err.into_inner());
+            while let FlightState::Running = *state {
+                state = flight.done.wait(state).unwrap_or_else(|err| err.into_inner());
+            }
+            if let FlightState::Done(value) = &*state {
+                return value.clone();
+            }
+            drop(state);
+            return work();
+        }
+        let flight = Arc::new(Flight {
+            state: Mutex::new(FlightState::Running),
+            done: Condvar::new(),
+        });
+        flights.insert(key.clone(), Arc::clone(&flight));
+        drop(flights);
+        let mut landing = Landing {
+            flights: &self.flights,
+            key,
+            flight: &flight,
+            value: None,
+        };
+        let value = work();
+        landing.value = Some(value.clone());
+        value
+    }
+
+    pub fn stats(&self) -> FlightStats {
+        FlightStats {
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            in_flight: self.flights.lock().unwrap_or_else(|err| err.into_inner()).len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{LruCache, SingleFlight};
+
+    #[test]
+    fn test_lru_cache() {
+        let now = Instant::now();
+        let mut cache = LruCache::new(10, None);
+        cache.insert("a", 1, 4, now);
+        cache.insert("b", 2, 4, now);
+        assert_eq!(cache.get(&"a", now), Some(1));
+        // "b" is the least recently used now, so it makes room for "c".
+        cache.insert("c", 3, 4, now);
+        assert_eq!(cache.get(&"b", now), None);
+        assert_eq!((cache.get(&"a", now), cache.get(&"c", now)), (Some(1), Some(3)));
+        cache.insert("huge", 4, 11, now);
+        assert_eq!(cache.get(&"huge", now), None);
+        cache.insert("a", 5, 2, now);
+        assert_eq!(cache.get(&"a", now), Some(5));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (4, 2, 1));
+        assert_eq!((stats.entries, stats.size), (2, 6));
+
+        let mut cache = LruCache::new(10, Some(Duration::from_secs(60)));
+        cache.insert("a", 1, 1, now);
+        assert_eq!(cache.get(&"a", now + Duration::from_secs(59)), Some(1));
+        assert_eq!(cache.get(&"a", now + Duration::from_secs(60)), None);
+        assert_eq!((cache.stats().entries, cache.stats().size), (0, 0));
+    }
+
+    #[test]
+    fn test_single_flight() {
+        let flights = SingleFlight::default();
+        let runs = AtomicU32::new(0);
+        let (release, released) = mpsc::channel::<()>();
+        let values = thread::scope(|scope| {
+            let flights = &flights;
+            let work = || {
+                runs.fetch_add(1, Ordering::SeqCst);
+                42
+            };
+            let leader = scope.spawn(move || {
+                flights.run(&"key", || {
+                    released.recv().unwrap();
+                    work()
+                })
+            });
+            while flights.stats().in_flight == 0 {
+                thread::yield_now();
+            }
+            let followers: Vec<_> = (0..3).map(|_| scope.spawn(move || flights.run(&"key", work))).collect();
+            while flights.stats().coalesced < 3 {
+                thread::yield_now();
+            }
+            release.send(()).unwrap();
+            let mut values = vec![leader.join().unwrap()];
+            values.extend(followers.into_iter().map(|follower| follower.join().unwrap()));
+            values
+        });
+        assert_eq!((values, runs.load(Ordering::SeqCst)), (vec![42; 4], 1));
+        assert_eq!(flights.stats().in_flight, 0);
+        // The flight is over, so the next call does the work again.
+        assert_eq!(flights.run(&"key", || 7), 7);
+    }
+}
@@ -0,0 +1,87 @@
+//! Cancelling generation from another thread: a [`CancellationToken`] set
+//! in [`GenerateOptions::cancellation`](crate::strategy::GenerateOptions::cancellation)
+//! cuts lines short between chunks, and one given to
+//! [`Pipeline::run_cancellable`](crate::pipeline::Pipeline::run_cancellable)
+//! stops reading jobs. What was generated until then is kept.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Whether a run did all it was asked to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Complete,
+    /// The run was cancelled, so its records cover only part of the input,
+    /// and the last of them possibly only the start of its range.
+    Cancelled,
+}
+
+/// A flag shared between the thread running the generation and those that
+/// may cancel it. Clones share the flag, and a cancelled token stays
+/// cancelled.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn status(&self) -> Status {
+        match self.is_cancelled() {
+            true => Status::Cancelled,
+            false => Status::Complete,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::{CancellationToken, Status};
+    use crate::pipeline::{jobs_source, CollectSink, Pipeline};
+    use crate::strategy::{GenerateOptions, RecordGenerator};
+    use crate::LineNumbers;
+
+    #[test]
+    fn test_token() {
+        let token = CancellationToken::new();
+        assert_eq!(token.status(), Status::Complete);
+        let clone = token.clone();
+        thread::spawn(move || clone.cancel()).join().unwrap();
+        assert!(token.is_cancelled());
+        assert_eq!(token.status(), Status::Cancelled);
+    }
+
+    #[test]
+    fn test_cancelled_generation() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let generator = RecordGenerator::new(GenerateOptions {
+            cancellation: Some(token.clone()),
+            ..Default::default()
+        });
+        // Lines are cut short before their first chunk.
+        let job = LineNumbers { a: 3, b: 5, end: 1_000_000 };
+        assert!(generator.generate_chunked(job, 1, None, &|_| {}).result.numbers.is_empty());
+        assert_eq!(generator.chunks(job, 1000).count(), 0);
+
+        let jobs = vec![LineNumbers { a: 3, b: 5, end: 100 }; 3];
+        let mut sink = CollectSink::default();
+        let report = Pipeline::builder(jobs_source(jobs)).build().run_cancellable(&mut sink, &token).unwrap();
+        assert_eq!((report.status, report.records, sink.records.len()), (Status::Cancelled, 0, 0));
+        let mut sink = CollectSink::default();
+        let jobs = vec![LineNumbers { a: 3, b: 5, end: 100 }; 3];
+        let report = Pipeline::builder(jobs_source(jobs)).build().run_cancellable(&mut sink, &CancellationToken::new()).unwrap();
+        assert_eq!((report.status, report.records), (Status::Complete, 3));
+    }
+}
@@ -0,0 +1,112 @@
+//! Chunk sizes for generating one range on several threads, adapted to how
+//! fast each thread gets through its chunks and whether the writer keeps up.
+//!
+//! A chunk should take long enough that handing it out costs nothing next
+//! to generating it, and short enough that the threads finish together.
+//! How many values that is depends on the divisors, the strategy and the
+//! filters by orders of magnitude, so rather than a fixed size every thread
+//! measures its own throughput and aims its next chunk at
+//! [`TARGET_CHUNK_TIME`].
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// The smallest chunk, and the size of the first one.
+pub const MIN_CHUNK: u32 = 1 << 16;
+
+/// The largest chunk, which bounds the memory of a chunk's numbers.
+pub const MAX_CHUNK: u32 = 1 << 26;
+
+/// How long a chunk should take to generate.
+pub const TARGET_CHUNK_TIME: Duration = Duration::from_millis(20);
+
+/// Chunks aim at this many times [`TARGET_CHUNK_TIME`] while the writer is
+/// behind, since generating faster wouldn't help then.
+const BACKPRESSURE_FACTOR: u32 = 4;
+
+/// The chunk size of one thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkSizer {
+    size: u32,
+}
+
+impl Default for ChunkSizer {
+    fn default() -> Self {
+        ChunkSizer { size: MIN_CHUNK }
+    }
+}
+
+impl ChunkSizer {
+    /// The number of values of the next chunk.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Adjusts the size after a chunk of `values` took `elapsed`: halfway
+    /// towards the size that would have taken the target time, but at most
+    /// doubling or halving, so that one chunk slowed down by something else
+    /// doesn't swing it.
+    pub fn observe(&mut self, values: u64, elapsed: Duration, writer_behind: bool) {
+        let target = match writer_behind {
+            true => TARGET_CHUNK_TIME * BACKPRESSURE_FACTOR,
+            false => TARGET_CHUNK_TIME,
+        };
+        let rate = values as f64 / elapsed.as_secs_f64().max(1e-6);
+        let ideal = rate * target.as_secs_f64();
+        let size = (self.size as f64 + ideal) / 2.0;
+        let size = size.clamp(self.size as f64 / 2.0, self.size as f64 * 2.0);
+        self.size = (size as u32).clamp(MIN_CHUNK, MAX_CHUNK);
+    }
+}
+
+/// Whether the stages after the generator workers are keeping up, as the
+/// workers last found their queue, see
+/// [`PipelineBuilder::backpressure`](crate::pipeline::PipelineBuilder::backpressure).
+#[derive(Debug, Default)]
+pub struct Backpressure {
+    behind: AtomicBool,
+}
+
+impl Backpressure {
+    pub fn set(&self, behind: bool) {
+        self.behind.store(behind, Ordering::Relaxed);
+    }
+
+    pub fn is_behind(&self) -> bool {
+        self.behind.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{ChunkSizer, MAX_CHUNK, MIN_CHUNK};
+
+    #[test]
+    fn test_chunk_sizer() {
+        let mut sizer = ChunkSizer::default();
+        assert_eq!(sizer.size(), MIN_CHUNK);
+        // 100M values a second would fill 20 ms with 2M of them; the size
+        // doubles towards that with every chunk.
+        sizer.observe(MIN_CHUNK as u64, Duration::from_micros(655), false);
+        assert_eq!(sizer.size(), 2 * MIN_CHUNK);
+        for _ in 0..10 {
+            let values = sizer.size() as u64;
+            sizer.observe(values, Duration::from_secs_f64(values as f64 / 1e8), false);
+        }
+        assert!((1_900_000..=2_100_000).contains(&sizer.size()), "{}", sizer.size());
+        let settled = sizer.size();
+        sizer.observe(settled as u64, Duration::from_secs_f64(settled as f64 / 1e8), true);
+        assert!(sizer.size() > settled);
+
+        // A slow chunk halves the size, but never below the minimum.
+        let mut sizer = ChunkSizer::default();
+        sizer.observe(MIN_CHUNK as u64, Duration::from_secs(10), false);
+        assert_eq!(sizer.size(), MIN_CHUNK);
+        for _ in 0..20 {
+            sizer.observe(MAX_CHUNK as u64, Duration::from_micros(1), false);
+        }
+        assert_eq!(sizer.size(), MAX_CHUNK);
+    }
+}
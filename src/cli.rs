@@ -0,0 +1,2570 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+use multiple_of_a_and_b::backfill::Naming;
+use multiple_of_a_and_b::color::ColorChoice;
+use multiple_of_a_and_b::compat::Compat;
+use multiple_of_a_and_b::decimal::MAX_SCALE;
+use multiple_of_a_and_b::degenerate;
+use multiple_of_a_and_b::errors::{INVALID_OPTION_VALUE, UNKNOWN_NAME, UNKNOWN_OPTION, UNSUPPORTED_BY_FORMAT};
+use multiple_of_a_and_b::formatter::{validate_array_name, ARRAY_FORMATS, CHECKSUMMED_FORMATS};
+use multiple_of_a_and_b::locale::{tr_args, Lang, LANGUAGES};
+use multiple_of_a_and_b::formula::Mode;
+use multiple_of_a_and_b::input::Bound;
+use multiple_of_a_and_b::jobfilter::{JobFilter, TagFilter};
+use multiple_of_a_and_b::gaps::parse_percentiles;
+use multiple_of_a_and_b::limits::InputLimits;
+use multiple_of_a_and_b::lint;
+use multiple_of_a_and_b::merge::MergeMode;
+use multiple_of_a_and_b::newline::LineEnding;
+use multiple_of_a_and_b::number;
+use multiple_of_a_and_b::otlp::OtlpExporter;
+use multiple_of_a_and_b::output::{FsyncPolicy, IoBackend, WritePolicy};
+use multiple_of_a_and_b::pipeline::DEFAULT_QUEUE_SIZE;
+use multiple_of_a_and_b::progress::ProgressFormat;
+use multiple_of_a_and_b::provenance;
+use multiple_of_a_and_b::random::JobBounds;
+use multiple_of_a_and_b::runlog;
+use multiple_of_a_and_b::schedule::ScheduledJobs;
+use multiple_of_a_and_b::server::ServerConfig;
+use multiple_of_a_and_b::setops::SetOp;
+use multiple_of_a_and_b::shard::Shard;
+use multiple_of_a_and_b::sort::{SortKey, DEFAULT_MAX_MEMORY};
+use multiple_of_a_and_b::strategy::Strategy;
+use multiple_of_a_and_b::sweep::Sweep;
+use multiple_of_a_and_b::throttle::{self, Throttle};
+use multiple_of_a_and_b::timeline::{parse_duration, parse_timestamp};
+use multiple_of_a_and_b::LineNumbers;
+
+/// A single command-line token, split into flags and positional arguments.
+pub enum Arg {
+    Flag(String),
+    Positional(String),
+}
+
+/// Iterates over command-line arguments, understanding `--flag value`,
+/// `--flag=value` and a `--` separator after which everything is positional.
+pub struct ArgStream {
+    args: VecDeque<String>,
+    inline_value: Option<String>,
+    only_positionals: bool,
+}
+
+impl ArgStream {
+    pub fn new<I: IntoIterator<Item = String>>(args: I) -> Self {
+        ArgStream {
+            args: args.into_iter().collect(),
+            inline_value: None,
+            only_positionals: false,
+        }
+    }
+
+    pub fn next_arg(&mut self) -> Result<Option<Arg>> {
+        if let Some(value) = self.inline_value.take() {
+            bail!("Unexpected value: {:?}", value);
+        }
+        let Some(arg) = self.args.pop_front() else {
+            return Ok(None);
+        };
+        if self.only_positionals || !arg.starts_with("--") {
+            return Ok(Some(Arg::Positional(arg)));
+        }
+        if arg == "--" {
+            self.only_positionals = true;
+            return self.next_arg();
+        }
+        match arg.split_once('=') {
+            Some((flag, value)) => {
+                self.inline_value = Some(value.to_string());
+                Ok(Some(Arg::Flag(flag.to_string())))
+            }
+            None => Ok(Some(Arg::Flag(arg))),
+        }
+    }
+
+    /// Returns the value belonging to `flag`.
+    pub fn value(&mut self, flag: &str) -> Result<String> {
+        self.inline_value
+            .take()
+            .or_else(|| self.args.pop_front())
+            .ok_or_else(|| INVALID_OPTION_VALUE.error(tr_args("Missing value for {}", &[&flag])))
+    }
+
+    /// Returns the value belonging to `flag` parsed as a number literal.
+    pub fn number(&mut self, flag: &str) -> Result<u64> {
+        let value = self.value(flag)?;
+        number::parse_number(&value).map_err(|err| INVALID_OPTION_VALUE.error(tr_args("Invalid value for {}: {}", &[&flag, &err])))
+    }
+
+    /// Returns the value belonging to `flag` as a positive count.
+    pub fn count(&mut self, flag: &str) -> Result<usize> {
+        match usize::try_from(self.number(flag)?) {
+            Ok(0) | Err(_) => Err(INVALID_OPTION_VALUE.error(tr_args("Invalid value for {}: must be a positive count", &[&flag]))),
+            Ok(count) => Ok(count),
+        }
+    }
+
+    /// Returns the value belonging to `flag` as a number of threads, where
+    /// `auto` is one per CPU the process may use.
+    pub fn threads(&mut self, flag: &str) -> Result<usize> {
+        let next = self.inline_value.as_ref().or(self.args.front());
+        if next.is_some_and(|value| value == "auto") {
+            self.value(flag)?;
+            return Ok(throttle::default_threads());
+        }
+        self.count(flag)
+    }
+}
+
+/// Removes `--lang <name>` from `args`, wherever it is before a `--`, and
+/// returns the language it names.
+pub fn take_lang(args: &mut Vec<String>) -> Result<Option<Lang>> {
+    let mut lang = None;
+    let mut i = 1;
+    while i < args.len() && args[i] != "--" {
+        let name = if args[i] == "--lang" {
+            if i + 1 == args.len() {
+                return Err(INVALID_OPTION_VALUE.error(tr_args("Missing value for {}", &[&"--lang"])));
+            }
+            args.drain(i..i + 2).nth(1).unwrap_or_default()
+        } else if let Some(name) = args[i].strip_prefix("--lang=") {
+            let name = name.to_string();
+            args.remove(i);
+            name
+        } else {
+            i += 1;
+            continue;
+        };
+        let found = LANGUAGES.iter().find(|(known, _)| *known == name);
+        let names: Vec<&str> = LANGUAGES.iter().map(|(known, _)| *known).collect();
+        lang = Some(found.map(|&(_, lang)| lang).ok_or_else(|| {
+            UNKNOWN_NAME.error(tr_args("Unknown language: {} (available: {})", &[&name, &names.join(", ")]))
+        })?);
+    }
+    Ok(lang)
+}
+
+/// The error for an option `flag` the command doesn't know.
+fn unknown_option(flag: &str) -> anyhow::Error {
+    UNKNOWN_OPTION.error(tr_args("Unknown option: {}", &[&flag]))
+}
+
+/// Option summary printed with the usage of the generate command.
+pub const GENERATE_OPTIONS: &str = "\
+Options:
+  --config <path>         read options from a file such as multiples.toml; options given on the
+                          command line win over it
+  --input-format <name>   text, csv, json or ndjson (default: by file extension)
+  --fast-parse            read text input with a byte tokenizer reusing one line buffer, for
+                          inputs of millions of lines
+  --output-format <name>  text, json, ndjson, csv, store, auto, delta, roaring, rust-array,
+                          c-array or python-list (default: text)
+  --plugin <path>         load a formatter plugin (requires the `plugins` feature)
+  --threads <n>           number of generator threads, or auto for one per CPU within the
+                          cgroup's CPU limit (default: 1)
+  --format-threads <n>    format the multiples of lines with 64k or more of them on n threads,
+                          or auto for one per CPU; the output is the same (default: 1)
+  --nice                  lower the priority of the run's threads to niceness 10
+  --throttle <percent>    sleep between chunks so that each generator thread takes at most
+                          this share of a CPU, e.g. 50%
+  --queue-size <n>        records buffered between pipeline stages (default: 64)
+  --unordered             with --threads, pass records on as they complete instead of in
+                          input order
+  --longest-first         read the whole input first, then generate the lines with the most
+                          work first, so that a long line doesn't start last
+  --max-concurrent-heavy <n>  generate at most n lines with 10M or more multiples at once, so
+                          that small lines aren't stuck behind them (default: no limit)
+  --max-memory <n>        write lines whose multiples would take more than n bytes as they are
+                          generated instead of holding them (default: the available memory)
+  --strict-memory         fail on such lines instead
+  --max-line-length <n>   refuse an input with a line longer than n bytes
+  --max-lines <n>         refuse an input of more than n lines
+  --max-tokens <n>        refuse an input with a line of more than n words or fields
+  --max-multiples <n>     refuse an input whose lines select more than n multiples together; the
+                          limits are checked before any line is generated (default: no limits)
+  --io-backend <name>     buffered or mmap, which maps the input, parsing text input on
+                          --threads threads, and the output (default: buffered)
+  --append                append to the output, skipping records it already holds
+  --max-output-size <n>   split the output into parts of at most n bytes, e.g. 1G
+  --sink <url>            publish every record to nats://host[:port]/subject instead of writing
+                          <output> (requires the `messaging` feature)
+  --sink-format <fmt>     json or msgpack messages (default: json)
+  --sink-batch <n>        split records into messages of at most n multiples
+  --fsync <policy>        never, per-record (after every batch) or per-file (default: never)
+  --batch-size <n>        hand records to the OS n at a time (default: 1 with --fsync per-record)
+  --min-count <n>         drop records with fewer than n multiples
+  --max-count <n>         drop records with more than n multiples
+  --top <n>               keep only the n records ranking highest by --by
+  --by <key>              end, count or input (default: count)
+  --digit-sum-divisible-by <n>  keep multiples whose digit sum is divisible by n
+  --ends-with <digits>    keep multiples ending in the given digits
+  --palindrome            keep multiples that read the same backwards
+  --mode <mode>           or: multiples of a or b, xor: of exactly one of them, multiset: of a
+                          or b, listing multiples of both twice (default: or)
+  --multiset              the same as --mode multiset
+  --annotate              write which divisors each multiple matched (text, json, ndjson)
+  --group-by divisor      list the multiples of a only, of b only and of both separately
+                          (text, json, ndjson)
+  --descending            list the multiples of each line from end downward
+  --count-only            write end:count per line instead of the multiples
+  --aggregate             write one summary of all lines instead of a record per line: the
+                          total, smallest, largest and sum of the multiples, the multiples of
+                          each divisor and a table of the lines' counts (text or json)
+  --preview <n>           print the first and last n multiples and the count of every line
+                          instead of writing an output; takes only <input>
+  --power <k>             select multiples of a^k or b^k instead of a or b
+  --decimal               read a, b and end as decimals such as 0.25 and write the multiples as
+                          decimals (text or csv input; text, json, ndjson or csv output)
+  --scale <n>             decimal places of --decimal, from 0 to 9 (default: 2)
+  --time <start>          read a and b as intervals such as 90s or 7m and end as a duration or
+                          timestamp, and write the timestamps after start that fall on either
+                          interval, e.g. --time 2026-01-01T00:00:00Z
+  --shard <i/n>           generate only the i-th of n contiguous chunks of every range; join
+                          the outputs of all n shards with merge-shards
+  --strategy <name>       trial or wheel (default: trial)
+  --force-scalar          don't use the AVX2/NEON trial division kernels
+  --cross-check           compare a sample of every range with plain trial division and fail on
+                          any difference
+  --no-normalize          generate with the divisors as written, without dropping redundant ones
+  --bound <bound>         inclusive, or exclusive for the multiples below end as in Project
+                          Euler; lines may override it with bound=<bound> (default: inclusive)
+  --only-tag <tags>       generate only the lines with a tag=<name> token naming one of the comma
+                          separated tags; may be repeated
+  --skip-tag <tags>       leave out the lines tagged with one of the tags; may be repeated
+  --include-zero          start every range at 0, a multiple of every divisor (default: ranges
+                          start at 1)
+  --stop-after <n>        list only the first n multiples of every line, generating only the
+                          part of the range holding them
+  --stop-above <n>        generate no multiples above n, as if it ended every longer range
+  --degenerate <policy>   warn about lines with equal divisors, a divisor of 1 or 0, a redundant
+                          divisor or an end below both divisors, simplify them silently or
+                          error on them (default: warn)
+  --keep-going            leave lines that fail to parse or generate out of the output and go
+                          on, listing them at the end; the run still fails
+  --profile               print how long parsing, generating, formatting and writing took
+  --profile-out <path>    also write the timings as a Chrome trace (implies --profile)
+  --color <when>          auto, always or never: color the records printed to a terminal and
+                          frame the --profile table (default: auto)
+  --otlp-endpoint <url>   export the timings of every line as OpenTelemetry traces to an OTLP/HTTP
+                          collector, e.g. http://localhost:4318
+  --otlp-sample <ratio>   share of lines exported, from 0 to 1 (default: 1)
+  --progress-format <fmt> report progress on stderr as text lines or JSON events
+  --progress-interval <ms>  time between progress reports (default: 500)
+  --progress-to <path>    write the progress reports to a file or named pipe instead of stderr
+  --service               run as a systemd service: notify readiness, ping the watchdog and
+                          finish the lines in flight on SIGTERM or SIGINT
+  --pid-file <path>       write the process id to path for the length of the run
+  --fix                   write a cleaned copy of the input to <output> and a report of the
+                          changes to <output>.report instead of generating
+  --array-name <template> identifier of each array in the array formats; {a}, {b}, {end} and
+                          {index} are replaced by the line's values (default: MULTIPLES_{a}_{b}_{end})
+  --compat <version>      write exactly what release <version> wrote, e.g. 1.0: plain decimal
+                          text input and end:numbers lines sorted by count; options changing the
+                          output can't be given
+  --line-ending <ending>  lf or crlf: the line ending of text outputs (default: crlf on Windows,
+                          else lf)
+  --pure                  read only <input> and write only <output>: no pid file, trace,
+                          progress file, append index, output parts or outputs named by lines,
+                          checked on every file the run opens
+  --audit                 append the start, version, arguments, input SHA-256, output, duration
+                          and outcome of the run to the run log runs.log
+  --audit-log <path>      the same, appending to path instead
+  --provenance            start text, csv and ndjson outputs with a header naming the version and
+                          git commit of the tool, the effective options and the input's SHA-256,
+                          which `provenance` reads back
+  --checksums             write the CRC-32 of every record with it (json, ndjson, csv), which
+                          the commands reading the output check";
+
+/// Arguments of the default generate command.
+pub struct GenerateArgs {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub input_format: Option<String>,
+    pub fast_parse: bool,
+    pub output_format: String,
+    pub plugins: Vec<PathBuf>,
+    pub threads: usize,
+    pub nice: bool,
+    pub throttle: Option<Throttle>,
+    pub queue_size: usize,
+    pub unordered: bool,
+    pub longest_first: bool,
+    pub max_concurrent_heavy: Option<usize>,
+    pub max_memory: Option<u64>,
+    pub strict_memory: bool,
+    pub io_backend: IoBackend,
+    pub append: bool,
+    pub max_output_size: Option<u64>,
+    pub sink_format: String,
+    pub sink_batch: Option<usize>,
+    pub write_policy: WritePolicy,
+    pub min_count: Option<u64>,
+    pub max_count: Option<u64>,
+    pub top: Option<usize>,
+    pub by: SortKey,
+    pub digit_sum_divisor: Option<u32>,
+    pub ends_with: Option<String>,
+    pub palindrome: bool,
+    pub mode: Mode,
+    pub annotate: bool,
+    pub group_by_divisor: bool,
+    pub descending: bool,
+    pub count_only: bool,
+    pub aggregate: bool,
+    /// The multiples `--preview` prints from each end of a record.
+    pub preview: Option<usize>,
+    pub power: Option<u32>,
+    /// The decimal places of `--decimal`.
+    pub decimal: Option<u32>,
+    /// The start of `--time`, in Unix seconds.
+    pub time: Option<u64>,
+    pub shard: Option<Shard>,
+    pub strategy: Strategy,
+    pub force_scalar: bool,
+    pub cross_check: bool,
+    pub normalize: bool,
+    pub degenerate: degenerate::Policy,
+    pub keep_going: bool,
+    pub limits: InputLimits,
+    pub bound: Bound,
+    pub tags: TagFilter,
+    pub include_zero: bool,
+    pub stop_after: Option<u64>,
+    pub stop_above: Option<u32>,
+    pub profile: bool,
+    pub profile_out: Option<PathBuf>,
+    pub color: ColorChoice,
+    pub otlp_endpoint: Option<String>,
+    pub otlp_sample: f64,
+    pub progress: Option<ProgressFormat>,
+    pub progress_interval: Duration,
+    pub progress_to: Option<PathBuf>,
+    pub service: bool,
+    pub pid_file: Option<PathBuf>,
+    pub fix: bool,
+    pub array_name: Option<String>,
+    pub compat: Option<Compat>,
+    pub line_ending: LineEnding,
+    pub pure: bool,
+    /// The run log of `--audit` or `--audit-log`.
+    pub audit_log: Option<PathBuf>,
+    pub provenance: bool,
+    pub checksums: bool,
+    pub format_threads: usize,
+}
+
+/// Parses the generate command line. Returns `Ok(None)` when the positional
+/// arguments don't match so the caller can print the usage.
+pub fn parse_generate_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<GenerateArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    let mut input_format = None;
+    let mut fast_parse = false;
+    let mut output_format = String::from("text");
+    let mut plugins = Vec::new();
+    let mut threads = 1;
+    let mut nice = false;
+    let mut throttle = None;
+    let mut queue_size = DEFAULT_QUEUE_SIZE;
+    let mut unordered = false;
+    let mut longest_first = false;
+    let mut max_concurrent_heavy = None;
+    let mut max_memory = None;
+    let mut strict_memory = false;
+    let mut io_backend = IoBackend::Buffered;
+    let mut append = false;
+    let mut max_output_size = None;
+    let mut sink = None;
+    let mut sink_format = String::from("json");
+    let mut sink_batch = None;
+    let mut fsync = FsyncPolicy::Never;
+    let mut batch_size = None;
+    let mut min_count = None;
+    let mut max_count = None;
+    let mut top = None;
+    let mut by = SortKey::Count;
+    let mut digit_sum_divisor = None;
+    let mut ends_with = None;
+    let mut palindrome = false;
+    let mut mode = Mode::Or;
+    let mut annotate = false;
+    let mut group_by_divisor = false;
+    let mut descending = false;
+    let mut count_only = false;
+    let mut aggregate = false;
+    let mut preview = None;
+    let mut power = None;
+    let mut decimal = false;
+    let mut scale = None;
+    let mut time = None;
+    let mut shard = None;
+    let mut strategy = Strategy::Trial;
+    let mut force_scalar = false;
+    let mut cross_check = false;
+    let mut normalize = true;
+    let mut degenerate = degenerate::Policy::Warn;
+    let mut keep_going = false;
+    let mut limits = InputLimits::default();
+    let mut bound = Bound::Inclusive;
+    let mut tags = TagFilter::default();
+    let mut include_zero = false;
+    let mut stop_after = None;
+    let mut stop_above = None;
+    let mut profile = false;
+    let mut profile_out = None;
+    let mut color = ColorChoice::Auto;
+    let mut otlp_endpoint = None;
+    let mut otlp_sample = 1.0;
+    let mut progress = None;
+    let mut progress_interval = Duration::from_millis(500);
+    let mut progress_to = None;
+    let mut service = false;
+    let mut pid_file = None;
+    let mut fix = false;
+    let mut array_name = None;
+    let mut compat = None;
+    let mut line_ending = None;
+    let mut pure = false;
+    let mut audit_log = None;
+    let mut provenance = false;
+    let mut checksums = false;
+    let mut format_threads = 1;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(value),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--input-format" => input_format = Some(stream.value(&flag)?),
+                "--fast-parse" => fast_parse = true,
+                "--output-format" => output_format = stream.value(&flag)?,
+                "--plugin" => plugins.push(PathBuf::from(stream.value(&flag)?)),
+                "--threads" => threads = stream.threads(&flag)?,
+                "--format-threads" => format_threads = stream.threads(&flag)?,
+                "--nice" => nice = true,
+                "--throttle" => throttle = Some(stream.value(&flag)?.parse()?),
+                "--queue-size" => queue_size = stream.count(&flag)?,
+                "--longest-first" => longest_first = true,
+                "--max-concurrent-heavy" => max_concurrent_heavy = Some(stream.count(&flag)?),
+                "--max-memory" => max_memory = Some(stream.number(&flag)?),
+                "--strict-memory" => strict_memory = true,
+                "--max-line-length" | "--max-lines" | "--max-tokens" | "--max-multiples" => {
+                    limit(&mut stream, &flag, &mut limits)?
+                }
+                "--unordered" => unordered = true,
+                "--io-backend" => io_backend = stream.value(&flag)?.parse()?,
+                "--append" => append = true,
+                "--max-output-size" => max_output_size = Some(stream.number(&flag)?),
+                "--sink" => sink = Some(stream.value(&flag)?),
+                "--sink-format" => sink_format = stream.value(&flag)?,
+                "--sink-batch" => sink_batch = Some(stream.count(&flag)?),
+                "--fsync" => fsync = stream.value(&flag)?.parse()?,
+                "--batch-size" => batch_size = Some(stream.count(&flag)?),
+                "--min-count" => min_count = Some(stream.number(&flag)?),
+                "--max-count" => max_count = Some(stream.number(&flag)?),
+                "--top" => top = Some(stream.count(&flag)?),
+                "--by" => by = stream.value(&flag)?.parse()?,
+                "--digit-sum-divisible-by" => {
+                    let divisor = u32::try_from(stream.count(&flag)?);
+                    digit_sum_divisor = Some(divisor.map_err(|_| INVALID_OPTION_VALUE.error(format!("Invalid value for {}: too large", flag)))?);
+                }
+                "--ends-with" => ends_with = Some(stream.value(&flag)?),
+                "--palindrome" => palindrome = true,
+                "--mode" => mode = stream.value(&flag)?.parse()?,
+                "--multiset" => mode = Mode::Multiset,
+                "--annotate" => annotate = true,
+                "--group-by" => match stream.value(&flag)?.as_str() {
+                    "divisor" => group_by_divisor = true,
+                    other => bail!("Unknown grouping: {} (available: divisor)", other),
+                },
+                "--descending" => descending = true,
+                "--count-only" => count_only = true,
+                "--aggregate" => aggregate = true,
+                "--preview" => preview = Some(stream.count(&flag)?),
+                "--decimal" => decimal = true,
+                "--scale" => scale = Some(stream.number(&flag)?),
+                "--time" => time = Some(parse_timestamp(&stream.value(&flag)?)?),
+                "--shard" => shard = Some(stream.value(&flag)?.parse()?),
+                "--strategy" => strategy = stream.value(&flag)?.parse()?,
+                "--force-scalar" => force_scalar = true,
+                "--cross-check" => cross_check = true,
+                "--no-normalize" => normalize = false,
+                "--degenerate" => degenerate = stream.value(&flag)?.parse()?,
+                "--keep-going" => keep_going = true,
+                "--bound" => bound = stream.value(&flag)?.parse()?,
+                "--only-tag" => tags.only(&stream.value(&flag)?)?,
+                "--skip-tag" => tags.skip(&stream.value(&flag)?)?,
+                "--include-zero" => include_zero = true,
+                "--stop-after" => stop_after = Some(stream.count(&flag)? as u64),
+                "--stop-above" => {
+                    let value = u32::try_from(stream.number(&flag)?);
+                    stop_above = Some(value.map_err(|_| INVALID_OPTION_VALUE.error(format!("Invalid value for {}: too large", flag)))?);
+                }
+                "--profile" => profile = true,
+                "--profile-out" => {
+                    profile = true;
+                    profile_out = Some(PathBuf::from(stream.value(&flag)?));
+                }
+                "--color" => color = stream.value(&flag)?.parse()?,
+                "--otlp-endpoint" => otlp_endpoint = Some(stream.value(&flag)?),
+                "--otlp-sample" => otlp_sample = sample_ratio(&mut stream, &flag)?,
+                "--progress-format" => progress = Some(stream.value(&flag)?.parse()?),
+                "--progress-interval" => progress_interval = Duration::from_millis(stream.count(&flag)? as u64),
+                "--progress-to" => progress_to = Some(PathBuf::from(stream.value(&flag)?)),
+                "--service" => service = true,
+                "--pid-file" => pid_file = Some(PathBuf::from(stream.value(&flag)?)),
+                "--fix" => fix = true,
+                "--power" => {
+                    let exponent = u32::try_from(stream.count(&flag)?);
+                    power = Some(exponent.map_err(|_| INVALID_OPTION_VALUE.error(format!("Invalid value for {}: too large", flag)))?);
+                }
+                "--array-name" => {
+                    let name = stream.value(&flag)?;
+                    validate_array_name(&name)?;
+                    array_name = Some(name);
+                }
+                "--compat" => compat = Some(stream.value(&flag)?.parse()?),
+                "--line-ending" => line_ending = Some(stream.value(&flag)?.parse()?),
+                "--pure" => pure = true,
+                "--audit" => audit_log = audit_log.or_else(|| Some(PathBuf::from(runlog::DEFAULT_LOG))),
+                "--audit-log" => audit_log = Some(PathBuf::from(stream.value(&flag)?)),
+                "--provenance" => provenance = true,
+                "--checksums" => checksums = true,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    if compat.is_some() {
+        let changed = [
+            (input_format.is_some() || fast_parse, "--input-format"),
+            (output_format != "text" || !plugins.is_empty() || array_name.is_some(), "--output-format"),
+            (mode != Mode::Or, "--mode"),
+            (count_only || aggregate || preview.is_some(), "--count-only, --aggregate or --preview"),
+            (min_count.is_some() || max_count.is_some() || top.is_some(), "--min-count, --max-count or --top"),
+            (digit_sum_divisor.is_some() || ends_with.is_some() || palindrome, "digit filters"),
+            (annotate || group_by_divisor || descending, "--annotate, --group-by or --descending"),
+            (power.is_some() || decimal || time.is_some() || shard.is_some(), "--power, --decimal, --time or --shard"),
+            (include_zero || stop_after.is_some() || stop_above.is_some(), "--include-zero or --stop-*"),
+            (bound != Bound::Inclusive, "--bound"),
+            (!tags.is_empty(), "--only-tag or --skip-tag"),
+            (append || max_output_size.is_some() || sink.is_some(), "--append, --max-output-size or --sink"),
+            (keep_going || fix, "--keep-going or --fix"),
+            (line_ending.is_some(), "--line-ending"),
+            (provenance || checksums, "--provenance or --checksums"),
+        ];
+        if let Some((_, flags)) = changed.iter().find(|(changed, _)| *changed) {
+            bail!("--compat can't be combined with {}, which change the output", flags);
+        }
+        // Release 1.0 read every input as text, warned about nothing and
+        // wrote \n on every platform.
+        line_ending = Some(LineEnding::Lf);
+        input_format = Some(String::from("text"));
+        color = ColorChoice::Never;
+        degenerate = degenerate::Policy::Simplify;
+    }
+    if pure {
+        let other_files = [
+            (append || max_output_size.is_some(), "--append or --max-output-size"),
+            (profile_out.is_some() || progress_to.is_some(), "--profile-out or --progress-to"),
+            (service || pid_file.is_some(), "--service or --pid-file"),
+            (sink.is_some() || otlp_endpoint.is_some(), "--sink or --otlp-endpoint"),
+            (!plugins.is_empty() || fix, "--plugin or --fix"),
+            (audit_log.is_some(), "--audit or --audit-log"),
+        ];
+        if let Some((_, flags)) = other_files.iter().find(|(other, _)| *other) {
+            bail!("--pure can't be combined with {}, which read or write more than the input and output", flags);
+        }
+    }
+    if max_output_size.is_some() && (append || io_backend != IoBackend::Buffered) {
+        bail!("--max-output-size can't be combined with --append or --io-backend mmap");
+    }
+    if io_backend == IoBackend::Mmap && (fsync == FsyncPolicy::PerRecord || batch_size.is_some()) {
+        bail!("--io-backend mmap writes the output at once; use --fsync per-file without --batch-size");
+    }
+    if progress_to.is_some() && progress.is_none() {
+        bail!("--progress-to needs --progress-format");
+    }
+    if annotate && group_by_divisor {
+        bail!("--annotate can't be combined with --group-by");
+    }
+    if degenerate == degenerate::Policy::Simplify && !normalize {
+        bail!("--degenerate simplify can't be combined with --no-normalize");
+    }
+    if (stop_after.is_some() || stop_above.is_some()) && (shard.is_some() || cross_check) {
+        bail!("--stop-after and --stop-above can't be combined with --shard or --cross-check");
+    }
+    if aggregate && (annotate || group_by_divisor || append || max_output_size.is_some() || sink.is_some()) {
+        bail!("--aggregate can't be combined with --annotate, --group-by, --append, --max-output-size or --sink");
+    }
+    let headed = provenance::FORMATS.contains(&output_format.as_str());
+    if provenance && (!headed || aggregate || append || preview.is_some() || sink.is_some() || fix) {
+        bail!(
+            "--provenance heads text, csv and ndjson outputs; it can't be combined with --aggregate, --append, \
+             --preview, --sink or --fix"
+        );
+    }
+    let checksummed = CHECKSUMMED_FORMATS.contains(&output_format.as_str());
+    if checksums && (!checksummed || aggregate || preview.is_some() || decimal || time.is_some()) {
+        bail!(
+            "--checksums checks the records of json, ndjson and csv outputs; it can't be combined with --aggregate, \
+             --preview, --decimal or --time"
+        );
+    }
+    // Rejected before the output is opened, which the formatters would only fail on the first record.
+    let multiples_only = output_format == "roaring" || ARRAY_FORMATS.iter().any(|(name, _)| *name == output_format);
+    if count_only && multiples_only {
+        return Err(UNSUPPORTED_BY_FORMAT.error(format!(
+            "--count-only can't be combined with --output-format {}, which holds multiples only",
+            output_format
+        )));
+    }
+    let decimal = match (decimal, scale) {
+        (false, Some(_)) => bail!("--scale needs --decimal"),
+        (false, None) => None,
+        (true, Some(scale)) if scale > MAX_SCALE as u64 => bail!("Invalid value for --scale: at most {} decimal places", MAX_SCALE),
+        (true, scale) => Some(scale.unwrap_or(2) as u32),
+    };
+    let has_digit_filter = digit_sum_divisor.is_some() || ends_with.is_some() || palindrome;
+    let integer_only = annotate || group_by_divisor || aggregate || append || fix || power.is_some() || has_digit_filter;
+    if decimal.is_some() && time.is_some() {
+        bail!("--decimal can't be combined with --time");
+    }
+    if stop_above.is_some() && (decimal.is_some() || time.is_some()) {
+        bail!("--stop-above can't be combined with --decimal or --time, whose values are scaled");
+    }
+    if fast_parse && (decimal.is_some() || time.is_some()) {
+        bail!("--fast-parse reads integer input; it can't be combined with --decimal or --time");
+    }
+    if (decimal.is_some() || time.is_some()) && (integer_only || !plugins.is_empty() || array_name.is_some() || sink.is_some()) {
+        bail!(
+            "--decimal and --time can't be combined with --annotate, --group-by, --aggregate, --append, --fix, --power, \
+             digit filters, --plugin, --array-name or --sink"
+        );
+    }
+    let writes_output = aggregate || append || fix || max_output_size.is_some() || sink.is_some();
+    if preview.is_some() && (writes_output || decimal.is_some() || time.is_some()) {
+        bail!(
+            "--preview writes no output; it can't be combined with --aggregate, --append, --fix, --max-output-size, \
+             --sink, --decimal or --time"
+        );
+    }
+    // A sink takes the place of the output file, and previews have none.
+    positionals.extend(sink);
+    if positionals.len() != if preview.is_some() { 1 } else { 2 } {
+        return Ok(None);
+    }
+    let output = match preview {
+        Some(_) => PathBuf::new(),
+        None => PathBuf::from(positionals.pop().unwrap()),
+    };
+    let input = PathBuf::from(positionals.pop().unwrap());
+    Ok(Some(GenerateArgs {
+        input,
+        output,
+        input_format,
+        fast_parse,
+        output_format,
+        plugins,
+        threads,
+        nice,
+        throttle,
+        queue_size,
+        unordered,
+        longest_first,
+        max_concurrent_heavy,
+        max_memory,
+        strict_memory,
+        io_backend,
+        append,
+        max_output_size,
+        sink_format,
+        sink_batch,
+        write_policy: WritePolicy { fsync, batch_size },
+        min_count,
+        max_count,
+        top,
+        by,
+        digit_sum_divisor,
+        ends_with,
+        palindrome,
+        mode,
+        annotate,
+        group_by_divisor,
+        descending,
+        count_only,
+        aggregate,
+        preview,
+        power,
+        decimal,
+        time,
+        shard,
+        strategy,
+        force_scalar,
+        cross_check,
+        normalize,
+        degenerate,
+        keep_going,
+        limits,
+        bound,
+        tags,
+        include_zero,
+        stop_after,
+        stop_above,
+        profile,
+        profile_out,
+        color,
+        otlp_endpoint,
+        otlp_sample,
+        progress,
+        progress_interval,
+        progress_to,
+        service,
+        pid_file,
+        fix,
+        array_name,
+        compat,
+        line_ending: line_ending.unwrap_or_else(LineEnding::native),
+        pure,
+        audit_log,
+        provenance,
+        checksums,
+        format_threads,
+    }))
+}
+
+pub const MERGE_OPTIONS: &str = "\
+Options:
+  --merge-mode <mode>     concat, dedup or union (default: concat)
+  --input-format <name>   format of the inputs (default: by file extension)
+  --output-format <name>  format of the output (default: format of the first input)
+  --checksums             write the CRC-32 of every record with it (json, ndjson, csv)";
+
+/// Arguments of the `merge` command.
+pub struct MergeArgs {
+    pub inputs: Vec<PathBuf>,
+    pub output: PathBuf,
+    pub mode: MergeMode,
+    pub input_format: Option<String>,
+    pub output_format: Option<String>,
+    pub checksums: bool,
+}
+
+/// Parses `merge [options] <inputs>... <output>`. Returns `Ok(None)` when
+/// fewer than two paths are given.
+pub fn parse_merge_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<MergeArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut paths = Vec::new();
+    let mut mode = MergeMode::Concat;
+    let mut input_format = None;
+    let mut output_format = None;
+    let mut checksums = false;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => paths.push(PathBuf::from(value)),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--merge-mode" => mode = stream.value(&flag)?.parse()?,
+                "--input-format" => input_format = Some(stream.value(&flag)?),
+                "--output-format" => output_format = Some(stream.value(&flag)?),
+                "--checksums" => checksums = true,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    if paths.len() < 2 {
+        return Ok(None);
+    }
+    let output = paths.pop().unwrap();
+    Ok(Some(MergeArgs {
+        inputs: paths,
+        output,
+        mode,
+        input_format,
+        output_format,
+        checksums,
+    }))
+}
+
+pub const MERGE_SHARDS_OPTIONS: &str = "\
+Options:
+  --mode <mode>           mode the shards were generated with, to check the merged counts
+                          (default: or)
+  --no-verify             don't check the merged counts, e.g. for shards generated with digit
+                          filters
+  --descending            list the merged multiples from end downward
+  --input-format <name>   format of the shards (default: by file extension)
+  --output-format <name>  format of the output (default: format of the first shard)";
+
+/// Arguments of the `merge-shards` command.
+pub struct MergeShardsArgs {
+    pub shards: Vec<PathBuf>,
+    pub output: PathBuf,
+    /// The mode to check merged counts against, `None` with `--no-verify`.
+    pub verify: Option<Mode>,
+    pub descending: bool,
+    pub input_format: Option<String>,
+    pub output_format: Option<String>,
+}
+
+/// Parses `merge-shards [options] <shards>... <output>`. Returns `Ok(None)`
+/// when fewer than two paths are given.
+pub fn parse_merge_shards_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<MergeShardsArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut paths = Vec::new();
+    let mut mode = Mode::Or;
+    let mut verify = true;
+    let mut descending = false;
+    let mut input_format = None;
+    let mut output_format = None;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => paths.push(PathBuf::from(value)),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--mode" => mode = stream.value(&flag)?.parse()?,
+                "--no-verify" => verify = false,
+                "--descending" => descending = true,
+                "--input-format" => input_format = Some(stream.value(&flag)?),
+                "--output-format" => output_format = Some(stream.value(&flag)?),
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    if paths.len() < 2 {
+        return Ok(None);
+    }
+    let output = paths.pop().unwrap();
+    Ok(Some(MergeShardsArgs {
+        shards: paths,
+        output,
+        verify: verify.then_some(mode),
+        descending,
+        input_format,
+        output_format,
+    }))
+}
+
+pub const BACKFILL_OPTIONS: &str = "\
+Options:
+  --target <end>          the last end of the series, e.g. 1e7
+  --stride <n>            the step between the ends of the series, e.g. 1e6
+  --mode <mode>           mode the existing files were generated with (default: or)
+  --name <template>       name of the new files in the directory, with {a}, {b} and {end}
+                          (default: like the existing files, with their end replaced)
+  --dry-run               list the files that would be generated without generating them";
+
+/// Arguments of the `backfill` command.
+pub struct BackfillArgs {
+    pub dir: PathBuf,
+    pub target: u32,
+    pub stride: u32,
+    pub mode: Mode,
+    pub naming: Naming,
+    pub dry_run: bool,
+}
+
+/// Parses `backfill --target <end> --stride <n> [options] <dir>`. Returns
+/// `Ok(None)` without a directory, a target or a stride.
+pub fn parse_backfill_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<BackfillArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut paths = Vec::new();
+    let mut target = None;
+    let mut stride = None;
+    let mut mode = Mode::Or;
+    let mut naming = Naming::LikeExisting;
+    let mut dry_run = false;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => paths.push(PathBuf::from(value)),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--target" => target = Some(end_value(&mut stream, &flag)?),
+                "--stride" => stride = Some(end_value(&mut stream, &flag)?),
+                "--mode" => mode = stream.value(&flag)?.parse()?,
+                "--name" => naming = Naming::Template(stream.value(&flag)?),
+                "--dry-run" => dry_run = true,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    let (Some(target), Some(stride)) = (target, stride) else {
+        return Ok(None);
+    };
+    if paths.len() != 1 {
+        return Ok(None);
+    }
+    Ok(Some(BackfillArgs {
+        dir: paths.pop().unwrap(),
+        target,
+        stride,
+        mode,
+        naming,
+        dry_run,
+    }))
+}
+
+pub const SWEEP_OPTIONS: &str = "\
+Options:
+  --a <values>            values of a: a number, a list such as 3,5,7 or a range such as 2..10
+                          or 2..20:2, with its step
+  --b <values>            values of b, likewise
+  --end <values>          values of end, likewise, e.g. 1e6 or 1e6..1e7:1e6
+  --where <constraint>    run only the combinations meeting a constraint such as a<b,
+                          gcd(a,b)==1 or end%lcm(a,b)==0; may be given several times
+  --require <names>       run only the combinations meeting all of coprime, a-prime, b-prime
+                          and a<b named, e.g. coprime,a-prime; may be given several times
+  --output-format <name>  format of the outputs, written to <dir>/a=<a>/b=<b>/end=<end>.<ext>
+                          (default: text)
+  --dry-run               list the jobs without running them
+
+Options after -- are passed on to the generate run, e.g. -- --threads 4 --count-only";
+
+/// Arguments of the `sweep` command.
+pub struct SweepArgs {
+    pub sweep: Sweep,
+    pub dir: PathBuf,
+    pub output_format: String,
+    pub dry_run: bool,
+    /// Generate options the jobs run with.
+    pub options: Vec<String>,
+}
+
+/// Parses `sweep --a <values> --b <values> --end <values> [options] <dir>
+/// [-- <generate options>]`. Returns `Ok(None)` without a directory or the
+/// values of a parameter.
+pub fn parse_sweep_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<SweepArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    let (mut a, mut b, mut end) = (None, None, None);
+    let mut constraints = Vec::new();
+    let mut filter = JobFilter::default();
+    let mut output_format = String::from("text");
+    let mut dry_run = false;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(value),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--a" => a = Some(stream.value(&flag)?.parse().with_context(|| format!("Invalid value for {}", flag))?),
+                "--b" => b = Some(stream.value(&flag)?.parse().with_context(|| format!("Invalid value for {}", flag))?),
+                "--end" => end = Some(stream.value(&flag)?.parse().with_context(|| format!("Invalid value for {}", flag))?),
+                "--where" => constraints.push(stream.value(&flag)?.parse()?),
+                "--require" => filter.require(&stream.value(&flag)?)?,
+                "--output-format" => output_format = stream.value(&flag)?,
+                "--dry-run" => dry_run = true,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    let (Some(a), Some(b), Some(end)) = (a, b, end) else {
+        return Ok(None);
+    };
+    if positionals.is_empty() {
+        return Ok(None);
+    }
+    let dir = PathBuf::from(positionals.remove(0));
+    if let Some(option) = positionals.iter().find(|option| option.starts_with("--output-format")) {
+        bail!("Give the format of a sweep as its own --output-format, not as the generate option {}", option);
+    }
+    Ok(Some(SweepArgs {
+        sweep: Sweep { a, b, end, constraints, filter },
+        dir,
+        output_format,
+        dry_run,
+        options: positionals,
+    }))
+}
+
+/// Returns the value belonging to `flag` as a positive end of a range.
+fn end_value(stream: &mut ArgStream, flag: &str) -> Result<u32> {
+    match u32::try_from(stream.number(flag)?) {
+        Ok(0) | Err(_) => Err(INVALID_OPTION_VALUE.error(format!("Invalid value for {}: must be from 1 to {}", flag, u32::MAX))),
+        Ok(end) => Ok(end),
+    }
+}
+
+pub const CONVERT_OPTIONS: &str = "\
+Options:
+  --input-format <name>   format of the input (default: by file extension)
+  --output-format <name>  format of the output (default: by file extension)
+  --checksums             write the CRC-32 of every record with it (json, ndjson, csv)";
+
+/// Arguments of the `convert` command.
+pub struct ConvertArgs {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub input_format: Option<String>,
+    pub output_format: Option<String>,
+    pub checksums: bool,
+}
+
+/// Parses `convert [options] <input> <output>`.
+pub fn parse_convert_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<ConvertArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut paths = Vec::new();
+    let mut input_format = None;
+    let mut output_format = None;
+    let mut checksums = false;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => paths.push(PathBuf::from(value)),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--input-format" => input_format = Some(stream.value(&flag)?),
+                "--output-format" => output_format = Some(stream.value(&flag)?),
+                "--checksums" => checksums = true,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    if paths.len() != 2 {
+        return Ok(None);
+    }
+    let output = paths.pop().unwrap();
+    let input = paths.pop().unwrap();
+    Ok(Some(ConvertArgs {
+        input,
+        output,
+        input_format,
+        output_format,
+        checksums,
+    }))
+}
+
+pub const MIGRATE_OPTIONS: &str = "\
+Options:
+  --input-format <name>   format of the input (default: by file extension)
+  --output-format <name>  format of the output (default: that of the input)
+  --checksums             write the CRC-32 of every record with it (json, ndjson)";
+
+/// Parses `migrate [options] <input> <output>`, whose options are those of
+/// `convert`.
+pub fn parse_migrate_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<ConvertArgs>> {
+    parse_convert_args(args)
+}
+
+pub const DESCRIBE_OPTIONS: &str = "\
+Options:
+  --input-format <name>   format of the file (default: by its contents, then its extension)
+  --records <n>           records to list (default: 10)";
+
+/// Arguments of the `describe` command.
+pub struct DescribeArgs {
+    pub path: PathBuf,
+    pub input_format: Option<String>,
+    pub records: usize,
+}
+
+/// Parses `describe [options] <file>`.
+pub fn parse_describe_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<DescribeArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut paths = Vec::new();
+    let mut input_format = None;
+    let mut records = 10;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => paths.push(PathBuf::from(value)),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--input-format" => input_format = Some(stream.value(&flag)?),
+                "--records" => records = stream.count(&flag)?,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    if paths.len() != 1 {
+        return Ok(None);
+    }
+    Ok(Some(DescribeArgs {
+        path: paths.pop().unwrap(),
+        input_format,
+        records,
+    }))
+}
+
+pub const PROVENANCE_OPTIONS: &str = "\
+Options:
+  --verify                check that this build wrote the file from its input as it is now: the
+                          version, the git commit and the input's SHA-256 must match";
+
+/// Arguments of the `provenance` command.
+pub struct ProvenanceArgs {
+    pub path: PathBuf,
+    pub verify: bool,
+}
+
+/// Parses `provenance [--verify] <file>`.
+pub fn parse_provenance_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<ProvenanceArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut paths = Vec::new();
+    let mut verify = false;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => paths.push(PathBuf::from(value)),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--verify" => verify = true,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    if paths.len() != 1 {
+        return Ok(None);
+    }
+    Ok(Some(ProvenanceArgs {
+        path: paths.pop().unwrap(),
+        verify,
+    }))
+}
+
+pub const SORT_OPTIONS: &str = "\
+Options:
+  --by <key>              end, count or input (default: count)
+  --max-memory <bytes>    records kept in memory before spilling to disk (default: 1G)
+  --temp-dir <dir>        directory for spilled runs (default: system temp dir)
+  --input-format <name>   format of the input (default: by file extension)
+  --output-format <name>  format of the output (default: by file extension)";
+
+/// Arguments of the `sort` command.
+pub struct SortArgs {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub by: SortKey,
+    pub max_memory: u64,
+    pub temp_dir: PathBuf,
+    pub input_format: Option<String>,
+    pub output_format: Option<String>,
+}
+
+/// Parses `sort [options] <input> <output>`.
+pub fn parse_sort_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<SortArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut paths = Vec::new();
+    let mut by = SortKey::Count;
+    let mut max_memory = DEFAULT_MAX_MEMORY;
+    let mut temp_dir = std::env::temp_dir();
+    let mut input_format = None;
+    let mut output_format = None;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => paths.push(PathBuf::from(value)),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--by" => by = stream.value(&flag)?.parse()?,
+                "--max-memory" => max_memory = stream.number(&flag)?,
+                "--temp-dir" => temp_dir = PathBuf::from(stream.value(&flag)?),
+                "--input-format" => input_format = Some(stream.value(&flag)?),
+                "--output-format" => output_format = Some(stream.value(&flag)?),
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    if paths.len() != 2 {
+        return Ok(None);
+    }
+    let output = paths.pop().unwrap();
+    let input = paths.pop().unwrap();
+    Ok(Some(SortArgs {
+        input,
+        output,
+        by,
+        max_memory,
+        temp_dir,
+        input_format,
+        output_format,
+    }))
+}
+
+pub const ANALYZE_OPTIONS: &str = "\
+Analyses:
+  gaps                    the distribution of the gaps between consecutive multiples of each line
+
+Options:
+  --mode <mode>           or, xor or multiset (default: or)
+  --percentiles <list>    comma separated percentiles of the gaps to report (default: 50,90,99)
+  --input-format <name>   text, csv, json or ndjson (default: by file extension)
+  --output-format <name>  text or json (default: text)";
+
+/// Arguments of the `analyze` command.
+pub struct AnalyzeArgs {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub mode: Mode,
+    pub percentiles: Vec<f64>,
+    pub input_format: Option<String>,
+    pub output_format: String,
+}
+
+/// Parses `analyze gaps [options] <input> <output>`. Returns `Ok(None)`
+/// when the positional arguments don't match.
+pub fn parse_analyze_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<AnalyzeArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    let mut mode = Mode::Or;
+    let mut percentiles = vec![50.0, 90.0, 99.0];
+    let mut input_format = None;
+    let mut output_format = String::from("text");
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(value),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--mode" => mode = stream.value(&flag)?.parse()?,
+                "--percentiles" => percentiles = parse_percentiles(&stream.value(&flag)?)?,
+                "--input-format" => input_format = Some(stream.value(&flag)?),
+                "--output-format" => output_format = stream.value(&flag)?,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    if !matches!(output_format.as_str(), "text" | "json") {
+        bail!("Unknown output format: {} (available: text, json)", output_format);
+    }
+    let Ok([analysis, input, output]) = <[String; 3]>::try_from(positionals) else {
+        return Ok(None);
+    };
+    if analysis != "gaps" {
+        bail!("Unknown analysis: {} (available: gaps)", analysis);
+    }
+    Ok(Some(AnalyzeArgs {
+        input: PathBuf::from(input),
+        output: PathBuf::from(output),
+        mode,
+        percentiles,
+        input_format,
+        output_format,
+    }))
+}
+
+pub const SETOPS_OPTIONS: &str = "\
+Options:
+  --op <name>             intersection, union or difference (default: intersection)
+  --count                 print only the number of results";
+
+/// Arguments of the `setops` command.
+pub struct SetOpsArgs {
+    pub op: SetOp,
+    pub first: (u32, u32),
+    pub second: (u32, u32),
+    pub end: u32,
+    pub count_only: bool,
+}
+
+/// Parses `setops [options] <a1> <b1> <a2> <b2> <end>`.
+pub fn parse_setops_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<SetOpsArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut numbers = Vec::new();
+    let mut op = SetOp::Intersection;
+    let mut count_only = false;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => numbers.push(number::parse_u32(&value)?),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--op" => op = stream.value(&flag)?.parse()?,
+                "--count" => count_only = true,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    let [a1, b1, a2, b2, end] = numbers[..] else {
+        return Ok(None);
+    };
+    Ok(Some(SetOpsArgs {
+        op,
+        first: (a1, b1),
+        second: (a2, b2),
+        end,
+        count_only,
+    }))
+}
+
+pub const BITMAP_OPTIONS: &str = "\
+Actions:
+  union <inputs>...       the multiples in any record of any input
+  intersect <inputs>...   the multiples in some record of every input
+  rank <input> <n>        for every record, how many of its multiples are at most n
+  select <input> <i>      for every record, its i-th smallest multiple, counting from 1
+
+Options:
+  --count                 with union and intersect, print only the number of results
+  --output <path>         with union and intersect, write the result to a roaring file instead";
+
+/// What the `bitmap` command does with its inputs.
+#[derive(Debug, PartialEq)]
+pub enum BitmapAction {
+    Union,
+    Intersect,
+    Rank(u32),
+    Select(u64),
+}
+
+/// Arguments of the `bitmap` command.
+pub struct BitmapArgs {
+    pub action: BitmapAction,
+    pub inputs: Vec<PathBuf>,
+    pub count_only: bool,
+    pub output: Option<PathBuf>,
+}
+
+/// Parses `bitmap <action> [options] <inputs>...`.
+pub fn parse_bitmap_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<BitmapArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    let mut count_only = false;
+    let mut output = None;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(value),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--count" => count_only = true,
+                "--output" => output = Some(PathBuf::from(stream.value(&flag)?)),
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    let Some((action, rest)) = positionals.split_first() else {
+        return Ok(None);
+    };
+    let action = match (action.as_str(), rest) {
+        ("union", [_, ..]) => BitmapAction::Union,
+        ("intersect", [_, ..]) => BitmapAction::Intersect,
+        ("rank", [_, n]) => BitmapAction::Rank(number::parse_u32(n)?),
+        ("select", [_, i]) => match number::parse_u32(i)? {
+            0 => bail!("Invalid value for select: multiples are counted from 1"),
+            i => BitmapAction::Select(i as u64),
+        },
+        ("union" | "intersect" | "rank" | "select", _) => return Ok(None),
+        (action, _) => bail!("Unknown bitmap action: {} (available: union, intersect, rank, select)", action),
+    };
+    let sets = matches!(action, BitmapAction::Union | BitmapAction::Intersect);
+    if !sets && (count_only || output.is_some()) {
+        bail!("--count and --output only apply to union and intersect");
+    }
+    let inputs = match action {
+        BitmapAction::Union | BitmapAction::Intersect => rest.iter().map(PathBuf::from).collect(),
+        _ => vec![PathBuf::from(&rest[0])],
+    };
+    Ok(Some(BitmapArgs {
+        action,
+        inputs,
+        count_only,
+        output,
+    }))
+}
+
+pub const EXPLAIN_OPTIONS: &str = "\
+Options:
+  --input-format <name>   text, csv, json or ndjson (default: by file extension)
+  --output-format <name>  format the sizes are predicted for (default: text)
+  --threads <n>           number of generator threads, or auto for one per CPU (default: 1)
+  --mode <mode>           or, xor or multiset (default: or)
+  --multiset              the same as --mode multiset
+  --count-only            plan writing only the number of multiples
+  --power <k>             select multiples of a^k or b^k instead of a or b
+  --strategy <name>       trial or wheel (default: trial)
+  --force-scalar          don't use the AVX2/NEON trial division kernels
+  --no-normalize          plan with the divisors as written, without dropping redundant ones
+  --longest-first         plan generating the lines with the most work first, as generate
+                          --longest-first does";
+
+/// Arguments of the `explain` command, a subset of the generate options.
+pub struct ExplainArgs {
+    pub input: PathBuf,
+    pub input_format: Option<String>,
+    pub output_format: String,
+    pub threads: usize,
+    pub mode: Mode,
+    pub count_only: bool,
+    pub power: Option<u32>,
+    pub strategy: Strategy,
+    pub force_scalar: bool,
+    pub normalize: bool,
+    pub longest_first: bool,
+}
+
+/// Parses `explain [options] <input>`.
+pub fn parse_explain_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<ExplainArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    let mut input_format = None;
+    let mut output_format = String::from("text");
+    let mut threads = 1;
+    let mut mode = Mode::Or;
+    let mut count_only = false;
+    let mut power = None;
+    let mut strategy = Strategy::Trial;
+    let mut force_scalar = false;
+    let mut normalize = true;
+    let mut longest_first = false;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(value),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--input-format" => input_format = Some(stream.value(&flag)?),
+                "--output-format" => output_format = stream.value(&flag)?,
+                "--threads" => threads = stream.threads(&flag)?,
+                "--longest-first" => longest_first = true,
+                "--mode" => mode = stream.value(&flag)?.parse()?,
+                "--multiset" => mode = Mode::Multiset,
+                "--count-only" => count_only = true,
+                "--power" => {
+                    let exponent = u32::try_from(stream.count(&flag)?);
+                    power = Some(exponent.map_err(|_| INVALID_OPTION_VALUE.error(format!("Invalid value for {}: too large", flag)))?);
+                }
+                "--strategy" => strategy = stream.value(&flag)?.parse()?,
+                "--force-scalar" => force_scalar = true,
+                "--no-normalize" => normalize = false,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    let [input] = &positionals[..] else {
+        return Ok(None);
+    };
+    Ok(Some(ExplainArgs {
+        input: PathBuf::from(input),
+        input_format,
+        output_format,
+        threads,
+        mode,
+        count_only,
+        power,
+        strategy,
+        force_scalar,
+        normalize,
+        longest_first,
+    }))
+}
+
+pub const GET_OPTIONS: &str = "\
+Options:
+  --count                 print only the number of multiples";
+
+/// Arguments of the `get` command.
+pub struct GetArgs {
+    pub store: PathBuf,
+    pub job: LineNumbers,
+    pub count_only: bool,
+}
+
+/// Parses `get [options] <store> <a> <b> <end>`.
+pub fn parse_get_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<GetArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    let mut count_only = false;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(value),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--count" => count_only = true,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    let [store, a, b, end] = &positionals[..] else {
+        return Ok(None);
+    };
+    Ok(Some(GetArgs {
+        store: PathBuf::from(store),
+        job: LineNumbers::new(number::parse_u32(a)?, number::parse_u32(b)?, number::parse_u32(end)?)?,
+        count_only,
+    }))
+}
+
+/// Parses `list <store>`, returning the store's path.
+pub fn parse_list_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<PathBuf>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(PathBuf::from(value)),
+            Arg::Flag(flag) => return Err(unknown_option(&flag)),
+        }
+    }
+    Ok(if positionals.len() == 1 { positionals.pop() } else { None })
+}
+
+/// Parses `init <dir>`, returning the directory to scaffold.
+pub fn parse_init_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<PathBuf>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(PathBuf::from(value)),
+            Arg::Flag(flag) => return Err(unknown_option(&flag)),
+        }
+    }
+    Ok(if positionals.len() == 1 { positionals.pop() } else { None })
+}
+
+pub const REGRESS_OPTIONS: &str = "\
+Options:
+  --cases <dir>           folder of cases, each a folder holding input.txt, options.toml and
+                          expected.* (default: cases)";
+
+/// Parses `regress [options]`, returning the folder of cases.
+pub fn parse_regress_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<PathBuf>> {
+    let mut stream = ArgStream::new(args);
+    let mut cases = PathBuf::from("cases");
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(_) => return Ok(None),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--cases" => cases = PathBuf::from(stream.value(&flag)?),
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+    Ok(Some(cases))
+}
+
+pub const BENCH_OPTIONS: &str = "\
+Options:
+  --compare-with <path>   also run another build of the program, such as the previous release,
+                          check that both write the same output and report the speed difference
+  --runs <n>              runs of each build, alternating; the median is reported (default: 3)
+
+Options after -- are passed on to the generate runs, e.g. -- --threads 4 --output-format json";
+
+/// Arguments of the `bench` command.
+pub struct BenchArgs {
+    pub input: PathBuf,
+    /// Generate options both builds run with.
+    pub options: Vec<String>,
+    pub compare_with: Option<PathBuf>,
+    pub runs: usize,
+}
+
+/// Parses `bench [options] <input> [-- <generate options>]`.
+pub fn parse_bench_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<BenchArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    let mut compare_with = None;
+    let mut runs = 3;
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(value),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--compare-with" => compare_with = Some(PathBuf::from(stream.value(&flag)?)),
+                "--runs" => runs = stream.count(&flag)?,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+    if positionals.is_empty() {
+        return Ok(None);
+    }
+    let input = PathBuf::from(positionals.remove(0));
+    Ok(Some(BenchArgs {
+        input,
+        options: positionals,
+        compare_with,
+        runs,
+    }))
+}
+
+/// Parses `explain-error [code]`, returning the code if one is given.
+pub fn parse_explain_error_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<String>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(value),
+            Arg::Flag(flag) => return Err(unknown_option(&flag)),
+        }
+    }
+    match positionals.len() {
+        0 | 1 => Ok(positionals.pop()),
+        _ => bail!("explain-error takes one error code"),
+    }
+}
+
+/// Parses `help-topics [topic]` and `examples [topic]`, returning the topic
+/// if one is given.
+pub fn parse_help_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<String>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(value),
+            Arg::Flag(flag) => return Err(unknown_option(&flag)),
+        }
+    }
+    match positionals.len() {
+        0 | 1 => Ok(positionals.pop()),
+        _ => bail!("help-topics and examples take one topic"),
+    }
+}
+
+/// Parses `wizard [input]`, returning the input file to write if one is
+/// given.
+pub fn parse_wizard_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<PathBuf>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(PathBuf::from(value)),
+            Arg::Flag(flag) => return Err(unknown_option(&flag)),
+        }
+    }
+    match positionals.len() {
+        0 | 1 => Ok(positionals.pop()),
+        _ => bail!("wizard takes one input file"),
+    }
+}
+
+pub const GEN_INPUT_OPTIONS: &str = "\
+Options:
+  --lines <n>             number of lines to write (default: 1000)
+  --max-divisor <n>       largest divisor drawn, at least 1 (default: 100)
+  --max-end <n>           largest end drawn, at least 1 (default: 1e6)
+  --seed <n>              seed of the random generator; the same seed writes the same file
+                          (default: 0)";
+
+/// Arguments of the `gen-input` command.
+pub struct GenInputArgs {
+    /// Where to write the input, or `None` for stdout.
+    pub output: Option<PathBuf>,
+    pub lines: usize,
+    pub bounds: JobBounds,
+    pub seed: u64,
+}
+
+/// Parses `gen-input [options] [output]`.
+pub fn parse_gen_input_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<GenInputArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    let mut lines = 1000;
+    let mut max_divisor = 100;
+    let mut max_end = 1_000_000;
+    let mut seed = 0;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(PathBuf::from(value)),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--lines" => lines = stream.count(&flag)?,
+                "--max-divisor" => {
+                    let divisor = u32::try_from(stream.count(&flag)?);
+                    max_divisor = divisor.map_err(|_| INVALID_OPTION_VALUE.error(format!("Invalid value for {}: too large", flag)))?;
+                }
+                "--max-end" => {
+                    let end = u32::try_from(stream.count(&flag)?);
+                    max_end = end.map_err(|_| INVALID_OPTION_VALUE.error(format!("Invalid value for {}: too large", flag)))?;
+                }
+                "--seed" => seed = stream.number(&flag)?,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    if positionals.len() > 1 {
+        return Ok(None);
+    }
+    Ok(Some(GenInputArgs {
+        output: positionals.pop(),
+        lines,
+        bounds: JobBounds { max_divisor, max_end },
+        seed,
+    }))
+}
+
+pub const SELFTEST_OPTIONS: &str = "\
+Options:
+  --cases <n>             number of random jobs checked, each in both modes (default: 1000)
+  --seed <n>              seed of the random generator (default: taken from the clock)
+  --max-divisor <n>       largest divisor drawn, at least 1 (default: 1000)
+  --max-end <n>           largest end drawn, at least 1 (default: 100k)";
+
+/// Arguments of the `selftest` command.
+pub struct SelfTestArgs {
+    pub cases: usize,
+    pub seed: Option<u64>,
+    pub bounds: JobBounds,
+}
+
+/// Parses `selftest [options]`.
+pub fn parse_selftest_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<SelfTestArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut cases = 1000;
+    let mut seed = None;
+    let mut max_divisor = 1000;
+    let mut max_end = 100_000;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(_) => return Ok(None),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--cases" => cases = stream.count(&flag)?,
+                "--seed" => seed = Some(stream.number(&flag)?),
+                "--max-divisor" => {
+                    let divisor = u32::try_from(stream.count(&flag)?);
+                    max_divisor = divisor.map_err(|_| INVALID_OPTION_VALUE.error(format!("Invalid value for {}: too large", flag)))?;
+                }
+                "--max-end" => {
+                    let end = u32::try_from(stream.count(&flag)?);
+                    max_end = end.map_err(|_| INVALID_OPTION_VALUE.error(format!("Invalid value for {}: too large", flag)))?;
+                }
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    Ok(Some(SelfTestArgs {
+        cases,
+        seed,
+        bounds: JobBounds { max_divisor, max_end },
+    }))
+}
+
+pub const LINT_OPTIONS: &str = "\
+Options:
+  --input-format <name>   text, csv, json or ndjson (default: by file extension)
+  --mode <mode>           or, xor or multiset (default: or)
+  --max-size <n>          report records taking more than n bytes of text output (default: 1G)
+  --deny <level>          fail on warnings, or on notes and warnings, not only on lines that
+                          don't parse";
+
+/// Arguments of the `lint` command.
+pub struct LintArgs {
+    pub input: PathBuf,
+    pub input_format: Option<String>,
+    pub mode: Mode,
+    pub max_size: u64,
+    pub deny: Option<lint::Severity>,
+}
+
+/// Parses `lint [options] <input>`.
+pub fn parse_lint_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<LintArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    let mut input_format = None;
+    let mut mode = Mode::Or;
+    let mut max_size = 1_000_000_000;
+    let mut deny = None;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(value),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--input-format" => input_format = Some(stream.value(&flag)?),
+                "--mode" => mode = stream.value(&flag)?.parse()?,
+                "--max-size" => max_size = stream.number(&flag)?,
+                "--deny" => deny = Some(stream.value(&flag)?.parse()?),
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    let [input] = &positionals[..] else {
+        return Ok(None);
+    };
+    Ok(Some(LintArgs {
+        input: PathBuf::from(input),
+        input_format,
+        mode,
+        max_size,
+        deny,
+    }))
+}
+
+pub const SERVE_OPTIONS: &str = "\
+Options:
+  --listen <addr>         address to listen on (default: 127.0.0.1:8080)
+  --max-end <n>           largest end a request may ask for (default: 10M)
+  --max-response-size <n> largest response in bytes, e.g. 1M (default: 16M)
+  --rate-limit <n/period> requests each client may make per s, min or h, e.g. 60/min
+                          (default: unlimited)
+  --api-keys <path>       require an X-API-Key header matching one of the \"name key\" lines of path
+  --jwt-secret-file <path>  accept Authorization: Bearer tokens signed with HS256 by the key in
+                          path; repeat to accept several keys
+  --jwt-issuer <iss>      require bearer tokens to have this iss claim
+  --max-request-size <n>  largest request body in bytes, which bounds a batch upload
+                          (default: 1M)
+  --max-line-length <n>   longest line of a batch upload in bytes (default: 4096)
+  --max-lines <n>         most lines, or jobs of a JSON array, of a batch upload (default: 100k)
+  --max-tokens <n>        most words or fields in a line of a batch upload (default: 64)
+  --max-multiples <n>     most multiples the lines of a batch upload may select together
+                          (default: no limit); 0 lifts any of the limits
+  --threads <n>           generator threads of each batch, or auto (default: auto, one per CPU
+                          within the cgroup's CPU limit)
+  --cache-size <n>        bytes of /multiples answers kept for repeated queries, 0 for none
+                          (default: 64M)
+  --cache-ttl <duration>  serve a cached answer for at most this long, e.g. 5m (default: until
+                          newer answers push it out)
+  --otlp-endpoint <url>   export a span per request as OpenTelemetry traces to an OTLP/HTTP
+                          collector, e.g. http://localhost:4318
+  --otlp-sample <ratio>   share of requests exported, from 0 to 1 (default: 1)
+  --max-connections <n>   connections answered at once, beyond which clients get 503
+                          (default: 256)
+  --service               run as a systemd service: notify readiness once listening and ping the
+                          watchdog
+  --pid-file <path>       write the process id to path while the server runs";
+
+/// Reads the value of `flag`, a share from 0 to 1 such as `--otlp-sample`.
+fn sample_ratio(stream: &mut ArgStream, flag: &str) -> Result<f64> {
+    let value = stream.value(flag)?;
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|ratio| (0.0..=1.0).contains(ratio))
+        .ok_or_else(|| INVALID_OPTION_VALUE.error(format!("Invalid value for {}: {} (must be between 0 and 1)", flag, value)))
+}
+
+/// The exporter of `--otlp-endpoint` and `--otlp-sample` in the long-running
+/// modes, if an endpoint was given.
+fn otlp_exporter(endpoint: Option<String>, sample: f64) -> Result<Option<OtlpExporter>> {
+    endpoint.map(|endpoint| OtlpExporter::new(&endpoint, sample)).transpose()
+}
+
+/// Sets the input limit `flag` names to its value, where 0 is no limit.
+fn limit(stream: &mut ArgStream, flag: &str, limits: &mut InputLimits) -> Result<()> {
+    let value = Some(stream.number(flag)?).filter(|&value| value > 0);
+    let size = value.map(usize::try_from).transpose();
+    let size = size.map_err(|_| INVALID_OPTION_VALUE.error(format!("Invalid value for {}: too large", flag)))?;
+    match flag {
+        "--max-line-length" => limits.max_line_length = size,
+        "--max-lines" => limits.max_lines = size,
+        "--max-tokens" => limits.max_tokens = size,
+        _ => limits.max_multiples = value,
+    }
+    Ok(())
+}
+
+/// Arguments of the `serve` command.
+pub struct ServeArgs {
+    pub listen: String,
+    pub config: ServerConfig,
+    pub api_keys: Option<PathBuf>,
+    pub jwt_secrets: Vec<PathBuf>,
+    pub jwt_issuer: Option<String>,
+    pub service: bool,
+    pub pid_file: Option<PathBuf>,
+}
+
+/// Parses `serve [options]`.
+pub fn parse_serve_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<ServeArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut listen = String::from("127.0.0.1:8080");
+    let mut config = ServerConfig::default();
+    let mut api_keys = None;
+    let mut jwt_secrets = Vec::new();
+    let mut jwt_issuer = None;
+    let mut otlp_endpoint = None;
+    let mut otlp_sample = 1.0;
+    let mut service = false;
+    let mut pid_file = None;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(_) => return Ok(None),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--listen" => listen = stream.value(&flag)?,
+                "--max-end" => {
+                    let end = u32::try_from(stream.count(&flag)?);
+                    config.max_end = end.map_err(|_| INVALID_OPTION_VALUE.error(format!("Invalid value for {}: too large", flag)))?;
+                }
+                "--max-response-size" => config.max_response_size = stream.count(&flag)? as u64,
+                "--rate-limit" => config.rate_limit = Some(stream.value(&flag)?.parse()?),
+                "--api-keys" => api_keys = Some(PathBuf::from(stream.value(&flag)?)),
+                "--jwt-secret-file" => jwt_secrets.push(PathBuf::from(stream.value(&flag)?)),
+                "--jwt-issuer" => jwt_issuer = Some(stream.value(&flag)?),
+                "--max-request-size" => config.max_request_size = stream.count(&flag)?,
+                "--max-line-length" | "--max-lines" | "--max-tokens" | "--max-multiples" => {
+                    limit(&mut stream, &flag, &mut config.limits)?
+                }
+                "--threads" => config.threads = stream.threads(&flag)?,
+                "--cache-size" => config.cache_size = stream.number(&flag)?,
+                "--cache-ttl" => match parse_duration(&stream.value(&flag)?)? {
+                    0 => bail!("Invalid value for {}: must be longer than 0s", flag),
+                    seconds => config.cache_ttl = Some(Duration::from_secs(seconds as u64)),
+                },
+                "--otlp-endpoint" => otlp_endpoint = Some(stream.value(&flag)?),
+                "--otlp-sample" => otlp_sample = sample_ratio(&mut stream, &flag)?,
+                "--max-connections" => config.max_connections = stream.count(&flag)?,
+                "--service" => service = true,
+                "--pid-file" => pid_file = Some(PathBuf::from(stream.value(&flag)?)),
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    if jwt_issuer.is_some() && jwt_secrets.is_empty() {
+        bail!("--jwt-issuer needs --jwt-secret-file");
+    }
+    config.otlp = otlp_exporter(otlp_endpoint, otlp_sample)?;
+    Ok(Some(ServeArgs {
+        listen,
+        config,
+        api_keys,
+        jwt_secrets,
+        jwt_issuer,
+        service,
+        pid_file,
+    }))
+}
+
+pub const DAEMON_OPTIONS: &str = "\
+Options:
+  --state-dir <dir>       where schedules and their run history are kept
+                          (default: multiples-daemon)
+  --schedule <cron>       add a schedule running <input> into <output> whenever cron matches, in
+                          UTC, e.g. \"0 2 * * *\" or @hourly, then start
+  --job <jobs>            with --schedule, run these \"a b end\" jobs, separated by ';', into
+                          <output> instead of an input file
+  --output-format <name>  output format of the schedule added (default: text)
+  --list                  print the schedules and exit
+  --remove <id>           remove a schedule and exit
+  --history               print the outcome of every run and exit
+  --service               run as a systemd service: notify readiness and ping the watchdog
+  --pid-file <path>       write the process id to path while the daemon runs
+  --otlp-endpoint <url>   export a span per scheduled run as OpenTelemetry traces to an
+                          OTLP/HTTP collector, e.g. http://localhost:4318
+  --otlp-sample <ratio>   share of runs exported, from 0 to 1 (default: 1)";
+
+/// What the `daemon` command does once any schedule is added.
+#[derive(Debug, PartialEq)]
+pub enum DaemonAction {
+    Run,
+    List,
+    Remove(u32),
+    History,
+}
+
+/// A schedule to add with `daemon --schedule`.
+pub struct NewSchedule {
+    pub cron: String,
+    pub jobs: ScheduledJobs,
+    pub output: PathBuf,
+    pub output_format: String,
+}
+
+/// Arguments of the `daemon` command.
+pub struct DaemonArgs {
+    pub state_dir: PathBuf,
+    pub add: Option<NewSchedule>,
+    pub action: DaemonAction,
+    pub service: bool,
+    pub pid_file: Option<PathBuf>,
+    pub otlp: Option<OtlpExporter>,
+}
+
+/// Parses `daemon [options] [<input>] [<output>]`.
+pub fn parse_daemon_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<DaemonArgs>> {
+    let mut stream = ArgStream::new(args);
+    let mut positionals = Vec::new();
+    let mut state_dir = PathBuf::from("multiples-daemon");
+    let mut cron = None;
+    let mut jobs = None;
+    let mut output_format = None;
+    let mut actions = Vec::new();
+    let mut service = false;
+    let mut pid_file = None;
+    let mut otlp_endpoint = None;
+    let mut otlp_sample = 1.0;
+
+    while let Some(arg) = stream.next_arg()? {
+        match arg {
+            Arg::Positional(value) => positionals.push(PathBuf::from(value)),
+            Arg::Flag(flag) => match flag.as_str() {
+                "--state-dir" => state_dir = PathBuf::from(stream.value(&flag)?),
+                "--schedule" => cron = Some(stream.value(&flag)?),
+                "--job" => jobs = Some(stream.value(&flag)?.replace(';', "\n")),
+                "--output-format" => output_format = Some(stream.value(&flag)?),
+                "--list" => actions.push(DaemonAction::List),
+                "--remove" => {
+                    let id = u32::try_from(stream.count(&flag)?);
+                    actions.push(DaemonAction::Remove(id.map_err(|_| INVALID_OPTION_VALUE.error(format!("Invalid value for {}: too large", flag)))?));
+                }
+                "--history" => actions.push(DaemonAction::History),
+                "--service" => service = true,
+                "--pid-file" => pid_file = Some(PathBuf::from(stream.value(&flag)?)),
+                "--otlp-endpoint" => otlp_endpoint = Some(stream.value(&flag)?),
+                "--otlp-sample" => otlp_sample = sample_ratio(&mut stream, &flag)?,
+                _ => return Err(unknown_option(&flag)),
+            },
+        }
+    }
+
+    if actions.len() > 1 {
+        bail!("--list, --remove and --history can't be combined");
+    }
+    let add = match cron {
+        Some(cron) => {
+            let expected = if jobs.is_some() { 1 } else { 2 };
+            if positionals.len() != expected {
+                return Ok(None);
+            }
+            let output = positionals.pop().unwrap_or_default();
+            let jobs = match jobs {
+                Some(jobs) => ScheduledJobs::Inline(jobs),
+                None => ScheduledJobs::File(positionals.pop().unwrap_or_default()),
+            };
+            Some(NewSchedule {
+                cron,
+                jobs,
+                output,
+                output_format: output_format.unwrap_or_else(|| String::from("text")),
+            })
+        }
+        None if jobs.is_some() || output_format.is_some() => bail!("--job and --output-format need --schedule"),
+        None if !positionals.is_empty() => return Ok(None),
+        None => None,
+    };
+    Ok(Some(DaemonArgs {
+        state_dir,
+        add,
+        action: actions.pop().unwrap_or(DaemonAction::Run),
+        service,
+        pid_file,
+        otlp: otlp_exporter(otlp_endpoint, otlp_sample)?,
+    }))
+}
+
+/// Environment variables read when the program runs without arguments.
+pub const ENV_VARIABLES: &str = "\
+Environment (used when run without arguments):
+  MULTIPLES_JOB           the jobs to run, \"a b end\" lines separated by newlines or ';'
+  MULTIPLES_FORMAT        text, json, ndjson, csv, rust-array, c-array or python-list
+                          (default: text)
+  MULTIPLES_MODE          or, xor or multiset (default: or)
+  MULTIPLES_COUNT_ONLY    1 or true to write end:count instead of the multiples
+  MULTIPLES_OUTPUT        file to write to instead of stdout";
+
+/// A single-shot run configured through the environment, see [`ENV_VARIABLES`].
+pub struct EnvJob {
+    /// The jobs in the text input format.
+    pub jobs: String,
+    pub output_format: String,
+    pub mode: Mode,
+    pub count_only: bool,
+    pub output: Option<PathBuf>,
+}
+
+/// Reads an [`EnvJob`] through `var`, or returns `Ok(None)` when
+/// `MULTIPLES_JOB` isn't set.
+pub fn parse_env_job(var: impl Fn(&str) -> Option<String>) -> Result<Option<EnvJob>> {
+    let Some(jobs) = var("MULTIPLES_JOB") else {
+        return Ok(None);
+    };
+    let count_only = match var("MULTIPLES_COUNT_ONLY").as_deref() {
+        None | Some("" | "0" | "false" | "no") => false,
+        Some("1" | "true" | "yes") => true,
+        Some(other) => bail!("Invalid value for MULTIPLES_COUNT_ONLY: {} (expected 1, true, 0 or false)", other),
+    };
+    let mode = match var("MULTIPLES_MODE") {
+        Some(mode) => mode.parse().context("Invalid value for MULTIPLES_MODE")?,
+        None => Mode::Or,
+    };
+    Ok(Some(EnvJob {
+        jobs: jobs.replace(';', "\n"),
+        output_format: var("MULTIPLES_FORMAT").unwrap_or_else(|| String::from("text")),
+        mode,
+        count_only,
+        output: var("MULTIPLES_OUTPUT").filter(|path| !path.is_empty()).map(PathBuf::from),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use super::{
+        parse_backfill_args, parse_bitmap_args, parse_sweep_args, parse_convert_args, parse_daemon_args, parse_env_job, parse_explain_args, parse_gen_input_args, parse_generate_args, parse_get_args, parse_init_args, parse_list_args, parse_merge_args, parse_merge_shards_args, parse_analyze_args, parse_bench_args, parse_explain_error_args, parse_lint_args, parse_regress_args, parse_selftest_args, parse_serve_args,
+        parse_help_args, parse_wizard_args,
+        parse_setops_args, parse_sort_args, take_lang, Arg, ArgStream, BitmapAction, DaemonAction, ENV_VARIABLES,
+    };
+    use multiple_of_a_and_b::backfill::Naming;
+    use multiple_of_a_and_b::color::ColorChoice;
+    use multiple_of_a_and_b::compat::Compat;
+    use multiple_of_a_and_b::degenerate;
+    use multiple_of_a_and_b::errors;
+    use multiple_of_a_and_b::locale::Lang;
+    use multiple_of_a_and_b::formula::Mode;
+    use multiple_of_a_and_b::input::Bound;
+    use multiple_of_a_and_b::lint::Severity;
+    use multiple_of_a_and_b::merge::MergeMode;
+    use multiple_of_a_and_b::newline::LineEnding;
+    use multiple_of_a_and_b::progress::ProgressFormat;
+    use multiple_of_a_and_b::schedule::ScheduledJobs;
+    use multiple_of_a_and_b::setops::SetOp;
+    use multiple_of_a_and_b::shard::Shard;
+    use multiple_of_a_and_b::sort::SortKey;
+    use multiple_of_a_and_b::throttle;
+    use multiple_of_a_and_b::LineNumbers;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_env_job() {
+        let env = |vars: &[(&str, &str)]| -> HashMap<String, String> {
+            vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+        };
+        let parse = |vars: HashMap<String, String>| parse_env_job(move |name| vars.get(name).cloned());
+        assert!(parse(env(&[("MULTIPLES_FORMAT", "json")])).unwrap().is_none());
+        let job = parse(env(&[("MULTIPLES_JOB", "3 5 1000;2 7 10")])).unwrap().unwrap();
+        assert_eq!((job.jobs.as_str(), job.output_format.as_str()), ("3 5 1000\n2 7 10", "text"));
+        assert_eq!((job.mode, job.count_only, job.output), (Mode::Or, false, None));
+        let job = parse(env(&[
+            ("MULTIPLES_JOB", "3 5 1000"),
+            ("MULTIPLES_MODE", "xor"),
+            ("MULTIPLES_COUNT_ONLY", "true"),
+            ("MULTIPLES_OUTPUT", "/out/result.json"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!((job.mode, job.count_only), (Mode::Xor, true));
+        assert_eq!(job.output, Some(PathBuf::from("/out/result.json")));
+        assert!(parse(env(&[("MULTIPLES_JOB", "3 5 10"), ("MULTIPLES_COUNT_ONLY", "maybe")])).is_err());
+        assert!(parse(env(&[("MULTIPLES_JOB", "3 5 10"), ("MULTIPLES_MODE", "and")])).is_err());
+    }
+
+    #[test]
+    fn test_arg_stream_values() {
+        let mut stream = ArgStream::new(args(&["--a=1", "--b", "2k", "pos", "--", "--c"]));
+        assert!(matches!(stream.next_arg().unwrap(), Some(Arg::Flag(f)) if f == "--a"));
+        assert_eq!(stream.number("--a").unwrap(), 1);
+        assert!(matches!(stream.next_arg().unwrap(), Some(Arg::Flag(f)) if f == "--b"));
+        assert_eq!(stream.number("--b").unwrap(), 2000);
+        assert!(matches!(stream.next_arg().unwrap(), Some(Arg::Positional(p)) if p == "pos"));
+        assert!(matches!(stream.next_arg().unwrap(), Some(Arg::Positional(p)) if p == "--c"));
+        assert!(stream.next_arg().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_generate_args() {
+        let parsed = parse_generate_args(args(&["in.txt", "--output-format", "json", "out.json"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.input.to_str(), Some("in.txt"));
+        assert_eq!(parsed.output.to_str(), Some("out.json"));
+        assert_eq!(parsed.output_format, "json");
+
+        assert!(parse_generate_args(args(&["in.txt"])).unwrap().is_none());
+        assert!(parse_generate_args(args(&["--bogus", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--threads", "0", "a", "b"])).is_err());
+        let parsed = parse_generate_args(args(&["--threads", "auto", "a", "b"])).unwrap().unwrap();
+        assert_eq!(parsed.threads, throttle::default_threads());
+        let parsed = parse_generate_args(args(&["--format-threads", "4", "a", "b"])).unwrap().unwrap();
+        assert_eq!((parsed.threads, parsed.format_threads), (1, 4));
+        assert!(parse_generate_args(args(&["--format-threads", "0", "a", "b"])).is_err());
+        assert!(!parsed.nice && parsed.throttle.is_none());
+        let parsed = parse_generate_args(args(&["--nice", "--throttle", "50%", "a", "b"])).unwrap().unwrap();
+        assert!(parsed.nice && parsed.throttle == Some("50".parse().unwrap()));
+        assert!(parse_generate_args(args(&["--throttle", "0%", "a", "b"])).is_err());
+        let parsed = parse_generate_args(args(&["--shard", "2/8", "a", "b"])).unwrap().unwrap();
+        assert_eq!(parsed.shard, Some(Shard { index: 2, count: 8 }));
+        assert!(!parsed.unordered);
+        assert!(parse_generate_args(args(&["--unordered", "a", "b"])).unwrap().unwrap().unordered);
+        assert_eq!(parsed.max_concurrent_heavy, None);
+        let parsed = parse_generate_args(args(&["--max-concurrent-heavy=2", "a", "b"])).unwrap().unwrap();
+        assert_eq!(parsed.max_concurrent_heavy, Some(2));
+        assert_eq!((parsed.max_memory, parsed.strict_memory), (None, false));
+        let parsed = parse_generate_args(args(&["--max-memory", "2G", "--strict-memory", "a", "b"])).unwrap().unwrap();
+        assert_eq!((parsed.max_memory, parsed.strict_memory), (Some(2_000_000_000), true));
+        assert!(!parsed.fast_parse && !parsed.longest_first);
+        assert!(parse_generate_args(args(&["--longest-first", "a", "b"])).unwrap().unwrap().longest_first);
+        assert!(parse_generate_args(args(&["--fast-parse", "a", "b"])).unwrap().unwrap().fast_parse);
+        assert!(parse_generate_args(args(&["--fast-parse", "--decimal", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--cross-check", "a", "b"])).unwrap().unwrap().cross_check);
+        assert_eq!(parse_generate_args(args(&["--decimal", "a", "b"])).unwrap().unwrap().decimal, Some(2));
+        assert_eq!(parse_generate_args(args(&["--decimal", "--scale", "0", "a", "b"])).unwrap().unwrap().decimal, Some(0));
+        assert!(parse_generate_args(args(&["--scale", "3", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--decimal", "--scale", "10", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--decimal", "--palindrome", "a", "b"])).is_err());
+        let parsed = parse_generate_args(args(&["--time", "1970-01-02", "a", "b"])).unwrap().unwrap();
+        assert_eq!(parsed.time, Some(86_400));
+        assert!(parse_generate_args(args(&["--time", "tomorrow", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--time", "2026-01-01", "--decimal", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--aggregate", "a", "b"])).unwrap().unwrap().aggregate);
+        assert!(parse_generate_args(args(&["--aggregate", "--append", "a", "b"])).is_err());
+        let parsed = parse_generate_args(args(&["--preview", "5", "in.txt"])).unwrap().unwrap();
+        assert_eq!((parsed.preview, parsed.input.to_str()), (Some(5), Some("in.txt")));
+        assert!(parse_generate_args(args(&["--preview", "5", "in.txt", "out.txt"])).unwrap().is_none());
+        assert!(parse_generate_args(args(&["--preview", "5", "--aggregate", "in.txt"])).is_err());
+        assert!(parse_generate_args(args(&["--preview", "0", "in.txt"])).is_err());
+        assert!(parse_generate_args(args(&["--shard", "9/8", "a", "b"])).is_err());
+
+        let parsed = parse_generate_args(args(&["--min-count", "1k", "--top=10", "--by", "end", "a", "b"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!((parsed.min_count, parsed.max_count, parsed.top), (Some(1000), None, Some(10)));
+        assert_eq!(parsed.by, SortKey::End);
+        assert!(!parsed.palindrome);
+        assert!(!parsed.fix);
+        assert!(parsed.normalize);
+
+        let parsed = parse_generate_args(args(&["--palindrome", "--ends-with", "05", "a", "b"]))
+            .unwrap()
+            .unwrap();
+        assert!(parsed.palindrome);
+        assert_eq!(parsed.mode, Mode::Or);
+        assert!(parse_generate_args(args(&["--mode", "and", "a", "b"])).is_err());
+        let multiset = parse_generate_args(args(&["--multiset", "a", "b"])).unwrap().unwrap();
+        assert_eq!(multiset.mode, Mode::Multiset);
+        assert!(parse_generate_args(args(&["--group-by", "divisor", "a", "b"])).unwrap().unwrap().group_by_divisor);
+        assert!(parse_generate_args(args(&["--group-by", "digit", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--group-by", "divisor", "--annotate", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--array-name", "1st", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--power", "0", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--strategy", "sieve", "a", "b"])).is_err());
+        assert_eq!(parsed.ends_with.as_deref(), Some("05"));
+        assert!(parse_generate_args(args(&["--digit-sum-divisible-by", "0", "a", "b"])).is_err());
+        let parsed = parse_generate_args(args(&["--progress-format", "json", "--progress-interval", "1k", "a", "b"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!((parsed.progress, parsed.progress_interval), (Some(ProgressFormat::Json), Duration::from_secs(1)));
+        assert!(parse_generate_args(args(&["--progress-to", "fifo", "a", "b"])).is_err());
+        assert_eq!(parsed.color, ColorChoice::Auto);
+        let parsed = parse_generate_args(args(&["--color", "never", "a", "b"])).unwrap().unwrap();
+        assert_eq!(parsed.color, ColorChoice::Never);
+        assert!(parse_generate_args(args(&["--color", "sometimes", "a", "b"])).is_err());
+        assert_eq!(parsed.degenerate, degenerate::Policy::Warn);
+        let parsed = parse_generate_args(args(&["--degenerate", "error", "a", "b"])).unwrap().unwrap();
+        assert_eq!(parsed.degenerate, degenerate::Policy::Error);
+        assert!(!parsed.keep_going);
+        assert!(parse_generate_args(args(&["--keep-going", "a", "b"])).unwrap().unwrap().keep_going);
+        let parsed = parse_generate_args(args(&["--max-lines", "1k", "--max-multiples=1G", "a", "b"])).unwrap().unwrap();
+        assert_eq!((parsed.limits.max_lines, parsed.limits.max_multiples, parsed.limits.max_tokens), (Some(1000), Some(1_000_000_000), None));
+        assert!(parse_generate_args(args(&["--pure", "--io-backend", "mmap", "a", "b"])).unwrap().unwrap().pure);
+        assert_eq!(parse_generate_args(args(&["a", "b"])).unwrap().unwrap().line_ending, LineEnding::native());
+        let parsed = parse_generate_args(args(&["--line-ending", "crlf", "a", "b"])).unwrap().unwrap();
+        assert_eq!(parsed.line_ending, LineEnding::Crlf);
+        let parsed = parse_generate_args(args(&["--compat", "1.0", "a", "b"])).unwrap().unwrap();
+        assert_eq!(parsed.line_ending, LineEnding::Lf);
+        let err = parse_generate_args(args(&["--pure", "--pid-file", "run.pid", "a", "b"])).err().unwrap();
+        assert_eq!(err.to_string(), "--pure can't be combined with --service or --pid-file, which read or write more than the input and output");
+        assert_eq!(parse_generate_args(args(&["a", "b"])).unwrap().unwrap().audit_log, None);
+        let audited = parse_generate_args(args(&["--audit", "a", "b"])).unwrap().unwrap();
+        assert_eq!(audited.audit_log, Some(PathBuf::from("runs.log")));
+        let audited = parse_generate_args(args(&["--audit-log", "logs/runs.ndjson", "--audit", "a", "b"])).unwrap().unwrap();
+        assert_eq!(audited.audit_log, Some(PathBuf::from("logs/runs.ndjson")));
+        assert!(parse_generate_args(args(&["--pure", "--audit", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--provenance", "--output-format", "csv", "a", "b"])).unwrap().unwrap().provenance);
+        assert!(parse_generate_args(args(&["--provenance", "--output-format", "store", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--provenance", "--append", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--checksums", "--output-format", "ndjson", "a", "b"])).unwrap().unwrap().checksums);
+        assert!(parse_generate_args(args(&["--checksums", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--checksums", "--output-format", "csv", "--decimal", "a", "b"])).is_err());
+        let parsed = parse_generate_args(args(&["--compat", "1.0", "--threads", "4", "a.csv", "b"])).unwrap().unwrap();
+        assert_eq!(parsed.compat, Some(Compat::V1_0));
+        assert_eq!((parsed.input_format.as_deref(), parsed.color, parsed.degenerate), (Some("text"), ColorChoice::Never, degenerate::Policy::Simplify));
+        let err = parse_generate_args(args(&["--compat", "1.0", "--mode", "xor", "a", "b"])).err().unwrap();
+        assert_eq!(err.to_string(), "--compat can't be combined with --mode, which change the output");
+        assert!(parse_generate_args(args(&["--compat", "0.9", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--degenerate", "simplify", "--no-normalize", "a", "b"])).is_err());
+        assert_eq!(parsed.bound, Bound::Inclusive);
+        let parsed = parse_generate_args(args(&["--bound", "exclusive", "a", "b"])).unwrap().unwrap();
+        assert_eq!(parsed.bound, Bound::Exclusive);
+        assert!(parse_generate_args(args(&["--bound", "below", "a", "b"])).is_err());
+        assert!(parsed.tags.is_empty());
+        let tagged = parse_generate_args(args(&["--only-tag", "nightly,weekly", "--skip-tag=slow", "--only-tag", "adhoc", "a", "b"]));
+        let tags = tagged.unwrap().unwrap().tags;
+        assert!(tags.matches(&[String::from("adhoc")]) && !tags.matches(&[String::from("nightly"), String::from("slow")]));
+        assert!(parse_generate_args(args(&["--skip-tag", "", "a", "b"])).is_err());
+        assert!(!parsed.include_zero);
+        assert!(parse_generate_args(args(&["--include-zero", "a", "b"])).unwrap().unwrap().include_zero);
+        for format in ["c-array", "rust-array", "python-list", "roaring"] {
+            let err = parse_generate_args(args(&["--count-only", "--output-format", format, "a", "b"])).err().unwrap();
+            assert_eq!(err.to_string(), format!("--count-only can't be combined with --output-format {}, which holds multiples only", format));
+        }
+        assert!(parse_generate_args(args(&["--count-only", "--output-format", "csv", "a", "b"])).unwrap().unwrap().count_only);
+        let parsed = parse_generate_args(args(&["--stop-after", "10", "--stop-above=1M", "a", "b"])).unwrap().unwrap();
+        assert_eq!((parsed.stop_after, parsed.stop_above), (Some(10), Some(1_000_000)));
+        assert!(parse_generate_args(args(&["--stop-after", "10", "--shard", "1/2", "a", "b"])).is_err());
+        assert!(parse_generate_args(args(&["--stop-above", "5G", "a", "b"])).is_err());
+        let parsed = parse_generate_args(args(&["--sink", "nats://localhost/multiples", "--sink-batch", "100", "in.txt"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!((parsed.output.to_str(), parsed.sink_batch), (Some("nats://localhost/multiples"), Some(100)));
+        assert!(parse_generate_args(args(&["--sink", "nats://localhost/multiples", "in.txt", "out.txt"])).unwrap().is_none());
+        let parsed = parse_generate_args(args(&["--service", "--pid-file", "run.pid", "a", "b"])).unwrap().unwrap();
+        assert!(parsed.service && parsed.pid_file == Some(PathBuf::from("run.pid")));
+    }
+
+    #[test]
+    fn test_parse_merge_args() {
+        let parsed = parse_merge_args(args(&["--merge-mode=union", "a.txt", "b.txt", "out.txt"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.inputs.len(), 2);
+        assert_eq!(parsed.output.to_str(), Some("out.txt"));
+        assert_eq!(parsed.mode, MergeMode::Union);
+        assert!(!parsed.checksums);
+        assert!(parse_merge_args(args(&["--checksums", "a.csv", "out.csv"])).unwrap().unwrap().checksums);
+        assert!(parse_merge_args(args(&["out.txt"])).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_backfill_args() {
+        let parsed = parse_backfill_args(args(&["--target", "1e7", "--stride", "1e6", "results"])).unwrap().unwrap();
+        assert_eq!((parsed.target, parsed.stride, parsed.mode), (10_000_000, 1_000_000, Mode::Or));
+        assert_eq!(parsed.naming, Naming::LikeExisting);
+        let parsed = parse_backfill_args(args(&["--target=10M", "--stride=1M", "--name", "{end}.csv", "--dry-run", "."]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.naming, Naming::Template("{end}.csv".to_string()));
+        assert!(parsed.dry_run);
+        assert!(parse_backfill_args(args(&["--target", "1e7", "results"])).unwrap().is_none());
+        assert!(parse_backfill_args(args(&["--target", "1e10", "--stride", "1e6", "results"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_sweep_args() {
+        let parsed = parse_sweep_args(args(&[
+            "--a", "2..4", "--b", "3,5", "--end", "1e6", "--where", "a<b", "out", "--", "--threads", "4",
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!((parsed.sweep.a.0.len(), parsed.sweep.b.0, parsed.sweep.end.0), (3, vec![3, 5], vec![1_000_000]));
+        assert_eq!(parsed.sweep.constraints.len(), 1);
+        assert!(parsed.sweep.filter.is_empty());
+        let required = parse_sweep_args(args(&["--a=2", "--b=3", "--end=9", "--require", "coprime", "--require=a<b", "out"]));
+        assert_eq!(required.unwrap().unwrap().sweep.filter.to_string(), "coprime,a<b");
+        assert!(parse_sweep_args(args(&["--a=2", "--b=3", "--end=9", "--require", "even", "out"])).is_err());
+        assert_eq!((parsed.dir, parsed.output_format, parsed.options), (PathBuf::from("out"), "text".to_string(), args(&["--threads", "4"])));
+        assert!(parse_sweep_args(args(&["--a", "2", "--b", "3", "out"])).unwrap().is_none());
+        assert!(parse_sweep_args(args(&["--a", "2", "--b", "3", "--end", "1..0", "out"])).is_err());
+        assert!(parse_sweep_args(args(&["--a", "2", "--b", "3", "--end", "9", "--where", "a<", "out"])).is_err());
+        assert!(parse_sweep_args(args(&["--a", "2", "--b", "3", "--end", "9", "out", "--", "--output-format=csv"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_merge_shards_args() {
+        let parsed = parse_merge_shards_args(args(&["--mode", "xor", "s1.csv", "s2.csv", "out.csv"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.shards.len(), 2);
+        assert_eq!(parsed.output.to_str(), Some("out.csv"));
+        assert_eq!(parsed.verify, Some(Mode::Xor));
+        let parsed = parse_merge_shards_args(args(&["--no-verify", "s1.csv", "out.csv"])).unwrap().unwrap();
+        assert_eq!(parsed.verify, None);
+        assert!(parse_merge_shards_args(args(&["out.csv"])).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_analyze_args() {
+        let parsed = parse_analyze_args(args(&["gaps", "--percentiles=25,75", "in.txt", "gaps.json", "--output-format", "json"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.input.to_str(), Some("in.txt"));
+        assert_eq!(parsed.percentiles, [25.0, 75.0]);
+        assert_eq!(parsed.output_format, "json");
+        assert!(parse_analyze_args(args(&["gaps", "in.txt"])).unwrap().is_none());
+        assert!(parse_analyze_args(args(&["runs", "in.txt", "out.txt"])).is_err());
+        assert!(parse_analyze_args(args(&["--output-format", "csv", "gaps", "in.txt", "out.txt"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_convert_args() {
+        let parsed = parse_convert_args(args(&["in.ndjson", "out.csv"])).unwrap().unwrap();
+        assert_eq!(parsed.input.to_str(), Some("in.ndjson"));
+        assert!(parsed.output_format.is_none() && !parsed.checksums);
+        assert!(parse_convert_args(args(&["--checksums", "in.csv", "out.ndjson"])).unwrap().unwrap().checksums);
+        assert!(parse_convert_args(args(&["a", "b", "c"])).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_sort_args() {
+        let parsed = parse_sort_args(args(&["--by", "end", "--max-memory=64M", "in.csv", "out.csv"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.by, SortKey::End);
+        assert_eq!(parsed.max_memory, 64_000_000);
+        assert!(parse_sort_args(args(&["--by", "size", "a", "b"])).is_err());
+        assert!(parse_sort_args(args(&["in.csv"])).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_setops_args() {
+        let parsed = parse_setops_args(args(&["--op", "difference", "2", "3", "5", "7", "1k"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.op, SetOp::Difference);
+        assert_eq!((parsed.first, parsed.second, parsed.end), ((2, 3), (5, 7), 1000));
+        assert!(parse_setops_args(args(&["2", "3", "5", "7"])).unwrap().is_none());
+        assert!(parse_setops_args(args(&["2", "3", "5", "7", "x"])).is_err());
+
+        let parsed = parse_bitmap_args(args(&["union", "--count", "a.roaring", "b.roaring"])).unwrap().unwrap();
+        assert_eq!((parsed.action, parsed.inputs.len(), parsed.count_only), (BitmapAction::Union, 2, true));
+        let parsed = parse_bitmap_args(args(&["select", "a.roaring", "1k"])).unwrap().unwrap();
+        assert_eq!((parsed.action, parsed.inputs), (BitmapAction::Select(1000), vec![PathBuf::from("a.roaring")]));
+        assert!(parse_bitmap_args(args(&["rank", "a.roaring"])).unwrap().is_none());
+        assert!(parse_bitmap_args(args(&["select", "a.roaring", "0"])).is_err());
+        assert!(parse_bitmap_args(args(&["rank", "--count", "a.roaring", "5"])).is_err());
+        assert!(parse_bitmap_args(args(&["xor", "a.roaring"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_explain_args() {
+        let parsed = parse_explain_args(args(&["--mode", "xor", "--power", "2", "jobs.txt"])).unwrap().unwrap();
+        assert_eq!(parsed.input, PathBuf::from("jobs.txt"));
+        assert_eq!((parsed.mode, parsed.power), (Mode::Xor, Some(2)));
+        assert!(parse_explain_args(args(&["--no-normalize", "jobs.txt"])).unwrap().is_some_and(|parsed| !parsed.normalize));
+        assert!(parse_explain_args(args(&["jobs.txt", "out.txt"])).unwrap().is_none());
+        assert!(parse_explain_args(args(&["--palindrome", "jobs.txt"])).is_err());
+        let parsed = parse_explain_args(args(&["--longest-first", "jobs.txt"])).unwrap().unwrap();
+        assert!(parsed.longest_first);
+    }
+
+    #[test]
+    fn test_parse_store_args() {
+        let parsed = parse_get_args(args(&["results.db", "3", "5", "1k", "--count"])).unwrap().unwrap();
+        assert_eq!(parsed.job, LineNumbers::new(3, 5, 1000).unwrap());
+        assert!(parsed.count_only);
+        assert!(parse_get_args(args(&["results.db", "3", "5"])).unwrap().is_none());
+        assert_eq!(parse_list_args(args(&["results.db"])).unwrap(), Some(PathBuf::from("results.db")));
+        assert!(parse_list_args(args(&["--all", "results.db"])).is_err());
+        assert_eq!(parse_init_args(args(&["demo"])).unwrap(), Some(PathBuf::from("demo")));
+        assert!(parse_init_args(args(&[])).unwrap().is_none());
+        assert_eq!(parse_regress_args(args(&["--cases=golden"])).unwrap(), Some(PathBuf::from("golden")));
+        assert_eq!(parse_regress_args(args(&[])).unwrap(), Some(PathBuf::from("cases")));
+        assert!(parse_regress_args(args(&["golden"])).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_bench_args() {
+        let parsed = parse_bench_args(args(&["--compare-with", "/opt/old/multiples", "in.txt", "--", "--threads", "4"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.input, PathBuf::from("in.txt"));
+        assert_eq!(parsed.options, ["--threads", "4"]);
+        assert_eq!(parsed.compare_with, Some(PathBuf::from("/opt/old/multiples")));
+        assert_eq!(parsed.runs, 3);
+        assert_eq!(parse_bench_args(args(&["--runs=5", "in.txt"])).unwrap().unwrap().runs, 5);
+        assert!(parse_bench_args(args(&["--compare-with", "old"])).unwrap().is_none());
+        assert!(parse_bench_args(args(&["--threads", "4", "in.txt"])).is_err());
+    }
+
+    #[test]
+    fn test_take_lang() {
+        let mut parsed = args(&["multiples", "--threads", "2", "--lang", "de", "in.txt", "out.txt"]);
+        assert_eq!(take_lang(&mut parsed).unwrap(), Some(Lang::De));
+        assert_eq!(parsed, ["multiples", "--threads", "2", "in.txt", "out.txt"]);
+        let mut parsed = args(&["multiples", "explain-error", "--lang=en", "MP0001"]);
+        assert_eq!(take_lang(&mut parsed).unwrap(), Some(Lang::En));
+        assert_eq!(parsed, ["multiples", "explain-error", "MP0001"]);
+        let mut parsed = args(&["multiples", "bench", "in.txt", "--", "--lang", "de"]);
+        assert_eq!(take_lang(&mut parsed).unwrap(), None);
+        assert_eq!(parsed.len(), 6);
+        assert!(take_lang(&mut args(&["multiples", "--lang", "xx"])).is_err());
+        assert!(take_lang(&mut args(&["multiples", "--lang"])).is_err());
+        // The catalog is keyed by the English text, so it has to follow it.
+        assert_ne!(Lang::De.tr(ENV_VARIABLES), ENV_VARIABLES);
+    }
+
+    #[test]
+    fn test_parse_explain_error_args() {
+        assert_eq!(parse_explain_error_args(args(&["MP0003"])).unwrap(), Some(String::from("MP0003")));
+        assert_eq!(parse_explain_error_args(args(&[])).unwrap(), None);
+        assert!(parse_explain_error_args(args(&["MP0001", "MP0002"])).is_err());
+        assert_eq!(parse_wizard_args(args(&["jobs.txt"])).unwrap(), Some(PathBuf::from("jobs.txt")));
+        assert_eq!(parse_wizard_args(args(&[])).unwrap(), None);
+        assert!(parse_wizard_args(args(&["--mode", "xor"])).is_err());
+        assert_eq!(parse_help_args(args(&["server"])).unwrap(), Some(String::from("server")));
+        assert!(parse_help_args(args(&["server", "formats"])).is_err());
+        let err = parse_explain_error_args(args(&["--verbose"])).unwrap_err();
+        assert_eq!(errors::code_of(&err), Some(&errors::UNKNOWN_OPTION));
+    }
+
+    #[test]
+    fn test_parse_gen_input_args() {
+        let parsed = parse_gen_input_args(args(&["--lines", "10", "--max-end", "1e6", "--seed", "7"])).unwrap().unwrap();
+        assert_eq!((parsed.output, parsed.lines, parsed.seed), (None, 10, 7));
+        assert_eq!((parsed.bounds.max_divisor, parsed.bounds.max_end), (100, 1_000_000));
+        let parsed = parse_gen_input_args(args(&["jobs.txt"])).unwrap().unwrap();
+        assert_eq!(parsed.output, Some(PathBuf::from("jobs.txt")));
+        assert!(parse_gen_input_args(args(&["--max-divisor", "0"])).is_err());
+        assert!(parse_gen_input_args(args(&["--max-end", "5G"])).is_err());
+        assert!(parse_gen_input_args(args(&["a.txt", "b.txt"])).unwrap().is_none());
+
+        let parsed = parse_selftest_args(args(&["--cases", "10", "--max-end=1k"])).unwrap().unwrap();
+        assert_eq!((parsed.cases, parsed.seed, parsed.bounds.max_end), (10, None, 1000));
+        assert!(parse_selftest_args(args(&["extra"])).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_lint_args() {
+        let parsed = parse_lint_args(args(&["--deny", "warnings", "--max-size=1M", "jobs.txt"])).unwrap().unwrap();
+        assert_eq!(parsed.input, PathBuf::from("jobs.txt"));
+        assert_eq!((parsed.max_size, parsed.deny), (1_000_000, Some(Severity::Warning)));
+        let parsed = parse_lint_args(args(&["jobs.txt"])).unwrap().unwrap();
+        assert_eq!((parsed.mode, parsed.max_size, parsed.deny), (Mode::Or, 1_000_000_000, None));
+        assert!(parse_lint_args(args(&["--deny", "errors", "jobs.txt"])).is_err());
+        assert!(parse_lint_args(args(&["jobs.txt", "out.txt"])).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_serve_args() {
+        let parsed = parse_serve_args(args(&["--listen", "0.0.0.0:80", "--max-end=1k", "--rate-limit", "10/s"])).unwrap().unwrap();
+        assert_eq!((parsed.listen.as_str(), parsed.config.max_end), ("0.0.0.0:80", 1000));
+        assert_eq!(parsed.config.rate_limit.map(|limit| limit.requests), Some(10));
+        assert_eq!(parsed.config.max_response_size, 16_000_000);
+        assert!(parse_serve_args(args(&["--rate-limit", "10"])).is_err());
+        let parsed = parse_serve_args(args(&["--jwt-secret-file", "a.key", "--jwt-secret-file=b.key", "--jwt-issuer", "ci"])).unwrap().unwrap();
+        assert_eq!((parsed.jwt_secrets.len(), parsed.jwt_issuer.as_deref(), parsed.api_keys), (2, Some("ci"), None));
+        assert!(parse_serve_args(args(&["--jwt-issuer", "ci"])).is_err());
+        let parsed = parse_serve_args(args(&["--max-request-size", "10M", "--threads=4"])).unwrap().unwrap();
+        assert_eq!((parsed.config.max_request_size, parsed.config.threads), (10_000_000, 4));
+        let parsed = parse_serve_args(args(&["--max-tokens", "0", "--max-line-length", "1k"])).unwrap().unwrap();
+        assert_eq!((parsed.config.limits.max_tokens, parsed.config.limits.max_line_length), (None, Some(1000)));
+        assert_eq!(parsed.config.limits.max_lines, Some(100_000));
+        let parsed = parse_serve_args(args(&["--cache-size", "0", "--cache-ttl=1m30s"])).unwrap().unwrap();
+        assert_eq!((parsed.config.cache_size, parsed.config.cache_ttl), (0, Some(Duration::from_secs(90))));
+        assert_eq!(parse_serve_args(args(&[])).unwrap().unwrap().config.cache_ttl, None);
+        assert!(parse_serve_args(args(&["--cache-ttl", "0"])).is_err());
+        assert!(parse_serve_args(args(&["--otlp-endpoint", "http://localhost:4318", "--otlp-sample=0.1"])).unwrap().unwrap().config.otlp.is_some());
+        assert!(parse_serve_args(args(&["--otlp-endpoint", "https://localhost:4318"])).is_err());
+        assert!(parse_serve_args(args(&["--otlp-sample", "2"])).is_err());
+        let parsed = parse_serve_args(args(&["--service", "--pid-file", "serve.pid"])).unwrap().unwrap();
+        assert!(parsed.service && parsed.pid_file == Some(PathBuf::from("serve.pid")));
+        assert_eq!(parse_serve_args(args(&["--max-connections", "1k"])).unwrap().unwrap().config.max_connections, 1000);
+        assert_eq!(parse_serve_args(args(&[])).unwrap().unwrap().config.max_connections, 256);
+        assert!(parse_serve_args(args(&["--max-connections", "0"])).is_err());
+        assert!(parse_serve_args(args(&["extra"])).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_daemon_args() {
+        let parsed = parse_daemon_args(args(&["--schedule", "0 2 * * *", "jobs.txt", "out.json", "--output-format=json"])).unwrap().unwrap();
+        let add = parsed.add.unwrap();
+        assert_eq!((add.cron.as_str(), add.jobs), ("0 2 * * *", ScheduledJobs::File(PathBuf::from("jobs.txt"))));
+        assert_eq!((add.output, add.output_format.as_str()), (PathBuf::from("out.json"), "json"));
+        assert_eq!((parsed.state_dir, parsed.action), (PathBuf::from("multiples-daemon"), DaemonAction::Run));
+        let parsed = parse_daemon_args(args(&["--schedule", "@hourly", "--job", "2 3 10;5 7 20", "out.txt"])).unwrap().unwrap();
+        assert_eq!(parsed.add.unwrap().jobs, ScheduledJobs::Inline(String::from("2 3 10\n5 7 20")));
+        assert!(parse_daemon_args(args(&["--schedule", "@hourly", "--job", "2 3 10", "in", "out"])).unwrap().is_none());
+        let parsed = parse_daemon_args(args(&["--state-dir", "/var/lib/multiples", "--remove", "3"])).unwrap().unwrap();
+        assert_eq!((parsed.add.is_none(), parsed.action), (true, DaemonAction::Remove(3)));
+        assert!(parse_daemon_args(args(&["--list", "--history"])).is_err());
+        assert!(parse_daemon_args(args(&["--job", "2 3 10"])).is_err());
+        assert!(parse_daemon_args(args(&["--otlp-endpoint", "http://collector:4318"])).unwrap().unwrap().otlp.is_some());
+        assert!(parse_daemon_args(args(&[])).unwrap().unwrap().otlp.is_none());
+        assert!(parse_daemon_args(args(&["--otlp-sample", "-1"])).is_err());
+        assert!(parse_daemon_args(args(&["out.txt"])).unwrap().is_none());
+    }
+}
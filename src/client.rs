@@ -0,0 +1,186 @@
+//! A typed client for the HTTP mode started by `serve`, so that other Rust
+//! services don't have to build the requests themselves.
+//!
+//! ```no_run
+//! use multiple_of_a_and_b::client::MultiplesClient;
+//!
+//! let client = MultiplesClient::new("http://multiples.internal:8080")?.with_api_key("key-a");
+//! let record = client.generate(2, 3, 100)?;
+//! assert_eq!(record.result.count(), 67);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::io::Cursor;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::formula::Mode;
+use crate::http::{self, HttpUrl};
+use crate::json;
+use crate::reader::{JsonReader, ResultReader};
+use crate::{LineNumbers, ResultRecord};
+
+/// What to ask `/multiples` for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GenerateRequest {
+    pub job: LineNumbers,
+    pub mode: Mode,
+    pub count_only: bool,
+}
+
+impl GenerateRequest {
+    pub fn new(a: u32, b: u32, end: u32) -> Self {
+        GenerateRequest {
+            job: LineNumbers { a, b, end },
+            mode: Mode::Or,
+            count_only: false,
+        }
+    }
+}
+
+/// The credentials sent with every request.
+#[derive(Clone, Debug)]
+enum Credentials {
+    None,
+    ApiKey(String),
+    Bearer(String),
+}
+
+/// Calls a `serve` instance.
+#[derive(Clone, Debug)]
+pub struct MultiplesClient {
+    base: HttpUrl,
+    credentials: Credentials,
+    timeout: Duration,
+}
+
+impl MultiplesClient {
+    /// A client for the server at `base_url`, e.g. `http://localhost:8080`.
+    pub fn new(base_url: &str) -> Result<Self> {
+        let mut base = HttpUrl::parse(base_url)?;
+        base.path = base.path.trim_end_matches('/').to_string();
+        Ok(MultiplesClient {
+            base,
+            credentials: Credentials::None,
+            timeout: Duration::from_secs(30),
+        })
+    }
+
+    /// Sends `key` in the `X-API-Key` header.
+    pub fn with_api_key(mut self, key: &str) -> Self {
+        self.credentials = Credentials::ApiKey(key.to_string());
+        self
+    }
+
+    /// Sends `token` as an `Authorization: Bearer` token.
+    pub fn with_bearer_token(mut self, token: &str) -> Self {
+        self.credentials = Credentials::Bearer(token.to_string());
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The multiples of `a` or `b` up to `end`.
+    pub fn generate(&self, a: u32, b: u32, end: u32) -> Result<ResultRecord> {
+        self.generate_with(&GenerateRequest::new(a, b, end))
+    }
+
+    /// The number of multiples of `a` or `b` up to `end`.
+    pub fn count(&self, a: u32, b: u32, end: u32) -> Result<u64> {
+        let request = GenerateRequest {
+            count_only: true,
+            ..GenerateRequest::new(a, b, end)
+        };
+        Ok(self.generate_with(&request)?.result.count())
+    }
+
+    pub fn generate_with(&self, request: &GenerateRequest) -> Result<ResultRecord> {
+        let job = request.job;
+        let mut url = self.base.clone();
+        url.path = format!(
+            "{}/multiples?a={}&b={}&end={}&mode={}&count_only={}&format=json",
+            url.path,
+            job.a,
+            job.b,
+            job.end,
+            request.mode.name(),
+            request.count_only
+        );
+        let authorization;
+        let headers: Vec<(&str, &str)> = match &self.credentials {
+            Credentials::None => Vec::new(),
+            Credentials::ApiKey(key) => vec![("X-API-Key", key.as_str())],
+            Credentials::Bearer(token) => {
+                authorization = format!("Bearer {}", token);
+                vec![("Authorization", authorization.as_str())]
+            }
+        };
+        let response = http::get(&url, &headers, self.timeout)?;
+        if response.status != 200 {
+            bail!("Server answered {}", problem_summary(response.status, &response.body));
+        }
+        let mut records = JsonReader.read(Box::new(Cursor::new(response.body)));
+        let record = records.next().ok_or_else(|| anyhow!("Server answered without a record"))?;
+        let mut record = record.context("Invalid answer from the server")?;
+        // Count-only records of nothing read back as listing no numbers.
+        if request.count_only && record.result.count.is_none() {
+            record.result.count = Some(record.result.numbers.len() as u64);
+        }
+        Ok(record)
+    }
+}
+
+/// `status title: detail`, with the parameters at fault, from a problem
+/// document, or the status and body of any other answer.
+fn problem_summary(status: u16, body: &[u8]) -> String {
+    let text = String::from_utf8_lossy(body);
+    let Ok(problem) = json::parse(&text) else {
+        return format!("{}: {}", status, text.trim());
+    };
+    let field = |key: &str| problem.get(key).and_then(|value| value.as_text()).unwrap_or_default().to_string();
+    let mut summary = format!("{} {}: {}", status, field("title"), field("detail"));
+    for error in problem.get("errors").and_then(|errors| errors.as_array()).unwrap_or_default() {
+        let field = |key: &str| error.get(key).and_then(|value| value.as_text()).unwrap_or_default().to_string();
+        summary.push_str(&format!("; {}: {}", field("parameter"), field("detail")));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::{GenerateRequest, MultiplesClient};
+    use crate::formula::Mode;
+    use crate::server::{Server, ServerConfig};
+
+    #[test]
+    fn test_client_against_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = Arc::new(Server::new(ServerConfig {
+            max_end: 1000,
+            ..Default::default()
+        }));
+        thread::spawn(move || server.serve(listener));
+
+        let client = MultiplesClient::new(&format!("http://{}/", address)).unwrap();
+        let record = client.generate(2, 3, 10).unwrap();
+        assert_eq!(record.result.numbers, [2, 3, 4, 6, 8, 9, 10]);
+        assert_eq!(client.count(2, 3, 1000).unwrap(), 667);
+        let xor = GenerateRequest {
+            mode: Mode::Xor,
+            ..GenerateRequest::new(2, 3, 10)
+        };
+        assert_eq!(client.generate_with(&xor).unwrap().result.numbers, [2, 3, 4, 8, 9, 10]);
+        let err = client.generate(2, 3, 5000).unwrap_err().to_string();
+        assert!(err.starts_with("Server answered 400 Invalid request"), "{}", err);
+        assert!(err.contains("end: Must be at most 1000"), "{}", err);
+    }
+}
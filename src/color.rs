@@ -0,0 +1,172 @@
+//! Colored output for terminals, for `--color`: record headers in bold,
+//! multiples of both divisors highlighted and tables drawn with box-drawing
+//! characters. Output that doesn't go to a terminal stays plain text.
+
+use std::env;
+use std::fmt::Write as _;
+use std::io::IsTerminal;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use crate::console;
+use crate::formatter::Render;
+use crate::{LineNumbers, ResultNumbers};
+
+const BOLD: &str = "\x1b[1m";
+const HIGHLIGHT: &str = "\x1b[1;33m";
+const RESET: &str = "\x1b[0m";
+
+/// When to color output.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorChoice {
+    /// Only on terminals that show escape codes, unless `NO_COLOR` is set
+    /// or `TERM` is `dumb`.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(anyhow!("--color is auto, always or never, not {}", name)),
+        }
+    }
+}
+
+impl ColorChoice {
+    /// Whether to color what is written to `stream`.
+    pub fn enabled(self, stream: &impl IsTerminal) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+                let dumb = env::var("TERM").is_ok_and(|term| term == "dumb");
+                stream.is_terminal() && !no_color && !dumb && console::supports_ansi()
+            }
+        }
+    }
+}
+
+/// The record `result` of `job` as [`ResultNumbers`] displays it, or with the
+/// values written by `render` for `--decimal` and `--time`, with the `end:`
+/// header in bold and the multiples of both divisors highlighted.
+pub fn paint_record(result: &ResultNumbers, job: &LineNumbers, render: Option<&dyn Render>) -> String {
+    let value = |n: u32| render.map_or_else(|| n.to_string(), |render| render.value(n));
+    let mut text = format!("{}{}:{}", BOLD, value(result.end), RESET);
+    if let Some(count) = result.count {
+        let _ = write!(text, "{}", count);
+        return text;
+    }
+    let both = |n: u32| job.a != 0 && job.b != 0 && n.is_multiple_of(job.a) && n.is_multiple_of(job.b);
+    for (i, &n) in result.numbers.iter().enumerate() {
+        let separator = if i > 0 { " " } else { "" };
+        match both(n) {
+            true => write!(text, "{}{}{}{}", separator, HIGHLIGHT, value(n), RESET),
+            false => write!(text, "{}{}", separator, value(n)),
+        }
+        .unwrap();
+    }
+    text
+}
+
+/// A table with a box-drawing frame around `header` and `rows`, whose
+/// columns are left-aligned except where `right` is set.
+pub fn boxed_table(header: &[&str], rows: &[Vec<String>], right: &[bool]) -> String {
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| {
+            let cells = rows.iter().map(|row| row[column].chars().count());
+            cells.chain([header[column].chars().count()]).max().unwrap_or(0)
+        })
+        .collect();
+    let rule = |left: char, middle: char, end: char| {
+        let lines: Vec<String> = widths.iter().map(|&width| "─".repeat(width + 2)).collect();
+        format!("{}{}{}\n", left, lines.join(&middle.to_string()), end)
+    };
+    // Cells are padded before the header is set in bold, since the escape
+    // codes take up no columns.
+    let row = |cells: &[String], style: &str| {
+        let reset = if style.is_empty() { "" } else { RESET };
+        let cells: Vec<String> = cells
+            .iter()
+            .enumerate()
+            .map(|(column, cell)| match right.get(column) {
+                Some(true) => format!(" {}{:>width$}{} ", style, cell, reset, width = widths[column]),
+                _ => format!(" {}{:<width$}{} ", style, cell, reset, width = widths[column]),
+            })
+            .collect();
+        format!("│{}│\n", cells.join("│"))
+    };
+    let mut table = rule('┌', '┬', '┐');
+    table.push_str(&row(&header.iter().map(|cell| cell.to_string()).collect::<Vec<_>>(), BOLD));
+    table.push_str(&rule('├', '┼', '┤'));
+    for cells in rows {
+        table.push_str(&row(cells, ""));
+    }
+    table.push_str(&rule('└', '┴', '┘'));
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::{boxed_table, paint_record, ColorChoice};
+    use crate::decimal;
+    use crate::{LineNumbers, ResultNumbers};
+
+    #[test]
+    fn test_color_choice() {
+        let file = File::open("Cargo.toml").unwrap();
+        assert_eq!("auto".parse::<ColorChoice>().unwrap(), ColorChoice::Auto);
+        assert!("always".parse::<ColorChoice>().unwrap().enabled(&file));
+        assert!(!"never".parse::<ColorChoice>().unwrap().enabled(&std::io::stdout()));
+        // A file is no terminal.
+        assert!(!ColorChoice::Auto.enabled(&file));
+        assert!("yes".parse::<ColorChoice>().is_err());
+    }
+
+    #[test]
+    fn test_paint_record() {
+        let job = LineNumbers { a: 3, b: 5, end: 16 };
+        let result = ResultNumbers {
+            end: 16,
+            numbers: vec![3, 5, 15],
+            count: None,
+        };
+        let painted = paint_record(&result, &job, None);
+        assert_eq!(painted, "\x1b[1m16:\x1b[0m3 5 \x1b[1;33m15\x1b[0m");
+        let plain = painted.replace("\x1b[1m", "").replace("\x1b[1;33m", "").replace("\x1b[0m", "");
+        assert_eq!(plain, result.to_string());
+        let painted = paint_record(&result, &job, Some(&*decimal::render(1)));
+        assert_eq!(painted, "\x1b[1m1.6:\x1b[0m0.3 0.5 \x1b[1;33m1.5\x1b[0m");
+        let counted = ResultNumbers {
+            count: Some(7),
+            ..result
+        };
+        assert_eq!(paint_record(&counted, &job, None), "\x1b[1m16:\x1b[0m7");
+    }
+
+    #[test]
+    fn test_boxed_table() {
+        let rows = vec![vec!["parse".to_string(), "3".to_string()], vec!["generate".to_string(), "12".to_string()]];
+        let table = boxed_table(&["phase", "spans"], &rows, &[false, true]);
+        let expected = "\
+┌──────────┬───────┐
+│ \x1b[1mphase   \x1b[0m │ \x1b[1mspans\x1b[0m │
+├──────────┼───────┤
+│ parse    │     3 │
+│ generate │    12 │
+└──────────┴───────┘
+";
+        assert_eq!(table, expected);
+    }
+}
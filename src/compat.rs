@@ -0,0 +1,102 @@
+//! `--compat`: runs that write exactly what an earlier release wrote for
+//! the same input, for pipelines that compare or parse the output and
+//! shouldn't see it change when the binary is upgraded.
+//!
+//! Release 1.0 read `a b end` lines of plain decimal numbers, ignoring any
+//! other token, and wrote one `end:numbers` line per record to the output
+//! and to stdout, sorted by the number of multiples.
+
+use std::fmt;
+use std::io::BufRead;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::input::{InputParser, JobIter};
+use crate::locale::tr_args;
+use crate::LineNumbers;
+
+/// A release whose output a run reproduces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compat {
+    V1_0,
+}
+
+impl fmt::Display for Compat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Compat::V1_0 => "1.0",
+        })
+    }
+}
+
+impl FromStr for Compat {
+    type Err = anyhow::Error;
+
+    fn from_str(version: &str) -> Result<Self> {
+        match version {
+            "1" | "1.0" => Ok(Compat::V1_0),
+            _ => Err(anyhow!("Unknown --compat version: {} (available: 1.0)", version)),
+        }
+    }
+}
+
+/// Text input as release 1.0 read it: the tokens of a line that are plain
+/// decimal `u32`s, of which there must be exactly three. Literals such as
+/// `1_000` or `0x10`, output paths and line options are ignored like any
+/// other word.
+pub struct LegacyTextParser;
+
+impl InputParser for LegacyTextParser {
+    fn parse<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> JobIter<'a> {
+        Box::new(reader.lines().enumerate().map(|(line_num, line)| {
+            let line = line.with_context(|| tr_args("Failed to read line {}", &[&(line_num + 1)]))?;
+            let numbers: Vec<u32> = line.split_whitespace().filter_map(|token| token.parse().ok()).collect();
+            match numbers[..] {
+                [a, b, end] => Ok(LineNumbers { a, b, end }),
+                _ => Err(anyhow!("Line {} does not contain exactly 3 numbers", line_num + 1)),
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::{BufReader, Cursor};
+
+    use super::{Compat, LegacyTextParser};
+    use crate::formatter::FormatterRegistry;
+    use crate::input::InputParser;
+    use crate::pipeline::{FormatterSink, Pipeline};
+    use crate::LineNumbers;
+
+    #[test]
+    fn test_legacy_parser() {
+        let parse = |text: &'static str| LegacyTextParser.parse(Box::new(Cursor::new(text))).collect::<Vec<_>>();
+        let jobs = parse("3 5 100\n  4\t7 20 words\n+2 3 10 > out.txt\n");
+        let jobs: Vec<_> = jobs.into_iter().map(Result::unwrap).collect();
+        assert_eq!(jobs[1], LineNumbers { a: 4, b: 7, end: 20 });
+        assert_eq!(jobs[2], LineNumbers { a: 2, b: 3, end: 10 });
+        let err = parse("3 5 1_000\n").remove(0).unwrap_err();
+        assert_eq!(err.to_string(), "Line 1 does not contain exactly 3 numbers");
+        assert!(parse("0x3 5 100\n")[0].is_err());
+        assert_eq!("1.0".parse::<Compat>().unwrap().to_string(), "1.0");
+        assert!("2.0".parse::<Compat>().is_err());
+    }
+
+    /// The output of release 1.0 for the same input, byte for byte.
+    #[test]
+    fn test_legacy_output() {
+        let file = File::open("test_data/input_2_rows.txt").unwrap();
+        let source = LegacyTextParser.parse(Box::new(BufReader::new(file)));
+        let mut sink = FormatterSink::new(Vec::new(), FormatterRegistry::with_builtins().create("text").unwrap());
+        Pipeline::builder(source)
+            .sort_by_key(|record| record.result.count())
+            .build()
+            .run(&mut sink)
+            .unwrap();
+        let expected = fs::read("test_data/result_2_comparison.txt").unwrap();
+        assert_eq!(sink.into_inner(), expected);
+    }
+}
@@ -0,0 +1,201 @@
+//! Options files such as `multiples.toml`, read with `--config`.
+//!
+//! An options file holds one `key = value` pair per line in a small subset
+//! of TOML, where each key is the name of a command line option without its
+//! leading dashes:
+//!
+//! ```toml
+//! # Multiples of a or b, written as JSON.
+//! output-format = "json"
+//! threads = 4
+//! count-only = true
+//! ```
+//!
+//! Strings, numbers and booleans are supported, as well as arrays of them
+//! for options that may be given several times. `true` turns a flag on and
+//! `false` leaves it out. Tables aren't supported.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// One value of an options file.
+enum Item {
+    Bool(bool),
+    Text(String),
+}
+
+/// The items of the value at the start of `text`, more than one for an
+/// array, and the text after it.
+fn values(text: &str, line_num: usize) -> Result<(Vec<Item>, &str)> {
+    let text = text.trim_start();
+    if let Some(rest) = text.strip_prefix('[') {
+        let mut items = Vec::new();
+        let mut rest = rest.trim_start();
+        while !rest.starts_with(']') {
+            let (mut item, after) = values(rest, line_num)?;
+            if item.len() != 1 || rest.starts_with('[') {
+                bail!("Line {}: arrays may only hold strings and numbers", line_num);
+            }
+            items.append(&mut item);
+            rest = after.trim_start();
+            rest = match rest.strip_prefix(',') {
+                Some(after) => after.trim_start(),
+                None if rest.starts_with(']') => rest,
+                None => bail!("Line {}: expected , or ] in array", line_num),
+            };
+        }
+        return Ok((items, &rest[1..]));
+    }
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((at, c)) = chars.next() {
+            match c {
+                '"' => return Ok((vec![Item::Text(value)], &rest[at + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    _ => bail!("Line {}: unsupported escape in string", line_num),
+                },
+                c => value.push(c),
+            }
+        }
+        bail!("Line {}: unterminated string", line_num);
+    }
+    let end = text.find([',', ']', '#', ' ', '\t']).unwrap_or(text.len());
+    let (token, rest) = text.split_at(end);
+    match token {
+        "" => bail!("Line {}: missing value", line_num),
+        "true" => Ok((vec![Item::Bool(true)], rest)),
+        "false" => Ok((vec![Item::Bool(false)], rest)),
+        // Numbers are handed to the option as written, so they may use the
+        // same literals as the command line.
+        _ if token.starts_with(|c: char| c.is_ascii_digit() || c == '+' || c == '.') => Ok((vec![Item::Text(token.to_string())], rest)),
+        _ => bail!("Line {}: {:?} isn't a string, number or boolean (strings need quotes)", line_num, token),
+    }
+}
+
+/// Turns the text of an options file into the command line arguments it
+/// stands for, in the order of its lines.
+pub fn parse_options(text: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut seen = HashSet::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_num = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            bail!("Line {}: tables aren't supported; write every option at the top level", line_num);
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Line {}: expected key = value", line_num))?;
+        let key = key.trim();
+        if key.is_empty() || !key.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_') {
+            bail!("Line {}: invalid key {:?}", line_num, key);
+        }
+        if key == "config" {
+            bail!("Line {}: options files can't include other options files", line_num);
+        }
+        if !seen.insert(key.to_string()) {
+            bail!("Line {}: {} is set twice", line_num, key);
+        }
+        let (values, rest) = values(value, line_num)?;
+        let rest = rest.trim_start();
+        if !rest.is_empty() && !rest.starts_with('#') {
+            bail!("Line {}: unexpected {:?} after the value", line_num, rest);
+        }
+        let flag = format!("--{}", key);
+        for value in values {
+            match value {
+                Item::Bool(true) => args.push(flag.clone()),
+                Item::Bool(false) => {}
+                Item::Text(value) => args.extend([flag.clone(), value]),
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// Reads the options file at `path`, see [`parse_options`].
+pub fn read_options(path: &Path) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    parse_options(&text).with_context(|| format!("Invalid options file {:?}", path))
+}
+
+/// Replaces each `--config <path>` in `args` by the options of that file.
+/// The options of the files come first, so that the ones given on the
+/// command line win over them.
+pub fn expand_config(args: Vec<String>) -> Result<Vec<String>> {
+    let mut options = Vec::new();
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let path = match arg.strip_prefix("--config") {
+            Some("") => args.next().ok_or_else(|| anyhow!("Missing value for --config"))?,
+            Some(path) if path.starts_with('=') => path[1..].to_string(),
+            _ => {
+                rest.push(arg);
+                continue;
+            }
+        };
+        options.extend(read_options(Path::new(&path))?);
+    }
+    options.extend(rest);
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_options;
+
+    #[test]
+    fn test_parse_options() {
+        let text = r#"
+# Comments and blank lines are skipped.
+output-format = "json"   # trailing comments too
+threads = 4
+count-only = true
+palindrome = false
+max-end = 1.5G
+plugin = ["a.so", "b \"quoted\".so"]
+"#;
+        assert_eq!(
+            parse_options(text).unwrap(),
+            [
+                "--output-format",
+                "json",
+                "--threads",
+                "4",
+                "--count-only",
+                "--max-end",
+                "1.5G",
+                "--plugin",
+                "a.so",
+                "--plugin",
+                "b \"quoted\".so"
+            ]
+        );
+        for (text, error) in [
+            ("[generate]", "tables"),
+            ("mode = or", "need quotes"),
+            ("mode = \"or", "unterminated"),
+            ("threads", "key = value"),
+            ("a = 1\na = 2", "set twice"),
+            ("config = \"other.toml\"", "can't include"),
+            ("plugin = [\"a\" \"b\"]", "expected , or ]"),
+            ("plugin = [[\"a\"]]", "only hold"),
+            ("threads = 4 4", "after the value"),
+        ] {
+            let message = parse_options(text).unwrap_err().to_string();
+            assert!(message.contains(error), "{}: {}", text, message);
+        }
+    }
+}
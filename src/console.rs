@@ -0,0 +1,64 @@
+//! The Windows console, which by default shows UTF-8 output — the frames of
+//! `--profile` tables, the `…` of previews and translated messages — in
+//! the legacy code page of the system, and escape codes as text.
+//! [`prepare`] switches it to UTF-8 and, where the console supports them,
+//! to escape codes; elsewhere terminals need neither.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the console interprets escape codes, see [`supports_ansi`].
+static ANSI: AtomicBool = AtomicBool::new(true);
+
+/// Sets up the console of the process for UTF-8 and escape codes. Called
+/// once, before anything is printed.
+pub fn prepare() {
+    #[cfg(windows)]
+    windows::prepare();
+}
+
+/// Whether escape codes written to the console are shown as colors rather
+/// than as text: always, except on Windows consoles too old to enable them.
+pub fn supports_ansi() -> bool {
+    ANSI.load(Ordering::Relaxed)
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+    use std::sync::atomic::Ordering;
+
+    const CP_UTF8: u32 = 65001;
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleOutputCP(code_page: u32) -> i32;
+        fn SetConsoleCP(code_page: u32) -> i32;
+        fn GetStdHandle(handle: u32) -> *mut c_void;
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+
+    pub fn prepare() {
+        // SAFETY: these calls take no pointers but the mode, which outlives
+        // them, and fail harmlessly when there is no console, as when the
+        // output is redirected.
+        unsafe {
+            SetConsoleOutputCP(CP_UTF8);
+            // The wizard reads its answers from the console.
+            SetConsoleCP(CP_UTF8);
+            for handle in [STD_OUTPUT_HANDLE, STD_ERROR_HANDLE] {
+                let console = GetStdHandle(handle);
+                let mut mode = 0;
+                if GetConsoleMode(console, &mut mode) == 0 {
+                    continue;
+                }
+                if SetConsoleMode(console, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) == 0 {
+                    super::ANSI.store(false, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! CRC-32 (the IEEE polynomial of zlib and PNG), for the per-record
+//! checksums of `--checksums`: every record of a `json`, `ndjson` or `csv`
+//! output carries the CRC-32 of its contents, which the result readers
+//! check, so that a record corrupted on its way through storage fails the
+//! command reading it rather than being carried on.
+//!
+//! The checksum is taken over the values of a record, not over its text,
+//! so that it is the same in every format: the index as 8 bytes, `a`, `b`
+//! and the end as 4, the multiples as 4 each, and the count as 8, all
+//! little-endian.
+
+use crate::{LineNumbers, ResultRecord};
+
+const POLYNOMIAL: u32 = 0xedb8_8320;
+
+const TABLE: [u32; 256] = table();
+
+const fn table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLYNOMIAL } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Checksums data fed in pieces.
+#[derive(Clone)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32 { crc: !0 }
+    }
+}
+
+impl Crc32 {
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.crc = TABLE[((self.crc ^ byte as u32) & 0xff) as usize] ^ (self.crc >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.crc
+    }
+}
+
+/// The CRC-32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::default();
+    crc.update(data);
+    crc.finish()
+}
+
+/// The checksum of a record whose multiples are fed in pieces, as they are
+/// written.
+pub struct RecordChecksum {
+    crc: Crc32,
+    numbers: u64,
+}
+
+impl RecordChecksum {
+    pub fn new(index: usize, job: &LineNumbers, end: u32) -> Self {
+        let mut crc = Crc32::default();
+        crc.update(&(index as u64).to_le_bytes());
+        for value in [job.a, job.b, end] {
+            crc.update(&value.to_le_bytes());
+        }
+        RecordChecksum { crc, numbers: 0 }
+    }
+
+    pub fn numbers(&mut self, numbers: &[u32]) {
+        for n in numbers {
+            self.crc.update(&n.to_le_bytes());
+        }
+        self.numbers += numbers.len() as u64;
+    }
+
+    /// The checksum of the record with `count` multiples, or as many as were
+    /// fed when the count isn't known.
+    pub fn finish(mut self, count: Option<u64>) -> u32 {
+        self.crc.update(&count.unwrap_or(self.numbers).to_le_bytes());
+        self.crc.finish()
+    }
+}
+
+/// The checksum of `r`, as written by `--checksums`.
+pub fn record_checksum(r: &ResultRecord) -> u32 {
+    let mut checksum = RecordChecksum::new(r.index, &r.job, r.result.end);
+    checksum.numbers(&r.result.numbers);
+    checksum.finish(Some(r.result.count()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, record_checksum, Crc32, RecordChecksum};
+    use crate::{generate_record, LineNumbers};
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414f_a339);
+        let mut pieces = Crc32::default();
+        pieces.update(b"12345");
+        pieces.update(b"6789");
+        assert_eq!(pieces.finish(), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_record_checksum() {
+        let record = generate_record(LineNumbers { a: 2, b: 3, end: 10 });
+        let mut streamed = RecordChecksum::new(record.index, &record.job, 10);
+        streamed.numbers(&record.result.numbers[..3]);
+        streamed.numbers(&record.result.numbers[3..]);
+        assert_eq!(streamed.finish(None), record_checksum(&record));
+        let mut moved = record.clone();
+        moved.index = 1;
+        assert_ne!(record_checksum(&moved), record_checksum(&record));
+    }
+}
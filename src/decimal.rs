@@ -0,0 +1,148 @@
+//! Decimal divisors and ends for `--decimal`, e.g. every 0.25 or 0.4 up to
+//! 100.0.
+//!
+//! Values are scaled by `10^scale` into whole numbers when parsed, so the
+//! multiples are generated exactly like integer ones and only turned back
+//! into decimals when written: the multiples of 0.25 up to 100.00 are those
+//! of 25 up to 10000, each divided by 100.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::formatter::{self, FormatterRegistry, Render};
+use crate::input::{self, InputParserRegistry};
+
+/// The largest `--scale`: `10^9` still fits into a `u32`.
+pub const MAX_SCALE: u32 = 9;
+
+/// Parses `text`, e.g. `0.25`, as a whole number of `10^-scale` units.
+/// More decimal places than `scale` are only accepted when they are zeros.
+pub fn parse_decimal(text: &str, scale: u32) -> Result<u32> {
+    let invalid = || anyhow!("Invalid decimal: {:?}", text);
+    let digits = text.strip_prefix('+').unwrap_or(text);
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid());
+    }
+    if !whole.bytes().chain(fraction.bytes()).all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let (kept, dropped) = fraction.split_at(fraction.len().min(scale as usize));
+    if dropped.bytes().any(|byte| byte != b'0') {
+        bail!("{} has more than {} decimal places", text, scale);
+    }
+    let units = format!("{}{}{}", whole, kept, "0".repeat(scale as usize - kept.len()));
+    let value: u64 = match units.trim_start_matches('0') {
+        "" => 0,
+        units => units.parse().map_err(|_| invalid())?,
+    };
+    u32::try_from(value).map_err(|_| anyhow!("Decimal out of range: {} (at most {} with --scale {})", text, format_decimal(u32::MAX, scale), scale))
+}
+
+/// Writes `units` of `10^-scale` as a decimal with exactly `scale` places.
+pub fn format_decimal(units: u32, scale: u32) -> String {
+    if scale == 0 {
+        return units.to_string();
+    }
+    let unit = 10u64.pow(scale);
+    format!("{}.{:0width$}", units as u64 / unit, units as u64 % unit, width = scale as usize)
+}
+
+/// The parsers of `--decimal` input: the `text` and `csv` layouts with
+/// decimal fields.
+pub fn parsers(scale: u32) -> InputParserRegistry {
+    input::field_parsers(Arc::new(move |_, text| parse_decimal(text, scale)))
+}
+
+/// The formatters of `--decimal` output: `text`, `json`, `ndjson` and `csv`
+/// with every divisor, end and multiple written to `scale` places.
+pub fn formatters(scale: u32) -> FormatterRegistry {
+    formatter::rendered_formatters(render(scale))
+}
+
+/// Writes values to `scale` places, as the `--decimal` formatters do.
+pub fn render(scale: u32) -> Arc<dyn Render> {
+    Arc::new(Decimals(scale))
+}
+
+/// Writes values as decimals with the given number of places.
+struct Decimals(u32);
+
+impl Render for Decimals {
+    fn divisor(&self, n: u32) -> String {
+        format_decimal(n, self.0)
+    }
+
+    fn value(&self, n: u32) -> String {
+        format_decimal(n, self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{format_decimal, formatters, parse_decimal, parsers};
+    use crate::formatter::rendered_text;
+    use crate::{generate_record, LineNumbers};
+
+    #[test]
+    fn test_parse_and_format_decimal() {
+        assert_eq!(parse_decimal("0.25", 2).unwrap(), 25);
+        assert_eq!(parse_decimal("100.0", 2).unwrap(), 10_000);
+        assert_eq!(parse_decimal("100", 2).unwrap(), 10_000);
+        assert_eq!(parse_decimal(".4", 2).unwrap(), 40);
+        assert_eq!(parse_decimal("1.500", 2).unwrap(), 150);
+        assert_eq!(parse_decimal("7", 0).unwrap(), 7);
+        for text in ["", ".", "1.2.3", "-1", "1e3", "0x10"] {
+            assert!(parse_decimal(text, 2).is_err(), "{}", text);
+        }
+        assert!(parse_decimal("0.125", 2).unwrap_err().to_string().contains("more than 2 decimal places"));
+        assert!(parse_decimal("42949673", 2).is_err());
+        assert_eq!(parse_decimal("42949672.95", 2).unwrap(), u32::MAX);
+
+        assert_eq!(format_decimal(25, 2), "0.25");
+        assert_eq!(format_decimal(10_000, 2), "100.00");
+        assert_eq!(format_decimal(7, 3), "0.007");
+        assert_eq!(format_decimal(7, 0), "7");
+    }
+
+    #[test]
+    fn test_decimal_round_trip() {
+        let parsers = parsers(2);
+        let jobs: Vec<LineNumbers> = parsers
+            .get("text")
+            .unwrap()
+            .parse(Box::new(Cursor::new("0.25 0.4 2.0\n")))
+            .collect::<anyhow::Result<_>>()
+            .unwrap();
+        assert_eq!(jobs, vec![LineNumbers { a: 25, b: 40, end: 200 }]);
+        let csv = parsers.get("csv").unwrap().parse_routed(Box::new(Cursor::new("a,b,end\n0.5,1,3,out.txt\n")));
+        let routed: Vec<_> = csv.collect::<anyhow::Result<_>>().unwrap();
+        assert_eq!(routed[0].0, LineNumbers { a: 50, b: 100, end: 300 });
+        assert_eq!(routed[0].1.output.as_deref(), Some(std::path::Path::new("out.txt")));
+        assert!(parsers.get("text").unwrap().parse(Box::new(Cursor::new("0.125 1 2\n"))).next().unwrap().is_err());
+        assert!(parsers.get("json").is_err());
+
+        let record = generate_record(jobs[0]);
+        let registry = formatters(2);
+        let render = |format: &str| {
+            let mut formatter = registry.create(format).unwrap();
+            let mut out = Vec::new();
+            formatter.begin(&mut out).unwrap();
+            formatter.write_record(&mut out, &record).unwrap();
+            formatter.finish(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(render("text"), "2.00:0.25 0.40 0.50 0.75 0.80 1.00 1.20 1.25 1.50 1.60 1.75 2.00\n");
+        // The line echoed to stdout while the output is written.
+        assert_eq!(format!("{}\n", rendered_text(&*super::render(2), &record.result)), render("text"));
+        assert_eq!(
+            render("ndjson"),
+            "{\"schema\":1,\"index\":0,\"a\":0.25,\"b\":0.40,\"end\":2.00,\"count\":12,\"numbers\":[0.25,0.40,0.50,0.75,0.80,1.00,1.20,1.25,1.50,1.60,1.75,2.00]}\n"
+        );
+        assert!(render("csv").starts_with("index,a,b,end,count,numbers\n0,0.25,0.40,2.00,12,0.25 0.40"));
+        assert!(registry.create("store").is_err());
+    }
+}
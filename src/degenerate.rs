@@ -0,0 +1,127 @@
+//! Lines with nothing to compute, for `--degenerate`: equal divisors, a
+//! divisor of 1, which divides every number, a divisor that adds nothing to
+//! the other one, and ranges ending below both divisors.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use crate::errors::DEGENERATE_LINE;
+use crate::formula::{self, Mode};
+use crate::input::JobIter;
+use crate::report::Warnings;
+use crate::LineNumbers;
+
+/// What to do about a degenerate line.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Policy {
+    /// Generate it, printing why it is degenerate on stderr.
+    #[default]
+    Warn,
+    /// Generate its simplified form without a word.
+    Simplify,
+    /// Fail the run.
+    Error,
+}
+
+impl FromStr for Policy {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "warn" => Ok(Policy::Warn),
+            "simplify" => Ok(Policy::Simplify),
+            "error" => Ok(Policy::Error),
+            _ => Err(anyhow!("--degenerate is warn, simplify or error, not {}", name)),
+        }
+    }
+}
+
+/// Why `job` is degenerate in `mode`, or `None` if it isn't.
+pub fn check(job: &LineNumbers, mode: Mode) -> Option<String> {
+    let (a, b) = (job.a.min(job.b), job.a.max(job.b));
+    if a == b {
+        return Some(format!("both divisors are {}", a));
+    }
+    if a == 1 {
+        return Some(String::from("1 divides every number"));
+    }
+    if let (_, Some(reason)) = formula::simplify(job, mode) {
+        return Some(reason);
+    }
+    // 0 has no multiples, so only the other divisor can have any.
+    let smallest = if a == 0 { b } else { a };
+    if job.end < smallest {
+        return Some(format!("end {} is below both divisors", job.end));
+    }
+    None
+}
+
+/// `source` with every degenerate line handled by `policy`, warned about
+/// in `warnings`. `normalize` tells whether the generator simplifies lines,
+/// which warnings mention.
+pub fn checked(source: JobIter<'_>, mode: Mode, policy: Policy, normalize: bool, warnings: Warnings) -> JobIter<'_> {
+    Box::new(source.enumerate().map(move |(index, job)| {
+        let job = job?;
+        let Some(reason) = check(&job, mode) else {
+            return Ok(job);
+        };
+        match policy {
+            Policy::Warn => match formula::simplify(&job, mode) {
+                (simplified, Some(_)) if normalize => {
+                    let message = format!("{}, generating the multiples of {} only", reason, simplified.a);
+                    warnings.push(Some(index + 1), message)
+                }
+                _ => warnings.push(Some(index + 1), reason),
+            },
+            Policy::Simplify => {}
+            Policy::Error => return Err(DEGENERATE_LINE.error(format!("Line {}: {}", index + 1, reason))),
+        }
+        Ok(job)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, checked, Policy};
+    use crate::errors::{code_of, DEGENERATE_LINE};
+    use crate::formula::Mode;
+    use crate::report::Warnings;
+    use crate::LineNumbers;
+
+    fn job(a: u32, b: u32, end: u32) -> LineNumbers {
+        LineNumbers { a, b, end }
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check(&job(7, 7, 50), Mode::Or).as_deref(), Some("both divisors are 7"));
+        assert_eq!(check(&job(1, 4, 50), Mode::Or).as_deref(), Some("1 divides every number"));
+        assert_eq!(check(&job(9, 3, 50), Mode::Or).as_deref(), Some("9 is a multiple of 3"));
+        assert_eq!(check(&job(0, 3, 50), Mode::Or).as_deref(), Some("0 has no multiples"));
+        assert_eq!(check(&job(30, 50, 20), Mode::Or).as_deref(), Some("end 20 is below both divisors"));
+        assert_eq!(check(&job(0, 30, 20), Mode::Xor).as_deref(), Some("end 20 is below both divisors"));
+        assert_eq!(check(&job(3, 5, 100), Mode::Or), None);
+        assert_eq!(check(&job(9, 3, 50), Mode::Xor), None);
+    }
+
+    #[test]
+    fn test_policies() {
+        let jobs = || Box::new(vec![Ok(job(3, 5, 100)), Ok(job(4, 4, 100))].into_iter());
+        for policy in [Policy::Warn, Policy::Simplify] {
+            let warnings = Warnings::default();
+            let lines: Vec<_> = checked(jobs(), Mode::Or, policy, true, warnings.clone()).collect::<anyhow::Result<_>>().unwrap();
+            assert_eq!(lines, [job(3, 5, 100), job(4, 4, 100)]);
+            let warned: Vec<_> = warnings.take().iter().map(ToString::to_string).collect();
+            match policy {
+                Policy::Warn => assert_eq!(warned, ["Line 2: both divisors are 4"]),
+                _ => assert!(warned.is_empty()),
+            }
+        }
+        let err = checked(jobs(), Mode::Or, Policy::Error, true, Warnings::default()).nth(1).unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "Line 2: both divisors are 4");
+        assert_eq!(code_of(&err), Some(&DEGENERATE_LINE));
+        assert_eq!("simplify".parse::<Policy>().unwrap(), Policy::Simplify);
+        assert!("ignore".parse::<Policy>().is_err());
+    }
+}
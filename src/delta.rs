@@ -0,0 +1,162 @@
+//! The `delta` output format: a compact binary stream of records, written
+//! in output order and read back by [`DeltaReader`].
+//!
+//! The file starts with [`MAGIC`], followed by one entry per record:
+//!
+//! ```text
+//! index, a, b, end  varints
+//! kind              u8: 0 lists the numbers, 1 only counts them
+//! count             varint
+//! numbers           count varints of the zigzag encoded differences
+//!                   between consecutive numbers, starting from 0
+//! ```
+//!
+//! Multiples are sorted, so the differences are small: for `a=2,b=3` every
+//! number takes a single byte instead of up to 11 characters of text.
+//! Unlike a [store](crate::store), a delta file has no key index and keeps
+//! every record in the order it was written.
+
+use std::io::{BufRead, Read, Write};
+
+use anyhow::{bail, Context, Result};
+
+use crate::formatter::OutputFormatter;
+use crate::reader::{RecordIter, ResultReader};
+use crate::store::{read_varint, unzigzag, write_varint, zigzag};
+use crate::{LineNumbers, ResultNumbers, ResultRecord};
+
+/// The first bytes of every delta file.
+pub const MAGIC: &[u8; 8] = b"MULTDL1\n";
+
+const KIND_NUMBERS: u8 = 0;
+const KIND_COUNT: u8 = 1;
+
+/// Writes records as delta entries. Appending is supported, since entries
+/// don't refer to each other.
+#[derive(Default)]
+pub struct DeltaFormatter;
+
+impl OutputFormatter for DeltaFormatter {
+    fn begin(&mut self, w: &mut dyn Write) -> Result<()> {
+        w.write_all(MAGIC)?;
+        Ok(())
+    }
+
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        let mut entry = Vec::with_capacity(16 + r.result.numbers.len());
+        write_varint(&mut entry, r.index as u64)?;
+        for value in [r.job.a, r.job.b, r.result.end] {
+            write_varint(&mut entry, value as u64)?;
+        }
+        entry.push(if r.result.count.is_some() { KIND_COUNT } else { KIND_NUMBERS });
+        write_varint(&mut entry, r.result.count())?;
+        let mut previous = 0i64;
+        for &n in &r.result.numbers {
+            write_varint(&mut entry, zigzag(n as i64 - previous))?;
+            previous = n as i64;
+        }
+        w.write_all(&entry)?;
+        Ok(())
+    }
+}
+
+/// Reads the entry at the start of `r`, or `None` at the end of the file.
+fn read_entry(r: &mut dyn BufRead) -> Result<Option<ResultRecord>> {
+    if r.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let index = read_varint(r)? as usize;
+    let mut field = || -> Result<u32> { u32::try_from(read_varint(r)?).context("Value out of range") };
+    let job = LineNumbers {
+        a: field()?,
+        b: field()?,
+        end: field()?,
+    };
+    let mut kind = [0u8];
+    r.read_exact(&mut kind)?;
+    let count = read_varint(r)?;
+    let (numbers, count) = match kind[0] {
+        KIND_COUNT => (Vec::new(), Some(count)),
+        KIND_NUMBERS => {
+            let mut numbers = Vec::with_capacity(count.min(1 << 20) as usize);
+            let mut previous = 0i64;
+            for _ in 0..count {
+                let n = previous + unzigzag(read_varint(r)?);
+                numbers.push(u32::try_from(n).context("Number out of range")?);
+                previous = n;
+            }
+            (numbers, None)
+        }
+        kind => bail!("Unknown entry kind {}", kind),
+    };
+    Ok(Some(ResultRecord {
+        index,
+        job,
+        result: ResultNumbers { end: job.end, numbers, count },
+    }))
+}
+
+/// Decodes a delta file, record by record.
+pub struct DeltaReader;
+
+impl ResultReader for DeltaReader {
+    fn read<'a>(&self, mut reader: Box<dyn BufRead + Send + 'a>) -> RecordIter<'a> {
+        let mut magic = [0u8; 8];
+        if reader.read_exact(&mut magic).is_err() || &magic != MAGIC {
+            return Box::new(std::iter::once(Err(anyhow::anyhow!("Not a delta file: unexpected header"))));
+        }
+        let mut entry = 0usize;
+        let mut failed = false;
+        Box::new(std::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+            entry += 1;
+            let record = read_entry(&mut reader).with_context(|| format!("Entry {}: truncated or damaged", entry));
+            failed = record.is_err();
+            record.transpose()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeltaFormatter, DeltaReader};
+    use crate::formatter::OutputFormatter;
+    use crate::predicate::Divisible;
+    use crate::reader::ResultReader;
+    use crate::{count_record, formula::Mode, generate_record, generate_record_descending, LineNumbers, ResultRecord};
+
+    fn write_delta(records: &[ResultRecord]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut formatter = DeltaFormatter;
+        formatter.begin(&mut out).unwrap();
+        for record in records {
+            formatter.write_record(&mut out, record).unwrap();
+        }
+        formatter.finish(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let job = |a, b, end| LineNumbers { a, b, end };
+        let mut records = vec![
+            generate_record(job(2, 3, 1_000_000)),
+            generate_record_descending(job(2, 7, 30), &Divisible),
+            count_record(job(4, 6, 1000), Mode::Or),
+            generate_record(job(7, 9, 5)),
+        ];
+        records[1].index = 3;
+        let bytes = write_delta(&records);
+        // One byte per multiple, plus the header and the entries' fields.
+        let multiples = records[0].result.numbers.len() + records[1].result.numbers.len();
+        assert!(bytes.len() < multiples + 64, "{}", bytes.len());
+
+        let read = |bytes: &[u8]| DeltaReader.read(Box::new(bytes)).collect::<anyhow::Result<Vec<_>>>();
+        assert_eq!(read(&bytes).unwrap(), records);
+        assert!(read(&bytes[..bytes.len() - 1]).is_err());
+        assert!(read(b"MULTKV1\n").is_err());
+        assert_eq!(read(&bytes[..8]).unwrap(), vec![]);
+    }
+}
@@ -0,0 +1,243 @@
+//! The `describe` command: what a result file is and holds, for files whose
+//! name doesn't tell. The format is told by the contents of the file where
+//! they are clear and by its extension otherwise.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::formatter::VERSIONED_FORMATS;
+use crate::provenance;
+use crate::quantity::{format_bytes, format_count};
+use crate::reader::{self, ResultReaderRegistry};
+use crate::LineNumbers;
+
+/// Bytes read from the start of a file to tell its format.
+const SNIFF_SIZE: usize = 512;
+
+/// The format the start of a result file shows, if it shows one: the magic
+/// of a binary format, the bracket of JSON, a CSV header or the first line
+/// of an `auto` or text file.
+pub fn sniff(head: &[u8]) -> Option<&'static str> {
+    let magics: [(&[u8; 8], &str); 3] =
+        [(crate::store::MAGIC, "store"), (crate::delta::MAGIC, "delta"), (crate::roaring::MAGIC, "roaring")];
+    // The digit before the newline is the schema version.
+    if let Some((_, format)) = magics.iter().find(|(magic, _)| head.len() >= 8 && head[..6] == magic[..6]) {
+        return Some(format);
+    }
+    let text = String::from_utf8_lossy(head);
+    let text = text.trim_start();
+    let first_line = text.lines().next()?;
+    let number = |word: &str| !word.is_empty() && word.bytes().all(|byte| byte.is_ascii_digit());
+    let words: Vec<&str> = first_line.split(' ').collect();
+    match first_line.as_bytes()[0] {
+        b'[' => Some("json"),
+        b'{' => Some("ndjson"),
+        _ if first_line.starts_with("index,a,b,end") || first_line.starts_with("a,b,end") => Some("csv"),
+        _ if words.len() >= 5 && words[..4].iter().all(|word| number(word)) && words[4].contains(':') => Some("auto"),
+        _ if first_line.split_once(':').is_some_and(|(end, _)| number(end)) => Some("text"),
+        _ => None,
+    }
+}
+
+/// How the format of a file was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Detection {
+    Given,
+    Contents,
+    Extension,
+    /// Neither told, so the file is read as text.
+    Default,
+}
+
+/// The least, largest and total of a value over the records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub min: u64,
+    pub max: u64,
+    pub total: u64,
+}
+
+impl Stats {
+    fn add(&mut self, value: u64, first: bool) {
+        self.min = if first { value } else { self.min.min(value) };
+        self.max = self.max.max(value);
+        self.total += value;
+    }
+}
+
+/// What a result file holds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Description {
+    pub format: String,
+    pub detection: Detection,
+    /// For the [`VERSIONED_FORMATS`] only.
+    pub schema: Option<u32>,
+    pub size: u64,
+    pub records: u64,
+    /// Records holding only their number of multiples.
+    pub count_only: u64,
+    /// The multiples listed in the file.
+    pub listed: u64,
+    /// The multiples per record, listed or counted.
+    pub multiples: Stats,
+    pub ends: Stats,
+    /// The index, job and number of multiples of the first records.
+    pub first_records: Vec<(usize, LineNumbers, u64)>,
+}
+
+/// Reads `path` to describe it, as `format` if given, listing the first
+/// `max_records` records.
+pub fn describe(registry: &ResultReaderRegistry, path: &Path, format: Option<&str>, max_records: usize) -> Result<Description> {
+    let mut head = Vec::with_capacity(SNIFF_SIZE);
+    let file = File::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
+    // The records after a provenance header tell the format.
+    let (_, records) = provenance::strip(Box::new(BufReader::new(file))).with_context(|| format!("{:?}", path))?;
+    records
+        .take(SNIFF_SIZE as u64)
+        .read_to_end(&mut head)
+        .with_context(|| format!("Failed to read {:?}", path))?;
+    let (format, detection) = match (format, sniff(&head), registry.format_of(path)) {
+        (Some(format), _, _) => (format, Detection::Given),
+        (None, Some(format), _) => (format, Detection::Contents),
+        (None, None, Some(format)) => (format, Detection::Extension),
+        (None, None, None) => ("text", Detection::Default),
+    };
+    let schema = match VERSIONED_FORMATS.contains(&format) {
+        true => Some(reader::schema_version(path, format)?),
+        false => None,
+    };
+    let size = fs::metadata(path).with_context(|| format!("Failed to read {:?}", path))?.len();
+    let mut description = Description {
+        format: format.to_string(),
+        detection,
+        schema,
+        size,
+        records: 0,
+        count_only: 0,
+        listed: 0,
+        multiples: Stats::default(),
+        ends: Stats::default(),
+        first_records: Vec::new(),
+    };
+    for record in registry.open(path, Some(format))? {
+        let record = record.with_context(|| format!("Failed to read {:?} as {}", path, format))?;
+        let first = description.records == 0;
+        let count = record.result.count();
+        description.records += 1;
+        description.count_only += u64::from(record.result.count.is_some());
+        description.listed += record.result.numbers.len() as u64;
+        description.multiples.add(count, first);
+        description.ends.add(record.result.end as u64, first);
+        if description.first_records.len() < max_records {
+            description.first_records.push((record.index, record.job, count));
+        }
+    }
+    Ok(description)
+}
+
+impl fmt::Display for Description {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let detection = match self.detection {
+            Detection::Given => "as given",
+            Detection::Contents => "told by its contents",
+            Detection::Extension => "told by its extension",
+            Detection::Default => "neither contents nor extension told, read as text",
+        };
+        writeln!(f, "Format:          {} ({})", self.format, detection)?;
+        match self.schema {
+            Some(0) => writeln!(f, "Schema version:  0 (written before outputs were versioned; see migrate)")?,
+            Some(schema) => writeln!(f, "Schema version:  {}", schema)?,
+            None => writeln!(f, "Schema version:  none (the format isn't versioned)")?,
+        }
+        writeln!(f, "Size:            {}", format_bytes(self.size))?;
+        write!(f, "Records:         {}", format_count(self.records))?;
+        match self.count_only {
+            0 => writeln!(f)?,
+            count_only => writeln!(f, " ({} count-only)", format_count(count_only))?,
+        }
+        writeln!(
+            f,
+            "Multiples:       {} in total, {} listed",
+            format_count(self.multiples.total),
+            format_count(self.listed)
+        )?;
+        if self.records == 0 {
+            return Ok(());
+        }
+        let mean = |stats: &Stats| stats.total as f64 / self.records as f64;
+        writeln!(
+            f,
+            "Per record:      {} to {} multiples, {:.1} on average",
+            format_count(self.multiples.min),
+            format_count(self.multiples.max),
+            mean(&self.multiples)
+        )?;
+        writeln!(f, "Ends:            {} to {}", format_count(self.ends.min), format_count(self.ends.max))?;
+        writeln!(f, "Bytes:           {:.1} per record", self.size as f64 / self.records as f64)?;
+        if self.listed > 0 {
+            writeln!(f, "                 {:.2} per listed multiple", self.size as f64 / self.listed as f64)?;
+        }
+        writeln!(f, "\nRecords:")?;
+        for (index, job, count) in &self.first_records {
+            // Text files don't keep the divisors.
+            match (job.a, job.b) {
+                (0, 0) => writeln!(f, "  {:>6}  end {}: {} multiples", index, job.end, format_count(*count))?,
+                (a, b) => writeln!(f, "  {:>6}  {} {} {}: {} multiples", index, a, b, job.end, format_count(*count))?,
+            }
+        }
+        let more = self.records - self.first_records.len() as u64;
+        if more > 0 {
+            writeln!(f, "  … and {} more", format_count(more))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{describe, sniff, Detection};
+    use crate::formatter::FormatterRegistry;
+    use crate::pipeline::{jobs_source, FormatterSink, Pipeline};
+    use crate::reader::ResultReaderRegistry;
+    use crate::LineNumbers;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"MULTDL1\n\x00\x01"), Some("delta"));
+        assert_eq!(sniff(b"MULTKV2\n"), Some("store"));
+        assert_eq!(sniff(b"\n  [\n{\"schema\":1"), Some("json"));
+        assert_eq!(sniff(b"{\"schema\":1,\"index\":0}\n"), Some("ndjson"));
+        assert_eq!(sniff(b"index,a,b,end,count,numbers\n"), Some("csv"));
+        assert_eq!(sniff(b"0 2 4 100 ranges:2..100/2\n"), Some("auto"));
+        assert_eq!(sniff(b"10:2 3 4 6 8 9 10\n"), Some("text"));
+        assert_eq!(sniff(b"hello\n"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[test]
+    fn test_describe() {
+        let path = std::env::temp_dir().join(format!("multiples_describe_{}.bin", std::process::id()));
+        let jobs = vec![LineNumbers { a: 3, b: 5, end: 100 }, LineNumbers { a: 2, b: 3, end: 10 }, LineNumbers { a: 7, b: 9, end: 5 }];
+        let formatter = FormatterRegistry::with_builtins().create("ndjson").unwrap();
+        let mut sink = FormatterSink::new(Vec::new(), formatter);
+        Pipeline::builder(jobs_source(jobs)).build().run(&mut sink).unwrap();
+        fs::write(&path, sink.into_inner()).unwrap();
+        let description = describe(&ResultReaderRegistry::with_builtins(), &path, None, 2).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((description.format.as_str(), description.detection), ("ndjson", Detection::Contents));
+        assert_eq!((description.schema, description.records, description.listed), (Some(1), 3, 54));
+        assert_eq!((description.multiples.min, description.multiples.max), (0, 47));
+        assert_eq!((description.ends.min, description.ends.max), (5, 100));
+        assert_eq!(description.first_records[1], (1, LineNumbers { a: 2, b: 3, end: 10 }, 7));
+        let text = description.to_string();
+        assert!(text.starts_with("Format:          ndjson (told by its contents)\nSchema version:  1\n"), "{}", text);
+        assert!(text.contains("Per record:      0 to 47 multiples, 18.0 on average\n"), "{}", text);
+        assert!(text.ends_with("       1  2 3 10: 7 multiples\n  … and 1 more\n"), "{}", text);
+    }
+}
@@ -0,0 +1,357 @@
+//! Stable codes for the errors users run into, such as `MP0001` for an input
+//! line without three numbers, so that scripts and support documents can
+//! key off them rather than off the wording of the messages.
+//!
+//! Errors get a code by being created with [`ErrorCode::error`]; I/O errors
+//! anywhere in the chain of causes get one of their own. `explain-error`
+//! prints the explanation of a code.
+
+use std::backtrace::BacktraceStatus;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::locale::Lang;
+
+/// A stable error code with a description of the error and how to fix it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ErrorCode {
+    pub number: u16,
+    pub title: &'static str,
+    /// What causes the error and what to do about it, in paragraphs.
+    pub explanation: &'static str,
+}
+
+impl ErrorCode {
+    /// An error with this code and `message`.
+    pub fn error(&'static self, message: impl Into<String>) -> anyhow::Error {
+        anyhow::Error::new(CodedError {
+            code: self,
+            message: message.into(),
+        })
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MP{:04}", self.number)
+    }
+}
+
+/// The error [`ErrorCode::error`] creates, which displays as its message.
+#[derive(Debug)]
+struct CodedError {
+    code: &'static ErrorCode,
+    message: String,
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for CodedError {}
+
+pub const MALFORMED_LINE: ErrorCode = ErrorCode {
+    number: 1,
+    title: "Malformed input line",
+    explanation: "\
+An input line doesn't describe a job: text and CSV lines need exactly three numbers, a, b and end, \
+JSON input must be an array of objects or arrays, and NDJSON lines must each be one of those.
+
+Tokens that aren't numbers are not counted, so a typo such as `3 5 l00` leaves a line with two numbers, \
+while a number too large for 32 bits, such as `2^40`, is an error of its own in any format. Check the \
+line named in the message, or run with --fix to write a cleaned copy of the input and a report of the \
+lines it dropped.",
+};
+
+pub const INVALID_LINE_OPTION: ErrorCode = ErrorCode {
+    number: 2,
+    title: "Invalid line option",
+    explanation: "\
+An input line carries a priority or an output that can't be used: priorities are prio=low, prio=normal \
+or prio=high (a \"priority\" string in JSON), and the path after `>` (an \"output\" string in JSON) must \
+not be empty.
+
+Fix or remove the option on the line named in the message.",
+};
+
+pub const UNKNOWN_OPTION: ErrorCode = ErrorCode {
+    number: 3,
+    title: "Unknown option",
+    explanation: "\
+The command line, or a --config file, holds an option the command doesn't know. Options differ between \
+commands: run the command without arguments to print its usage and options.
+
+Options of a newer release, or options spelled differently, are a common cause. Configuration file keys \
+are the option names without the leading dashes.",
+};
+
+pub const INVALID_OPTION_VALUE: ErrorCode = ErrorCode {
+    number: 4,
+    title: "Missing or invalid option value",
+    explanation: "\
+An option that takes a value was given none, or one it can't use, such as --threads 0 or --max-end abc.
+
+Numbers may be written as literals such as 1000, 1_000, 1e3, 1k or 0x3e8; counts must be positive. The \
+usage of the command lists what each option accepts.",
+};
+
+pub const UNKNOWN_NAME: ErrorCode = ErrorCode {
+    number: 5,
+    title: "Unknown format, mode or strategy",
+    explanation: "\
+A name given to an option such as --input-format, --output-format, --mode or --strategy isn't one this \
+build knows; the message lists the available ones.
+
+Formats loaded with --plugin are only known once the plugin is, and builds without the `plugins` \
+feature don't load any.",
+};
+
+pub const UNSUPPORTED_BY_FORMAT: ErrorCode = ErrorCode {
+    number: 6,
+    title: "Not supported by the output format",
+    explanation: "\
+The output format can't do what the run asks of it: only some formats support --annotate, --group-by, \
+--append, count-only records or lines too large for memory.
+
+Pick another --output-format, such as text or ndjson, or leave out the option the message names.",
+};
+
+pub const FILE_NOT_FOUND: ErrorCode = ErrorCode {
+    number: 7,
+    title: "File not found",
+    explanation: "\
+A file the run needs, usually the input, a --config file or a plugin, doesn't exist.
+
+Relative paths are taken from the current directory, not from the location of the program or of the \
+configuration file. Check the path in the message.",
+};
+
+pub const IO_ERROR: ErrorCode = ErrorCode {
+    number: 8,
+    title: "Input or output failed",
+    explanation: "\
+Reading or writing a file, a socket or the terminal failed, e.g. because of missing permissions, a full \
+disk, an input that isn't UTF-8 text or a closed connection.
+
+The message ends with the error the operating system reported. Output written before the error may be \
+incomplete: rerun once the cause is fixed, or continue the output with --append.",
+};
+
+pub const MEMORY_LIMIT: ErrorCode = ErrorCode {
+    number: 9,
+    title: "Line exceeds the memory limit",
+    explanation: "\
+With --strict-memory, a line whose multiples would take more memory than --max-memory, or than the \
+available memory, fails the run instead of being written as it is generated.
+
+Leave out --strict-memory to stream such lines, raise --max-memory, split the line's range with --shard, \
+or write only the count with --count-only.",
+};
+
+pub const CROSS_CHECK_FAILED: ErrorCode = ErrorCode {
+    number: 10,
+    title: "Cross-check failed",
+    explanation: "\
+With --cross-check, a record differed from plain trial division on one of the windows it was checked \
+on. This is a bug in the strategy or kernel named in the message, not in the input.
+
+Rerun with --strategy trial and --force-scalar to get correct output meanwhile, and report the message, \
+which names the job and the first value that differs.",
+};
+
+pub const POWER_TOO_LARGE: ErrorCode = ErrorCode {
+    number: 11,
+    title: "Power of a divisor too large",
+    explanation: "\
+With --power k, a divisor raised to the k-th power doesn't fit into 32 bits, the largest divisor there \
+is.
+
+A divisor that large has no multiples below any end that fits into 32 bits either, so lower --power or \
+remove the line.",
+};
+
+pub const DEGENERATE_LINE: ErrorCode = ErrorCode {
+    number: 12,
+    title: "Degenerate input line",
+    explanation: "\
+With --degenerate error, an input line has nothing to compute: its divisors are equal, one of them is 1, \
+which divides every number, or 0, one divisor adds no multiples to the other, or end is below both \
+divisors.
+
+Fix or remove the line named in the message, or pass --degenerate warn or --degenerate simplify to \
+generate such lines anyway.",
+};
+
+pub const INPUT_LIMIT: ErrorCode = ErrorCode {
+    number: 13,
+    title: "Input exceeds a size limit",
+    explanation: "\
+The input has a line longer than --max-line-length bytes, a line of more than --max-tokens tokens, more \
+than --max-lines lines, or jobs selecting more than --max-multiples multiples together. serve applies \
+the same limits to every upload, with defaults for inputs from untrusted clients.
+
+Split the input, fix the line named in the message, or raise the limit if the input is meant to be that \
+large.",
+};
+
+pub const CORRUPTED_RECORD: ErrorCode = ErrorCode {
+    number: 14,
+    title: "Corrupted result record",
+    explanation: "\
+A record of a result file written with --checksums doesn't match the crc32 stored with it: the record, \
+or its checksum, changed after it was written, such as by a failing disk or network storage, or by an \
+edit. Every command reading result files checks the checksums of the records that have one.
+
+Regenerate the file, or at least the record named in the message, from its input; the other records \
+can still be read by removing that one.",
+};
+
+pub const INVALID_USAGE: ErrorCode = ErrorCode {
+    number: 15,
+    title: "Invalid command line",
+    explanation: "\
+The command line, or a --config file, can't be run as given: options that exclude each other, such as \
+--annotate and --group-by, an option that needs another one, such as --scale without --decimal, or a \
+value that doesn't fit the others, such as --shard 5/3. The message names the options.
+
+Run the command without arguments to print its usage and options, and leave out or add the options \
+the message names.",
+};
+
+/// Every error code, in order.
+pub const CODES: &[&ErrorCode] = &[
+    &MALFORMED_LINE,
+    &INVALID_LINE_OPTION,
+    &UNKNOWN_OPTION,
+    &INVALID_OPTION_VALUE,
+    &UNKNOWN_NAME,
+    &UNSUPPORTED_BY_FORMAT,
+    &FILE_NOT_FOUND,
+    &IO_ERROR,
+    &MEMORY_LIMIT,
+    &CROSS_CHECK_FAILED,
+    &POWER_TOO_LARGE,
+    &DEGENERATE_LINE,
+    &INPUT_LIMIT,
+    &CORRUPTED_RECORD,
+    &INVALID_USAGE,
+];
+
+/// The code written as `name`, such as `MP0003`, `mp3` or `3`.
+pub fn lookup(name: &str) -> Option<&'static ErrorCode> {
+    let digits = match name.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("mp") => &name[2..],
+        _ => name,
+    };
+    let number: u16 = digits.parse().ok()?;
+    CODES.iter().copied().find(|code| code.number == number)
+}
+
+/// `err`, an error reading the command line, with the [`INVALID_USAGE`]
+/// code unless it has a more specific one.
+pub fn usage(err: anyhow::Error) -> anyhow::Error {
+    match code_of(&err) {
+        Some(_) => err,
+        None => INVALID_USAGE.error(format!("{:#}", err)),
+    }
+}
+
+/// The code of `err`: that of the error it was created with, if any, or of
+/// an I/O error among its causes.
+pub fn code_of(err: &anyhow::Error) -> Option<&'static ErrorCode> {
+    if let Some(coded) = err.chain().find_map(|cause| cause.downcast_ref::<CodedError>()) {
+        return Some(coded.code);
+    }
+    let io = err.chain().find_map(|cause| cause.downcast_ref::<io::Error>())?;
+    Some(match io.kind() {
+        io::ErrorKind::NotFound => &FILE_NOT_FOUND,
+        _ => &IO_ERROR,
+    })
+}
+
+/// The report of `err` when it ends the run of `program`: its message
+/// headed by its code, its causes, the backtrace if one was captured and
+/// where to find more about the code, in the language `lang`.
+pub fn report(err: &anyhow::Error, program: &str, lang: Lang) -> String {
+    let code = code_of(err);
+    let mut text = match code {
+        Some(code) => format!("{} {}: {}", lang.tr("Error"), code, err),
+        None => format!("{}: {}", lang.tr("Error"), err),
+    };
+    let causes: Vec<_> = err.chain().skip(1).collect();
+    if !causes.is_empty() {
+        text.push_str("\n\n");
+        text.push_str(lang.tr("Caused by:"));
+        for (i, cause) in causes.iter().enumerate() {
+            match causes.len() {
+                1 => text.push_str(&format!("\n    {}", cause)),
+                _ => text.push_str(&format!("\n    {}: {}", i, cause)),
+            }
+        }
+    }
+    if err.backtrace().status() == BacktraceStatus::Captured {
+        text.push_str(&format!("\n\nStack backtrace:\n{}", err.backtrace().to_string().trim_end()));
+    }
+    if let Some(code) = code {
+        text.push_str("\n\n");
+        text.push_str(&lang.tr_args("For more information about this error, try `{} explain-error {}`.", &[&program, code]));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use anyhow::Context;
+
+    use super::{code_of, lookup, report, usage, CODES, FILE_NOT_FOUND, INVALID_USAGE, MALFORMED_LINE, UNKNOWN_OPTION};
+    use crate::locale::Lang;
+
+    #[test]
+    fn test_codes() {
+        for (i, code) in CODES.iter().enumerate() {
+            assert_eq!(code.number as usize, i + 1);
+        }
+        assert_eq!(MALFORMED_LINE.to_string(), "MP0001");
+        assert_eq!(lookup("MP0001"), Some(&MALFORMED_LINE));
+        assert_eq!(lookup("mp1"), Some(&MALFORMED_LINE));
+        assert_eq!(lookup("7"), Some(&FILE_NOT_FOUND));
+        assert_eq!(lookup("MP9999"), None);
+        assert_eq!(lookup("E0001"), None);
+    }
+
+    #[test]
+    fn test_code_of() {
+        let err = MALFORMED_LINE.error("Line 3 does not contain exactly 3 numbers");
+        let err = Err::<(), _>(err).context("Failed to read the input").unwrap_err();
+        assert_eq!(code_of(&err), Some(&MALFORMED_LINE));
+        assert_eq!(format!("{:#}", err), "Failed to read the input: Line 3 does not contain exactly 3 numbers");
+
+        let err = File::open("/nonexistent/input.txt").context("Failed to open the input").unwrap_err();
+        assert_eq!(code_of(&err), Some(&FILE_NOT_FOUND));
+        assert_eq!(code_of(&anyhow::anyhow!("Something else")), None);
+
+        let err = usage(anyhow::anyhow!("--decimal can't be combined with --time"));
+        assert_eq!((code_of(&err), err.to_string().as_str()), (Some(&INVALID_USAGE), "--decimal can't be combined with --time"));
+        assert_eq!(code_of(&usage(UNKNOWN_OPTION.error("Unknown option: --x"))), Some(&UNKNOWN_OPTION));
+    }
+
+    #[test]
+    fn test_report() {
+        let err = MALFORMED_LINE.error("Line 3 does not contain exactly 3 numbers");
+        let err = Err::<(), _>(err).context("Failed to read the input").unwrap_err();
+        // With RUST_BACKTRACE set, a backtrace comes between the two.
+        let text = report(&err, "multiples", Lang::En);
+        assert!(text.starts_with("Error MP0001: Failed to read the input\n\nCaused by:\n    Line 3 does not contain exactly 3 numbers\n\n"));
+        assert!(text.ends_with("\n\nFor more information about this error, try `multiples explain-error MP0001`."));
+        let err = err.context("Failed to generate divisible numbers");
+        assert!(report(&err, "multiples", Lang::De).starts_with(
+            "Fehler MP0001: Failed to generate divisible numbers\n\nVerursacht durch:\n    0: Failed to read the input\n    1: Line 3"
+        ));
+        assert!(report(&anyhow::anyhow!("Something else"), "multiples", Lang::En).starts_with("Error: Something else"));
+    }
+}
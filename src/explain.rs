@@ -0,0 +1,274 @@
+//! Predictions of what generating a job will produce and cost, for the
+//! `explain` command.
+//!
+//! Counts come from [`formula::count`]. Output sizes are worked out from
+//! the number of selected values of each digit length together with the
+//! size of a few small records in the chosen format, so they are exact for
+//! formats that grow linearly with their numbers. Durations are estimates
+//! based on a short calibration run of the same generator and formatter.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::formatter::OutputFormatter;
+use crate::formula;
+use crate::strategy::RecordGenerator;
+use crate::wheel::Wheel;
+use crate::{LineNumbers, ResultNumbers, ResultRecord};
+
+pub type FormatterFactory<'a> = &'a dyn Fn() -> Result<Box<dyn OutputFormatter>>;
+
+/// What generating one line is expected to do.
+#[derive(Clone, Debug)]
+pub struct LinePlan {
+    pub job: LineNumbers,
+    pub strategy: String,
+    /// Number of selected values.
+    pub count: u64,
+    /// Bytes the line adds to the output.
+    pub bytes: u64,
+    /// Bytes the generated record holds while in memory.
+    pub memory: u64,
+    pub duration: Duration,
+}
+
+/// Time per number of a range and per selected number, measured on the
+/// machine running the plan.
+#[derive(Clone, Copy, Debug)]
+pub struct Calibration {
+    pub per_number: f64,
+    pub per_selected: f64,
+}
+
+impl Calibration {
+    /// Time spent on generating and formatting a job selecting every number
+    /// and one testing every number without selecting any, doubling their
+    /// size until they take long enough to measure.
+    pub fn measure(generator: &RecordGenerator, make_formatter: FormatterFactory) -> Result<Calibration> {
+        let run = |job: LineNumbers| -> Result<f64> {
+            let mut formatter = make_formatter()?;
+            let start = Instant::now();
+            let record = generator.generate(job);
+            formatter.write_record(&mut io::sink(), &record)?;
+            Ok(start.elapsed().as_secs_f64())
+        };
+        let mut end = 1u32 << 14;
+        loop {
+            let every = LineNumbers { a: 1, b: 1, end };
+            let time = run(every)?;
+            if time >= 0.02 || end >= 1 << 24 {
+                // A divisor beyond the range and too large for a wheel.
+                let divisor = end.max(Wheel::MAX_PERIOD) + 1;
+                let per_number = run(LineNumbers { a: divisor, b: divisor, end })? / end as f64;
+                let tested = if generator.tests_every_number(&every) { end as f64 } else { 0.0 };
+                return Ok(Calibration {
+                    per_number,
+                    per_selected: (time - tested * per_number).max(0.0) / end as f64,
+                });
+            }
+            end *= 2;
+        }
+    }
+}
+
+/// Number of decimal digits of `n`.
+fn digits(n: u64) -> u64 {
+    n.checked_ilog10().map_or(1, |log| log as u64 + 1)
+}
+
+/// Total number of digits of the values `mode` selects for `job`, counted
+/// per digit length in closed form.
+fn selected_digits(generator: &RecordGenerator, job: &LineNumbers) -> u64 {
+    let mode = generator.options().mode;
+    let upto = |end: u64| formula::count(&LineNumbers { end: end as u32, ..*job }, mode);
+    let mut total = 0;
+    let mut low = 1u64;
+    for length in 1..=10 {
+        if low > job.end as u64 {
+            break;
+        }
+        let high = (low * 10 - 1).min(job.end as u64);
+        total += (upto(high) - upto(low - 1)) * length;
+        low *= 10;
+    }
+    total
+}
+
+/// Size of `record` in the format, written after `previous` records.
+fn record_size(make_formatter: FormatterFactory, record: &ResultRecord, previous: bool) -> Result<u64> {
+    let mut formatter = make_formatter()?;
+    formatter.begin(&mut io::sink())?;
+    if previous {
+        formatter.write_record(&mut io::sink(), record)?;
+    }
+    let mut out = Vec::new();
+    formatter.write_record(&mut out, record)?;
+    Ok(out.len() as u64)
+}
+
+/// Predicts the lines of a run, see the module documentation. The
+/// generator's filter, if any, isn't taken into account.
+pub struct Planner<'a> {
+    generator: &'a RecordGenerator,
+    make_formatter: FormatterFactory<'a>,
+    calibration: Calibration,
+}
+
+impl<'a> Planner<'a> {
+    pub fn new(generator: &'a RecordGenerator, make_formatter: FormatterFactory<'a>, calibration: Calibration) -> Self {
+        Planner {
+            generator,
+            make_formatter,
+            calibration,
+        }
+    }
+
+    /// Bytes of the output's header and footer.
+    pub fn framing_bytes(&self) -> Result<u64> {
+        let mut formatter = (self.make_formatter)()?;
+        let mut out = Vec::new();
+        formatter.begin(&mut out)?;
+        formatter.finish(&mut out)?;
+        Ok(out.len() as u64)
+    }
+
+    /// Plans the line at `index` of the input.
+    pub fn plan(&self, index: usize, job: LineNumbers) -> Result<LinePlan> {
+        let options = self.generator.options();
+        let count = formula::count(&job, options.mode);
+        let shape = |numbers: Vec<u32>, count: Option<u64>| ResultRecord {
+            index,
+            job,
+            result: ResultNumbers {
+                end: job.end,
+                numbers,
+                count,
+            },
+        };
+        let size = |record: ResultRecord| record_size(self.make_formatter, &record, index > 0);
+        let bytes = if options.count_only {
+            size(shape(Vec::new(), Some(count)))?
+        } else if count == 0 {
+            size(shape(Vec::new(), None))?
+        } else {
+            // Records of ones tell the fixed size, the separator between
+            // numbers and how often the count itself is written.
+            let [one, two, nine, ten] = [1, 2, 9, 10].map(|len| size(shape(vec![1; len], None)));
+            let (one, two, nine, ten) = (one?, two?, nine?, ten?);
+            let separator = two - one - 1;
+            let count_fields = ten - nine - 1 - separator;
+            let fixed = one - 1 - count_fields;
+            fixed + selected_digits(self.generator, &job) + (count - 1) * separator + count_fields * digits(count)
+        };
+        let listed = if options.count_only { 0 } else { count };
+        let tested = if self.generator.tests_every_number(&job) { job.end } else { 0 };
+        let seconds = tested as f64 * self.calibration.per_number + listed as f64 * self.calibration.per_selected;
+        Ok(LinePlan {
+            job,
+            strategy: self.generator.describe(&job),
+            count,
+            bytes,
+            memory: std::mem::size_of::<ResultRecord>() as u64 + listed * 4,
+            duration: Duration::from_secs_f64(seconds),
+        })
+    }
+}
+
+/// How long `threads` workers take for jobs of `durations`, each job going
+/// to the worker that is done first, in the order given.
+pub fn makespan(durations: &[Duration], threads: usize) -> Duration {
+    let mut workers = BinaryHeap::from(vec![Reverse(Duration::ZERO); threads.max(1)]);
+    for &duration in durations {
+        let Reverse(busy) = workers.pop().unwrap_or_default();
+        workers.push(Reverse(busy + duration));
+    }
+    workers.into_iter().map(|Reverse(busy)| busy).max().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use std::time::Duration;
+
+    use super::{makespan, Calibration, Planner};
+    use crate::formatter::{FormatterRegistry, OutputFormatter, ARRAY_FORMATS};
+    use crate::formula::Mode;
+    use crate::strategy::{GenerateOptions, RecordGenerator};
+    use crate::LineNumbers;
+
+    fn jobs() -> Vec<LineNumbers> {
+        vec![
+            LineNumbers { a: 3, b: 5, end: 1000 },
+            LineNumbers { a: 7, b: 8, end: 7 },
+            LineNumbers { a: 1, b: 2, end: 123_456 },
+        ]
+    }
+
+    #[test]
+    fn test_predicted_bytes_match_output() {
+        let registry = FormatterRegistry::with_builtins();
+        let calibration = Calibration {
+            per_number: 1e-9,
+            per_selected: 1e-8,
+        };
+        let arrays = ARRAY_FORMATS.map(|(name, _)| name);
+        for format in ["text", "json", "ndjson", "csv"].into_iter().chain(arrays) {
+            for (mode, count_only) in [(Mode::Or, false), (Mode::Xor, false), (Mode::Multiset, false), (Mode::Or, true)] {
+                if count_only && arrays.contains(&format) {
+                    continue;
+                }
+                let generator = RecordGenerator::new(GenerateOptions {
+                    mode,
+                    count_only,
+                    ..Default::default()
+                });
+                let make_formatter = || registry.create(format);
+                let planner = Planner::new(&generator, &make_formatter, calibration);
+                let mut predicted = planner.framing_bytes().unwrap();
+                let mut formatter: Box<dyn OutputFormatter> = make_formatter().unwrap();
+                let mut out = Vec::new();
+                formatter.begin(&mut out).unwrap();
+                for (index, job) in jobs().into_iter().enumerate() {
+                    let plan = planner.plan(index, job).unwrap();
+                    predicted += plan.bytes;
+                    let mut record = generator.generate(job);
+                    record.index = index;
+                    assert_eq!(plan.count, record.result.count());
+                    formatter.write_record(&mut out, &record).unwrap();
+                }
+                formatter.finish(&mut out).unwrap();
+                out.flush().unwrap();
+                assert_eq!(predicted, out.len() as u64, "{} {:?} {}", format, mode, count_only);
+            }
+        }
+    }
+
+    #[test]
+    fn test_plan_estimates() {
+        let registry = FormatterRegistry::with_builtins();
+        let generator = RecordGenerator::new(GenerateOptions::default());
+        let make_formatter = || registry.create("text");
+        let calibration = Calibration::measure(&generator, &make_formatter).unwrap();
+        assert!(calibration.per_number > 0.0);
+        let planner = Planner::new(&generator, &make_formatter, calibration);
+        let small = planner.plan(0, LineNumbers { a: 3, b: 5, end: 1000 }).unwrap();
+        let large = planner.plan(1, LineNumbers { a: 3, b: 5, end: 1_000_000 }).unwrap();
+        assert!(large.duration > small.duration);
+        assert_eq!(small.memory + (large.count - small.count) * 4, large.memory);
+    }
+
+    #[test]
+    fn test_makespan() {
+        let seconds = |list: &[u64]| list.iter().map(|&s| Duration::from_secs(s)).collect::<Vec<_>>();
+        // The long job started last keeps one worker busy after the others.
+        assert_eq!(makespan(&seconds(&[1, 1, 1, 1, 4]), 2), Duration::from_secs(6));
+        assert_eq!(makespan(&seconds(&[4, 1, 1, 1, 1]), 2), Duration::from_secs(4));
+        assert_eq!(makespan(&seconds(&[3, 2]), 1), Duration::from_secs(5));
+        assert_eq!(makespan(&[], 4), Duration::ZERO);
+    }
+}
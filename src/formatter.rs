@@ -0,0 +1,1092 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::crc32::{record_checksum, RecordChecksum};
+use crate::errors::{UNKNOWN_NAME, UNSUPPORTED_BY_FORMAT};
+use crate::number::{format_u32, MAX_DIGITS};
+use crate::{LineNumbers, ResultNumbers, ResultRecord};
+
+/// The schema version of the structured outputs: the `schema` field of
+/// every record of the JSON formats, and the digit ending the magic of the
+/// binary ones, see [`VERSIONED_FORMATS`]. JSON records without the field
+/// were written before outputs were versioned, as version 0.
+pub const OUTPUT_SCHEMA: u32 = 1;
+
+/// The formats stamped with [`OUTPUT_SCHEMA`], which `migrate` upgrades.
+pub const VERSIONED_FORMATS: [&str; 5] = ["json", "ndjson", "store", "delta", "roaring"];
+
+/// The formats whose records can carry the [checksum](crate::crc32) of
+/// `--checksums`.
+pub const CHECKSUMMED_FORMATS: [&str; 3] = ["json", "ndjson", "csv"];
+
+/// Serializes result records into an output stream.
+///
+/// `begin` and `finish` are called once around all records, which lets
+/// document-style formats such as JSON emit their surrounding brackets.
+pub trait OutputFormatter {
+    fn begin(&mut self, _w: &mut dyn Write) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called instead of `begin` when records are appended to output that
+    /// already contains records. Formats that can't be continued fail here.
+    fn resume(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Switches to writing each multiple together with the divisors it is a
+    /// multiple of. Formats without a way to show them fail here.
+    fn annotate(&mut self) -> Result<()> {
+        Err(UNSUPPORTED_BY_FORMAT.error("This output format doesn't support annotations"))
+    }
+
+    /// Switches to writing the multiples of each record in three groups: of
+    /// `a` only, of `b` only and of both. Formats without sections fail here.
+    fn group_by_divisor(&mut self) -> Result<()> {
+        Err(UNSUPPORTED_BY_FORMAT.error("This output format doesn't support grouping"))
+    }
+
+    /// Formats the multiples of large records on `threads` threads, see
+    /// [`write_joined_on`]. Formats that format on one thread ignore it.
+    fn format_threads(&mut self, _threads: usize) {}
+
+    /// Switches to writing the [checksum](crate::crc32) of each record with
+    /// it. Formats without a place for it fail here.
+    fn checksum(&mut self) -> Result<()> {
+        Err(UNSUPPORTED_BY_FORMAT.error("This output format doesn't support checksums"))
+    }
+
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()>;
+
+    /// Writes a record whose multiples come in pieces from `chunks` rather
+    /// than in `r.result.numbers`, for lines too large to hold in memory.
+    /// `count` is the number of multiples, when known in advance. Formats
+    /// that can't write a record in pieces, or need the count first and
+    /// don't have it, fail here.
+    fn write_streamed(
+        &mut self,
+        _w: &mut dyn Write,
+        _r: &ResultRecord,
+        _count: Option<u64>,
+        _chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    ) -> Result<()> {
+        Err(UNSUPPORTED_BY_FORMAT.error("This output format can't write a record in pieces"))
+    }
+
+    fn finish(&mut self, _w: &mut dyn Write) -> Result<()> {
+        Ok(())
+    }
+}
+
+type FormatterFactory = Box<dyn Fn() -> Box<dyn OutputFormatter>>;
+
+/// Output formatters keyed by the name used with `--output-format`.
+pub struct FormatterRegistry {
+    factories: BTreeMap<String, FormatterFactory>,
+}
+
+impl FormatterRegistry {
+    pub fn new() -> Self {
+        FormatterRegistry {
+            factories: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a registry containing all built-in formatters.
+    pub fn with_builtins() -> Self {
+        let mut registry = FormatterRegistry::new();
+        registry.register("text", || Box::new(TextFormatter::default()));
+        registry.register("json", || Box::new(JsonFormatter::default()));
+        registry.register("ndjson", || Box::new(NdjsonFormatter::default()));
+        registry.register("csv", || Box::new(CsvFormatter::default()));
+        registry.register("store", || Box::new(crate::store::StoreFormatter));
+        registry.register("auto", || Box::new(crate::ranges::AutoFormatter::default()));
+        registry.register("delta", || Box::new(crate::delta::DeltaFormatter));
+        registry.register("roaring", || Box::new(crate::roaring::RoaringFormatter));
+        for (name, language) in ARRAY_FORMATS {
+            registry.register(name, move || Box::new(ArrayFormatter::new(language, DEFAULT_ARRAY_NAME)));
+        }
+        registry
+    }
+
+    /// Registers a formatter, replacing any existing one with the same name.
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn() -> Box<dyn OutputFormatter> + 'static,
+    {
+        self.factories.insert(name.to_string(), Box::new(factory));
+    }
+
+    pub fn create(&self, name: &str) -> Result<Box<dyn OutputFormatter>> {
+        let factory = self.factories.get(name).ok_or_else(|| {
+            UNKNOWN_NAME.error(format!(
+                "Unknown output format: {} (available: {})",
+                name,
+                self.names().collect::<Vec<_>>().join(", ")
+            ))
+        })?;
+        Ok(factory())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(String::as_str)
+    }
+}
+
+impl Default for FormatterRegistry {
+    fn default() -> Self {
+        FormatterRegistry::with_builtins()
+    }
+}
+
+/// The file extension of outputs written in `format`, which result readers
+/// detect the format by.
+pub fn extension(format: &str) -> &str {
+    match format {
+        "text" | "auto" => "txt",
+        "c-array" => "c",
+        "python-list" => "py",
+        "rust-array" => "rs",
+        "store" => "db",
+        format => format,
+    }
+}
+
+/// Bytes of formatted multiples gathered on the stack before each write.
+const WRITE_CHUNK: usize = 8192;
+
+/// Writes `numbers` separated by `separator`. The digits are formatted into
+/// a buffer on the stack and written a few kilobytes at a time, since going
+/// through `write!` for each of billions of multiples dominates the time of
+/// writing them.
+fn write_joined(w: &mut dyn Write, numbers: &[u32], separator: &str) -> Result<()> {
+    let separator = separator.as_bytes();
+    let mut buf = [0; WRITE_CHUNK];
+    let mut len = 0;
+    let mut digits = [0; MAX_DIGITS];
+    for (i, &num) in numbers.iter().enumerate() {
+        if len + separator.len() + MAX_DIGITS > WRITE_CHUNK {
+            w.write_all(&buf[..len])?;
+            len = 0;
+        }
+        if i > 0 {
+            buf[len..len + separator.len()].copy_from_slice(separator);
+            len += separator.len();
+        }
+        let digits = format_u32(num, &mut digits);
+        buf[len..len + digits.len()].copy_from_slice(digits);
+        len += digits.len();
+    }
+    w.write_all(&buf[..len])?;
+    Ok(())
+}
+
+/// Records with fewer multiples than this are formatted on one thread by
+/// [`write_joined_on`], since starting threads would take longer than
+/// formatting them.
+pub const PARALLEL_FORMAT_MIN: usize = 1 << 16;
+
+/// Most multiples formatted by a thread at once, so that the pieces waiting
+/// to be written take some megabytes however large the record.
+const PARALLEL_FORMAT_PIECE: usize = 1 << 20;
+
+/// [`write_joined`] on `threads` threads: the multiples are split into
+/// pieces, which are formatted into buffers side by side and written in
+/// order, so that the output is the same as on one thread.
+pub fn write_joined_on(w: &mut dyn Write, numbers: &[u32], separator: &str, threads: usize) -> Result<()> {
+    if threads <= 1 || numbers.len() < PARALLEL_FORMAT_MIN {
+        return write_joined(w, numbers, separator);
+    }
+    let piece = numbers.len().div_ceil(threads).min(PARALLEL_FORMAT_PIECE);
+    for (round, pieces) in numbers.chunks(piece * threads).enumerate() {
+        let buffers = std::thread::scope(|scope| {
+            let handles: Vec<_> = pieces
+                .chunks(piece)
+                .map(|piece| {
+                    scope.spawn(move || {
+                        let mut buffer = Vec::with_capacity(piece.len() * (separator.len() + MAX_DIGITS / 2));
+                        write_joined(&mut buffer, piece, separator).map(|()| buffer)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|handle| handle.join().expect("a formatting thread panicked")).collect::<Vec<_>>()
+        });
+        for (i, buffer) in buffers.into_iter().enumerate() {
+            if round > 0 || i > 0 {
+                w.write_all(separator.as_bytes())?;
+            }
+            w.write_all(&buffer?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the numbers of all `chunks` separated by `separator`, checking
+/// that there are `count` of them if that is known.
+fn write_joined_chunks(
+    w: &mut dyn Write,
+    chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    separator: &str,
+    count: Option<u64>,
+) -> Result<()> {
+    let mut written = 0u64;
+    for chunk in chunks {
+        if written > 0 && !chunk.is_empty() {
+            w.write_all(separator.as_bytes())?;
+        }
+        write_joined(w, &chunk, separator)?;
+        written += chunk.len() as u64;
+    }
+    match count {
+        Some(count) if count != written => bail!("Wrote {} multiples instead of the {} announced", written, count),
+        _ => Ok(()),
+    }
+}
+
+/// The count a streamed record must announce before its multiples.
+fn announced(count: Option<u64>, format: &str) -> Result<u64> {
+    count.ok_or_else(|| {
+        anyhow!(
+            "The {} format needs the count of a record before its multiples, which isn't known with filters; use text output",
+            format
+        )
+    })
+}
+
+/// Names of the divisors of `job` that `n` is a multiple of.
+pub fn matched_divisors(job: &LineNumbers, n: u32) -> &'static [&'static str] {
+    match (n.is_multiple_of(job.a), n.is_multiple_of(job.b)) {
+        (true, true) => &["a", "b"],
+        (true, false) => &["a"],
+        (false, true) => &["b"],
+        (false, false) => &[],
+    }
+}
+
+/// The divisors of `job` that `n` is a multiple of, joined by commas and
+/// quoted for JSON if `quoted`.
+fn matched_list(job: &LineNumbers, n: u32, quoted: bool) -> &'static str {
+    match (matched_divisors(job, n), quoted) {
+        (["a", "b"], false) => "a,b",
+        (["a", "b"], true) => "\"a\",\"b\"",
+        (["a"], false) => "a",
+        (["a"], true) => "\"a\"",
+        (["b"], false) => "b",
+        (["b"], true) => "\"b\"",
+        _ => "",
+    }
+}
+
+/// Splits `numbers` into the multiples of `job.a` only, of `job.b` only and
+/// of both, keeping their order.
+pub fn group_by_divisor(job: &LineNumbers, numbers: &[u32]) -> [Vec<u32>; 3] {
+    let mut groups = [Vec::new(), Vec::new(), Vec::new()];
+    for &n in numbers {
+        match matched_divisors(job, n) {
+            ["a"] => groups[0].push(n),
+            ["b"] => groups[1].push(n),
+            _ => groups[2].push(n),
+        }
+    }
+    groups
+}
+
+/// How the JSON formats list the multiples of a record.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum JsonNumbers {
+    #[default]
+    Plain,
+    Annotated,
+    Grouped,
+}
+
+/// Ends a record of the JSON formats with its checksum, if there is one.
+fn end_json_record(w: &mut dyn Write, checksum: Option<u32>) -> Result<()> {
+    match checksum {
+        Some(crc) => write!(w, ",\"crc32\":\"{:08x}\"}}", crc)?,
+        None => w.write_all(b"}")?,
+    }
+    Ok(())
+}
+
+fn write_json_record(
+    w: &mut dyn Write,
+    r: &ResultRecord,
+    numbers: JsonNumbers,
+    threads: usize,
+    checksum: bool,
+) -> Result<()> {
+    let checksum = checksum.then(|| record_checksum(r));
+    write!(
+        w,
+        "{{\"schema\":{},\"index\":{},\"a\":{},\"b\":{},\"end\":{},\"count\":{}",
+        OUTPUT_SCHEMA,
+        r.index,
+        r.job.a,
+        r.job.b,
+        r.result.end,
+        r.result.count()
+    )?;
+    // Count-only records have no multiples to list.
+    if r.result.count.is_some() {
+        return end_json_record(w, checksum);
+    }
+    w.write_all(b",\"numbers\":[")?;
+    match numbers {
+        JsonNumbers::Plain => write_joined_on(w, &r.result.numbers, ",", threads)?,
+        JsonNumbers::Annotated => {
+            for (i, &n) in r.result.numbers.iter().enumerate() {
+                let separator = if i > 0 { "," } else { "" };
+                write!(w, "{}{{\"n\":{},\"matched\":[{}]}}", separator, n, matched_list(&r.job, n, true))?;
+            }
+        }
+        JsonNumbers::Grouped => {
+            for (i, group) in group_by_divisor(&r.job, &r.result.numbers).iter().enumerate() {
+                w.write_all(if i > 0 { b",[" } else { b"[" })?;
+                write_joined(w, group, ",")?;
+                w.write_all(b"]")?;
+            }
+        }
+    }
+    w.write_all(b"]")?;
+    end_json_record(w, checksum)
+}
+
+/// The original `end:n1 n2 n3` line format. Annotated multiples are written
+/// as `n[a,b]`, grouped ones as `end:a[n1 n2] b[n3] both[n4]`.
+#[derive(Default)]
+pub struct TextFormatter {
+    annotate: bool,
+    grouped: bool,
+    threads: usize,
+}
+
+impl OutputFormatter for TextFormatter {
+    fn annotate(&mut self) -> Result<()> {
+        self.annotate = true;
+        Ok(())
+    }
+
+    fn group_by_divisor(&mut self) -> Result<()> {
+        self.grouped = true;
+        Ok(())
+    }
+
+    fn format_threads(&mut self, threads: usize) {
+        self.threads = threads;
+    }
+
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        if self.grouped && r.result.count.is_none() {
+            write!(w, "{}:", r.result.end)?;
+            let groups = group_by_divisor(&r.job, &r.result.numbers);
+            for (i, (name, group)) in ["a", "b", "both"].iter().zip(&groups).enumerate() {
+                let separator = if i > 0 { " " } else { "" };
+                write!(w, "{}{}[", separator, name)?;
+                write_joined(w, group, " ")?;
+                w.write_all(b"]")?;
+            }
+            w.write_all(b"\n")?;
+            return Ok(());
+        }
+        write!(w, "{}:", r.result.end)?;
+        if let Some(count) = r.result.count {
+            writeln!(w, "{}", count)?;
+            return Ok(());
+        }
+        if !self.annotate {
+            write_joined_on(w, &r.result.numbers, " ", self.threads)?;
+            w.write_all(b"\n")?;
+            return Ok(());
+        }
+        for (i, &n) in r.result.numbers.iter().enumerate() {
+            let separator = if i > 0 { " " } else { "" };
+            write!(w, "{}{}[{}]", separator, n, matched_list(&r.job, n, false))?;
+        }
+        w.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn write_streamed(
+        &mut self,
+        w: &mut dyn Write,
+        r: &ResultRecord,
+        count: Option<u64>,
+        chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    ) -> Result<()> {
+        if self.annotate || self.grouped {
+            bail!("Annotated or grouped text output can't be written in pieces");
+        }
+        write!(w, "{}:", r.result.end)?;
+        write_joined_chunks(w, chunks, " ", count)?;
+        w.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// Writes a streamed record of the JSON formats.
+fn write_json_streamed(
+    w: &mut dyn Write,
+    r: &ResultRecord,
+    numbers: JsonNumbers,
+    count: Option<u64>,
+    chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    checksum: bool,
+) -> Result<()> {
+    if numbers != JsonNumbers::Plain {
+        bail!("Annotated or grouped JSON output can't be written in pieces");
+    }
+    let count = announced(count, "JSON")?;
+    write!(
+        w,
+        "{{\"schema\":{},\"index\":{},\"a\":{},\"b\":{},\"end\":{},\"count\":{},\"numbers\":[",
+        OUTPUT_SCHEMA, r.index, r.job.a, r.job.b, r.result.end, count
+    )?;
+    let mut crc = checksum.then(|| RecordChecksum::new(r.index, &r.job, r.result.end));
+    let mut chunks = chunks.inspect(|chunk| crc.iter_mut().for_each(|crc| crc.numbers(chunk)));
+    write_joined_chunks(w, &mut chunks, ",", Some(count))?;
+    w.write_all(b"]")?;
+    end_json_record(w, crc.map(|crc| crc.finish(Some(count))))
+}
+
+/// A single JSON array containing one
+/// `{"index", "a", "b", "end", "count", "numbers"}` object per record;
+/// count-only records leave out `numbers`.
+/// Annotated multiples are written as `{"n", "matched"}` objects, grouped
+/// ones as three arrays: of `a` only, of `b` only and of both. Checksums
+/// are written as a last `"crc32"` member of 8 hex digits.
+#[derive(Default)]
+pub struct JsonFormatter {
+    written: usize,
+    numbers: JsonNumbers,
+    threads: usize,
+    checksum: bool,
+}
+
+impl OutputFormatter for JsonFormatter {
+    fn begin(&mut self, w: &mut dyn Write) -> Result<()> {
+        w.write_all(b"[")?;
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        Err(UNSUPPORTED_BY_FORMAT.error("JSON output can't be appended to (use ndjson instead)"))
+    }
+
+    fn annotate(&mut self) -> Result<()> {
+        self.numbers = JsonNumbers::Annotated;
+        Ok(())
+    }
+
+    fn group_by_divisor(&mut self) -> Result<()> {
+        self.numbers = JsonNumbers::Grouped;
+        Ok(())
+    }
+
+    fn format_threads(&mut self, threads: usize) {
+        self.threads = threads;
+    }
+
+    fn checksum(&mut self) -> Result<()> {
+        self.checksum = true;
+        Ok(())
+    }
+
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        w.write_all(if self.written == 0 { b"\n" } else { b",\n" })?;
+        write_json_record(w, r, self.numbers, self.threads, self.checksum)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    fn write_streamed(
+        &mut self,
+        w: &mut dyn Write,
+        r: &ResultRecord,
+        count: Option<u64>,
+        chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    ) -> Result<()> {
+        // Checked before the separator, so that a failure leaves no half
+        // written record behind.
+        announced(count, "JSON")?;
+        w.write_all(if self.written == 0 { b"\n" } else { b",\n" })?;
+        write_json_streamed(w, r, self.numbers, count, chunks, self.checksum)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self, w: &mut dyn Write) -> Result<()> {
+        w.write_all(b"\n]\n")?;
+        Ok(())
+    }
+}
+
+/// One JSON object per line, annotated, grouped and checksummed like
+/// `json`.
+#[derive(Default)]
+pub struct NdjsonFormatter {
+    numbers: JsonNumbers,
+    threads: usize,
+    checksum: bool,
+}
+
+impl OutputFormatter for NdjsonFormatter {
+    fn annotate(&mut self) -> Result<()> {
+        self.numbers = JsonNumbers::Annotated;
+        Ok(())
+    }
+
+    fn group_by_divisor(&mut self) -> Result<()> {
+        self.numbers = JsonNumbers::Grouped;
+        Ok(())
+    }
+
+    fn format_threads(&mut self, threads: usize) {
+        self.threads = threads;
+    }
+
+    fn checksum(&mut self) -> Result<()> {
+        self.checksum = true;
+        Ok(())
+    }
+
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        write_json_record(w, r, self.numbers, self.threads, self.checksum)?;
+        w.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn write_streamed(
+        &mut self,
+        w: &mut dyn Write,
+        r: &ResultRecord,
+        count: Option<u64>,
+        chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    ) -> Result<()> {
+        write_json_streamed(w, r, self.numbers, count, chunks, self.checksum)?;
+        w.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// Comma separated `index,a,b,end,count,numbers` values with a header row;
+/// the multiples are kept in a single space separated column, which is left
+/// empty for count-only records. Checksums are written in a last `crc32`
+/// column.
+#[derive(Default)]
+pub struct CsvFormatter {
+    header_written: bool,
+    threads: usize,
+    checksum: bool,
+}
+
+impl CsvFormatter {
+    fn write_header(&mut self, w: &mut dyn Write) -> Result<()> {
+        if !self.header_written {
+            w.write_all(b"index,a,b,end,count,numbers")?;
+            w.write_all(if self.checksum { b",crc32\n" } else { b"\n" })?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+}
+
+impl OutputFormatter for CsvFormatter {
+    fn resume(&mut self) -> Result<()> {
+        // The header of the existing rows may not have the column.
+        if self.checksum {
+            return Err(UNSUPPORTED_BY_FORMAT.error("CSV output with checksums can't be appended to"));
+        }
+        self.header_written = true;
+        Ok(())
+    }
+
+    fn format_threads(&mut self, threads: usize) {
+        self.threads = threads;
+    }
+
+    fn checksum(&mut self) -> Result<()> {
+        self.checksum = true;
+        Ok(())
+    }
+
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        self.write_header(w)?;
+        write!(
+            w,
+            "{},{},{},{},{},",
+            r.index,
+            r.job.a,
+            r.job.b,
+            r.result.end,
+            r.result.count()
+        )?;
+        write_joined_on(w, &r.result.numbers, " ", self.threads)?;
+        if self.checksum {
+            write!(w, ",{:08x}", record_checksum(r))?;
+        }
+        w.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn write_streamed(
+        &mut self,
+        w: &mut dyn Write,
+        r: &ResultRecord,
+        count: Option<u64>,
+        chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    ) -> Result<()> {
+        let count = announced(count, "CSV")?;
+        self.write_header(w)?;
+        write!(w, "{},{},{},{},{},", r.index, r.job.a, r.job.b, r.result.end, count)?;
+        let mut crc = self.checksum.then(|| RecordChecksum::new(r.index, &r.job, r.result.end));
+        let mut chunks = chunks.inspect(|chunk| crc.iter_mut().for_each(|crc| crc.numbers(chunk)));
+        write_joined_chunks(w, &mut chunks, " ", Some(count))?;
+        if let Some(crc) = crc {
+            write!(w, ",{:08x}", crc.finish(Some(count)))?;
+        }
+        w.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// How [`RenderedFormatter`] writes the values of records whose numbers
+/// stand for something else, such as decimals or timestamps.
+pub trait Render: Send + Sync {
+    /// Writes a divisor, `a` or `b`.
+    fn divisor(&self, n: u32) -> String;
+
+    /// Writes an end or a multiple.
+    fn value(&self, n: u32) -> String;
+
+    /// Whether the written values are strings in JSON rather than numbers.
+    fn quoted(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RenderedLayout {
+    Text,
+    Json,
+    Ndjson,
+    Csv,
+}
+
+/// The `text`, `json`, `ndjson` and `csv` layouts with every divisor, end
+/// and multiple written by a [`Render`]. Counts and indices stay integers.
+pub struct RenderedFormatter {
+    layout: RenderedLayout,
+    render: Arc<dyn Render>,
+    written: usize,
+}
+
+/// A registry of the [`RenderedFormatter`]s writing with `render`.
+pub fn rendered_formatters(render: Arc<dyn Render>) -> FormatterRegistry {
+    let layouts = [
+        ("text", RenderedLayout::Text),
+        ("json", RenderedLayout::Json),
+        ("ndjson", RenderedLayout::Ndjson),
+        ("csv", RenderedLayout::Csv),
+    ];
+    let mut registry = FormatterRegistry::new();
+    for (name, layout) in layouts {
+        let render = render.clone();
+        registry.register(name, move || {
+            Box::new(RenderedFormatter {
+                layout,
+                render: render.clone(),
+                written: 0,
+            })
+        });
+    }
+    registry
+}
+
+/// `result` as [`ResultNumbers`] displays it, with the end and the multiples
+/// written by `render`.
+pub fn rendered_text(render: &dyn Render, result: &ResultNumbers) -> String {
+    let end = render.value(result.end);
+    if let Some(count) = result.count {
+        return format!("{}:{}", end, count);
+    }
+    let numbers: Vec<String> = result.numbers.iter().map(|&n| render.value(n)).collect();
+    format!("{}:{}", end, numbers.join(" "))
+}
+
+impl RenderedFormatter {
+    fn joined(&self, numbers: &[u32], separator: &str, json: bool) -> String {
+        let numbers: Vec<String> = numbers.iter().map(|&n| self.json_value(self.render.value(n), json)).collect();
+        numbers.join(separator)
+    }
+
+    fn json_value(&self, value: String, json: bool) -> String {
+        if json && self.render.quoted() {
+            format!("\"{}\"", value)
+        } else {
+            value
+        }
+    }
+
+    fn json_record(&self, r: &ResultRecord) -> String {
+        let render = &self.render;
+        let mut object = format!(
+            "{{\"schema\":{},\"index\":{},\"a\":{},\"b\":{},\"end\":{},\"count\":{}",
+            OUTPUT_SCHEMA,
+            r.index,
+            self.json_value(render.divisor(r.job.a), true),
+            self.json_value(render.divisor(r.job.b), true),
+            self.json_value(render.value(r.result.end), true),
+            r.result.count()
+        );
+        if r.result.count.is_none() {
+            object.push_str(&format!(",\"numbers\":[{}]", self.joined(&r.result.numbers, ",", true)));
+        }
+        object.push('}');
+        object
+    }
+}
+
+impl OutputFormatter for RenderedFormatter {
+    fn begin(&mut self, w: &mut dyn Write) -> Result<()> {
+        if self.layout == RenderedLayout::Json {
+            w.write_all(b"[")?;
+        }
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        Err(UNSUPPORTED_BY_FORMAT.error("This output can't be appended to"))
+    }
+
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        let end = self.render.value(r.result.end);
+        match self.layout {
+            RenderedLayout::Text => writeln!(w, "{}", rendered_text(&*self.render, &r.result))?,
+            RenderedLayout::Json => {
+                w.write_all(if self.written == 0 { b"\n" } else { b",\n" })?;
+                w.write_all(self.json_record(r).as_bytes())?;
+            }
+            RenderedLayout::Ndjson => writeln!(w, "{}", self.json_record(r))?,
+            RenderedLayout::Csv => {
+                if self.written == 0 {
+                    w.write_all(b"index,a,b,end,count,numbers\n")?;
+                }
+                let (a, b) = (self.render.divisor(r.job.a), self.render.divisor(r.job.b));
+                let numbers = self.joined(&r.result.numbers, " ", false);
+                writeln!(w, "{},{},{},{},{},{}", r.index, a, b, end, r.result.count(), numbers)?;
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self, w: &mut dyn Write) -> Result<()> {
+        if self.layout == RenderedLayout::Json {
+            w.write_all(b"\n]\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArrayLanguage {
+    Rust,
+    C,
+    Python,
+}
+
+/// The array formats and the language each of them declares arrays in.
+pub const ARRAY_FORMATS: [(&str, ArrayLanguage); 3] = [
+    ("rust-array", ArrayLanguage::Rust),
+    ("c-array", ArrayLanguage::C),
+    ("python-list", ArrayLanguage::Python),
+];
+
+/// Identifier given to the array of each record unless configured otherwise.
+pub const DEFAULT_ARRAY_NAME: &str = "MULTIPLES_{a}_{b}_{end}";
+
+fn expand_array_name(template: &str, a: &str, b: &str, end: &str, index: &str) -> String {
+    template
+        .replace("{a}", a)
+        .replace("{b}", b)
+        .replace("{end}", end)
+        .replace("{index}", index)
+}
+
+/// Checks that `template` expands to a valid identifier in all array
+/// languages.
+pub fn validate_array_name(template: &str) -> Result<()> {
+    let name = expand_array_name(template, "0", "0", "0", "0");
+    let mut chars = name.chars();
+    let valid_start = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    if !valid_start || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        bail!(
+            "Invalid array name {:?}: must expand to an identifier; placeholders are {{a}}, {{b}}, {{end}} and {{index}}",
+            template
+        );
+    }
+    Ok(())
+}
+
+/// Source code declaring one constant array per record, named after
+/// `name_template` with `{a}`, `{b}`, `{end}` and `{index}` replaced by the
+/// record's values, e.g. `pub const MULTIPLES_3_5_10: [u32; 5] = [3, 5, 6, 9, 10];`.
+pub struct ArrayFormatter {
+    language: ArrayLanguage,
+    name_template: String,
+}
+
+impl ArrayFormatter {
+    pub fn new(language: ArrayLanguage, name_template: &str) -> Self {
+        ArrayFormatter {
+            language,
+            name_template: name_template.to_string(),
+        }
+    }
+}
+
+impl OutputFormatter for ArrayFormatter {
+    fn begin(&mut self, w: &mut dyn Write) -> Result<()> {
+        if self.language == ArrayLanguage::C {
+            w.write_all(b"#include <stdint.h>\n\n")?;
+        }
+        Ok(())
+    }
+
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        let name = expand_array_name(
+            &self.name_template,
+            &r.job.a.to_string(),
+            &r.job.b.to_string(),
+            &r.result.end.to_string(),
+            &r.index.to_string(),
+        );
+        if r.result.count.is_some() {
+            return Err(UNSUPPORTED_BY_FORMAT.error("Array formats need the multiples themselves, not just their count"));
+        }
+        let len = r.result.numbers.len();
+        match self.language {
+            ArrayLanguage::Rust => write!(w, "pub const {}: [u32; {}] = [", name, len)?,
+            ArrayLanguage::C if len == 0 => bail!("Can't write {}: C arrays can't be empty", name),
+            ArrayLanguage::C => write!(w, "const uint32_t {}[{}] = {{", name, len)?,
+            ArrayLanguage::Python => write!(w, "{} = [", name)?,
+        }
+        write_joined(w, &r.result.numbers, ", ")?;
+        w.write_all(match self.language {
+            ArrayLanguage::Rust => b"];\n",
+            ArrayLanguage::C => b"};\n",
+            ArrayLanguage::Python => b"]\n",
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        validate_array_name, write_joined_on, ArrayFormatter, ArrayLanguage, FormatterRegistry, OutputFormatter,
+        PARALLEL_FORMAT_MIN,
+    };
+    use crate::crc32::record_checksum;
+    use crate::{LineNumbers, ResultNumbers, ResultRecord};
+
+    fn render(name: &str, records: &[ResultRecord]) -> String {
+        let mut formatter = FormatterRegistry::with_builtins().create(name).unwrap();
+        let mut out = Vec::new();
+        formatter.begin(&mut out).unwrap();
+        for record in records {
+            formatter.write_record(&mut out, record).unwrap();
+        }
+        formatter.finish(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn sample() -> Vec<ResultRecord> {
+        vec![ResultRecord {
+            index: 0,
+            job: LineNumbers { a: 2, b: 3, end: 4 },
+            result: ResultNumbers {
+                end: 4,
+                numbers: vec![2, 3, 4],
+                count: None,
+            },
+        }]
+    }
+
+    #[test]
+    fn test_builtin_formatters() {
+        assert_eq!(render("text", &sample()), "4:2 3 4\n");
+        assert_eq!(
+            render("ndjson", &sample()),
+            "{\"schema\":1,\"index\":0,\"a\":2,\"b\":3,\"end\":4,\"count\":3,\"numbers\":[2,3,4]}\n"
+        );
+        assert_eq!(
+            render("json", &sample()),
+            "[\n{\"schema\":1,\"index\":0,\"a\":2,\"b\":3,\"end\":4,\"count\":3,\"numbers\":[2,3,4]}\n]\n"
+        );
+        assert_eq!(render("csv", &sample()), "index,a,b,end,count,numbers\n0,2,3,4,3,2 3 4\n");
+        assert_eq!(render("json", &[]), "[\n]\n");
+
+        let counted = vec![crate::count_record(LineNumbers { a: 2, b: 3, end: 4 }, crate::formula::Mode::Or)];
+        assert_eq!(render("text", &counted), "4:3\n");
+        assert_eq!(render("ndjson", &counted), "{\"schema\":1,\"index\":0,\"a\":2,\"b\":3,\"end\":4,\"count\":3}\n");
+        assert_eq!(render("csv", &counted), "index,a,b,end,count,numbers\n0,2,3,4,3,\n");
+        assert!(FormatterRegistry::with_builtins()
+            .create("rust-array")
+            .unwrap()
+            .write_record(&mut Vec::new(), &counted[0])
+            .is_err());
+    }
+
+    #[test]
+    fn test_annotated_formatters() {
+        let record = ResultRecord {
+            index: 0,
+            job: LineNumbers { a: 3, b: 5, end: 15 },
+            result: ResultNumbers {
+                end: 15,
+                numbers: vec![5, 9, 15],
+                count: None,
+            },
+        };
+        let render_annotated = |name: &str| {
+            let mut formatter = FormatterRegistry::with_builtins().create(name).unwrap();
+            formatter.annotate().unwrap();
+            let mut out = Vec::new();
+            formatter.write_record(&mut out, &record).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(render_annotated("text"), "15:5[b] 9[a] 15[a,b]\n");
+        assert_eq!(
+            render_annotated("ndjson"),
+            "{\"schema\":1,\"index\":0,\"a\":3,\"b\":5,\"end\":15,\"count\":3,\"numbers\":[\
+             {\"n\":5,\"matched\":[\"b\"]},{\"n\":9,\"matched\":[\"a\"]},{\"n\":15,\"matched\":[\"a\",\"b\"]}]}\n"
+        );
+        assert!(FormatterRegistry::with_builtins().create("csv").unwrap().annotate().is_err());
+    }
+
+    #[test]
+    fn test_grouped_formatters() {
+        let record = crate::generate_record(LineNumbers { a: 3, b: 5, end: 15 });
+        let render_grouped = |name: &str| {
+            let mut formatter = FormatterRegistry::with_builtins().create(name).unwrap();
+            formatter.group_by_divisor().unwrap();
+            let mut out = Vec::new();
+            formatter.write_record(&mut out, &record).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(render_grouped("text"), "15:a[3 6 9 12] b[5 10] both[15]\n");
+        assert_eq!(
+            render_grouped("ndjson"),
+            "{\"schema\":1,\"index\":0,\"a\":3,\"b\":5,\"end\":15,\"count\":7,\"numbers\":[[3,6,9,12],[5,10],[15]]}\n"
+        );
+        let empty = crate::generate_record(LineNumbers { a: 3, b: 5, end: 2 });
+        let mut formatter = FormatterRegistry::with_builtins().create("text").unwrap();
+        formatter.group_by_divisor().unwrap();
+        let mut out = Vec::new();
+        formatter.write_record(&mut out, &empty).unwrap();
+        assert_eq!(out, b"2:a[] b[] both[]\n");
+        assert!(FormatterRegistry::with_builtins().create("csv").unwrap().group_by_divisor().is_err());
+    }
+
+    #[test]
+    fn test_array_formatters() {
+        assert_eq!(render("rust-array", &sample()), "pub const MULTIPLES_2_3_4: [u32; 3] = [2, 3, 4];\n");
+        assert_eq!(
+            render("c-array", &sample()),
+            "#include <stdint.h>\n\nconst uint32_t MULTIPLES_2_3_4[3] = {2, 3, 4};\n"
+        );
+        assert_eq!(render("python-list", &sample()), "MULTIPLES_2_3_4 = [2, 3, 4]\n");
+
+        let mut formatter = ArrayFormatter::new(ArrayLanguage::Python, "table_{index}");
+        let mut out = Vec::new();
+        formatter.write_record(&mut out, &sample()[0]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "table_0 = [2, 3, 4]\n");
+
+        assert!(validate_array_name("T_{a}x{b}").is_ok());
+        assert!(validate_array_name("{end}_T").is_err());
+        assert!(validate_array_name("T-{a}").is_err());
+    }
+
+    /// Records formatted on several threads are written byte for byte as on
+    /// one, whether or not the threads divide them evenly.
+    #[test]
+    fn test_parallel_formatting() {
+        let numbers: Vec<u32> = (0..PARALLEL_FORMAT_MIN as u32 * 3 + 7).map(|n| n * 7919).collect();
+        let joined = |threads| {
+            let mut out = Vec::new();
+            write_joined_on(&mut out, &numbers, ", ", threads).unwrap();
+            out
+        };
+        assert_eq!(joined(3), joined(1));
+        assert_eq!(joined(8), joined(1));
+        assert!(joined(5).starts_with(b"0, 7919, 15838, "));
+
+        let record = ResultRecord {
+            index: 0,
+            job: LineNumbers { a: 1, b: 1, end: u32::MAX },
+            result: ResultNumbers { end: u32::MAX, numbers, count: None },
+        };
+        for name in ["text", "json", "ndjson", "csv"] {
+            let written = |threads| {
+                let mut formatter = FormatterRegistry::with_builtins().create(name).unwrap();
+                formatter.format_threads(threads);
+                let mut out = Vec::new();
+                formatter.begin(&mut out).unwrap();
+                formatter.write_record(&mut out, &record).unwrap();
+                formatter.finish(&mut out).unwrap();
+                out
+            };
+            assert_eq!(written(4), written(1), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_checksummed_formatters() {
+        let record = |numbers: Vec<u32>, count| ResultRecord {
+            index: 2,
+            job: LineNumbers { a: 2, b: 3, end: 4 },
+            result: ResultNumbers { end: 4, numbers, count },
+        };
+        let crc = record_checksum(&record(vec![2, 3, 4], None));
+        for (name, expected) in [
+            ("ndjson", format!("{{\"schema\":1,\"index\":2,\"a\":2,\"b\":3,\"end\":4,\"count\":3,\"numbers\":[2,3,4],\"crc32\":\"{:08x}\"}}\n", crc)),
+            ("csv", format!("index,a,b,end,count,numbers,crc32\n2,2,3,4,3,2 3 4,{:08x}\n", crc)),
+        ] {
+            let mut formatter = FormatterRegistry::with_builtins().create(name).unwrap();
+            formatter.checksum().unwrap();
+            let mut out = Vec::new();
+            formatter.write_record(&mut out, &record(vec![2, 3, 4], None)).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+            // Records written in pieces get the same checksum.
+            let mut formatter = FormatterRegistry::with_builtins().create(name).unwrap();
+            formatter.checksum().unwrap();
+            let mut out = Vec::new();
+            let mut chunks = vec![vec![2], vec![3, 4]].into_iter();
+            formatter.write_streamed(&mut out, &record(Vec::new(), None), Some(3), &mut chunks).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+        }
+        let counted = record(Vec::new(), Some(3));
+        let mut formatter = FormatterRegistry::with_builtins().create("json").unwrap();
+        formatter.checksum().unwrap();
+        let mut out = Vec::new();
+        formatter.write_record(&mut out, &counted).unwrap();
+        let crc = record_checksum(&counted);
+        let written = format!("{{\"schema\":1,\"index\":2,\"a\":2,\"b\":3,\"end\":4,\"count\":3,\"crc32\":\"{:08x}\"}}", crc);
+        assert_eq!(String::from_utf8(out).unwrap().trim(), written);
+        assert!(FormatterRegistry::with_builtins().create("text").unwrap().checksum().is_err());
+        // The header of the rows appended to may lack the column.
+        let mut csv = FormatterRegistry::with_builtins().create("csv").unwrap();
+        csv.checksum().unwrap();
+        assert!(csv.resume().is_err());
+    }
+
+    #[test]
+    fn test_unknown_formatter() {
+        assert!(FormatterRegistry::with_builtins().create("yaml").is_err());
+    }
+}
@@ -0,0 +1,238 @@
+//! Closed-form counts and sums of the multiples of a job, computed by
+//! inclusion–exclusion instead of enumerating them.
+
+use std::str::FromStr;
+
+use anyhow::Result;
+
+use crate::errors::UNKNOWN_NAME;
+use crate::LineNumbers;
+
+/// Which numbers of the range a job selects.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// Multiples of `a` or `b` (or both).
+    #[default]
+    Or,
+    /// Multiples of exactly one of `a` and `b`.
+    Xor,
+    /// Multiples of `a` or `b`, listing a multiple of both twice: once per
+    /// divisor.
+    Multiset,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Or => "or",
+            Mode::Xor => "xor",
+            Mode::Multiset => "multiset",
+        }
+    }
+
+    /// How often a number that is a multiple of `hits` of the two divisors
+    /// (0, 1 or 2) is selected.
+    #[inline(always)]
+    pub fn copies(self, hits: u8) -> usize {
+        match self {
+            Mode::Or => (hits > 0) as usize,
+            Mode::Xor => (hits == 1) as usize,
+            Mode::Multiset => hits as usize,
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "or" => Ok(Mode::Or),
+            "xor" => Ok(Mode::Xor),
+            "multiset" => Ok(Mode::Multiset),
+            _ => Err(UNKNOWN_NAME.error(format!("Unknown mode: {} (available: or, xor, multiset)", name))),
+        }
+    }
+}
+
+fn gcd(mut x: u64, mut y: u64) -> u64 {
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    x
+}
+
+/// Least common multiple of `a` and `b`, or 0 (no multiples) if either is 0.
+fn lcm(a: u32, b: u32) -> u64 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    a as u64 / gcd(a as u64, b as u64) * b as u64
+}
+
+/// Number of multiples of `d` in `1..=end`; 0 has none.
+fn multiples_of(d: u64, end: u32) -> u64 {
+    (end as u64).checked_div(d).unwrap_or(0)
+}
+
+/// Sum of the multiples of `d` in `1..=end`.
+fn sum_of(d: u64, end: u32) -> u128 {
+    let k = multiples_of(d, end) as u128;
+    d as u128 * k * (k + 1) / 2
+}
+
+/// Number of values in `1..=job.end` selected by `mode`, counting a value
+/// selected twice twice.
+pub fn count(job: &LineNumbers, mode: Mode) -> u64 {
+    let both = multiples_of(lcm(job.a, job.b), job.end);
+    let either = multiples_of(job.a as u64, job.end) + multiples_of(job.b as u64, job.end);
+    match mode {
+        Mode::Or => either - both,
+        Mode::Xor => either - 2 * both,
+        Mode::Multiset => either,
+    }
+}
+
+/// Sum of the values in `1..=job.end` selected by `mode`, with every
+/// selection of a value adding to it.
+pub fn sum(job: &LineNumbers, mode: Mode) -> u128 {
+    let both = sum_of(lcm(job.a, job.b), job.end);
+    let either = sum_of(job.a as u64, job.end) + sum_of(job.b as u64, job.end);
+    match mode {
+        Mode::Or => either - both,
+        Mode::Xor => either - 2 * both,
+        Mode::Multiset => either,
+    }
+}
+
+/// The smallest `x` for which `1..=x` holds at least `rank` selected
+/// values, or `None` when all of `1..=job.end` holds fewer. Found by
+/// bisection over [`count`], without listing the values before it.
+pub fn nth(job: &LineNumbers, mode: Mode, rank: u64) -> Option<u32> {
+    if rank == 0 {
+        return Some(0);
+    }
+    if count(job, mode) < rank {
+        return None;
+    }
+    let (mut low, mut high) = (0, job.end);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if count(&LineNumbers { end: middle, ..*job }, mode) >= rank {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    Some(low)
+}
+
+/// The selected values from the `offset`-th on (counting from 0), at most
+/// `limit` of them. Only the numbers of the page itself are tested.
+pub fn page(job: &LineNumbers, mode: Mode, offset: u64, limit: u64) -> Vec<u32> {
+    let (Some(first), true) = (nth(job, mode, offset + 1), limit > 0) else {
+        return Vec::new();
+    };
+    let last = nth(job, mode, offset.saturating_add(limit)).unwrap_or(job.end);
+    let skip = offset - count(&LineNumbers { end: first - 1, ..*job }, mode);
+    (first..=last)
+        .flat_map(|n| {
+            let hits = n.is_multiple_of(job.a) as u8 + n.is_multiple_of(job.b) as u8;
+            std::iter::repeat_n(n, mode.copies(hits))
+        })
+        .skip(skip as usize)
+        .take(limit as usize)
+        .collect()
+}
+
+/// Rewrites `job` into the canonical pair selecting the same numbers in
+/// `mode`: `a <= b`, with a divisor that adds nothing replaced by the other
+/// one. Returns the canonical job and, when a divisor was dropped, why.
+///
+/// In `or` mode a divisor of 0 adds no multiples, and neither does a
+/// multiple of the other divisor. In the other modes both change the
+/// selection, since a pair of equal divisors selects every multiple twice
+/// or not at all, so they are kept.
+pub fn simplify(job: &LineNumbers, mode: Mode) -> (LineNumbers, Option<String>) {
+    let (a, b) = (job.a.min(job.b), job.a.max(job.b));
+    let single = |d: u32, reason: String| (LineNumbers { a: d, b: d, end: job.end }, Some(reason));
+    if mode == Mode::Or && a == 0 && b != 0 {
+        return single(b, String::from("0 has no multiples"));
+    }
+    if mode == Mode::Or && a != 0 && a != b && b.is_multiple_of(a) {
+        return single(a, format!("{} is a multiple of {}", b, a));
+    }
+    (LineNumbers { a, b, end: job.end }, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count, nth, page, simplify, sum, Mode};
+    use crate::LineNumbers;
+
+    fn brute_force(job: &LineNumbers, mode: Mode) -> Vec<u32> {
+        (1..=job.end)
+            .flat_map(|n| {
+                let hits = n.is_multiple_of(job.a) as u8 + n.is_multiple_of(job.b) as u8;
+                std::iter::repeat_n(n, mode.copies(hits))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_count_and_sum_match_enumeration() {
+        let jobs = [(3, 5, 100), (4, 6, 1000), (7, 7, 50), (0, 3, 20), (1, 2, 9), (0, 0, 10)];
+        for (a, b, end) in jobs {
+            let job = LineNumbers { a, b, end };
+            for mode in [Mode::Or, Mode::Xor, Mode::Multiset] {
+                let numbers = brute_force(&job, mode);
+                assert_eq!(count(&job, mode), numbers.len() as u64, "{:?} {:?}", job, mode);
+                assert_eq!(sum(&job, mode), numbers.iter().map(|&n| n as u128).sum(), "{:?} {:?}", job, mode);
+            }
+        }
+    }
+
+    #[test]
+    fn test_simplify_keeps_selection() {
+        let jobs = [(3, 9, 100), (9, 3, 100), (7, 7, 50), (0, 3, 20), (1, 2, 9), (0, 0, 10), (4, 6, 60)];
+        for (a, b, end) in jobs {
+            let job = LineNumbers { a, b, end };
+            for mode in [Mode::Or, Mode::Xor, Mode::Multiset] {
+                let (simplified, _) = simplify(&job, mode);
+                assert!(simplified.a <= simplified.b);
+                assert_eq!(brute_force(&simplified, mode), brute_force(&job, mode), "{:?} {:?}", job, mode);
+            }
+        }
+        let (job, reason) = simplify(&LineNumbers { a: 9, b: 3, end: 100 }, Mode::Or);
+        assert_eq!((job.a, job.b, reason.as_deref()), (3, 3, Some("9 is a multiple of 3")));
+        assert_eq!(simplify(&LineNumbers { a: 9, b: 3, end: 100 }, Mode::Xor).1, None);
+        assert_eq!(simplify(&LineNumbers { a: 7, b: 0, end: 1 }, Mode::Or).0.a, 7);
+        assert_eq!(simplify(&LineNumbers { a: 7, b: 0, end: 1 }, Mode::Xor).0.a, 0);
+    }
+
+    #[test]
+    fn test_page_matches_enumeration() {
+        for (a, b, end) in [(3, 5, 100), (4, 6, 1000), (7, 7, 50), (1, 2, 9)] {
+            let job = LineNumbers { a, b, end };
+            for mode in [Mode::Or, Mode::Xor, Mode::Multiset] {
+                let numbers = brute_force(&job, mode);
+                for (offset, limit) in [(0, 5), (3, 4), (10, 1000), (numbers.len() as u64, 5), (1, 0)] {
+                    let expected: Vec<u32> = numbers.iter().copied().skip(offset as usize).take(limit as usize).collect();
+                    assert_eq!(page(&job, mode, offset, limit), expected, "{:?} {:?} {} {}", job, mode, offset, limit);
+                }
+            }
+        }
+        let job = LineNumbers { a: 2, b: 3, end: 10 };
+        assert_eq!((nth(&job, Mode::Or, 0), nth(&job, Mode::Or, 3), nth(&job, Mode::Or, 8)), (Some(0), Some(4), None));
+    }
+
+    #[test]
+    fn test_large_ranges() {
+        let job = LineNumbers { a: 1, b: 1, end: u32::MAX };
+        assert_eq!(count(&job, Mode::Or), u32::MAX as u64);
+        assert_eq!(count(&job, Mode::Xor), 0);
+        assert_eq!(count(&job, Mode::Multiset), 2 * u32::MAX as u64);
+        assert_eq!(sum(&job, Mode::Or), u32::MAX as u128 * (u32::MAX as u128 + 1) / 2);
+        assert_eq!(page(&job, Mode::Multiset, 2 * u32::MAX as u64 - 4, 10), [u32::MAX - 1, u32::MAX - 1, u32::MAX, u32::MAX]);
+    }
+}
@@ -0,0 +1,212 @@
+//! The distribution of the gaps between consecutive selected multiples of
+//! a line, for `analyze gaps`.
+//!
+//! Gaps are counted in a histogram as the multiples are enumerated rather
+//! than kept, which stays small: in `or` mode no gap is wider than the
+//! smaller divisor.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+use crate::formula::Mode;
+use crate::kernel::Kernel;
+use crate::wheel::Wheel;
+use crate::LineNumbers;
+
+/// Values tested at a time by lines too long-periodic for a wheel.
+const WINDOW: u64 = 1 << 16;
+
+/// The gaps of one line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GapStats {
+    /// The number of selected multiples, one more than the number of gaps
+    /// when there are any.
+    pub multiples: u64,
+    previous: Option<u32>,
+    /// How often each gap occurs.
+    pub histogram: BTreeMap<u32, u64>,
+    sum: u64,
+}
+
+impl GapStats {
+    /// Adds the next selected multiple, which must not be below the last.
+    pub fn push(&mut self, n: u32) {
+        if let Some(previous) = self.previous {
+            let gap = n - previous;
+            *self.histogram.entry(gap).or_default() += 1;
+            self.sum += gap as u64;
+        }
+        self.previous = Some(n);
+        self.multiples += 1;
+    }
+
+    pub fn gaps(&self) -> u64 {
+        self.multiples.saturating_sub(1)
+    }
+
+    pub fn min(&self) -> Option<u32> {
+        self.histogram.keys().next().copied()
+    }
+
+    pub fn max(&self) -> Option<u32> {
+        self.histogram.keys().next_back().copied()
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.gaps() > 0).then(|| self.sum as f64 / self.gaps() as f64)
+    }
+
+    /// The nearest-rank `p`-th percentile of the gaps, for `p` in `0..=100`.
+    pub fn percentile(&self, p: f64) -> Option<u32> {
+        let rank = ((p / 100.0 * self.gaps() as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (&gap, &count) in &self.histogram {
+            seen += count;
+            if seen >= rank {
+                return Some(gap);
+            }
+        }
+        None
+    }
+}
+
+/// Enumerates the multiples `mode` selects for `job`, in ascending order,
+/// into [`GapStats`]. Multiples of both divisors listed twice in
+/// `multiset` mode give gaps of 0.
+pub fn gap_stats(job: &LineNumbers, mode: Mode) -> GapStats {
+    let mut stats = GapStats::default();
+    match Wheel::new(job.a, job.b, mode) {
+        Some(wheel) => wheel.numbers(job.end).for_each(|n| stats.push(n)),
+        None => {
+            let kernel = Kernel::detect();
+            let mut first = 1u64;
+            while first <= job.end as u64 {
+                let last = (first + WINDOW - 1).min(job.end as u64) as u32;
+                let window = LineNumbers { end: last, ..*job };
+                for n in kernel.select_from(&window, first as u32, mode, &mut |_| {}) {
+                    stats.push(n);
+                }
+                first = last as u64 + 1;
+            }
+        }
+    }
+    stats
+}
+
+/// Parses a comma separated list of percentiles such as `50,90,99.9`.
+pub fn parse_percentiles(text: &str) -> Result<Vec<f64>> {
+    let mut percentiles = Vec::new();
+    for item in text.split(',') {
+        match item.trim().parse::<f64>() {
+            Ok(p) if p > 0.0 && p <= 100.0 => percentiles.push(p),
+            _ => bail!("Invalid percentile: {} (expected a number above 0 and at most 100)", item),
+        }
+    }
+    Ok(percentiles)
+}
+
+fn or_dash<T: ToString>(value: Option<T>) -> String {
+    value.map_or_else(|| String::from("-"), |value| value.to_string())
+}
+
+/// The header of [`text_row`]'s table.
+pub fn text_header(percentiles: &[f64]) -> String {
+    let mut header = format!(
+        "{:<8} {:>10} {:>10} {:>10} {:>12} {:>8} {:>8} {:>10}",
+        "line", "a", "b", "end", "multiples", "min", "max", "mean"
+    );
+    for p in percentiles {
+        header.push_str(&format!(" {:>8}", format!("p{}", p)));
+    }
+    header
+}
+
+/// One line of the text report of line `line` (counting from 1).
+pub fn text_row(line: usize, job: &LineNumbers, stats: &GapStats, percentiles: &[f64]) -> String {
+    let mut row = format!(
+        "{:<8} {:>10} {:>10} {:>10} {:>12} {:>8} {:>8} {:>10}",
+        line,
+        job.a,
+        job.b,
+        job.end,
+        stats.multiples,
+        or_dash(stats.min()),
+        or_dash(stats.max()),
+        or_dash(stats.mean().map(|mean| format!("{:.3}", mean)))
+    );
+    for &p in percentiles {
+        row.push_str(&format!(" {:>8}", or_dash(stats.percentile(p))));
+    }
+    row
+}
+
+/// The JSON object of line `line`, with `null` for lines without gaps.
+pub fn json_object(line: usize, job: &LineNumbers, stats: &GapStats, percentiles: &[f64]) -> String {
+    let null = |value: Option<String>| value.unwrap_or_else(|| String::from("null"));
+    let mut object = format!(
+        "{{\"line\":{},\"a\":{},\"b\":{},\"end\":{},\"multiples\":{},\"gaps\":{},\"min\":{},\"max\":{},\"mean\":{}",
+        line,
+        job.a,
+        job.b,
+        job.end,
+        stats.multiples,
+        stats.gaps(),
+        null(stats.min().map(|min| min.to_string())),
+        null(stats.max().map(|max| max.to_string())),
+        null(stats.mean().map(|mean| format!("{:.3}", mean)))
+    );
+    for &p in percentiles {
+        object.push_str(&format!(",\"p{}\":{}", p, null(stats.percentile(p).map(|gap| gap.to_string()))));
+    }
+    object.push('}');
+    object
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gap_stats, json_object, parse_percentiles, text_header, text_row};
+    use crate::formula::Mode;
+    use crate::{generate_record, LineNumbers};
+
+    #[test]
+    fn test_gap_stats() {
+        // 3 5 6 9 10 12 15 18 20 21 24 25 27 30: gaps 2 1 3 1 2 3 3 2 1 3 1 2 3.
+        let job = LineNumbers { a: 3, b: 5, end: 30 };
+        let stats = gap_stats(&job, Mode::Or);
+        assert_eq!(stats.multiples, 14);
+        assert_eq!((stats.min(), stats.max()), (Some(1), Some(3)));
+        assert_eq!(stats.mean(), Some(27.0 / 13.0));
+        assert_eq!(stats.percentile(50.0), Some(2));
+        assert_eq!(stats.percentile(100.0), Some(3));
+        assert_eq!(stats.percentile(1.0), Some(1));
+        assert_eq!(gap_stats(&job, Mode::Multiset).min(), Some(0));
+
+        // Too long a period for a wheel, so enumerated in windows.
+        let long = LineNumbers { a: 65_521, b: 65_519, end: 300_000 };
+        let numbers = generate_record(long).result.numbers;
+        let stats = gap_stats(&long, Mode::Or);
+        assert_eq!(stats.multiples, numbers.len() as u64);
+        let widest = numbers.windows(2).map(|pair| pair[1] - pair[0]).max();
+        assert_eq!(stats.max(), widest);
+
+        let empty = gap_stats(&LineNumbers { a: 7, b: 9, end: 8 }, Mode::Or);
+        assert_eq!((empty.multiples, empty.mean(), empty.percentile(50.0)), (1, None, None));
+    }
+
+    #[test]
+    fn test_gap_report() {
+        let percentiles = parse_percentiles("50, 99.9").unwrap();
+        assert!(parse_percentiles("0").is_err());
+        assert!(parse_percentiles("50,x").is_err());
+        let job = LineNumbers { a: 2, b: 2, end: 10 };
+        let stats = gap_stats(&job, Mode::Or);
+        let words = |text: String| text.split_whitespace().map(String::from).collect::<Vec<_>>();
+        assert_eq!(words(text_header(&percentiles))[7..], ["mean", "p50", "p99.9"]);
+        assert_eq!(words(text_row(1, &job, &stats, &percentiles)), ["1", "2", "2", "10", "5", "2", "2", "2.000", "2", "2"]);
+        assert_eq!(
+            json_object(1, &job, &stats, &percentiles),
+            r#"{"line":1,"a":2,"b":2,"end":10,"multiples":5,"gaps":4,"min":2,"max":2,"mean":2.000,"p50":2,"p99.9":2}"#
+        );
+    }
+}
@@ -0,0 +1,432 @@
+//! The `help-topics` and `examples` commands: long-form help on the
+//! features of the command line, each with runnable examples. The examples
+//! live in [`TOPICS`] as argument lists, which the tests run through the
+//! option parsers, so that none of them uses an option that doesn't exist.
+
+use std::fmt::Write as _;
+
+use crate::cli;
+
+/// One example invocation.
+pub struct Example {
+    /// The arguments after the program name.
+    pub args: &'static [&'static str],
+    pub description: &'static str,
+}
+
+impl Example {
+    /// The example as a command line of `program`.
+    pub fn command_line(&self, program: &str) -> String {
+        let mut line = String::from(program);
+        for arg in self.args {
+            line.push(' ');
+            line.push_str(arg);
+        }
+        line
+    }
+}
+
+/// A feature of the command line.
+pub struct Topic {
+    pub name: &'static str,
+    pub title: &'static str,
+    /// What the feature does, in paragraphs.
+    pub text: &'static str,
+    /// The options of the command the topic is about, if it has its own.
+    pub options: Option<&'static str>,
+    pub examples: &'static [Example],
+}
+
+impl Topic {
+    /// The examples of the topic, each command line after its description.
+    pub fn examples_text(&self, program: &str) -> String {
+        let mut text = String::new();
+        for example in self.examples {
+            let _ = writeln!(text, "  # {}\n  {}", example.description, example.command_line(program));
+        }
+        text
+    }
+
+    /// The long-form help on the topic.
+    pub fn long_help(&self, program: &str) -> String {
+        let mut text = format!("{}\n{}\n\n{}\n", self.title, "=".repeat(self.title.chars().count()), self.text);
+        if let Some(options) = self.options {
+            let _ = write!(text, "\n{}\n", options);
+        }
+        let _ = write!(text, "\nExamples:\n{}", self.examples_text(program));
+        text
+    }
+}
+
+pub const TOPICS: &[Topic] = &[
+    Topic {
+        name: "input",
+        title: "Input files",
+        text: "\
+Every line of the input is a job of two divisors and an end, `a b end`, selecting the multiples of a or b \
+from 1 to end. Text, CSV, JSON and NDJSON inputs are told apart by their file extension, or named with \
+--input-format. Lines may end in prio=high, bound=exclusive or tag=nightly, and in `> path` to write their \
+record to a file of its own; --only-tag and --skip-tag run the lines with or without some tags.
+
+lint checks an input for lines that are valid but most likely mistakes, --fix writes a cleaned copy of a \
+hand-written one and wizard builds one by asking for every line. sweep runs the combinations of ranges of \
+a, b and end meeting constraints such as a<b, with an output per job.",
+        options: Some(cli::LINT_OPTIONS),
+        examples: &[
+            Example {
+                args: &["--input-format", "csv", "jobs.csv", "results.txt"],
+                description: "read a CSV input whatever its extension",
+            },
+            Example {
+                args: &["--fast-parse", "jobs.txt", "results.txt"],
+                description: "read a text input of millions of lines faster",
+            },
+            Example {
+                args: &["lint", "--deny", "warnings", "jobs.txt"],
+                description: "fail on suspicious lines, e.g. in CI",
+            },
+            Example {
+                args: &["--fix", "jobs.txt", "clean.txt"],
+                description: "write a cleaned copy of a hand-written input and a report of the changes",
+            },
+            Example {
+                args: &["wizard", "jobs.txt"],
+                description: "build an input by answering questions",
+            },
+            Example {
+                args: &["sweep", "--a", "2..10", "--b", "3..12", "--end", "1e6", "--where", "gcd(a,b)==1", "results"],
+                description: "run every pair of coprime divisors into results/a=<a>/b=<b>/",
+            },
+        ],
+    },
+    Topic {
+        name: "formats",
+        title: "Output formats",
+        text: "\
+Records are written as text `end:multiples` lines unless --output-format names another format: json, \
+ndjson and csv for other programs, store for a file get can query, auto, delta and roaring for compact \
+files, and rust-array, c-array and python-list for source code.
+
+convert rewrites a result file in another format, and migrate upgrades a JSON or binary one written by \
+an earlier release to the current schema version. describe tells what a result file holds: its format, \
+schema version, records and multiples.",
+        options: None,
+        examples: &[
+            Example {
+                args: &["--output-format", "json", "jobs.txt", "results.json"],
+                description: "write the records as a JSON array",
+            },
+            Example {
+                args: &["--output-format", "rust-array", "--array-name", "M_{a}_{b}", "jobs.txt", "multiples.rs"],
+                description: "write a Rust array per line, named after its divisors",
+            },
+            Example {
+                args: &["--count-only", "jobs.txt", "counts.txt"],
+                description: "write only the number of multiples of every line",
+            },
+            Example {
+                args: &["--annotate", "--output-format", "ndjson", "jobs.txt", "results.ndjson"],
+                description: "tell which divisors every multiple matched",
+            },
+            Example {
+                args: &["convert", "results.json", "results.csv"],
+                description: "convert a result file, picking the formats by extension",
+            },
+            Example {
+                args: &["convert", "--checksums", "results.ndjson", "results.csv"],
+                description: "convert a result file checking its record checksums, and keep them",
+            },
+            Example {
+                args: &["migrate", "archive/results.json", "results.json"],
+                description: "upgrade an old JSON result file to the current schema",
+            },
+            Example {
+                args: &["describe", "unlabeled.bin"],
+                description: "tell the format and contents of a result file",
+            },
+            Example {
+                args: &["provenance", "--verify", "results.csv"],
+                description: "check that a file written with --provenance came from this build and its input",
+            },
+        ],
+    },
+    Topic {
+        name: "modes",
+        title: "Selection modes",
+        text: "\
+--mode or, the default, selects the multiples of a or b, xor those of exactly one of them and multiset \
+those of either, listing the multiples of both twice. --power selects multiples of a^k or b^k instead.
+
+Ranges run from 1 to end: --bound exclusive stops below end, and --include-zero starts them at 0, a \
+multiple of every divisor.",
+        options: None,
+        examples: &[
+            Example {
+                args: &["--mode", "xor", "jobs.txt", "results.txt"],
+                description: "select the multiples of exactly one divisor",
+            },
+            Example {
+                args: &["--multiset", "jobs.txt", "results.txt"],
+                description: "list the multiples of both divisors twice",
+            },
+            Example {
+                args: &["--power", "2", "jobs.txt", "results.txt"],
+                description: "select the multiples of a^2 or b^2",
+            },
+            Example {
+                args: &["--bound", "exclusive", "jobs.txt", "results.txt"],
+                description: "select the multiples below end, as in Project Euler",
+            },
+            Example {
+                args: &["--only-tag", "nightly", "--skip-tag", "slow", "jobs.txt", "results.txt"],
+                description: "generate the lines tagged nightly but not slow",
+            },
+        ],
+    },
+    Topic {
+        name: "filters",
+        title: "Filtering and summarizing",
+        text: "\
+Records can be dropped by their number of multiples with --min-count and --max-count, or ranked with \
+--top and --by; the multiples themselves can be filtered with --digit-sum-divisible-by, --ends-with and \
+--palindrome.
+
+--aggregate writes one summary of all lines instead of their records, and --preview prints the ends and \
+count of every record instead of writing an output.",
+        options: None,
+        examples: &[
+            Example {
+                args: &["--min-count", "10", "jobs.txt", "results.txt"],
+                description: "drop records with fewer than 10 multiples",
+            },
+            Example {
+                args: &["--top", "5", "--by", "count", "jobs.txt", "results.txt"],
+                description: "keep the 5 records with the most multiples",
+            },
+            Example {
+                args: &["--palindrome", "jobs.txt", "results.txt"],
+                description: "keep the multiples that read the same backwards",
+            },
+            Example {
+                args: &["--aggregate", "jobs.txt", "report.txt"],
+                description: "write one summary of all lines",
+            },
+            Example {
+                args: &["--preview", "3", "jobs.txt"],
+                description: "print the first and last 3 multiples of every line",
+            },
+            Example {
+                args: &["--stop-after", "100", "jobs.txt", "results.txt"],
+                description: "list only the first 100 multiples of every line, however long its range",
+            },
+        ],
+    },
+    Topic {
+        name: "performance",
+        title: "Performance",
+        text: "\
+--threads generates lines in parallel and --strategy picks how multiples are found. Lines whose \
+multiples take more than --max-memory are written as they are generated.
+
+explain predicts the count, size, memory and duration of every line without generating it, and bench \
+times a run, optionally against another build.",
+        options: Some(cli::EXPLAIN_OPTIONS),
+        examples: &[
+            Example {
+                args: &["--threads", "auto", "jobs.txt", "results.txt"],
+                description: "generate on one thread per CPU",
+            },
+            Example {
+                args: &["--threads", "4", "--longest-first", "jobs.txt", "results.txt"],
+                description: "start the lines with the most work first",
+            },
+            Example {
+                args: &["--strategy", "wheel", "jobs.txt", "results.txt"],
+                description: "step through the multiples instead of testing every number",
+            },
+            Example {
+                args: &["--max-memory", "1G", "jobs.txt", "results.txt"],
+                description: "stream lines that would hold more than 1 GB",
+            },
+            Example {
+                args: &["explain", "--threads", "4", "--longest-first", "jobs.txt"],
+                description: "plan a run on 4 threads",
+            },
+            Example {
+                args: &["bench", "--runs", "5", "jobs.txt"],
+                description: "time a run, taking the median of 5",
+            },
+        ],
+    },
+    Topic {
+        name: "sharding",
+        title: "Splitting a range across machines",
+        text: "\
+--shard i/n generates only the i-th of n contiguous chunks of every range, so that n machines can share \
+a run. merge-shards joins their outputs into the output a single run would have written, checking the \
+merged counts against the closed-form ones. backfill fills the gaps of a directory of results for a \
+series of ends, extending the nearest lower end of each.",
+        options: Some(cli::MERGE_SHARDS_OPTIONS),
+        examples: &[
+            Example {
+                args: &["--shard", "1/4", "jobs.txt", "part1.txt"],
+                description: "generate the first of 4 chunks of every range",
+            },
+            Example {
+                args: &["merge-shards", "part1.txt", "part2.txt", "part3.txt", "part4.txt", "results.txt"],
+                description: "join the outputs of all 4 shards",
+            },
+            Example {
+                args: &["backfill", "--target", "1e7", "--stride", "1e6", "results"],
+                description: "generate the files of the ends 1e6 to 1e7 that results/ lacks",
+            },
+        ],
+    },
+    Topic {
+        name: "server",
+        title: "Server mode",
+        text: "\
+serve answers HTTP requests such as `GET /multiples?a=3&b=5&end=100` and batches of jobs posted to \
+/batch, with an optional rate limit, API keys or JWT bearer tokens and a cache of recent answers.",
+        options: Some(cli::SERVE_OPTIONS),
+        examples: &[
+            Example {
+                args: &["serve", "--listen", "0.0.0.0:8080"],
+                description: "answer requests from other machines",
+            },
+            Example {
+                args: &["serve", "--rate-limit", "60/min", "--api-keys", "keys.txt"],
+                description: "require API keys and limit every client to 60 requests a minute",
+            },
+            Example {
+                args: &["serve", "--cache-size", "128M", "--cache-ttl", "5m"],
+                description: "keep up to 128 MB of answers for 5 minutes",
+            },
+            Example {
+                args: &["serve", "--service", "--pid-file", "/run/multiples-serve.pid"],
+                description: "run under systemd, finishing open requests on SIGTERM",
+            },
+        ],
+    },
+    Topic {
+        name: "results",
+        title: "Working with result files",
+        text: "\
+Result files can be merged, sorted and converted without generating them again, and a store written \
+with --output-format store answers single queries with get.",
+        options: Some(cli::MERGE_OPTIONS),
+        examples: &[
+            Example {
+                args: &["merge", "--merge-mode", "union", "a.txt", "b.txt", "merged.txt"],
+                description: "merge the records of two runs, joining those of the same job",
+            },
+            Example {
+                args: &["sort", "--by", "end", "results.txt", "sorted.txt"],
+                description: "sort records by their end",
+            },
+            Example {
+                args: &["--output-format", "store", "jobs.txt", "results.db"],
+                description: "write a store",
+            },
+            Example {
+                args: &["get", "--count", "results.db", "3", "5", "100"],
+                description: "look up the number of multiples of one job in it",
+            },
+        ],
+    },
+    Topic {
+        name: "checks",
+        title: "Checking results",
+        text: "\
+selftest compares the strategies and kernels on random jobs, --cross-check compares a sample of every \
+range of a run with plain trial division, and regress runs the recorded regression cases. Errors come \
+with a code that explain-error explains.",
+        options: Some(cli::SELFTEST_OPTIONS),
+        examples: &[
+            Example {
+                args: &["selftest", "--cases", "100", "--seed", "7"],
+                description: "check 100 random jobs, reproducibly",
+            },
+            Example {
+                args: &["--cross-check", "jobs.txt", "results.txt"],
+                description: "check a run while generating it",
+            },
+            Example {
+                args: &["regress", "--cases", "cases"],
+                description: "run the regression cases",
+            },
+            Example {
+                args: &["explain-error", "MP0001"],
+                description: "explain an error code",
+            },
+        ],
+    },
+];
+
+/// The topic called `name`.
+pub fn lookup(name: &str) -> Option<&'static Topic> {
+    TOPICS.iter().find(|topic| topic.name == name)
+}
+
+/// The list of topics, one `name  title` line each.
+pub fn topic_list() -> String {
+    TOPICS.iter().map(|topic| format!("  {:<13}{}\n", topic.name, topic.title)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lookup, topic_list, TOPICS};
+    use crate::cli;
+
+    /// Whether `args` parse as the command line of one run.
+    fn parses(args: &[&str]) -> anyhow::Result<bool> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let rest = args[1..].iter().cloned();
+        Ok(match args[0].as_str() {
+            "lint" => cli::parse_lint_args(rest)?.is_some(),
+            "wizard" => cli::parse_wizard_args(rest).is_ok(),
+            "convert" => cli::parse_convert_args(rest)?.is_some(),
+            "migrate" => cli::parse_migrate_args(rest)?.is_some(),
+            "describe" => cli::parse_describe_args(rest)?.is_some(),
+            "provenance" => cli::parse_provenance_args(rest)?.is_some(),
+            "explain" => cli::parse_explain_args(rest)?.is_some(),
+            "bench" => cli::parse_bench_args(rest)?.is_some(),
+            "merge-shards" => cli::parse_merge_shards_args(rest)?.is_some(),
+            "backfill" => cli::parse_backfill_args(rest)?.is_some(),
+            "sweep" => cli::parse_sweep_args(rest)?.is_some(),
+            "serve" => cli::parse_serve_args(rest)?.is_some(),
+            "merge" => cli::parse_merge_args(rest)?.is_some(),
+            "sort" => cli::parse_sort_args(rest)?.is_some(),
+            "get" => cli::parse_get_args(rest)?.is_some(),
+            "selftest" => cli::parse_selftest_args(rest)?.is_some(),
+            "regress" => cli::parse_regress_args(rest)?.is_some(),
+            "explain-error" => cli::parse_explain_error_args(rest)?.is_some(),
+            _ => cli::parse_generate_args(args)?.is_some(),
+        })
+    }
+
+    #[test]
+    fn test_examples_parse() {
+        for topic in TOPICS {
+            assert!(!topic.examples.is_empty(), "{} has no examples", topic.name);
+            for example in topic.examples {
+                let line = example.command_line("multiples");
+                assert!(parses(example.args).unwrap_or_else(|err| panic!("{}: {:#}", line, err)), "{}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn test_topics() {
+        let sharding = lookup("sharding").unwrap();
+        assert!(sharding.examples_text("multiples").starts_with(
+            "  # generate the first of 4 chunks of every range\n  multiples --shard 1/4 jobs.txt part1.txt\n"
+        ));
+        let help = sharding.long_help("multiples");
+        assert!(help.starts_with("Splitting a range across machines\n=================================\n\n--shard i/n"));
+        assert!(help.contains("\nOptions:\n  --mode <mode>"));
+        assert!(lookup("sharding ").is_none());
+        assert_eq!(topic_list().lines().count(), TOPICS.len());
+        assert!(topic_list().starts_with("  input        Input files\n"));
+    }
+}
@@ -0,0 +1,477 @@
+//! Just enough HTTP/1.1 over plain TCP to post documents to collectors and
+//! to answer the requests of `serve`, without pulling in a library.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// An `http://host[:port]/path` URL. TLS isn't supported.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpUrl {
+    pub host: String,
+    pub port: u16,
+    /// Starts with `/`.
+    pub path: String,
+}
+
+impl HttpUrl {
+    pub fn parse(url: &str) -> Result<Self> {
+        let rest = match url.strip_prefix("http://") {
+            Some(rest) => rest,
+            None if url.starts_with("https://") => bail!("HTTPS isn't supported: {}", url),
+            None => bail!("Not an http:// URL: {}", url),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| anyhow!("Invalid port in {}", url))?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            bail!("Missing host in {}", url);
+        }
+        Ok(HttpUrl {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+/// Largest request head `Request::read` accepts.
+pub const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+/// A request read by [`Request::read`], one per connection.
+#[derive(Debug, Default)]
+pub struct Request {
+    pub method: String,
+    /// The path of the target, without the query.
+    pub path: String,
+    /// The decoded query parameters, in order.
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Reads a request, refusing heads over [`MAX_HEAD_SIZE`] and bodies over
+    /// `max_body` bytes. Returns `None` if the peer closed the connection
+    /// without sending anything.
+    pub fn read(reader: &mut impl BufRead, max_body: usize) -> Result<Option<Request>> {
+        let mut head_size = 0;
+        let mut read_line = |reader: &mut dyn BufRead| -> Result<String> {
+            let mut line = String::new();
+            let limit = (MAX_HEAD_SIZE - head_size + 1) as u64;
+            reader.take(limit).read_line(&mut line)?;
+            head_size += line.len();
+            if head_size > MAX_HEAD_SIZE {
+                bail!("Request head larger than {} bytes", MAX_HEAD_SIZE);
+            }
+            Ok(line)
+        };
+        let request_line = read_line(reader)?;
+        if request_line.is_empty() {
+            return Ok(None);
+        }
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next()) else {
+            bail!("Invalid request line: {:?}", request_line.trim_end());
+        };
+        if !version.starts_with("HTTP/1.") {
+            bail!("Unsupported HTTP version: {}", version);
+        }
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let mut request = Request {
+            method: method.to_string(),
+            path: percent_decode(path)?,
+            query: parse_query(query)?,
+            ..Default::default()
+        };
+        loop {
+            let line = read_line(reader)?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').ok_or_else(|| anyhow!("Invalid header: {:?}", line))?;
+            request.headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+        if request.header("transfer-encoding").is_some_and(|coding| !coding.eq_ignore_ascii_case("identity")) {
+            bail!("Chunked request bodies aren't supported; send a Content-Length");
+        }
+        if let Some(length) = request.header("content-length") {
+            let length: usize = length.parse().map_err(|_| anyhow!("Invalid Content-Length: {}", length))?;
+            if length > max_body {
+                bail!("Request body larger than {} bytes", max_body);
+            }
+            request.body.resize(length, 0);
+            reader.read_exact(&mut request.body)?;
+        }
+        Ok(Some(request))
+    }
+
+    /// The value of the header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let mut headers = self.headers.iter();
+        headers.find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Splits `a=1&b=2` into its decoded pairs; `+` stands for a space.
+pub fn parse_query(query: &str) -> Result<Vec<(String, String)>> {
+    let pairs = query.split('&').filter(|pair| !pair.is_empty());
+    pairs
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(&name.replace('+', " "))?, percent_decode(&value.replace('+', " "))?))
+        })
+        .collect()
+}
+
+/// Decodes the `%XX` escapes of a URL component.
+pub fn percent_decode(text: &str) -> Result<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+            let byte = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            decoded.push(byte.ok_or_else(|| anyhow!("Invalid escape in {:?}", text))?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| anyhow!("Invalid UTF-8 in {:?}", text))
+}
+
+/// One part of a `multipart/form-data` body.
+#[derive(Debug, Default)]
+pub struct Part {
+    pub name: Option<String>,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// The boundary of a `multipart/form-data` content type.
+pub fn multipart_boundary(content_type: &str) -> Option<String> {
+    let mut fields = content_type.split(';').map(str::trim);
+    if !fields.next()?.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    let boundary = fields.find_map(|field| field.strip_prefix("boundary="))?;
+    Some(boundary.trim_matches('"').to_string())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Splits a `multipart/form-data` body into its parts.
+pub fn multipart_parts(body: &[u8], boundary: &str) -> Result<Vec<Part>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let start = find(body, &delimiter).ok_or_else(|| anyhow!("Missing multipart boundary"))?;
+    let mut rest = &body[start + delimiter.len()..];
+    let mut parts = Vec::new();
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        rest = rest.strip_prefix(b"\r\n").ok_or_else(|| anyhow!("Invalid multipart body"))?;
+        let head_end = find(rest, b"\r\n\r\n").ok_or_else(|| anyhow!("Invalid multipart part headers"))?;
+        let head = std::str::from_utf8(&rest[..head_end]).map_err(|_| anyhow!("Invalid multipart part headers"))?;
+        let mut part = Part::default();
+        for header in head.split("\r\n") {
+            let Some((name, value)) = header.split_once(':') else {
+                continue;
+            };
+            if name.trim().eq_ignore_ascii_case("content-type") {
+                part.content_type = Some(value.trim().to_string());
+            } else if name.trim().eq_ignore_ascii_case("content-disposition") {
+                for field in value.split(';').map(str::trim) {
+                    let unquote = |value: &str| value.trim_matches('"').to_string();
+                    if let Some(filename) = field.strip_prefix("filename=") {
+                        part.filename = Some(unquote(filename));
+                    } else if let Some(name) = field.strip_prefix("name=") {
+                        part.name = Some(unquote(name));
+                    }
+                }
+            }
+        }
+        rest = &rest[head_end + 4..];
+        let mut end_delimiter = b"\r\n".to_vec();
+        end_delimiter.extend_from_slice(&delimiter);
+        let end = find(rest, &end_delimiter).ok_or_else(|| anyhow!("Unterminated multipart part"))?;
+        part.data = rest[..end].to_vec();
+        parts.push(part);
+        rest = &rest[end + end_delimiter.len()..];
+    }
+}
+
+/// A response to [`post`], or one to send with [`Response::write_to`].
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        Response {
+            status,
+            headers: vec![(String::from("Content-Type"), content_type.to_string())],
+            body: body.into(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: impl ToString) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// The value of the header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let mut headers = self.headers.iter();
+        headers.find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    /// Writes the response, closing the connection after it.
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "HTTP/1.1 {} {}\r\n", self.status, reason(self.status))?;
+        for (name, value) in &self.headers {
+            write!(out, "{}: {}\r\n", name, value)?;
+        }
+        write!(out, "Content-Length: {}\r\nConnection: close\r\n\r\n", self.body.len())?;
+        out.write_all(&self.body)?;
+        out.flush()
+    }
+}
+
+/// The part of a body a `Range` header asks for.
+#[derive(Debug, PartialEq)]
+pub enum ByteRange {
+    /// The whole body: no range, or one this server doesn't serve in parts,
+    /// such as a list of several ranges.
+    Whole,
+    /// The bytes `start..=end`.
+    Part(usize, usize),
+    Unsatisfiable,
+}
+
+/// The range `header` selects of a body of `len` bytes.
+pub fn byte_range(header: &str, len: usize) -> ByteRange {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return ByteRange::Whole;
+    };
+    let Some((start, end)) = spec.trim().split_once('-').filter(|_| !spec.contains(',')) else {
+        return ByteRange::Whole;
+    };
+    let parse = |text: &str| text.trim().parse::<usize>().ok();
+    let (start, end) = match (start.trim().is_empty(), parse(start), end.trim().is_empty(), parse(end)) {
+        // `bytes=-n`: the last n bytes.
+        (true, _, false, Some(suffix)) if suffix > 0 => (len.saturating_sub(suffix), len.saturating_sub(1)),
+        (true, _, false, Some(_)) => return ByteRange::Unsatisfiable,
+        (false, Some(start), true, _) => (start, len.saturating_sub(1)),
+        (false, Some(start), false, Some(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+        _ => return ByteRange::Whole,
+    };
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Part(start, end)
+}
+
+/// The reason phrase of the status codes `serve` answers with.
+pub fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        206 => "Partial Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Content Too Large",
+        416 => "Range Not Satisfiable",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+/// Posts `body` to `url` and reads the whole response.
+pub fn post(url: &HttpUrl, content_type: &str, body: &[u8], timeout: Duration) -> Result<Response> {
+    send("POST", url, &[("Content-Type", content_type)], body, timeout)
+}
+
+/// Sends a request without a body to `url` and reads the whole response.
+pub fn get(url: &HttpUrl, headers: &[(&str, &str)], timeout: Duration) -> Result<Response> {
+    send("GET", url, headers, &[], timeout)
+}
+
+/// Sends a request with `headers` and `body`, which is only announced for
+/// methods that take one, and reads the whole response.
+pub fn send(method: &str, url: &HttpUrl, headers: &[(&str, &str)], body: &[u8], timeout: Duration) -> Result<Response> {
+    let address = format!("{}:{}", url.host, url.port);
+    let mut stream = TcpStream::connect(&address).with_context(|| format!("Failed to connect to {}", address))?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", method, url.path, url.host);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if !body.is_empty() || matches!(method, "POST" | "PUT" | "PATCH") {
+        head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(stream);
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| anyhow!("Invalid HTTP response from {}: {:?}", address, status_line.trim_end()))?;
+    let mut headers = Vec::new();
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    let length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok());
+    let mut body = Vec::new();
+    match length {
+        Some(length) => {
+            body.resize(length, 0);
+            reader.read_exact(&mut body)?;
+        }
+        None => {
+            reader.read_to_end(&mut body)?;
+        }
+    }
+    Ok(Response { status, headers, body })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{byte_range, multipart_boundary, multipart_parts, parse_query, post, ByteRange, HttpUrl, Request, Response};
+
+    #[test]
+    fn test_parse_url() {
+        let url = HttpUrl::parse("http://collector:4318/v1/traces").unwrap();
+        assert_eq!((url.host.as_str(), url.port, url.path.as_str()), ("collector", 4318, "/v1/traces"));
+        let url = HttpUrl::parse("http://localhost").unwrap();
+        assert_eq!((url.port, url.path.as_str()), (80, "/"));
+        assert!(HttpUrl::parse("https://localhost/").is_err());
+        assert!(HttpUrl::parse("http://:80/").is_err());
+        assert!(HttpUrl::parse("http://localhost:http/").is_err());
+    }
+
+    #[test]
+    fn test_post() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = HttpUrl::parse(&format!("http://{}/collect", listener.local_addr().unwrap())).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.strip_prefix("Content-Length: ") {
+                    length = value.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            (&stream).write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 2\r\n\r\n{}").unwrap();
+            (request, body)
+        });
+        let response = post(&url, "application/json", b"[1,2]", Duration::from_secs(5)).unwrap();
+        let (request, body) = server.join().unwrap();
+        assert!(request.starts_with("POST /collect HTTP/1.1\r\n"));
+        assert!(request.contains("Content-Type: application/json\r\n"));
+        assert_eq!(body, b"[1,2]");
+        assert_eq!((response.status, response.body.as_slice()), (202, &b"{}"[..]));
+        assert_eq!(response.header("content-length"), Some("2"));
+    }
+
+    #[test]
+    fn test_byte_range() {
+        assert_eq!(byte_range("bytes=0-9", 100), ByteRange::Part(0, 9));
+        assert_eq!(byte_range("bytes=90-", 100), ByteRange::Part(90, 99));
+        assert_eq!(byte_range("bytes=-10", 100), ByteRange::Part(90, 99));
+        assert_eq!(byte_range("bytes=-500", 100), ByteRange::Part(0, 99));
+        assert_eq!(byte_range("bytes=50-500", 100), ByteRange::Part(50, 99));
+        assert_eq!(byte_range("bytes=100-", 100), ByteRange::Unsatisfiable);
+        assert_eq!(byte_range("bytes=-0", 100), ByteRange::Unsatisfiable);
+        assert_eq!(byte_range("bytes=0-1,5-6", 100), ByteRange::Whole);
+        assert_eq!(byte_range("bytes=9-0", 100), ByteRange::Whole);
+        assert_eq!(byte_range("items=0-9", 100), ByteRange::Whole);
+    }
+
+    #[test]
+    fn test_multipart_parts() {
+        let boundary = multipart_boundary("multipart/form-data; boundary=\"xyz\"").unwrap();
+        assert_eq!(boundary, "xyz");
+        assert!(multipart_boundary("text/plain").is_none());
+        let body = "--xyz\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhi\r\n\
+                    --xyz\r\nContent-Disposition: form-data; name=\"input\"; filename=\"jobs.csv\"\r\n\
+                    Content-Type: text/csv\r\n\r\na,b,end\r\n2,3,10\r\n--xyz--\r\n";
+        let parts = multipart_parts(body.as_bytes(), &boundary).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!((parts[0].name.as_deref(), parts[0].data.as_slice()), (Some("note"), &b"hi"[..]));
+        assert_eq!((parts[1].filename.as_deref(), parts[1].content_type.as_deref()), (Some("jobs.csv"), Some("text/csv")));
+        assert_eq!(parts[1].data, b"a,b,end\r\n2,3,10");
+        assert!(multipart_parts(b"--xyz\r\n\r\nunterminated", "xyz").is_err());
+    }
+
+    #[test]
+    fn test_read_request_and_write_response() {
+        let raw = "POST /multiples?a=2&b=3&note=a%20b+c HTTP/1.1\r\nHost: x\r\nContent-Length: 4\r\n\r\nbody";
+        let request = Request::read(&mut raw.as_bytes(), 16).unwrap().unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/multiples"));
+        assert_eq!(request.query[2], (String::from("note"), String::from("a b c")));
+        assert_eq!((request.header("HOST"), request.body.as_slice()), (Some("x"), &b"body"[..]));
+        assert!(Request::read(&mut raw.as_bytes(), 3).is_err());
+        assert!(Request::read(&mut &b""[..], 16).unwrap().is_none());
+        let huge = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "x".repeat(super::MAX_HEAD_SIZE));
+        assert!(Request::read(&mut huge.as_bytes(), 16).is_err());
+        assert!(parse_query("a=%zz").is_err());
+
+        let mut out = Vec::new();
+        Response::new(429, "text/plain", "slow down").with_header("Retry-After", 2).write_to(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 429 Too Many Requests\r\nContent-Type: text/plain\r\nRetry-After: 2\r\n"));
+        assert!(out.ends_with("Content-Length: 9\r\nConnection: close\r\n\r\nslow down"));
+    }
+}
@@ -0,0 +1,870 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::errors::{INVALID_LINE_OPTION, MALFORMED_LINE, UNKNOWN_NAME};
+use crate::json::{self, Value};
+use crate::locale::{tr, tr_args};
+use crate::number;
+use crate::LineNumbers;
+
+pub type JobIter<'a> = Box<dyn Iterator<Item = Result<LineNumbers>> + Send + 'a>;
+
+/// Jobs together with the options their lines give.
+pub type RoutedJobIter<'a> = Box<dyn Iterator<Item = Result<(LineNumbers, LineOptions)>> + Send + 'a>;
+
+/// How urgently a line should be generated, given as `prio=high` on a text
+/// line, or a `priority` field.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl FromStr for Priority {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            _ => Err(INVALID_LINE_OPTION.error(tr_args("Unknown priority: {} (expected low, normal or high)", &[&s]))),
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        })
+    }
+}
+
+/// Whether the `end` of a line is one of its numbers, or the first number
+/// past them as in "the multiples below 1000"; given as `bound=exclusive` on
+/// a text line, or a `bound` field.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Bound {
+    #[default]
+    Inclusive,
+    Exclusive,
+}
+
+impl Bound {
+    /// `job` with the inclusive end it stands for. Records carry that end,
+    /// so enumerating, counting and summing all see the same range.
+    pub fn resolve(self, job: LineNumbers) -> LineNumbers {
+        match self {
+            Bound::Inclusive => job,
+            Bound::Exclusive => LineNumbers {
+                end: job.end.saturating_sub(1),
+                ..job
+            },
+        }
+    }
+}
+
+impl FromStr for Bound {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "inclusive" => Ok(Bound::Inclusive),
+            "exclusive" => Ok(Bound::Exclusive),
+            _ => Err(INVALID_LINE_OPTION.error(tr_args("Unknown bound: {} (expected inclusive or exclusive)", &[&s]))),
+        }
+    }
+}
+
+/// What a line asks for besides its job.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LineOptions {
+    /// The output the line's record goes to instead of the main one.
+    pub output: Option<PathBuf>,
+    pub priority: Priority,
+    /// The bound of the line's `end`, when it overrides that of the run.
+    pub bound: Option<Bound>,
+    /// The tags `--only-tag` and `--skip-tag` pick the line by, given as
+    /// `tag=<name>`, once per tag, or a `tags` array.
+    pub tags: Vec<String>,
+}
+
+/// Turns an input stream into job records.
+pub trait InputParser: Sync {
+    fn parse<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> JobIter<'a>;
+
+    /// Like [`InputParser::parse`], also returning the options of each
+    /// line, such as the output it names. Formats without a way to give
+    /// any return the defaults.
+    fn parse_routed<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> RoutedJobIter<'a> {
+        Box::new(self.parse(reader).map(|job| job.map(|job| (job, LineOptions::default()))))
+    }
+
+    /// Whether the format is the text lines a [`FastReader`] parses the
+    /// same way, so that an input can be split at line boundaries and its
+    /// parts parsed at once, see [`ParallelReader`].
+    fn splits_at_lines(&self) -> bool {
+        false
+    }
+}
+
+/// Drops the options of routed jobs, applying the bounds they give.
+fn unrouted(jobs: RoutedJobIter<'_>) -> JobIter<'_> {
+    Box::new(jobs.map(|job| job.map(|(job, options)| options.bound.unwrap_or_default().resolve(job))))
+}
+
+/// The output named by `text`, which must not be blank.
+fn route(text: &str, line_num: usize) -> Result<PathBuf> {
+    match text.trim() {
+        "" => Err(INVALID_LINE_OPTION.error(tr_args("Line {} names an empty output", &[&line_num]))),
+        path => Ok(PathBuf::from(path)),
+    }
+}
+
+/// Whether `token` gives a line option, `prio=<priority>`,
+/// `bound=<bound>` or `tag=<name>`.
+fn is_option_token(token: &str) -> bool {
+    token.starts_with("prio=") || token.starts_with("bound=") || token.starts_with("tag=")
+}
+
+/// Takes the option `token` gives into `options`. Returns whether it gives
+/// one, see [`is_option_token`].
+fn option_token(token: &str, line_num: usize, options: &mut LineOptions) -> Result<bool> {
+    if let Some(priority) = token.strip_prefix("prio=") {
+        options.priority = priority.parse().with_context(|| format!("Line {}", line_num))?;
+    } else if let Some(bound) = token.strip_prefix("bound=") {
+        options.bound = Some(bound.parse().with_context(|| format!("Line {}", line_num))?);
+    } else if let Some(tag) = token.strip_prefix("tag=") {
+        add_tag(&mut options.tags, tag, line_num)?;
+    } else {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+fn add_tag(tags: &mut Vec<String>, tag: &str, line_num: usize) -> Result<()> {
+    if tag.is_empty() {
+        return Err(INVALID_LINE_OPTION.error(tr_args("Line {} has an empty tag", &[&line_num])));
+    }
+    if !tags.iter().any(|known| known == tag) {
+        tags.push(tag.to_string());
+    }
+    Ok(())
+}
+
+/// Input parsers keyed by the name used with `--input-format`, plus the file
+/// extensions each of them is picked for automatically.
+pub struct InputParserRegistry {
+    parsers: BTreeMap<String, Box<dyn InputParser>>,
+    extensions: BTreeMap<String, String>,
+}
+
+impl InputParserRegistry {
+    pub fn new() -> Self {
+        InputParserRegistry {
+            parsers: BTreeMap::new(),
+            extensions: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a registry containing all built-in parsers.
+    pub fn with_builtins() -> Self {
+        let mut registry = InputParserRegistry::new();
+        registry.register("text", &["txt"], TextParser);
+        registry.register("csv", &["csv"], CsvParser);
+        registry.register("json", &["json"], JsonParser);
+        registry.register("ndjson", &["ndjson", "jsonl"], NdjsonParser);
+        registry
+    }
+
+    pub fn register<P>(&mut self, name: &str, extensions: &[&str], parser: P)
+    where
+        P: InputParser + 'static,
+    {
+        self.parsers.insert(name.to_string(), Box::new(parser));
+        for extension in extensions {
+            self.extensions.insert(extension.to_string(), name.to_string());
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<&dyn InputParser> {
+        self.parsers.get(name).map(|parser| parser.as_ref()).ok_or_else(|| {
+            UNKNOWN_NAME.error(format!(
+                "Unknown input format: {} (available: {})",
+                name,
+                self.names().collect::<Vec<_>>().join(", ")
+            ))
+        })
+    }
+
+    /// Picks the parser for `path`: the explicitly requested `format`, or the
+    /// one registered for its extension, falling back to `text`.
+    pub fn select(&self, path: &Path, format: Option<&str>) -> Result<&dyn InputParser> {
+        self.get(format.or_else(|| self.format_for(path)).unwrap_or("text"))
+    }
+
+    /// The format registered for the extension of `path`, if any.
+    pub fn format_for(&self, path: &Path) -> Option<&str> {
+        let extension = path.extension()?.to_str()?;
+        self.extensions.get(&extension.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.parsers.keys().map(String::as_str)
+    }
+}
+
+impl Default for InputParserRegistry {
+    fn default() -> Self {
+        InputParserRegistry::with_builtins()
+    }
+}
+
+fn job_from_numbers(numbers: &[u32], line_num: usize) -> Result<LineNumbers> {
+    if numbers.len() != 3 {
+        return Err(MALFORMED_LINE.error(tr_args("Line {} does not contain exactly 3 numbers", &[&line_num])));
+    }
+    Ok(LineNumbers {
+        a: numbers[0],
+        b: numbers[1],
+        end: numbers[2],
+    })
+}
+
+/// Whitespace separated `a b end` lines, optionally followed by a
+/// `prio=<priority>` token and by `> path` to write the line's record to
+/// `path`.
+pub struct TextParser;
+
+impl InputParser for TextParser {
+    fn parse<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> JobIter<'a> {
+        unrouted(self.parse_routed(reader))
+    }
+
+    fn parse_routed<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> RoutedJobIter<'a> {
+        Box::new(reader.lines().enumerate().map(|(line_num, line)| {
+            let line = line.with_context(|| tr_args("Failed to read line {}", &[&(line_num + 1)]))?;
+            parse_text_line(&line, line_num + 1, &mut Vec::new())
+        }))
+    }
+
+    fn splits_at_lines(&self) -> bool {
+        true
+    }
+}
+
+/// Parses a line of [`TextParser`] input, collecting its numbers in `numbers`.
+fn parse_text_line(line: &str, line_num: usize, numbers: &mut Vec<u32>) -> Result<(LineNumbers, LineOptions)> {
+    let (job, output) = match line.split_once('>') {
+        Some((job, output)) => (job, Some(route(output, line_num)?)),
+        None => (line, None),
+    };
+    let mut options = LineOptions { output, ..Default::default() };
+    numbers.clear();
+    for token in job.split_whitespace() {
+        if !option_token(token, line_num, &mut options)? {
+            numbers.extend(number_token(token, line_num)?);
+        }
+    }
+    Ok((job_from_numbers(numbers, line_num)?, options))
+}
+
+/// The number `token` of a text, CSV or JSON line stands for, or `None` for
+/// a word, which isn't counted. A literal that doesn't fit into 32 bits, such as
+/// `2^40`, fails the line rather than leaving it a number short.
+fn number_token(token: &str, line_num: usize) -> Result<Option<u32>> {
+    if let Ok(n) = number::parse_u32(token) {
+        return Ok(Some(n));
+    }
+    let parts: Vec<&str> = token.split('^').collect();
+    if parts.len() > 2 || parts.iter().any(|part| number::parse_number(part).is_err()) {
+        return Ok(None);
+    }
+    Err(MALFORMED_LINE.error(tr_args("Line {} has a number too large for 32 bits: {}", &[&line_num, &token])))
+}
+
+/// [`TextParser`] input read with a [`FastReader`], for `--fast-parse`.
+pub struct FastTextParser;
+
+impl InputParser for FastTextParser {
+    fn parse<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> JobIter<'a> {
+        unrouted(self.parse_routed(reader))
+    }
+
+    fn parse_routed<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> RoutedJobIter<'a> {
+        Box::new(FastReader::new(reader))
+    }
+
+    fn splits_at_lines(&self) -> bool {
+        true
+    }
+}
+
+/// Reads [`TextParser`] input into one buffer reused for every line, and
+/// tokenizes ASCII lines as bytes, parsing plain decimal numbers directly.
+/// Other numbers and lines with non-ASCII bytes take the way of
+/// [`TextParser`], so the jobs and errors are the same.
+pub struct FastReader<R> {
+    reader: R,
+    line: Vec<u8>,
+    numbers: Vec<u32>,
+    line_num: usize,
+}
+
+impl<R: BufRead> FastReader<R> {
+    pub fn new(reader: R) -> Self {
+        FastReader {
+            reader,
+            line: Vec::new(),
+            numbers: Vec::new(),
+            line_num: 0,
+        }
+    }
+
+    /// Numbers the lines read as coming after the first `lines` lines of
+    /// the input, for reading a part of it.
+    pub fn numbered_from(mut self, lines: usize) -> Self {
+        self.line_num = lines;
+        self
+    }
+
+    fn parse_line(&mut self) -> Result<(LineNumbers, LineOptions)> {
+        let line_num = self.line_num;
+        let mut line = self.line.as_slice();
+        // The same line endings as `BufRead::lines`.
+        if let Some(rest) = line.strip_suffix(b"\n") {
+            line = rest.strip_suffix(b"\r").unwrap_or(rest);
+        }
+        if !line.is_ascii() {
+            let line = std::str::from_utf8(line)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"))
+                .with_context(|| tr_args("Failed to read line {}", &[&line_num]))?;
+            return parse_text_line(line, line_num, &mut self.numbers);
+        }
+        // ASCII is valid UTF-8, so none of these conversions fail.
+        let text = |bytes| std::str::from_utf8(bytes).unwrap_or_default();
+        let (job, output) = match line.iter().position(|&byte| byte == b'>') {
+            Some(at) => (&line[..at], Some(route(text(&line[at + 1..]), line_num)?)),
+            None => (line, None),
+        };
+        let mut options = LineOptions { output, ..Default::default() };
+        self.numbers.clear();
+        // The ASCII characters `str::split_whitespace` splits at.
+        let tokens = job.split(|byte| matches!(byte, b' ' | b'\t' | b'\n' | b'\x0b' | b'\x0c' | b'\r'));
+        for token in tokens.filter(|token| !token.is_empty()) {
+            match parse_digits(token) {
+                Some(n) => self.numbers.push(n),
+                None if option_token(text(token), line_num, &mut options)? => {}
+                None => self.numbers.extend(number_token(text(token), line_num)?),
+            }
+        }
+        Ok((job_from_numbers(&self.numbers, line_num)?, options))
+    }
+}
+
+/// Bytes of input each thread of a [`ParallelReader`] parses at a time.
+const PART_SIZE: usize = 4 << 20;
+
+/// Parses text input held in memory, such as a mapped file, on several
+/// threads. The input is taken a window of one part per thread at a time,
+/// each part ending at a line boundary; the lines of the parts are counted,
+/// and then the parts parsed at once with [`FastReader`]s numbering their
+/// lines from there. Jobs come out in input order.
+pub struct ParallelReader<T> {
+    data: T,
+    offset: usize,
+    lines: usize,
+    threads: usize,
+    part_size: usize,
+    parsed: std::vec::IntoIter<Result<(LineNumbers, LineOptions)>>,
+}
+
+impl<T: AsRef<[u8]>> ParallelReader<T> {
+    pub fn new(data: T, threads: usize) -> Self {
+        ParallelReader {
+            data,
+            offset: 0,
+            lines: 0,
+            threads: threads.max(1),
+            part_size: PART_SIZE,
+            parsed: Vec::new().into_iter(),
+        }
+    }
+
+    /// Parses the next window of the input.
+    fn parse_window(&mut self) {
+        let data = self.data.as_ref();
+        let mut parts = Vec::new();
+        while parts.len() < self.threads && self.offset < data.len() {
+            let from = (self.offset + self.part_size).min(data.len());
+            let end = data[from..].iter().position(|&byte| byte == b'\n').map_or(data.len(), |at| from + at + 1);
+            parts.push(&data[self.offset..end]);
+            self.offset = end;
+        }
+        let counts = on_threads(&parts, |part| part.iter().filter(|&&byte| byte == b'\n').count());
+        let mut firsts = Vec::with_capacity(parts.len());
+        for count in counts {
+            firsts.push(self.lines);
+            self.lines += count;
+        }
+        let parts: Vec<_> = parts.into_iter().zip(firsts).collect();
+        let parsed = on_threads(&parts, |&(part, first)| FastReader::new(part).numbered_from(first).collect::<Vec<_>>());
+        self.parsed = parsed.into_iter().flatten().collect::<Vec<_>>().into_iter();
+    }
+}
+
+/// The results of `f` for each of `items`, each on a thread of its own.
+fn on_threads<I: Sync, R: Send>(items: &[I], f: impl Fn(&I) -> R + Sync) -> Vec<R> {
+    if items.len() < 2 {
+        return items.iter().map(f).collect();
+    }
+    thread::scope(|scope| {
+        let f = &f;
+        let handles: Vec<_> = items.iter().map(|item| scope.spawn(move || f(item))).collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+impl<T: AsRef<[u8]>> Iterator for ParallelReader<T> {
+    type Item = Result<(LineNumbers, LineOptions)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(job) = self.parsed.next() {
+                return Some(job);
+            }
+            if self.offset >= self.data.as_ref().len() {
+                return None;
+            }
+            self.parse_window();
+        }
+    }
+}
+
+/// The value of a token of at most 9 decimal digits, which always fits
+/// into a `u32`, or `None` for any other token.
+fn parse_digits(token: &[u8]) -> Option<u32> {
+    if token.len() > 9 || !token.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(token.iter().fold(0, |n, &digit| n * 10 + (digit - b'0') as u32))
+}
+
+impl<R: BufRead> Iterator for FastReader<R> {
+    type Item = Result<(LineNumbers, LineOptions)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.line.clear();
+        self.line_num += 1;
+        match self.reader.read_until(b'\n', &mut self.line) {
+            Ok(0) => None,
+            Ok(_) => Some(self.parse_line()),
+            Err(err) => Some(Err(anyhow!(err).context(tr_args("Failed to read line {}", &[&self.line_num])))),
+        }
+    }
+}
+
+/// Comma separated `a,b,end` rows with an optional `a,b,end` header row.
+/// A fourth field names the output of the row, see [`TextParser`], and a
+/// `prio=<priority>` field after the numbers gives its priority.
+pub struct CsvParser;
+
+impl InputParser for CsvParser {
+    fn parse<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> JobIter<'a> {
+        unrouted(self.parse_routed(reader))
+    }
+
+    fn parse_routed<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> RoutedJobIter<'a> {
+        Box::new(
+            reader
+                .lines()
+                .enumerate()
+                .filter_map(|(line_num, line)| {
+                    let line = match line {
+                        Ok(line) => line,
+                        Err(err) => {
+                            return Some(Err(anyhow!(err).context(tr_args("Failed to read line {}", &[&(line_num + 1)]))))
+                        }
+                    };
+                    let mut fields: Vec<&str> = line.split(',').map(str::trim).collect();
+                    if line_num == 0 && is_csv_header(&fields) {
+                        return None;
+                    }
+                    let mut options = LineOptions::default();
+                    let extra = fields.split_off(fields.len().min(3));
+                    let (tokens, rest): (Vec<&str>, Vec<&str>) = extra.into_iter().partition(|field| is_option_token(field));
+                    for token in tokens {
+                        if let Err(err) = option_token(token, line_num + 1, &mut options) {
+                            return Some(Err(err));
+                        }
+                    }
+                    options.output = match rest.first().copied() {
+                        Some("") => None,
+                        // A number too large for 32 bits is left to fail the line below.
+                        Some(output) if matches!(number_token(output, line_num + 1), Ok(None)) => {
+                            Some(PathBuf::from(output))
+                        }
+                        _ => None,
+                    };
+                    if options.output.is_none() {
+                        fields.extend(rest);
+                    }
+                    let numbers = fields.iter().filter_map(|n| number_token(n, line_num + 1).transpose());
+                    let numbers = numbers.collect::<Result<Vec<u32>>>();
+                    Some(numbers.and_then(|numbers| job_from_numbers(&numbers, line_num + 1)).map(|job| (job, options)))
+                }),
+        )
+    }
+}
+
+/// Whether CSV fields are a header row.
+fn is_csv_header(fields: &[&str]) -> bool {
+    matches!(fields, ["a", "b", "end"] | ["a", "b", "end", "output"] | ["a", "b", "end", "output", "priority"])
+}
+
+/// The job of `value` and the options of its `output`, `priority`,
+/// `bound` and `tags` fields.
+fn routed_job_from_json(value: &Value, line_num: usize) -> Result<(LineNumbers, LineOptions)> {
+    let output = match value.get("output") {
+        None => None,
+        Some(Value::String(text)) => Some(route(text, line_num)?),
+        Some(_) => return Err(INVALID_LINE_OPTION.error(tr_args("Line {} has an output that isn't a string", &[&line_num]))),
+    };
+    let priority = match value.get("priority") {
+        None => Priority::default(),
+        Some(Value::String(text)) => text.parse().with_context(|| format!("Line {}", line_num))?,
+        Some(_) => return Err(INVALID_LINE_OPTION.error(tr_args("Line {} has a priority that isn't a string", &[&line_num]))),
+    };
+    let bound = match value.get("bound") {
+        None => None,
+        Some(Value::String(text)) => Some(text.parse().with_context(|| format!("Line {}", line_num))?),
+        Some(_) => return Err(INVALID_LINE_OPTION.error(tr_args("Line {} has a bound that isn't a string", &[&line_num]))),
+    };
+    let mut tags = Vec::new();
+    match value.get("tags") {
+        None => {}
+        Some(Value::Array(items)) => {
+            for item in items {
+                match item {
+                    Value::String(tag) => add_tag(&mut tags, tag, line_num)?,
+                    _ => return Err(INVALID_LINE_OPTION.error(tr_args("Line {} has a tag that isn't a string", &[&line_num]))),
+                }
+            }
+        }
+        Some(_) => return Err(INVALID_LINE_OPTION.error(tr_args("Line {} has tags that aren't an array", &[&line_num]))),
+    }
+    let options = LineOptions { output, priority, bound, tags };
+    Ok((job_from_json(value, line_num)?, options))
+}
+
+fn job_from_json(value: &Value, line_num: usize) -> Result<LineNumbers> {
+    let fields: Vec<Option<&Value>> = match value {
+        Value::Array(items) => items.iter().map(Some).collect(),
+        Value::Object(_) => vec![value.get("a"), value.get("b"), value.get("end")],
+        _ => return Err(MALFORMED_LINE.error(tr_args("Line {} is neither an object nor an array", &[&line_num]))),
+    };
+    let numbers = fields
+        .into_iter()
+        .flatten()
+        .filter_map(|value| value.as_text().and_then(|text| number_token(text, line_num).transpose()))
+        .collect::<Result<Vec<u32>>>()?;
+    job_from_numbers(&numbers, line_num)
+}
+
+/// A JSON array of `{"a": .., "b": .., "end": ..}` objects or `[a, b, end]`
+/// arrays. Lines are numbered by their position in the array. Objects may
+/// name their output in an `output` field and give a `priority`.
+pub struct JsonParser;
+
+impl InputParser for JsonParser {
+    fn parse<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> JobIter<'a> {
+        unrouted(self.parse_routed(reader))
+    }
+
+    fn parse_routed<'a>(&self, mut reader: Box<dyn BufRead + Send + 'a>) -> RoutedJobIter<'a> {
+        let mut text = String::new();
+        if let Err(err) = reader.read_to_string(&mut text) {
+            return Box::new(std::iter::once(Err(anyhow!(err).context("Failed to read JSON input"))));
+        }
+        let items = match json::parse(&text).context("Invalid JSON input") {
+            Ok(Value::Array(items)) => items,
+            Ok(_) => return Box::new(std::iter::once(Err(MALFORMED_LINE.error(tr("JSON input must be an array"))))),
+            Err(err) => return Box::new(std::iter::once(Err(err))),
+        };
+        Box::new(
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(index, item)| routed_job_from_json(&item, index + 1)),
+        )
+    }
+}
+
+/// One JSON object or array per line, see [`JsonParser`].
+pub struct NdjsonParser;
+
+impl InputParser for NdjsonParser {
+    fn parse<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> JobIter<'a> {
+        unrouted(self.parse_routed(reader))
+    }
+
+    fn parse_routed<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> RoutedJobIter<'a> {
+        Box::new(reader.lines().enumerate().map(|(line_num, line)| {
+            let line = line.with_context(|| tr_args("Failed to read line {}", &[&(line_num + 1)]))?;
+            let value = json::parse(&line).with_context(|| format!("Line {} is not valid JSON", line_num + 1))?;
+            routed_job_from_json(&value, line_num + 1)
+        }))
+    }
+}
+
+/// Turns field `i` (0 for `a`, 1 for `b`, 2 for `end`) of a line into its
+/// number, for inputs whose fields aren't plain integers.
+pub type FieldFn = Arc<dyn Fn(usize, &str) -> Result<u32> + Send + Sync>;
+
+/// The `text` and `csv` layouts with every field read by a [`FieldFn`],
+/// including the outputs and priorities lines may give.
+pub struct FieldParser {
+    field: FieldFn,
+    csv: bool,
+}
+
+/// A registry of the [`FieldParser`]s reading fields with `field`.
+pub fn field_parsers(field: FieldFn) -> InputParserRegistry {
+    let mut registry = InputParserRegistry::new();
+    registry.register("text", &["txt"], FieldParser { field: field.clone(), csv: false });
+    registry.register("csv", &["csv"], FieldParser { field, csv: true });
+    registry
+}
+
+impl InputParser for FieldParser {
+    fn parse<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> JobIter<'a> {
+        unrouted(self.parse_routed(reader))
+    }
+
+    fn parse_routed<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> RoutedJobIter<'a> {
+        let (field, csv) = (self.field.clone(), self.csv);
+        Box::new(reader.lines().enumerate().filter_map(move |(line_num, line)| {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => return Some(Err(anyhow!(err).context(tr_args("Failed to read line {}", &[&(line_num + 1)])))),
+            };
+            let (fields, output): (Vec<&str>, _) = if csv {
+                let mut fields: Vec<&str> = line.split(',').map(str::trim).collect();
+                if line_num == 0 && is_csv_header(&fields) {
+                    return None;
+                }
+                let extra = fields.split_off(fields.len().min(3));
+                let (tokens, rest): (Vec<&str>, Vec<&str>) = extra.into_iter().partition(|field| is_option_token(field));
+                let output = rest.first().filter(|output| !output.is_empty()).map(PathBuf::from);
+                fields.extend(tokens);
+                (fields, output)
+            } else {
+                match line.split_once('>') {
+                    Some((job, output)) => match route(output, line_num + 1) {
+                        Ok(output) => (job.split_whitespace().collect(), Some(output)),
+                        Err(err) => return Some(Err(err)),
+                    },
+                    None => (line.split_whitespace().collect(), None),
+                }
+            };
+            let mut options = LineOptions { output, ..Default::default() };
+            let mut values = Vec::new();
+            for token in fields {
+                match option_token(token, line_num + 1, &mut options) {
+                    Ok(true) => {}
+                    Ok(false) => values.push(token),
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+            let numbers: Result<Vec<u32>> = values.iter().enumerate().map(|(i, text)| field(i, text)).collect();
+            Some(
+                numbers
+                    .with_context(|| format!("Line {} holds an invalid value", line_num + 1))
+                    .and_then(|numbers| job_from_numbers(&numbers, line_num + 1))
+                    .map(|job| (job, options)),
+            )
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::path::{Path, PathBuf};
+
+    use anyhow::Result;
+
+    use super::{
+        Bound, FastReader, FastTextParser, InputParser, InputParserRegistry, LineOptions, ParallelReader, Priority, TextParser,
+    };
+    use crate::LineNumbers;
+
+    fn parse(format: &str, text: &'static str) -> Result<Vec<LineNumbers>> {
+        InputParserRegistry::with_builtins()
+            .get(format)
+            .unwrap()
+            .parse(Box::new(Cursor::new(text)))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let jobs = parse("csv", "a,b,end\n2, 3, 10\n4,5,1k\n").unwrap();
+        assert_eq!(jobs, vec![LineNumbers { a: 2, b: 3, end: 10 }, LineNumbers { a: 4, b: 5, end: 1000 }]);
+        assert!(parse("csv", "2,3\n").is_err());
+        for line in ["2,3,2^40,10\n", "2,3,10,2^40\n"] {
+            assert_eq!(parse("csv", line).unwrap_err().to_string(), "Line 1 has a number too large for 32 bits: 2^40");
+        }
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let jobs = parse("json", r#"[{"a": 2, "b": 3, "end": 10}, [4, 5, "1k"]]"#).unwrap();
+        assert_eq!(jobs, vec![LineNumbers { a: 2, b: 3, end: 10 }, LineNumbers { a: 4, b: 5, end: 1000 }]);
+        assert!(parse("json", r#"[{"a": 2, "b": 3}]"#).is_err());
+        assert!(parse("json", r#"{"a": 2}"#).is_err());
+        let err = parse("json", r#"[[4, 5, 20], [2, 3, "2^40", 10]]"#).unwrap_err();
+        assert_eq!(err.to_string(), "Line 2 has a number too large for 32 bits: 2^40");
+        assert!(parse("json", r#"[{"a": 2, "b": 3, "end": 4294967296}]"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_ndjson() {
+        let jobs = parse("ndjson", "{\"a\": 2, \"b\": 3, \"end\": 10}\n[4, 5, 20]\n").unwrap();
+        assert_eq!(jobs.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_routed() {
+        let routed = |format: &str, text: &'static str| -> Result<Vec<Option<PathBuf>>> {
+            let parser = InputParserRegistry::with_builtins();
+            let jobs = parser.get(format).unwrap().parse_routed(Box::new(Cursor::new(text)));
+            jobs.map(|job| job.map(|(_, options)| options.output)).collect()
+        };
+        let small = Some(PathBuf::from("results/small.txt"));
+        assert_eq!(routed("text", "3 5 1000 > results/small.txt\n2 3 10\n").unwrap(), [small.clone(), None]);
+        assert!(routed("text", "3 5 1000 >\n").is_err());
+        assert_eq!(parse("text", "3 5 1000 > out 7.txt\n").unwrap(), [LineNumbers { a: 3, b: 5, end: 1000 }]);
+        let csv = "a,b,end,output\n3,5,1000,results/small.txt\n2,3,10,\n";
+        assert_eq!(routed("csv", csv).unwrap(), [small.clone(), None]);
+        let ndjson = "{\"a\": 3, \"b\": 5, \"end\": 1000, \"output\": \"results/small.txt\"}\n[2, 3, 10]\n";
+        assert_eq!(routed("ndjson", ndjson).unwrap(), [small, None]);
+        assert!(routed("ndjson", "{\"a\": 3, \"b\": 5, \"end\": 1000, \"output\": 1}\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_priorities() {
+        let priorities = |format: &str, text: &'static str| -> Result<Vec<Priority>> {
+            let parser = InputParserRegistry::with_builtins();
+            let jobs = parser.get(format).unwrap().parse_routed(Box::new(Cursor::new(text)));
+            jobs.map(|job| job.map(|(_, options)| options.priority)).collect()
+        };
+        use Priority::{High, Low, Normal};
+        assert_eq!(priorities("text", "3 5 1e9 prio=low
+2 3 10 prio=high > out.txt
+2 3 10
+").unwrap(), [Low, High, Normal]);
+        assert_eq!(parse("text", "3 5 1e9 prio=low
+").unwrap(), [LineNumbers { a: 3, b: 5, end: 1_000_000_000 }]);
+        assert!(priorities("text", "3 5 10 prio=urgent
+").unwrap_err().to_string().contains("Line 1"));
+        let csv = "a,b,end,output,priority
+3,5,10,out.txt,prio=low
+3,5,10,prio=high
+3,5,10
+";
+        assert_eq!(priorities("csv", csv).unwrap(), [Low, High, Normal]);
+        assert_eq!(parse("csv", "3,5,10,prio=high
+").unwrap(), [LineNumbers { a: 3, b: 5, end: 10 }]);
+        let ndjson = "{\"a\": 3, \"b\": 5, \"end\": 10, \"priority\": \"high\"}\n[2, 3, 10]\n";
+        assert_eq!(priorities("ndjson", ndjson).unwrap(), [High, Normal]);
+        assert!(priorities("ndjson", "{\"a\": 3, \"b\": 5, \"end\": 10, \"priority\": 1}\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_bounds() {
+        let ends = |format: &str, text: &'static str| -> Result<Vec<u32>> {
+            parse(format, text).map(|jobs| jobs.iter().map(|job| job.end).collect())
+        };
+        assert_eq!(ends("text", "3 5 1000 bound=exclusive\n3 5 1000 bound=inclusive prio=high\n3 5 0 bound=exclusive\n").unwrap(), [999, 1000, 0]);
+        assert!(ends("text", "3 5 10 bound=below\n").unwrap_err().to_string().contains("Line 1"));
+        assert_eq!(ends("csv", "a,b,end\n3,5,10,out.txt,bound=exclusive\n3,5,10,bound=exclusive,prio=low\n").unwrap(), [9, 9]);
+        assert_eq!(ends("ndjson", "{\"a\": 3, \"b\": 5, \"end\": 10, \"bound\": \"exclusive\"}\n").unwrap(), [9]);
+        assert!(ends("ndjson", "{\"a\": 3, \"b\": 5, \"end\": 10, \"bound\": true}\n").is_err());
+        let mut fast = FastReader::new(&b"3 5 100 bound=exclusive\n"[..]);
+        assert_eq!(fast.next().unwrap().unwrap().1.bound, Some(Bound::Exclusive));
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        let tags = |format: &str, text: &'static str| -> Result<Vec<Vec<String>>> {
+            let parser = InputParserRegistry::with_builtins();
+            let jobs = parser.get(format).unwrap().parse_routed(Box::new(Cursor::new(text)));
+            jobs.map(|job| job.map(|(_, options)| options.tags)).collect()
+        };
+        let nightly = vec![String::from("nightly")];
+        let both = vec![String::from("nightly"), String::from("slow")];
+        assert_eq!(tags("text", "3 5 10 tag=nightly\n3 5 10 tag=nightly tag=slow tag=nightly > out.txt\n3 5 10\n").unwrap(), [nightly.clone(), both.clone(), vec![]]);
+        let mut fast = FastReader::new(&b"3 5 10 tag=nightly\n"[..]);
+        assert_eq!(fast.next().unwrap().unwrap().1.tags, nightly);
+        assert_eq!(parse("text", "3 5 10 tag=slow\n").unwrap(), [LineNumbers { a: 3, b: 5, end: 10 }]);
+        assert!(tags("text", "3 5 10 tag=\n").unwrap_err().to_string().contains("Line 1 has an empty tag"));
+        assert_eq!(tags("csv", "a,b,end\n3,5,10,out.txt,tag=nightly,tag=slow\n3,5,10,tag=nightly\n").unwrap(), [both, nightly.clone()]);
+        assert_eq!(tags("ndjson", "{\"a\": 3, \"b\": 5, \"end\": 10, \"tags\": [\"nightly\"]}\n[2, 3, 10]\n").unwrap(), [nightly, vec![]]);
+        assert!(tags("ndjson", "{\"a\": 3, \"b\": 5, \"end\": 10, \"tags\": \"nightly\"}\n").is_err());
+    }
+
+    #[test]
+    fn test_fast_parse() {
+        let input: &[u8] = b"3 5 100\n  3\t5  1e3 \r\n2 3 10 prio=high > out.txt\n3 5\n3 5 100 7\n\
+            x 3 y 5 100\n3 5 4294967296\n007 0x10 1_000\n3 5 4294967295\n3\xc2\xa05 100\n3 \xff 5 100\n\
+            3 5 10 prio=urgent\n3 5 10 >\n\n9 8 7\n2^40 5 50\n2^3^2 5 50";
+        let describe = |job: Result<(LineNumbers, LineOptions)>| match job {
+            Ok(job) => format!("{:?}", job),
+            Err(err) => format!("{:#}", err),
+        };
+        let slow: Vec<_> = TextParser.parse_routed(Box::new(Cursor::new(input))).map(describe).collect();
+        let fast: Vec<_> = FastTextParser.parse_routed(Box::new(Cursor::new(input))).map(describe).collect();
+        assert_eq!(fast, slow);
+        assert_eq!(fast.len(), 17);
+        assert!(fast[10].contains("valid UTF-8"), "{}", fast[10]);
+        assert_eq!(fast[6], "Line 7 has a number too large for 32 bits: 4294967296");
+        assert_eq!(fast[15], "Line 16 has a number too large for 32 bits: 2^40");
+        assert_eq!(fast[16], "Line 17 does not contain exactly 3 numbers");
+
+        // Split into parts of a line or two, whichever way the parts fall.
+        for (threads, part_size) in [(1, 1), (3, 1), (2, 20), (4, 1000)] {
+            let mut reader = ParallelReader::new(input, threads);
+            reader.part_size = part_size;
+            assert_eq!(reader.map(describe).collect::<Vec<_>>(), slow);
+        }
+    }
+
+    #[test]
+    fn test_select_by_extension() {
+        let registry = InputParserRegistry::with_builtins();
+        let text: Vec<LineNumbers> = registry
+            .select(Path::new("jobs.CSV"), None)
+            .unwrap()
+            .parse(Box::new(Cursor::new("2,3,10\n")))
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(text.len(), 1);
+        assert!(registry.select(Path::new("jobs.txt"), Some("xml")).is_err());
+        assert!(registry.select(Path::new("jobs.unknown"), None).is_ok());
+        assert_eq!(registry.format_for(Path::new("jobs.jsonl")), Some("ndjson"));
+        assert_eq!(registry.format_for(Path::new("jobs")), None);
+    }
+}
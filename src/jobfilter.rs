@@ -0,0 +1,255 @@
+//! Requirements on the parameters of jobs, checked before they are run so
+//! that a sweep or a batch only generates the divisor pairs an experiment
+//! is about: `coprime` divisors, a prime `a` or `b` (`a-prime`,
+//! `b-prime`) or `a<b`. Unlike the `--where` constraints of a sweep they
+//! are named, so that they read the same in a query string.
+//!
+//! A [`TagFilter`] picks jobs by the `tag=<name>` tokens of their lines
+//! instead, so that one input can serve pipelines running different parts
+//! of it.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use crate::LineNumbers;
+
+/// A requirement on the divisors of a job.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Requirement {
+    /// `gcd(a, b) == 1`.
+    Coprime,
+    APrime,
+    BPrime,
+    ALessThanB,
+}
+
+impl Requirement {
+    pub const ALL: [Requirement; 4] = [Requirement::Coprime, Requirement::APrime, Requirement::BPrime, Requirement::ALessThanB];
+
+    pub fn holds(self, job: &LineNumbers) -> bool {
+        match self {
+            Requirement::Coprime => gcd(job.a, job.b) == 1,
+            Requirement::APrime => is_prime(job.a),
+            Requirement::BPrime => is_prime(job.b),
+            Requirement::ALessThanB => job.a < job.b,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Requirement::Coprime => "coprime",
+            Requirement::APrime => "a-prime",
+            Requirement::BPrime => "b-prime",
+            Requirement::ALessThanB => "a<b",
+        }
+    }
+}
+
+impl FromStr for Requirement {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Requirement::ALL.into_iter().find(|requirement| requirement.name() == name.trim()).ok_or_else(|| {
+            let names: Vec<_> = Requirement::ALL.iter().map(|requirement| requirement.name()).collect();
+            anyhow!("Unknown requirement {:?} (available: {})", name.trim(), names.join(", "))
+        })
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The requirements a job must all meet to be run; without any, every job
+/// is.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JobFilter {
+    requirements: Vec<Requirement>,
+}
+
+impl JobFilter {
+    /// Adds the comma separated requirements of `list`, e.g. `coprime,a<b`.
+    pub fn require(&mut self, list: &str) -> Result<()> {
+        for name in list.split(',') {
+            let requirement = name.parse()?;
+            if !self.requirements.contains(&requirement) {
+                self.requirements.push(requirement);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requirements.is_empty()
+    }
+
+    pub fn matches(&self, job: &LineNumbers) -> bool {
+        self.requirements.iter().all(|requirement| requirement.holds(job))
+    }
+}
+
+impl FromStr for JobFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(list: &str) -> Result<Self> {
+        let mut filter = JobFilter::default();
+        filter.require(list)?;
+        Ok(filter)
+    }
+}
+
+impl fmt::Display for JobFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<_> = self.requirements.iter().map(|requirement| requirement.name()).collect();
+        f.write_str(&names.join(","))
+    }
+}
+
+/// The tags of `--only-tag` and `--skip-tag`: a job is run when its line
+/// has one of the tags to keep, if any are given, and none of those to
+/// skip.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TagFilter {
+    only: Vec<String>,
+    skip: Vec<String>,
+}
+
+impl TagFilter {
+    /// Keeps the jobs tagged with one of the comma separated `tags`.
+    pub fn only(&mut self, tags: &str) -> Result<()> {
+        add_tags(&mut self.only, tags)
+    }
+
+    /// Leaves out the jobs tagged with one of the comma separated `tags`.
+    pub fn skip(&mut self, tags: &str) -> Result<()> {
+        add_tags(&mut self.skip, tags)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.skip.is_empty()
+    }
+
+    pub fn matches(&self, tags: &[String]) -> bool {
+        let tagged = |wanted: &Vec<String>| tags.iter().any(|tag| wanted.contains(tag));
+        (self.only.is_empty() || tagged(&self.only)) && !tagged(&self.skip)
+    }
+}
+
+fn add_tags(tags: &mut Vec<String>, list: &str) -> Result<()> {
+    for tag in list.split(',').map(str::trim) {
+        if tag.is_empty() {
+            return Err(anyhow!("Empty tag in {:?}", list));
+        }
+        if !tags.iter().any(|known| known == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+    Ok(())
+}
+
+fn gcd(mut x: u32, mut y: u32) -> u32 {
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    x
+}
+
+/// Whether `n` is prime, by the deterministic Miller-Rabin test, for which
+/// the bases 2, 7 and 61 suffice below 2^32.
+fn is_prime(n: u32) -> bool {
+    if n < 2 {
+        return false;
+    }
+    for p in [2, 3, 5, 7, 11, 13, 61] {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+    let n = n as u64;
+    let (mut d, mut s) = (n - 1, 0);
+    while d.is_multiple_of(2) {
+        d /= 2;
+        s += 1;
+    }
+    let pow = |mut base: u64, mut exp: u64| {
+        let mut result = 1;
+        base %= n;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result * base % n;
+            }
+            base = base * base % n;
+            exp >>= 1;
+        }
+        result
+    };
+    [2, 7, 61].into_iter().all(|base| {
+        let mut x = pow(base, d);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..s {
+            x = x * x % n;
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_prime, JobFilter, Requirement, TagFilter};
+    use crate::LineNumbers;
+
+    fn job(a: u32, b: u32) -> LineNumbers {
+        LineNumbers { a, b, end: 100 }
+    }
+
+    #[test]
+    fn test_is_prime() {
+        let small: Vec<u32> = (0..50).filter(|&n| is_prime(n)).collect();
+        assert_eq!(small, [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47]);
+        // The largest prime below 2^32, and strong pseudoprimes to some bases.
+        assert!(is_prime(4_294_967_291) && is_prime(2_147_483_647));
+        assert!(!is_prime(3_215_031_751) && !is_prime(4_294_967_295) && !is_prime(25_326_001));
+        let trial = |n: u32| n >= 2 && (2..).take_while(|d| d * d <= n).all(|d| !n.is_multiple_of(d));
+        assert!((0..20_000).all(|n| is_prime(n) == trial(n)));
+    }
+
+    #[test]
+    fn test_requirements() {
+        let filter: JobFilter = "coprime, a<b".parse().unwrap();
+        assert!(filter.matches(&job(4, 9)));
+        assert!(!filter.matches(&job(9, 4)) && !filter.matches(&job(4, 6)));
+        let mut primes = JobFilter::default();
+        primes.require("a-prime").unwrap();
+        primes.require("b-prime,a-prime").unwrap();
+        assert_eq!(primes.to_string(), "a-prime,b-prime");
+        assert!(primes.matches(&job(3, 7)) && !primes.matches(&job(3, 9)) && !primes.matches(&job(1, 7)));
+        assert!(JobFilter::default().matches(&job(0, 0)) && JobFilter::default().is_empty());
+        assert!(!Requirement::Coprime.holds(&job(0, 0)) && Requirement::Coprime.holds(&job(1, 0)));
+        let err = "odd".parse::<JobFilter>().unwrap_err();
+        assert_eq!(err.to_string(), "Unknown requirement \"odd\" (available: coprime, a-prime, b-prime, a<b)");
+    }
+
+    #[test]
+    fn test_tags() {
+        let tags = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        let mut filter = TagFilter::default();
+        assert!(filter.is_empty() && filter.matches(&[]));
+        filter.only("nightly, weekly").unwrap();
+        filter.skip("slow").unwrap();
+        assert!(filter.matches(&tags(&["weekly"])) && filter.matches(&tags(&["fast", "nightly"])));
+        assert!(!filter.matches(&[]) && !filter.matches(&tags(&["nightly", "slow"])) && !filter.matches(&tags(&["fast"])));
+        let mut skipping = TagFilter::default();
+        skipping.skip("slow").unwrap();
+        assert!(skipping.matches(&[]) && !skipping.matches(&tags(&["slow"])));
+        assert_eq!(skipping.only("nightly,").unwrap_err().to_string(), "Empty tag in \"nightly,\"");
+    }
+}
@@ -0,0 +1,236 @@
+//! The hot divisibility loop of trial division, compiled once per
+//! instruction set and picked at runtime.
+//!
+//! A vector unit can't divide, so the SIMD kernels test divisibility with a
+//! multiplication by the modular inverse of the divisor instead (Granlund and
+//! Montgomery): with `d = d0 * 2^k` and `d0` odd, `n` is a multiple of `d`
+//! exactly when `(n * d0^-1 mod 2^32).rotate_right(k) <= u32::MAX / d`. That
+//! loop is written once and compiled with AVX2 or NEON enabled, leaving the
+//! compiler to vectorize it; it counts how many of the two divisors each
+//! number is a multiple of, and the selected numbers are then compacted on
+//! the scalar side.
+
+use crate::formula::Mode;
+use crate::LineNumbers;
+
+/// Numbers tested per block before the selected ones are compacted.
+const BLOCK: usize = 4096;
+
+/// Divisibility by a fixed `u32`, without a division.
+#[derive(Clone, Copy, Debug)]
+struct DivisibilityTest {
+    inverse: u32,
+    shift: u32,
+    limit: u32,
+}
+
+impl DivisibilityTest {
+    fn new(divisor: u32) -> Self {
+        if divisor == 0 {
+            // `n * 1 <= 0` never holds for the positive numbers tested.
+            return DivisibilityTest { inverse: 1, shift: 0, limit: 0 };
+        }
+        let shift = divisor.trailing_zeros();
+        let odd = divisor >> shift;
+        // Newton's iteration doubles the correct low bits of the inverse on
+        // every step, starting from 3 bits for `odd * odd = 1 mod 8`.
+        let mut inverse = odd;
+        for _ in 0..4 {
+            inverse = inverse.wrapping_mul(2u32.wrapping_sub(odd.wrapping_mul(inverse)));
+        }
+        DivisibilityTest {
+            inverse,
+            shift,
+            limit: u32::MAX / divisor,
+        }
+    }
+
+    #[inline(always)]
+    fn divides(&self, n: u32) -> bool {
+        n.wrapping_mul(self.inverse).rotate_right(self.shift) <= self.limit
+    }
+}
+
+/// An implementation of the trial division loop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kernel {
+    /// Plain `%` on every number, available everywhere.
+    Scalar,
+    /// Multiply-and-compare blocks compiled for AVX2.
+    Avx2,
+    /// Multiply-and-compare blocks compiled for NEON.
+    Neon,
+}
+
+impl Kernel {
+    /// The fastest kernel the running CPU supports.
+    pub fn detect() -> Kernel {
+        #[cfg(target_arch = "x86_64")]
+        if std::arch::is_x86_feature_detected!("avx2") {
+            return Kernel::Avx2;
+        }
+        #[cfg(target_arch = "aarch64")]
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            return Kernel::Neon;
+        }
+        Kernel::Scalar
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kernel::Scalar => "scalar",
+            Kernel::Avx2 => "avx2",
+            Kernel::Neon => "neon",
+        }
+    }
+
+    /// Whether the running CPU can execute this kernel.
+    pub fn is_supported(self) -> bool {
+        match self {
+            Kernel::Scalar => true,
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => std::arch::is_x86_feature_detected!("avx2"),
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// The numbers in `1..=job.end` selected by `mode`, ascending, with a
+    /// number selected twice listed twice.
+    ///
+    /// Falls back to [`Kernel::Scalar`] when the CPU doesn't support `self`.
+    pub fn select(self, job: &LineNumbers, mode: Mode) -> Vec<u32> {
+        self.select_with(job, mode, &mut |_| {})
+    }
+
+    /// Like [`Kernel::select`], calling `progress` with the number of values
+    /// tested after every block of them.
+    pub fn select_with(self, job: &LineNumbers, mode: Mode, progress: &mut dyn FnMut(u64)) -> Vec<u32> {
+        self.select_from(job, 1, mode, progress)
+    }
+
+    /// Like [`Kernel::select_with`], testing only `first..=job.end`.
+    pub fn select_from(self, job: &LineNumbers, first: u32, mode: Mode, progress: &mut dyn FnMut(u64)) -> Vec<u32> {
+        let scalar = self == Kernel::Scalar || !self.is_supported();
+        let tests = [DivisibilityTest::new(job.a), DivisibilityTest::new(job.b)];
+        let mut numbers = Vec::new();
+        let mut hits = [0u8; BLOCK];
+        let mut start = first.max(1) as u64;
+        while start <= job.end as u64 {
+            let len = (job.end as u64 - start + 1).min(BLOCK as u64) as usize;
+            let hits = &mut hits[..len];
+            if scalar {
+                count_hits_scalar(job, start as u32, hits);
+            } else {
+                // SAFETY: `is_supported` checked that the CPU has the feature
+                // the kernel was compiled for.
+                unsafe { self.count_hits(&tests, start as u32, hits) };
+            }
+            for (i, &hits) in hits.iter().enumerate() {
+                let copies = mode.copies(hits);
+                if copies > 0 {
+                    numbers.extend(std::iter::repeat_n(start as u32 + i as u32, copies));
+                }
+            }
+            progress(len as u64);
+            start += len as u64;
+        }
+        numbers
+    }
+
+    /// # Safety
+    ///
+    /// The CPU must support the instruction set of `self`.
+    unsafe fn count_hits(self, tests: &[DivisibilityTest; 2], start: u32, hits: &mut [u8]) {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => count_hits_avx2(tests, start, hits),
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => count_hits_neon(tests, start, hits),
+            _ => count_hits(tests, start, hits),
+        }
+    }
+}
+
+/// [`count_hits`] with plain `%`.
+fn count_hits_scalar(job: &LineNumbers, start: u32, hits: &mut [u8]) {
+    for (i, hit) in hits.iter_mut().enumerate() {
+        let n = start.wrapping_add(i as u32);
+        *hit = n.is_multiple_of(job.a) as u8 + n.is_multiple_of(job.b) as u8;
+    }
+}
+
+/// Sets `hits[i]` to the number of divisors `start + i` is a multiple of.
+#[inline(always)]
+fn count_hits(tests: &[DivisibilityTest; 2], start: u32, hits: &mut [u8]) {
+    let [a, b] = *tests;
+    for (i, hit) in hits.iter_mut().enumerate() {
+        let n = start.wrapping_add(i as u32);
+        *hit = a.divides(n) as u8 + b.divides(n) as u8;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn count_hits_avx2(tests: &[DivisibilityTest; 2], start: u32, hits: &mut [u8]) {
+    count_hits(tests, start, hits)
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn count_hits_neon(tests: &[DivisibilityTest; 2], start: u32, hits: &mut [u8]) {
+    count_hits(tests, start, hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DivisibilityTest, Kernel};
+    use crate::formula::Mode;
+    use crate::LineNumbers;
+
+    #[test]
+    fn test_divisibility_test_matches_remainder() {
+        let divisors = [1, 2, 3, 5, 6, 7, 8, 12, 96, 1 << 31, 65_521, u32::MAX - 1, u32::MAX];
+        for d in divisors {
+            let test = DivisibilityTest::new(d);
+            for n in (1..5000).chain(u32::MAX - 5000..=u32::MAX).chain([d, d.wrapping_mul(3)]) {
+                assert_eq!(test.divides(n), n % d == 0 && n != 0, "{} {}", n, d);
+            }
+        }
+        assert!(!DivisibilityTest::new(0).divides(7));
+    }
+
+    #[test]
+    fn test_kernels_agree() {
+        let detected = Kernel::detect();
+        assert!(detected.is_supported());
+        let jobs = [(3, 5, 10_000), (0, 4, 9_000), (0, 0, 10), (6, 6, 4097), (1, 7, 1)];
+        for (a, b, end) in jobs {
+            let job = LineNumbers { a, b, end };
+            for mode in [Mode::Or, Mode::Xor, Mode::Multiset] {
+                let expected = Kernel::Scalar.select(&job, mode);
+                for kernel in [detected, Kernel::Avx2, Kernel::Neon] {
+                    let mut tested = 0;
+                    let selected = kernel.select_with(&job, mode, &mut |n| tested += n);
+                    assert_eq!(selected, expected, "{:?} {:?} {:?}", kernel, job, mode);
+                    assert_eq!(tested, job.end as u64);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_select_from() {
+        let job = LineNumbers { a: 3, b: 5, end: 10_000 };
+        let all = Kernel::detect().select(&job, Mode::Multiset);
+        for first in [0, 1, 15, 4097, 9_999, 10_000, 10_001] {
+            let expected: Vec<u32> = all.iter().copied().filter(|&n| n >= first).collect();
+            let mut tested = 0;
+            let selected = Kernel::detect().select_from(&job, first, Mode::Multiset, &mut |n| tested += n);
+            assert_eq!(selected, expected, "{}", first);
+            assert_eq!(tested, (job.end + 1).saturating_sub(first.max(1)) as u64);
+        }
+    }
+}
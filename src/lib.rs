@@ -0,0 +1,400 @@
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+pub mod aggregate;
+pub mod audit;
+pub mod auth;
+pub mod backfill;
+pub mod bench;
+pub mod cache;
+pub mod cancel;
+pub mod chunk;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod color;
+pub mod compat;
+pub mod config;
+pub mod console;
+pub mod crc32;
+pub mod decimal;
+pub mod degenerate;
+pub mod delta;
+pub mod describe;
+pub mod errors;
+pub mod explain;
+pub mod formatter;
+pub mod formula;
+pub mod gaps;
+pub mod http;
+pub mod input;
+pub mod jobfilter;
+pub mod json;
+pub mod kernel;
+pub mod limits;
+pub mod lint;
+pub mod locale;
+pub mod memory;
+pub mod merge;
+#[cfg(feature = "messaging")]
+pub mod messaging;
+#[cfg(unix)]
+pub mod mmap;
+pub mod newline;
+pub mod number;
+pub mod observer;
+pub mod otlp;
+pub mod output;
+pub mod paths;
+pub mod pipeline;
+#[cfg(all(feature = "plugins", unix))]
+pub mod plugin;
+pub mod predicate;
+pub mod preview;
+pub mod profile;
+pub mod progress;
+pub mod provenance;
+pub mod quantity;
+pub mod random;
+pub mod ranges;
+pub mod reader;
+pub mod regress;
+pub mod repair;
+pub mod report;
+pub mod roaring;
+pub mod runlog;
+pub mod schedule;
+pub mod selftest;
+pub mod serial;
+pub mod server;
+pub mod service;
+pub mod setops;
+pub mod shard;
+pub mod sha256;
+pub mod sort;
+pub mod spec;
+pub mod store;
+pub mod strategy;
+pub mod sweep;
+pub mod throttle;
+pub mod timeline;
+pub mod wheel;
+pub mod wizard;
+
+use errors::POWER_TOO_LARGE;
+use pipeline::{CollectSink, Pipeline};
+use spec::DivisorSet;
+
+/// A job: the multiples of `a` or `b` from 1 to `end`.
+///
+/// Outside this crate jobs are made with [`LineNumbers::new`], which rejects
+/// a divisor of 0; only the input parsers keep such lines, for
+/// [`degenerate`] and the generators to handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LineNumbers {
+    pub(crate) a: u32,
+    pub(crate) b: u32,
+    pub(crate) end: u32,
+}
+
+impl LineNumbers {
+    pub fn new(a: u32, b: u32, end: u32) -> Result<Self> {
+        Ok(DivisorSet::new(a, b)?.up_to(end))
+    }
+
+    pub fn a(&self) -> u32 {
+        self.a
+    }
+
+    pub fn b(&self) -> u32 {
+        self.b
+    }
+
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+
+    /// Raises both divisors to the `k`-th power, so that the job selects the
+    /// multiples of `a^k` or `b^k`.
+    pub fn with_power(self, k: u32) -> Result<Self> {
+        let raise = |d: u32| d.checked_pow(k).ok_or_else(|| POWER_TOO_LARGE.error(format!("{}^{} doesn't fit into 32 bits", d, k)));
+        Ok(LineNumbers {
+            a: raise(self.a)?,
+            b: raise(self.b)?,
+            end: self.end,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResultNumbers {
+    pub end: u32,
+    pub numbers: Vec<u32>,
+    /// Set instead of `numbers` when only the number of multiples was
+    /// computed.
+    pub count: Option<u64>,
+}
+
+impl ResultNumbers {
+    /// The number of multiples, whether or not they were enumerated.
+    pub fn count(&self) -> u64 {
+        self.count.unwrap_or(self.numbers.len() as u64)
+    }
+}
+
+/// A result together with the input line it was generated from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResultRecord {
+    /// Position of the job in the input, starting at 0.
+    pub index: usize,
+    pub job: LineNumbers,
+    pub result: ResultNumbers,
+}
+
+impl fmt::Display for ResultNumbers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(count) = self.count {
+            return write!(f, "{}:{}", self.end, count);
+        }
+        write!(f, "{}:", self.end)?;
+        for (i, num) in self.numbers.iter().enumerate() {
+            let separator = if i > 0 { " " } else { "" };
+            write!(f, "{}{}", separator, num)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn read_items(input: &Path) -> Result<Vec<LineNumbers>> {
+    read_items_as(input, None)
+}
+
+/// Reads jobs from `input` with the named input format, or with the format
+/// implied by the file extension when `format` is `None`.
+pub fn read_items_as(input: &Path, format: Option<&str>) -> Result<Vec<LineNumbers>> {
+    pipeline::file_source(input, format)?.collect()
+}
+
+pub fn is_number_divisible_by(item: &LineNumbers, n: &u32) -> bool {
+    n.is_multiple_of(item.a) || n.is_multiple_of(item.b)
+}
+
+/// Generates the record for a single job. The record's `index` is left at 0;
+/// the pipeline sets it to the job's position in its source.
+pub fn generate_record(job: LineNumbers) -> ResultRecord {
+    generate_record_with(job, &predicate::Divisible)
+}
+
+/// The numbers in `1..=job.end` matching `predicate`, ascending. The
+/// iterator is double-ended, so `.rev()` walks down from `end` directly.
+pub fn matching_numbers<'a>(
+    job: LineNumbers,
+    predicate: &'a dyn predicate::Predicate,
+) -> impl DoubleEndedIterator<Item = u32> + 'a {
+    (1..=job.end).filter(move |&n| predicate.matches(&job, n))
+}
+
+fn record_from(job: LineNumbers, numbers: Vec<u32>) -> ResultRecord {
+    ResultRecord {
+        index: 0,
+        job,
+        result: ResultNumbers {
+            end: job.end,
+            numbers,
+            count: None,
+        },
+    }
+}
+
+fn count_record_from(job: LineNumbers, count: u64) -> ResultRecord {
+    ResultRecord {
+        index: 0,
+        job,
+        result: ResultNumbers {
+            end: job.end,
+            numbers: Vec::new(),
+            count: Some(count),
+        },
+    }
+}
+
+/// Builds a record holding only the number of values `mode` selects for
+/// `job`, worked out in closed form.
+pub fn count_record(job: LineNumbers, mode: formula::Mode) -> ResultRecord {
+    count_record_from(job, formula::count(&job, mode))
+}
+
+/// Builds a record holding only the number of values matching `predicate`,
+/// for predicates without a closed form. The values are tested but not
+/// collected.
+pub fn count_record_with(job: LineNumbers, predicate: &dyn predicate::Predicate) -> ResultRecord {
+    count_record_from(job, matching_numbers(job, predicate).count() as u64)
+}
+
+/// Like `generate_record`, but keeps the numbers matching `predicate`
+/// instead of the multiples of `a` or `b`.
+pub fn generate_record_with(job: LineNumbers, predicate: &dyn predicate::Predicate) -> ResultRecord {
+    record_from(job, matching_numbers(job, predicate).collect())
+}
+
+/// Like `generate_record_with`, but lists the numbers from `end` downward.
+pub fn generate_record_descending(job: LineNumbers, predicate: &dyn predicate::Predicate) -> ResultRecord {
+    record_from(job, matching_numbers(job, predicate).rev().collect())
+}
+
+/// Generates the results for every line of `input`, sorted by their number of multiples.
+pub fn generate_divisible_numbers(input: &Path) -> Result<Vec<ResultNumbers>> {
+    let source = pipeline::file_source(input, None).context("Failed to read items from input file")?;
+    let mut sink = CollectSink::default();
+    Pipeline::builder(source)
+        .sort_by_key(|record| record.result.count())
+        .build()
+        .run(&mut sink)?;
+    Ok(sink.records.into_iter().map(|record| record.result).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+    use std::path::PathBuf;
+
+    use super::generate_divisible_numbers;
+    use super::is_number_divisible_by;
+    use super::LineNumbers;
+    use super::read_items;
+    use super::ResultNumbers;
+
+    fn read_result_numbers_from_file(file_path: &PathBuf) -> Result<Vec<ResultNumbers>, Box<dyn std::error::Error>> {
+        let content = read_to_string(file_path)?;
+        let lines: Vec<&str> = content.lines().collect();
+
+        let mut result_numbers_vec = Vec::new();
+
+        for line in lines {
+            let parts: Vec<&str> = line.split(':').collect();
+            let end: u32 = parts[0].parse()?;
+            let numbers: Vec<u32> = parts[1]
+                .split_whitespace()
+                .map(|num| num.parse().unwrap())
+                .collect();
+
+            result_numbers_vec.push(ResultNumbers {
+                end,
+                numbers,
+                count: None,
+            });
+        }
+
+        Ok(result_numbers_vec)
+    }
+
+    #[test]
+    fn test_with_power() {
+        let job = LineNumbers { a: 2, b: 5, end: 100 };
+        assert_eq!(job.with_power(3).unwrap(), LineNumbers { a: 8, b: 125, end: 100 });
+        assert!(job.with_power(14).is_err());
+        assert!(LineNumbers { a: 2, b: 1, end: 1 }.with_power(32).is_err());
+    }
+
+    #[test]
+    fn test_generate_record_descending() {
+        let job = LineNumbers { a: 3, b: 5, end: 10 };
+        let record = super::generate_record_descending(job, &crate::predicate::Divisible);
+        assert_eq!(record.result.numbers, vec![10, 9, 6, 5, 3]);
+    }
+
+    #[test]
+    fn test_read_items() {
+        let input = PathBuf::from("test_data/input_2_rows.txt");
+        let items = read_items(&input).unwrap();
+        assert_eq!(items.len(), 2);
+    }
+
+    #[test]
+    fn test_is_number_divisible_by() {
+        let item = LineNumbers {
+            a: 2,
+            b: 3,
+            end: 10,
+        };
+
+        let expected_results = vec![
+            (1, false),
+            (2, true),
+            (3, true),
+            (4, true),
+            (5, false),
+            (6, true),
+            (7, false),
+            (8, true),
+            (9, true),
+            (10, true),
+        ];
+
+        for (n, expected) in expected_results {
+            assert_eq!(is_number_divisible_by(&item, &n), expected, "Failed for number {}", n);
+        }
+    }
+
+    #[test]
+    fn test_generate_divisible_numbers() {
+        // Read the expected results from the comparison file
+        let comparison_path = PathBuf::from("test_data/result_2_comparison.txt");
+        let expected_results = read_result_numbers_from_file(&comparison_path).unwrap();
+
+        // Call the function with the test input
+        let input_path = PathBuf::from("test_data/input_2_rows.txt");
+        let actual_results = generate_divisible_numbers(&input_path).unwrap();
+
+        // Compare the output with the expected results
+        assert_eq!(actual_results.len(), expected_results.len());
+        for (actual, expected) in actual_results.iter().zip(expected_results.iter()) {
+            assert!(actual == expected);
+        }
+    }
+
+    #[test]
+    fn test_read_items_number_literals() {
+        let input = PathBuf::from("test_data/input_number_literals.txt");
+        let items = read_items(&input).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!((items[0].a, items[0].b, items[0].end), (2, 3, 1000));
+        assert_eq!((items[1].a, items[1].b, items[1].end), (2, 10, 2000));
+    }
+
+    #[test]
+    fn test_read_items_incorrect_format() {
+        let input = PathBuf::from("test_data/input_incorrect_format.txt");
+        let result = read_items(&input);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_read_items_empty_file() {
+        let input = PathBuf::from("test_data/input_empty.txt");
+        let items = read_items(&input).unwrap();
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn test_read_items_mixed_format() {
+        let input = PathBuf::from("test_data/input_mixed_format.txt");
+        let result = read_items(&input);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_generate_divisible_numbers_large_numbers() {
+        let input = PathBuf::from("test_data/input_large_numbers.txt");
+        let items = read_items(&input).unwrap();
+        assert_eq!(items.len(), 1);
+
+        let result = generate_divisible_numbers(&input).unwrap();
+
+        // Example: Test that it generates expected numbers for a large range
+        // Assuming a specific input, adjust the expected output as needed
+        let expected_numbers: Vec<u32> = (1..=100000).filter(|&n| n % 2 == 0 || n % 3 == 0).collect();
+        assert_eq!(result[0].numbers, expected_numbers);
+    }
+}
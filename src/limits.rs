@@ -0,0 +1,179 @@
+//! Limits on the size of an input, checked before any of it is generated so
+//! that a pathological input — a line of gigabytes without a newline,
+//! millions of tokens, more lines than anyone means to run, or jobs whose
+//! multiples would fill the disk — is refused with an error naming the
+//! limit instead of taking memory until the run fails. `serve` applies them
+//! to every upload, see [`ServerConfig::limits`](crate::server::ServerConfig::limits).
+//!
+//! The text of the input is scanned as it is read, holding no more than a
+//! buffer of it. Tokens are runs of bytes between whitespace and commas, so
+//! that text, CSV and NDJSON lines are measured alike.
+
+use std::io::BufRead;
+
+use anyhow::{Context, Result};
+
+use crate::errors::INPUT_LIMIT;
+use crate::quantity::format_count;
+
+/// The limits of an input; `None` is no limit.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputLimits {
+    /// Longest line, in bytes without the newline.
+    pub max_line_length: Option<usize>,
+    pub max_lines: Option<usize>,
+    pub max_tokens: Option<usize>,
+    /// Most multiples all lines together may select.
+    pub max_multiples: Option<u64>,
+}
+
+impl InputLimits {
+    /// The limits `serve` starts with, generous for any input written by
+    /// hand or by a program and small enough that an upload within them
+    /// can't exhaust the server.
+    pub fn untrusted() -> Self {
+        InputLimits {
+            max_line_length: Some(4096),
+            max_lines: Some(100_000),
+            max_tokens: Some(64),
+            max_multiples: None,
+        }
+    }
+
+    /// Whether the text of an input needs scanning for any limit.
+    pub fn limits_text(&self) -> bool {
+        self.max_line_length.is_some() || self.max_lines.is_some() || self.max_tokens.is_some()
+    }
+
+    /// Reads `reader` to its end, failing at the first line over a limit.
+    pub fn check_text(&self, mut reader: impl BufRead) -> Result<()> {
+        let mut scanner = Scanner::default();
+        loop {
+            let buf = reader.fill_buf().context("Failed to read the input")?;
+            if buf.is_empty() {
+                return Ok(());
+            }
+            let len = buf.len();
+            scanner.feed(buf, self)?;
+            reader.consume(len);
+        }
+    }
+
+    /// Checks the multiples the lines select, given as the predicted count of
+    /// each, against [`InputLimits::max_multiples`].
+    pub fn check_multiples(&self, counts: impl IntoIterator<Item = u64>) -> Result<()> {
+        let Some(limit) = self.max_multiples else {
+            return Ok(());
+        };
+        let total = counts.into_iter().fold(0u64, u64::saturating_add);
+        if total > limit {
+            return Err(INPUT_LIMIT.error(format!(
+                "The input selects {} multiples, more than the limit of {}",
+                format_count(total),
+                format_count(limit)
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Where scanning the text of an input is.
+#[derive(Default)]
+struct Scanner {
+    /// The number of the current line, counting from 1.
+    line: usize,
+    length: usize,
+    tokens: usize,
+    in_token: bool,
+    /// Whether the current line has started, so that a file ending in a
+    /// newline has no empty line after it.
+    started: bool,
+}
+
+impl Scanner {
+    fn feed(&mut self, bytes: &[u8], limits: &InputLimits) -> Result<()> {
+        for &byte in bytes {
+            if !self.started {
+                self.start_line(limits)?;
+            }
+            match byte {
+                b'\n' => {
+                    *self = Scanner { line: self.line, ..Scanner::default() };
+                    continue;
+                }
+                b',' | b'\r' | b' ' | b'\t' | b'\x0b' | b'\x0c' => self.in_token = false,
+                _ if !self.in_token => {
+                    self.in_token = true;
+                    self.tokens += 1;
+                    if limits.max_tokens.is_some_and(|max| self.tokens > max) {
+                        return Err(self.error(format!("has more than {} tokens", format_count(self.tokens as u64 - 1))));
+                    }
+                }
+                _ => {}
+            }
+            self.length += 1;
+            if limits.max_line_length.is_some_and(|max| self.length > max) {
+                return Err(self.error(format!("is longer than {} bytes", format_count(self.length as u64 - 1))));
+            }
+        }
+        Ok(())
+    }
+
+    fn start_line(&mut self, limits: &InputLimits) -> Result<()> {
+        self.started = true;
+        self.line += 1;
+        match limits.max_lines {
+            Some(max) if self.line > max => {
+                Err(INPUT_LIMIT.error(format!("The input has more than {} lines", format_count(max as u64))))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn error(&self, what: String) -> anyhow::Error {
+        INPUT_LIMIT.error(format!("Line {} {}", self.line, what))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::InputLimits;
+    use crate::errors::{code_of, INPUT_LIMIT};
+
+    fn check(limits: &InputLimits, text: &str) -> Result<(), String> {
+        // A small buffer, so that lines span several reads.
+        let reader = std::io::BufReader::with_capacity(4, Cursor::new(text.to_string()));
+        limits.check_text(reader).map_err(|err| {
+            assert_eq!(code_of(&err), Some(&INPUT_LIMIT));
+            err.to_string()
+        })
+    }
+
+    #[test]
+    fn test_text_limits() {
+        let limits = InputLimits {
+            max_line_length: Some(12),
+            max_lines: Some(2),
+            max_tokens: Some(4),
+            max_multiples: None,
+        };
+        assert_eq!(check(&limits, "3 5 100\n2,3,10 out\n"), Ok(()));
+        assert_eq!(check(&limits, "3 5 100\n2 3 10\n4 5 6"), Err(String::from("The input has more than 2 lines")));
+        assert_eq!(check(&limits, "3 5 100\n1 2 3 4 5\n"), Err(String::from("Line 2 has more than 4 tokens")));
+        assert_eq!(check(&limits, "3 5 1000000000000"), Err(String::from("Line 1 is longer than 12 bytes")));
+        assert_eq!(check(&limits, "3 ,5 100\r\n"), Ok(()));
+        assert_eq!(check(&InputLimits::default(), &"7".repeat(100_000)), Ok(()));
+        assert!(!InputLimits::default().limits_text() && InputLimits::untrusted().limits_text());
+    }
+
+    #[test]
+    fn test_multiples_limit() {
+        let limits = InputLimits { max_multiples: Some(1_000_000), ..InputLimits::default() };
+        assert!(limits.check_multiples([600_000, 400_000]).is_ok());
+        let err = limits.check_multiples([600_000, 400_001]).unwrap_err();
+        assert_eq!(err.to_string(), "The input selects 1,000,001 multiples, more than the limit of 1,000,000");
+        assert!(InputLimits::default().check_multiples([u64::MAX, u64::MAX]).is_ok());
+    }
+}
@@ -0,0 +1,191 @@
+//! Checks of input files for the `lint` command: lines that parse but are
+//! probably not what was meant, such as a divisor above the end, a divisor
+//! adding nothing, a repeated job or a record too large to be useful.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use crate::explain::Planner;
+use crate::formula::{self, Mode};
+use crate::quantity::format_bytes;
+use crate::LineNumbers;
+
+/// How serious a finding is, from least to most.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Harmless, but worth a look.
+    Note,
+    /// Valid, but most likely a mistake.
+    Warning,
+    /// A line that doesn't parse.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// The least severity `--deny` fails on.
+impl FromStr for Severity {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "notes" => Ok(Severity::Note),
+            "warnings" => Ok(Severity::Warning),
+            _ => Err(anyhow!("--deny is warnings or notes, not {}", name)),
+        }
+    }
+}
+
+/// One thing found on a line, which displays as
+/// `3: warning[redundant-divisor]: 9 is a multiple of 3`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub line: usize,
+    pub severity: Severity,
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}[{}]: {}", self.line, self.severity, self.rule, self.message)
+    }
+}
+
+impl Finding {
+    /// The finding of a line that failed to parse with `err`.
+    pub fn invalid(line: usize, err: &anyhow::Error) -> Self {
+        Finding {
+            line,
+            severity: Severity::Error,
+            rule: "invalid-line",
+            message: format!("{:#}", err),
+        }
+    }
+}
+
+/// Checks the lines of one input in order, remembering the jobs seen to
+/// find repeated ones.
+pub struct Linter<'a> {
+    planner: &'a Planner<'a>,
+    mode: Mode,
+    /// Output size of a record above which it is reported.
+    max_size: u64,
+    seen: HashMap<LineNumbers, usize>,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(planner: &'a Planner<'a>, mode: Mode, max_size: u64) -> Self {
+        Linter {
+            planner,
+            mode,
+            max_size,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// The findings of `job`, read from input line `line`.
+    pub fn check(&mut self, line: usize, job: &LineNumbers) -> Result<Vec<Finding>> {
+        let mut findings = Vec::new();
+        let mut find = |severity, rule, message: String| {
+            findings.push(Finding {
+                line,
+                severity,
+                rule,
+                message,
+            })
+        };
+        let (low, high) = (job.a.min(job.b), job.a.max(job.b));
+        if low > job.end {
+            let message = format!("both divisors are above end {}, so the line selects nothing", job.end);
+            find(Severity::Warning, "divisor-above-end", message);
+        } else if high > job.end {
+            find(Severity::Warning, "divisor-above-end", format!("{} is above end {}, so it has no multiples", high, job.end));
+        }
+        if low == high {
+            find(Severity::Note, "duplicate-divisor", format!("both divisors are {}", low));
+        }
+        if low == 1 {
+            find(Severity::Warning, "dense-pair", format!("1 divides every number, so all of 1 to {} are multiples", job.end));
+        } else if low != high {
+            if let (_, Some(reason)) = formula::simplify(job, self.mode) {
+                find(Severity::Warning, "redundant-divisor", reason);
+            }
+        }
+        // The order of the divisors doesn't change the multiples.
+        let key = LineNumbers { a: low, b: high, end: job.end };
+        match self.seen.get(&key) {
+            Some(first) => find(Severity::Warning, "duplicate-line", format!("same job as line {}", first)),
+            None => {
+                self.seen.insert(key, line);
+            }
+        }
+        let bytes = self.planner.plan(line - 1, *job)?.bytes;
+        if bytes > self.max_size {
+            let message = format!(
+                "the record takes {} of output, more than --max-size {}",
+                format_bytes(bytes),
+                format_bytes(self.max_size)
+            );
+            find(Severity::Warning, "large-output", message);
+        }
+        Ok(findings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Finding, Linter, Severity};
+    use crate::explain::{Calibration, Planner};
+    use crate::formatter::FormatterRegistry;
+    use crate::formula::Mode;
+    use crate::strategy::{GenerateOptions, RecordGenerator};
+    use crate::LineNumbers;
+
+    fn job(a: u32, b: u32, end: u32) -> LineNumbers {
+        LineNumbers { a, b, end }
+    }
+
+    #[test]
+    fn test_lint() {
+        let registry = FormatterRegistry::with_builtins();
+        let make_formatter = || registry.create("text");
+        let generator = RecordGenerator::new(GenerateOptions::default());
+        let calibration = Calibration {
+            per_number: 0.0,
+            per_selected: 0.0,
+        };
+        let planner = Planner::new(&generator, &make_formatter, calibration);
+        let mut linter = Linter::new(&planner, Mode::Or, 1_000_000);
+        let mut rules = |line, job: LineNumbers| -> Vec<&'static str> {
+            linter.check(line, &job).unwrap().iter().map(|finding| finding.rule).collect()
+        };
+        assert!(rules(1, job(3, 5, 100)).is_empty());
+        assert_eq!(rules(2, job(30, 50, 20)), ["divisor-above-end"]);
+        assert_eq!(rules(3, job(3, 200, 100)), ["divisor-above-end"]);
+        assert_eq!(rules(4, job(7, 7, 100)), ["duplicate-divisor"]);
+        assert_eq!(rules(5, job(1, 4, 100)), ["dense-pair"]);
+        assert_eq!(rules(6, job(9, 3, 100)), ["redundant-divisor"]);
+        assert_eq!(rules(7, job(5, 3, 100)), ["duplicate-line"]);
+        assert_eq!(rules(8, job(3, 5, 10_000_000)), ["large-output"]);
+
+        let findings = linter.check(9, &job(3, 5, 100)).unwrap();
+        assert_eq!(findings[0].to_string(), "9: warning[duplicate-line]: same job as line 1");
+        let err = anyhow::anyhow!("Line 10 does not contain exactly 3 numbers");
+        assert_eq!(Finding::invalid(10, &err).to_string(), "10: error[invalid-line]: Line 10 does not contain exactly 3 numbers");
+        assert!(Severity::Note < Severity::Warning && Severity::Warning < Severity::Error);
+        assert_eq!("warnings".parse::<Severity>().unwrap(), Severity::Warning);
+        assert!("errors".parse::<Severity>().is_err());
+    }
+}
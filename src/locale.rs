@@ -0,0 +1,431 @@
+//! Translations of user-facing messages, gettext-style: a message is looked
+//! up by its English text in the catalog of the language the messages are
+//! shown in, and stays English when the catalog has no translation for it.
+//!
+//! The language is set once per run from `--lang` or the locale of the
+//! environment (`LANGUAGE`, `LC_ALL`, `LC_MESSAGES`, `LANG`); without it
+//! messages are English, as they are for the server.
+
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// A language messages can be shown in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Lang {
+    #[default]
+    En,
+    De,
+}
+
+/// The languages there are catalogs for, by the name `--lang` takes.
+pub const LANGUAGES: &[(&str, Lang)] = &[("en", Lang::En), ("de", Lang::De)];
+
+impl Lang {
+    /// The language of a locale name such as `de`, `de_AT.UTF-8` or `en-US`;
+    /// `C` and `POSIX` are English.
+    pub fn from_locale(locale: &str) -> Option<Lang> {
+        let language = locale.split(['_', '-', '.', '@']).next()?.to_ascii_lowercase();
+        match language.as_str() {
+            "c" | "posix" => Some(Lang::En),
+            language => LANGUAGES.iter().find(|(name, _)| *name == language).map(|&(_, lang)| lang),
+        }
+    }
+
+    /// The language of the environment: the first of `LANGUAGE` (a list
+    /// separated by colons), `LC_ALL`, `LC_MESSAGES` and `LANG` that is set
+    /// and names a language there is a catalog for, or English.
+    pub fn detect(var: impl Fn(&str) -> Option<String>) -> Lang {
+        let languages = var("LANGUAGE").unwrap_or_default();
+        let locales = ["LC_ALL", "LC_MESSAGES", "LANG"].into_iter().filter_map(&var);
+        let candidates = languages.split(':').map(String::from).chain(locales);
+        candidates.filter(|locale| !locale.is_empty()).find_map(|locale| Lang::from_locale(&locale)).unwrap_or_default()
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Lang::En => &[],
+            Lang::De => DE,
+        }
+    }
+
+    /// The translation of `msgid`, or `msgid` itself.
+    pub fn tr(self, msgid: &'static str) -> &'static str {
+        self.catalog().iter().find(|(id, _)| *id == msgid).map_or(msgid, |&(_, msgstr)| msgstr)
+    }
+
+    /// The translation of `msgid` with its `{}` placeholders replaced by
+    /// `args`, in order.
+    pub fn tr_args(self, msgid: &'static str, args: &[&dyn Display]) -> String {
+        let mut args = args.iter();
+        let mut parts = self.tr(msgid).split("{}");
+        let mut text = parts.next().unwrap_or_default().to_string();
+        for part in parts {
+            if let Some(arg) = args.next() {
+                text.push_str(&arg.to_string());
+            }
+            text.push_str(part);
+        }
+        text
+    }
+}
+
+static LANGUAGE: OnceLock<Lang> = OnceLock::new();
+
+/// Sets the language of this run's messages. Only the first call counts.
+pub fn set_language(lang: Lang) {
+    let _ = LANGUAGE.set(lang);
+}
+
+/// The language of this run's messages, English unless set.
+pub fn language() -> Lang {
+    LANGUAGE.get().copied().unwrap_or_default()
+}
+
+/// [`Lang::tr`] in the language of the run.
+pub fn tr(msgid: &'static str) -> &'static str {
+    language().tr(msgid)
+}
+
+/// [`Lang::tr_args`] in the language of the run.
+pub fn tr_args(msgid: &'static str, args: &[&dyn Display]) -> String {
+    language().tr_args(msgid, args)
+}
+
+/// German messages.
+const DE: &[(&str, &str)] = &[
+    // Reports.
+    ("Error", "Fehler"),
+    ("Caused by:", "Verursacht durch:"),
+    ("For more information about this error, try `{} explain-error {}`.", "Mehr zu diesem Fehler zeigt `{} explain-error {}`."),
+    ("Usage: {} [options] <input> <output>", "Aufruf: {} [Optionen] <Eingabe> <Ausgabe>"),
+    ("Usage: {} explain-error <code>", "Aufruf: {} explain-error <Code>"),
+    ("Unknown error code: {} (run explain-error without one to list them)", "Unbekannter Fehlercode: {} (explain-error ohne Code listet alle auf)"),
+    ("Unknown language: {} (available: {})", "Unbekannte Sprache: {} (verfügbar: {})"),
+    ("{} cases, {} passed, {} failed", "{} Fälle, {} bestanden, {} fehlgeschlagen"),
+    (
+        "\
+Environment (used when run without arguments):
+  MULTIPLES_JOB           the jobs to run, \"a b end\" lines separated by newlines or ';'
+  MULTIPLES_FORMAT        text, json, ndjson, csv, rust-array, c-array or python-list
+                          (default: text)
+  MULTIPLES_MODE          or, xor or multiset (default: or)
+  MULTIPLES_COUNT_ONLY    1 or true to write end:count instead of the multiples
+  MULTIPLES_OUTPUT        file to write to instead of stdout",
+        "\
+Umgebung (beim Aufruf ohne Argumente):
+  MULTIPLES_JOB           die Aufträge, \"a b end\"-Zeilen getrennt durch Zeilenumbrüche oder ';'
+  MULTIPLES_FORMAT        text, json, ndjson, csv, rust-array, c-array oder python-list
+                          (Standard: text)
+  MULTIPLES_MODE          or, xor oder multiset (Standard: or)
+  MULTIPLES_COUNT_ONLY    1 oder true, um end:Anzahl statt der Vielfachen zu schreiben
+  MULTIPLES_OUTPUT        Datei, in die statt auf stdout geschrieben wird",
+    ),
+    // Errors.
+    ("Failed to generate divisible numbers", "Erzeugen der teilbaren Zahlen fehlgeschlagen"),
+    ("Failed to read line {}", "Lesen von Zeile {} fehlgeschlagen"),
+    ("Input file does not exist: {}", "Eingabedatei existiert nicht: {}"),
+    ("Line {} does not contain exactly 3 numbers", "Zeile {} enthält nicht genau 3 Zahlen"),
+    ("Line {} names an empty output", "Zeile {} nennt eine leere Ausgabe"),
+    ("Unknown priority: {} (expected low, normal or high)", "Unbekannte Priorität: {} (erwartet: low, normal oder high)"),
+    ("Line {} has an output that isn't a string", "Die Ausgabe in Zeile {} ist keine Zeichenkette"),
+    ("Line {} has a priority that isn't a string", "Die Priorität in Zeile {} ist keine Zeichenkette"),
+    ("Unknown bound: {} (expected inclusive or exclusive)", "Unbekannte Grenze: {} (erwartet: inclusive oder exclusive)"),
+    ("Line {} has a bound that isn't a string", "Die Grenze in Zeile {} ist keine Zeichenkette"),
+    ("Line {} has an empty tag", "Zeile {} hat ein leeres Tag"),
+    ("Line {} has a number too large for 32 bits: {}", "Zeile {} hat eine Zahl, die nicht in 32 Bit passt: {}"),
+    ("Line {} has a tag that isn't a string", "Ein Tag in Zeile {} ist keine Zeichenkette"),
+    ("Line {} has tags that aren't an array", "Die Tags in Zeile {} sind keine Liste"),
+    ("Line {} is neither an object nor an array", "Zeile {} ist weder ein Objekt noch ein Array"),
+    ("JSON input must be an array", "JSON-Eingaben müssen ein Array sein"),
+    ("Unknown option: {}", "Unbekannte Option: {}"),
+    ("Missing value for {}", "Fehlender Wert für {}"),
+    ("Invalid value for {}: {}", "Ungültiger Wert für {}: {}"),
+    ("Invalid value for {}: must be a positive count", "Ungültiger Wert für {}: muss eine positive Anzahl sein"),
+    // Error codes.
+    ("Malformed input line", "Fehlerhafte Eingabezeile"),
+    (
+        "\
+An input line doesn't describe a job: text and CSV lines need exactly three numbers, a, b and end, \
+JSON input must be an array of objects or arrays, and NDJSON lines must each be one of those.
+
+Tokens that aren't numbers are not counted, so a typo such as `3 5 l00` leaves a line with two numbers, \
+while a number too large for 32 bits, such as `2^40`, is an error of its own in any format. Check the \
+line named in the message, or run with --fix to write a cleaned copy of the input and a report of the \
+lines it dropped.",
+        "\
+Eine Eingabezeile beschreibt keinen Auftrag: Text- und CSV-Zeilen brauchen genau drei Zahlen, a, b und \
+end, JSON-Eingaben müssen ein Array von Objekten oder Arrays sein, und NDJSON-Zeilen jeweils eines davon.
+
+Wörter, die keine Zahlen sind, werden nicht mitgezählt, sodass ein Tippfehler wie `3 5 l00` eine Zeile \
+mit zwei Zahlen ergibt, während eine Zahl, die nicht in 32 Bit passt, etwa `2^40`, in jedem Format ein \
+eigener Fehler ist. Prüfe die in der Meldung genannte Zeile, oder schreibe mit --fix eine bereinigte \
+Kopie der Eingabe und einen Bericht über die entfernten Zeilen.",
+    ),
+    ("Invalid line option", "Ungültige Zeilenoption"),
+    (
+        "\
+An input line carries a priority or an output that can't be used: priorities are prio=low, prio=normal \
+or prio=high (a \"priority\" string in JSON), and the path after `>` (an \"output\" string in JSON) must \
+not be empty.
+
+Fix or remove the option on the line named in the message.",
+        "\
+Eine Eingabezeile trägt eine unbrauchbare Priorität oder Ausgabe: Prioritäten sind prio=low, prio=normal \
+oder prio=high (eine \"priority\"-Zeichenkette in JSON), und der Pfad nach `>` (eine \"output\"-Zeichenkette \
+in JSON) darf nicht leer sein.
+
+Korrigiere oder entferne die Option in der in der Meldung genannten Zeile.",
+    ),
+    ("Unknown option", "Unbekannte Option"),
+    (
+        "\
+The command line, or a --config file, holds an option the command doesn't know. Options differ between \
+commands: run the command without arguments to print its usage and options.
+
+Options of a newer release, or options spelled differently, are a common cause. Configuration file keys \
+are the option names without the leading dashes.",
+        "\
+Die Befehlszeile oder eine --config-Datei enthält eine Option, die der Befehl nicht kennt. Die Optionen \
+unterscheiden sich zwischen den Befehlen: ohne Argumente aufgerufen, zeigt ein Befehl seinen Aufruf und \
+seine Optionen.
+
+Häufige Ursachen sind Optionen einer neueren Version oder anders geschriebene Optionen. Die Schlüssel \
+einer Konfigurationsdatei sind die Namen der Optionen ohne die führenden Striche.",
+    ),
+    ("Missing or invalid option value", "Fehlender oder ungültiger Optionswert"),
+    (
+        "\
+An option that takes a value was given none, or one it can't use, such as --threads 0 or --max-end abc.
+
+Numbers may be written as literals such as 1000, 1_000, 1e3, 1k or 0x3e8; counts must be positive. The \
+usage of the command lists what each option accepts.",
+        "\
+Eine Option, die einen Wert erwartet, hat keinen oder einen unbrauchbaren bekommen, etwa --threads 0 oder \
+--max-end abc.
+
+Zahlen können als 1000, 1_000, 1e3, 1k oder 0x3e8 geschrieben werden; Anzahlen müssen positiv sein. Der \
+Aufruf des Befehls zeigt, was jede Option annimmt.",
+    ),
+    ("Unknown format, mode or strategy", "Unbekanntes Format, Modus oder Strategie"),
+    (
+        "\
+A name given to an option such as --input-format, --output-format, --mode or --strategy isn't one this \
+build knows; the message lists the available ones.
+
+Formats loaded with --plugin are only known once the plugin is, and builds without the `plugins` \
+feature don't load any.",
+        "\
+Ein Name, der einer Option wie --input-format, --output-format, --mode oder --strategy gegeben wurde, ist \
+diesem Build unbekannt; die Meldung listet die verfügbaren auf.
+
+Mit --plugin geladene Formate sind erst bekannt, wenn das Plugin geladen ist, und Builds ohne das Feature \
+`plugins` laden keine.",
+    ),
+    ("Not supported by the output format", "Vom Ausgabeformat nicht unterstützt"),
+    (
+        "\
+The output format can't do what the run asks of it: only some formats support --annotate, --group-by, \
+--append, count-only records or lines too large for memory.
+
+Pick another --output-format, such as text or ndjson, or leave out the option the message names.",
+        "\
+Das Ausgabeformat kann nicht, was der Lauf verlangt: nur manche Formate unterstützen --annotate, \
+--group-by, --append, reine Anzahlen oder Zeilen, die zu groß für den Speicher sind.
+
+Wähle ein anderes --output-format, etwa text oder ndjson, oder lass die genannte Option weg.",
+    ),
+    ("File not found", "Datei nicht gefunden"),
+    (
+        "\
+A file the run needs, usually the input, a --config file or a plugin, doesn't exist.
+
+Relative paths are taken from the current directory, not from the location of the program or of the \
+configuration file. Check the path in the message.",
+        "\
+Eine Datei, die der Lauf braucht, meist die Eingabe, eine --config-Datei oder ein Plugin, existiert \
+nicht.
+
+Relative Pfade gelten ab dem aktuellen Verzeichnis, nicht ab dem Ort des Programms oder der \
+Konfigurationsdatei. Prüfe den Pfad in der Meldung.",
+    ),
+    ("Input or output failed", "Ein- oder Ausgabe fehlgeschlagen"),
+    (
+        "\
+Reading or writing a file, a socket or the terminal failed, e.g. because of missing permissions, a full \
+disk, an input that isn't UTF-8 text or a closed connection.
+
+The message ends with the error the operating system reported. Output written before the error may be \
+incomplete: rerun once the cause is fixed, or continue the output with --append.",
+        "\
+Das Lesen oder Schreiben einer Datei, eines Sockets oder des Terminals ist fehlgeschlagen, etwa wegen \
+fehlender Rechte, einer vollen Platte, einer Eingabe, die kein UTF-8-Text ist, oder einer geschlossenen \
+Verbindung.
+
+Die Meldung endet mit dem Fehler, den das Betriebssystem gemeldet hat. Vor dem Fehler geschriebene \
+Ausgaben können unvollständig sein: starte den Lauf neu, sobald die Ursache behoben ist, oder setze die \
+Ausgabe mit --append fort.",
+    ),
+    ("Line exceeds the memory limit", "Zeile überschreitet die Speichergrenze"),
+    (
+        "\
+With --strict-memory, a line whose multiples would take more memory than --max-memory, or than the \
+available memory, fails the run instead of being written as it is generated.
+
+Leave out --strict-memory to stream such lines, raise --max-memory, split the line's range with --shard, \
+or write only the count with --count-only.",
+        "\
+Mit --strict-memory lässt eine Zeile, deren Vielfache mehr Speicher als --max-memory oder als der \
+verfügbare Speicher bräuchten, den Lauf fehlschlagen, statt beim Erzeugen geschrieben zu werden.
+
+Lass --strict-memory weg, um solche Zeilen zu streamen, erhöhe --max-memory, teile den Bereich der Zeile \
+mit --shard auf, oder schreibe mit --count-only nur die Anzahl.",
+    ),
+    ("Cross-check failed", "Gegenprüfung fehlgeschlagen"),
+    (
+        "\
+With --cross-check, a record differed from plain trial division on one of the windows it was checked \
+on. This is a bug in the strategy or kernel named in the message, not in the input.
+
+Rerun with --strategy trial and --force-scalar to get correct output meanwhile, and report the message, \
+which names the job and the first value that differs.",
+        "\
+Mit --cross-check wich ein Datensatz in einem der geprüften Fenster von einfacher Probedivision ab. Das \
+ist ein Fehler in der Strategie oder dem Kernel, die die Meldung nennt, nicht in der Eingabe.
+
+Starte den Lauf bis dahin mit --strategy trial und --force-scalar neu, um eine korrekte Ausgabe zu \
+bekommen, und melde die Meldung, die den Auftrag und den ersten abweichenden Wert nennt.",
+    ),
+    ("Power of a divisor too large", "Potenz eines Teilers zu groß"),
+    (
+        "\
+With --power k, a divisor raised to the k-th power doesn't fit into 32 bits, the largest divisor there \
+is.
+
+A divisor that large has no multiples below any end that fits into 32 bits either, so lower --power or \
+remove the line.",
+        "\
+Mit --power k passt ein Teiler hoch k nicht in 32 Bit, den größten Teiler, den es gibt.
+
+Ein so großer Teiler hat auch unter keinem end, das in 32 Bit passt, Vielfache; verringere also --power \
+oder entferne die Zeile.",
+    ),
+    ("Degenerate input line", "Entartete Eingabezeile"),
+    (
+        "\
+With --degenerate error, an input line has nothing to compute: its divisors are equal, one of them is 1, \
+which divides every number, or 0, one divisor adds no multiples to the other, or end is below both \
+divisors.
+
+Fix or remove the line named in the message, or pass --degenerate warn or --degenerate simplify to \
+generate such lines anyway.",
+        "\
+Mit --degenerate error gibt es für eine Eingabezeile nichts zu berechnen: ihre Teiler sind gleich, einer \
+von ihnen ist 1, die jede Zahl teilt, oder 0, ein Teiler fügt dem anderen keine Vielfachen hinzu, oder \
+end liegt unter beiden Teilern.
+
+Korrigiere oder entferne die in der Meldung genannte Zeile, oder gib --degenerate warn oder --degenerate \
+simplify an, um solche Zeilen trotzdem zu erzeugen.",
+    ),
+    ("Input exceeds a size limit", "Eingabe überschreitet eine Größengrenze"),
+    (
+        "\
+The input has a line longer than --max-line-length bytes, a line of more than --max-tokens tokens, more \
+than --max-lines lines, or jobs selecting more than --max-multiples multiples together. serve applies \
+the same limits to every upload, with defaults for inputs from untrusted clients.
+
+Split the input, fix the line named in the message, or raise the limit if the input is meant to be that \
+large.",
+        "\
+Die Eingabe hat eine Zeile mit mehr als --max-line-length Bytes, eine Zeile mit mehr als --max-tokens \
+Tokens, mehr als --max-lines Zeilen, oder Aufträge, die zusammen mehr als --max-multiples Vielfache \
+auswählen. serve wendet dieselben Grenzen auf jeden Upload an, mit Vorgaben für Eingaben nicht \
+vertrauenswürdiger Clients.
+
+Teile die Eingabe auf, korrigiere die in der Meldung genannte Zeile, oder erhöhe die Grenze, wenn die \
+Eingabe so groß sein soll.",
+    ),
+    ("Corrupted result record", "Beschädigter Ergebnisdatensatz"),
+    (
+        "\
+A record of a result file written with --checksums doesn't match the crc32 stored with it: the record, \
+or its checksum, changed after it was written, such as by a failing disk or network storage, or by an \
+edit. Every command reading result files checks the checksums of the records that have one.
+
+Regenerate the file, or at least the record named in the message, from its input; the other records \
+can still be read by removing that one.",
+        "\
+Ein Datensatz einer mit --checksums geschriebenen Ergebnisdatei passt nicht zu der mit ihm gespeicherten \
+crc32: der Datensatz oder seine Prüfsumme hat sich nach dem Schreiben geändert, etwa durch eine defekte \
+Festplatte, einen Netzwerkspeicher oder eine Bearbeitung. Jeder Befehl, der Ergebnisdateien liest, prüft \
+die Prüfsummen der Datensätze, die eine haben.
+
+Erzeuge die Datei, oder zumindest den in der Meldung genannten Datensatz, neu aus ihrer Eingabe; die \
+übrigen Datensätze lassen sich noch lesen, wenn dieser entfernt wird.",
+    ),
+    ("Invalid command line", "Ungültige Befehlszeile"),
+    (
+        "\
+The command line, or a --config file, can't be run as given: options that exclude each other, such as \
+--annotate and --group-by, an option that needs another one, such as --scale without --decimal, or a \
+value that doesn't fit the others, such as --shard 5/3. The message names the options.
+
+Run the command without arguments to print its usage and options, and leave out or add the options \
+the message names.",
+        "\
+Die Befehlszeile oder eine --config-Datei lässt sich so nicht ausführen: Optionen, die einander \
+ausschließen, etwa --annotate und --group-by, eine Option, die eine andere braucht, etwa --scale ohne \
+--decimal, oder ein Wert, der nicht zu den übrigen passt, etwa --shard 5/3. Die Meldung nennt die Optionen.
+
+Rufe den Befehl ohne Argumente auf, um seine Verwendung und Optionen anzuzeigen, und lass die in der \
+Meldung genannten Optionen weg oder ergänze sie.",
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Lang, DE};
+    use crate::errors::CODES;
+
+    #[test]
+    fn test_from_locale() {
+        assert_eq!(Lang::from_locale("de_AT.UTF-8"), Some(Lang::De));
+        assert_eq!(Lang::from_locale("DE"), Some(Lang::De));
+        assert_eq!(Lang::from_locale("en-US"), Some(Lang::En));
+        assert_eq!(Lang::from_locale("C.UTF-8"), Some(Lang::En));
+        assert_eq!(Lang::from_locale("fi_FI"), None);
+
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            let vars: HashMap<_, _> = vars.iter().copied().collect();
+            Lang::detect(move |name| vars.get(name).map(|value| value.to_string()))
+        };
+        assert_eq!(env(&[]), Lang::En);
+        assert_eq!(env(&[("LANG", "de_DE.UTF-8")]), Lang::De);
+        assert_eq!(env(&[("LANG", "de_DE.UTF-8"), ("LC_ALL", "C")]), Lang::En);
+        assert_eq!(env(&[("LANGUAGE", "fi:de"), ("LANG", "en_US.UTF-8")]), Lang::De);
+        assert_eq!(env(&[("LC_MESSAGES", "fi_FI"), ("LANG", "de_DE")]), Lang::De);
+    }
+
+    #[test]
+    fn test_tr() {
+        assert_eq!(Lang::De.tr("Error"), "Fehler");
+        assert_eq!(Lang::De.tr("Not in the catalog"), "Not in the catalog");
+        assert_eq!(Lang::En.tr("Error"), "Error");
+        let line = 3;
+        assert_eq!(Lang::De.tr_args("Line {} does not contain exactly 3 numbers", &[&line]), "Zeile 3 enthält nicht genau 3 Zahlen");
+        assert_eq!(Lang::En.tr_args("Invalid value for {}: {}", &[&"--threads", &"Missing digits"]), "Invalid value for --threads: Missing digits");
+    }
+
+    #[test]
+    fn test_catalog() {
+        for (msgid, msgstr) in DE {
+            assert_eq!(msgid.matches("{}").count(), msgstr.matches("{}").count(), "{}", msgid);
+            assert_eq!(DE.iter().filter(|(id, _)| id == msgid).count(), 1, "{}", msgid);
+        }
+        for code in CODES {
+            assert_ne!(Lang::De.tr(code.title), code.title);
+            assert_ne!(Lang::De.tr(code.explanation), code.explanation);
+        }
+    }
+}
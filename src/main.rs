@@ -1,241 +1,1560 @@
+use std::collections::BTreeSet;
 use std::env;
-use std::fmt;
-use std::fs::File;
-use std::io::{self, BufRead, BufWriter, Write};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Cursor, Write};
+use std::net::TcpListener;
 use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 
-struct LineNumbers {
-    a: u32,
-    b: u32,
-    end: u32,
+use multiple_of_a_and_b::aggregate::{AggregateFormat, AggregateSink};
+use multiple_of_a_and_b::audit::{self, Access};
+use multiple_of_a_and_b::auth::{read_api_keys, Authenticator, JwtConfig};
+use multiple_of_a_and_b::backfill;
+use multiple_of_a_and_b::bench::{self, Contender};
+use multiple_of_a_and_b::chunk::Backpressure;
+use multiple_of_a_and_b::color;
+use multiple_of_a_and_b::compat::{Compat, LegacyTextParser};
+use multiple_of_a_and_b::config;
+use multiple_of_a_and_b::console;
+use multiple_of_a_and_b::decimal;
+use multiple_of_a_and_b::degenerate;
+use multiple_of_a_and_b::describe;
+use multiple_of_a_and_b::errors;
+use multiple_of_a_and_b::explain::{makespan, Calibration, Planner};
+use multiple_of_a_and_b::formatter::{
+    self, ArrayFormatter, FormatterRegistry, OutputFormatter, ARRAY_FORMATS, OUTPUT_SCHEMA, VERSIONED_FORMATS,
+};
+use multiple_of_a_and_b::formula::Mode;
+use multiple_of_a_and_b::gaps;
+use multiple_of_a_and_b::input::{Bound, FastTextParser, InputParserRegistry, JobIter};
+use multiple_of_a_and_b::jobfilter::TagFilter;
+use multiple_of_a_and_b::lint::{Finding, Linter};
+use multiple_of_a_and_b::locale::{self, tr, tr_args, Lang};
+use multiple_of_a_and_b::memory::{MemoryGuardSink, MemoryPolicy};
+use multiple_of_a_and_b::merge::merge_records;
+use multiple_of_a_and_b::otlp::OtlpExporter;
+use multiple_of_a_and_b::output::{
+    AppendSink, FsyncPolicy, IoBackend, LazyFile, MmapSink, OutputTarget, Routes, RoutingSink, SplitSink, StreamOutput,
+};
+use multiple_of_a_and_b::paths;
+use multiple_of_a_and_b::pipeline::{self, FormatterSink, Pipeline, Priorities, Sink};
+use multiple_of_a_and_b::predicate::{All, DigitSumDivisibleBy, EndsWith, Palindrome, Predicate};
+use multiple_of_a_and_b::preview::PreviewSink;
+use multiple_of_a_and_b::profile::{ProfiledFormatter, Profiler};
+use multiple_of_a_and_b::provenance::{self, Provenance};
+use multiple_of_a_and_b::progress::{Progress, ProgressReporter};
+use multiple_of_a_and_b::quantity::{describe_bytes, format_bytes, format_count};
+use multiple_of_a_and_b::random::random_jobs;
+use multiple_of_a_and_b::reader::{self, ResultReaderRegistry};
+use multiple_of_a_and_b::regress;
+use multiple_of_a_and_b::repair::repair;
+use multiple_of_a_and_b::report::{RunReport, Warning, Warnings};
+use multiple_of_a_and_b::roaring::{self, BitmapEntry, RoaringBitmap};
+use multiple_of_a_and_b::runlog;
+use multiple_of_a_and_b::schedule::{self, ScheduleStore};
+use multiple_of_a_and_b::selftest;
+use multiple_of_a_and_b::server::Server;
+use multiple_of_a_and_b::service::{self, Service};
+use multiple_of_a_and_b::setops::SetOperation;
+use multiple_of_a_and_b::shard::merge_shards;
+use multiple_of_a_and_b::sort::sort_external;
+use multiple_of_a_and_b::store::Store;
+use multiple_of_a_and_b::strategy::{GenerateOptions, RecordGenerator};
+use multiple_of_a_and_b::sweep::Sweep;
+use multiple_of_a_and_b::throttle;
+use multiple_of_a_and_b::timeline;
+use multiple_of_a_and_b::wizard::Wizard;
+use multiple_of_a_and_b::{LineNumbers, ResultNumbers, ResultRecord};
+
+mod cli;
+mod help;
+
+#[cfg(all(feature = "plugins", unix))]
+fn load_plugins(registry: &mut FormatterRegistry, plugins: &[PathBuf]) -> Result<()> {
+    for path in plugins {
+        multiple_of_a_and_b::plugin::load_formatter_plugin(registry, path)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "messaging")]
+fn message_sink(url: &str, format: &str, batch: Option<usize>) -> Result<Box<dyn Sink>> {
+    multiple_of_a_and_b::messaging::connect(url, format.parse()?, batch)
+}
+
+#[cfg(not(feature = "messaging"))]
+fn message_sink(_url: &str, _format: &str, _batch: Option<usize>) -> Result<Box<dyn Sink>> {
+    bail!("This build does not support message brokers (enable the `messaging` feature)");
+}
+
+#[cfg(not(all(feature = "plugins", unix)))]
+fn load_plugins(_registry: &mut FormatterRegistry, plugins: &[PathBuf]) -> Result<()> {
+    if !plugins.is_empty() {
+        anyhow::bail!("This build does not support formatter plugins (enable the `plugins` feature)");
+    }
+    Ok(())
+}
+
+/// Combines the digit filters given on the command line, or returns `None`
+/// when there are none.
+fn digit_filter(args: &cli::GenerateArgs) -> Result<Option<Box<dyn Predicate>>> {
+    let mut filter = All::default();
+    if let Some(divisor) = args.digit_sum_divisor {
+        filter = filter.with(DigitSumDivisibleBy(divisor));
+    }
+    if let Some(digits) = &args.ends_with {
+        filter = filter.with(EndsWith::new(digits)?);
+    }
+    if args.palindrome {
+        filter = filter.with(Palindrome);
+    }
+    Ok(if filter.0.is_empty() { None } else { Some(Box::new(filter)) })
+}
+
+/// The options besides the job itself that change how a record is written,
+/// so that appending regenerates records written with different ones.
+fn append_options(args: &cli::GenerateArgs) -> String {
+    let mut options = args.output_format.clone();
+    if args.mode != Mode::Or {
+        options.push_str(&format!(" --mode {}", args.mode.name()));
+    }
+    if let Some(divisor) = args.digit_sum_divisor {
+        options.push_str(&format!(" --digit-sum-divisible-by {}", divisor));
+    }
+    if let Some(digits) = &args.ends_with {
+        options.push_str(&format!(" --ends-with {}", digits));
+    }
+    if args.palindrome {
+        options.push_str(" --palindrome");
+    }
+    if args.annotate {
+        options.push_str(" --annotate");
+    }
+    if args.group_by_divisor {
+        options.push_str(" --group-by divisor");
+    }
+    if args.descending {
+        options.push_str(" --descending");
+    }
+    if args.count_only {
+        options.push_str(" --count-only");
+    }
+    if let Some(power) = args.power {
+        options.push_str(&format!(" --power {}", power));
+    }
+    if let Some(shard) = args.shard {
+        options.push_str(&format!(" --shard {}", shard));
+    }
+    if let Some(name) = &args.array_name {
+        options.push_str(&format!(" --array-name {}", name));
+    }
+    options
+}
+
+/// The sink of a generate run: its output, plus the outputs named by lines
+/// of the input, which are noted in `routes` as the input is read.
+fn output_sink(
+    args: &cli::GenerateArgs,
+    registry: FormatterRegistry,
+    profiler: Option<Arc<Profiler>>,
+    routes: Routes,
+    provenance: Option<Provenance>,
+) -> Result<Box<dyn Sink>> {
+    let registry = Rc::new(registry);
+    let format = args.output_format.clone();
+    let (annotate, group_by_divisor, checksums) = (args.annotate, args.group_by_divisor, args.checksums);
+    let line_ending = args.line_ending;
+    let format_threads = args.format_threads;
+    let create = move || {
+        let mut formatter = registry.create(&format)?;
+        formatter.format_threads(format_threads);
+        if annotate {
+            formatter
+                .annotate()
+                .with_context(|| format!("Can't annotate {} output", format))?;
+        }
+        if group_by_divisor {
+            formatter
+                .group_by_divisor()
+                .with_context(|| format!("Can't group {} output by divisor", format))?;
+        }
+        if checksums {
+            formatter
+                .checksum()
+                .with_context(|| format!("Can't write checksums in {} output", format))?;
+        }
+        if let Some(provenance) = &provenance {
+            formatter = provenance.apply(&format, formatter);
+        }
+        formatter = line_ending.apply(&format, formatter);
+        if let Some(profiler) = &profiler {
+            formatter = Box::new(ProfiledFormatter::new(formatter, profiler.clone()));
+        }
+        Ok(formatter)
+    };
+    let sink = primary_sink(args, create.clone())?;
+    let shared = args.append || args.max_output_size.is_some() || args.io_backend == IoBackend::Mmap;
+    let policy = args.write_policy;
+    let route_sink = move |path: &Path| -> Result<Box<dyn Sink>> {
+        audit::check(path, Access::Write)?;
+        if shared {
+            bail!("Lines naming their own output can't be combined with --append, --max-output-size or --io-backend mmap");
+        }
+        let target = OutputTarget::parse(path)?;
+        match target {
+            OutputTarget::Broker(url) => bail!("Lines can't name a message broker as their output: {}", url),
+            OutputTarget::File(_) => {
+                if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                    fs::create_dir_all(paths::native(parent)).with_context(|| format!("Failed to create {:?}", parent))?;
+                }
+                Ok(Box::new(FormatterSink::new(LazyFile::new(path).with_policy(policy), create()?)))
+            }
+            _ => Ok(Box::new(FormatterSink::new(StreamOutput::connect(&target)?, create()?))),
+        }
+    };
+    Ok(Box::new(RoutingSink::new(&args.output, sink, routes, route_sink)))
+}
+
+/// The jobs of a generate run with `--io-backend mmap`.
+#[cfg(unix)]
+fn mapped_source(
+    args: &cli::GenerateArgs,
+    parsers: &InputParserRegistry,
+    routes: Routes,
+    priorities: Priorities,
+) -> Result<JobIter<'static>> {
+    let format = args.input_format.as_deref();
+    pipeline::mapped_file_source(parsers, &args.input, format, routes, priorities, args.bound, &args.tags, args.threads)
 }
 
-#[derive(PartialEq)]
-struct ResultNumbers {
-    end: u32,
-    numbers: Vec<u32>,
+#[cfg(not(unix))]
+fn mapped_source(
+    _args: &cli::GenerateArgs,
+    _parsers: &InputParserRegistry,
+    _routes: Routes,
+    _priorities: Priorities,
+) -> Result<JobIter<'static>> {
+    bail!("The mmap I/O backend is not supported on this platform")
 }
 
-impl fmt::Display for ResultNumbers {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let numbers_str = self
-            .numbers
-            .iter()
-            .map(|num| num.to_string())
-            .collect::<Vec<String>>()
-            .join(" ");
-        write!(f, "{}:{}", self.end, numbers_str)
+/// Refuses an input over the limits of `--max-lines` and the like before
+/// any of it is generated.
+fn check_limits(args: &cli::GenerateArgs, parsers: &InputParserRegistry, generator: &RecordGenerator) -> Result<()> {
+    let limits = &args.limits;
+    let refused = || format!("{:?} exceeds an input limit", args.input);
+    if limits.limits_text() {
+        let file = audit::open(&args.input).with_context(|| format!("Failed to open file: {:?}", args.input))?;
+        limits.check_text(BufReader::new(file)).with_context(refused)?;
+    }
+    if limits.max_multiples.is_some() && !args.count_only {
+        // Lines that don't parse are left for the run itself to report.
+        let format = args.input_format.as_deref();
+        let (routes, priorities) = (Routes::default(), Priorities::default());
+        let jobs = pipeline::routed_file_source(parsers, &args.input, format, routes, priorities, args.bound, &args.tags)?;
+        let power = args.power;
+        let jobs = jobs.filter_map(|job| match power {
+            Some(power) => job.ok()?.with_power(power).ok(),
+            None => job.ok(),
+        });
+        limits.check_multiples(jobs.map(|job| generator.predicted_count(&job))).with_context(refused)?;
     }
+    Ok(())
 }
 
-fn read_lines<P>(filename: P) -> Result<io::Lines<io::BufReader<File>>>
-    where
-        P: AsRef<Path>,
+/// The sink of the output given on the command line.
+fn primary_sink<F>(args: &cli::GenerateArgs, create: F) -> Result<Box<dyn Sink>>
+where
+    F: Fn() -> Result<Box<dyn OutputFormatter>> + 'static,
 {
-    let path = filename.as_ref();
-    let file = File::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
-    Ok(io::BufReader::new(file).lines())
-}
-
-fn read_items(input: &PathBuf) -> Result<Vec<LineNumbers>> {
-    let mut results = Vec::new();
-    let lines = read_lines(input).context("Failed to read lines from file")?;
-
-    for (line_num, line) in lines.enumerate() {
-        let line = line.with_context(|| format!("Failed to read line {}", line_num + 1))?;
-        let numbers: Vec<u32> = line
-            .split_whitespace()
-            .filter_map(|n| n.parse::<u32>().ok())
-            .collect();
-
-        if numbers.len() == 3 {
-            results.push(LineNumbers {
-                a: numbers[0],
-                b: numbers[1],
-                end: numbers[2],
-            });
-        } else {
-            return Err(anyhow::anyhow!("Line {} does not contain exactly 3 numbers", line_num + 1));
+    let output = &args.output;
+    let (annotate, group_by_divisor) = (args.annotate, args.group_by_divisor);
+    // Creating one formatter up front reports an unknown format or an
+    // unsupported option before any work is done.
+    let formatter = create()?;
+    let target = OutputTarget::parse(output)?;
+    if target.is_stream() {
+        if args.append || args.max_output_size.is_some() || args.io_backend == IoBackend::Mmap {
+            bail!("--append, --max-output-size and --io-backend mmap need a regular output file");
+        }
+        if let OutputTarget::Broker(url) = &target {
+            if annotate || group_by_divisor {
+                bail!("--annotate and --group-by can't be used with a message broker");
+            }
+            return message_sink(url, &args.sink_format, args.sink_batch);
+        }
+        return Ok(Box::new(FormatterSink::new(StreamOutput::connect(&target)?, formatter)));
+    }
+    if args.append {
+        let sink = AppendSink::new(output, formatter, &append_options(args))?;
+        return Ok(Box::new(sink.with_policy(args.write_policy)));
+    }
+    if let Some(max_size) = args.max_output_size {
+        return Ok(Box::new(SplitSink::new(output, max_size, create)?.with_policy(args.write_policy)));
+    }
+    Ok(match args.io_backend {
+        IoBackend::Buffered => {
+            let file = LazyFile::new(output).with_policy(args.write_policy);
+            Box::new(FormatterSink::new(file, formatter))
         }
+        IoBackend::Mmap if args.write_policy.fsync == FsyncPolicy::PerFile => {
+            Box::new(MmapSink::new(output, create).sync_on_finish())
+        }
+        IoBackend::Mmap => Box::new(MmapSink::new(output, create)),
+    })
+}
+
+/// Writes already generated records to `output` in the named format.
+fn write_records<I>(output: &Path, format: &str, records: I, checksums: bool) -> Result<()>
+where
+    I: IntoIterator<Item = Result<ResultRecord>>,
+{
+    let mut formatter = FormatterRegistry::with_builtins().create(format)?;
+    if checksums {
+        formatter.checksum().with_context(|| format!("Can't write checksums in {} output", format))?;
+    }
+    let mut sink = FormatterSink::new(LazyFile::new(output), formatter);
+    for record in records {
+        sink.write(record?)?;
     }
+    sink.finish()
+}
+
+fn run_merge(program: &str, args: &[String]) -> Result<()> {
+    let Some(merge) = cli::parse_merge_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} merge [options] <inputs>... <output>\n\n{}", program, cli::MERGE_OPTIONS);
+        std::process::exit(1);
+    };
 
-    Ok(results)
+    let registry = ResultReaderRegistry::with_builtins();
+    let output_format = match &merge.output_format {
+        Some(format) => format.clone(),
+        None => registry.detect(&merge.inputs[0], merge.input_format.as_deref()).to_string(),
+    };
+    let mut records = Vec::new();
+    for input in &merge.inputs {
+        reader::check_conversion(registry.detect(input, merge.input_format.as_deref()), &output_format)?;
+        records.extend(reader::read_records(input, merge.input_format.as_deref())?);
+    }
+    let merged = merge_records(records, merge.mode).into_iter().map(Ok);
+    write_records(&merge.output, &output_format, merged, merge.checksums).context("Failed to write merged results")
 }
 
-fn is_number_divisible_by(item: &LineNumbers, n: &u32) -> bool {
-    n % item.a == 0 || n % item.b == 0
+fn run_merge_shards(program: &str, args: &[String]) -> Result<()> {
+    let Some(merge) = cli::parse_merge_shards_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!(
+            "Usage: {} merge-shards [options] <shards>... <output>\n\n{}",
+            program,
+            cli::MERGE_SHARDS_OPTIONS
+        );
+        std::process::exit(1);
+    };
+
+    let registry = ResultReaderRegistry::with_builtins();
+    let output_format = match &merge.output_format {
+        Some(format) => format.clone(),
+        None => registry.detect(&merge.shards[0], merge.input_format.as_deref()).to_string(),
+    };
+    let mut shards = Vec::new();
+    for shard in &merge.shards {
+        shards.push(reader::read_records(shard, merge.input_format.as_deref())?);
+    }
+    let merged = merge_shards(shards, merge.verify, merge.descending).context("Failed to merge shards")?;
+    let merged = merged.into_iter().map(Ok);
+    write_records(&merge.output, &output_format, merged, false).context("Failed to write merged results")
 }
 
-fn generate_divisible_numbers(input: &PathBuf) -> Result<Vec<ResultNumbers>> {
-    let items = read_items(&input).context("Failed to read items from input file")?;
+fn run_backfill(program: &str, args: &[String]) -> Result<()> {
+    let Some(backfill) = cli::parse_backfill_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!(
+            "Usage: {} backfill --target <end> --stride <n> [options] <dir>\n\n{}",
+            program,
+            cli::BACKFILL_OPTIONS
+        );
+        std::process::exit(1);
+    };
 
-    let mut results: Vec<ResultNumbers> = items
-        .into_iter()
-        .map(|item| {
-            let numbers: Vec<u32> = (1..=item.end)
-                .filter(|n| is_number_divisible_by(&item, n))
-                .collect();
-            ResultNumbers {
-                end: item.end,
-                numbers,
-            }
-        })
-        .collect();
+    let registry = ResultReaderRegistry::with_builtins();
+    let plan = backfill::plan(&registry, &backfill.dir, backfill.stride, backfill.target, backfill.mode, &backfill.naming)?;
+    for (path, reason) in &plan.skipped {
+        eprintln!("Skipped {:?}: {}", path, reason);
+    }
+    if plan.fills.is_empty() {
+        eprintln!("Nothing to backfill: every end of the series has a file");
+        return Ok(());
+    }
+    let describe = |fill: &backfill::Fill| match &fill.base {
+        Some(base) => format!("{:?} from {:?}", fill.output, base),
+        None => format!("{:?} from 1", fill.output),
+    };
+    if backfill.dry_run {
+        plan.fills.iter().for_each(|fill| println!("Would generate {}", describe(fill)));
+        return Ok(());
+    }
+    backfill::backfill(&plan, backfill.mode, |fill, record| {
+        let mut formatter = FormatterRegistry::with_builtins().create(&fill.format)?;
+        let mut file = BufWriter::new(audit::create(&fill.output)?);
+        formatter.begin(&mut file)?;
+        formatter.write_record(&mut file, record)?;
+        formatter.finish(&mut file)?;
+        file.flush()?;
+        eprintln!("Generated {}", describe(fill));
+        Ok(())
+    })
+}
+
+fn run_sweep(program: &str, args: &[String]) -> Result<()> {
+    let Some(sweep) = cli::parse_sweep_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!(
+            "Usage: {} sweep --a <values> --b <values> --end <values> [options] <dir> [-- <generate options>]\n\n{}",
+            program,
+            cli::SWEEP_OPTIONS
+        );
+        std::process::exit(1);
+    };
+
+    let jobs = sweep.sweep.jobs()?;
+    if jobs.is_empty() {
+        bail!("No combination of the sweep meets its constraints");
+    }
+    let extension = formatter::extension(&sweep.output_format);
+    if sweep.dry_run {
+        for job in &jobs {
+            println!("{} {} {} > {}", job.a(), job.b(), job.end(), Sweep::output(&sweep.dir, job, extension).display());
+        }
+        return Ok(());
+    }
+    for job in &jobs {
+        let output = Sweep::output(&sweep.dir, job, extension);
+        let parent = output.parent().unwrap();
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {:?}", parent))?;
+    }
+    // The input is kept next to the outputs as the record of what was run.
+    let input = sweep.dir.join("sweep.txt");
+    fs::write(&input, Sweep::input(&sweep.dir, &jobs, extension)).with_context(|| format!("Failed to write {:?}", input))?;
 
-    results.sort_by(|a, b| a.numbers.len().cmp(&b.numbers.len()));
-    Ok(results)
+    // Every line names its output; the run's own is that of the first one,
+    // which goes there like the others.
+    let exe = env::current_exe().context("Failed to find the executable to run the sweep with")?;
+    let status = Command::new(&exe)
+        .arg("--output-format")
+        .arg(&sweep.output_format)
+        .args(&sweep.options)
+        .arg(&input)
+        .arg(Sweep::output(&sweep.dir, &jobs[0], extension))
+        .stdout(Stdio::null())
+        .status()
+        .context("Failed to run the sweep")?;
+    if !status.success() {
+        bail!("The run of the sweep failed; its input is {:?}", input);
+    }
+    eprintln!("Ran {} jobs into {:?}", jobs.len(), sweep.dir);
+    Ok(())
 }
 
-fn write_results(output: &PathBuf, results: Vec<ResultNumbers>) -> Result<()> {
-    let file = File::create(&output).with_context(|| format!("Failed to create output file: {:?}", output))?;
-    let mut out = BufWriter::new(file);
+fn run_convert(program: &str, args: &[String]) -> Result<()> {
+    let Some(convert) = cli::parse_convert_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} convert [options] <input> <output>\n\n{}", program, cli::CONVERT_OPTIONS);
+        std::process::exit(1);
+    };
 
-    for (index, result) in results.iter().enumerate() {
-        println!("{}", result);
-        writeln!(out, "{}", result).with_context(|| format!("Failed to write result {} to output file", index + 1))?;
+    let registry = ResultReaderRegistry::with_builtins();
+    let output_format = registry.detect(&convert.output, convert.output_format.as_deref());
+    reader::check_conversion(registry.detect(&convert.input, convert.input_format.as_deref()), output_format)?;
+    let records = registry.open(&convert.input, convert.input_format.as_deref())?;
+    write_records(&convert.output, output_format, records, convert.checksums)
+        .with_context(|| format!("Failed to convert {:?}", convert.input))
+}
+
+fn run_migrate(program: &str, args: &[String]) -> Result<()> {
+    let Some(migrate) = cli::parse_migrate_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} migrate [options] <input> <output>\n\n{}", program, cli::MIGRATE_OPTIONS);
+        std::process::exit(1);
+    };
+
+    let registry = ResultReaderRegistry::with_builtins();
+    let input_format = registry.detect(&migrate.input, migrate.input_format.as_deref());
+    if !VERSIONED_FORMATS.contains(&input_format) {
+        bail!("{} results carry no schema version; migrate upgrades {}", input_format, VERSIONED_FORMATS.join(", "));
+    }
+    // The input is read as the output is written.
+    if migrate.input == migrate.output {
+        bail!("migrate writes a new file; give an output other than the input");
+    }
+    let version = reader::schema_version(&migrate.input, input_format)?;
+    if version > OUTPUT_SCHEMA {
+        bail!("{:?} has schema version {}, newer than {}, the latest this build writes", migrate.input, version, OUTPUT_SCHEMA);
     }
+    let output_format = migrate.output_format.as_deref().unwrap_or(input_format);
+    let records = registry.open(&migrate.input, Some(input_format))?;
+    write_records(&migrate.output, output_format, records, migrate.checksums)
+        .with_context(|| format!("Failed to migrate {:?}", migrate.input))?;
+    eprintln!("Migrated {:?} from schema version {} to {}", migrate.input, version, OUTPUT_SCHEMA);
+    Ok(())
+}
+
+fn run_describe(program: &str, args: &[String]) -> Result<()> {
+    let Some(describe) = cli::parse_describe_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} describe [options] <file>\n\n{}", program, cli::DESCRIBE_OPTIONS);
+        std::process::exit(1);
+    };
+
+    let registry = ResultReaderRegistry::with_builtins();
+    let description = describe::describe(&registry, &describe.path, describe.input_format.as_deref(), describe.records)
+        .with_context(|| format!("Failed to describe {:?}", describe.path))?;
+    print!("{}", description);
+    Ok(())
+}
+
+fn run_provenance(program: &str, args: &[String]) -> Result<()> {
+    let Some(args) = cli::parse_provenance_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} provenance [options] <file>\n\n{}", program, cli::PROVENANCE_OPTIONS);
+        std::process::exit(1);
+    };
+
+    let provenance = provenance::read(&args.path)?;
+    println!("{}", provenance);
+    if args.verify {
+        let (checks, passed) = provenance.verify();
+        for check in checks {
+            println!("{}", check);
+        }
+        if !passed {
+            bail!("{:?} can't be verified as written by this build from its input", args.path);
+        }
+    }
+    Ok(())
+}
+
+fn run_sort(program: &str, args: &[String]) -> Result<()> {
+    let Some(sort) = cli::parse_sort_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} sort [options] <input> <output>\n\n{}", program, cli::SORT_OPTIONS);
+        std::process::exit(1);
+    };
+
+    let registry = ResultReaderRegistry::with_builtins();
+    let output_format = registry.detect(&sort.output, sort.output_format.as_deref());
+    reader::check_conversion(registry.detect(&sort.input, sort.input_format.as_deref()), output_format)?;
+    let records = registry.open(&sort.input, sort.input_format.as_deref())?;
+    let formatter = FormatterRegistry::with_builtins().create(output_format)?;
+    let mut sink = FormatterSink::new(LazyFile::new(&sort.output), formatter);
+    sort_external(records, sort.by, sort.max_memory, &sort.temp_dir, &mut |record| sink.write(record))
+        .and_then(|()| sink.finish())
+        .with_context(|| format!("Failed to sort {:?}", sort.input))
+}
 
-    out.flush().context("Failed to flush output buffer")?;
+fn run_setops(program: &str, args: &[String]) -> Result<()> {
+    let Some(setops) = cli::parse_setops_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} setops [options] <a1> <b1> <a2> <b2> <end>\n\n{}", program, cli::SETOPS_OPTIONS);
+        std::process::exit(1);
+    };
+
+    let operation = SetOperation::new(setops.op, setops.first, setops.second);
+    if setops.count_only {
+        println!("{}:{}", setops.end, operation.count(setops.end));
+    } else {
+        let numbers = operation.members(setops.end).collect();
+        let result = ResultNumbers {
+            end: setops.end,
+            numbers,
+            count: None,
+        };
+        println!("{}", result);
+    }
     Ok(())
 }
 
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 3 {
-        eprintln!("Usage: {} <input> <output>", args[0]);
+fn run_analyze(program: &str, args: &[String]) -> Result<()> {
+    let Some(analyze) = cli::parse_analyze_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} analyze gaps [options] <input> <output>\n\n{}", program, cli::ANALYZE_OPTIONS);
+        std::process::exit(1);
+    };
+
+    let source = pipeline::file_source(&analyze.input, analyze.input_format.as_deref())?;
+    let file = File::create(&analyze.output).with_context(|| format!("Failed to create {:?}", analyze.output))?;
+    let mut out = BufWriter::new(file);
+    let json = analyze.output_format == "json";
+    let percentiles = &analyze.percentiles;
+    if json {
+        write!(out, "[")?;
+    } else {
+        writeln!(out, "{}", gaps::text_header(percentiles))?;
+    }
+    for (index, job) in source.enumerate() {
+        let job = job?;
+        let stats = gaps::gap_stats(&job, analyze.mode);
+        if json {
+            let separator = if index == 0 { "\n" } else { ",\n" };
+            write!(out, "{}{}", separator, gaps::json_object(index + 1, &job, &stats, percentiles))?;
+        } else {
+            writeln!(out, "{}", gaps::text_row(index + 1, &job, &stats, percentiles))?;
+        }
+    }
+    if json {
+        writeln!(out, "\n]")?;
+    }
+    out.flush().with_context(|| format!("Failed to write {:?}", analyze.output))
+}
+
+fn run_bitmap(program: &str, args: &[String]) -> Result<()> {
+    let Some(bitmap) = cli::parse_bitmap_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} bitmap <action> [options] <inputs>...\n\n{}", program, cli::BITMAP_OPTIONS);
         std::process::exit(1);
+    };
+
+    let open = |path: &Path| -> Result<Vec<BitmapEntry>> {
+        let file = File::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
+        roaring::read_entries(Box::new(BufReader::new(file)))
+            .collect::<Result<_>>()
+            .with_context(|| format!("Failed to read bitmaps from {:?}", path))
+    };
+    let select = match bitmap.action {
+        cli::BitmapAction::Rank(n) => {
+            for entry in open(&bitmap.inputs[0])? {
+                println!("{} {} {} {}", entry.job.a(), entry.job.b(), entry.end, entry.bitmap.rank(n));
+            }
+            return Ok(());
+        }
+        cli::BitmapAction::Select(i) => {
+            for entry in open(&bitmap.inputs[0])? {
+                let n = entry.bitmap.select(i - 1).map_or_else(|| String::from("-"), |n| n.to_string());
+                println!("{} {} {} {}", entry.job.a(), entry.job.b(), entry.end, n);
+            }
+            return Ok(());
+        }
+        cli::BitmapAction::Union => RoaringBitmap::or,
+        cli::BitmapAction::Intersect => RoaringBitmap::and,
+    };
+
+    // Each input stands for the union of its records.
+    let mut result: Option<RoaringBitmap> = None;
+    let mut end = 0;
+    for path in &bitmap.inputs {
+        let entries = open(path)?;
+        end = entries.iter().map(|entry| entry.end).fold(end, u32::max);
+        let set = entries.iter().fold(RoaringBitmap::default(), |set, entry| set.or(&entry.bitmap));
+        result = Some(match result {
+            Some(result) => select(&result, &set),
+            None => set,
+        });
     }
+    let result = result.unwrap_or_default();
+    match &bitmap.output {
+        Some(output) => {
+            let record = BitmapEntry::combined(end, result).into_record();
+            write_records(output, "roaring", [Ok(record)], false)?;
+        }
+        None if bitmap.count_only => println!("{}:{}", end, result.len()),
+        None => {
+            let numbers = ResultNumbers {
+                end,
+                numbers: result.iter().collect(),
+                count: None,
+            };
+            println!("{}", numbers);
+        }
+    }
+    Ok(())
+}
+
+fn run_explain(program: &str, args: &[String]) -> Result<()> {
+    let Some(explain) = cli::parse_explain_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} explain [options] <input>\n\n{}", program, cli::EXPLAIN_OPTIONS);
+        std::process::exit(1);
+    };
+
+    let registry = FormatterRegistry::with_builtins();
+    let make_formatter = || registry.create(&explain.output_format);
+    make_formatter()?;
+    let generator = RecordGenerator::new(GenerateOptions {
+        strategy: explain.strategy,
+        mode: explain.mode,
+        count_only: explain.count_only,
+        force_scalar: explain.force_scalar,
+        keep_divisors: !explain.normalize,
+        ..Default::default()
+    });
+    let calibration = Calibration::measure(&generator, &make_formatter)?;
+    println!(
+        "Calibration: {:.2} ns per number, {:.2} ns per multiple",
+        calibration.per_number * 1e9,
+        calibration.per_selected * 1e9
+    );
+    let planner = Planner::new(&generator, &make_formatter, calibration);
 
-    let input = PathBuf::from(&args[1]);
-    let output = PathBuf::from(&args[2]);
+    println!(
+        "{:>6} {:>13} {:>13} {:>13}  {:<42} {:>13} {:>10} {:>10} {:>10}",
+        "line", "a", "b", "end", "strategy", "count", "bytes", "memory", "time"
+    );
+    let (mut count, mut bytes, mut memory, mut duration) = (0, planner.framing_bytes()?, 0, std::time::Duration::ZERO);
+    let parsers = InputParserRegistry::with_builtins();
+    let priorities = Priorities::default();
+    let format = explain.input_format.as_deref();
+    let routes = Routes::default();
+    let tags = TagFilter::default();
+    let source = pipeline::routed_file_source(&parsers, &explain.input, format, routes, priorities.clone(), Bound::Inclusive, &tags)?;
+    let mut schedule = Vec::new();
+    for (index, job) in source.enumerate() {
+        let mut job = job?;
+        if let Some(power) = explain.power {
+            job = job.with_power(power)?;
+        }
+        let plan = planner.plan(index, job)?;
+        println!(
+            "{:>6} {:>13} {:>13} {:>13}  {:<42} {:>13} {:>10} {:>10} {:>10.1?}",
+            index + 1,
+            format_count(job.a()),
+            format_count(job.b()),
+            format_count(job.end()),
+            plan.strategy,
+            format_count(plan.count),
+            format_bytes(plan.bytes),
+            format_bytes(plan.memory),
+            plan.duration
+        );
+        count += plan.count;
+        bytes += plan.bytes;
+        memory += plan.memory;
+        duration += plan.duration;
+        schedule.push((index, generator.estimated_cost(&job), plan.duration));
+    }
+    println!(
+        "\nTotal: {} multiples, {} of {} output",
+        format_count(count),
+        describe_bytes(bytes),
+        explain.output_format
+    );
+    // The generate command sorts the records by count before writing them,
+    // so all of them are held in memory at once.
+    println!("Peak memory: {} for the generated records", describe_bytes(memory));
+    let threads = format!("{} thread{}", explain.threads, if explain.threads == 1 { "" } else { "s" });
+    if !explain.longest_first {
+        println!("Estimated duration: {:.1?} on {}", duration / explain.threads as u32, threads);
+        return Ok(());
+    }
+    // The order generate --longest-first hands the lines out in.
+    let in_order: Vec<_> = schedule.iter().map(|&(_, _, duration)| duration).collect();
+    let priorities = priorities.lock().unwrap();
+    let priority = |index| priorities.get(&index).copied().unwrap_or_default();
+    schedule.sort_by_key(|&(index, cost, _)| std::cmp::Reverse((priority(index), cost, std::cmp::Reverse(index))));
+    let mut lines: Vec<String> = schedule.iter().take(10).map(|(index, _, _)| (index + 1).to_string()).collect();
+    if schedule.len() > 10 {
+        lines.push(String::from("…"));
+    }
+    println!("Order: lines {}", lines.join(", "));
+    let longest_first: Vec<_> = schedule.iter().map(|&(_, _, duration)| duration).collect();
+    println!(
+        "Estimated duration: {:.1?} on {} longest first, {:.1?} in input order",
+        makespan(&longest_first, explain.threads),
+        threads,
+        makespan(&in_order, explain.threads)
+    );
+    Ok(())
+}
 
-    if !input.exists() {
-        eprintln!("Input file does not exist: {:?}", input);
+fn run_get(program: &str, args: &[String]) -> Result<()> {
+    let Some(get) = cli::parse_get_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} get [options] <store> <a> <b> <end>\n\n{}", program, cli::GET_OPTIONS);
         std::process::exit(1);
+    };
+
+    let mut store = Store::open(&get.store)?;
+    let job = get.job;
+    let Some(record) = store.get(job)? else {
+        anyhow::bail!("No record for {} {} {} in {:?}", job.a(), job.b(), job.end(), get.store);
+    };
+    if get.count_only {
+        println!("{}:{}", job.end(), record.result.count());
+    } else {
+        println!("{}", record.result);
     }
+    Ok(())
+}
 
-    let results = generate_divisible_numbers(&input).context("Failed to generate divisible numbers")?;
+fn run_list(program: &str, args: &[String]) -> Result<()> {
+    let Some(path) = cli::parse_list_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} list <store>", program);
+        std::process::exit(1);
+    };
+
+    let store = Store::open(&path)?;
+    for entry in store.entries() {
+        println!("{} {} {} {}", entry.job.a(), entry.job.b(), entry.job.end(), entry.count);
+    }
+    Ok(())
+}
 
-    write_results(&output, results).context("Failed to write results to output file")?;
+fn run_gen_input(program: &str, args: &[String]) -> Result<()> {
+    let Some(gen) = cli::parse_gen_input_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} gen-input [options] [output]\n\n{}", program, cli::GEN_INPUT_OPTIONS);
+        std::process::exit(1);
+    };
 
+    let mut out: Box<dyn Write> = match &gen.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path).with_context(|| format!("Failed to create {:?}", path))?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    for job in random_jobs(gen.seed, gen.lines, gen.bounds) {
+        writeln!(out, "{} {} {}", job.a(), job.b(), job.end())?;
+    }
+    out.flush().context("Failed to write the input")?;
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use std::fs::read_to_string;
-    use std::path::PathBuf;
+/// The sample input written by `init`.
+const INIT_INPUT: &str = "3 5 100\n4 6 50\n7 11 1000\n";
+
+/// The options file written by `init`: the defaults, spelled out so they
+/// are easy to change.
+const INIT_OPTIONS: &str = "\
+# Options of the generate command, one `name = value` per line, read with
+# --config. Options given on the command line win over these.
+output-format = \"text\"
+mode = \"or\"
+threads = 1
+# count-only = true
+# min-count = 10
+";
 
-    use super::generate_divisible_numbers;
-    use super::is_number_divisible_by;
-    use super::LineNumbers;
-    use super::read_items;
-    use super::ResultNumbers;
+/// The input of the example test case written by `init`.
+const INIT_CASE_INPUT: &str = "2 3 20\n5 7 70\n";
 
-    fn read_result_numbers_from_file(file_path: &PathBuf) -> Result<Vec<ResultNumbers>, Box<dyn std::error::Error>> {
-        let content = read_to_string(file_path)?;
-        let lines: Vec<&str> = content.lines().collect();
+fn run_init(program: &str, args: &[String]) -> Result<()> {
+    let Some(dir) = cli::parse_init_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} init <dir>", program);
+        std::process::exit(1);
+    };
 
-        let mut result_numbers_vec = Vec::new();
+    let case = dir.join("cases").join("example");
+    let generator = RecordGenerator::new(GenerateOptions::default());
+    let source = InputParserRegistry::with_builtins().get("text")?.parse(Box::new(Cursor::new(INIT_CASE_INPUT)));
+    let mut records = source.map(|job| job.map(|job| generator.generate(job))).collect::<Result<Vec<_>>>()?;
+    // The order generate writes a file in.
+    records.sort_by_key(|record| record.result.count());
+    let files = [
+        (dir.join("input.txt"), INIT_INPUT),
+        (dir.join("multiples.toml"), INIT_OPTIONS),
+        (case.join("input.txt"), INIT_CASE_INPUT),
+        (case.join("options.toml"), "output-format = \"text\"\n"),
+    ];
+    let expected = case.join("expected.txt");
+    if let Some(path) = files.iter().map(|(path, _)| path).chain([&expected]).find(|path| path.exists()) {
+        bail!("{:?} already exists; init only creates new projects", path);
+    }
+    fs::create_dir_all(&case).with_context(|| format!("Failed to create {:?}", case))?;
+    for (path, contents) in &files {
+        fs::write(path, contents).with_context(|| format!("Failed to write {:?}", path))?;
+    }
+    write_records(&expected, "text", records.into_iter().map(Ok), false)?;
 
-        for line in lines {
-            let parts: Vec<&str> = line.split(':').collect();
-            let end: u32 = parts[0].parse()?;
-            let numbers: Vec<u32> = parts[1]
-                .split_whitespace()
-                .map(|num| num.parse().unwrap())
-                .collect();
+    println!("Created {}:", dir.display());
+    for path in files.iter().map(|(path, _)| path).chain([&expected]) {
+        println!("  {}", path.display());
+    }
+    println!("\nGenerate the multiples of the sample input with:\n");
+    println!(
+        "  {} --config {} {} {}",
+        program,
+        dir.join("multiples.toml").display(),
+        dir.join("input.txt").display(),
+        dir.join("output.txt").display()
+    );
+    println!("\nand check that the test cases still give their expected outputs with:\n");
+    println!("  {} regress --cases {}", program, dir.join("cases").display());
+    Ok(())
+}
+
+fn run_regress(program: &str, args: &[String]) -> Result<()> {
+    let Some(dir) = cli::parse_regress_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} regress [options]\n\n{}", program, cli::REGRESS_OPTIONS);
+        std::process::exit(1);
+    };
 
-            result_numbers_vec.push(ResultNumbers { end, numbers });
+    // Every case runs in a process of its own, exactly as it would from the
+    // command line.
+    let exe = env::current_exe().context("Failed to find the executable to run the cases with")?;
+    let cases = regress::find_cases(&dir)?;
+    let mut failed = 0;
+    for case in &cases {
+        let actual = case.actual();
+        let run = Command::new(&exe)
+            .arg("--config")
+            .arg(&case.options)
+            .arg(&case.input)
+            .arg(&actual)
+            .stdout(Stdio::null())
+            .output()
+            .with_context(|| format!("Failed to run case {}", case.name))?;
+        let failure = if run.status.success() {
+            regress::compare(&case.expected, &actual)?
+        } else {
+            let stderr = String::from_utf8_lossy(&run.stderr);
+            Some(format!("the run failed: {}", stderr.trim().replace('\n', "\n    ")))
+        };
+        match failure {
+            None => {
+                println!("PASS {}", case.name);
+                fs::remove_file(&actual).with_context(|| format!("Failed to remove {:?}", actual))?;
+            }
+            Some(failure) => {
+                println!("FAIL {}: {}", case.name, failure);
+                failed += 1;
+            }
         }
+    }
+    println!("{}", tr_args("{} cases, {} passed, {} failed", &[&cases.len(), &(cases.len() - failed), &failed]));
+    if failed > 0 {
+        bail!("{} of {} cases failed; their outputs are kept as actual.* next to expected.*", failed, cases.len());
+    }
+    Ok(())
+}
+
+fn run_bench(program: &str, args: &[String]) -> Result<()> {
+    let Some(bench) = cli::parse_bench_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} bench [options] <input> [-- <generate options>]\n\n{}", program, cli::BENCH_OPTIONS);
+        std::process::exit(1);
+    };
 
-        Ok(result_numbers_vec)
+    let exe = env::current_exe().context("Failed to find the executable to benchmark")?;
+    let output = |name: &str| env::temp_dir().join(format!("multiples_bench_{}_{}", std::process::id(), name));
+    let mut contenders = vec![Contender::new("current", exe, output("current"))];
+    if let Some(baseline) = &bench.compare_with {
+        contenders.push(Contender::new("baseline", baseline.clone(), output("baseline")));
+    }
+    for run in 1..=bench.runs {
+        for contender in &mut contenders {
+            let elapsed = contender.run(&bench.input, &bench.options)?;
+            println!("Run {} of the {} build: {:.2?}", run, contender.name, elapsed);
+        }
     }
+    for contender in &contenders {
+        println!("{:<8} {:>10.2?}  median of {} runs of {}", contender.name, contender.median(), bench.runs, contender.exe.display());
+    }
+    if let [current, baseline] = &contenders[..] {
+        if let Some(difference) = bench::first_difference(&current.output, &baseline.output)? {
+            bail!(
+                "The builds write different outputs, {}; they are kept at {:?} and {:?}",
+                difference,
+                current.output,
+                baseline.output
+            );
+        }
+        println!("Outputs identical; the current build is {}", bench::describe_delta(current.median(), baseline.median()));
+    }
+    for contender in &contenders {
+        fs::remove_file(&contender.output).with_context(|| format!("Failed to remove {:?}", contender.output))?;
+    }
+    Ok(())
+}
 
-    #[test]
-    fn test_read_items() {
-        let input = PathBuf::from("test_data/input_2_rows.txt");
-        let items = read_items(&input).unwrap();
-        assert_eq!(items.len(), 2);
+fn run_selftest(program: &str, args: &[String]) -> Result<()> {
+    let Some(selftest) = cli::parse_selftest_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} selftest [options]\n\n{}", program, cli::SELFTEST_OPTIONS);
+        std::process::exit(1);
+    };
+
+    let seed = selftest.seed.unwrap_or_else(|| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        now.as_nanos() as u64
+    });
+    println!("Checking {} random jobs with --seed {}", selftest.cases, seed);
+    let report = selftest::run(seed, selftest.cases, selftest.bounds);
+    for mismatch in &report.mismatches {
+        println!("MISMATCH {}", mismatch);
     }
+    println!(
+        "{} cases, {} checks, {} mismatches",
+        report.cases,
+        report.checks,
+        report.mismatches.len()
+    );
+    if !report.mismatches.is_empty() {
+        anyhow::bail!("Self-test failed; rerun with --seed {} to reproduce", seed);
+    }
+    Ok(())
+}
+
+fn run_lint(program: &str, args: &[String]) -> Result<()> {
+    let Some(lint) = cli::parse_lint_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} lint [options] <input>\n\n{}", program, cli::LINT_OPTIONS);
+        std::process::exit(1);
+    };
 
-    #[test]
-    fn test_is_number_divisible_by() {
-        let item = LineNumbers {
-            a: 2,
-            b: 3,
-            end: 10,
+    let registry = FormatterRegistry::with_builtins();
+    let make_formatter = || registry.create("text");
+    let generator = RecordGenerator::new(GenerateOptions {
+        mode: lint.mode,
+        ..Default::default()
+    });
+    // Only sizes are predicted, so nothing needs to be timed.
+    let calibration = Calibration {
+        per_number: 0.0,
+        per_selected: 0.0,
+    };
+    let planner = Planner::new(&generator, &make_formatter, calibration);
+    let mut linter = Linter::new(&planner, lint.mode, lint.max_size);
+    let parsers = InputParserRegistry::with_builtins();
+    let format = lint.input_format.as_deref();
+    let (routes, priorities, tags) = (Routes::default(), Priorities::default(), TagFilter::default());
+    let source = pipeline::routed_file_source(&parsers, &lint.input, format, routes, priorities, Bound::Inclusive, &tags)?;
+    let mut counts = [0; 3];
+    let mut denied = 0;
+    for (index, job) in source.enumerate() {
+        let findings = match job {
+            Ok(job) => linter.check(index + 1, &job)?,
+            Err(err) => vec![Finding::invalid(index + 1, &err)],
         };
+        for finding in findings {
+            println!("{}:{}", lint.input.display(), finding);
+            counts[finding.severity as usize] += 1;
+            if lint.deny.is_some_and(|deny| finding.severity >= deny) {
+                denied += 1;
+            }
+        }
+    }
+    let [notes, warnings, errors] = counts;
+    println!("{} errors, {} warnings, {} notes", errors, warnings, notes);
+    if errors > 0 {
+        bail!("Lint failed: {} lines of {} don't parse", errors, lint.input.display());
+    }
+    if denied > 0 {
+        bail!("Lint failed: {} findings are denied by --deny", denied);
+    }
+    Ok(())
+}
+
+fn run_wizard(program: &str, args: &[String]) -> Result<()> {
+    let input = cli::parse_wizard_args(args.iter().cloned()).map_err(errors::usage)?;
+    let registry = FormatterRegistry::with_builtins();
+    let answers = Wizard::new(io::stdin().lock(), io::stdout()).run(&registry, input)?;
+    fs::write(&answers.input, answers.input_file()).with_context(|| format!("Failed to write {:?}", answers.input))?;
+    println!(
+        "\nWrote {} lines to {}. Generate the results with:\n\n    {}",
+        answers.jobs.len(),
+        answers.input.display(),
+        answers.command_line(program)
+    );
+    Ok(())
+}
+
+fn run_help_topics(program: &str, args: &[String]) -> Result<()> {
+    let Some(name) = cli::parse_help_args(args.iter().cloned()).map_err(errors::usage)? else {
+        println!("Usage: {} help-topics <topic>\n\n{}", program, help::topic_list());
+        return Ok(());
+    };
+    let topic = help::lookup(&name).with_context(|| format!("Unknown help topic: {} (run help-topics without one to list them)", name))?;
+    print!("{}", topic.long_help(program));
+    Ok(())
+}
+
+fn run_examples(program: &str, args: &[String]) -> Result<()> {
+    let Some(name) = cli::parse_help_args(args.iter().cloned()).map_err(errors::usage)? else {
+        for (i, topic) in help::TOPICS.iter().enumerate() {
+            let separator = if i > 0 { "\n" } else { "" };
+            print!("{}{}:\n{}", separator, topic.title, topic.examples_text(program));
+        }
+        return Ok(());
+    };
+    let topic = help::lookup(&name).with_context(|| format!("Unknown help topic: {} (run help-topics to list them)", name))?;
+    print!("{}", topic.examples_text(program));
+    Ok(())
+}
+
+fn run_serve(program: &str, args: &[String]) -> Result<()> {
+    let Some(mut serve) = cli::parse_serve_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} serve [options]\n\n{}", program, cli::SERVE_OPTIONS);
+        std::process::exit(1);
+    };
 
-        let expected_results = vec![
-            (1, false),
-            (2, true),
-            (3, true),
-            (4, true),
-            (5, false),
-            (6, true),
-            (7, false),
-            (8, true),
-            (9, true),
-            (10, true),
-        ];
+    let api_keys = serve.api_keys.as_deref().map(read_api_keys).transpose()?.unwrap_or_default();
+    let mut jwt_keys = Vec::new();
+    for path in &serve.jwt_secrets {
+        let key = fs::read(path).with_context(|| format!("Failed to read JWT key from {:?}", path))?;
+        // Keys are usually written with a trailing newline that isn't part of them.
+        jwt_keys.push(key.trim_ascii_end().to_vec());
+    }
+    let jwt = (!jwt_keys.is_empty()).then(|| JwtConfig {
+        keys: jwt_keys,
+        issuer: serve.jwt_issuer.take(),
+    });
+    serve.config.auth = Authenticator::new(api_keys, jwt);
 
-        for (n, expected) in expected_results {
-            assert_eq!(is_number_divisible_by(&item, &n), expected, "Failed for number {}", n);
+    let listener = TcpListener::bind(&serve.listen).with_context(|| format!("Failed to listen on {}", serve.listen))?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    let service = Service::start(serve.pid_file.as_deref(), serve.service)?;
+    // Like a daemon, a server always finishes the requests it accepted before exiting.
+    service::handle_termination();
+    service.ready()?;
+    let served = Arc::new(Server::new(serve.config)).serve_until(listener, service::stop_requested);
+    service.stop();
+    served
+}
+
+fn run_daemon(program: &str, args: &[String]) -> Result<()> {
+    let Some(daemon) = cli::parse_daemon_args(args.iter().cloned()).map_err(errors::usage)? else {
+        eprintln!("Usage: {} daemon [options] [--schedule <cron> <input> <output>]\n\n{}", program, cli::DAEMON_OPTIONS);
+        std::process::exit(1);
+    };
+
+    let store = ScheduleStore::open(&daemon.state_dir)?;
+    if let Some(add) = daemon.add {
+        let (schedule, added) = store.add(&add.cron, add.jobs, &add.output, &add.output_format)?;
+        let verb = if added { "Added" } else { "Keeping" };
+        eprintln!("{} schedule {}: {} into {:?}", verb, schedule.id, schedule.cron, schedule.output);
+    }
+    match daemon.action {
+        cli::DaemonAction::List => {
+            for schedule in store.schedules()? {
+                let jobs = match &schedule.jobs {
+                    schedule::ScheduledJobs::File(path) => format!("{:?}", path),
+                    schedule::ScheduledJobs::Inline(jobs) => format!("{:?}", jobs.replace('\n', ";")),
+                };
+                println!("{}\t{}\t{} -> {:?} ({})", schedule.id, schedule.cron, jobs, schedule.output, schedule.output_format);
+            }
+            Ok(())
         }
+        cli::DaemonAction::Remove(id) => {
+            if !store.remove(id)? {
+                bail!("No schedule {} in {:?}", id, daemon.state_dir);
+            }
+            Ok(())
+        }
+        cli::DaemonAction::History => {
+            for run in store.history()? {
+                println!("{}", run);
+            }
+            Ok(())
+        }
+        cli::DaemonAction::Run => {
+            let service = Service::start(daemon.pid_file.as_deref(), daemon.service)?;
+            // A daemon always stops between runs rather than halfway through one.
+            service::handle_termination();
+            service.ready()?;
+            eprintln!("Running the schedules in {:?}", daemon.state_dir);
+            let run = schedule::run_daemon(&store, service::stop_requested, daemon.otlp.as_ref());
+            service.stop();
+            run
+        }
+    }
+}
+
+/// Writes the repaired input and its report for `--fix`.
+fn run_fix(args: &cli::GenerateArgs) -> Result<()> {
+    let input = File::open(&args.input).with_context(|| format!("Failed to open input file: {:?}", args.input))?;
+    let repaired = repair(BufReader::new(input)).with_context(|| format!("Failed to read {:?}", args.input))?;
+    let mut report_path = args.output.clone().into_os_string();
+    report_path.push(".report");
+    let report_path = PathBuf::from(report_path);
+    fs::write(&args.output, repaired.canonical()).with_context(|| format!("Failed to write {:?}", args.output))?;
+    fs::write(&report_path, repaired.report()).with_context(|| format!("Failed to write {:?}", report_path))?;
+    let changed: BTreeSet<usize> = repaired.fixes.iter().map(|(line, _)| *line).collect();
+    eprintln!(
+        "Kept {} jobs, changed or removed {} lines; see {:?}",
+        repaired.jobs.len(),
+        changed.len(),
+        report_path
+    );
+    Ok(())
+}
+
+/// Runs the jobs given in `MULTIPLES_JOB`, for containers started without
+/// arguments.
+fn run_env_job(job: cli::EnvJob) -> Result<()> {
+    let parser = InputParserRegistry::with_builtins();
+    let source = parser.get("text")?.parse(Box::new(Cursor::new(job.jobs.into_bytes())));
+    let warnings = Warnings::default();
+    let source = degenerate::checked(source, job.mode, degenerate::Policy::Warn, true, warnings.clone());
+    let generator = RecordGenerator::new(GenerateOptions {
+        mode: job.mode,
+        count_only: job.count_only,
+        ..Default::default()
+    });
+    let formatter = FormatterRegistry::with_builtins().create(&job.output_format)?;
+    let mut sink: Box<dyn Sink> = match &job.output {
+        Some(path) => Box::new(FormatterSink::new(LazyFile::new(path), formatter)),
+        None => Box::new(FormatterSink::new(BufWriter::new(io::stdout()), formatter)),
+    };
+    let report = Pipeline::builder(source)
+        .generator(move |job| Ok(generator.generate(job)))
+        .warnings(warnings.clone())
+        .build()
+        .run(sink.as_mut())
+        .inspect_err(|_| print_warnings(&warnings.take()))
+        .context("Failed to run MULTIPLES_JOB")?;
+    check_report(&report)
+}
+
+/// Prints `warnings` to stderr, one per line.
+fn print_warnings(warnings: &[Warning]) {
+    for warning in warnings {
+        eprintln!("{}", warning);
     }
+}
 
-    #[test]
-    fn test_generate_divisible_numbers() {
-        // Read the expected results from the comparison file
-        let comparison_path = PathBuf::from("test_data/result_2_comparison.txt");
-        let expected_results = read_result_numbers_from_file(&comparison_path).unwrap();
+/// Prints the warnings and failed lines of `report`, failing if any line
+/// failed.
+fn check_report(report: &RunReport) -> Result<()> {
+    print_warnings(&report.warnings);
+    for error in &report.errors {
+        eprintln!("Left out: {}", error);
+    }
+    match report.errors.len() {
+        0 => Ok(()),
+        1 => bail!("1 line failed and was left out of the output"),
+        failed => bail!("{} lines failed and were left out of the output", failed),
+    }
+}
 
-        // Call the function with the test input
-        let input_path = PathBuf::from("test_data/input_2_rows.txt");
-        let actual_results = generate_divisible_numbers(&input_path).unwrap();
+fn run_explain_error(program: &str, args: &[String]) -> Result<()> {
+    let Some(name) = cli::parse_explain_error_args(args.iter().cloned()).map_err(errors::usage)? else {
+        println!("{}\n", tr_args("Usage: {} explain-error <code>", &[&program]));
+        for code in errors::CODES {
+            println!("  {}  {}", code, tr(code.title));
+        }
+        return Ok(());
+    };
+    let code = errors::lookup(&name)
+        .with_context(|| tr_args("Unknown error code: {} (run explain-error without one to list them)", &[&name]))?;
+    println!("{}: {}\n\n{}", code, tr(code.title), tr(code.explanation));
+    Ok(())
+}
 
-        // Compare the output with the expected results
-        assert_eq!(actual_results.len(), expected_results.len());
-        for (actual, expected) in actual_results.iter().zip(expected_results.iter()) {
-            assert!(actual == expected);
+fn main() -> ExitCode {
+    let program = env::args().next().unwrap_or_else(|| String::from("multiples"));
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", errors::report(&err, &program, locale::language()));
+            ExitCode::FAILURE
         }
     }
+}
 
-    #[test]
-    fn test_read_items_incorrect_format() {
-        let input = PathBuf::from("test_data/input_incorrect_format.txt");
-        let result = read_items(&input);
-        assert!(result.is_err());
+fn run() -> Result<()> {
+    console::prepare();
+    let mut args: Vec<String> = env::args().collect();
+    let lang = cli::take_lang(&mut args).map_err(errors::usage)?;
+    // The server answers in English unless told otherwise, whatever the
+    // locale it was started in.
+    let serving = matches!(args.get(1).map(String::as_str), Some("serve" | "daemon"));
+    match lang {
+        Some(lang) => locale::set_language(lang),
+        None if !serving => locale::set_language(Lang::detect(|name| env::var(name).ok())),
+        None => {}
+    }
+    if args.len() == 1 {
+        if let Some(job) = cli::parse_env_job(|name| env::var(name).ok()).map_err(errors::usage)? {
+            return run_env_job(job);
+        }
+    }
+    match args.get(1).map(String::as_str) {
+        Some("merge") => return run_merge(&args[0], &args[2..]),
+        Some("merge-shards") => return run_merge_shards(&args[0], &args[2..]),
+        Some("backfill") => return run_backfill(&args[0], &args[2..]),
+        Some("sweep") => return run_sweep(&args[0], &args[2..]),
+        Some("convert") => return run_convert(&args[0], &args[2..]),
+        Some("migrate") => return run_migrate(&args[0], &args[2..]),
+        Some("describe") => return run_describe(&args[0], &args[2..]),
+        Some("provenance") => return run_provenance(&args[0], &args[2..]),
+        Some("sort") => return run_sort(&args[0], &args[2..]),
+        Some("setops") => return run_setops(&args[0], &args[2..]),
+        Some("bitmap") => return run_bitmap(&args[0], &args[2..]),
+        Some("explain") => return run_explain(&args[0], &args[2..]),
+        Some("analyze") => return run_analyze(&args[0], &args[2..]),
+        Some("get") => return run_get(&args[0], &args[2..]),
+        Some("list") => return run_list(&args[0], &args[2..]),
+        Some("gen-input") => return run_gen_input(&args[0], &args[2..]),
+        Some("init") => return run_init(&args[0], &args[2..]),
+        Some("regress") => return run_regress(&args[0], &args[2..]),
+        Some("bench") => return run_bench(&args[0], &args[2..]),
+        Some("explain-error") => return run_explain_error(&args[0], &args[2..]),
+        Some("selftest") => return run_selftest(&args[0], &args[2..]),
+        Some("lint") => return run_lint(&args[0], &args[2..]),
+        Some("wizard") => return run_wizard(&args[0], &args[2..]),
+        Some("help-topics") => return run_help_topics(&args[0], &args[2..]),
+        Some("examples") => return run_examples(&args[0], &args[2..]),
+        Some("serve") => return run_serve(&args[0], &args[2..]),
+        Some("daemon") => return run_daemon(&args[0], &args[2..]),
+        _ => {}
+    }
+    // Already while parsing, which reads the cgroup's CPU limit for
+    // --threads auto.
+    if args[1..].iter().any(|arg| arg == "--pure") {
+        audit::enforce();
+    }
+    let options = config::expand_config(args[1..].to_vec()).map_err(errors::usage)?;
+    let Some(generate) = cli::parse_generate_args(options.clone()).map_err(errors::usage)? else {
+        eprintln!(
+            "{}\n\n{}\n\n{}",
+            tr_args("Usage: {} [options] <input> <output>", &[&args[0]]),
+            cli::GENERATE_OPTIONS,
+            tr(cli::ENV_VARIABLES)
+        );
+        std::process::exit(1);
+    };
+    if generate.fix {
+        return run_fix(&generate);
+    }
+    if generate.pure {
+        audit::enforce();
+        audit::allow(&generate.input, Access::Read);
+        audit::allow(&generate.output, Access::Write);
+    }
+    if !generate.provenance && generate.audit_log.is_none() {
+        return run_generate(generate, None);
     }
+    // Hashed before anything is generated, as the input the run reads.
+    let provenance = Provenance::of(&generate.input, &options);
+    let header = generate.provenance.then(|| provenance.clone());
+    let Some(log) = generate.audit_log.clone() else {
+        return run_generate(generate, header);
+    };
+    let output = generate.output.clone();
+    let (entry, result) = runlog::logged(provenance, &output, &args[1..], || run_generate(generate, header));
+    // A failed run is reported over a log that couldn't be written.
+    let logged = runlog::append(&log, &entry);
+    result.and(logged)
+}
 
-    #[test]
-    fn test_read_items_empty_file() {
-        let input = PathBuf::from("test_data/input_empty.txt");
-        let items = read_items(&input).unwrap();
-        assert!(items.is_empty());
+/// The default command, generating the records of the jobs of an input,
+/// headed by `provenance` for `--provenance`.
+fn run_generate(generate: cli::GenerateArgs, provenance: Option<Provenance>) -> Result<()> {
+    if generate.nice {
+        // Before any thread is started, so that they all inherit it.
+        throttle::lower_priority(throttle::NICENESS)?;
     }
 
-    #[test]
-    fn test_read_items_mixed_format() {
-        let input = PathBuf::from("test_data/input_mixed_format.txt");
-        let result = read_items(&input);
-        assert!(result.is_err());
+    // How --decimal and --time write values, for the records echoed to stdout.
+    let render = match (generate.decimal, generate.time) {
+        (Some(scale), _) => Some(decimal::render(scale)),
+        (_, Some(start)) => Some(timeline::render(start)),
+        (None, None) => None,
+    };
+    let (mut registry, mut parsers) = match (generate.decimal, generate.time) {
+        (Some(scale), _) => (decimal::formatters(scale), decimal::parsers(scale)),
+        (_, Some(start)) => (timeline::formatters(start), timeline::parsers(start)),
+        (None, None) => (FormatterRegistry::with_builtins(), InputParserRegistry::with_builtins()),
+    };
+    if generate.fast_parse {
+        parsers.register("text", &["txt"], FastTextParser);
+    }
+    if generate.compat == Some(Compat::V1_0) {
+        parsers.register("text", &["txt"], LegacyTextParser);
     }
+    load_plugins(&mut registry, &generate.plugins)?;
+    if let Some(name) = &generate.array_name {
+        for (format, language) in ARRAY_FORMATS {
+            let name = name.clone();
+            registry.register(format, move || Box::new(ArrayFormatter::new(language, &name)));
+        }
+    }
+    let generator = Arc::new(RecordGenerator::new(GenerateOptions {
+        strategy: generate.strategy,
+        mode: generate.mode,
+        filter: digit_filter(&generate)?,
+        descending: generate.descending,
+        count_only: generate.count_only,
+        force_scalar: generate.force_scalar,
+        keep_divisors: !generate.normalize,
+        shard: generate.shard,
+        throttle: generate.throttle,
+        include_zero: generate.include_zero,
+        stop_after: generate.stop_after,
+        stop_above: generate.stop_above,
+        ..Default::default()
+    }));
+    let exporter = match &generate.otlp_endpoint {
+        Some(endpoint) => Some(OtlpExporter::new(endpoint, generate.otlp_sample)?),
+        None => None,
+    };
+    let profiler = (generate.profile || exporter.is_some()).then(|| Arc::new(Profiler::default()));
+    let routes = Routes::default();
+    // Aggregates are built from whole records, so no line is streamed.
+    let memory = match generate.aggregate {
+        true => None,
+        false => MemoryPolicy::new(generate.max_memory, generate.strict_memory),
+    };
+    let mut sink = if generate.aggregate {
+        let format: AggregateFormat = generate.output_format.parse()?;
+        if OutputTarget::parse(&generate.output)?.is_stream() || generate.io_backend == IoBackend::Mmap {
+            bail!("--aggregate writes a regular output file with the buffered backend");
+        }
+        Box::new(AggregateSink::new(LazyFile::new(&generate.output), format))
+    } else {
+        let sink: Box<dyn Sink> = match generate.preview {
+            Some(size) => Box::new(PreviewSink::new(io::stdout(), size)),
+            None => output_sink(&generate, registry, profiler.clone(), routes.clone(), provenance)?,
+        };
+        match &memory {
+            Some(policy) => Box::new(MemoryGuardSink::new(sink, policy.clone(), generator.clone())),
+            None => sink,
+        }
+    };
 
-    #[test]
-    fn test_generate_divisible_numbers_large_numbers() {
-        let input = PathBuf::from("test_data/input_large_numbers.txt");
-        let items = read_items(&input).unwrap();
-        assert_eq!(items.len(), 1);
+    if !generate.input.exists() {
+        return Err(errors::FILE_NOT_FOUND.error(tr_args("Input file does not exist: {}", &[&format!("{:?}", generate.input)])));
+    }
+    check_limits(&generate, &parsers, &generator)?;
 
-        let result = generate_divisible_numbers(&input).unwrap();
+    let service = Service::start(generate.pid_file.as_deref(), generate.service)?;
+    let input_format = generate.input_format.as_deref();
+    let priorities = Priorities::default();
+    let mut source = match generate.io_backend {
+        IoBackend::Buffered => {
+            let (bound, tags) = (generate.bound, &generate.tags);
+            pipeline::routed_file_source(&parsers, &generate.input, input_format, routes, priorities.clone(), bound, tags)?
+        }
+        IoBackend::Mmap => mapped_source(&generate, &parsers, routes, priorities.clone())?,
+    };
+    if let Some(power) = generate.power {
+        source = Box::new(source.map(move |job| job?.with_power(power)));
+    }
+    let warnings = Warnings::default();
+    source = degenerate::checked(source, generate.mode, generate.degenerate, generate.normalize, warnings.clone());
+    let (min_count, max_count) = (generate.min_count, generate.max_count);
+    let mut builder = Pipeline::builder(source)
+        .workers(generate.threads)
+        .queue_size(generate.queue_size)
+        .schedule(priorities, generate.max_concurrent_heavy)
+        .warnings(warnings.clone());
+    if generate.keep_going {
+        builder = builder.keep_going();
+    }
+    if generate.longest_first {
+        let generator = generator.clone();
+        builder = builder.longest_first(Arc::new(move |job: &LineNumbers| generator.estimated_cost(job)));
+    }
+    if generate.unordered {
+        builder = builder.unordered();
+    }
+    if generate.service {
+        builder = builder.stop_when(service::stop_requested);
+    }
+    let mut reporter = None;
+    let progress = match generate.progress {
+        Some(format) => {
+            // Input errors are left for the run itself to report.
+            let source = pipeline::routed_file_source(
+                &parsers,
+                &generate.input,
+                input_format,
+                Routes::default(),
+                Priorities::default(),
+                generate.bound,
+                &generate.tags,
+            )?;
+            let total = source.filter_map(Result::ok).map(|job| job.end() as u64).sum();
+            let progress = Arc::new(Progress::new(total));
+            let out: Box<dyn Write + Send> = match &generate.progress_to {
+                Some(path) => Box::new(File::create(path).with_context(|| format!("Failed to open {:?}", path))?),
+                None => Box::new(io::stderr()),
+            };
+            reporter = Some(ProgressReporter::spawn(progress.clone(), format, generate.progress_interval, out));
+            builder = builder.progress(progress.clone());
+            Some(progress)
+        }
+        None => None,
+    };
+    let cross_check = generate.cross_check;
+    let threads = generate.threads;
+    let busy = AtomicUsize::new(0);
+    let backpressure = Arc::new(Backpressure::default());
+    builder = builder.backpressure(backpressure.clone()).generator(move |job| {
+        let advance = |n| {
+            if let Some(progress) = &progress {
+                progress.advance(n);
+            }
+        };
+        if let Some(policy) = &memory {
+            policy.check(&generator, &job)?;
+            if policy.exceeds(&generator, &job) {
+                // Only the predicted count, which the record is sorted and
+                // ranked by; the sink generates the multiples as it writes.
+                advance(job.end() as u64);
+                let result = ResultNumbers {
+                    end: job.end(),
+                    numbers: Vec::new(),
+                    count: Some(generator.predicted_count(&job)),
+                };
+                return Ok(ResultRecord { index: 0, job, result });
+            }
+        }
+        // Threads the pipeline has no line for help with this one, unless
+        // the writer is behind anyway.
+        let idle = threads.saturating_sub(busy.fetch_add(1, Ordering::SeqCst) + 1);
+        let helpers = if backpressure.is_behind() { 0 } else { idle };
+        busy.fetch_add(helpers, Ordering::SeqCst);
+        let record = generator.generate_chunked(job, helpers + 1, Some(&backpressure), &advance);
+        busy.fetch_sub(helpers + 1, Ordering::SeqCst);
+        if cross_check {
+            generator.cross_check(&record)?;
+        }
+        Ok(record)
+    });
+    if let Some(profiler) = &profiler {
+        builder = builder.profile(profiler.clone());
+    }
+    if min_count.is_some() || max_count.is_some() {
+        builder = builder.filter(move |record| {
+            let count = record.result.count();
+            min_count.is_none_or(|min| count >= min) && max_count.is_none_or(|max| count <= max)
+        });
+    }
+    if let Some(top) = generate.top {
+        let by = generate.by;
+        builder = builder.top_by_key(top, move |record| by.key(record));
+    }
+    // Records are streamed to pipes and sockets as they complete rather
+    // than sorted once all are done; release 1.0 sorted them anyway.
+    if !OutputTarget::parse(&generate.output)?.is_stream() || generate.compat.is_some() {
+        builder = builder.sort_by_key(|record| record.result.count());
+    }
+    service.ready()?;
+    let color = generate.color.enabled(&io::stdout());
+    // Previews are printed by the sink instead.
+    let echo = generate.preview.is_none();
+    let run = builder
+        .inspect(move |record| match (echo, color) {
+            (false, _) => {}
+            (true, true) => println!("{}", color::paint_record(&record.result, &record.job, render.as_deref())),
+            (true, false) => match &render {
+                Some(render) => println!("{}", formatter::rendered_text(&**render, &record.result)),
+                None => println!("{}", record.result),
+            },
+        })
+        .build()
+        .run(sink.as_mut());
+    service.stop();
+    if let Some(reporter) = reporter {
+        reporter.finish().context("Failed to report progress")?;
+    }
+    let report = run.inspect_err(|_| print_warnings(&warnings.take())).context(tr("Failed to generate divisible numbers"))?;
+    if service::terminated() {
+        eprintln!("Stopped by a termination signal; the output holds the lines finished before it");
+    }
 
-        // Example: Test that it generates expected numbers for a large range
-        // Assuming a specific input, adjust the expected output as needed
-        let expected_numbers: Vec<u32> = (1..=100000).filter(|&n| n % 2 == 0 || n % 3 == 0).collect();
-        assert_eq!(result[0].numbers, expected_numbers);
+    if let (Some(exporter), Some(profiler)) = (&exporter, &profiler) {
+        // Losing traces shouldn't fail a run whose output is complete.
+        if let Err(err) = exporter.export(profiler) {
+            eprintln!("Failed to export traces: {:#}", err);
+        }
+    }
+    if let Some(profiler) = profiler.as_ref().filter(|_| generate.profile) {
+        match generate.color.enabled(&io::stderr()) {
+            true => eprint!("{}", profiler.boxed_summary()),
+            false => eprint!("{}", profiler.summary()),
+        }
+        if let Some(path) = &generate.profile_out {
+            let mut trace = BufWriter::new(File::create(path).with_context(|| format!("Failed to create {:?}", path))?);
+            profiler
+                .write_chrome_trace(&mut trace)
+                .with_context(|| format!("Failed to write profile to {:?}", path))?;
+        }
     }
+    check_report(&report)
 }
@@ -0,0 +1,190 @@
+//! Falling back to writing a line's multiples as they are generated when
+//! holding its record would take more memory than there is.
+//!
+//! Records are normally generated whole, which lets the pipeline sort,
+//! rank and filter them, and lets every format write them. A record of a
+//! few billion multiples takes gigabytes though, so a line whose predicted
+//! record exceeds the limit is only counted by the workers, and the
+//! [`MemoryGuardSink`] generates its multiples again in chunks while
+//! writing it.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+
+use crate::audit;
+use crate::errors::MEMORY_LIMIT;
+use crate::pipeline::Sink;
+use crate::quantity::format_bytes;
+use crate::strategy::RecordGenerator;
+use crate::{LineNumbers, ResultRecord};
+
+/// Values of the range generated for each chunk of a streamed line, which
+/// bounds the memory of one chunk to 64 MiB.
+pub const STREAM_CHUNK: u32 = 1 << 24;
+
+/// The memory available to new allocations, as the kernel estimates it.
+/// Only known on Linux.
+pub fn available_memory() -> Option<u64> {
+    parse_meminfo(&audit::read_to_string(Path::new("/proc/meminfo")).ok()?)
+}
+
+fn parse_meminfo(meminfo: &str) -> Option<u64> {
+    let line = meminfo.lines().find(|line| line.starts_with("MemAvailable:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Which lines are too large to hold in memory, and what to do about them.
+#[derive(Clone, Debug)]
+pub struct MemoryPolicy {
+    /// The most memory one line's record may take.
+    pub limit: u64,
+    /// Where the limit comes from, for messages.
+    pub source: &'static str,
+    /// Fail on lines over the limit instead of streaming them.
+    pub strict: bool,
+}
+
+impl MemoryPolicy {
+    /// The policy for `--max-memory`, or for the available memory without
+    /// it. Without either there is nothing to compare with.
+    pub fn new(max_memory: Option<u64>, strict: bool) -> Option<Self> {
+        let (limit, source) = match max_memory {
+            Some(limit) => (limit, "--max-memory"),
+            None => (available_memory()?, "available memory"),
+        };
+        Some(MemoryPolicy { limit, source, strict })
+    }
+
+    /// The predicted size of the numbers of `job`'s record.
+    pub fn predicted_size(generator: &RecordGenerator, job: &LineNumbers) -> u64 {
+        generator.predicted_count(job) * size_of::<u32>() as u64
+    }
+
+    /// Whether `job`'s record would take more than the limit. Count-only
+    /// records take no memory for their numbers.
+    pub fn exceeds(&self, generator: &RecordGenerator, job: &LineNumbers) -> bool {
+        !generator.options().count_only && Self::predicted_size(generator, job) > self.limit
+    }
+
+    /// Checks that `job` may be generated, streamed or not: with
+    /// [`MemoryPolicy::strict`], lines over the limit are an error.
+    pub fn check(&self, generator: &RecordGenerator, job: &LineNumbers) -> Result<()> {
+        if self.strict && self.exceeds(generator, job) {
+            return Err(MEMORY_LIMIT.error(format!(
+                "a:{} b:{} end:{} needs about {} of memory, more than the {} limit of {}",
+                job.a,
+                job.b,
+                job.end,
+                format_bytes(Self::predicted_size(generator, job)),
+                self.source,
+                format_bytes(self.limit)
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Writes the records of lines over the limit of a [`MemoryPolicy`] in
+/// chunks, generating their multiples once more; the workers only counted
+/// them. Every other record goes to the inner sink as it is.
+pub struct MemoryGuardSink {
+    inner: Box<dyn Sink>,
+    policy: MemoryPolicy,
+    generator: Arc<RecordGenerator>,
+}
+
+impl MemoryGuardSink {
+    pub fn new(inner: Box<dyn Sink>, policy: MemoryPolicy, generator: Arc<RecordGenerator>) -> Self {
+        MemoryGuardSink {
+            inner,
+            policy,
+            generator,
+        }
+    }
+}
+
+impl Sink for MemoryGuardSink {
+    fn write(&mut self, record: ResultRecord) -> Result<()> {
+        let job = record.job;
+        if !self.policy.exceeds(&self.generator, &job) {
+            return self.inner.write(record);
+        }
+        eprintln!(
+            "Line {}: needs about {} of memory, more than the {} limit of {}; writing it as it is generated",
+            record.index + 1,
+            format_bytes(MemoryPolicy::predicted_size(&self.generator, &job)),
+            self.policy.source,
+            format_bytes(self.policy.limit)
+        );
+        let count = self.generator.known_count(&job);
+        let index = record.index;
+        let mut chunks = self.generator.chunks(job, STREAM_CHUNK);
+        self.inner
+            .write_streamed(record, count, &mut chunks)
+            .with_context(|| format!("Failed to stream line {}, which doesn't fit into memory", index + 1))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.inner.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::Arc;
+
+    use super::{parse_meminfo, MemoryGuardSink, MemoryPolicy};
+    use crate::formatter::FormatterRegistry;
+    use crate::output::LazyFile;
+    use crate::pipeline::{FormatterSink, Sink};
+    use crate::strategy::{GenerateOptions, RecordGenerator};
+    use crate::{count_record, formula::Mode, LineNumbers};
+
+    #[test]
+    fn test_parse_meminfo() {
+        let meminfo = "MemTotal:       16314264 kB\nMemFree:         1022488 kB\nMemAvailable:    8157132 kB\n";
+        assert_eq!(parse_meminfo(meminfo), Some(8_157_132 * 1024));
+        assert_eq!(parse_meminfo("MemTotal: 1 kB\n"), None);
+    }
+
+    #[test]
+    fn test_memory_guard_sink() {
+        let large = LineNumbers { a: 3, b: 5, end: 1000 };
+        let small = LineNumbers { a: 3, b: 5, end: 10 };
+        let policy = MemoryPolicy {
+            limit: 100,
+            source: "--max-memory",
+            strict: false,
+        };
+        let generator = Arc::new(RecordGenerator::new(GenerateOptions::default()));
+        assert!(policy.exceeds(&generator, &large) && !policy.exceeds(&generator, &small));
+        assert!(policy.check(&generator, &large).is_ok());
+        let strict = MemoryPolicy { strict: true, ..policy.clone() };
+        let err = strict.check(&generator, &large).unwrap_err();
+        assert!(err.to_string().contains("needs about 1.9 kB of memory"), "{}", err);
+
+        for format in ["text", "json", "csv"] {
+            let mut expected = Vec::new();
+            let formatter = FormatterRegistry::with_builtins().create(format).unwrap();
+            let mut sink = FormatterSink::new(&mut expected, formatter);
+            sink.write(generator.generate(large)).unwrap();
+            sink.write(generator.generate(small)).unwrap();
+            sink.finish().unwrap();
+
+            let path = std::env::temp_dir().join(format!("multiples_memory_{}.{}", std::process::id(), format));
+            let formatter = FormatterRegistry::with_builtins().create(format).unwrap();
+            let inner = Box::new(FormatterSink::new(LazyFile::new(&path), formatter));
+            // The workers hand over a count in place of the large record.
+            let mut sink = MemoryGuardSink::new(inner, policy.clone(), generator.clone());
+            sink.write(count_record(large, Mode::Or)).unwrap();
+            sink.write(generator.generate(small)).unwrap();
+            sink.finish().unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), String::from_utf8(expected).unwrap());
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
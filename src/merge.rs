@@ -0,0 +1,105 @@
+//! Combining several result sets into one.
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use crate::{LineNumbers, ResultRecord};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergeMode {
+    /// Keep every record, in input order.
+    Concat,
+    /// Drop records identical to an earlier one.
+    Dedup,
+    /// Combine records with the same parameters into one holding the union
+    /// of their multiples.
+    Union,
+}
+
+impl FromStr for MergeMode {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "concat" => Ok(MergeMode::Concat),
+            "dedup" => Ok(MergeMode::Dedup),
+            "union" => Ok(MergeMode::Union),
+            _ => Err(anyhow!("Unknown merge mode: {} (available: concat, dedup, union)", name)),
+        }
+    }
+}
+
+/// Merges `records` according to `mode`. Records keep the order in which
+/// they (or, for `Union`, the first record with their parameters) appear.
+pub fn merge_records<I>(records: I, mode: MergeMode) -> Vec<ResultRecord>
+where
+    I: IntoIterator<Item = ResultRecord>,
+{
+    match mode {
+        MergeMode::Concat => records.into_iter().collect(),
+        MergeMode::Dedup => {
+            let mut seen = HashSet::new();
+            records
+                .into_iter()
+                .filter(|record| seen.insert((record.job, record.result.numbers.clone())))
+                .collect()
+        }
+        MergeMode::Union => {
+            let mut positions: HashMap<LineNumbers, usize> = HashMap::new();
+            let mut merged: Vec<ResultRecord> = Vec::new();
+            for record in records {
+                match positions.get(&record.job) {
+                    Some(&position) => merged[position].result.numbers.extend(record.result.numbers),
+                    None => {
+                        positions.insert(record.job, merged.len());
+                        merged.push(record);
+                    }
+                }
+            }
+            for record in &mut merged {
+                record.result.numbers.sort_unstable();
+                record.result.numbers.dedup();
+            }
+            merged
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_records, MergeMode};
+    use crate::{LineNumbers, ResultNumbers, ResultRecord};
+
+    fn record(end: u32, numbers: &[u32]) -> ResultRecord {
+        ResultRecord {
+            index: 0,
+            job: LineNumbers { a: 2, b: 3, end },
+            result: ResultNumbers {
+                end,
+                numbers: numbers.to_vec(),
+                count: None,
+            },
+        }
+    }
+
+    fn merged(mode: MergeMode) -> Vec<ResultRecord> {
+        let records = vec![record(6, &[2, 3]), record(4, &[2]), record(6, &[2, 3]), record(6, &[4, 6, 2])];
+        merge_records(records, mode)
+    }
+
+    #[test]
+    fn test_merge_modes() {
+        assert_eq!(merged(MergeMode::Concat).len(), 4);
+
+        let dedup = merged(MergeMode::Dedup);
+        assert_eq!(dedup.len(), 3);
+        assert_eq!(dedup[2].result.numbers, vec![4, 6, 2]);
+
+        let union = merged(MergeMode::Union);
+        assert_eq!(union.len(), 2);
+        assert_eq!(union[0].result.numbers, vec![2, 3, 4, 6]);
+        assert_eq!(union[1].result.end, 4);
+    }
+}
@@ -0,0 +1,280 @@
+//! Publishing records to a message broker, for `--sink`.
+//!
+//! NATS is spoken directly over its text protocol: a `CONNECT`, one `PUB`
+//! per message and a closing `PING` whose `PONG` confirms the server
+//! accepted everything. Kafka's protocol needs a client library this build
+//! doesn't have, so `kafka://` URLs are recognised but rejected.
+
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::formatter::{NdjsonFormatter, OutputFormatter};
+use crate::pipeline::Sink;
+use crate::{ResultNumbers, ResultRecord};
+
+/// Default port of a NATS server.
+pub const NATS_PORT: u16 = 4222;
+
+/// How each message encodes its record.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MessageFormat {
+    /// The record as one `ndjson` object, without the line break.
+    #[default]
+    Json,
+    /// The same fields as a MessagePack map.
+    Msgpack,
+}
+
+impl FromStr for MessageFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "json" => Ok(MessageFormat::Json),
+            "msgpack" => Ok(MessageFormat::Msgpack),
+            _ => Err(anyhow!("Unknown message format: {} (available: json, msgpack)", name)),
+        }
+    }
+}
+
+/// Splits `record` into records of at most `batch` multiples each, so that
+/// large ranges don't end up in a single huge message. Count-only records
+/// are kept whole.
+pub fn split_record(record: ResultRecord, batch: Option<usize>) -> Vec<ResultRecord> {
+    let Some(batch) = batch.filter(|&batch| record.result.numbers.len() > batch) else {
+        return vec![record];
+    };
+    record
+        .result
+        .numbers
+        .chunks(batch)
+        .map(|numbers| ResultRecord {
+            result: ResultNumbers {
+                end: record.result.end,
+                numbers: numbers.to_vec(),
+                count: None,
+            },
+            ..record.clone()
+        })
+        .collect()
+}
+
+/// Appends the message for `record` to `out`.
+pub fn encode(record: &ResultRecord, format: MessageFormat, out: &mut Vec<u8>) -> Result<()> {
+    match format {
+        MessageFormat::Json => {
+            NdjsonFormatter::default().write_record(out, record)?;
+            out.pop();
+        }
+        MessageFormat::Msgpack => {
+            let listed = record.result.count.is_none();
+            out.push(0x80 | if listed { 6 } else { 5 });
+            let fields = [
+                ("index", record.index as u64),
+                ("a", record.job.a as u64),
+                ("b", record.job.b as u64),
+                ("end", record.result.end as u64),
+                ("count", record.result.count()),
+            ];
+            for (key, value) in fields {
+                msgpack_str(out, key);
+                msgpack_uint(out, value);
+            }
+            if listed {
+                msgpack_str(out, "numbers");
+                let len = record.result.numbers.len();
+                match u16::try_from(len) {
+                    Ok(len) if len < 16 => out.push(0x90 | len as u8),
+                    Ok(len) => {
+                        out.push(0xdc);
+                        out.extend_from_slice(&len.to_be_bytes());
+                    }
+                    Err(_) => {
+                        out.push(0xdd);
+                        out.extend_from_slice(&(len as u32).to_be_bytes());
+                    }
+                }
+                for &n in &record.result.numbers {
+                    msgpack_uint(out, n as u64);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a MessagePack string; the keys written are all shorter than 32 bytes.
+fn msgpack_str(out: &mut Vec<u8>, text: &str) {
+    out.push(0xa0 | text.len() as u8);
+    out.extend_from_slice(text.as_bytes());
+}
+
+/// Writes `value` in the shortest MessagePack unsigned integer form.
+fn msgpack_uint(out: &mut Vec<u8>, value: u64) {
+    if value < 0x80 {
+        out.push(value as u8);
+    } else if let Ok(value) = u8::try_from(value) {
+        out.extend_from_slice(&[0xcc, value]);
+    } else if let Ok(value) = u16::try_from(value) {
+        out.push(0xcd);
+        out.extend_from_slice(&value.to_be_bytes());
+    } else if let Ok(value) = u32::try_from(value) {
+        out.push(0xce);
+        out.extend_from_slice(&value.to_be_bytes());
+    } else {
+        out.push(0xcf);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+/// Publishes every record, or every batch of its multiples, as a message on
+/// a NATS subject.
+pub struct NatsSink {
+    out: BufWriter<TcpStream>,
+    replies: BufReader<TcpStream>,
+    subject: String,
+    format: MessageFormat,
+    batch: Option<usize>,
+    message: Vec<u8>,
+}
+
+impl NatsSink {
+    /// Connects to the server at `address` (`host:port`) and waits for its
+    /// greeting.
+    pub fn connect(address: &str, subject: &str, format: MessageFormat, batch: Option<usize>) -> Result<Self> {
+        if subject.is_empty() || subject.contains(char::is_whitespace) {
+            bail!("Invalid NATS subject: {:?}", subject);
+        }
+        let stream = TcpStream::connect(address).with_context(|| format!("Failed to connect to {}", address))?;
+        let mut replies = BufReader::new(stream.try_clone()?);
+        let mut greeting = String::new();
+        replies.read_line(&mut greeting)?;
+        if !greeting.starts_with("INFO ") {
+            bail!("{} is not a NATS server: {:?}", address, greeting.trim_end());
+        }
+        let mut out = BufWriter::new(stream);
+        out.write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false,\"name\":\"multiple_of_a_and_b\"}\r\n")?;
+        Ok(NatsSink {
+            out,
+            replies,
+            subject: subject.to_string(),
+            format,
+            batch,
+            message: Vec::new(),
+        })
+    }
+}
+
+impl Sink for NatsSink {
+    fn write(&mut self, record: ResultRecord) -> Result<()> {
+        for part in split_record(record, self.batch) {
+            self.message.clear();
+            encode(&part, self.format, &mut self.message)?;
+            write!(self.out, "PUB {} {}\r\n", self.subject, self.message.len())?;
+            self.out.write_all(&self.message)?;
+            self.out.write_all(b"\r\n")?;
+        }
+        self.out.flush().context("Failed to publish to NATS")
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.out.write_all(b"PING\r\n")?;
+        self.out.flush().context("Failed to publish to NATS")?;
+        loop {
+            let mut reply = String::new();
+            if self.replies.read_line(&mut reply)? == 0 {
+                bail!("NATS server closed the connection");
+            }
+            match reply.trim_end() {
+                "PONG" => return Ok(()),
+                "PING" => self.out.write_all(b"PONG\r\n")?,
+                error if error.starts_with("-ERR") => bail!("NATS server error: {}", error),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Connects the sink for a `nats://host[:port]/subject` URL.
+pub fn connect(url: &str, format: MessageFormat, batch: Option<usize>) -> Result<Box<dyn Sink>> {
+    if let Some(rest) = url.strip_prefix("nats://") {
+        let (host, subject) = rest
+            .split_once('/')
+            .ok_or_else(|| anyhow!("Missing subject in {} (expected nats://host[:port]/subject)", url))?;
+        let address = if host.contains(':') { host.to_string() } else { format!("{}:{}", host, NATS_PORT) };
+        return Ok(Box::new(NatsSink::connect(&address, subject, format, batch)?));
+    }
+    if url.starts_with("kafka://") {
+        bail!("Kafka sinks aren't supported: this build has no Kafka client; publish through nats:// instead");
+    }
+    bail!("Unknown message broker URL: {} (expected nats://host[:port]/subject)", url)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::{connect, encode, split_record, MessageFormat};
+    use crate::{count_record, generate_record, formula::Mode, LineNumbers};
+
+    #[test]
+    fn test_encode_and_split() {
+        let record = generate_record(LineNumbers { a: 2, b: 3, end: 4 });
+        let mut message = Vec::new();
+        encode(&record, MessageFormat::Json, &mut message).unwrap();
+        assert_eq!(message, b"{\"schema\":1,\"index\":0,\"a\":2,\"b\":3,\"end\":4,\"count\":3,\"numbers\":[2,3,4]}");
+        message.clear();
+        encode(&count_record(LineNumbers { a: 2, b: 3, end: 300 }, Mode::Or), MessageFormat::Msgpack, &mut message).unwrap();
+        let mut expected = vec![0x85];
+        for (key, value) in [("index", &[0][..]), ("a", &[2]), ("b", &[3]), ("end", &[0xcd, 1, 44]), ("count", &[0xcc, 200])] {
+            expected.push(0xa0 | key.len() as u8);
+            expected.extend_from_slice(key.as_bytes());
+            expected.extend_from_slice(value);
+        }
+        assert_eq!(message, expected);
+
+        let parts = split_record(generate_record(LineNumbers { a: 1, b: 1, end: 5 }), Some(2));
+        let numbers: Vec<_> = parts.iter().map(|part| part.result.numbers.clone()).collect();
+        assert_eq!(numbers, [vec![1, 2], vec![3, 4], vec![5]]);
+        assert_eq!(split_record(generate_record(LineNumbers { a: 1, b: 1, end: 2 }), Some(2)).len(), 1);
+    }
+
+    #[test]
+    fn test_nats_sink_publishes_and_confirms() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("nats://{}/multiples", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"INFO {}\r\n").unwrap();
+            let mut lines = BufReader::new(stream.try_clone().unwrap());
+            let mut received = Vec::new();
+            loop {
+                let mut line = String::new();
+                lines.read_line(&mut line).unwrap();
+                if line == "PING\r\n" {
+                    stream.write_all(b"PONG\r\n").unwrap();
+                    return received;
+                }
+                if let Some(len) = line.strip_prefix("PUB multiples ") {
+                    let mut payload = vec![0; len.trim_end().parse::<usize>().unwrap() + 2];
+                    lines.read_exact(&mut payload).unwrap();
+                    payload.truncate(payload.len() - 2);
+                    received.push(String::from_utf8(payload).unwrap());
+                }
+            }
+        });
+        let mut sink = connect(&url, MessageFormat::Json, Some(2)).unwrap();
+        sink.write(generate_record(LineNumbers { a: 2, b: 3, end: 4 })).unwrap();
+        sink.finish().unwrap();
+        let received = server.join().unwrap();
+        assert_eq!(received.len(), 2);
+        assert!(received[0].ends_with("\"count\":2,\"numbers\":[2,3]}"));
+        assert!(received[1].ends_with("\"numbers\":[4]}"));
+        assert!(connect("kafka://localhost/multiples", MessageFormat::Json, None).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+//! The line endings of text outputs, for `--line-ending`: `\n` as the
+//! formatters write them, or `\r\n` for Windows programs such as Notepad
+//! and Excel, which is the default on Windows. Binary formats are written
+//! as they are.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use crate::formatter::OutputFormatter;
+use crate::ResultRecord;
+
+/// Formats whose bytes aren't text, whose line endings are left alone.
+pub const BINARY_FORMATS: [&str; 3] = ["store", "delta", "roaring"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    /// The line ending of text files on this platform.
+    pub fn native() -> Self {
+        match cfg!(windows) {
+            true => LineEnding::Crlf,
+            false => LineEnding::Lf,
+        }
+    }
+
+    /// `formatter`, writing this line ending if `format` is text.
+    pub fn apply(self, format: &str, formatter: Box<dyn OutputFormatter>) -> Box<dyn OutputFormatter> {
+        match self {
+            LineEnding::Crlf if !BINARY_FORMATS.contains(&format) => Box::new(CrlfFormatter { inner: formatter }),
+            _ => formatter,
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            _ => Err(anyhow!("--line-ending is lf or crlf, not {}", name)),
+        }
+    }
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LineEnding::Lf => "lf",
+            LineEnding::Crlf => "crlf",
+        })
+    }
+}
+
+/// A formatter whose `\n`s are written as `\r\n`.
+struct CrlfFormatter {
+    inner: Box<dyn OutputFormatter>,
+}
+
+impl OutputFormatter for CrlfFormatter {
+    fn begin(&mut self, w: &mut dyn Write) -> Result<()> {
+        self.inner.begin(&mut Crlf(w))
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        self.inner.resume()
+    }
+
+    fn annotate(&mut self) -> Result<()> {
+        self.inner.annotate()
+    }
+
+    fn group_by_divisor(&mut self) -> Result<()> {
+        self.inner.group_by_divisor()
+    }
+
+    fn format_threads(&mut self, threads: usize) {
+        self.inner.format_threads(threads)
+    }
+
+    fn checksum(&mut self) -> Result<()> {
+        self.inner.checksum()
+    }
+
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        self.inner.write_record(&mut Crlf(w), r)
+    }
+
+    fn write_streamed(
+        &mut self,
+        w: &mut dyn Write,
+        r: &ResultRecord,
+        count: Option<u64>,
+        chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    ) -> Result<()> {
+        self.inner.write_streamed(&mut Crlf(w), r, count, chunks)
+    }
+
+    fn finish(&mut self, w: &mut dyn Write) -> Result<()> {
+        self.inner.finish(&mut Crlf(w))
+    }
+}
+
+/// Writes to the inner writer with every `\n` as `\r\n`.
+struct Crlf<'a>(&'a mut dyn Write);
+
+impl Write for Crlf<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for line in buf.split_inclusive(|&byte| byte == b'\n') {
+            match line.strip_suffix(b"\n") {
+                Some(line) => {
+                    self.0.write_all(line)?;
+                    self.0.write_all(b"\r\n")?;
+                }
+                None => self.0.write_all(line)?,
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LineEnding;
+    use crate::formatter::FormatterRegistry;
+    use crate::pipeline::{jobs_source, FormatterSink, Pipeline};
+    use crate::reader::ResultReaderRegistry;
+    use crate::LineNumbers;
+
+    fn written(format: &str, ending: LineEnding) -> Vec<u8> {
+        let formatter = ending.apply(format, FormatterRegistry::with_builtins().create(format).unwrap());
+        let mut sink = FormatterSink::new(Vec::new(), formatter);
+        let jobs = vec![LineNumbers { a: 2, b: 3, end: 10 }, LineNumbers { a: 5, b: 7, end: 20 }];
+        Pipeline::builder(jobs_source(jobs)).build().run(&mut sink).unwrap();
+        sink.into_inner()
+    }
+
+    #[test]
+    fn test_crlf() {
+        assert_eq!(written("text", LineEnding::Crlf), b"10:2 3 4 6 8 9 10\r\n20:5 7 10 14 15 20\r\n");
+        assert_eq!(written("text", LineEnding::Lf), b"10:2 3 4 6 8 9 10\n20:5 7 10 14 15 20\n");
+        assert!(written("csv", LineEnding::Crlf).starts_with(b"index,a,b,end,count,numbers\r\n0,2,3,10,"));
+        assert_eq!(written("delta", LineEnding::Crlf), written("delta", LineEnding::Lf));
+        assert_eq!("crlf".parse::<LineEnding>().unwrap(), LineEnding::Crlf);
+        assert!("cr".parse::<LineEnding>().is_err());
+    }
+
+    /// Files with CRLF line endings read back as the same records.
+    #[test]
+    fn test_read_crlf() {
+        let path = std::env::temp_dir().join(format!("multiples_crlf_{}.ndjson", std::process::id()));
+        std::fs::write(&path, written("ndjson", LineEnding::Crlf)).unwrap();
+        let records: Vec<_> = ResultReaderRegistry::with_builtins().open(&path, None).unwrap().map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].result.numbers, [5, 7, 10, 14, 15, 20]);
+    }
+}
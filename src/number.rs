@@ -0,0 +1,266 @@
+use anyhow::{anyhow, bail, Context, Result};
+
+/// Parses a human-friendly integer literal.
+///
+/// Besides plain decimal digits this accepts `_` digit separators (`1_000_000`),
+/// hexadecimal, octal and binary prefixes (`0x3E8`, `0o17`, `0b101`), exponents
+/// (`1e9`, `2.5e3`), decimal unit suffixes (`10k`, `2M`, `2.5G`, `3T`), binary
+/// ones (`64Ki`, `1.5Gi`) and powers (`2^3`). The value described by the
+/// literal must be a whole number.
+pub fn parse_number(literal: &str) -> Result<u64> {
+    if let Some((base, exponent)) = literal.split_once('^') {
+        if exponent.contains('^') {
+            bail!("Chained powers aren't supported: {:?}", literal);
+        }
+        let base = parse_number(base)?;
+        let exponent = parse_number(exponent)?;
+        return u32::try_from(exponent)
+            .ok()
+            .and_then(|exponent| base.checked_pow(exponent))
+            .with_context(|| format!("Power too large: {:?}", literal));
+    }
+    let text = literal.strip_prefix('+').unwrap_or(literal);
+    if text.is_empty() {
+        bail!("Empty number literal");
+    }
+
+    let lower = text.to_ascii_lowercase();
+    let radix = if lower.starts_with("0x") {
+        Some(16)
+    } else if lower.starts_with("0o") {
+        Some(8)
+    } else if lower.starts_with("0b") {
+        Some(2)
+    } else {
+        None
+    };
+
+    let value = match radix {
+        Some(radix) => parse_radix(&text[2..], radix),
+        None => parse_decimal(text),
+    };
+    value.with_context(|| format!("Invalid number literal: {:?}", literal))
+}
+
+/// Parses a literal with [`parse_number`] and checks that it fits into a `u32`.
+pub fn parse_u32(literal: &str) -> Result<u32> {
+    let value = parse_number(literal)?;
+    u32::try_from(value).with_context(|| format!("Number out of range: {:?}", literal))
+}
+
+/// The number of digits of the largest `u32`.
+pub const MAX_DIGITS: usize = 10;
+
+/// Pairs of digits `00` to `99`, to write two digits per division.
+const DIGIT_PAIRS: &[u8; 200] = b"\
+0001020304050607080910111213141516171819\
+2021222324252627282930313233343536373839\
+4041424344454647484950515253545556575859\
+6061626364656667686970717273747576777879\
+8081828384858687888990919293949596979899";
+
+/// Writes the decimal digits of `n` to the end of `buf`, returning them,
+/// without the formatting machinery of `write!` or a `String`.
+pub fn format_u32(mut n: u32, buf: &mut [u8; MAX_DIGITS]) -> &[u8] {
+    let mut start = MAX_DIGITS;
+    while n >= 100 {
+        let pair = (n % 100) as usize * 2;
+        n /= 100;
+        start -= 2;
+        buf[start..start + 2].copy_from_slice(&DIGIT_PAIRS[pair..pair + 2]);
+    }
+    if n >= 10 {
+        let pair = n as usize * 2;
+        start -= 2;
+        buf[start..start + 2].copy_from_slice(&DIGIT_PAIRS[pair..pair + 2]);
+    } else {
+        start -= 1;
+        buf[start] = b'0' + n as u8;
+    }
+    &buf[start..]
+}
+
+fn strip_separators(digits: &str) -> Result<String> {
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        bail!("Misplaced digit separator");
+    }
+    Ok(digits.replace('_', ""))
+}
+
+fn parse_radix(digits: &str, radix: u32) -> Result<u64> {
+    let digits = strip_separators(digits)?;
+    if digits.is_empty() {
+        bail!("Missing digits after radix prefix");
+    }
+    Ok(u64::from_str_radix(&digits, radix)?)
+}
+
+fn suffix_exponent(suffix: char) -> Option<u32> {
+    match suffix {
+        'k' | 'K' => Some(3),
+        'M' => Some(6),
+        'G' => Some(9),
+        'T' => Some(12),
+        _ => None,
+    }
+}
+
+fn binary_power(suffix: &str) -> Option<u32> {
+    match suffix {
+        "Ki" | "ki" => Some(1),
+        "Mi" => Some(2),
+        "Gi" => Some(3),
+        "Ti" => Some(4),
+        _ => None,
+    }
+}
+
+/// `mantissa` times `1024^power`, e.g. `1.5` and 1 for `1.5Ki`.
+fn parse_binary(mantissa: &str, power: u32) -> Result<u64> {
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let frac_digits = strip_separators(frac_part)?;
+    let digits = strip_separators(int_part)? + &frac_digits;
+    if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+        bail!("Unexpected character");
+    }
+    let too_large = || anyhow!("Number too large");
+    let unit = 10u128.checked_pow(frac_digits.len() as u32).ok_or_else(too_large)?;
+    let scaled = digits
+        .parse::<u128>()
+        .ok()
+        .and_then(|digits| digits.checked_mul(1024u128.pow(power)))
+        .ok_or_else(too_large)?;
+    if scaled % unit != 0 {
+        bail!("Not a whole number");
+    }
+    u64::try_from(scaled / unit).map_err(|_| too_large())
+}
+
+fn parse_decimal(text: &str) -> Result<u64> {
+    if let Some(power) = text.get(text.len().saturating_sub(2)..).and_then(binary_power) {
+        return parse_binary(&text[..text.len() - 2], power);
+    }
+    let (text, mut exponent) = match text.chars().last().and_then(suffix_exponent) {
+        Some(exp) => (&text[..text.len() - 1], exp as i64),
+        None => (text, 0),
+    };
+
+    let (mantissa, exp_part) = match text.find(['e', 'E']) {
+        Some(pos) => (&text[..pos], Some(&text[pos + 1..])),
+        None => (text, None),
+    };
+    if let Some(exp_part) = exp_part {
+        let exp_part = exp_part.strip_prefix('+').unwrap_or(exp_part);
+        if exp_part.is_empty() || !exp_part.bytes().all(|c| c.is_ascii_digit()) {
+            bail!("Invalid exponent");
+        }
+        exponent += exp_part.parse::<i64>()?;
+    }
+
+    let (int_part, frac_part) = match mantissa.split_once('.') {
+        Some((int_part, frac_part)) => (int_part, frac_part),
+        None => (mantissa, ""),
+    };
+    let int_digits = strip_separators(int_part)?;
+    let frac_digits = strip_separators(frac_part)?;
+    if int_digits.is_empty() && frac_digits.is_empty() {
+        bail!("Missing digits");
+    }
+    if !int_digits.bytes().chain(frac_digits.bytes()).all(|c| c.is_ascii_digit()) {
+        bail!("Unexpected character");
+    }
+
+    // Scale the digit string so that `exponent` applies to its last digit.
+    let mut digits = int_digits + frac_digits.trim_end_matches('0');
+    exponent -= frac_digits.trim_end_matches('0').len() as i64;
+    if exponent < 0 {
+        let keep = digits.len() as i64 + exponent;
+        if keep < 0 || digits[keep as usize..].bytes().any(|c| c != b'0') {
+            bail!("Not a whole number");
+        }
+        digits.truncate(keep as usize);
+        exponent = 0;
+    }
+
+    let mut value: u64 = if digits.is_empty() { 0 } else { digits.parse()? };
+    for _ in 0..exponent {
+        if value == 0 {
+            break;
+        }
+        value = value.checked_mul(10).context("Number too large")?;
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_u32, parse_number, parse_u32};
+
+    #[test]
+    fn test_format_u32() {
+        let mut buf = [0; 10];
+        for n in [0, 7, 10, 99, 100, 12_345, 1_000_000, u32::MAX] {
+            assert_eq!(format_u32(n, &mut buf), n.to_string().as_bytes());
+        }
+        for n in 0..100_000 {
+            assert_eq!(format_u32(n * 7919, &mut buf), (n * 7919).to_string().as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_parse_number_plain_and_separators() {
+        assert_eq!(parse_number("42").unwrap(), 42);
+        assert_eq!(parse_number("+7").unwrap(), 7);
+        assert_eq!(parse_number("1_000_000").unwrap(), 1_000_000);
+        assert!(parse_number("_1").is_err());
+        assert!(parse_number("1__0").is_err());
+    }
+
+    #[test]
+    fn test_parse_number_exponents_and_suffixes() {
+        assert_eq!(parse_number("1e9").unwrap(), 1_000_000_000);
+        assert_eq!(parse_number("2.5e3").unwrap(), 2500);
+        assert_eq!(parse_number("10k").unwrap(), 10_000);
+        assert_eq!(parse_number("2M").unwrap(), 2_000_000);
+        assert_eq!(parse_number("1.5G").unwrap(), 1_500_000_000);
+        assert_eq!(parse_number("12.0").unwrap(), 12);
+        assert_eq!(parse_number("2.5G").unwrap(), 2_500_000_000);
+        assert_eq!(parse_number("64Ki").unwrap(), 65_536);
+        assert_eq!(parse_number("1.5Gi").unwrap(), 3 << 29);
+        assert_eq!(parse_number("1_024Mi").unwrap(), 1 << 30);
+        assert!(parse_number("1.0001Ki").is_err());
+        assert!(parse_number("Ki").is_err());
+        assert!(parse_u32("4Gi").is_err());
+        assert!(parse_number("1.5").is_err());
+        assert!(parse_number("1e").is_err());
+    }
+
+    #[test]
+    fn test_parse_number_radix_prefixes() {
+        assert_eq!(parse_number("0x3E8").unwrap(), 1000);
+        assert_eq!(parse_number("0o17").unwrap(), 15);
+        assert_eq!(parse_number("0b1010_1010").unwrap(), 170);
+        assert!(parse_number("0x").is_err());
+    }
+
+    #[test]
+    fn test_parse_number_powers() {
+        assert_eq!(parse_number("2^3").unwrap(), 8);
+        assert_eq!(parse_number("0x10^2").unwrap(), 256);
+        assert_eq!(parse_number("10^0").unwrap(), 1);
+        assert_eq!(parse_u32("2^31").unwrap(), 1 << 31);
+        assert!(parse_u32("2^32").is_err());
+        assert!(parse_number("2^64").is_err());
+        assert!(parse_number("2^3^2").is_err());
+        assert!(parse_number("^3").is_err());
+    }
+
+    #[test]
+    fn test_parse_number_rejects_garbage() {
+        assert!(parse_number("").is_err());
+        assert!(parse_number("nine").is_err());
+        assert!(parse_number("-5").is_err());
+        assert!(parse_u32("5G").is_err());
+        assert_eq!(parse_u32("4k").unwrap(), 4000);
+    }
+}
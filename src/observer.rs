@@ -0,0 +1,136 @@
+//! Hooks for library users into generating a line, registered with
+//! [`GenerateOptions::observers`](crate::strategy::GenerateOptions::observers):
+//! for progress displays of their own, aggregating multiples as they are
+//! found or cutting a line short, without a pipeline stage or sink.
+
+use std::time::Duration;
+
+use crate::LineNumbers;
+
+/// Whether to go on generating a line after a chunk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    /// Generate no further chunks of the line. Its record holds the
+    /// multiples of the part of the range generated before.
+    Stop,
+}
+
+/// What generating a line came to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineStats {
+    /// The multiples selected, or counted for count-only records.
+    pub count: u64,
+    pub elapsed: Duration,
+    /// Whether an observer or a cancelled
+    /// [`CancellationToken`](crate::cancel::CancellationToken) stopped the line.
+    pub stopped: bool,
+}
+
+/// Called by the generator for every line it generates, on the threads
+/// generating it. All hooks do nothing by default.
+pub trait Observer: Send + Sync {
+    /// Before the first chunk of `job` is generated.
+    fn on_line_start(&self, _job: &LineNumbers) {}
+
+    /// Once the multiples of a chunk of `job`'s range are found, which are
+    /// empty for count-only records. A line generated on one thread passes
+    /// its chunks in listing order, one generated on several threads as
+    /// they are done, not in order, and a line counted in closed form
+    /// passes one chunk.
+    fn on_chunk(&self, _job: &LineNumbers, _numbers: &[u32]) -> Flow {
+        Flow::Continue
+    }
+
+    /// Once `job` is generated.
+    fn on_line_done(&self, _job: &LineNumbers, _stats: &LineStats) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use super::{Flow, LineStats, Observer};
+    use crate::strategy::{GenerateOptions, RecordGenerator};
+    use crate::LineNumbers;
+
+    /// Sums the multiples it is shown, stopping lines after `limit` of them.
+    #[derive(Default)]
+    struct Summing {
+        sum: AtomicU64,
+        limit: Option<u64>,
+        seen: AtomicU64,
+        done: Mutex<Vec<LineStats>>,
+    }
+
+    impl Observer for Summing {
+        fn on_chunk(&self, _job: &LineNumbers, numbers: &[u32]) -> Flow {
+            self.sum.fetch_add(numbers.iter().map(|&n| n as u64).sum(), Ordering::Relaxed);
+            let seen = self.seen.fetch_add(numbers.len() as u64, Ordering::Relaxed) + numbers.len() as u64;
+            match self.limit {
+                Some(limit) if seen >= limit => Flow::Stop,
+                _ => Flow::Continue,
+            }
+        }
+
+        fn on_line_done(&self, _job: &LineNumbers, stats: &LineStats) {
+            self.done.lock().unwrap().push(*stats);
+        }
+    }
+
+    #[test]
+    fn test_observers() {
+        let summing = Arc::new(Summing::default());
+        let generator = RecordGenerator::new(GenerateOptions {
+            observers: vec![summing.clone()],
+            ..Default::default()
+        });
+        let job = LineNumbers { a: 3, b: 5, end: 100 };
+        generator.generate(job);
+        let record = generator.generate_chunked(LineNumbers { end: 1_000_000, ..job }, 2, None, &|_| {});
+        assert_eq!(summing.sum.load(Ordering::Relaxed), 2418 + record.result.numbers.iter().map(|&n| n as u64).sum::<u64>());
+        let done = summing.done.lock().unwrap();
+        assert_eq!(done.iter().map(|stats| (stats.count, stats.stopped)).collect::<Vec<_>>(), [(47, false), (466_667, false)]);
+
+        // Chunks passed to observers leave the record as it is.
+        for descending in [false, true] {
+            let options = || GenerateOptions { descending, include_zero: true, ..Default::default() };
+            let observed = RecordGenerator::new(GenerateOptions { observers: vec![Arc::new(Summing::default())], ..options() });
+            let job = LineNumbers { end: 300_000, ..job };
+            assert_eq!(observed.generate(job), RecordGenerator::new(options()).generate(job));
+        }
+    }
+
+    #[test]
+    fn test_stop() {
+        let summing = Arc::new(Summing {
+            limit: Some(1),
+            ..Default::default()
+        });
+        let generator = RecordGenerator::new(GenerateOptions {
+            observers: vec![summing.clone()],
+            ..Default::default()
+        });
+        let job = LineNumbers { a: 3, b: 5, end: 1_000_000 };
+        let record = generator.generate_chunked(job, 2, None, &|_| {});
+        let full = RecordGenerator::new(GenerateOptions::default()).generate(job);
+        // What is left is the start of the record.
+        let numbers = &record.result.numbers;
+        assert!(!numbers.is_empty() && numbers.len() < full.result.numbers.len());
+        assert_eq!(numbers[..], full.result.numbers[..numbers.len()]);
+        let stats = summing.done.lock().unwrap()[0];
+        assert!(stats.stopped);
+        assert_eq!(stats.count, numbers.len() as u64);
+        // Streamed lines stop after the chunk that stopped them.
+        assert_eq!(generator.chunks(job, 1000).count(), 1);
+        let stats = summing.done.lock().unwrap()[1];
+        assert_eq!((stats.count, stats.stopped), (467, true));
+        // So do lines generated on one thread.
+        let numbers = generator.generate(job).result.numbers;
+        assert!(!numbers.is_empty() && numbers.len() < full.result.numbers.len());
+        assert_eq!(numbers[..], full.result.numbers[..numbers.len()]);
+        let stats = summing.done.lock().unwrap()[2];
+        assert_eq!((stats.count, stats.stopped), (numbers.len() as u64, true));
+    }
+}
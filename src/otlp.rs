@@ -0,0 +1,332 @@
+//! Export of the spans collected by a [`Profiler`] to an OpenTelemetry
+//! collector, for `--otlp-endpoint`.
+//!
+//! Every sampled input line becomes a trace of its own: a `job` span
+//! covering the line, with a child span for each phase it went through
+//! (parse, generate, format, write). Traces are posted as OTLP/HTTP JSON to
+//! `<endpoint>/v1/traces`, in requests of at most [`TRACES_PER_REQUEST`].
+//!
+//! The long-running modes export an [`Operation`] at a time instead: a
+//! span per HTTP request of `serve` and per scheduled run of `daemon`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+
+use crate::http::{self, HttpUrl};
+use crate::json;
+use crate::profile::{Profiler, Span};
+use crate::random::Rng;
+
+/// Lines sent to the collector in one request.
+pub const TRACES_PER_REQUEST: usize = 256;
+
+const SERVICE_NAME: &str = "multiple_of_a_and_b";
+
+/// Where and how many traces are exported.
+#[derive(Clone, Debug)]
+pub struct OtlpExporter {
+    url: HttpUrl,
+    /// The share of lines exported, from 0 to 1.
+    sample: f64,
+    timeout: Duration,
+}
+
+impl OtlpExporter {
+    /// Exports to the collector at `endpoint`, e.g. `http://localhost:4318`;
+    /// `/v1/traces` is added unless the endpoint has a path of its own.
+    pub fn new(endpoint: &str, sample: f64) -> Result<Self> {
+        if !(0.0..=1.0).contains(&sample) {
+            bail!("Invalid sampling ratio: {} (must be between 0 and 1)", sample);
+        }
+        let mut url = HttpUrl::parse(endpoint)?;
+        if url.path == "/" {
+            url.path = String::from("/v1/traces");
+        }
+        Ok(OtlpExporter {
+            url,
+            sample,
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    /// Sends the traces of the lines `profiler` timed, returning how many
+    /// were sampled.
+    pub fn export(&self, profiler: &Profiler) -> Result<usize> {
+        let mut rng = seeded_rng();
+        let spans = profiler.spans();
+        let traces: Vec<Vec<&Span>> = lines(&spans)
+            .into_values()
+            .filter(|_| self.sample >= 1.0 || rng.unit() < self.sample)
+            .collect();
+        let epoch = profiler.started_at();
+        for batch in traces.chunks(TRACES_PER_REQUEST) {
+            self.post(&request_body(batch, epoch, &mut rng))?;
+        }
+        Ok(traces.len())
+    }
+
+    /// Sends `operation` as a trace of its own, if it is sampled, returning
+    /// whether it was.
+    pub fn export_operation(&self, operation: &Operation) -> Result<bool> {
+        let mut rng = seeded_rng();
+        if self.sample < 1.0 && rng.unit() >= self.sample {
+            return Ok(false);
+        }
+        self.post(&operation_body(operation, &mut rng))?;
+        Ok(true)
+    }
+
+    fn post(&self, body: &str) -> Result<()> {
+        let response = http::post(&self.url, "application/json", body.as_bytes(), self.timeout)?;
+        if !(200..300).contains(&response.status) {
+            bail!(
+                "Collector answered {}: {}",
+                response.status,
+                String::from_utf8_lossy(&response.body).trim()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The value of a span attribute.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Attribute {
+    Int(u64),
+    Text(String),
+}
+
+/// A unit of work of a long-running mode, exported as a single span.
+#[derive(Clone, Debug)]
+pub struct Operation {
+    pub name: String,
+    /// `SPAN_KIND_SERVER` rather than internal, for answered requests.
+    pub server: bool,
+    pub start: SystemTime,
+    pub duration: Duration,
+    pub attributes: Vec<(&'static str, Attribute)>,
+    /// Why the operation failed, if it did.
+    pub error: Option<String>,
+}
+
+fn seeded_rng() -> Rng {
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+    Rng::new(seed ^ std::process::id() as u64)
+}
+
+/// The spans tied to an input line, by line.
+fn lines(spans: &[Span]) -> BTreeMap<usize, Vec<&Span>> {
+    let mut lines: BTreeMap<usize, Vec<&Span>> = BTreeMap::new();
+    for span in spans {
+        if let Some(index) = span.index {
+            lines.entry(index).or_default().push(span);
+        }
+    }
+    lines
+}
+
+fn hex_id(rng: &mut Rng, words: usize) -> String {
+    (0..words).map(|_| format!("{:016x}", rng.next_u64())).collect()
+}
+
+fn unix_nanos(epoch: SystemTime, offset: Duration) -> u128 {
+    (epoch + offset).duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+}
+
+/// `attributes` as the members of an OTLP attribute list.
+fn attributes_json(attributes: &[(&str, Attribute)]) -> String {
+    let attributes: Vec<String> = attributes
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Attribute::Int(n) => format!("{{\"intValue\":\"{}\"}}", n),
+                Attribute::Text(text) => format!("{{\"stringValue\":{}}}", json::quote(text)),
+            };
+            format!("{{\"key\":{},\"value\":{}}}", json::quote(key), value)
+        })
+        .collect();
+    attributes.join(",")
+}
+
+/// Appends one span of `kind`; `times` are its start and end in Unix
+/// nanoseconds, `extra` the members of the span after its attributes.
+fn write_span(
+    out: &mut String,
+    (trace, id, parent): (&str, &str, Option<&str>),
+    name: &str,
+    kind: u8,
+    times: (u128, u128),
+    attributes: &[(&str, Attribute)],
+    extra: &str,
+) {
+    if !out.ends_with('[') {
+        out.push(',');
+    }
+    let parent = parent.map_or_else(String::new, |parent| format!(",\"parentSpanId\":\"{}\"", parent));
+    let _ = write!(
+        out,
+        "{{\"traceId\":\"{}\",\"spanId\":\"{}\"{},\"name\":{},\"kind\":{},\"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\
+         \"attributes\":[{}]{}}}",
+        trace,
+        id,
+        parent,
+        json::quote(name),
+        kind,
+        times.0,
+        times.1,
+        attributes_json(attributes),
+        extra
+    );
+}
+
+/// The start of an OTLP/HTTP JSON request, up to its list of spans.
+fn request_start() -> String {
+    format!(
+        "{{\"resourceSpans\":[{{\"resource\":{{\"attributes\":[{{\"key\":\"service.name\",\"value\":{{\"stringValue\":\"{}\"}}}}]}},\
+         \"scopeSpans\":[{{\"scope\":{{\"name\":\"{}\"}},\"spans\":[",
+        SERVICE_NAME, SERVICE_NAME
+    )
+}
+
+/// The OTLP/HTTP JSON request for the trace of `operation`.
+fn operation_body(operation: &Operation, rng: &mut Rng) -> String {
+    let mut out = request_start();
+    let start = operation.start.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let times = (start, start + operation.duration.as_nanos());
+    // STATUS_CODE_ERROR with its message; unset otherwise.
+    let status = match &operation.error {
+        Some(error) => format!(",\"status\":{{\"code\":2,\"message\":{}}}", json::quote(error)),
+        None => String::new(),
+    };
+    let (trace, id) = (hex_id(rng, 2), hex_id(rng, 1));
+    let kind = if operation.server { 2 } else { 1 };
+    write_span(&mut out, (&trace, &id, None), &operation.name, kind, times, &operation.attributes, &status);
+    out.push_str("]}]}]}");
+    out
+}
+
+/// The OTLP/HTTP JSON request for the traces of `lines`.
+fn request_body(lines: &[Vec<&Span>], epoch: SystemTime, rng: &mut Rng) -> String {
+    let mut out = request_start();
+    for spans in lines {
+        let Some(line) = spans.first().and_then(|span| span.index) else {
+            continue;
+        };
+        let trace = hex_id(rng, 2);
+        let root = hex_id(rng, 1);
+        let start = spans.iter().map(|span| span.start).min().unwrap_or_default();
+        let end = spans.iter().map(|span| span.start + span.duration).max().unwrap_or_default();
+        let times = (unix_nanos(epoch, start), unix_nanos(epoch, end));
+        let attributes = [("line", Attribute::Int(line as u64 + 1))];
+        write_span(&mut out, (&trace, &root, None), "job", 1, times, &attributes, "");
+        for span in spans {
+            let id = hex_id(rng, 1);
+            let times = (unix_nanos(epoch, span.start), unix_nanos(epoch, span.start + span.duration));
+            write_span(&mut out, (&trace, &id, Some(&root)), span.phase.name(), 1, times, &attributes, "");
+        }
+    }
+    out.push_str("]}]}]}");
+    out
+}
+
+/// A collector for tests at the returned endpoint, answering every request
+/// with 204 and passing its body on.
+#[cfg(test)]
+pub(crate) fn stub_collector() -> (String, std::sync::mpsc::Receiver<String>) {
+    use std::io::{BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, bodies) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            if let Ok(Some(request)) = http::Request::read(&mut reader, 1 << 20) {
+                let _ = (&stream).write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n");
+                let _ = sender.send(String::from_utf8_lossy(&request.body).into_owned());
+            }
+        }
+    });
+    (endpoint, bodies)
+}
+
+/// The spans of an OTLP/HTTP JSON request.
+#[cfg(test)]
+pub(crate) fn spans_of(body: &str) -> Vec<json::Value> {
+    let request = json::parse(body).unwrap();
+    let scope = &request.get("resourceSpans").unwrap().as_array().unwrap()[0];
+    let scope = &scope.get("scopeSpans").unwrap().as_array().unwrap()[0];
+    scope.get("spans").unwrap().as_array().unwrap().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use super::{lines, request_body, spans_of, stub_collector, Attribute, Operation, OtlpExporter};
+    use crate::json;
+    use crate::profile::{Phase, Profiler};
+    use crate::random::Rng;
+
+    #[test]
+    fn test_request_body() {
+        let profiler = Profiler::default();
+        for index in 0..2 {
+            profiler.time(Phase::Parse, Some(index), || ());
+            profiler.time(Phase::Generate, Some(index), || std::thread::sleep(Duration::from_millis(1)));
+        }
+        profiler.time(Phase::Write, None, || ());
+        let spans = profiler.spans();
+        let traces: Vec<_> = lines(&spans).into_values().collect();
+        let body = request_body(&traces, UNIX_EPOCH + Duration::from_secs(1), &mut Rng::new(1));
+        let spans = spans_of(&body);
+        // A job span and two phase spans per line; the header isn't a line.
+        assert_eq!(spans.len(), 6);
+        let text = |span: &json::Value, key: &str| span.get(key).and_then(|value| value.as_text()).map(String::from);
+        assert_eq!(text(&spans[0], "name").as_deref(), Some("job"));
+        assert_eq!(text(&spans[2], "name").as_deref(), Some("generate"));
+        assert_eq!(text(&spans[2], "parentSpanId"), text(&spans[0], "spanId"));
+        assert_eq!(text(&spans[1], "traceId"), text(&spans[0], "traceId"));
+        assert_ne!(text(&spans[3], "traceId"), text(&spans[0], "traceId"));
+        let nanos = |span: &json::Value, key: &str| text(span, key).unwrap().parse::<u128>().unwrap();
+        assert!(nanos(&spans[0], "startTimeUnixNano") >= 1_000_000_000);
+        assert!(nanos(&spans[0], "endTimeUnixNano") >= nanos(&spans[2], "endTimeUnixNano"));
+    }
+
+    #[test]
+    fn test_export_operation() {
+        let (endpoint, bodies) = stub_collector();
+        let operation = Operation {
+            name: String::from("GET /multiples"),
+            server: true,
+            start: UNIX_EPOCH + Duration::from_secs(2),
+            duration: Duration::from_millis(3),
+            attributes: vec![("http.response.status_code", Attribute::Int(500)), ("url.path", Attribute::Text(String::from("/multiples")))],
+            error: Some(String::from("Internal error")),
+        };
+        assert!(OtlpExporter::new(&endpoint, 1.0).unwrap().export_operation(&operation).unwrap());
+        let spans = spans_of(&bodies.recv_timeout(Duration::from_secs(5)).unwrap());
+        assert_eq!(spans.len(), 1);
+        let text = |value: Option<&json::Value>| value.and_then(|value| value.as_text()).map(String::from);
+        assert_eq!(text(spans[0].get("name")).as_deref(), Some("GET /multiples"));
+        assert_eq!(text(spans[0].get("kind")).as_deref(), Some("2"));
+        assert_eq!(text(spans[0].get("endTimeUnixNano")).as_deref(), Some("2003000000"));
+        let attributes = spans[0].get("attributes").unwrap().as_array().unwrap();
+        assert_eq!(text(attributes[0].get("value").unwrap().get("intValue")).as_deref(), Some("500"));
+        assert_eq!(text(spans[0].get("status").unwrap().get("code")).as_deref(), Some("2"));
+        // Nothing is sent for operations left out of the sample.
+        let operation = Operation { start: SystemTime::now(), ..operation };
+        assert!(!OtlpExporter::new(&endpoint, 0.0).unwrap().export_operation(&operation).unwrap());
+    }
+
+    #[test]
+    fn test_exporter_endpoint() {
+        assert!(OtlpExporter::new("http://localhost:4318", 1.5).is_err());
+        assert_eq!(OtlpExporter::new("http://localhost:4318", 0.5).unwrap().url.path, "/v1/traces");
+        assert_eq!(OtlpExporter::new("http://localhost:4318/otlp", 1.0).unwrap().url.path, "/otlp");
+    }
+}
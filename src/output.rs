@@ -0,0 +1,888 @@
+//! Output targets that bytes produced by a formatter can be written to.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::audit::{self, Access};
+use crate::formatter::OutputFormatter;
+use crate::pipeline::Sink;
+use crate::ResultRecord;
+
+/// How the input file is read and the output file written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IoBackend {
+    /// Read through a `BufReader`, and streamed through a `BufWriter` as
+    /// records arrive.
+    Buffered,
+    /// Read through a memory map, and pre-sized to the exact output length
+    /// and written through a memory map.
+    Mmap,
+}
+
+impl FromStr for IoBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "buffered" => Ok(IoBackend::Buffered),
+            "mmap" => Ok(IoBackend::Mmap),
+            _ => Err(anyhow!("Unknown I/O backend: {} (available: buffered, mmap)", name)),
+        }
+    }
+}
+
+/// When written output is synced to the disk.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FsyncPolicy {
+    /// Left to the operating system.
+    #[default]
+    Never,
+    /// After every batch of records, one record per batch unless set otherwise.
+    PerRecord,
+    /// Once every output file is complete.
+    PerFile,
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "never" => Ok(FsyncPolicy::Never),
+            "per-record" => Ok(FsyncPolicy::PerRecord),
+            "per-file" => Ok(FsyncPolicy::PerFile),
+            _ => Err(anyhow!("Unknown fsync policy: {} (available: never, per-record, per-file)", name)),
+        }
+    }
+}
+
+/// How the records written to a file are batched and made durable.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WritePolicy {
+    pub fsync: FsyncPolicy,
+    /// Number of records handed to the operating system at once. `None`
+    /// leaves it to the `BufWriter`, except with [`FsyncPolicy::PerRecord`].
+    pub batch_size: Option<usize>,
+}
+
+impl WritePolicy {
+    fn batch_size(&self) -> Option<usize> {
+        match (self.batch_size, self.fsync) {
+            (None, FsyncPolicy::PerRecord) => Some(1),
+            (batch_size, _) => batch_size,
+        }
+    }
+}
+
+/// A destination for formatted records that is told where records end, so
+/// it can group them into batches.
+pub trait RecordOutput: Write {
+    /// Called after each record.
+    fn end_record(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once after the last record. Flushes by default.
+    fn close(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl RecordOutput for Vec<u8> {}
+
+impl<W: Write> RecordOutput for BufWriter<W> {}
+
+impl<T: RecordOutput + ?Sized> RecordOutput for &mut T {
+    fn end_record(&mut self) -> io::Result<()> {
+        (**self).end_record()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        (**self).close()
+    }
+}
+
+/// A buffered file written according to a [`WritePolicy`]: every complete
+/// batch of records is flushed, and synced with `--fsync per-record`.
+pub struct OutputFile {
+    out: BufWriter<File>,
+    policy: WritePolicy,
+    pending: usize,
+}
+
+impl OutputFile {
+    pub fn new(file: File, policy: WritePolicy) -> Self {
+        OutputFile {
+            out: BufWriter::new(file),
+            policy,
+            pending: 0,
+        }
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.out.flush()?;
+        self.out.get_ref().sync_data()
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl RecordOutput for OutputFile {
+    fn end_record(&mut self) -> io::Result<()> {
+        let Some(batch_size) = self.policy.batch_size() else {
+            return Ok(());
+        };
+        self.pending += 1;
+        if self.pending < batch_size {
+            return Ok(());
+        }
+        self.pending = 0;
+        match self.policy.fsync {
+            FsyncPolicy::PerRecord => self.sync(),
+            _ => self.out.flush(),
+        }
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        match self.policy.fsync {
+            FsyncPolicy::Never => self.out.flush(),
+            FsyncPolicy::PerRecord | FsyncPolicy::PerFile => self.sync(),
+        }
+    }
+}
+
+/// A buffered output file that is only created on the first write or flush,
+/// so a pipeline failing before producing output leaves no file behind.
+pub struct LazyFile {
+    path: PathBuf,
+    policy: WritePolicy,
+    file: Option<OutputFile>,
+}
+
+impl LazyFile {
+    pub fn new(path: &Path) -> Self {
+        LazyFile {
+            path: path.to_path_buf(),
+            policy: WritePolicy::default(),
+            file: None,
+        }
+    }
+
+    pub fn with_policy(mut self, policy: WritePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    fn file(&mut self) -> io::Result<&mut OutputFile> {
+        if self.file.is_none() {
+            let file = audit::create(&self.path).map_err(|err| {
+                io::Error::new(err.kind(), format!("Failed to create output file: {:?}: {}", self.path, err))
+            })?;
+            self.file = Some(OutputFile::new(file, self.policy));
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+}
+
+impl Write for LazyFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file()?.flush()
+    }
+}
+
+impl RecordOutput for LazyFile {
+    fn end_record(&mut self) -> io::Result<()> {
+        self.file()?.end_record()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.file()?.close()
+    }
+}
+
+/// Where the output of a run goes, parsed from the output argument.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputTarget {
+    /// A regular file, created or replaced.
+    File(PathBuf),
+    /// An existing named pipe.
+    Fifo(PathBuf),
+    /// `unix:///path/socket`, a listening Unix domain socket.
+    Unix(PathBuf),
+    /// `tcp://host:port`, a listening TCP socket.
+    Tcp(String),
+    /// `nats://` or `kafka://`, a message broker published to with the
+    /// `messaging` feature.
+    Broker(String),
+}
+
+impl OutputTarget {
+    pub fn parse(output: &Path) -> Result<Self> {
+        let name = output.to_string_lossy();
+        if let Some(path) = name.strip_prefix("unix://") {
+            if path.is_empty() {
+                bail!("Missing socket path in {}", name);
+            }
+            return Ok(OutputTarget::Unix(PathBuf::from(path)));
+        }
+        if let Some(address) = name.strip_prefix("tcp://") {
+            match address.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+                _ => bail!("Invalid TCP address: {} (expected tcp://host:port)", name),
+            }
+            return Ok(OutputTarget::Tcp(address.to_string()));
+        }
+        if name.starts_with("nats://") || name.starts_with("kafka://") {
+            return Ok(OutputTarget::Broker(name.into_owned()));
+        }
+        if is_fifo(output) {
+            return Ok(OutputTarget::Fifo(output.to_path_buf()));
+        }
+        Ok(OutputTarget::File(output.to_path_buf()))
+    }
+
+    /// Whether records are streamed to a consumer rather than written to a
+    /// file.
+    pub fn is_stream(&self) -> bool {
+        !matches!(self, OutputTarget::File(_))
+    }
+}
+
+#[cfg(unix)]
+fn is_fifo(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &Path) -> bool {
+    false
+}
+
+/// A named pipe or socket that every record is flushed to as soon as it is
+/// written, so a consumer sees records as they complete.
+pub struct StreamOutput {
+    out: BufWriter<Box<dyn Write>>,
+}
+
+impl StreamOutput {
+    /// Opens the pipe or connects to the socket of a streaming `target`.
+    /// Opening a named pipe waits for its reader.
+    pub fn connect(target: &OutputTarget) -> Result<Self> {
+        let out: Box<dyn Write> = match target {
+            OutputTarget::File(path) => bail!("{:?} is a file, not a stream", path),
+            OutputTarget::Broker(url) => bail!("{} is a message broker, not a stream", url),
+            OutputTarget::Fifo(path) => Box::new(
+                audit::open_with(OpenOptions::new().write(true), path)
+                    .with_context(|| format!("Failed to open named pipe {:?}", path))?,
+            ),
+            #[cfg(unix)]
+            OutputTarget::Unix(path) => {
+                audit::check(path, Access::Write)?;
+                Box::new(
+                    std::os::unix::net::UnixStream::connect(path)
+                        .with_context(|| format!("Failed to connect to socket {:?}", path))?,
+                )
+            }
+            #[cfg(not(unix))]
+            OutputTarget::Unix(_) => bail!("Unix domain sockets aren't supported on this platform"),
+            OutputTarget::Tcp(address) => Box::new(
+                std::net::TcpStream::connect(address.as_str())
+                    .with_context(|| format!("Failed to connect to {}", address))?,
+            ),
+        };
+        Ok(StreamOutput { out: BufWriter::new(out) })
+    }
+}
+
+impl Write for StreamOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl RecordOutput for StreamOutput {
+    fn end_record(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+type FormatterFactory = Box<dyn Fn() -> Result<Box<dyn OutputFormatter>>>;
+
+/// Buffers all records, then writes them into a file pre-sized to the exact
+/// output length through a shared memory map, avoiding a second copy of the
+/// output in a user space buffer.
+///
+/// The exact length is found by running the formatter once into a byte
+/// counter, which is why the sink needs a factory for fresh formatters.
+pub struct MmapSink {
+    path: PathBuf,
+    make_formatter: FormatterFactory,
+    records: Vec<ResultRecord>,
+    sync: bool,
+}
+
+impl MmapSink {
+    pub fn new<F>(path: &Path, make_formatter: F) -> Self
+    where
+        F: Fn() -> Result<Box<dyn OutputFormatter>> + 'static,
+    {
+        MmapSink {
+            path: path.to_path_buf(),
+            make_formatter: Box::new(make_formatter),
+            records: Vec::new(),
+            sync: false,
+        }
+    }
+
+    /// Syncs the file to the disk once it is written. The whole output is
+    /// written at once, so there are no batches to sync.
+    pub fn sync_on_finish(mut self) -> Self {
+        self.sync = true;
+        self
+    }
+}
+
+/// Counts the bytes written to it.
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn format_all(formatter: &mut dyn OutputFormatter, w: &mut dyn Write, records: &[ResultRecord]) -> Result<()> {
+    formatter.begin(w).context("Failed to write output header")?;
+    for (index, record) in records.iter().enumerate() {
+        formatter
+            .write_record(w, record)
+            .with_context(|| format!("Failed to write result {} to output file", index + 1))?;
+    }
+    formatter.finish(w).context("Failed to write output footer")
+}
+
+impl Sink for MmapSink {
+    fn write(&mut self, record: ResultRecord) -> Result<()> {
+        self.records.push(record);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn finish(&mut self) -> Result<()> {
+        let mut counter = ByteCounter(0);
+        format_all((self.make_formatter)()?.as_mut(), &mut counter, &self.records)?;
+        let len = usize::try_from(counter.0).context("Output too large to map")?;
+
+        let file = audit::open_with(OpenOptions::new().read(true).write(true).create(true).truncate(true), &self.path)
+            .with_context(|| format!("Failed to create output file: {:?}", self.path))?;
+        file.set_len(counter.0).context("Failed to resize output file")?;
+
+        let mut map = crate::mmap::MmapMut::map(&file, len).context("Failed to map output file")?;
+        let mut remaining = map.as_mut_slice();
+        format_all((self.make_formatter)()?.as_mut(), &mut remaining, &self.records)?;
+        if !remaining.is_empty() {
+            bail!("Formatter produced less output than predicted");
+        }
+        drop(map);
+        if self.sync {
+            file.sync_data().context("Failed to sync output file")?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn finish(&mut self) -> Result<()> {
+        bail!("The mmap I/O backend is not supported on this platform")
+    }
+}
+
+/// Returns the path of part `part` of a split output: the output itself for
+/// the first part, `name.partN.ext` for the following ones.
+pub fn part_path(output: &Path, part: usize) -> PathBuf {
+    if part <= 1 {
+        return output.to_path_buf();
+    }
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output.extension() {
+        Some(ext) => format!("{}.part{}.{}", stem, part, ext.to_string_lossy()),
+        None => format!("{}.part{}", stem, part),
+    };
+    output.with_file_name(name)
+}
+
+/// Writes records into a sequence of files, each at most `max_size` bytes,
+/// starting a new part (see [`part_path`]) whenever the next record wouldn't
+/// fit. Records are never split, so a single record larger than the limit
+/// gets a part of its own. Every part is a complete document of its format.
+pub struct SplitSink {
+    path: PathBuf,
+    max_size: u64,
+    make_formatter: FormatterFactory,
+    footer_size: u64,
+    part: usize,
+    current: Option<(OutputFile, Box<dyn OutputFormatter>, u64)>,
+    records_in_part: usize,
+    policy: WritePolicy,
+}
+
+impl SplitSink {
+    pub fn new<F>(path: &Path, max_size: u64, make_formatter: F) -> Result<Self>
+    where
+        F: Fn() -> Result<Box<dyn OutputFormatter>> + 'static,
+    {
+        let mut footer = Vec::new();
+        make_formatter()?.finish(&mut footer)?;
+        Ok(SplitSink {
+            path: path.to_path_buf(),
+            max_size,
+            make_formatter: Box::new(make_formatter),
+            footer_size: footer.len() as u64,
+            part: 0,
+            current: None,
+            records_in_part: 0,
+            policy: WritePolicy::default(),
+        })
+    }
+
+    pub fn with_policy(mut self, policy: WritePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    fn close_part(&mut self) -> Result<()> {
+        if let Some((mut out, mut formatter, _)) = self.current.take() {
+            formatter.finish(&mut out).context("Failed to write output footer")?;
+            out.close().context("Failed to flush output buffer")?;
+        }
+        Ok(())
+    }
+
+    fn open_part(&mut self) -> Result<()> {
+        self.close_part()?;
+        self.part += 1;
+        self.records_in_part = 0;
+        let path = part_path(&self.path, self.part);
+        let file = audit::create(&path).with_context(|| format!("Failed to create output file: {:?}", path))?;
+        let mut out = OutputFile::new(file, self.policy);
+        let mut formatter = (self.make_formatter)()?;
+        let mut header = Vec::new();
+        formatter.begin(&mut header).context("Failed to write output header")?;
+        out.write_all(&header)?;
+        self.current = Some((out, formatter, header.len() as u64));
+        Ok(())
+    }
+}
+
+impl Sink for SplitSink {
+    fn write(&mut self, record: ResultRecord) -> Result<()> {
+        if self.current.is_none() {
+            self.open_part()?;
+        }
+        let mut bytes = Vec::new();
+        self.current.as_mut().unwrap().1.write_record(&mut bytes, &record)?;
+        let size = self.current.as_ref().unwrap().2;
+        if self.records_in_part > 0 && size + bytes.len() as u64 + self.footer_size > self.max_size {
+            self.open_part()?;
+            // Re-format for the new part, whose formatter state is fresh.
+            bytes.clear();
+            self.current.as_mut().unwrap().1.write_record(&mut bytes, &record)?;
+        }
+        let (out, _, size) = self.current.as_mut().unwrap();
+        out.write_all(&bytes)
+            .and_then(|()| out.end_record())
+            .with_context(|| format!("Failed to write result to output part {}", self.part))?;
+        *size += bytes.len() as u64;
+        self.records_in_part += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.current.is_none() {
+            self.open_part()?;
+        }
+        self.close_part()
+    }
+}
+
+/// Returns the path of the sidecar index kept next to an appended output.
+pub fn append_index_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".idx");
+    PathBuf::from(path)
+}
+
+/// Identifies a record by its parameters and the output affecting `options`.
+pub fn record_signature(record: &ResultRecord, options: &str) -> String {
+    format!("{} {} {} {}", record.job.a, record.job.b, record.job.end, options)
+}
+
+/// Appends records to an existing output, skipping records whose signature
+/// is already listed in the sidecar index (see [`append_index_path`]), so
+/// repeated runs never add the same record twice.
+pub struct AppendSink {
+    path: PathBuf,
+    options: String,
+    formatter: Box<dyn OutputFormatter>,
+    seen: HashSet<String>,
+    files: Option<(OutputFile, OutputFile)>,
+    policy: WritePolicy,
+}
+
+impl AppendSink {
+    pub fn new(path: &Path, formatter: Box<dyn OutputFormatter>, options: &str) -> Result<Self> {
+        let index_path = append_index_path(path);
+        let mut seen = HashSet::new();
+        if index_path.exists() {
+            let index = File::open(&index_path).with_context(|| format!("Failed to open index: {:?}", index_path))?;
+            for line in BufReader::new(index).lines() {
+                seen.insert(line.with_context(|| format!("Failed to read index: {:?}", index_path))?);
+            }
+        }
+        Ok(AppendSink {
+            path: path.to_path_buf(),
+            options: options.to_string(),
+            formatter,
+            seen,
+            files: None,
+            policy: WritePolicy::default(),
+        })
+    }
+
+    /// Sets how the output and its index are written. A record's index line
+    /// only reaches the disk after the record itself.
+    pub fn with_policy(mut self, policy: WritePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    fn files(&mut self) -> Result<&mut (OutputFile, OutputFile)> {
+        if self.files.is_none() {
+            let open = |path: &Path| {
+                audit::open_with(OpenOptions::new().create(true).append(true), path)
+                    .with_context(|| format!("Failed to open output file: {:?}", path))
+            };
+            let mut out = open(&self.path)?;
+            let index = open(&append_index_path(&self.path))?;
+            if out.metadata()?.len() > 0 {
+                self.formatter.resume()?;
+            } else {
+                self.formatter.begin(&mut out).context("Failed to write output header")?;
+            }
+            self.files = Some((OutputFile::new(out, self.policy), OutputFile::new(index, self.policy)));
+        }
+        Ok(self.files.as_mut().unwrap())
+    }
+}
+
+impl Sink for AppendSink {
+    fn write(&mut self, record: ResultRecord) -> Result<()> {
+        let signature = record_signature(&record, &self.options);
+        if self.seen.contains(&signature) {
+            return Ok(());
+        }
+        self.files()?;
+        let (out, index) = self.files.as_mut().unwrap();
+        self.formatter
+            .write_record(out, &record)
+            .context("Failed to append result to output file")?;
+        out.end_record().context("Failed to append result to output file")?;
+        writeln!(index, "{}", signature)
+            .and_then(|()| index.end_record())
+            .context("Failed to update output index")?;
+        self.seen.insert(signature);
+        Ok(())
+    }
+
+    fn write_streamed(
+        &mut self,
+        record: ResultRecord,
+        count: Option<u64>,
+        chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    ) -> Result<()> {
+        let signature = record_signature(&record, &self.options);
+        if self.seen.contains(&signature) {
+            return Ok(());
+        }
+        self.files()?;
+        let (out, index) = self.files.as_mut().unwrap();
+        self.formatter
+            .write_streamed(out, &record, count, chunks)
+            .context("Failed to append result to output file")?;
+        out.end_record().context("Failed to append result to output file")?;
+        writeln!(index, "{}", signature)
+            .and_then(|()| index.end_record())
+            .context("Failed to update output index")?;
+        self.seen.insert(signature);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.files()?;
+        let (out, index) = self.files.as_mut().unwrap();
+        self.formatter.finish(out).context("Failed to write output footer")?;
+        // The output is flushed before the index so that the index never
+        // lists a record that isn't in the output.
+        out.close().context("Failed to flush output buffer")?;
+        index.close().context("Failed to flush output index")
+    }
+}
+
+/// The outputs named by input lines, keyed by the index of their record.
+pub type Routes = Arc<Mutex<HashMap<usize, PathBuf>>>;
+
+type SinkFactory = Box<dyn FnMut(&Path) -> Result<Box<dyn Sink>>>;
+
+/// Writes the records of lines that name their own output (see
+/// [`Routes`]) to a sink for that output, opened by `make_sink` on first
+/// use, and all other records to the run's own output. Lines naming the
+/// run's output go there as well.
+pub struct RoutingSink {
+    output: PathBuf,
+    default: Box<dyn Sink>,
+    routes: Routes,
+    make_sink: SinkFactory,
+    sinks: BTreeMap<PathBuf, Box<dyn Sink>>,
+}
+
+impl RoutingSink {
+    pub fn new<F>(output: &Path, default: Box<dyn Sink>, routes: Routes, make_sink: F) -> Self
+    where
+        F: FnMut(&Path) -> Result<Box<dyn Sink>> + 'static,
+    {
+        RoutingSink {
+            output: output.to_path_buf(),
+            default,
+            routes,
+            make_sink: Box::new(make_sink),
+            sinks: BTreeMap::new(),
+        }
+    }
+}
+
+impl RoutingSink {
+    /// The sink the record with `index` goes to.
+    fn sink(&mut self, index: usize) -> Result<&mut dyn Sink> {
+        let route = self.routes.lock().unwrap().remove(&index);
+        let Some(path) = route.filter(|path| *path != self.output) else {
+            return Ok(self.default.as_mut());
+        };
+        if !self.sinks.contains_key(&path) {
+            let sink = (self.make_sink)(&path)
+                .with_context(|| format!("Failed to open the output of line {}: {:?}", index + 1, path))?;
+            self.sinks.insert(path.clone(), sink);
+        }
+        Ok(self.sinks.get_mut(&path).unwrap().as_mut())
+    }
+}
+
+impl Sink for RoutingSink {
+    fn write(&mut self, record: ResultRecord) -> Result<()> {
+        self.sink(record.index)?.write(record)
+    }
+
+    fn write_streamed(
+        &mut self,
+        record: ResultRecord,
+        count: Option<u64>,
+        chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    ) -> Result<()> {
+        self.sink(record.index)?.write_streamed(record, count, chunks)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (path, sink) in &mut self.sinks {
+            sink.finish().with_context(|| format!("Failed to finish {:?}", path))?;
+        }
+        self.default.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::path::{Path, PathBuf};
+
+    use super::{
+        append_index_path, part_path, AppendSink, FsyncPolicy, IoBackend, LazyFile, MmapSink, OutputTarget, Routes,
+        RoutingSink, SplitSink, StreamOutput, WritePolicy,
+    };
+    use crate::formatter::FormatterRegistry;
+    use crate::pipeline::{jobs_source, FormatterSink, Pipeline, Sink};
+    use crate::{generate_record, LineNumbers};
+
+    #[test]
+    fn test_io_backend_names() {
+        assert_eq!("mmap".parse::<IoBackend>().unwrap(), IoBackend::Mmap);
+        assert!("direct".parse::<IoBackend>().is_err());
+    }
+
+    #[test]
+    fn test_write_policy_batches() {
+        assert_eq!("per-file".parse::<FsyncPolicy>().unwrap(), FsyncPolicy::PerFile);
+        assert!("always".parse::<FsyncPolicy>().is_err());
+
+        let path = std::env::temp_dir().join(format!("multiples_batches_{}.txt", std::process::id()));
+        let policy = WritePolicy {
+            fsync: FsyncPolicy::PerRecord,
+            batch_size: Some(2),
+        };
+        let formatter = FormatterRegistry::with_builtins().create("text").unwrap();
+        let mut sink = FormatterSink::new(LazyFile::new(&path).with_policy(policy), formatter);
+        let mut written = Vec::new();
+        for end in 1..=3 {
+            sink.write(generate_record(LineNumbers { a: 1, b: 1, end })).unwrap();
+            written.push(read_to_string(&path).unwrap());
+        }
+        sink.finish().unwrap();
+        let finished = read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, ["", "1:1\n2:1 2\n", "1:1\n2:1 2\n"]);
+        assert_eq!(finished, "1:1\n2:1 2\n3:1 2 3\n");
+    }
+
+    #[test]
+    fn test_output_targets() {
+        let parse = |output: &str| OutputTarget::parse(Path::new(output));
+        assert_eq!(parse("out.txt").unwrap(), OutputTarget::File(PathBuf::from("out.txt")));
+        assert_eq!(parse("unix:///run/multiples.sock").unwrap(), OutputTarget::Unix(PathBuf::from("/run/multiples.sock")));
+        assert_eq!(parse("tcp://localhost:7000").unwrap(), OutputTarget::Tcp(String::from("localhost:7000")));
+        assert!(parse("tcp://localhost").is_err());
+        assert!(parse("tcp://:7000").is_err());
+        assert!(parse("unix://").is_err());
+        assert_eq!(parse("nats://localhost/multiples").unwrap(), OutputTarget::Broker(String::from("nats://localhost/multiples")));
+        assert!(!parse("out.txt").unwrap().is_stream());
+    }
+
+    #[test]
+    fn test_stream_output_sends_records_as_written() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = OutputTarget::Tcp(listener.local_addr().unwrap().to_string());
+        let formatter = FormatterRegistry::with_builtins().create("text").unwrap();
+        let mut sink = FormatterSink::new(StreamOutput::connect(&target).unwrap(), formatter);
+        let (mut consumer, _) = listener.accept().unwrap();
+        sink.write(generate_record(LineNumbers { a: 2, b: 3, end: 4 })).unwrap();
+        let mut first = [0; 8];
+        consumer.read_exact(&mut first).unwrap();
+        assert_eq!(&first, b"4:2 3 4\n");
+        sink.write(generate_record(LineNumbers { a: 5, b: 7, end: 7 })).unwrap();
+        sink.finish().unwrap();
+        drop(sink);
+        let mut rest = String::new();
+        consumer.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "7:5 7\n");
+    }
+
+    #[test]
+    fn test_part_path() {
+        let output = std::path::Path::new("out/results.txt");
+        assert_eq!(part_path(output, 1), output);
+        assert_eq!(part_path(output, 3), std::path::Path::new("out/results.part3.txt"));
+        assert_eq!(part_path(std::path::Path::new("results"), 2), std::path::Path::new("results.part2"));
+    }
+
+    #[test]
+    fn test_split_sink_rolls_over_between_records() {
+        let path = std::env::temp_dir().join(format!("multiples_split_{}.json", std::process::id()));
+        let mut sink = SplitSink::new(&path, 80, || FormatterRegistry::with_builtins().create("json")).unwrap();
+        let jobs = vec![
+            LineNumbers { a: 2, b: 3, end: 4 },
+            LineNumbers { a: 2, b: 3, end: 6 },
+            LineNumbers { a: 1, b: 1, end: 30 },
+        ];
+        Pipeline::builder(jobs_source(jobs)).build().run(&mut sink).unwrap();
+        let parts: Vec<String> = (1..=3).map(|part| read_to_string(part_path(&path, part)).unwrap()).collect();
+        for part in 1..=3 {
+            std::fs::remove_file(part_path(&path, part)).unwrap();
+        }
+        assert!(!part_path(&path, 4).exists());
+        assert!(parts[0].len() <= 80 && parts[1].len() <= 80);
+        assert!(parts[0].contains("\"end\":4") && parts[1].contains("\"end\":6"));
+        // The last record alone exceeds the limit and gets a part of its own.
+        assert!(parts[2].starts_with('[') && parts[2].ends_with("]\n"));
+    }
+
+    #[test]
+    fn test_append_sink_skips_known_records() {
+        let path = std::env::temp_dir().join(format!("multiples_append_{}.txt", std::process::id()));
+        let run = |jobs: Vec<LineNumbers>| {
+            let formatter = FormatterRegistry::with_builtins().create("text").unwrap();
+            let mut sink = AppendSink::new(&path, formatter, "text").unwrap();
+            Pipeline::builder(jobs_source(jobs)).build().run(&mut sink).unwrap();
+        };
+        run(vec![LineNumbers { a: 2, b: 3, end: 4 }]);
+        run(vec![LineNumbers { a: 2, b: 3, end: 4 }, LineNumbers { a: 5, b: 7, end: 7 }]);
+        let written = read_to_string(&path).unwrap();
+        let index = read_to_string(append_index_path(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(append_index_path(&path)).unwrap();
+        assert_eq!(written, "4:2 3 4\n7:5 7\n");
+        assert_eq!(index, "2 3 4 text\n5 7 7 text\n");
+    }
+
+    #[test]
+    fn test_routing_sink() {
+        let dir = std::env::temp_dir().join(format!("multiples_routes_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let text_sink = |path: &Path| -> anyhow::Result<Box<dyn Sink>> {
+            let formatter = FormatterRegistry::with_builtins().create("text")?;
+            Ok(Box::new(FormatterSink::new(LazyFile::new(path), formatter)))
+        };
+        let output = dir.join("all.txt");
+        let routes = Routes::default();
+        routes.lock().unwrap().extend([(1, dir.join("small.txt")), (2, output.clone()), (3, dir.join("small.txt"))]);
+        let mut sink = RoutingSink::new(&output, text_sink(&output).unwrap(), routes.clone(), text_sink);
+        let jobs = (1..=4).map(|end| LineNumbers { a: 1, b: 1, end }).collect();
+        Pipeline::builder(jobs_source(jobs)).build().run(&mut sink).unwrap();
+        let all = read_to_string(&output).unwrap();
+        let small = read_to_string(dir.join("small.txt")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(all, "1:1\n3:1 2 3\n");
+        assert_eq!(small, "2:1 2\n4:1 2 3 4\n");
+        assert!(routes.lock().unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mmap_sink_matches_formatter_output() {
+        let path = std::env::temp_dir().join(format!("multiples_mmap_{}.csv", std::process::id()));
+        let mut sink = MmapSink::new(&path, || FormatterRegistry::with_builtins().create("csv"));
+        let jobs = vec![LineNumbers { a: 2, b: 3, end: 10 }, LineNumbers { a: 4, b: 7, end: 20 }];
+        Pipeline::builder(jobs_source(jobs)).build().run(&mut sink).unwrap();
+        let written = read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            written,
+            "index,a,b,end,count,numbers\n0,2,3,10,7,2 3 4 6 8 9 10\n1,4,7,20,7,4 7 8 12 14 16 20\n"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! Paths as Windows opens them. Its file APIs refuse paths of 260
+//! characters or more, such as inputs deep in a network share, unless they
+//! are given in the extended-length form `\\?\C:\...` or
+//! `\\?\UNC\server\share\...`, which in turn must be absolute, use
+//! backslashes only and hold no `.` or `..`. [`native`] rewrites long paths
+//! into that form before they are opened; elsewhere paths are opened as
+//! given.
+
+use std::borrow::Cow;
+use std::path::Path;
+
+/// The length from which paths are opened in the extended-length form:
+/// `MAX_PATH` less the 12 characters Windows keeps free for the name of a
+/// file in a directory.
+#[cfg_attr(not(windows), allow(dead_code))]
+const LONG_PATH: usize = 248;
+
+/// `path` as it can be opened on this platform.
+pub fn native(path: &Path) -> Cow<'_, Path> {
+    #[cfg(windows)]
+    {
+        let absolute = match std::path::absolute(path) {
+            Ok(absolute) => absolute,
+            Err(_) => return Cow::Borrowed(path),
+        };
+        if let Some(extended) = absolute.to_str().filter(|text| text.len() >= LONG_PATH).and_then(extended) {
+            return Cow::Owned(std::path::PathBuf::from(extended));
+        }
+    }
+    Cow::Borrowed(path)
+}
+
+/// The extended-length form of the absolute Windows path `path`, a drive
+/// path or a UNC path of a share, with either kind of slash. `None` for
+/// relative paths and paths already in a `\\?\` or device form.
+pub fn extended(path: &str) -> Option<String> {
+    let path = path.replace('/', "\\");
+    if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        return None;
+    }
+    let (prefix, rest) = match path.strip_prefix(r"\\") {
+        Some(share) => (String::from(r"\\?\UNC\"), share),
+        None => {
+            let bytes = path.as_bytes();
+            if bytes.len() < 3 || !bytes[0].is_ascii_alphabetic() || &bytes[1..3] != b":\\" {
+                return None;
+            }
+            (format!(r"\\?\{}", &path[..3]), &path[3..])
+        }
+    };
+    // The server and share of a UNC path can't be left by `..`.
+    let root = if prefix.ends_with(r"UNC\") { 2 } else { 0 };
+    let mut components: Vec<&str> = Vec::new();
+    for component in rest.split('\\') {
+        match component {
+            "" | "." if components.len() >= root => {}
+            ".." if components.len() > root => {
+                components.pop();
+            }
+            ".." if components.len() == root => {}
+            _ => components.push(component),
+        }
+    }
+    if components.len() < root {
+        return None;
+    }
+    Some(prefix + &components.join("\\"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{extended, native, LONG_PATH};
+
+    #[test]
+    fn test_extended() {
+        assert_eq!(extended(r"C:\data\jobs.txt").as_deref(), Some(r"\\?\C:\data\jobs.txt"));
+        assert_eq!(extended("d:/data/./runs/../jobs.txt").as_deref(), Some(r"\\?\d:\data\jobs.txt"));
+        assert_eq!(extended(r"C:\..\jobs.txt").as_deref(), Some(r"\\?\C:\jobs.txt"));
+        assert_eq!(extended(r"\\server\share\runs\jobs.txt").as_deref(), Some(r"\\?\UNC\server\share\runs\jobs.txt"));
+        assert_eq!(extended(r"\\server\share\..\..\jobs.txt").as_deref(), Some(r"\\?\UNC\server\share\jobs.txt"));
+        assert_eq!(extended("//server/share/jobs.txt").as_deref(), Some(r"\\?\UNC\server\share\jobs.txt"));
+        assert_eq!(extended(r"\\server"), None);
+        assert_eq!(extended(r"\\?\C:\jobs.txt"), None);
+        assert_eq!(extended(r"\\.\pipe\multiples"), None);
+        assert_eq!(extended(r"data\jobs.txt"), None);
+        assert_eq!(extended(r"C:jobs.txt"), None);
+    }
+
+    #[test]
+    fn test_native() {
+        // Short paths are opened as given everywhere.
+        assert_eq!(native(Path::new("jobs.txt")), Path::new("jobs.txt"));
+        let long = format!("{}/jobs.txt", "d".repeat(LONG_PATH));
+        #[cfg(not(windows))]
+        assert_eq!(native(Path::new(&long)), Path::new(&long));
+        #[cfg(windows)]
+        assert!(native(Path::new(&long)).to_str().unwrap().starts_with(r"\\?\"));
+    }
+}
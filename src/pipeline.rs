@@ -0,0 +1,1419 @@
+//! The processing pipeline: a source of jobs, a generator turning each job
+//! into a record, any number of record stages, and a sink.
+//!
+//! Every stage runs on its own thread and is connected to its neighbours by
+//! a bounded channel, so stages overlap and slow sinks apply backpressure.
+//! Generation can be spread over several worker threads, all feeding the
+//! same channel; their records are put back into input order before the
+//! first stage unless the pipeline is built [`unordered`](PipelineBuilder::unordered).
+//! A [scheduled](PipelineBuilder::schedule) pipeline hands its workers the
+//! most urgent of the lines read so far instead of the next one.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap};
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use crate::audit;
+use crate::cancel::{CancellationToken, Status};
+use crate::chunk::Backpressure;
+use crate::formatter::OutputFormatter;
+use crate::formula::{self, Mode};
+use crate::input::{Bound, InputParserRegistry, JobIter, Priority, RoutedJobIter};
+use crate::jobfilter::TagFilter;
+use crate::output::{RecordOutput, Routes};
+use crate::profile::{Phase, Profiler};
+use crate::progress::Progress;
+use crate::report::{LineError, RunReport, Timings, Warnings};
+use crate::{count_record, generate_record, LineNumbers, ResultRecord};
+
+/// Default number of jobs or records buffered between two neighbouring stages.
+pub const DEFAULT_QUEUE_SIZE: usize = 64;
+
+/// Lines a scheduled pipeline reads ahead of its workers to pick from.
+pub const SCHEDULE_WINDOW: usize = 65_536;
+
+/// Lines with at least this many multiples in `or` mode count as heavy for
+/// [`PipelineBuilder::schedule`].
+pub const HEAVY_MULTIPLES: u64 = 10_000_000;
+
+/// The priorities of the lines that give one, keyed by the index their
+/// record will have.
+pub type Priorities = Arc<Mutex<HashMap<usize, Priority>>>;
+
+/// An estimate of the work of generating a job, in any unit, see
+/// [`PipelineBuilder::longest_first`].
+pub type CostFn = Arc<dyn Fn(&LineNumbers) -> u64 + Send + Sync>;
+
+/// What passes between the stages: a record, or the index of a line gone
+/// past with [`PipelineBuilder::keep_going`], so that the [`ReorderStage`]
+/// doesn't wait for it.
+enum Item {
+    Record(ResultRecord),
+    Skipped(usize),
+}
+
+type Message = Result<Item>;
+pub type Generator<'a> = Box<dyn Fn(LineNumbers) -> Result<ResultRecord> + Send + Sync + 'a>;
+type StopCheck<'a> = Box<dyn Fn() -> bool + Send + Sync + 'a>;
+
+/// A record-level processing step.
+pub trait Stage: Send {
+    /// Handles one record, passing any number of records on through `emit`.
+    fn process(&mut self, record: ResultRecord, emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()>;
+
+    /// Called in place of `process` for a line that failed and was gone
+    /// past with [`PipelineBuilder::keep_going`].
+    fn skip(&mut self, _index: usize, _emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once after the last record, e.g. to flush buffered records.
+    fn finish(&mut self, _emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The final consumer of records. It runs on the thread calling
+/// [`Pipeline::run`] and therefore doesn't need to be `Send`.
+pub trait Sink {
+    fn write(&mut self, record: ResultRecord) -> Result<()>;
+
+    /// Writes `record` with the multiples of `chunks` in place of its own,
+    /// see [`OutputFormatter::write_streamed`]. Sinks that need whole
+    /// records fail here.
+    fn write_streamed(
+        &mut self,
+        _record: ResultRecord,
+        _count: Option<u64>,
+        _chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    ) -> Result<()> {
+        anyhow::bail!("This output can't write a record in pieces")
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns a job source reading `path` with the given input format, or the
+/// format implied by the file extension.
+pub fn file_source(path: &Path, format: Option<&str>) -> Result<JobIter<'static>> {
+    let registry = InputParserRegistry::with_builtins();
+    let parser = registry.select(path, format)?;
+    let file = audit::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
+    Ok(parser.parse(Box::new(BufReader::new(file))))
+}
+
+/// Like [`file_source`] with the parsers of `registry`, noting in `routes`
+/// the output named by each line that names one and in `priorities` the
+/// priority of each line that isn't `normal`, keyed by the index its record
+/// will have. Lines that don't give a bound of their own have `bound`, and
+/// lines whose tags `tags` doesn't match are left out before they are
+/// indexed.
+pub fn routed_file_source(
+    registry: &InputParserRegistry,
+    path: &Path,
+    format: Option<&str>,
+    routes: Routes,
+    priorities: Priorities,
+    bound: Bound,
+    tags: &TagFilter,
+) -> Result<JobIter<'static>> {
+    let parser = registry.select(path, format)?;
+    let file = audit::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
+    let jobs = parser.parse_routed(Box::new(BufReader::new(file)));
+    Ok(noting_options(jobs, routes, priorities, bound, tags))
+}
+
+/// Like [`routed_file_source`], reading `path` through a memory map. Text
+/// input is parsed on `threads` threads, see
+/// [`ParallelReader`](crate::input::ParallelReader); other
+/// formats are parsed from the mapping on one.
+#[cfg(unix)]
+#[allow(clippy::too_many_arguments)]
+pub fn mapped_file_source(
+    registry: &InputParserRegistry,
+    path: &Path,
+    format: Option<&str>,
+    routes: Routes,
+    priorities: Priorities,
+    bound: Bound,
+    tags: &TagFilter,
+    threads: usize,
+) -> Result<JobIter<'static>> {
+    let parser = registry.select(path, format)?;
+    let file = audit::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
+    let map = crate::mmap::Mmap::map(&file).with_context(|| format!("Failed to map file: {:?}", path))?;
+    let jobs: RoutedJobIter<'static> = match parser.splits_at_lines() {
+        true => Box::new(crate::input::ParallelReader::new(map, threads)),
+        false => parser.parse_routed(Box::new(std::io::Cursor::new(map))),
+    };
+    Ok(noting_options(jobs, routes, priorities, bound, tags))
+}
+
+/// Notes the options of `jobs` in `routes` and `priorities` and applies
+/// their bounds, see [`routed_file_source`].
+fn noting_options(
+    jobs: RoutedJobIter<'static>,
+    routes: Routes,
+    priorities: Priorities,
+    bound: Bound,
+    tags: &TagFilter,
+) -> JobIter<'static> {
+    // Lines that fail to parse are kept, for the run to report.
+    let tags = tags.clone();
+    let jobs = jobs.filter(move |job| job.as_ref().map_or(true, |(_, options)| tags.matches(&options.tags)));
+    Box::new(jobs.enumerate().map(move |(index, job)| {
+        let (job, options) = job?;
+        if let Some(output) = options.output {
+            routes.lock().unwrap().insert(index, output);
+        }
+        if options.priority != Priority::Normal {
+            priorities.lock().unwrap().insert(index, options.priority);
+        }
+        Ok(options.bound.unwrap_or(bound).resolve(job))
+    }))
+}
+
+/// Returns a job source yielding `jobs`.
+pub fn jobs_source(jobs: Vec<LineNumbers>) -> JobIter<'static> {
+    Box::new(jobs.into_iter().map(Ok))
+}
+
+pub struct PipelineBuilder<'a> {
+    source: JobIter<'a>,
+    generator: Generator<'a>,
+    stages: Vec<Box<dyn Stage + 'a>>,
+    workers: usize,
+    ordered: bool,
+    queue_size: usize,
+    profiler: Option<Arc<Profiler>>,
+    progress: Option<Arc<Progress>>,
+    stop: Option<StopCheck<'a>>,
+    schedule: Option<Schedule>,
+    backpressure: Option<Arc<Backpressure>>,
+    keep_going: bool,
+    warnings: Warnings,
+}
+
+/// How a scheduled pipeline picks the next job, see
+/// [`PipelineBuilder::schedule`].
+struct Schedule {
+    priorities: Priorities,
+    max_heavy: Option<usize>,
+    cost: Option<CostFn>,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    /// Replaces the default generator, [`generate_record`].
+    pub fn generator<F>(mut self, generator: F) -> Self
+    where
+        F: Fn(LineNumbers) -> Result<ResultRecord> + Send + Sync + 'a,
+    {
+        self.generator = Box::new(generator);
+        self
+    }
+
+    /// Replaces the generator with [`count_record`], which only counts the
+    /// values `mode` selects instead of listing them.
+    pub fn count_only(self, mode: Mode) -> Self {
+        self.generator(move |job| Ok(count_record(job, mode)))
+    }
+
+    /// Sets the number of threads running the generator (at least one).
+    /// Records still reach the stages in input order, see
+    /// [`PipelineBuilder::unordered`].
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Passes records on in the order workers finish them instead of
+    /// holding back those generated ahead of a slower line. This only makes
+    /// a difference with more than one worker.
+    pub fn unordered(mut self) -> Self {
+        self.ordered = false;
+        self
+    }
+
+    /// Sets the capacity of the channels between stages (at least one).
+    pub fn queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size.max(1);
+        self
+    }
+
+    /// Records how long parsing and generating each job takes in `profiler`.
+    pub fn profile(mut self, profiler: Arc<Profiler>) -> Self {
+        self.profiler = Some(profiler);
+        self
+    }
+
+    /// Notes in `progress` which line is being generated. Advancing it is
+    /// left to the generator, see [`Progress`].
+    pub fn progress(mut self, progress: Arc<Progress>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Leaves lines whose job fails to parse or generate out of the output
+    /// and goes on with the next, listing them in the
+    /// [`RunReport::errors`] of the run instead of failing it. Failures to
+    /// read the input, of stages and of the sink still fail the run.
+    pub fn keep_going(mut self) -> Self {
+        self.keep_going = true;
+        self
+    }
+
+    /// Reports the warnings noted in `warnings`, such as those of
+    /// [`degenerate::checked`](crate::degenerate::checked), in the
+    /// [`RunReport::warnings`] of the run.
+    pub fn warnings(mut self, warnings: Warnings) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    /// Stops reading and generating new jobs once `stop` returns true.
+    /// Jobs already being generated are finished and passed on, and the
+    /// stages and sink finish as if the source had ended.
+    pub fn stop_when<F>(mut self, stop: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'a,
+    {
+        self.stop = Some(Box::new(stop));
+        self
+    }
+
+    /// Hands the workers the line with the highest priority in
+    /// `priorities` among the [`SCHEDULE_WINDOW`] lines read ahead, the
+    /// earliest first among equals, instead of the next line of the input.
+    /// With `max_heavy`, at most that many [heavy](HEAVY_MULTIPLES) lines
+    /// are generated at once, so that small lines get workers too.
+    /// Records still reach the stages in input order unless the pipeline is
+    /// [`unordered`](PipelineBuilder::unordered).
+    pub fn schedule(mut self, priorities: Priorities, max_heavy: Option<usize>) -> Self {
+        let cost = self.schedule.and_then(|schedule| schedule.cost);
+        self.schedule = Some(Schedule {
+            priorities,
+            max_heavy,
+            cost,
+        });
+        self
+    }
+
+    /// Reads the whole source before generating anything, and then hands
+    /// the workers the jobs of each priority in order of their `cost`, the
+    /// largest first, so that the longest jobs don't start last and keep one
+    /// worker busy after the others are done. Scheduled without priorities
+    /// unless [`schedule`](PipelineBuilder::schedule) is given too.
+    pub fn longest_first(mut self, cost: CostFn) -> Self {
+        let schedule = self.schedule.get_or_insert_with(|| Schedule {
+            priorities: Priorities::default(),
+            max_heavy: None,
+            cost: None,
+        });
+        schedule.cost = Some(cost);
+        self
+    }
+
+    /// Notes in `backpressure` whether the workers last found the queue to
+    /// the stages full, for generators that adapt to a slow writer.
+    pub fn backpressure(mut self, backpressure: Arc<Backpressure>) -> Self {
+        self.backpressure = Some(backpressure);
+        self
+    }
+
+    pub fn stage<S: Stage + 'a>(mut self, stage: S) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Keeps only the records matching `predicate`.
+    pub fn filter<F>(self, predicate: F) -> Self
+    where
+        F: FnMut(&ResultRecord) -> bool + Send + 'a,
+    {
+        self.stage(FilterStage(predicate))
+    }
+
+    /// Calls `f` for every record passing through.
+    pub fn inspect<F>(self, f: F) -> Self
+    where
+        F: FnMut(&ResultRecord) + Send + 'a,
+    {
+        self.stage(InspectStage(f))
+    }
+
+    /// Collects all records and passes them on sorted by `key`.
+    /// The sort is stable, so records with equal keys keep their order.
+    pub fn sort_by_key<K, F>(self, key: F) -> Self
+    where
+        K: Ord,
+        F: FnMut(&ResultRecord) -> K + Send + 'a,
+    {
+        self.stage(SortStage {
+            key,
+            records: Vec::new(),
+        })
+    }
+
+    /// Passes on only the `n` records with the largest `key`, largest first.
+    /// Records with equal keys keep their order.
+    pub fn top_by_key<K, F>(self, n: usize, key: F) -> Self
+    where
+        K: Ord,
+        F: FnMut(&ResultRecord) -> K + Send + 'a,
+    {
+        self.stage(TopStage {
+            n,
+            key,
+            records: Vec::new(),
+        })
+    }
+
+    pub fn build(mut self) -> Pipeline<'a> {
+        if self.ordered && self.workers > 1 {
+            self.stages.insert(0, Box::new(ReorderStage::default()));
+        }
+        Pipeline {
+            source: self.source,
+            generator: self.generator,
+            stages: self.stages,
+            workers: self.workers,
+            queue_size: self.queue_size,
+            profiler: self.profiler,
+            progress: self.progress,
+            stop: self.stop,
+            schedule: self.schedule,
+            backpressure: self.backpressure,
+            keep_going: self.keep_going,
+            warnings: self.warnings,
+        }
+    }
+}
+
+pub struct Pipeline<'a> {
+    source: JobIter<'a>,
+    generator: Generator<'a>,
+    stages: Vec<Box<dyn Stage + 'a>>,
+    workers: usize,
+    queue_size: usize,
+    profiler: Option<Arc<Profiler>>,
+    progress: Option<Arc<Progress>>,
+    stop: Option<StopCheck<'a>>,
+    schedule: Option<Schedule>,
+    backpressure: Option<Arc<Backpressure>>,
+    keep_going: bool,
+    warnings: Warnings,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn builder(source: JobIter<'a>) -> PipelineBuilder<'a> {
+        PipelineBuilder {
+            source,
+            generator: Box::new(|job| Ok(generate_record(job))),
+            stages: Vec::new(),
+            workers: 1,
+            ordered: true,
+            queue_size: DEFAULT_QUEUE_SIZE,
+            profiler: None,
+            progress: None,
+            stop: None,
+            schedule: None,
+            backpressure: None,
+            keep_going: false,
+            warnings: Warnings::default(),
+        }
+    }
+
+    /// Runs the pipeline as [`Pipeline::run`] until `token` is cancelled,
+    /// then stops as with [`PipelineBuilder::stop_when`]. The records
+    /// passed to `sink` until then are kept, and the status of the report
+    /// tells whether the run was cut short.
+    pub fn run_cancellable(mut self, sink: &mut dyn Sink, token: &CancellationToken) -> Result<RunReport> {
+        let cancelled = token.clone();
+        let stop = self.stop.take();
+        self.stop = Some(Box::new(move || cancelled.is_cancelled() || stop.as_ref().is_some_and(|stop| stop())));
+        let report = self.run(sink)?;
+        Ok(RunReport {
+            status: token.status(),
+            ..report
+        })
+    }
+
+    /// Runs the pipeline until the source is exhausted or an error occurs.
+    /// Each record's `index` is set to the position of its job in the source.
+    /// The first error stops all stages and is returned, unless the pipeline
+    /// is built to [keep going](PipelineBuilder::keep_going) past failed
+    /// lines, which the report then lists.
+    pub fn run(self, sink: &mut dyn Sink) -> Result<RunReport> {
+        let started = Instant::now();
+        let Pipeline {
+            source,
+            generator,
+            stages,
+            workers,
+            queue_size,
+            profiler,
+            progress,
+            stop,
+            schedule,
+            backpressure,
+            keep_going,
+            warnings,
+        } = self;
+        let (job_tx, job_rx) = mpsc::sync_channel::<(usize, Result<LineNumbers>)>(queue_size);
+        // Shared by the workers only, so that it is dropped once the last of
+        // them gives up and the source isn't left blocked on a full channel.
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let scheduler = schedule.map(|schedule| Scheduler::new(schedule, SCHEDULE_WINDOW));
+        let scheduler = scheduler.as_ref();
+        let generator = &generator;
+        let profiler = profiler.as_deref();
+        let progress = progress.as_deref();
+        let backpressure = backpressure.as_deref();
+        let stopped = || stop.as_ref().is_some_and(|stop| stop());
+        let errors = Mutex::new(Vec::new());
+        let errors = &errors;
+        // A line's failure is kept for the report when going on past it.
+        let skipped = |index, error: anyhow::Error| match keep_going {
+            true => {
+                errors.lock().unwrap().push(LineError { index, error });
+                None
+            }
+            false => Some(error),
+        };
+        let generating = AtomicU64::new(0);
+        let generating = &generating;
+        let (mut records, mut writing) = (0, Duration::ZERO);
+
+        thread::scope(|scope| {
+            let (tx, mut rx) = mpsc::sync_channel::<Message>(queue_size);
+            let source_tx = tx.clone();
+            scope.spawn(move || {
+                let mut source = source;
+                for index in 0.. {
+                    if stopped() {
+                        break;
+                    }
+                    let Some(job) = timed(profiler, Phase::Parse, index, || source.next()) else {
+                        break;
+                    };
+                    // The reader may fail the same way again and again, so
+                    // failing to read is never gone past.
+                    let job = match job {
+                        Err(err) if !err.chain().any(|cause| cause.is::<io::Error>()) => match skipped(index, err) {
+                            Some(err) => Err(err),
+                            None if source_tx.send(Ok(Item::Skipped(index))).is_ok() => continue,
+                            None => break,
+                        },
+                        job => job,
+                    };
+                    let failed = job.is_err();
+                    let sent = match scheduler {
+                        Some(scheduler) => scheduler.push(index, job),
+                        None => job_tx.send((index, job)).is_ok(),
+                    };
+                    if !sent || failed {
+                        break;
+                    }
+                }
+                if let Some(scheduler) = scheduler {
+                    scheduler.close();
+                }
+            });
+
+            for _ in 0..workers {
+                let tx = tx.clone();
+                let job_rx = job_rx.clone();
+                scope.spawn(move || loop {
+                    let next = match scheduler {
+                        Some(scheduler) => scheduler.take(),
+                        None => job_rx.lock().unwrap().recv().ok().map(|(index, job)| (index, job, false)),
+                    };
+                    let Some((index, job, heavy)) = next else {
+                        break;
+                    };
+                    let _running = scheduler.map(|scheduler| Running { scheduler, heavy });
+                    // Queued jobs are dropped rather than left in the channel,
+                    // so that the source isn't blocked sending more.
+                    if stopped() {
+                        continue;
+                    }
+                    if let Some(progress) = progress {
+                        progress.start_line(index);
+                    }
+                    let generated = Instant::now();
+                    let record = job
+                        .and_then(|job| timed(profiler, Phase::Generate, index, || generator(job)))
+                        .map(|record| ResultRecord { index, ..record });
+                    generating.fetch_add(generated.elapsed().as_nanos() as u64, AtomicOrdering::Relaxed);
+                    let message = match record {
+                        Ok(record) => Ok(Item::Record(record)),
+                        Err(err) => match skipped(index, err) {
+                            Some(err) => Err(err),
+                            None => Ok(Item::Skipped(index)),
+                        },
+                    };
+                    let failed = message.is_err();
+                    if !send_noting(&tx, message, backpressure) || failed {
+                        if let Some(scheduler) = scheduler {
+                            scheduler.cancel();
+                        }
+                        break;
+                    }
+                });
+            }
+            drop((tx, job_rx));
+
+            for mut stage in stages {
+                let (stage_tx, stage_rx) = mpsc::sync_channel::<Message>(queue_size);
+                let upstream = rx;
+                scope.spawn(move || run_stage(stage.as_mut(), upstream, stage_tx));
+                rx = stage_rx;
+            }
+
+            for message in rx {
+                let Item::Record(record) = message? else {
+                    continue;
+                };
+                let written = Instant::now();
+                sink.write(record)?;
+                writing += written.elapsed();
+                records += 1;
+            }
+            let written = Instant::now();
+            sink.finish()?;
+            writing += written.elapsed();
+            Ok::<_, anyhow::Error>(())
+        })?;
+        let mut errors = std::mem::take(&mut *errors.lock().unwrap());
+        errors.sort_by_key(|error| error.index);
+        Ok(RunReport {
+            records,
+            warnings: warnings.take(),
+            errors,
+            timings: Timings {
+                elapsed: started.elapsed(),
+                generating: Duration::from_nanos(generating.load(AtomicOrdering::Relaxed)),
+                writing,
+            },
+            status: Status::Complete,
+        })
+    }
+}
+
+/// Sends `message`, noting in `backpressure` whether the queue was full.
+/// Returns false once the receiver is gone.
+fn send_noting(tx: &SyncSender<Message>, message: Message, backpressure: Option<&Backpressure>) -> bool {
+    let Some(backpressure) = backpressure else {
+        return tx.send(message).is_ok();
+    };
+    match tx.try_send(message) {
+        Ok(()) => {
+            backpressure.set(false);
+            true
+        }
+        Err(TrySendError::Full(message)) => {
+            backpressure.set(true);
+            tx.send(message).is_ok()
+        }
+        Err(TrySendError::Disconnected(_)) => false,
+    }
+}
+
+/// A job waiting in a [`Scheduler`], ordered by its priority, then by its
+/// cost, then by the line it comes from, earliest first.
+struct Queued {
+    priority: Priority,
+    cost: u64,
+    index: usize,
+    job: Result<LineNumbers>,
+}
+
+impl Queued {
+    fn key(&self) -> (Priority, u64, Reverse<usize>) {
+        (self.priority, self.cost, Reverse(self.index))
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    light: BinaryHeap<Queued>,
+    heavy: BinaryHeap<Queued>,
+    heavy_running: usize,
+    /// The source is done.
+    closed: bool,
+    /// The workers are done, so the source should stop.
+    cancelled: bool,
+}
+
+/// The queue between the source and the workers of a scheduled pipeline.
+struct Scheduler {
+    priorities: Priorities,
+    max_heavy: usize,
+    cost: Option<CostFn>,
+    window: usize,
+    state: Mutex<SchedulerState>,
+    changed: Condvar,
+}
+
+impl Scheduler {
+    fn new(schedule: Schedule, window: usize) -> Self {
+        // Jobs ordered by cost are only handed out once all are known.
+        let window = if schedule.cost.is_some() { usize::MAX } else { window.max(1) };
+        Scheduler {
+            priorities: schedule.priorities,
+            max_heavy: schedule.max_heavy.unwrap_or(usize::MAX),
+            cost: schedule.cost,
+            window,
+            state: Mutex::default(),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SchedulerState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Queues the job of line `index`, waiting while the window is full.
+    /// Returns false once the workers are gone.
+    fn push(&self, index: usize, job: Result<LineNumbers>) -> bool {
+        let priority = self.priorities.lock().unwrap().remove(&index).unwrap_or_default();
+        // Errors go first, so that a broken input fails without waiting.
+        let (priority, heavy, cost) = match &job {
+            Ok(job) => {
+                let cost = self.cost.as_ref().map_or(0, |cost| cost(job));
+                (priority, formula::count(job, Mode::Or) >= HEAVY_MULTIPLES, cost)
+            }
+            Err(_) => (Priority::High, false, 0),
+        };
+        let mut state = self.lock();
+        while state.light.len() + state.heavy.len() >= self.window && !state.cancelled {
+            state = self.changed.wait(state).unwrap_or_else(|err| err.into_inner());
+        }
+        if state.cancelled {
+            return false;
+        }
+        let queued = Queued {
+            priority,
+            cost,
+            index,
+            job,
+        };
+        if heavy {
+            state.heavy.push(queued);
+        } else {
+            state.light.push(queued);
+        }
+        self.changed.notify_all();
+        true
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.changed.notify_all();
+    }
+
+    fn cancel(&self) {
+        self.lock().cancelled = true;
+        self.changed.notify_all();
+    }
+
+    /// The most urgent job a worker may start, the index of its line and
+    /// whether it is heavy, waiting until there is one. `None` once every
+    /// job has been handed out.
+    fn take(&self) -> Option<(usize, Result<LineNumbers>, bool)> {
+        let mut state = self.lock();
+        loop {
+            if state.cancelled {
+                return None;
+            }
+            if self.cost.is_some() && !state.closed {
+                state = self.changed.wait(state).unwrap_or_else(|err| err.into_inner());
+                continue;
+            }
+            let heavy_allowed = state.heavy_running < self.max_heavy;
+            let heavy = match (state.light.peek(), state.heavy.peek().filter(|_| heavy_allowed)) {
+                (Some(light), Some(heavy)) => heavy > light,
+                (None, Some(_)) => true,
+                (Some(_), None) => false,
+                (None, None) if state.closed && state.heavy.is_empty() => return None,
+                (None, None) => {
+                    state = self.changed.wait(state).unwrap_or_else(|err| err.into_inner());
+                    continue;
+                }
+            };
+            let queued = if heavy { state.heavy.pop() } else { state.light.pop() }?;
+            state.heavy_running += usize::from(heavy);
+            self.changed.notify_all();
+            return Some((queued.index, queued.job, heavy));
+        }
+    }
+
+    fn finish(&self, heavy: bool) {
+        if heavy {
+            self.lock().heavy_running -= 1;
+            self.changed.notify_all();
+        }
+    }
+}
+
+/// Tells the scheduler a worker is done with its job, even if generating
+/// it panicked.
+struct Running<'a> {
+    scheduler: &'a Scheduler,
+    heavy: bool,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.scheduler.finish(self.heavy);
+    }
+}
+
+fn timed<T>(profiler: Option<&Profiler>, phase: Phase, index: usize, f: impl FnOnce() -> T) -> T {
+    match profiler {
+        Some(profiler) => profiler.time(phase, Some(index), f),
+        None => f(),
+    }
+}
+
+fn run_stage(stage: &mut dyn Stage, rx: Receiver<Message>, tx: SyncSender<Message>) {
+    let stopped = |_| anyhow::anyhow!("Pipeline was stopped");
+    let mut emit = |record| tx.send(Ok(Item::Record(record))).map_err(stopped);
+    for message in rx {
+        let result = match message {
+            Ok(Item::Record(record)) => stage.process(record, &mut emit),
+            Ok(Item::Skipped(index)) => {
+                stage.skip(index, &mut emit).and_then(|()| tx.send(Ok(Item::Skipped(index))).map_err(stopped))
+            }
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            let _ = tx.send(Err(err));
+            return;
+        }
+    }
+    if let Err(err) = stage.finish(&mut emit) {
+        let _ = tx.send(Err(err));
+    }
+}
+
+struct FilterStage<F>(F);
+
+impl<F: FnMut(&ResultRecord) -> bool + Send> Stage for FilterStage<F> {
+    fn process(&mut self, record: ResultRecord, emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()> {
+        if (self.0)(&record) {
+            emit(record)?;
+        }
+        Ok(())
+    }
+}
+
+struct InspectStage<F>(F);
+
+impl<F: FnMut(&ResultRecord) + Send> Stage for InspectStage<F> {
+    fn process(&mut self, record: ResultRecord, emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()> {
+        (self.0)(&record);
+        emit(record)
+    }
+}
+
+/// Puts records back into the order of their `index`. Records generated
+/// ahead of a slow line wait here until it is done. Lines that failed and
+/// were gone past with [`PipelineBuilder::keep_going`] arrive as skips and
+/// aren't waited for; lines that never arrive at all, such as those dropped
+/// when the pipeline is stopped, are skipped at the end.
+#[derive(Default)]
+struct ReorderStage {
+    next: usize,
+    pending: BTreeMap<usize, ResultRecord>,
+    skipped: BTreeSet<usize>,
+}
+
+impl ReorderStage {
+    /// Passes on the records that are next in order, going past skipped lines.
+    fn advance(&mut self, emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()> {
+        loop {
+            if let Some(record) = self.pending.remove(&self.next) {
+                emit(record)?;
+            } else if !self.skipped.remove(&self.next) {
+                return Ok(());
+            }
+            self.next += 1;
+        }
+    }
+}
+
+impl Stage for ReorderStage {
+    fn process(&mut self, record: ResultRecord, emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()> {
+        self.pending.insert(record.index, record);
+        self.advance(emit)
+    }
+
+    fn skip(&mut self, index: usize, emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()> {
+        self.skipped.insert(index);
+        self.advance(emit)
+    }
+
+    fn finish(&mut self, emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()> {
+        for record in std::mem::take(&mut self.pending).into_values() {
+            emit(record)?;
+        }
+        Ok(())
+    }
+}
+
+struct SortStage<F> {
+    key: F,
+    records: Vec<ResultRecord>,
+}
+
+impl<K: Ord, F: FnMut(&ResultRecord) -> K + Send> Stage for SortStage<F> {
+    fn process(&mut self, record: ResultRecord, _emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()> {
+        self.records.push(record);
+        Ok(())
+    }
+
+    fn finish(&mut self, emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()> {
+        let mut records = std::mem::take(&mut self.records);
+        records.sort_by_key(&mut self.key);
+        for record in records {
+            emit(record)?;
+        }
+        Ok(())
+    }
+}
+
+struct TopStage<F> {
+    n: usize,
+    key: F,
+    records: Vec<ResultRecord>,
+}
+
+impl<K: Ord, F: FnMut(&ResultRecord) -> K> TopStage<F> {
+    fn truncate(&mut self) {
+        let key = &mut self.key;
+        self.records.sort_by_key(|record| std::cmp::Reverse(key(record)));
+        self.records.truncate(self.n);
+    }
+}
+
+impl<K: Ord, F: FnMut(&ResultRecord) -> K + Send> Stage for TopStage<F> {
+    fn process(&mut self, record: ResultRecord, _emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()> {
+        self.records.push(record);
+        // Trimming only once the buffer doubles keeps this from sorting on
+        // every record.
+        if self.records.len() >= 2 * self.n.max(1) {
+            self.truncate();
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()> {
+        self.truncate();
+        for record in std::mem::take(&mut self.records) {
+            emit(record)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects all records in memory.
+#[derive(Default)]
+pub struct CollectSink {
+    pub records: Vec<ResultRecord>,
+}
+
+impl Sink for CollectSink {
+    fn write(&mut self, record: ResultRecord) -> Result<()> {
+        self.records.push(record);
+        Ok(())
+    }
+}
+
+/// Serializes records with an [`OutputFormatter`] into a writer.
+pub struct FormatterSink<W: RecordOutput> {
+    out: W,
+    formatter: Box<dyn OutputFormatter>,
+    started: bool,
+    written: usize,
+}
+
+impl<W: RecordOutput> FormatterSink<W> {
+    pub fn new(out: W, formatter: Box<dyn OutputFormatter>) -> Self {
+        FormatterSink {
+            out,
+            formatter,
+            started: false,
+            written: 0,
+        }
+    }
+
+    /// The writer, once the sink is finished.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn start(&mut self) -> Result<()> {
+        if !self.started {
+            self.started = true;
+            self.formatter.begin(&mut self.out).context("Failed to write output header")?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: RecordOutput> Sink for FormatterSink<W> {
+    fn write(&mut self, record: ResultRecord) -> Result<()> {
+        self.start()?;
+        self.written += 1;
+        self.formatter
+            .write_record(&mut self.out, &record)
+            .and_then(|()| self.out.end_record().map_err(Into::into))
+            .with_context(|| format!("Failed to write result {} to output file", self.written))
+    }
+
+    fn write_streamed(
+        &mut self,
+        record: ResultRecord,
+        count: Option<u64>,
+        chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    ) -> Result<()> {
+        self.start()?;
+        self.written += 1;
+        self.formatter
+            .write_streamed(&mut self.out, &record, count, chunks)
+            .and_then(|()| self.out.end_record().map_err(Into::into))
+            .with_context(|| format!("Failed to write result {} to output file", self.written))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.start()?;
+        self.formatter.finish(&mut self.out).context("Failed to write output footer")?;
+        self.out.close().context("Failed to flush output buffer")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use anyhow::{anyhow, Result};
+
+    use super::{
+        jobs_source, CollectSink, FormatterSink, Pipeline, Priorities, ReorderStage, Schedule, Scheduler, Sink, Stage,
+    };
+    use crate::formatter::FormatterRegistry;
+    use crate::input::Priority;
+    use crate::profile::{Phase, Profiler};
+    use crate::{LineNumbers, ResultRecord};
+
+    fn jobs() -> Vec<LineNumbers> {
+        vec![
+            LineNumbers { a: 2, b: 3, end: 12 },
+            LineNumbers { a: 5, b: 7, end: 10 },
+            LineNumbers { a: 1, b: 1, end: 3 },
+        ]
+    }
+
+    #[test]
+    fn test_pipeline_filter_and_sort() {
+        let mut sink = CollectSink::default();
+        Pipeline::builder(jobs_source(jobs()))
+            .filter(|record| record.job.a > 1)
+            .sort_by_key(|record| record.result.numbers.len())
+            .build()
+            .run(&mut sink)
+            .unwrap();
+        let ends: Vec<u32> = sink.records.iter().map(|record| record.result.end).collect();
+        assert_eq!(ends, vec![10, 12]);
+    }
+
+    #[test]
+    fn test_pipeline_stop_when() {
+        let generated = AtomicUsize::new(0);
+        let jobs: Vec<LineNumbers> = (1..=100).map(|end| LineNumbers { a: 2, b: 3, end }).collect();
+        for workers in [1, 4] {
+            generated.store(0, Ordering::SeqCst);
+            let mut sink = CollectSink::default();
+            Pipeline::builder(jobs_source(jobs.clone()))
+                .workers(workers)
+                .queue_size(2)
+                .generator(|job| {
+                    generated.fetch_add(1, Ordering::SeqCst);
+                    Ok(crate::generate_record(job))
+                })
+                .stop_when(|| generated.load(Ordering::SeqCst) >= 3)
+                .build()
+                .run(&mut sink)
+                .unwrap();
+            // Every job started before the stop is finished and written.
+            assert_eq!(sink.records.len(), generated.load(Ordering::SeqCst));
+            assert!((3..3 + workers).contains(&sink.records.len()), "{}", sink.records.len());
+        }
+    }
+
+    #[test]
+    fn test_pipeline_top_by_key() {
+        let jobs: Vec<LineNumbers> = (1..=20).map(|end| LineNumbers { a: 2, b: 2, end: end % 7 * 10 }).collect();
+        let mut sink = CollectSink::default();
+        Pipeline::builder(jobs_source(jobs))
+            .top_by_key(4, |record| record.result.end)
+            .build()
+            .run(&mut sink)
+            .unwrap();
+        let picked: Vec<(u32, usize)> = sink.records.iter().map(|record| (record.result.end, record.index)).collect();
+        assert_eq!(picked, vec![(60, 5), (60, 12), (60, 19), (50, 4)]);
+    }
+
+    #[test]
+    fn test_pipeline_count_only() {
+        let mut sink = CollectSink::default();
+        Pipeline::builder(jobs_source(jobs()))
+            .count_only(crate::formula::Mode::Or)
+            .build()
+            .run(&mut sink)
+            .unwrap();
+        let counts: Vec<Option<u64>> = sink.records.iter().map(|record| record.result.count).collect();
+        assert_eq!(counts, vec![Some(8), Some(3), Some(3)]);
+        assert!(sink.records.iter().all(|record| record.result.numbers.is_empty()));
+    }
+
+    #[test]
+    fn test_pipeline_profile() {
+        let profiler = std::sync::Arc::new(Profiler::default());
+        let mut sink = CollectSink::default();
+        Pipeline::builder(jobs_source(jobs()))
+            .workers(2)
+            .profile(profiler.clone())
+            .build()
+            .run(&mut sink)
+            .unwrap();
+        let mut spans: Vec<_> = profiler.spans().iter().map(|span| (span.phase, span.index)).collect();
+        spans.sort();
+        let lines = [Some(0), Some(1), Some(2)];
+        let parsed = lines.map(|index| (Phase::Parse, index));
+        assert_eq!(spans[..4], [parsed[0], parsed[1], parsed[2], (Phase::Parse, Some(3))]);
+        assert_eq!(spans[4..], lines.map(|index| (Phase::Generate, index)));
+    }
+
+    struct Duplicate;
+
+    impl Stage for Duplicate {
+        fn process(&mut self, record: ResultRecord, emit: &mut dyn FnMut(ResultRecord) -> Result<()>) -> Result<()> {
+            emit(record.clone())?;
+            emit(record)
+        }
+    }
+
+    #[test]
+    fn test_pipeline_custom_stage_and_formatter_sink() {
+        let mut out = Vec::new();
+        let formatter = FormatterRegistry::with_builtins().create("text").unwrap();
+        let mut sink = FormatterSink::new(&mut out, formatter);
+        Pipeline::builder(jobs_source(vec![LineNumbers { a: 2, b: 3, end: 4 }]))
+            .stage(Duplicate)
+            .build()
+            .run(&mut sink)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "4:2 3 4\n4:2 3 4\n");
+    }
+
+    #[test]
+    fn test_pipeline_parallel_workers() {
+        let jobs: Vec<LineNumbers> = (1..=50).map(|end| LineNumbers { a: 2, b: 3, end }).collect();
+        let mut sink = CollectSink::default();
+        Pipeline::builder(jobs_source(jobs))
+            .workers(4)
+            .queue_size(2)
+            .sort_by_key(|record| record.result.end)
+            .build()
+            .run(&mut sink)
+            .unwrap();
+        let ends: Vec<u32> = sink.records.iter().map(|record| record.result.end).collect();
+        assert_eq!(ends, (1..=50).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_pipeline_keeps_input_order() {
+        let jobs: Vec<LineNumbers> = (1..=40).map(|end| LineNumbers { a: 2, b: 3, end }).collect();
+        let run = |ordered: bool| {
+            let mut builder = Pipeline::builder(jobs_source(jobs.clone()))
+                .workers(4)
+                .queue_size(1)
+                .generator(|job| {
+                    // Early lines take longest, so workers finish out of order.
+                    std::thread::sleep(std::time::Duration::from_millis((40 - job.end as u64) / 4));
+                    Ok(crate::generate_record(job))
+                });
+            if !ordered {
+                builder = builder.unordered();
+            }
+            let mut sink = CollectSink::default();
+            builder.build().run(&mut sink).unwrap();
+            sink.records.iter().map(|record| record.index).collect::<Vec<usize>>()
+        };
+        assert_eq!(run(true), (0..40).collect::<Vec<usize>>());
+        let mut unordered = run(false);
+        unordered.sort();
+        assert_eq!(unordered, (0..40).collect::<Vec<usize>>());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mapped_file_source() {
+        use super::{mapped_file_source, routed_file_source};
+        use crate::input::{Bound, InputParserRegistry};
+        use crate::jobfilter::TagFilter;
+        use crate::output::Routes;
+
+        let registry = InputParserRegistry::with_builtins();
+        let inputs = [
+            ("txt", "2 3 12 prio=high\n5 7 10 > out.txt\n1 1 3"),
+            ("csv", "a,b,end\n2,3,12\n5,7,10\n"),
+            ("txt", ""),
+        ];
+        for (name, text) in inputs {
+            let path = std::env::temp_dir().join(format!("multiples_mapped_{}.{}", std::process::id(), name));
+            std::fs::write(&path, text).unwrap();
+            let (routes, priorities) = (Routes::default(), Priorities::default());
+            let (bound, tags) = (Bound::Inclusive, TagFilter::default());
+            let mapped = mapped_file_source(&registry, &path, None, routes.clone(), priorities.clone(), bound, &tags, 2).unwrap();
+            let mapped: Vec<_> = mapped.collect::<Result<_>>().unwrap();
+            let read = routed_file_source(&registry, &path, None, Routes::default(), Priorities::default(), bound, &tags).unwrap();
+            assert_eq!(mapped, read.collect::<Result<Vec<_>>>().unwrap());
+            if name == "txt" && !text.is_empty() {
+                assert_eq!(mapped, jobs());
+                let below = mapped_file_source(&registry, &path, None, routes.clone(), priorities.clone(), Bound::Exclusive, &tags, 2);
+                let ends: Vec<u32> = below.unwrap().map(|job| job.unwrap().end).collect();
+                assert_eq!(ends, [11, 9, 2]);
+                assert_eq!(routes.lock().unwrap().len(), 1);
+                assert_eq!(priorities.lock().unwrap().get(&0), Some(&Priority::High));
+            }
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    /// Lines left out by their tags don't take an index, so the routes of
+    /// the others still name their records.
+    #[test]
+    fn test_tagged_source() {
+        use super::routed_file_source;
+        use crate::input::{Bound, InputParserRegistry};
+        use crate::jobfilter::TagFilter;
+        use crate::output::Routes;
+
+        let path = std::env::temp_dir().join(format!("multiples_tagged_{}.txt", std::process::id()));
+        std::fs::write(&path, "2 3 12 tag=slow\n5 7 10 tag=nightly > out.txt\n1 1 3 tag=nightly tag=slow\n2 2 4\n").unwrap();
+        let (registry, routes) = (InputParserRegistry::with_builtins(), Routes::default());
+        let mut tags = TagFilter::default();
+        tags.only("nightly").unwrap();
+        let source = routed_file_source(&registry, &path, None, routes.clone(), Priorities::default(), Bound::Inclusive, &tags);
+        let ends: Vec<u32> = source.unwrap().map(|job| job.unwrap().end).collect();
+        assert_eq!(ends, [10, 3]);
+        assert_eq!(routes.lock().unwrap().get(&0), Some(&std::path::PathBuf::from("out.txt")));
+        tags.skip("slow").unwrap();
+        let source = routed_file_source(&registry, &path, None, Routes::default(), Priorities::default(), Bound::Inclusive, &tags);
+        assert_eq!(source.unwrap().count(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let priorities = Priorities::default();
+        priorities.lock().unwrap().extend([(1, Priority::Low), (3, Priority::High)]);
+        let scheduler = Scheduler::new(
+            Schedule {
+                priorities: priorities.clone(),
+                max_heavy: Some(1),
+                cost: None,
+            },
+            8,
+        );
+        let job = |end| Ok(LineNumbers { a: 1, b: 1, end });
+        for (index, end) in [10, 10, 4_000_000_000, 10, 4_000_000_000].into_iter().enumerate() {
+            assert!(scheduler.push(index, job(end)));
+        }
+        scheduler.close();
+        let take = || scheduler.take().map(|(index, _, heavy)| (index, heavy));
+        // The high priority line first, then input order, but the running
+        // heavy line keeps the second one waiting behind the low priority one.
+        assert_eq!(take(), Some((3, false)));
+        assert_eq!(take(), Some((0, false)));
+        assert_eq!(take(), Some((2, true)));
+        assert_eq!(take(), Some((1, false)));
+        scheduler.finish(true);
+        assert_eq!(take(), Some((4, true)));
+        assert_eq!(take(), None);
+        assert!(priorities.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_pipeline_schedule() {
+        let priorities = Priorities::default();
+        priorities.lock().unwrap().insert(29, Priority::High);
+        let jobs: Vec<LineNumbers> = (1..=30).map(|end| LineNumbers { a: 2, b: 3, end }).collect();
+        let mut sink = CollectSink::default();
+        Pipeline::builder(jobs_source(jobs))
+            .workers(3)
+            .schedule(priorities, Some(1))
+            .build()
+            .run(&mut sink)
+            .unwrap();
+        let indexes: Vec<usize> = sink.records.iter().map(|record| record.index).collect();
+        assert_eq!(indexes, (0..30).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_pipeline_longest_first() {
+        let priorities = Priorities::default();
+        priorities.lock().unwrap().insert(0, Priority::High);
+        let jobs: Vec<LineNumbers> = [5, 30, 10, 20, 30].map(|end| LineNumbers { a: 2, b: 3, end }).into();
+        let mut sink = CollectSink::default();
+        Pipeline::builder(jobs_source(jobs))
+            .unordered()
+            .longest_first(Arc::new(|job: &LineNumbers| job.end as u64))
+            .schedule(priorities, None)
+            .build()
+            .run(&mut sink)
+            .unwrap();
+        // The high priority line first, then the longest, the earliest of
+        // those with equal costs first.
+        let indexes: Vec<usize> = sink.records.iter().map(|record| record.index).collect();
+        assert_eq!(indexes, [0, 1, 4, 3, 2]);
+    }
+
+    #[test]
+    fn test_pipeline_stops_on_error() {
+        let mut sink = CollectSink::default();
+        let result = Pipeline::builder(jobs_source(jobs()))
+            .generator(|job| {
+                if job.a == 5 {
+                    Err(anyhow!("boom"))
+                } else {
+                    Ok(crate::generate_record(job))
+                }
+            })
+            .sort_by_key(|record| record.result.end)
+            .build()
+            .run(&mut sink);
+        assert!(result.is_err());
+        assert!(sink.records.is_empty());
+    }
+
+    #[test]
+    fn test_reorder_stage_goes_past_missing_lines() {
+        fn into(emitted: &mut Vec<usize>) -> impl FnMut(ResultRecord) -> Result<()> + '_ {
+            |record| {
+                emitted.push(record.index);
+                Ok(())
+            }
+        }
+        let record = |index| ResultRecord { index, ..crate::generate_record(LineNumbers { a: 2, b: 3, end: 10 }) };
+        let (mut stage, mut emitted) = (ReorderStage::default(), Vec::new());
+        for index in [2, 1, 5] {
+            stage.process(record(index), &mut into(&mut emitted)).unwrap();
+        }
+        stage.skip(3, &mut into(&mut emitted)).unwrap();
+        assert!(emitted.is_empty());
+        // Line 0 failed, and line 4 never arrives, as when it is dropped on stop.
+        stage.skip(0, &mut into(&mut emitted)).unwrap();
+        assert_eq!(emitted, [1, 2]);
+        stage.process(record(6), &mut into(&mut emitted)).unwrap();
+        assert_eq!(emitted, [1, 2]);
+        stage.finish(&mut into(&mut emitted)).unwrap();
+        assert_eq!(emitted, [1, 2, 5, 6]);
+    }
+
+    #[test]
+    fn test_pipeline_keep_going_doesnt_hold_records_back() {
+        let jobs: Vec<LineNumbers> = (1..=20).map(|end| LineNumbers { a: 2, b: 3, end }).collect();
+        let emitted = AtomicUsize::new(0);
+        let early = AtomicBool::new(false);
+        let mut sink = CollectSink::default();
+        let report = Pipeline::builder(jobs_source(jobs))
+            .workers(2)
+            .queue_size(2)
+            .keep_going()
+            .generator(|job| match job.end {
+                1 => Err(anyhow!("boom")),
+                // The last line waits for the records before it, which only
+                // get out while it is still running if the failed first line
+                // isn't waited for.
+                20 => {
+                    let waited = Instant::now();
+                    while emitted.load(Ordering::SeqCst) == 0 && waited.elapsed() < Duration::from_secs(5) {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                    early.store(emitted.load(Ordering::SeqCst) > 0, Ordering::SeqCst);
+                    Ok(crate::generate_record(job))
+                }
+                _ => Ok(crate::generate_record(job)),
+            })
+            .inspect(|_| {
+                emitted.fetch_add(1, Ordering::SeqCst);
+            })
+            .build()
+            .run(&mut sink)
+            .unwrap();
+        assert!(early.load(Ordering::SeqCst));
+        assert_eq!(report.errors.iter().map(|error| error.index).collect::<Vec<usize>>(), [0]);
+        let indexes: Vec<usize> = sink.records.iter().map(|record| record.index).collect();
+        assert_eq!(indexes, (1..20).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_pipeline_stops_on_error_with_a_full_job_queue() {
+        let jobs: Vec<LineNumbers> = (1..=1000).map(|end| LineNumbers { a: 2, b: 3, end }).collect();
+        let result = Pipeline::builder(jobs_source(jobs.clone()))
+            .workers(2)
+            .queue_size(4)
+            .generator(|job| match job.end {
+                1 => Err(anyhow!("boom")),
+                _ => Ok(crate::generate_record(job)),
+            })
+            .build()
+            .run(&mut CollectSink::default());
+        assert!(result.is_err());
+
+        struct FailingSink;
+        impl Sink for FailingSink {
+            fn write(&mut self, _record: ResultRecord) -> Result<()> {
+                Err(anyhow!("disk full"))
+            }
+        }
+        let result = Pipeline::builder(jobs_source(jobs)).workers(2).queue_size(4).build().run(&mut FailingSink);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! Loading of external output formatters from shared libraries.
+//!
+//! A plugin is a shared library exporting two C functions:
+//!
+//! ```c
+//! const char *multiples_formatter_name(void);
+//! int multiples_formatter_write_record(
+//!     uint32_t a, uint32_t b, uint32_t end,
+//!     const uint32_t *numbers, size_t len,
+//!     void *ctx, size_t (*write)(void *ctx, const uint8_t *buf, size_t len));
+//! ```
+//!
+//! The formatter is registered under the returned name and receives every
+//! record; it emits bytes through `write` and returns 0 on success.
+
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::formatter::{FormatterRegistry, OutputFormatter};
+use crate::ResultRecord;
+
+const RTLD_NOW: c_int = 2;
+
+#[cfg_attr(target_os = "linux", link(name = "dl"))]
+extern "C" {
+    fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlerror() -> *mut c_char;
+}
+
+type NameFn = unsafe extern "C" fn() -> *const c_char;
+type WriteCallback = unsafe extern "C" fn(*mut c_void, *const u8, usize) -> usize;
+type WriteRecordFn =
+    unsafe extern "C" fn(u32, u32, u32, *const u32, usize, *mut c_void, WriteCallback) -> c_int;
+
+fn last_error() -> String {
+    // SAFETY: dlerror returns either null or a valid C string owned by the loader.
+    unsafe {
+        let err = dlerror();
+        if err.is_null() {
+            String::from("unknown error")
+        } else {
+            CStr::from_ptr(err).to_string_lossy().into_owned()
+        }
+    }
+}
+
+unsafe fn symbol(handle: *mut c_void, name: &str) -> Result<*mut c_void> {
+    let c_name = CString::new(name)?;
+    let sym = dlsym(handle, c_name.as_ptr());
+    if sym.is_null() {
+        bail!("Missing symbol {}: {}", name, last_error());
+    }
+    Ok(sym)
+}
+
+struct PluginFormatter {
+    write_record: WriteRecordFn,
+}
+
+struct CallbackContext<'a> {
+    writer: &'a mut dyn Write,
+    error: Option<std::io::Error>,
+}
+
+unsafe extern "C" fn write_callback(ctx: *mut c_void, buf: *const u8, len: usize) -> usize {
+    let ctx = &mut *(ctx as *mut CallbackContext);
+    let bytes = std::slice::from_raw_parts(buf, len);
+    match ctx.writer.write_all(bytes) {
+        Ok(()) => len,
+        Err(err) => {
+            ctx.error = Some(err);
+            0
+        }
+    }
+}
+
+impl OutputFormatter for PluginFormatter {
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        let mut ctx = CallbackContext {
+            writer: w,
+            error: None,
+        };
+        let numbers = &r.result.numbers;
+        // SAFETY: the plugin contract only allows reading `numbers` and calling
+        // `write_callback` with the context pointer during this call.
+        let status = unsafe {
+            (self.write_record)(
+                r.job.a,
+                r.job.b,
+                r.result.end,
+                numbers.as_ptr(),
+                numbers.len(),
+                &mut ctx as *mut CallbackContext as *mut c_void,
+                write_callback,
+            )
+        };
+        if let Some(err) = ctx.error {
+            return Err(err.into());
+        }
+        if status != 0 {
+            bail!("Plugin formatter failed with status {}", status);
+        }
+        Ok(())
+    }
+}
+
+/// Loads the formatter plugin at `path` and registers it. Returns its name.
+///
+/// Loaded libraries are never unloaded.
+pub fn load_formatter_plugin(registry: &mut FormatterRegistry, path: &Path) -> Result<String> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .with_context(|| format!("Invalid plugin path: {:?}", path))?;
+
+    // SAFETY: loading a library runs its initializers; plugins are trusted
+    // code explicitly requested by the user.
+    unsafe {
+        let handle = dlopen(c_path.as_ptr(), RTLD_NOW);
+        if handle.is_null() {
+            return Err(anyhow!("Failed to load plugin {:?}: {}", path, last_error()));
+        }
+        let name_fn: NameFn = std::mem::transmute(symbol(handle, "multiples_formatter_name")?);
+        let write_record: WriteRecordFn =
+            std::mem::transmute(symbol(handle, "multiples_formatter_write_record")?);
+
+        let name_ptr = name_fn();
+        if name_ptr.is_null() {
+            bail!("Plugin {:?} returned no formatter name", path);
+        }
+        let name = CStr::from_ptr(name_ptr).to_string_lossy().into_owned();
+        registry.register(&name, move || Box::new(PluginFormatter { write_record }));
+        Ok(name)
+    }
+}
@@ -0,0 +1,134 @@
+//! Conditions a number must meet to be reported for a job.
+
+use anyhow::{bail, Result};
+
+use crate::LineNumbers;
+
+/// A condition on a candidate number `n` of a job.
+pub trait Predicate: Send + Sync {
+    fn matches(&self, job: &LineNumbers, n: u32) -> bool;
+}
+
+/// The default condition: `n` is a multiple of `a` or `b`.
+pub struct Divisible;
+
+impl Predicate for Divisible {
+    fn matches(&self, job: &LineNumbers, n: u32) -> bool {
+        crate::is_number_divisible_by(job, &n)
+    }
+}
+
+/// `n` is a multiple of exactly one of `a` and `b`.
+pub struct DivisibleByExactlyOne;
+
+impl Predicate for DivisibleByExactlyOne {
+    fn matches(&self, job: &LineNumbers, n: u32) -> bool {
+        n.is_multiple_of(job.a) != n.is_multiple_of(job.b)
+    }
+}
+
+/// Matches when every one of the contained predicates does.
+#[derive(Default)]
+pub struct All(pub Vec<Box<dyn Predicate>>);
+
+impl All {
+    pub fn with<P: Predicate + 'static>(mut self, predicate: P) -> Self {
+        self.0.push(Box::new(predicate));
+        self
+    }
+}
+
+impl Predicate for All {
+    fn matches(&self, job: &LineNumbers, n: u32) -> bool {
+        self.0.iter().all(|predicate| predicate.matches(job, n))
+    }
+}
+
+/// The sum of the decimal digits of `n` is a multiple of the given number.
+pub struct DigitSumDivisibleBy(pub u32);
+
+impl Predicate for DigitSumDivisibleBy {
+    fn matches(&self, _job: &LineNumbers, mut n: u32) -> bool {
+        let mut sum = 0;
+        while n > 0 {
+            sum += n % 10;
+            n /= 10;
+        }
+        sum.is_multiple_of(self.0)
+    }
+}
+
+/// The decimal representation of `n` ends with the given digits.
+pub struct EndsWith {
+    suffix: u64,
+    modulus: u64,
+}
+
+impl EndsWith {
+    pub fn new(digits: &str) -> Result<Self> {
+        if digits.is_empty() || digits.len() > 10 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            bail!("Invalid digit suffix: {:?}", digits);
+        }
+        Ok(EndsWith {
+            suffix: digits.parse()?,
+            modulus: 10u64.pow(digits.len() as u32),
+        })
+    }
+}
+
+impl Predicate for EndsWith {
+    fn matches(&self, _job: &LineNumbers, n: u32) -> bool {
+        // A suffix with leading zeros such as "05" can't match a shorter
+        // number such as 5.
+        n as u64 >= self.modulus / 10 && n as u64 % self.modulus == self.suffix
+    }
+}
+
+/// `n` reads the same in both directions in decimal.
+pub struct Palindrome;
+
+impl Predicate for Palindrome {
+    fn matches(&self, _job: &LineNumbers, n: u32) -> bool {
+        let (mut rest, mut reversed) = (n, 0u64);
+        while rest > 0 {
+            reversed = reversed * 10 + (rest % 10) as u64;
+            rest /= 10;
+        }
+        reversed == n as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{All, DigitSumDivisibleBy, Divisible, DivisibleByExactlyOne, EndsWith, Palindrome, Predicate};
+    use crate::LineNumbers;
+
+    const JOB: LineNumbers = LineNumbers { a: 3, b: 5, end: 200 };
+
+    fn matching(predicate: &dyn Predicate) -> Vec<u32> {
+        (1..=JOB.end).filter(|&n| predicate.matches(&JOB, n)).collect()
+    }
+
+    #[test]
+    fn test_digit_predicates() {
+        assert!(DigitSumDivisibleBy(9).matches(&JOB, 981));
+        assert!(!DigitSumDivisibleBy(9).matches(&JOB, 982));
+        assert!(EndsWith::new("25").unwrap().matches(&JOB, 125));
+        assert!(!EndsWith::new("05").unwrap().matches(&JOB, 5));
+        assert!(EndsWith::new("05").unwrap().matches(&JOB, 105));
+        assert!(EndsWith::new("5x").is_err());
+        assert!(Palindrome.matches(&JOB, 12321));
+        assert!(!Palindrome.matches(&JOB, 120));
+    }
+
+    #[test]
+    fn test_predicates_compose_with_divisibility() {
+        let predicate = All::default().with(Divisible).with(Palindrome);
+        assert_eq!(matching(&predicate), vec![3, 5, 6, 9, 33, 55, 66, 99, 111, 141, 171]);
+        let predicate = All::default().with(Divisible).with(EndsWith::new("5").unwrap()).with(DigitSumDivisibleBy(9));
+        assert_eq!(matching(&predicate), vec![45, 135]);
+        let predicate = All::default().with(DivisibleByExactlyOne).with(Palindrome);
+        assert_eq!(matching(&predicate), vec![3, 5, 6, 9, 33, 55, 66, 99, 111, 141, 171]);
+        assert!(!DivisibleByExactlyOne.matches(&JOB, 15));
+    }
+}
@@ -0,0 +1,132 @@
+//! The `--preview` output: the first and last few multiples of every record
+//! and their count, printed instead of writing the records out.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::Write;
+
+use anyhow::{Context, Result};
+
+use crate::pipeline::Sink;
+use crate::ResultRecord;
+
+/// The head and tail of one record's multiples.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Preview {
+    head: Vec<u32>,
+    tail: VecDeque<u32>,
+    count: u64,
+    /// How many multiples the head and the tail each keep.
+    size: usize,
+}
+
+impl Preview {
+    pub fn new(size: usize) -> Self {
+        Preview {
+            size,
+            ..Preview::default()
+        }
+    }
+
+    pub fn push(&mut self, n: u32) {
+        self.count += 1;
+        if self.head.len() < self.size {
+            self.head.push(n);
+            return;
+        }
+        if self.tail.len() == self.size {
+            self.tail.pop_front();
+        }
+        self.tail.push_back(n);
+    }
+
+    /// The preview of the record of a line up to `end`, such as
+    /// `999:3 6 9 … 993 996 999 (count=333)`. `count` is the count of a
+    /// counts-only record, whose multiples weren't listed.
+    pub fn line(&self, end: u32, count: Option<u64>) -> String {
+        let mut line = format!("{}:", end);
+        if let Some(count) = count {
+            let _ = write!(line, "(count={})", count);
+            return line;
+        }
+        let join = |numbers: &mut dyn Iterator<Item = &u32>| numbers.map(u32::to_string).collect::<Vec<_>>().join(" ");
+        line.push_str(&join(&mut self.head.iter()));
+        if !self.tail.is_empty() {
+            // Nothing was left out when the tail directly follows the head.
+            let skipped = self.count > (self.head.len() + self.tail.len()) as u64;
+            line.push_str(if skipped { " … " } else { " " });
+            line.push_str(&join(&mut self.tail.iter()));
+        }
+        let separator = if self.count > 0 { " " } else { "" };
+        let _ = write!(line, "{}(count={})", separator, self.count);
+        line
+    }
+}
+
+/// Prints the [`Preview`] of every record to `out`, one line each.
+pub struct PreviewSink<W: Write> {
+    out: W,
+    size: usize,
+}
+
+impl<W: Write> PreviewSink<W> {
+    pub fn new(out: W, size: usize) -> Self {
+        PreviewSink { out, size }
+    }
+
+    fn print(&mut self, preview: &Preview, record: &ResultRecord, count: Option<u64>) -> Result<()> {
+        writeln!(self.out, "{}", preview.line(record.result.end, count)).context("Failed to print the preview")
+    }
+}
+
+impl<W: Write> Sink for PreviewSink<W> {
+    fn write(&mut self, record: ResultRecord) -> Result<()> {
+        let mut preview = Preview::new(self.size);
+        record.result.numbers.iter().for_each(|&n| preview.push(n));
+        self.print(&preview, &record, record.result.count)
+    }
+
+    /// Previews a line too large for memory as it is generated, keeping only
+    /// its head and tail.
+    fn write_streamed(
+        &mut self,
+        record: ResultRecord,
+        _count: Option<u64>,
+        chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    ) -> Result<()> {
+        let mut preview = Preview::new(self.size);
+        chunks.flatten().for_each(|n| preview.push(n));
+        self.print(&preview, &record, None)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.out.flush().context("Failed to print the preview")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Preview, PreviewSink};
+    use crate::pipeline::{jobs_source, Pipeline};
+    use crate::LineNumbers;
+
+    #[test]
+    fn test_preview() {
+        let mut preview = Preview::new(3);
+        (1..=333).map(|n| n * 3).for_each(|n| preview.push(n));
+        assert_eq!(preview.line(999, None), "999:3 6 9 … 993 996 999 (count=333)");
+        let mut short = Preview::new(3);
+        [3, 5, 6, 9].into_iter().for_each(|n| short.push(n));
+        assert_eq!(short.line(10, None), "10:3 5 6 9 (count=4)");
+        assert_eq!(Preview::new(3).line(2, None), "2:(count=0)");
+        assert_eq!(Preview::new(3).line(16, Some(7)), "16:(count=7)");
+    }
+
+    #[test]
+    fn test_preview_sink() {
+        let mut out = Vec::new();
+        let jobs = vec![LineNumbers { a: 3, b: 5, end: 100 }, LineNumbers { a: 2, b: 3, end: 6 }];
+        Pipeline::builder(jobs_source(jobs)).build().run(&mut PreviewSink::new(&mut out, 2)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "100:3 5 … 99 100 (count=47)\n6:2 3 4 6 (count=4)\n");
+    }
+}
@@ -0,0 +1,312 @@
+//! Per-line timing of the phases of a run, for `--profile`.
+//!
+//! The pipeline times parsing and generation, and [`ProfiledFormatter`]
+//! splits the output of each record into formatting it and writing it out.
+//! The collected spans can be summed up into a table or written in the Chrome
+//! trace event format, viewable in `chrome://tracing` or Perfetto.
+
+use std::cell::Cell;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Result;
+
+use crate::color;
+use crate::formatter::OutputFormatter;
+use crate::ResultRecord;
+
+const SUMMARY_HEADER: [&str; 6] = ["phase", "spans", "total", "share", "mean", "slowest line"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Reading a job from the input.
+    Parse,
+    /// Turning a job into a record.
+    Generate,
+    /// Serializing a record.
+    Format,
+    /// Handing the serialized record to the output.
+    Write,
+}
+
+impl Phase {
+    const ALL: [Phase; 4] = [Phase::Parse, Phase::Generate, Phase::Format, Phase::Write];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Parse => "parse",
+            Phase::Generate => "generate",
+            Phase::Format => "format",
+            Phase::Write => "write",
+        }
+    }
+}
+
+/// One timed piece of work.
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub phase: Phase,
+    /// The input line, or `None` for work not tied to a line such as headers.
+    pub index: Option<usize>,
+    /// A small number identifying the thread that did the work.
+    pub thread: usize,
+    /// Offset from the creation of the profiler.
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+fn thread_number() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(1);
+    thread_local!(static NUMBER: Cell<usize> = const { Cell::new(0) });
+    NUMBER.with(|number| {
+        if number.get() == 0 {
+            number.set(NEXT.fetch_add(1, Ordering::Relaxed));
+        }
+        number.get()
+    })
+}
+
+/// Collects spans from any number of threads.
+pub struct Profiler {
+    epoch: Instant,
+    /// The wall-clock time of `epoch`.
+    started_at: SystemTime,
+    spans: Mutex<Vec<Span>>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler {
+            epoch: Instant::now(),
+            started_at: SystemTime::now(),
+            spans: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Profiler {
+    /// Runs `f`, recording the time it took as a span of `phase`.
+    pub fn time<T>(&self, phase: Phase, index: Option<usize>, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let value = f();
+        let span = Span {
+            phase,
+            index,
+            thread: thread_number(),
+            start: start - self.epoch,
+            duration: start.elapsed(),
+        };
+        self.spans.lock().unwrap().push(span);
+        value
+    }
+
+    /// When the profiler was created, which span starts are offsets from.
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    pub fn spans(&self) -> Vec<Span> {
+        self.spans.lock().unwrap().clone()
+    }
+
+    /// A table with the total, mean and slowest line of every phase.
+    ///
+    /// With several worker threads the totals can add up to more than the
+    /// wall-clock time, since the phases overlap.
+    pub fn summary(&self) -> String {
+        let [phase, spans, total, share, mean, slowest] = SUMMARY_HEADER;
+        let mut table = format!("{:<10} {:>8} {:>12} {:>7} {:>12} {:>12}\n", phase, spans, total, share, mean, slowest);
+        for [phase, spans, total, share, mean, slowest] in self.summary_rows() {
+            let _ = writeln!(table, "{:<10} {:>8} {:>12} {:>7} {:>12} {:>12}", phase, spans, total, share, mean, slowest);
+        }
+        table
+    }
+
+    /// The [`Profiler::summary`] framed with box-drawing characters, for
+    /// terminals.
+    pub fn boxed_summary(&self) -> String {
+        let rows: Vec<Vec<String>> = self.summary_rows().into_iter().map(Vec::from).collect();
+        color::boxed_table(&SUMMARY_HEADER, &rows, &[false, true, true, true, true, true])
+    }
+
+    fn summary_rows(&self) -> Vec<[String; 6]> {
+        let spans = self.spans();
+        let total: Duration = spans.iter().map(|span| span.duration).sum();
+        let mut rows = Vec::new();
+        for phase in Phase::ALL {
+            let spans: Vec<&Span> = spans.iter().filter(|span| span.phase == phase).collect();
+            let sum: Duration = spans.iter().map(|span| span.duration).sum();
+            let mean = sum.checked_div(spans.len() as u32).unwrap_or_default();
+            let share = if total.is_zero() { 0.0 } else { sum.as_secs_f64() / total.as_secs_f64() * 100.0 };
+            let slowest = spans
+                .iter()
+                .filter(|span| span.index.is_some())
+                .max_by_key(|span| span.duration)
+                .map(|span| format!("{} ({:.1?})", span.index.unwrap(), span.duration))
+                .unwrap_or_else(|| String::from("-"));
+            rows.push([
+                phase.name().to_string(),
+                spans.len().to_string(),
+                format!("{:.1?}", sum),
+                format!("{:.1}%", share),
+                format!("{:.1?}", mean),
+                slowest,
+            ]);
+        }
+        rows
+    }
+
+    /// Writes the spans as a Chrome trace: one complete (`"ph": "X"`) event
+    /// per span, in microseconds.
+    pub fn write_chrome_trace(&self, w: &mut dyn Write) -> Result<()> {
+        writeln!(w, "{{\"traceEvents\":[")?;
+        for (i, span) in self.spans().iter().enumerate() {
+            let separator = if i == 0 { "" } else { ",\n" };
+            let index = span.index.map_or_else(|| String::from("null"), |index| index.to_string());
+            write!(
+                w,
+                "{}{{\"name\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":{},\"args\":{{\"line\":{}}}}}",
+                separator,
+                span.phase.name(),
+                span.start.as_secs_f64() * 1e6,
+                span.duration.as_secs_f64() * 1e6,
+                span.thread,
+                index
+            )?;
+        }
+        writeln!(w, "\n],\"displayTimeUnit\":\"ms\"}}")?;
+        Ok(())
+    }
+}
+
+/// Wraps a formatter to time formatting and writing separately: each record
+/// is formatted into a buffer first, then written out in one piece.
+pub struct ProfiledFormatter {
+    inner: Box<dyn OutputFormatter>,
+    profiler: Arc<Profiler>,
+    buffer: Vec<u8>,
+}
+
+impl ProfiledFormatter {
+    pub fn new(inner: Box<dyn OutputFormatter>, profiler: Arc<Profiler>) -> Self {
+        ProfiledFormatter {
+            inner,
+            profiler,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn timed<F>(&mut self, w: &mut dyn Write, index: Option<usize>, format: F) -> Result<()>
+    where
+        F: FnOnce(&mut dyn OutputFormatter, &mut Vec<u8>) -> Result<()>,
+    {
+        self.buffer.clear();
+        let (inner, buffer) = (self.inner.as_mut(), &mut self.buffer);
+        self.profiler.time(Phase::Format, index, || format(inner, buffer))?;
+        self.profiler.time(Phase::Write, index, || w.write_all(&self.buffer))?;
+        Ok(())
+    }
+}
+
+impl OutputFormatter for ProfiledFormatter {
+    fn begin(&mut self, w: &mut dyn Write) -> Result<()> {
+        self.timed(w, None, |inner, buffer| inner.begin(buffer))
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        self.inner.resume()
+    }
+
+    fn annotate(&mut self) -> Result<()> {
+        self.inner.annotate()
+    }
+
+    fn group_by_divisor(&mut self) -> Result<()> {
+        self.inner.group_by_divisor()
+    }
+
+    fn format_threads(&mut self, threads: usize) {
+        self.inner.format_threads(threads)
+    }
+
+    fn checksum(&mut self) -> Result<()> {
+        self.inner.checksum()
+    }
+
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        self.timed(w, Some(r.index), |inner, buffer| inner.write_record(buffer, r))
+    }
+
+    /// Streamed records aren't buffered, so their formatting and writing
+    /// are timed together as formatting.
+    fn write_streamed(
+        &mut self,
+        w: &mut dyn Write,
+        r: &ResultRecord,
+        count: Option<u64>,
+        chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    ) -> Result<()> {
+        let inner = self.inner.as_mut();
+        self.profiler.time(Phase::Format, Some(r.index), || inner.write_streamed(w, r, count, chunks))
+    }
+
+    fn finish(&mut self, w: &mut dyn Write) -> Result<()> {
+        self.timed(w, None, |inner, buffer| inner.finish(buffer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{Phase, ProfiledFormatter, Profiler};
+    use crate::formatter::{FormatterRegistry, OutputFormatter};
+    use crate::{generate_record, json, LineNumbers, ResultRecord};
+
+    fn render(formatter: &mut dyn OutputFormatter, record: &ResultRecord) -> Vec<u8> {
+        let mut out = Vec::new();
+        formatter.begin(&mut out).unwrap();
+        formatter.write_record(&mut out, record).unwrap();
+        formatter.finish(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_profiled_formatter_keeps_output() {
+        let profiler = Arc::new(Profiler::default());
+        let registry = FormatterRegistry::with_builtins();
+        let record = generate_record(LineNumbers { a: 3, b: 5, end: 20 });
+        let plain = render(registry.create("json").unwrap().as_mut(), &record);
+        let mut wrapped = ProfiledFormatter::new(registry.create("json").unwrap(), profiler.clone());
+        let profiled = render(&mut wrapped, &record);
+        assert_eq!(profiled, plain);
+
+        let phases: Vec<_> = profiler.spans().iter().map(|span| (span.phase, span.index)).collect();
+        assert_eq!(phases[2..4], [(Phase::Format, Some(0)), (Phase::Write, Some(0))]);
+        assert_eq!(phases.len(), 6);
+    }
+
+    #[test]
+    fn test_summary_and_chrome_trace() {
+        let profiler = Profiler::default();
+        for index in 0..3 {
+            profiler.time(Phase::Generate, Some(index), || ());
+        }
+        let summary = profiler.summary();
+        assert!(summary.lines().any(|line| line.starts_with("generate") && line.contains(" 3 ")));
+        let boxed = profiler.boxed_summary();
+        assert!(boxed.starts_with("┌"));
+        assert!(boxed.lines().any(|line| line.starts_with("│ generate") && line.contains("│     3 │")));
+
+        let mut trace = Vec::new();
+        profiler.write_chrome_trace(&mut trace).unwrap();
+        let trace = json::parse(std::str::from_utf8(&trace).unwrap()).unwrap();
+        let events = trace.get("traceEvents").and_then(|events| events.as_array()).unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[2].get("name").and_then(|name| name.as_text()), Some("generate"));
+        assert_eq!(events[2].get("args").and_then(|args| args.get("line")).and_then(|line| line.as_text()), Some("2"));
+    }
+}
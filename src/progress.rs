@@ -0,0 +1,204 @@
+//! Progress of a run, for `--progress-format`.
+//!
+//! Progress is counted in numbers of the input's ranges: a run's total is
+//! the sum of the `end` of every line, and generating a line advances it by
+//! that line's `end`, block by block where the strategy tests numbers one
+//! by one. A [`ProgressReporter`] thread writes a snapshot every interval,
+//! either as a human readable line or as a JSON event for frontends that
+//! draw their own progress display.
+
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProgressFormat {
+    /// `line 3: 31.2% (1500000/4800000), eta 2.1s`
+    Text,
+    /// `{"line":3,"done":1500000,"total":4800000,"elapsed_ms":950,"eta_ms":2090}`
+    Json,
+}
+
+impl FromStr for ProgressFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "text" => Ok(ProgressFormat::Text),
+            "json" => Ok(ProgressFormat::Json),
+            _ => Err(anyhow!("Unknown progress format: {} (available: text, json)", name)),
+        }
+    }
+}
+
+/// Counters shared by the threads of a run.
+pub struct Progress {
+    total: u64,
+    done: AtomicU64,
+    line: AtomicUsize,
+    started: Instant,
+}
+
+/// The state of a run at one point in time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Snapshot {
+    /// The latest line started, counting from 1, or 0 before the first.
+    pub line: usize,
+    pub done: u64,
+    pub total: u64,
+    pub elapsed: Duration,
+    /// Extrapolated from the rate so far; `None` until something is done.
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    /// Progress of a run over `total` numbers.
+    pub fn new(total: u64) -> Self {
+        Progress {
+            total,
+            done: AtomicU64::new(0),
+            line: AtomicUsize::new(0),
+            started: Instant::now(),
+        }
+    }
+
+    /// Notes that the line at `index` of the input is being generated.
+    pub fn start_line(&self, index: usize) {
+        self.line.fetch_max(index + 1, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, numbers: u64) {
+        self.done.fetch_add(numbers, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let done = self.done.load(Ordering::Relaxed).min(self.total);
+        let elapsed = self.started.elapsed();
+        let eta = (done > 0).then(|| elapsed.mul_f64((self.total - done) as f64 / done as f64));
+        Snapshot {
+            line: self.line.load(Ordering::Relaxed),
+            done,
+            total: self.total,
+            elapsed,
+            eta,
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn format(&self, format: ProgressFormat) -> String {
+        match format {
+            ProgressFormat::Text => {
+                let percent = if self.total == 0 { 100.0 } else { self.done as f64 / self.total as f64 * 100.0 };
+                let eta = self.eta.map_or_else(|| String::from("unknown"), |eta| format!("{:.1?}", eta));
+                format!("line {}: {:.1}% ({}/{}), eta {}", self.line, percent, self.done, self.total, eta)
+            }
+            ProgressFormat::Json => {
+                let eta = self.eta.map_or_else(|| String::from("null"), |eta| eta.as_millis().to_string());
+                format!(
+                    "{{\"line\":{},\"done\":{},\"total\":{},\"elapsed_ms\":{},\"eta_ms\":{}}}",
+                    self.line,
+                    self.done,
+                    self.total,
+                    self.elapsed.as_millis(),
+                    eta
+                )
+            }
+        }
+    }
+}
+
+/// Writes a snapshot of a [`Progress`] every interval on its own thread,
+/// and a last one when finished.
+pub struct ProgressReporter {
+    stop: Sender<()>,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl ProgressReporter {
+    pub fn spawn(
+        progress: Arc<Progress>,
+        format: ProgressFormat,
+        interval: Duration,
+        mut out: Box<dyn Write + Send>,
+    ) -> ProgressReporter {
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::spawn(move || loop {
+            let last = match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => false,
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => true,
+            };
+            writeln!(out, "{}", progress.snapshot().format(format))?;
+            out.flush()?;
+            if last {
+                return Ok(());
+            }
+        });
+        ProgressReporter { stop, thread }
+    }
+
+    /// Writes the last snapshot and waits for the thread to end.
+    pub fn finish(self) -> Result<()> {
+        let _ = self.stop.send(());
+        self.thread
+            .join()
+            .map_err(|_| anyhow!("Progress reporter panicked"))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::{Progress, ProgressFormat, ProgressReporter};
+    use crate::json;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_snapshot_formats() {
+        let progress = Progress::new(4000);
+        assert_eq!(progress.snapshot().eta, None);
+        progress.start_line(2);
+        progress.start_line(0);
+        progress.advance(1000);
+        let snapshot = progress.snapshot();
+        assert_eq!((snapshot.line, snapshot.done, snapshot.total), (3, 1000, 4000));
+        assert!(snapshot.format(ProgressFormat::Text).starts_with("line 3: 25.0% (1000/4000), eta "));
+        let event = json::parse(&snapshot.format(ProgressFormat::Json)).unwrap();
+        assert_eq!(event.get("done").and_then(|done| done.as_text()), Some("1000"));
+        assert!(event.get("eta_ms").is_some());
+    }
+
+    #[test]
+    fn test_reporter_writes_a_last_event() {
+        let progress = Arc::new(Progress::new(10));
+        let out = Shared::default();
+        let reporter = ProgressReporter::spawn(progress.clone(), ProgressFormat::Json, Duration::from_secs(60), Box::new(out.clone()));
+        progress.advance(10);
+        reporter.finish().unwrap();
+        let written = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(written.lines().count(), 1);
+        assert!(written.starts_with("{\"line\":0,\"done\":10,\"total\":10,"));
+        assert!(written.trim_end().ends_with("\"eta_ms\":0}"));
+    }
+}
@@ -0,0 +1,290 @@
+//! The provenance of a result file, for `--provenance`: the version and
+//! git commit of the tool that wrote it, the effective configuration of the
+//! run (its arguments with every options file expanded), and the input it
+//! read, by path and SHA-256. It is written as the first line of `text`,
+//! `csv` and `ndjson` outputs, `#provenance {...}` in the text formats and
+//! `{"provenance":{...}}` in NDJSON, which the result readers skip, and
+//! logged by `--audit`. `provenance` reads it back and checks it against
+//! the running tool and the input.
+
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::audit;
+use crate::formatter::OutputFormatter;
+use crate::json::{self, Value};
+use crate::sha256::{hex, sha256_reader};
+use crate::ResultRecord;
+
+/// The formats a provenance header can be written in.
+pub const FORMATS: [&str; 3] = ["text", "csv", "ndjson"];
+
+/// The git commit the tool was built from, `-dirty` when the tree had
+/// changes, or empty when it wasn't built from a git checkout.
+pub const GIT_HASH: &str = env!("MULTIPLES_GIT_HASH");
+
+const TEXT_PREFIX: &str = "#provenance ";
+const NDJSON_PREFIX: &str = "{\"provenance\":";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Provenance {
+    pub version: String,
+    pub git: String,
+    /// The working directory relative paths of the run are relative to.
+    pub dir: PathBuf,
+    /// The arguments of the run with options files expanded.
+    pub config: Vec<String>,
+    pub input: PathBuf,
+    /// The lower-case hex SHA-256 of the input as the run started, if it
+    /// could be read.
+    pub input_sha256: Option<String>,
+}
+
+impl Provenance {
+    /// The provenance of a run of this build on `input` with the arguments
+    /// `config`, hashing the input.
+    pub fn of(input: &Path, config: &[String]) -> Self {
+        Provenance {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git: GIT_HASH.to_string(),
+            dir: env::current_dir().unwrap_or_default(),
+            config: config.to_vec(),
+            input: input.to_path_buf(),
+            input_sha256: hash_file(input).ok(),
+        }
+    }
+
+    /// The fields as the members of a JSON object, without the braces, so
+    /// that the run log can add its own.
+    pub fn json_fields(&self) -> String {
+        let config: Vec<String> = self.config.iter().map(|arg| json::quote(arg)).collect();
+        format!(
+            "\"version\":{},\"git\":{},\"dir\":{},\"config\":[{}],\"input\":{},\"input_sha256\":{}",
+            json::quote(&self.version),
+            json::quote(&self.git),
+            json::quote(&self.dir.to_string_lossy()),
+            config.join(","),
+            json::quote(&self.input.to_string_lossy()),
+            self.input_sha256.as_deref().map_or_else(|| String::from("null"), json::quote)
+        )
+    }
+
+    fn from_json(value: &Value) -> Result<Self> {
+        let text = |key: &str| value.get(key).and_then(Value::as_text).ok_or_else(|| anyhow!("Missing {}", key));
+        let config = value.get("config").and_then(Value::as_array).ok_or_else(|| anyhow!("Missing config"))?;
+        Ok(Provenance {
+            version: text("version")?.to_string(),
+            git: text("git")?.to_string(),
+            dir: PathBuf::from(text("dir")?),
+            config: config.iter().map(|arg| arg.as_text().unwrap_or_default().to_string()).collect(),
+            input: PathBuf::from(text("input")?),
+            input_sha256: text("input_sha256").ok().map(str::to_string),
+        })
+    }
+
+    /// The header line of an output in `format`.
+    fn header(&self, format: &str) -> String {
+        match format {
+            "ndjson" => format!("{}{{{}}}}}\n", NDJSON_PREFIX, self.json_fields()),
+            _ => format!("{}{{{}}}\n", TEXT_PREFIX, self.json_fields()),
+        }
+    }
+
+    /// Parses a header `line`, which starts with one of the prefixes.
+    fn parse_header(line: &str) -> Result<Self> {
+        let line = line.trim_end();
+        let value = match line.strip_prefix(TEXT_PREFIX) {
+            Some(object) => json::parse(object),
+            None => json::parse(line)
+                .and_then(|value| value.get("provenance").cloned().ok_or_else(|| anyhow!("Missing provenance"))),
+        };
+        value.and_then(|value| Provenance::from_json(&value)).context("Invalid provenance header")
+    }
+
+    /// Wraps `formatter` to start every output in `format` with this
+    /// header.
+    pub fn apply(&self, format: &str, formatter: Box<dyn OutputFormatter>) -> Box<dyn OutputFormatter> {
+        Box::new(HeaderFormatter {
+            header: self.header(format),
+            inner: formatter,
+        })
+    }
+
+    /// Checks that this build wrote the file and that its input still has
+    /// the hash it had, returning a line per check and whether they all
+    /// passed.
+    pub fn verify(&self) -> (Vec<String>, bool) {
+        let mut lines = Vec::new();
+        let mut passed = true;
+        let mut check = |what: &str, ok: bool, detail: String| {
+            lines.push(format!("{} {}: {}", if ok { "ok     " } else { "FAILED " }, what, detail));
+            passed &= ok;
+        };
+        let version = env!("CARGO_PKG_VERSION");
+        check("version", self.version == version, format!("{} (this build: {})", self.version, version));
+        let git = |hash: &str| if hash.is_empty() { String::from("unknown") } else { hash.to_string() };
+        // Uncommitted changes aren't told apart by the hash.
+        let detail = match self.git.ends_with("-dirty") {
+            true => format!("{}, built with uncommitted changes", self.git),
+            false => format!("{} (this build: {})", git(&self.git), git(GIT_HASH)),
+        };
+        check("git", self.git == GIT_HASH && !self.git.is_empty() && !self.git.ends_with("-dirty"), detail);
+        let input = self.dir.join(&self.input);
+        match (&self.input_sha256, hash_file(&input)) {
+            (Some(expected), Ok(actual)) => {
+                check("input", *expected == actual, format!("{:?} has SHA-256 {}", input, actual));
+            }
+            (Some(_), Err(err)) => check("input", false, format!("{:?} can't be read: {}", input, err)),
+            (None, _) => check("input", false, format!("{:?} couldn't be read when the file was written", input)),
+        }
+        (lines, passed)
+    }
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "version: {}", self.version)?;
+        writeln!(f, "git: {}", if self.git.is_empty() { "unknown" } else { &self.git })?;
+        writeln!(f, "dir: {}", self.dir.display())?;
+        writeln!(f, "config: {}", self.config.join(" "))?;
+        writeln!(f, "input: {}", self.input.display())?;
+        write!(f, "input sha256: {}", self.input_sha256.as_deref().unwrap_or("unknown"))
+    }
+}
+
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    let file = audit::open(path)?;
+    sha256_reader(BufReader::new(file)).map(|hash| hex(&hash))
+}
+
+/// Takes the provenance header off the start of `reader`, if it has one.
+pub fn strip<'a>(mut reader: Box<dyn BufRead + Send + 'a>) -> Result<(Option<Provenance>, Box<dyn BufRead + Send + 'a>)> {
+    // Records never start with either prefix, and binary formats are left
+    // alone as only what is buffered is looked at.
+    let buffered = reader.fill_buf().context("Failed to read the results")?;
+    if !buffered.starts_with(TEXT_PREFIX.as_bytes()) && !buffered.starts_with(NDJSON_PREFIX.as_bytes()) {
+        return Ok((None, reader));
+    }
+    let mut line = String::new();
+    reader.read_line(&mut line).context("Failed to read the results")?;
+    Ok((Some(Provenance::parse_header(&line)?), reader))
+}
+
+/// Reads the provenance header of the result file at `path`.
+pub fn read(path: &Path) -> Result<Provenance> {
+    let file = audit::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
+    match strip(Box::new(BufReader::new(file)))? {
+        (Some(provenance), _) => Ok(provenance),
+        (None, _) => bail!("{:?} has no provenance header; outputs carry one when written with --provenance", path),
+    }
+}
+
+/// A formatter starting its output with a header line.
+struct HeaderFormatter {
+    header: String,
+    inner: Box<dyn OutputFormatter>,
+}
+
+impl OutputFormatter for HeaderFormatter {
+    fn begin(&mut self, w: &mut dyn Write) -> Result<()> {
+        w.write_all(self.header.as_bytes())?;
+        self.inner.begin(w)
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        self.inner.resume()
+    }
+
+    fn annotate(&mut self) -> Result<()> {
+        self.inner.annotate()
+    }
+
+    fn group_by_divisor(&mut self) -> Result<()> {
+        self.inner.group_by_divisor()
+    }
+
+    fn format_threads(&mut self, threads: usize) {
+        self.inner.format_threads(threads)
+    }
+
+    fn checksum(&mut self) -> Result<()> {
+        self.inner.checksum()
+    }
+
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        self.inner.write_record(w, r)
+    }
+
+    fn write_streamed(
+        &mut self,
+        w: &mut dyn Write,
+        r: &ResultRecord,
+        count: Option<u64>,
+        chunks: &mut dyn Iterator<Item = Vec<u32>>,
+    ) -> Result<()> {
+        self.inner.write_streamed(w, r, count, chunks)
+    }
+
+    fn finish(&mut self, w: &mut dyn Write) -> Result<()> {
+        self.inner.finish(w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Cursor, Read};
+
+    use super::{strip, Provenance};
+    use crate::formatter::FormatterRegistry;
+    use crate::pipeline::{jobs_source, FormatterSink, Pipeline};
+    use crate::reader::ResultReaderRegistry;
+    use crate::LineNumbers;
+
+    fn written(format: &str, provenance: &Provenance) -> Vec<u8> {
+        let formatter = provenance.apply(format, FormatterRegistry::with_builtins().create(format).unwrap());
+        let mut sink = FormatterSink::new(Vec::new(), formatter);
+        Pipeline::builder(jobs_source(vec![LineNumbers { a: 2, b: 3, end: 10 }])).build().run(&mut sink).unwrap();
+        sink.into_inner()
+    }
+
+    #[test]
+    fn test_header() {
+        let dir = std::env::temp_dir().join(format!("multiples_provenance_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.txt");
+        std::fs::write(&input, "abc").unwrap();
+        let provenance = Provenance::of(&input, &[String::from("--mode"), String::from("xor")]);
+        assert_eq!(provenance.input_sha256.as_deref(), Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"));
+        for format in ["text", "csv", "ndjson"] {
+            let out = written(format, &provenance);
+            let (read, mut rest) = strip(Box::new(Cursor::new(out.clone()))).unwrap();
+            assert_eq!(read.as_ref(), Some(&provenance), "{}", format);
+            let mut first = String::new();
+            rest.read_line(&mut first).unwrap();
+            assert!(first.starts_with("10:") || first.starts_with("index,") || first.starts_with("{\"schema\""), "{}", first);
+            let records = ResultReaderRegistry::with_builtins().get(format).unwrap().read(strip(Box::new(Cursor::new(out))).unwrap().1);
+            assert_eq!(records.map(|record| record.unwrap().result.count()).collect::<Vec<_>>(), [7]);
+        }
+        let (checks, _) = provenance.verify();
+        assert!(checks[2].starts_with("ok      input"));
+        std::fs::write(&input, "abd").unwrap();
+        let (checks, passed) = provenance.verify();
+        assert!(!passed && checks[2].starts_with("FAILED  input"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Files without a header, NDJSON records included, are read as they are.
+    #[test]
+    fn test_no_header() {
+        for text in ["10:2 3 4\n", "{\"schema\":1,\"index\":0}\n", "#provenanc\n", ""] {
+            let (read, mut rest) = strip(Box::new(Cursor::new(text.as_bytes().to_vec()))).unwrap();
+            let mut left = String::new();
+            rest.read_to_string(&mut left).unwrap();
+            assert_eq!((read, left.as_str()), (None, text));
+        }
+        assert!(strip(Box::new(Cursor::new(b"#provenance {}\n".to_vec()))).is_err());
+    }
+}
@@ -0,0 +1,63 @@
+//! Human readable quantities for reports: byte sizes with decimal units
+//! and counts with thousands separators.
+
+/// Formats a byte count with a decimal unit, matching the unit suffixes
+/// accepted in number literals: `999 B`, `1.5 kB`, `2.0 GB`.
+pub fn format_bytes(bytes: u64) -> String {
+    let units = ["kB", "MB", "GB", "TB", "PB", "EB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while value >= 999.95 && unit + 1 < units.len() {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, units[unit])
+}
+
+/// Formats a count with `,` between groups of three digits:
+/// `133,333,334`.
+pub fn format_count<N: Into<u128>>(count: N) -> String {
+    let digits = count.into().to_string();
+    let mut text = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            text.push(',');
+        }
+        text.push(digit);
+    }
+    text
+}
+
+/// A byte size both rounded and exact, e.g. `≈ 1.2 GB (1,234,567,890 bytes)`.
+/// Sizes below a kilobyte are exact to begin with.
+pub fn describe_bytes(bytes: u64) -> String {
+    if bytes < 1000 {
+        return format_bytes(bytes);
+    }
+    format!("≈ {} ({} bytes)", format_bytes(bytes), format_count(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{describe_bytes, format_bytes, format_count};
+
+    #[test]
+    fn test_quantities() {
+        assert_eq!(format_bytes(999), "999 B");
+        assert_eq!(format_bytes(1500), "1.5 kB");
+        assert_eq!(format_bytes(999_999), "1.0 MB");
+        assert_eq!(format_bytes(2_000_000_000), "2.0 GB");
+
+        assert_eq!(format_count(0u32), "0");
+        assert_eq!(format_count(999u32), "999");
+        assert_eq!(format_count(1000u32), "1,000");
+        assert_eq!(format_count(133_333_334u64), "133,333,334");
+        assert_eq!(format_count(u128::MAX).len(), 39 + 12);
+
+        assert_eq!(describe_bytes(12), "12 B");
+        assert_eq!(describe_bytes(1_234_567_890), "≈ 1.2 GB (1,234,567,890 bytes)");
+    }
+}
@@ -0,0 +1,111 @@
+//! A small seeded pseudo-random number generator and random jobs drawn
+//! from it, for `gen-input`.
+//!
+//! The generator is SplitMix64: not suitable for anything secret, but fast,
+//! and the same seed gives the same sequence on every platform, which is what
+//! reproducible test data needs.
+
+use crate::LineNumbers;
+
+/// SplitMix64, see the module documentation.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A uniformly distributed value in `0.0..1.0`.
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A uniformly distributed value in `low..=high`.
+    pub fn range(&mut self, low: u64, high: u64) -> u64 {
+        assert!(low <= high, "empty range {}..={}", low, high);
+        let span = high - low;
+        if span == u64::MAX {
+            return self.next_u64();
+        }
+        // Drawing from the largest multiple of the span's length avoids
+        // favouring small values.
+        let len = span + 1;
+        let zone = u64::MAX - (u64::MAX - len + 1) % len;
+        loop {
+            let value = self.next_u64();
+            if value <= zone {
+                return low + value % len;
+            }
+        }
+    }
+}
+
+/// Bounds of the jobs drawn by [`random_jobs`].
+#[derive(Clone, Copy, Debug)]
+pub struct JobBounds {
+    /// Divisors are drawn from `1..=max_divisor`.
+    pub max_divisor: u32,
+    /// Ends are drawn from `1..=max_end`.
+    pub max_end: u32,
+}
+
+/// `lines` jobs drawn uniformly within `bounds`, the same for every `seed`.
+pub fn random_jobs(seed: u64, lines: usize, bounds: JobBounds) -> impl Iterator<Item = LineNumbers> {
+    let mut rng = Rng::new(seed);
+    let mut draw = move |max: u32| rng.range(1, max as u64) as u32;
+    (0..lines).map(move |_| LineNumbers {
+        a: draw(bounds.max_divisor),
+        b: draw(bounds.max_divisor),
+        end: draw(bounds.max_end),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{random_jobs, JobBounds, Rng};
+
+    #[test]
+    fn test_rng_is_reproducible_and_in_range() {
+        let first: Vec<u64> = (0..5).scan(Rng::new(7), |rng, _| Some(rng.next_u64())).collect();
+        let second: Vec<u64> = (0..5).scan(Rng::new(7), |rng, _| Some(rng.next_u64())).collect();
+        assert_eq!(first, second);
+        assert_ne!(first, (0..5).scan(Rng::new(8), |rng, _| Some(rng.next_u64())).collect::<Vec<_>>());
+        // The first output of SplitMix64 seeded with 0.
+        assert_eq!(Rng::new(0).next_u64(), 0xe220_a839_7b1d_cdaf);
+
+        let mut rng = Rng::new(1);
+        let mut seen = [false; 6];
+        for _ in 0..1000 {
+            let value = rng.range(3, 8);
+            assert!((3..=8).contains(&value));
+            seen[value as usize - 3] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+        assert_eq!(rng.range(5, 5), 5);
+        assert!((0..1000).all(|_| (0.0..1.0).contains(&rng.unit())));
+        rng.range(0, u64::MAX);
+    }
+
+    #[test]
+    fn test_random_jobs_respect_bounds() {
+        let bounds = JobBounds {
+            max_divisor: 10,
+            max_end: 1000,
+        };
+        let jobs: Vec<_> = random_jobs(7, 200, bounds).collect();
+        assert_eq!(jobs.len(), 200);
+        assert!(jobs.iter().all(|job| (1..=10).contains(&job.a) && (1..=10).contains(&job.b) && (1..=1000).contains(&job.end)));
+        assert_eq!(jobs, random_jobs(7, 200, bounds).collect::<Vec<_>>());
+    }
+}
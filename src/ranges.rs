@@ -0,0 +1,272 @@
+//! The `auto` output format, which writes the multiples of every record
+//! either as a flat list or as ranges, whichever is shorter.
+//!
+//! A range is a run of at least three multiples with the same distance
+//! between them, written `first..last/step`, or `first..last` for a step
+//! of 1. The multiples of 2 up to 100 are the single range `2..100/2`,
+//! those of 3 or 5 no shorter than the list. Each record is one line,
+//! headed by the representation it was written in:
+//!
+//! ```text
+//! 0 2 4 100 ranges:2..100/2
+//! 1 3 5 20 list:3 5 6 9 10 12 15 18 20
+//! 2 3 5 1000000 count:466667
+//! ```
+//!
+//! The file ends with a `#` line comparing the size of the chosen
+//! representations with writing every record as a list.
+
+use std::io::{BufRead, Write};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::formatter::OutputFormatter;
+use crate::quantity::format_bytes;
+use crate::reader::{RecordIter, ResultReader};
+use crate::{LineNumbers, ResultNumbers, ResultRecord};
+
+/// How the multiples of a record are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Representation {
+    List,
+    Ranges,
+}
+
+impl Representation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Representation::List => "list",
+            Representation::Ranges => "ranges",
+        }
+    }
+}
+
+/// Writes `numbers` as space separated values.
+pub fn encode_list(numbers: &[u32]) -> String {
+    numbers.iter().map(u32::to_string).collect::<Vec<_>>().join(" ")
+}
+
+/// Writes `numbers` as space separated ranges and single values. Runs of
+/// equal numbers, as in `multiset` mode, are written value by value.
+pub fn encode_ranges(numbers: &[u32]) -> String {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    while start < numbers.len() {
+        let step = numbers.get(start + 1).map(|&next| next as i64 - numbers[start] as i64);
+        let mut end = start + 1;
+        if let Some(step) = step.filter(|&step| step != 0) {
+            while end < numbers.len() && numbers[end] as i64 - numbers[end - 1] as i64 == step {
+                end += 1;
+            }
+        }
+        if end - start < 3 {
+            parts.push(numbers[start].to_string());
+            start += 1;
+            continue;
+        }
+        let (first, last) = (numbers[start], numbers[end - 1]);
+        match step.unwrap_or_default().unsigned_abs() {
+            1 => parts.push(format!("{}..{}", first, last)),
+            step => parts.push(format!("{}..{}/{}", first, last, step)),
+        }
+        start = end;
+    }
+    parts.join(" ")
+}
+
+/// Reads back the output of [`encode_list`] or [`encode_ranges`]; ranges
+/// with a `last` below `first` run downward.
+pub fn decode(representation: Representation, text: &str) -> Result<Vec<u32>> {
+    let number = |text: &str| text.parse::<u32>().map_err(|_| anyhow!("invalid number {:?}", text));
+    let mut numbers = Vec::new();
+    for part in text.split_whitespace() {
+        let Some((first, rest)) = part.split_once("..").filter(|_| representation == Representation::Ranges) else {
+            numbers.push(number(part)?);
+            continue;
+        };
+        let (last, step) = rest.split_once('/').unwrap_or((rest, "1"));
+        let (first, last, step) = (number(first)?, number(last)?, number(step)?);
+        if step == 0 || first.abs_diff(last) % step != 0 {
+            bail!("invalid range {:?}", part);
+        }
+        if first <= last {
+            numbers.extend((first..=last).step_by(step as usize));
+        } else {
+            numbers.extend((last..=first).rev().step_by(step as usize));
+        }
+    }
+    Ok(numbers)
+}
+
+/// The shorter representation of `numbers` and its text, preferring the
+/// list when both are as long.
+pub fn choose(numbers: &[u32]) -> (Representation, String) {
+    let list = encode_list(numbers);
+    let ranges = encode_ranges(numbers);
+    if ranges.len() < list.len() {
+        (Representation::Ranges, ranges)
+    } else {
+        (Representation::List, list)
+    }
+}
+
+/// How much the `auto` format saved over writing every list.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RepresentationStats {
+    pub lists: u64,
+    pub ranges: u64,
+    /// Bytes of the multiples as written.
+    pub written: u64,
+    /// Bytes they would have taken as lists.
+    pub as_lists: u64,
+}
+
+impl RepresentationStats {
+    /// The closing `#` line of an `auto` file.
+    pub fn summary(&self) -> String {
+        let saved = match self.as_lists {
+            0 => 0.0,
+            total => 100.0 * (total - self.written) as f64 / total as f64,
+        };
+        format!(
+            "# {} as ranges, {} as lists: {} of multiples instead of {} as lists ({:.1}% smaller)",
+            self.ranges,
+            self.lists,
+            format_bytes(self.written),
+            format_bytes(self.as_lists),
+            saved
+        )
+    }
+}
+
+/// The `auto` format, see the [module documentation](self).
+#[derive(Default)]
+pub struct AutoFormatter {
+    stats: RepresentationStats,
+}
+
+impl OutputFormatter for AutoFormatter {
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        write!(w, "{} {} {} {} ", r.index, r.job.a, r.job.b, r.result.end)?;
+        if let Some(count) = r.result.count {
+            writeln!(w, "count:{}", count)?;
+            return Ok(());
+        }
+        let (representation, text) = choose(&r.result.numbers);
+        match representation {
+            Representation::List => self.stats.lists += 1,
+            Representation::Ranges => self.stats.ranges += 1,
+        }
+        self.stats.written += text.len() as u64;
+        self.stats.as_lists += encode_list(&r.result.numbers).len() as u64;
+        writeln!(w, "{}:{}", representation.name(), text)?;
+        Ok(())
+    }
+
+    fn finish(&mut self, w: &mut dyn Write) -> Result<()> {
+        if self.stats.lists + self.stats.ranges > 0 {
+            writeln!(w, "{}", self.stats.summary())?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads `auto` files, skipping their `#` lines.
+pub struct AutoReader;
+
+impl ResultReader for AutoReader {
+    fn read<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> RecordIter<'a> {
+        let lines = reader.lines().enumerate();
+        let records = lines.filter_map(|(line_num, line)| {
+            let line_num = line_num + 1;
+            match line.with_context(|| format!("Failed to read line {}", line_num)) {
+                Ok(line) if line.starts_with('#') || line.trim().is_empty() => None,
+                Ok(line) => Some(parse_line(&line).with_context(|| format!("Record {}", line_num))),
+                Err(err) => Some(Err(err)),
+            }
+        });
+        Box::new(records)
+    }
+}
+
+fn parse_line(line: &str) -> Result<ResultRecord> {
+    let mut fields = line.splitn(5, ' ');
+    let mut field = |name: &str| fields.next().ok_or_else(|| anyhow!("missing {}", name));
+    let number = |text: &str, name: &str| text.parse::<u32>().map_err(|_| anyhow!("invalid {} {:?}", name, text));
+    let index = field("index")?.parse::<usize>().map_err(|_| anyhow!("invalid index"))?;
+    let (a, b, end) = (number(field("a")?, "a")?, number(field("b")?, "b")?, number(field("end")?, "end")?);
+    let (kind, payload) = field("numbers")?.split_once(':').ok_or_else(|| anyhow!("missing ':'"))?;
+    let (numbers, count) = match kind {
+        "list" => (decode(Representation::List, payload)?, None),
+        "ranges" => (decode(Representation::Ranges, payload)?, None),
+        "count" => (Vec::new(), Some(payload.parse().map_err(|_| anyhow!("invalid count {:?}", payload))?)),
+        _ => bail!("unknown representation {:?} (expected list, ranges or count)", kind),
+    };
+    Ok(ResultRecord {
+        index,
+        job: LineNumbers { a, b, end },
+        result: ResultNumbers { end, numbers, count },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{choose, decode, encode_ranges, AutoFormatter, AutoReader, Representation};
+    use crate::formatter::OutputFormatter;
+    use crate::formula::Mode;
+    use crate::reader::ResultReader;
+    use crate::{count_record, generate_record, LineNumbers, ResultRecord};
+
+    #[test]
+    fn test_encode_and_decode_ranges() {
+        assert_eq!(encode_ranges(&[2, 4, 6, 8, 10]), "2..10/2");
+        assert_eq!(encode_ranges(&[3, 5, 6, 7, 8, 12]), "3 5..8 12");
+        assert_eq!(encode_ranges(&[10, 8, 6, 5]), "10..6/2 5");
+        assert_eq!(encode_ranges(&[6, 6, 6, 7]), "6 6 6 7");
+        assert_eq!(encode_ranges(&[]), "");
+        for numbers in [vec![2, 4, 6, 8, 10], vec![3, 5, 6, 7, 8, 12], vec![10, 8, 6, 5], vec![6, 6, 6, 7]] {
+            assert_eq!(decode(Representation::Ranges, &encode_ranges(&numbers)).unwrap(), numbers);
+        }
+        assert!(decode(Representation::Ranges, "2..9/2").is_err());
+        assert!(decode(Representation::Ranges, "2..8/0").is_err());
+        assert!(decode(Representation::List, "2..8").is_err());
+
+        let evens = generate_record(LineNumbers { a: 2, b: 4, end: 100 }).result.numbers;
+        assert_eq!(choose(&evens), (Representation::Ranges, String::from("2..100/2")));
+        assert_eq!(choose(&[3, 5, 6]).0, Representation::List);
+    }
+
+    #[test]
+    fn test_auto_round_trip() {
+        let mut records = vec![
+            generate_record(LineNumbers { a: 2, b: 4, end: 100 }),
+            generate_record(LineNumbers { a: 3, b: 5, end: 20 }),
+            count_record(LineNumbers { a: 3, b: 5, end: 1_000_000 }, Mode::Or),
+        ];
+        for (index, record) in records.iter_mut().enumerate() {
+            record.index = index;
+        }
+        let mut formatter = AutoFormatter::default();
+        let mut out = Vec::new();
+        formatter.begin(&mut out).unwrap();
+        for record in &records {
+            formatter.write_record(&mut out, record).unwrap();
+        }
+        formatter.finish(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(
+            text.lines().collect::<Vec<_>>(),
+            [
+                "0 2 4 100 ranges:2..100/2",
+                "1 3 5 20 list:3 5 6 9 10 12 15 18 20",
+                "2 3 5 1000000 count:466667",
+                "# 1 as ranges, 1 as lists: 30 B of multiples instead of 168 B as lists (82.1% smaller)",
+            ]
+        );
+        let read: Vec<ResultRecord> = AutoReader.read(Box::new(Cursor::new(text))).collect::<anyhow::Result<_>>().unwrap();
+        assert_eq!(read, records);
+        assert!(AutoReader.read(Box::new(Cursor::new("0 1 2 3 runs:1\n"))).next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,518 @@
+//! Readers turning the tool's own result files back into records, so they
+//! can be merged, converted or sorted without regenerating them. Records
+//! written with `--checksums` are checked against their
+//! [checksum](crate::crc32) as they are read.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::crc32::record_checksum;
+use crate::errors::{CORRUPTED_RECORD, UNSUPPORTED_BY_FORMAT};
+use crate::formatter::OUTPUT_SCHEMA;
+use crate::json::{self, Value};
+use crate::provenance;
+use crate::{LineNumbers, ResultNumbers, ResultRecord};
+
+pub type RecordIter<'a> = Box<dyn Iterator<Item = Result<ResultRecord>> + Send + 'a>;
+
+/// Parses a result stream written by the formatter of the same name.
+pub trait ResultReader: Sync {
+    fn read<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> RecordIter<'a>;
+}
+
+/// Result readers keyed by format name, plus the file extensions each of
+/// them is picked for automatically.
+pub struct ResultReaderRegistry {
+    readers: BTreeMap<String, Box<dyn ResultReader>>,
+    extensions: BTreeMap<String, String>,
+}
+
+impl ResultReaderRegistry {
+    pub fn new() -> Self {
+        ResultReaderRegistry {
+            readers: BTreeMap::new(),
+            extensions: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a registry containing readers for all built-in formats.
+    pub fn with_builtins() -> Self {
+        let mut registry = ResultReaderRegistry::new();
+        registry.register("text", &["txt"], TextReader);
+        registry.register("json", &["json"], JsonReader);
+        registry.register("ndjson", &["ndjson", "jsonl"], NdjsonReader);
+        registry.register("csv", &["csv"], CsvReader);
+        registry.register("store", &["db"], crate::store::StoreReader);
+        registry.register("auto", &[], crate::ranges::AutoReader);
+        registry.register("delta", &["delta"], crate::delta::DeltaReader);
+        registry.register("roaring", &["roaring"], crate::roaring::RoaringReader);
+        registry
+    }
+
+    pub fn register<R>(&mut self, name: &str, extensions: &[&str], reader: R)
+    where
+        R: ResultReader + 'static,
+    {
+        self.readers.insert(name.to_string(), Box::new(reader));
+        for extension in extensions {
+            self.extensions.insert(extension.to_string(), name.to_string());
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<&dyn ResultReader> {
+        self.readers.get(name).map(|reader| reader.as_ref()).ok_or_else(|| {
+            anyhow!(
+                "No reader for result format: {} (available: {})",
+                name,
+                self.readers.keys().map(String::as_str).collect::<Vec<_>>().join(", ")
+            )
+        })
+    }
+
+    /// Returns the format name for `path`: `format` if given, otherwise the
+    /// one registered for its extension, falling back to `text`.
+    pub fn detect<'a>(&'a self, path: &Path, format: Option<&'a str>) -> &'a str {
+        format.unwrap_or_else(|| self.format_of(path).unwrap_or("text"))
+    }
+
+    /// The format registered for the extension of `path`, if any.
+    pub fn format_of(&self, path: &Path) -> Option<&str> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.extensions.get(&ext.to_ascii_lowercase()))
+            .map(String::as_str)
+    }
+
+    /// Opens `path` and reads its records in the given or detected format,
+    /// skipping its [provenance](crate::provenance) header if it has one.
+    pub fn open(&self, path: &Path, format: Option<&str>) -> Result<RecordIter<'static>> {
+        let reader = self.get(self.detect(path, format))?;
+        let file = File::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
+        let (_, records) = provenance::strip(Box::new(BufReader::new(file))).with_context(|| format!("{:?}", path))?;
+        Ok(reader.read(records))
+    }
+}
+
+impl Default for ResultReaderRegistry {
+    fn default() -> Self {
+        ResultReaderRegistry::with_builtins()
+    }
+}
+
+/// The schema version `path` was written with in `format`, one of the
+/// [`VERSIONED_FORMATS`](crate::formatter::VERSIONED_FORMATS): that of its
+/// first record for the JSON formats, 0 for JSON written before outputs
+/// were versioned, and the one of its magic for the binary formats.
+pub fn schema_version(path: &Path, format: &str) -> Result<u32> {
+    let mut file = BufReader::new(File::open(path).with_context(|| format!("Failed to open file: {:?}", path))?);
+    let first = match format {
+        "json" => {
+            let mut text = String::new();
+            file.read_to_string(&mut text).with_context(|| format!("Failed to read {:?}", path))?;
+            json::parse(&text).context("Invalid JSON results")?.as_array().and_then(|items| items.first().cloned())
+        }
+        "ndjson" => {
+            let (_, records) = provenance::strip(Box::new(file))?;
+            match records.lines().map_while(Result::ok).find(|line| !line.trim().is_empty()) {
+                Some(line) => Some(json::parse(&line).context("Invalid NDJSON results")?),
+                None => None,
+            }
+        }
+        "store" | "delta" | "roaring" => {
+            let mut magic = [0; 8];
+            file.read_exact(&mut magic).with_context(|| format!("{:?} is too short for {} results", path, format))?;
+            return match magic[6] {
+                digit @ b'0'..=b'9' => Ok((digit - b'0') as u32),
+                _ => bail!("{:?} doesn't start with the magic of {} results", path, format),
+            };
+        }
+        _ => bail!("{} results carry no schema version", format),
+    };
+    let schema = first.as_ref().and_then(|record| record.get("schema")).and_then(Value::as_text);
+    match schema {
+        Some(text) => text.parse().map_err(|_| anyhow!("Invalid schema version: {}", text)),
+        None => Ok(0),
+    }
+}
+
+/// Fails when records read as `input` can't be written as `output`: text
+/// results don't keep the divisors of their lines, which every other format
+/// holds.
+pub fn check_conversion(input: &str, output: &str) -> Result<()> {
+    if input == "text" && output != "text" {
+        return Err(UNSUPPORTED_BY_FORMAT.error(format!(
+            "Text results don't keep the divisors {} output holds; use a json, ndjson or csv result file of the same run",
+            output
+        )));
+    }
+    Ok(())
+}
+
+/// Reads all records of a result file.
+pub fn read_records(path: &Path, format: Option<&str>) -> Result<Vec<ResultRecord>> {
+    ResultReaderRegistry::with_builtins()
+        .open(path, format)?
+        .collect::<Result<_>>()
+        .with_context(|| format!("Failed to read results from {:?}", path))
+}
+
+fn parse_field(text: &str, name: &str, line_num: usize) -> Result<u32> {
+    text.trim()
+        .parse()
+        .map_err(|_| anyhow!("Record {}: invalid {} {:?}", line_num, name, text))
+}
+
+fn record(index: usize, a: u32, b: u32, end: u32, numbers: Vec<u32>, count: Option<u64>) -> ResultRecord {
+    ResultRecord {
+        index,
+        job: LineNumbers { a, b, end },
+        result: ResultNumbers { end, numbers, count },
+    }
+}
+
+/// Checks a record's stored count against its numbers. A nonzero count
+/// without numbers marks a count-only record and is returned.
+fn check_count(count: Option<u32>, numbers: &[u32], line_num: usize) -> Result<Option<u64>> {
+    match count {
+        Some(count) if count > 0 && numbers.is_empty() => Ok(Some(count as u64)),
+        Some(count) if count as usize != numbers.len() => bail!(
+            "Record {}: count {} doesn't match its {} numbers",
+            line_num,
+            count,
+            numbers.len()
+        ),
+        _ => Ok(None),
+    }
+}
+
+/// Checks the `stored` checksum of `record`, read as record `line_num`, if
+/// it has one.
+fn check_checksum(record: ResultRecord, stored: Option<&str>, line_num: usize) -> Result<ResultRecord> {
+    let Some(stored) = stored else {
+        return Ok(record);
+    };
+    let computed = record_checksum(&record);
+    let valid = stored.len() == 8 && u32::from_str_radix(stored, 16).ok() == Some(computed);
+    if !valid {
+        return Err(CORRUPTED_RECORD.error(format!(
+            "Record {} (index {}, a={}, b={}, end={}) is corrupted: its crc32 is {:?}, but its contents have {:08x}",
+            line_num, record.index, record.job.a, record.job.b, record.result.end, stored, computed
+        )));
+    }
+    Ok(record)
+}
+
+/// Reads `end:n1 n2 n3` lines. The text format doesn't store the divisors,
+/// so they are read back as 0, nor the input position, so the position in
+/// the file is used instead.
+pub struct TextReader;
+
+impl ResultReader for TextReader {
+    fn read<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> RecordIter<'a> {
+        Box::new(reader.lines().enumerate().map(|(line_num, line)| {
+            let line_num = line_num + 1;
+            let line = line.with_context(|| format!("Failed to read line {}", line_num))?;
+            let (end, numbers) = line
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Record {}: missing ':'", line_num))?;
+            let end = parse_field(end, "end", line_num)?;
+            // Annotated multiples carry their matched divisors as `n[a,b]`.
+            let numbers = numbers
+                .split_whitespace()
+                .map(|n| parse_field(n.split('[').next().unwrap_or(n), "number", line_num))
+                .collect::<Result<_>>()?;
+            Ok(record(line_num - 1, 0, 0, end, numbers, None))
+        }))
+    }
+}
+
+fn record_from_json(value: &Value, line_num: usize) -> Result<ResultRecord> {
+    let field = |name: &str| -> Result<Option<u32>> {
+        match value.get(name) {
+            None => Ok(None),
+            Some(field) => {
+                let text = field
+                    .as_text()
+                    .ok_or_else(|| anyhow!("Record {}: {} is not a number", line_num, name))?;
+                parse_field(text, name, line_num).map(Some)
+            }
+        }
+    };
+    if let Some(schema) = field("schema")?.filter(|&schema| schema > OUTPUT_SCHEMA) {
+        bail!("Record {}: schema version {} is newer than {}, the latest this build reads", line_num, schema, OUTPUT_SCHEMA);
+    }
+    let end = field("end")?.ok_or_else(|| anyhow!("Record {}: missing end", line_num))?;
+    let numbers = match value.get("numbers") {
+        // Count-only records have a count but no numbers.
+        None if value.get("count").is_some() => &[],
+        numbers => numbers
+            .and_then(Value::as_array)
+            .ok_or_else(|| anyhow!("Record {}: missing numbers array", line_num))?,
+    };
+    let numbers = numbers
+        .iter()
+        .map(|n| {
+            n.get("n")
+                .unwrap_or(n)
+                .as_text()
+                .ok_or_else(|| anyhow!("Record {}: non-numeric multiple", line_num))
+                .and_then(|text| parse_field(text, "number", line_num))
+        })
+        .collect::<Result<Vec<u32>>>()?;
+    let count = check_count(field("count")?, &numbers, line_num)?;
+    let index = match field("index")? {
+        Some(index) => index as usize,
+        None => line_num - 1,
+    };
+    let read = record(index, field("a")?.unwrap_or(0), field("b")?.unwrap_or(0), end, numbers, count);
+    check_checksum(read, value.get("crc32").map(|crc| crc.as_text().unwrap_or_default()), line_num)
+}
+
+/// Reads the JSON array written by the `json` formatter.
+pub struct JsonReader;
+
+impl ResultReader for JsonReader {
+    fn read<'a>(&self, mut reader: Box<dyn BufRead + Send + 'a>) -> RecordIter<'a> {
+        let mut text = String::new();
+        if let Err(err) = reader.read_to_string(&mut text) {
+            return Box::new(std::iter::once(Err(anyhow!(err).context("Failed to read JSON results"))));
+        }
+        let items = match json::parse(&text).context("Invalid JSON results") {
+            Ok(Value::Array(items)) => items,
+            Ok(_) => return Box::new(std::iter::once(Err(anyhow!("JSON results must be an array")))),
+            Err(err) => return Box::new(std::iter::once(Err(err))),
+        };
+        Box::new(
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(index, item)| record_from_json(&item, index + 1)),
+        )
+    }
+}
+
+/// Reads one JSON record object per line.
+pub struct NdjsonReader;
+
+impl ResultReader for NdjsonReader {
+    fn read<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> RecordIter<'a> {
+        Box::new(reader.lines().enumerate().map(|(line_num, line)| {
+            let line = line.with_context(|| format!("Failed to read line {}", line_num + 1))?;
+            let value = json::parse(&line).with_context(|| format!("Record {} is not valid JSON", line_num + 1))?;
+            record_from_json(&value, line_num + 1)
+        }))
+    }
+}
+
+/// Reads `index,a,b,end,count,numbers` rows after the header row, with a
+/// `crc32` column when the header has one. Files whose header lacks the
+/// `index` column are read by row position.
+pub struct CsvReader;
+
+impl ResultReader for CsvReader {
+    fn read<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> RecordIter<'a> {
+        let (mut has_index, mut has_checksum) = (true, false);
+        Box::new(reader.lines().enumerate().filter_map(move |(line_num, line)| {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => return Some(Err(anyhow!(err).context(format!("Failed to read line {}", line_num + 1)))),
+            };
+            if line_num == 0 && (line.starts_with("index,") || line.starts_with("a,b,end")) {
+                has_index = line.starts_with("index,");
+                has_checksum = line.ends_with(",crc32");
+                return None;
+            }
+            Some(record_from_csv(&line, line_num + 1, has_index, has_checksum))
+        }))
+    }
+}
+
+fn record_from_csv(line: &str, line_num: usize, has_index: bool, has_checksum: bool) -> Result<ResultRecord> {
+    let (line, checksum) = match has_checksum {
+        true => line.rsplit_once(',').map(|(line, crc)| (line, Some(crc))).unwrap_or((line, Some(""))),
+        false => (line, None),
+    };
+    let columns = if has_index { 6 } else { 5 };
+    let mut fields: Vec<&str> = line.splitn(columns, ',').collect();
+    if fields.len() != columns {
+        bail!("Record {}: expected {} columns", line_num, columns);
+    }
+    let index = if has_index {
+        parse_field(fields.remove(0), "index", line_num)? as usize
+    } else {
+        line_num - 2
+    };
+    let numbers = fields[4]
+        .split_whitespace()
+        .map(|n| parse_field(n, "number", line_num))
+        .collect::<Result<Vec<u32>>>()?;
+    let count = check_count(Some(parse_field(fields[3], "count", line_num)?), &numbers, line_num)?;
+    let read = record(
+        index,
+        parse_field(fields[0], "a", line_num)?,
+        parse_field(fields[1], "b", line_num)?,
+        parse_field(fields[2], "end", line_num)?,
+        numbers,
+        count,
+    );
+    check_checksum(read, checksum, line_num)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+
+    use super::{check_conversion, schema_version, ResultReaderRegistry};
+    use crate::errors::{code_of, CORRUPTED_RECORD};
+    use crate::formatter::FormatterRegistry;
+    use crate::formula::Mode;
+    use crate::{count_record, generate_record, LineNumbers, ResultRecord};
+
+    fn round_trip(format: &str) -> Vec<ResultRecord> {
+        let mut records = vec![
+            generate_record(LineNumbers { a: 2, b: 3, end: 10 }),
+            generate_record(LineNumbers { a: 7, b: 9, end: 5 }),
+            count_record(LineNumbers { a: 3, b: 5, end: 100 }, Mode::Or),
+        ];
+        records[0].index = 4;
+        let mut formatter = FormatterRegistry::with_builtins().create(format).unwrap();
+        let mut out = Vec::new();
+        formatter.begin(&mut out).unwrap();
+        for record in &records {
+            formatter.write_record(&mut out, record).unwrap();
+        }
+        formatter.finish(&mut out).unwrap();
+        ResultReaderRegistry::with_builtins()
+            .get(format)
+            .unwrap()
+            .read(Box::new(Cursor::new(out)))
+            .collect::<anyhow::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_structured_formats() {
+        for format in ["json", "ndjson", "csv"] {
+            let records = round_trip(format);
+            assert_eq!(records.len(), 3, "{}", format);
+            assert_eq!(records[0].job, LineNumbers { a: 2, b: 3, end: 10 }, "{}", format);
+            assert_eq!(records[0].result.numbers, vec![2, 3, 4, 6, 8, 9, 10], "{}", format);
+            assert!(records[1].result.numbers.is_empty(), "{}", format);
+            assert_eq!((records[0].index, records[1].index), (4, 0), "{}", format);
+            assert_eq!(records[1].result.count, None, "{}", format);
+            assert_eq!(records[2].result.count, Some(47), "{}", format);
+        }
+    }
+
+    #[test]
+    fn test_read_annotated_records() {
+        let registry = ResultReaderRegistry::with_builtins();
+        let read = |format: &str, text: &'static str| -> Vec<u32> {
+            let mut records = registry.get(format).unwrap().read(Box::new(Cursor::new(text)));
+            records.next().unwrap().unwrap().result.numbers
+        };
+        assert_eq!(read("text", "15:5[b] 9[a] 15[a,b]\n"), vec![5, 9, 15]);
+        assert_eq!(
+            read("ndjson", "{\"end\":9,\"numbers\":[{\"n\":3,\"matched\":[\"a\"]},{\"n\":9,\"matched\":[\"a\"]}]}\n"),
+            vec![3, 9]
+        );
+    }
+
+    #[test]
+    fn test_csv_reader_accepts_files_without_index() {
+        let text = "a,b,end,count,numbers\n2,3,4,3,2 3 4\n5,7,7,2,5 7\n";
+        let records: Vec<ResultRecord> = ResultReaderRegistry::with_builtins()
+            .get("csv")
+            .unwrap()
+            .read(Box::new(Cursor::new(text)))
+            .collect::<anyhow::Result<_>>()
+            .unwrap();
+        assert_eq!(records[1].index, 1);
+        assert_eq!(records[1].job, LineNumbers { a: 5, b: 7, end: 7 });
+    }
+
+    #[test]
+    fn test_text_reader_drops_divisors() {
+        let records = round_trip("text");
+        assert_eq!(records[0].job, LineNumbers { a: 0, b: 0, end: 10 });
+        assert_eq!(records[0].result.numbers, vec![2, 3, 4, 6, 8, 9, 10]);
+        let err = check_conversion("text", "ndjson").unwrap_err();
+        assert_eq!(err.to_string(), "Text results don't keep the divisors ndjson output holds; use a json, ndjson or csv result file of the same run");
+        assert!(check_conversion("text", "text").is_ok() && check_conversion("csv", "json").is_ok());
+    }
+
+    #[test]
+    fn test_schema_versions() {
+        let dir = std::env::temp_dir().join(format!("multiples_schema_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, contents: &[u8]| {
+            let path = dir.join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        };
+        let old = write("old.json", b"[{\"index\":0,\"a\":2,\"b\":3,\"end\":4,\"count\":3,\"numbers\":[2,3,4]}]");
+        assert_eq!(schema_version(&old, "json").unwrap(), 0);
+        let current = write("current.ndjson", b"\n{\"schema\":1,\"end\":4,\"numbers\":[2,3,4]}\n");
+        assert_eq!(schema_version(&current, "ndjson").unwrap(), 1);
+        assert_eq!(schema_version(&write("results.db", crate::store::MAGIC), "store").unwrap(), 1);
+        assert!(schema_version(&write("results.txt", b"4:2 3 4\n"), "text").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Records of a later schema aren't guessed at.
+        let text = "{\"schema\":2,\"end\":4,\"numbers\":[2,3,4]}\n";
+        let mut records = ResultReaderRegistry::with_builtins().get("ndjson").unwrap().read(Box::new(Cursor::new(text)));
+        let err = records.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "Record 1: schema version 2 is newer than 1, the latest this build reads");
+    }
+
+    #[test]
+    fn test_checksums() {
+        let records = [
+            generate_record(LineNumbers { a: 2, b: 3, end: 10 }),
+            count_record(LineNumbers { a: 3, b: 5, end: 100 }, Mode::Or),
+        ];
+        let registry = ResultReaderRegistry::with_builtins();
+        for format in ["json", "ndjson", "csv"] {
+            let mut formatter = FormatterRegistry::with_builtins().create(format).unwrap();
+            formatter.checksum().unwrap();
+            let mut out = Vec::new();
+            formatter.begin(&mut out).unwrap();
+            for record in &records {
+                formatter.write_record(&mut out, record).unwrap();
+            }
+            formatter.finish(&mut out).unwrap();
+            let read = |text: Vec<u8>| registry.get(format).unwrap().read(Box::new(Cursor::new(text))).collect::<anyhow::Result<Vec<_>>>();
+            assert_eq!(read(out.clone()).unwrap(), records, "{}", format);
+
+            // A flipped bit in a multiple of the first record.
+            let text = String::from_utf8(out).unwrap();
+            let corrupted = text.replacen("2,3,4,6", "2,3,5,6", 1).replacen("2 3 4 6", "2 3 5 6", 1);
+            assert_ne!(corrupted, text);
+            let err = read(corrupted.into_bytes()).unwrap_err();
+            assert_eq!(code_of(&err), Some(&CORRUPTED_RECORD), "{}", format);
+            let record = if format == "csv" { 2 } else { 1 };
+            assert!(err.to_string().starts_with(&format!("Record {} (index 0, a=2, b=3, end=10) is corrupted: its crc32 is", record)), "{}", err);
+        }
+        // Files without checksums are read as they were.
+        let text = "index,a,b,end,count,numbers\n0,2,3,4,3,2 3 4\n";
+        assert_eq!(registry.get("csv").unwrap().read(Box::new(Cursor::new(text))).count(), 1);
+        let err = registry.get("ndjson").unwrap().read(Box::new(Cursor::new("{\"end\":4,\"numbers\":[2,3,4],\"crc32\":7}\n"))).next();
+        assert_eq!(code_of(&err.unwrap().unwrap_err()), Some(&CORRUPTED_RECORD));
+    }
+
+    #[test]
+    fn test_reader_rejects_count_mismatch() {
+        let text = "index,a,b,end,count,numbers\n0,2,3,4,2,2 3 4\n";
+        let result: anyhow::Result<Vec<ResultRecord>> = ResultReaderRegistry::with_builtins()
+            .get("csv")
+            .unwrap()
+            .read(Box::new(Cursor::new(text)))
+            .collect();
+        assert!(result.is_err());
+    }
+}
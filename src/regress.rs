@@ -0,0 +1,210 @@
+//! Golden-file regression cases for `regress`: folders holding an input, the
+//! options to run it with and the output it is expected to give, so that
+//! the behaviour a project relies on can be checked after every upgrade.
+//!
+//! ```text
+//! cases/
+//!   example/
+//!     input.txt
+//!     options.toml
+//!     expected.json
+//! ```
+//!
+//! Outputs in a format with a result reader are compared record by record,
+//! so that e.g. the spacing of JSON doesn't matter. Other outputs, such as
+//! the array formats, are compared line by line.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::reader::ResultReaderRegistry;
+use crate::ResultRecord;
+
+/// One case folder.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Case {
+    /// The name of the folder.
+    pub name: String,
+    pub input: PathBuf,
+    pub options: PathBuf,
+    pub expected: PathBuf,
+}
+
+impl Case {
+    /// Where the output of a run is written: `actual.<ext>` next to the
+    /// expected file, with the same extension.
+    pub fn actual(&self) -> PathBuf {
+        let mut name = String::from("actual");
+        if let Some(ext) = self.expected.extension() {
+            name.push('.');
+            name.push_str(&ext.to_string_lossy());
+        }
+        self.expected.with_file_name(name)
+    }
+}
+
+/// The case folders directly below `dir`, sorted by name. Every folder must
+/// hold an `input.txt`, an `options.toml` and exactly one `expected.*`.
+pub fn find_cases(dir: &Path) -> Result<Vec<Case>> {
+    let mut cases = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read cases from {:?}", dir))? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let mut expected = Vec::new();
+        for entry in fs::read_dir(&path).with_context(|| format!("Failed to read {:?}", path))? {
+            let file = entry?.path();
+            if file.file_stem().is_some_and(|stem| stem == "expected") {
+                expected.push(file);
+            }
+        }
+        let expected = match expected.len() {
+            1 => expected.pop().unwrap(),
+            0 => bail!("Case {}: no expected.* file in {:?}", name, path),
+            _ => bail!("Case {}: more than one expected.* file in {:?}", name, path),
+        };
+        for file in ["input.txt", "options.toml"] {
+            if !path.join(file).is_file() {
+                bail!("Case {}: no {} in {:?}", name, file, path);
+            }
+        }
+        cases.push(Case {
+            name,
+            input: path.join("input.txt"),
+            options: path.join("options.toml"),
+            expected,
+        });
+    }
+    if cases.is_empty() {
+        bail!("No case folders in {:?}", dir);
+    }
+    cases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(cases)
+}
+
+/// Compares the output of a run with the expected one, in the format of the
+/// expected file's extension. Returns the first difference, or `None` if
+/// there is none.
+pub fn compare(expected: &Path, actual: &Path) -> Result<Option<String>> {
+    let readers = ResultReaderRegistry::with_builtins();
+    match readers.format_of(expected) {
+        Some(format) => {
+            let read = |path: &Path| -> Result<Vec<ResultRecord>> {
+                readers
+                    .open(path, Some(format))?
+                    .collect::<Result<_>>()
+                    .with_context(|| format!("Failed to read results from {:?}", path))
+            };
+            Ok(compare_records(&read(expected)?, &read(actual)?))
+        }
+        None => {
+            let read = |path: &Path| fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path));
+            Ok(compare_lines(&read(expected)?, &read(actual)?))
+        }
+    }
+}
+
+/// The first difference between two lists of records.
+fn compare_records(expected: &[ResultRecord], actual: &[ResultRecord]) -> Option<String> {
+    for (i, (want, got)) in expected.iter().zip(actual).enumerate() {
+        let record = i + 1;
+        if want.job != got.job || want.result.end != got.result.end {
+            return Some(format!(
+                "record {}: expected a:{} b:{} end:{}, got a:{} b:{} end:{}",
+                record, want.job.a, want.job.b, want.result.end, got.job.a, got.job.b, got.result.end
+            ));
+        }
+        if want.result.count() != got.result.count() {
+            return Some(format!(
+                "record {} (end {}): expected {} multiples, got {}",
+                record,
+                want.result.end,
+                want.result.count(),
+                got.result.count()
+            ));
+        }
+        let numbers = want.result.numbers.iter().zip(&got.result.numbers);
+        if let Some((at, (n, m))) = numbers.enumerate().find(|(_, (n, m))| n != m) {
+            return Some(format!(
+                "record {} (end {}): multiple {} is {} instead of {}",
+                record,
+                want.result.end,
+                at + 1,
+                m,
+                n
+            ));
+        }
+        if want.index != got.index {
+            return Some(format!("record {}: expected line {} of the input, got line {}", record, want.index + 1, got.index + 1));
+        }
+    }
+    match expected.len().cmp(&actual.len()) {
+        std::cmp::Ordering::Equal => None,
+        _ => Some(format!("expected {} records, got {}", expected.len(), actual.len())),
+    }
+}
+
+/// The first differing line of two outputs.
+fn compare_lines(expected: &str, actual: &str) -> Option<String> {
+    let (mut want, mut got) = (expected.lines(), actual.lines());
+    let mut line = 0;
+    loop {
+        line += 1;
+        match (want.next(), got.next()) {
+            (None, None) => return None,
+            (Some(a), Some(b)) if a == b => continue,
+            (Some(a), Some(b)) => return Some(format!("line {}: expected {:?}, got {:?}", line, a, b)),
+            (Some(_), None) => return Some(format!("line {}: expected more output", line)),
+            (None, Some(b)) => return Some(format!("line {}: unexpected {:?}", line, b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{compare, compare_lines, find_cases};
+
+    #[test]
+    fn test_find_and_compare_cases() {
+        let dir = std::env::temp_dir().join(format!("multiples_regress_{}", std::process::id()));
+        let case = dir.join("basic");
+        fs::create_dir_all(&case).unwrap();
+        fs::write(case.join("input.txt"), "3 5 10\n").unwrap();
+        fs::write(case.join("options.toml"), "output-format = \"json\"\n").unwrap();
+        fs::write(
+            case.join("expected.json"),
+            "[\n  {\"index\": 0, \"a\": 3, \"b\": 5, \"end\": 10, \"count\": 5, \"numbers\": [3, 5, 6, 9, 10]}\n]\n",
+        )
+        .unwrap();
+        let cases = find_cases(&dir).unwrap();
+        assert_eq!(cases.len(), 1);
+        assert_eq!(cases[0].name, "basic");
+        assert_eq!(cases[0].actual(), case.join("actual.json"));
+
+        // The same records, written without spaces.
+        let actual = cases[0].actual();
+        fs::write(&actual, "[{\"index\":0,\"a\":3,\"b\":5,\"end\":10,\"count\":5,\"numbers\":[3,5,6,9,10]}]\n").unwrap();
+        assert_eq!(compare(&cases[0].expected, &actual).unwrap(), None);
+        fs::write(&actual, "[{\"index\":0,\"a\":3,\"b\":5,\"end\":10,\"count\":5,\"numbers\":[3,5,6,8,10]}]\n").unwrap();
+        assert_eq!(
+            compare(&cases[0].expected, &actual).unwrap().unwrap(),
+            "record 1 (end 10): multiple 4 is 8 instead of 9"
+        );
+        fs::write(&actual, "[]\n").unwrap();
+        assert_eq!(compare(&cases[0].expected, &actual).unwrap().unwrap(), "expected 1 records, got 0");
+
+        fs::write(case.join("expected.rs"), "").unwrap();
+        assert!(find_cases(&dir).unwrap_err().to_string().contains("more than one"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(compare_lines("a\nb\n", "a\nb"), None);
+        assert_eq!(compare_lines("a\nb\n", "a\nc\n").unwrap(), "line 2: expected \"b\", got \"c\"");
+        assert_eq!(compare_lines("a\n", "a\nb\n").unwrap(), "line 2: unexpected \"b\"");
+    }
+}
@@ -0,0 +1,189 @@
+//! Normalization of hand-written inputs, for `--fix`.
+//!
+//! Every line is split on whitespace and commas and rewritten as canonical
+//! `a b end` text with `a <= b`. Lines repeating an earlier job and lines
+//! that don't hold three numbers are dropped. Every change is recorded, so
+//! a report can tell what was done to which line.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::BufRead;
+
+use anyhow::{Context, Result};
+
+use crate::number;
+use crate::LineNumbers;
+
+/// A change made to one input line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fix {
+    /// Runs of spaces or tabs, or leading and trailing ones, were collapsed.
+    Whitespace,
+    /// Commas separating the numbers were replaced by spaces.
+    Commas,
+    /// A trailing comma was removed.
+    TrailingComma,
+    /// Numbers written as other literals, e.g. `1k`, were written in decimal.
+    Literals,
+    /// `a` was larger than `b`, so the two were swapped.
+    Swapped,
+    /// The line was empty and was removed.
+    Blank,
+    /// The line repeated the job of the given earlier line and was removed.
+    Duplicate(usize),
+    /// The line didn't hold three numbers and was removed.
+    Invalid(String),
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fix::Whitespace => write!(f, "collapsed whitespace"),
+            Fix::Commas => write!(f, "replaced commas by spaces"),
+            Fix::TrailingComma => write!(f, "removed trailing comma"),
+            Fix::Literals => write!(f, "wrote numbers in decimal"),
+            Fix::Swapped => write!(f, "swapped a and b"),
+            Fix::Blank => write!(f, "removed blank line"),
+            Fix::Duplicate(line) => write!(f, "removed duplicate of line {}", line),
+            Fix::Invalid(reason) => write!(f, "removed: {}", reason),
+        }
+    }
+}
+
+/// The outcome of [`repair`]: the jobs to keep, in input order, and the
+/// changes made, keyed by 1-based input line.
+#[derive(Debug, Default)]
+pub struct Repair {
+    pub jobs: Vec<LineNumbers>,
+    pub fixes: Vec<(usize, Fix)>,
+}
+
+impl Repair {
+    /// The jobs as canonical `a b end` lines.
+    pub fn canonical(&self) -> String {
+        self.jobs
+            .iter()
+            .map(|job| format!("{} {} {}\n", job.a, job.b, job.end))
+            .collect()
+    }
+
+    /// One `line N: change` line per change.
+    pub fn report(&self) -> String {
+        self.fixes
+            .iter()
+            .map(|(line, fix)| format!("line {}: {}\n", line, fix))
+            .collect()
+    }
+}
+
+/// Works out the fixes of one line and its job, if it has one.
+fn repair_line(line: &str, fixes: &mut Vec<Fix>) -> Option<LineNumbers> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() {
+        fixes.push(Fix::Blank);
+        return None;
+    }
+    let (body, trailing) = match trimmed.strip_suffix(',') {
+        Some(body) => (body.trim_end(), true),
+        None => (trimmed, false),
+    };
+    let tokens: Vec<&str> = body.split(|c: char| c == ',' || c.is_whitespace()).filter(|t| !t.is_empty()).collect();
+    let numbers: Vec<u32> = match tokens.iter().map(|token| number::parse_u32(token)).collect::<Result<_>>() {
+        Ok(numbers) => numbers,
+        Err(err) => {
+            fixes.push(Fix::Invalid(format!("{:#}", err)));
+            return None;
+        }
+    };
+    if numbers.len() != 3 {
+        fixes.push(Fix::Invalid(format!("{} numbers instead of 3", numbers.len())));
+        return None;
+    }
+
+    if body.contains(',') {
+        fixes.push(Fix::Commas);
+    }
+    if trailing {
+        fixes.push(Fix::TrailingComma);
+    }
+    let spaced = body.replace(',', " ");
+    if trimmed.len() != line.len() || spaced.split(' ').any(str::is_empty) || spaced.contains(|c: char| c.is_whitespace() && c != ' ') {
+        fixes.push(Fix::Whitespace);
+    }
+    if tokens.iter().zip(&numbers).any(|(token, n)| *token != n.to_string()) {
+        fixes.push(Fix::Literals);
+    }
+    let (mut a, mut b) = (numbers[0], numbers[1]);
+    if a > b {
+        (a, b) = (b, a);
+        fixes.push(Fix::Swapped);
+    }
+    Some(LineNumbers { a, b, end: numbers[2] })
+}
+
+/// Normalizes the lines of `reader`, see the module documentation.
+pub fn repair(reader: impl BufRead) -> Result<Repair> {
+    let mut repair = Repair::default();
+    let mut seen = HashMap::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line_num = index + 1;
+        let line = line.with_context(|| format!("Failed to read line {}", line_num))?;
+        let mut fixes = Vec::new();
+        let job = repair_line(&line, &mut fixes);
+        if let Some(job) = job {
+            match seen.get(&job) {
+                Some(&first) => fixes = vec![Fix::Duplicate(first)],
+                None => {
+                    seen.insert(job, line_num);
+                    repair.jobs.push(job);
+                }
+            }
+        }
+        repair.fixes.extend(fixes.into_iter().map(|fix| (line_num, fix)));
+    }
+    Ok(repair)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{repair, Fix};
+    use crate::LineNumbers;
+
+    #[test]
+    fn test_repair_normalizes_lines() {
+        let input = "3 5 100\n  3\t5   100 \n\n5,3,100,\n7 2 1k\n4 6\n2 7 30\n";
+        let repaired = repair(input.as_bytes()).unwrap();
+        assert_eq!(repaired.canonical(), "3 5 100\n2 7 1000\n2 7 30\n");
+        assert_eq!(repaired.jobs[1], LineNumbers { a: 2, b: 7, end: 1000 });
+        assert_eq!(
+            repaired.fixes,
+            vec![
+                (2, Fix::Duplicate(1)),
+                (3, Fix::Blank),
+                (4, Fix::Duplicate(1)),
+                (5, Fix::Literals),
+                (5, Fix::Swapped),
+                (6, Fix::Invalid(String::from("2 numbers instead of 3"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repair_reports_each_fix() {
+        let repaired = repair("9,\t4, 50,\nthree 5 10\n".as_bytes()).unwrap();
+        assert_eq!(repaired.canonical(), "4 9 50\n");
+        let report = repaired.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(
+            lines[..4],
+            [
+                "line 1: replaced commas by spaces",
+                "line 1: removed trailing comma",
+                "line 1: collapsed whitespace",
+                "line 1: swapped a and b",
+            ]
+        );
+        assert!(lines[4].starts_with("line 2: removed: Invalid number literal"));
+        assert_eq!(lines.len(), 5);
+    }
+}
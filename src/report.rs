@@ -0,0 +1,139 @@
+//! What a pipeline run came to, see [`Pipeline::run`](crate::pipeline::Pipeline::run):
+//! how many records it wrote, the warnings noted on the way, the lines that
+//! failed when it was told to [keep going](crate::pipeline::PipelineBuilder::keep_going)
+//! and where its time went.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::cancel::Status;
+
+/// Something worth telling about a line that was generated anyway, such as
+/// a degenerate line or one the generator simplified.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    /// The input line, counting from 1, if the warning is about one.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "Line {}: {}", line, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Where the warnings of a run are noted, shared between the sources and
+/// stages that note them and the pipeline reporting them. Clones share the
+/// warnings.
+#[derive(Clone, Debug, Default)]
+pub struct Warnings(Arc<Mutex<Vec<Warning>>>);
+
+impl Warnings {
+    pub fn push(&self, line: Option<usize>, message: String) {
+        self.0.lock().unwrap().push(Warning { line, message });
+    }
+
+    /// The warnings noted so far, which are noted no more.
+    pub fn take(&self) -> Vec<Warning> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+/// A line that failed and was left out of the output.
+#[derive(Debug)]
+pub struct LineError {
+    /// The position of the line's job in the source, as in the `index` of
+    /// records.
+    pub index: usize,
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+/// Where the time of a run went. Generating is summed over the workers, so
+/// it exceeds the elapsed time on several of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timings {
+    pub elapsed: Duration,
+    pub generating: Duration,
+    /// In the sink, writing records.
+    pub writing: Duration,
+}
+
+#[derive(Debug)]
+pub struct RunReport {
+    /// The records passed to the sink.
+    pub records: usize,
+    pub warnings: Vec<Warning>,
+    pub errors: Vec<LineError>,
+    pub timings: Timings,
+    pub status: Status,
+}
+
+impl RunReport {
+    /// Whether every line made it into the output.
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty() && self.status == Status::Complete
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Warning, Warnings};
+    use crate::cancel::Status;
+    use crate::pipeline::{jobs_source, CollectSink, Pipeline};
+    use crate::LineNumbers;
+
+    #[test]
+    fn test_warnings() {
+        let warnings = Warnings::default();
+        warnings.clone().push(Some(3), String::from("both divisors are 7"));
+        warnings.push(None, String::from("no lines"));
+        let taken = warnings.take();
+        assert_eq!(taken[0].to_string(), "Line 3: both divisors are 7");
+        assert_eq!(taken[1], Warning { line: None, message: String::from("no lines") });
+        assert!(warnings.take().is_empty());
+    }
+
+    #[test]
+    fn test_report() {
+        let jobs = vec![LineNumbers { a: 3, b: 5, end: 100 }, LineNumbers { a: 0, b: 5, end: 100 }, LineNumbers { a: 2, b: 3, end: 10 }];
+        let warnings = Warnings::default();
+        warnings.push(Some(1), String::from("a warning"));
+        let mut sink = CollectSink::default();
+        let report = Pipeline::builder(jobs_source(jobs.clone()))
+            .generator(|job| match job.a {
+                0 => Err(anyhow::anyhow!("Line 2: a is 0")),
+                _ => Ok(crate::generate_record(job)),
+            })
+            .keep_going()
+            .warnings(warnings)
+            .build()
+            .run(&mut sink)
+            .unwrap();
+        assert_eq!((report.records, sink.records.len(), report.status), (2, 2, Status::Complete));
+        assert_eq!(report.warnings[0].to_string(), "Line 1: a warning");
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!((report.errors[0].index, report.errors[0].to_string()), (1, String::from("Line 2: a is 0")));
+        assert!(!report.is_complete());
+
+        // Without keep_going, the first failed line fails the run.
+        let run = Pipeline::builder(jobs_source(jobs))
+            .generator(|job| match job.a {
+                0 => Err(anyhow::anyhow!("Line 2: a is 0")),
+                _ => Ok(crate::generate_record(job)),
+            })
+            .build()
+            .run(&mut CollectSink::default());
+        assert!(run.is_err());
+    }
+}
@@ -0,0 +1,531 @@
+//! Roaring bitmaps for the `roaring` output format and the `bitmap` command.
+//!
+//! A bitmap splits its values by their upper 16 bits into containers, each
+//! holding the lower 16 bits either as a sorted array, while it has at most
+//! 4096 values, or as a bitmap of 65536 bits. Dense ranges such as the
+//! multiples of 2 then take a bit per number, sparse ones two bytes.
+//!
+//! Bitmaps are serialized in the portable format of the other Roaring
+//! implementations (without run containers), so they can be loaded by
+//! e.g. CRoaring or the `roaring` crates. A `roaring` result file starts
+//! with [`MAGIC`], followed by one entry per record:
+//!
+//! ```text
+//! index, a, b, end  varints
+//! length            varint
+//! bitmap            length bytes of a serialized bitmap
+//! ```
+//!
+//! Bitmaps are sets: the multiples are read back in ascending order, and
+//! multiples listed twice by `multiset` mode once.
+
+use std::io::{BufRead, Read, Write};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::formatter::OutputFormatter;
+use crate::reader::{RecordIter, ResultReader};
+use crate::store::{read_varint, write_varint};
+use crate::{LineNumbers, ResultNumbers, ResultRecord};
+
+/// The first bytes of every `roaring` result file.
+pub const MAGIC: &[u8; 8] = b"MULTRB1\n";
+
+/// The cookie of serialized bitmaps without run containers.
+const SERIAL_COOKIE_NO_RUNCONTAINER: u32 = 12346;
+/// The cookie of serialized bitmaps with run containers, which aren't
+/// supported.
+const SERIAL_COOKIE: u16 = 12347;
+
+/// The most values an array container holds.
+const ARRAY_MAX: usize = 4096;
+/// 64-bit words of a bitmap container.
+const WORDS: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Container {
+    Array(Vec<u16>),
+    Bitmap(Vec<u64>),
+}
+
+impl Container {
+    /// The container of sorted, distinct `values`.
+    fn from_values(values: Vec<u16>) -> Container {
+        if values.len() <= ARRAY_MAX {
+            return Container::Array(values);
+        }
+        let mut words = vec![0u64; WORDS];
+        for value in values {
+            words[value as usize / 64] |= 1 << (value % 64);
+        }
+        Container::Bitmap(words)
+    }
+
+    /// The container of the set bits of `words`, or `None` if there are none.
+    fn from_words(words: Vec<u64>) -> Option<Container> {
+        let len: u32 = words.iter().map(|word| word.count_ones()).sum();
+        match len as usize {
+            0 => None,
+            len if len <= ARRAY_MAX => Some(Container::Array(Container::Bitmap(words).values().collect())),
+            _ => Some(Container::Bitmap(words)),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Container::Array(values) => values.len(),
+            Container::Bitmap(words) => words.iter().map(|word| word.count_ones() as usize).sum(),
+        }
+    }
+
+    fn words(&self) -> Vec<u64> {
+        match self {
+            Container::Array(values) => {
+                let mut words = vec![0u64; WORDS];
+                for &value in values {
+                    words[value as usize / 64] |= 1 << (value % 64);
+                }
+                words
+            }
+            Container::Bitmap(words) => words.clone(),
+        }
+    }
+
+    fn values(&self) -> Box<dyn Iterator<Item = u16> + '_> {
+        match self {
+            Container::Array(values) => Box::new(values.iter().copied()),
+            Container::Bitmap(words) => Box::new(words.iter().enumerate().flat_map(|(i, &word)| {
+                (0..64).filter(move |bit| word & (1 << bit) != 0).map(move |bit| (i * 64 + bit) as u16)
+            })),
+        }
+    }
+
+    /// The number of values up to and including `low`.
+    fn rank(&self, low: u16) -> usize {
+        match self {
+            Container::Array(values) => values.partition_point(|&value| value <= low),
+            Container::Bitmap(words) => {
+                let (word, bit) = (low as usize / 64, low as usize % 64);
+                let full: usize = words[..word].iter().map(|word| word.count_ones() as usize).sum();
+                let mask = if bit == 63 { u64::MAX } else { (1 << (bit + 1)) - 1 };
+                full + (words[word] & mask).count_ones() as usize
+            }
+        }
+    }
+
+    fn and(&self, other: &Container) -> Option<Container> {
+        if let (Container::Array(a), Container::Array(b)) = (self, other) {
+            let values: Vec<u16> = a.iter().copied().filter(|value| b.binary_search(value).is_ok()).collect();
+            return (!values.is_empty()).then_some(Container::Array(values));
+        }
+        let words = self.words().iter().zip(other.words()).map(|(a, b)| a & b).collect();
+        Container::from_words(words)
+    }
+
+    fn or(&self, other: &Container) -> Container {
+        if let (Container::Array(a), Container::Array(b)) = (self, other) {
+            let mut values = Vec::with_capacity(a.len() + b.len());
+            let (mut i, mut j) = (0, 0);
+            while i < a.len() || j < b.len() {
+                let next = match (a.get(i), b.get(j)) {
+                    (Some(&x), Some(&y)) if x == y => {
+                        i += 1;
+                        j += 1;
+                        x
+                    }
+                    (Some(&x), Some(&y)) if x < y => {
+                        i += 1;
+                        x
+                    }
+                    (Some(&x), None) => {
+                        i += 1;
+                        x
+                    }
+                    (_, Some(&y)) => {
+                        j += 1;
+                        y
+                    }
+                    (None, None) => unreachable!(),
+                };
+                values.push(next);
+            }
+            return Container::from_values(values);
+        }
+        let words = self.words().iter().zip(other.words()).map(|(a, b)| a | b).collect();
+        Container::from_words(words).expect("a union of non-empty containers isn't empty")
+    }
+}
+
+/// A set of `u32` values.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RoaringBitmap {
+    /// Containers by the upper 16 bits of their values, in ascending order.
+    containers: Vec<(u16, Container)>,
+}
+
+impl RoaringBitmap {
+    /// The bitmap of `values`, in any order and with any duplicates.
+    pub fn from_values(values: &[u32]) -> RoaringBitmap {
+        let mut sorted;
+        let mut values = values;
+        if !values.windows(2).all(|pair| pair[0] < pair[1]) {
+            sorted = values.to_vec();
+            sorted.sort_unstable();
+            sorted.dedup();
+            values = &sorted;
+        }
+        let mut containers = Vec::new();
+        for chunk in values.chunk_by(|a, b| a >> 16 == b >> 16) {
+            let low = chunk.iter().map(|&value| value as u16).collect();
+            containers.push(((chunk[0] >> 16) as u16, Container::from_values(low)));
+        }
+        RoaringBitmap { containers }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.containers.iter().map(|(_, container)| container.len() as u64).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.containers.is_empty()
+    }
+
+    /// The values in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.containers
+            .iter()
+            .flat_map(|(key, container)| container.values().map(move |low| (*key as u32) << 16 | low as u32))
+    }
+
+    /// The number of values up to and including `n`.
+    pub fn rank(&self, n: u32) -> u64 {
+        let key = (n >> 16) as u16;
+        let mut rank = 0;
+        for (k, container) in &self.containers {
+            if *k < key {
+                rank += container.len() as u64;
+            } else {
+                if *k == key {
+                    rank += container.rank(n as u16) as u64;
+                }
+                break;
+            }
+        }
+        rank
+    }
+
+    /// The `i`-th smallest value, counting from 0.
+    pub fn select(&self, i: u64) -> Option<u32> {
+        let mut rest = i;
+        for (key, container) in &self.containers {
+            let len = container.len() as u64;
+            if rest < len {
+                let low = container.values().nth(rest as usize)?;
+                return Some((*key as u32) << 16 | low as u32);
+            }
+            rest -= len;
+        }
+        None
+    }
+
+    /// The values in both bitmaps.
+    pub fn and(&self, other: &RoaringBitmap) -> RoaringBitmap {
+        let mut containers = Vec::new();
+        for (key, container) in &self.containers {
+            if let Ok(at) = other.containers.binary_search_by_key(key, |(k, _)| *k) {
+                if let Some(both) = container.and(&other.containers[at].1) {
+                    containers.push((*key, both));
+                }
+            }
+        }
+        RoaringBitmap { containers }
+    }
+
+    /// The values in either bitmap.
+    pub fn or(&self, other: &RoaringBitmap) -> RoaringBitmap {
+        let mut containers = Vec::with_capacity(self.containers.len().max(other.containers.len()));
+        let (mut a, mut b) = (self.containers.iter().peekable(), other.containers.iter().peekable());
+        loop {
+            let next = match (a.peek(), b.peek()) {
+                (Some((x, first)), Some((y, second))) if x == y => {
+                    let both = (*x, first.or(second));
+                    a.next();
+                    b.next();
+                    both
+                }
+                (Some((x, _)), Some((y, _))) if x < y => a.next().unwrap().clone(),
+                (Some(_), None) => a.next().unwrap().clone(),
+                (_, Some(_)) => b.next().unwrap().clone(),
+                (None, None) => break,
+            };
+            containers.push(next);
+        }
+        RoaringBitmap { containers }
+    }
+
+    /// Appends the portable serialization of the bitmap to `out`.
+    pub fn serialize(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        let n = self.containers.len();
+        out.extend_from_slice(&SERIAL_COOKIE_NO_RUNCONTAINER.to_le_bytes());
+        out.extend_from_slice(&(n as u32).to_le_bytes());
+        for (key, container) in &self.containers {
+            out.extend_from_slice(&key.to_le_bytes());
+            out.extend_from_slice(&((container.len() - 1) as u16).to_le_bytes());
+        }
+        let mut offset = 8 + 8 * n;
+        for (_, container) in &self.containers {
+            out.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += match container {
+                Container::Array(values) => 2 * values.len(),
+                Container::Bitmap(_) => 8 * WORDS,
+            };
+        }
+        for (_, container) in &self.containers {
+            match container {
+                Container::Array(values) => values.iter().for_each(|value| out.extend_from_slice(&value.to_le_bytes())),
+                Container::Bitmap(words) => words.iter().for_each(|word| out.extend_from_slice(&word.to_le_bytes())),
+            }
+        }
+        debug_assert_eq!(out.len() - start, offset);
+    }
+
+    /// Reads a bitmap in the portable serialization.
+    pub fn deserialize(bytes: &[u8]) -> Result<RoaringBitmap> {
+        let truncated = || anyhow!("Bitmap truncated");
+        let u16_at = |at: usize| bytes.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or_else(truncated);
+        let u32_at = |at: usize| bytes.get(at..at + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap())).ok_or_else(truncated);
+        let cookie = u32_at(0)?;
+        if cookie as u16 == SERIAL_COOKIE {
+            bail!("Bitmaps with run containers aren't supported");
+        }
+        if cookie != SERIAL_COOKIE_NO_RUNCONTAINER {
+            bail!("Not a serialized Roaring bitmap");
+        }
+        let n = u32_at(4)? as usize;
+        if n > 1 << 16 {
+            bail!("Bitmap with {} containers", n);
+        }
+        let mut containers = Vec::with_capacity(n);
+        for i in 0..n {
+            let key = u16_at(8 + 4 * i)?;
+            let len = u16_at(10 + 4 * i)? as usize + 1;
+            let offset = u32_at(8 + 4 * n + 4 * i)? as usize;
+            if containers.last().is_some_and(|(last, _)| *last >= key) {
+                bail!("Bitmap containers out of order");
+            }
+            let container = if len <= ARRAY_MAX {
+                let values = (0..len).map(|j| u16_at(offset + 2 * j)).collect::<Result<Vec<_>>>()?;
+                if !values.windows(2).all(|pair| pair[0] < pair[1]) {
+                    bail!("Bitmap array container out of order");
+                }
+                Container::Array(values)
+            } else {
+                let words = bytes.get(offset..offset + 8 * WORDS).ok_or_else(truncated)?;
+                let words = words.chunks_exact(8).map(|word| u64::from_le_bytes(word.try_into().unwrap())).collect();
+                let container = Container::Bitmap(words);
+                if container.len() != len {
+                    bail!("Bitmap container holds {} values instead of {}", container.len(), len);
+                }
+                container
+            };
+            containers.push((key, container));
+        }
+        Ok(RoaringBitmap { containers })
+    }
+}
+
+/// One record of a `roaring` file, with its multiples kept as a bitmap.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitmapEntry {
+    pub index: usize,
+    pub job: LineNumbers,
+    pub end: u32,
+    pub bitmap: RoaringBitmap,
+}
+
+impl BitmapEntry {
+    /// The entry of a set combined from other bitmaps up to `end`, which no
+    /// job generated: its divisors are written as 0.
+    pub fn combined(end: u32, bitmap: RoaringBitmap) -> BitmapEntry {
+        BitmapEntry {
+            index: 0,
+            job: LineNumbers { a: 0, b: 0, end },
+            end,
+            bitmap,
+        }
+    }
+
+    pub fn into_record(self) -> ResultRecord {
+        ResultRecord {
+            index: self.index,
+            job: self.job,
+            result: ResultNumbers {
+                end: self.end,
+                numbers: self.bitmap.iter().collect(),
+                count: None,
+            },
+        }
+    }
+}
+
+/// Writes records as `roaring` entries. Count-only records have no
+/// multiples to store and fail.
+#[derive(Default)]
+pub struct RoaringFormatter;
+
+impl OutputFormatter for RoaringFormatter {
+    fn begin(&mut self, w: &mut dyn Write) -> Result<()> {
+        w.write_all(MAGIC)?;
+        Ok(())
+    }
+
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        if r.result.count.is_some() {
+            bail!("The roaring format can't hold count-only records");
+        }
+        let mut bitmap = Vec::new();
+        RoaringBitmap::from_values(&r.result.numbers).serialize(&mut bitmap);
+        let mut entry = Vec::with_capacity(16 + bitmap.len());
+        for value in [r.index as u64, r.job.a as u64, r.job.b as u64, r.result.end as u64, bitmap.len() as u64] {
+            write_varint(&mut entry, value)?;
+        }
+        entry.extend_from_slice(&bitmap);
+        w.write_all(&entry)?;
+        Ok(())
+    }
+}
+
+/// Reads the entry at the start of `r`, or `None` at the end of the file.
+fn read_entry(r: &mut dyn BufRead) -> Result<Option<BitmapEntry>> {
+    if r.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let index = read_varint(r)? as usize;
+    let mut field = || -> Result<u32> { u32::try_from(read_varint(r)?).context("Value out of range") };
+    let job = LineNumbers {
+        a: field()?,
+        b: field()?,
+        end: field()?,
+    };
+    let len = read_varint(r)? as usize;
+    let mut bytes = vec![0u8; len.min(1 << 24)];
+    r.read_exact(&mut bytes)?;
+    if bytes.len() != len {
+        bail!("Bitmap of {} bytes", len);
+    }
+    let bitmap = RoaringBitmap::deserialize(&bytes)?;
+    Ok(Some(BitmapEntry { index, job, end: job.end, bitmap }))
+}
+
+/// Reads the entries of a `roaring` file, keeping their bitmaps.
+pub fn read_entries<'a>(mut reader: Box<dyn BufRead + Send + 'a>) -> Box<dyn Iterator<Item = Result<BitmapEntry>> + Send + 'a> {
+    let mut magic = [0u8; 8];
+    if reader.read_exact(&mut magic).is_err() || &magic != MAGIC {
+        return Box::new(std::iter::once(Err(anyhow!("Not a roaring file: unexpected header"))));
+    }
+    let mut entry = 0usize;
+    let mut failed = false;
+    Box::new(std::iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+        entry += 1;
+        let read = read_entry(&mut reader).with_context(|| format!("Entry {}: truncated or damaged", entry));
+        failed = read.is_err();
+        read.transpose()
+    }))
+}
+
+/// Reads `roaring` files as records with their multiples in ascending order.
+pub struct RoaringReader;
+
+impl ResultReader for RoaringReader {
+    fn read<'a>(&self, reader: Box<dyn BufRead + Send + 'a>) -> RecordIter<'a> {
+        Box::new(read_entries(reader).map(|entry| entry.map(BitmapEntry::into_record)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_entries, RoaringBitmap, RoaringFormatter, RoaringReader};
+    use crate::formatter::OutputFormatter;
+    use crate::reader::ResultReader;
+    use crate::{count_record, formula::Mode, generate_record, LineNumbers, ResultRecord};
+
+    fn bitmap(a: u32, b: u32, end: u32) -> (Vec<u32>, RoaringBitmap) {
+        let numbers = generate_record(LineNumbers { a, b, end }).result.numbers;
+        let bitmap = RoaringBitmap::from_values(&numbers);
+        (numbers, bitmap)
+    }
+
+    #[test]
+    fn test_bitmap_operations() {
+        // Dense containers for the multiples of 2, sparse ones for those of 1000.
+        let (evens, even_bitmap) = bitmap(2, 2, 300_000);
+        let (thousands, thousand_bitmap) = bitmap(1000, 1000, 300_000);
+        assert_eq!(even_bitmap.iter().collect::<Vec<_>>(), evens);
+        assert_eq!(even_bitmap.len(), 150_000);
+        assert_eq!(thousand_bitmap.iter().collect::<Vec<_>>(), thousands);
+
+        assert_eq!(even_bitmap.rank(1), 0);
+        assert_eq!(even_bitmap.rank(131_071), 65_535);
+        assert_eq!(even_bitmap.rank(131_072), 65_536);
+        assert_eq!(even_bitmap.rank(u32::MAX), 150_000);
+        assert_eq!(even_bitmap.select(0), Some(2));
+        assert_eq!(even_bitmap.select(70_000), Some(140_002));
+        assert_eq!(even_bitmap.select(150_000), None);
+
+        let (threes, three_bitmap) = bitmap(3, 3, 300_000);
+        let both: Vec<u32> = evens.iter().copied().filter(|n| n % 3 == 0).collect();
+        assert_eq!(even_bitmap.and(&three_bitmap).iter().collect::<Vec<_>>(), both);
+        let mut either: Vec<u32> = evens.iter().chain(&threes).copied().collect();
+        either.sort_unstable();
+        either.dedup();
+        assert_eq!(even_bitmap.or(&three_bitmap).iter().collect::<Vec<_>>(), either);
+        assert_eq!(thousand_bitmap.and(&even_bitmap), thousand_bitmap);
+        assert!(RoaringBitmap::from_values(&[1, 3]).and(&RoaringBitmap::from_values(&[2])).is_empty());
+        assert_eq!(RoaringBitmap::from_values(&[5, 1, 5, 70_000]).iter().collect::<Vec<_>>(), [1, 5, 70_000]);
+    }
+
+    #[test]
+    fn test_bitmap_serialization() {
+        // The layout of other Roaring implementations: cookie, container
+        // count, key and cardinality - 1, offset, values.
+        let mut bytes = Vec::new();
+        RoaringBitmap::from_values(&[1, 2, 65_536]).serialize(&mut bytes);
+        assert_eq!(
+            bytes,
+            [
+                0x3a, 0x30, 0, 0, 2, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 24, 0, 0, 0, 28, 0, 0, 0, 1, 0, 2, 0, 0, 0
+            ]
+        );
+        for (a, b, end) in [(2, 3, 300_000), (7, 11, 1000), (1, 1, 0)] {
+            let (_, bitmap) = bitmap(a, b, end);
+            let mut bytes = Vec::new();
+            bitmap.serialize(&mut bytes);
+            assert_eq!(RoaringBitmap::deserialize(&bytes).unwrap(), bitmap);
+            assert!(RoaringBitmap::deserialize(&bytes[..bytes.len() - 1]).is_err());
+        }
+        assert!(RoaringBitmap::deserialize(&[0x3b, 0x30, 0, 0]).unwrap_err().to_string().contains("run containers"));
+    }
+
+    #[test]
+    fn test_roaring_round_trip() {
+        let mut records = vec![generate_record(LineNumbers { a: 2, b: 3, end: 100_000 }), generate_record(LineNumbers { a: 7, b: 9, end: 5 })];
+        records[1].index = 1;
+        let mut formatter = RoaringFormatter;
+        let mut out = Vec::new();
+        formatter.begin(&mut out).unwrap();
+        for record in &records {
+            formatter.write_record(&mut out, record).unwrap();
+        }
+        let count = count_record(LineNumbers { a: 2, b: 3, end: 10 }, Mode::Or);
+        assert!(formatter.write_record(&mut Vec::new(), &count).is_err());
+        let read: Vec<ResultRecord> = RoaringReader.read(Box::new(&out[..])).collect::<anyhow::Result<_>>().unwrap();
+        assert_eq!(read, records);
+        let entries: Vec<_> = read_entries(Box::new(&out[..])).collect::<anyhow::Result<_>>().unwrap();
+        assert_eq!(entries[0].bitmap.len(), records[0].result.numbers.len() as u64);
+        assert!(RoaringReader.read(Box::new(&out[..out.len() - 1])).any(|record| record.is_err()));
+    }
+}
@@ -0,0 +1,133 @@
+//! The run log of `--audit`: every generate run appends one NDJSON line to
+//! an append-only log, `runs.log` by default, saying when it started, with
+//! which arguments, its [provenance](crate::provenance) (the version and
+//! git commit of the tool, the effective configuration and the input it
+//! read, by path and SHA-256), which output it wrote, how long it took and
+//! how it ended, so that an artifact can be traced back to what made it.
+//! The log is only appended to, never rewritten; concurrent runs each
+//! append whole lines.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+use crate::json;
+use crate::provenance::Provenance;
+use crate::schedule::format_utc;
+
+/// The log `--audit` appends to when `--audit-log` doesn't name another.
+pub const DEFAULT_LOG: &str = "runs.log";
+
+/// One line of the run log.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunEntry {
+    /// When the run started, in Unix seconds.
+    pub started: u64,
+    pub provenance: Provenance,
+    /// The arguments of the run as given, without the program.
+    pub args: Vec<String>,
+    pub output: PathBuf,
+    pub duration: Duration,
+    /// `Ok` or why the run failed.
+    pub outcome: Result<(), String>,
+}
+
+impl RunEntry {
+    fn to_json(&self) -> String {
+        let args: Vec<String> = self.args.iter().map(|arg| json::quote(arg)).collect();
+        let outcome = match &self.outcome {
+            Ok(()) => String::from("\"outcome\":\"ok\""),
+            Err(err) => format!("\"outcome\":\"failed\",\"error\":{}", json::quote(err)),
+        };
+        format!(
+            "{{\"started\":{},{},\"args\":[{}],\"output\":{},\"duration_ms\":{},{}}}",
+            json::quote(&format_utc(self.started)),
+            self.provenance.json_fields(),
+            args.join(","),
+            json::quote(&self.output.to_string_lossy()),
+            self.duration.as_millis(),
+            outcome
+        )
+    }
+}
+
+/// Runs `run`, a run of `provenance` writing `output`, and notes how it
+/// went, returning the entry to [`append`] with the result of the run.
+pub fn logged<T>(
+    provenance: Provenance,
+    output: &Path,
+    args: &[String],
+    run: impl FnOnce() -> Result<T>,
+) -> (RunEntry, Result<T>) {
+    let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let clock = Instant::now();
+    let result = run();
+    let entry = RunEntry {
+        started,
+        provenance,
+        args: args.to_vec(),
+        output: output.to_path_buf(),
+        duration: clock.elapsed(),
+        outcome: result.as_ref().map(|_| ()).map_err(|err| format!("{:#}", err)),
+    };
+    (entry, result)
+}
+
+/// Appends `entry` to the log at `path`, creating it if need be.
+pub fn append(path: &Path, entry: &RunEntry) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open run log {:?}", path))?;
+    // One write per line, so that lines of concurrent runs don't interleave.
+    file.write_all(format!("{}\n", entry.to_json()).as_bytes())
+        .with_context(|| format!("Failed to write run log {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use anyhow::anyhow;
+
+    use super::{append, logged};
+    use crate::json::{self, Value};
+    use crate::provenance::{Provenance, GIT_HASH};
+
+    #[test]
+    fn test_run_log() {
+        let dir = std::env::temp_dir().join(format!("multiples_runlog_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (input, log) = (dir.join("in.txt"), dir.join("runs.log"));
+        std::fs::write(&input, "abc").unwrap();
+        let args = [String::from("--threads"), String::from("2")];
+        let (entry, result) = logged(Provenance::of(&input, &args), Path::new("out.txt"), &args, || Ok(7));
+        assert_eq!(result.unwrap(), 7);
+        append(&log, &entry).unwrap();
+        let provenance = Provenance::of(&dir.join("missing.txt"), &[]);
+        let (entry, result) = logged(provenance, Path::new("out.txt"), &[], || Err::<(), _>(anyhow!("No \"input\"")));
+        assert!(result.is_err());
+        append(&log, &entry).unwrap();
+
+        let text = std::fs::read_to_string(&log).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let lines: Vec<Value> = text.lines().map(|line| json::parse(line).unwrap()).collect();
+        let text = |line: &Value, key: &str| line.get(key).and_then(Value::as_text).map(str::to_string);
+        assert_eq!(lines.len(), 2);
+        let hash = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(text(&lines[0], "input_sha256").as_deref(), Some(hash));
+        assert_eq!(text(&lines[0], "outcome").as_deref(), Some("ok"));
+        assert_eq!(text(&lines[0], "version").as_deref(), Some(env!("CARGO_PKG_VERSION")));
+        assert_eq!(text(&lines[0], "git").as_deref(), Some(GIT_HASH));
+        assert!(text(&lines[0], "started").unwrap().ends_with('Z'));
+        let args: Vec<_> = lines[0].get("args").and_then(Value::as_array).unwrap().iter().filter_map(Value::as_text).collect();
+        assert_eq!(args, ["--threads", "2"]);
+        assert_eq!(lines[1].get("input_sha256"), Some(&Value::Null));
+        assert_eq!(text(&lines[1], "outcome").as_deref(), Some("failed"));
+        assert_eq!(text(&lines[1], "error").as_deref(), Some("No \"input\""));
+    }
+}
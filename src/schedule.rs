@@ -0,0 +1,583 @@
+//! Recurring jobs for the `daemon` command: cron-style schedules, kept in a
+//! state directory so that they survive restarts, and a history of their
+//! runs.
+//!
+//! Each schedule runs an input file, or jobs given inline, into an output
+//! file whenever its cron expression matches, evaluated in UTC. The state
+//! directory holds `schedules.ndjson`, rewritten whenever a schedule is
+//! added or removed, and `history.ndjson`, with a line appended per run.
+//! Runs missed while the daemon was down aren't caught up on. With an
+//! [exporter](crate::otlp) every run is also sent to a collector as a span.
+
+use std::fs::{self, OpenOptions};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::formatter::FormatterRegistry;
+use crate::input::InputParserRegistry;
+use crate::json::{self, Value};
+use crate::otlp::{Attribute, Operation, OtlpExporter};
+use crate::output::LazyFile;
+use crate::pipeline::{self, FormatterSink, Pipeline};
+use crate::strategy::{GenerateOptions, RecordGenerator};
+
+/// How far ahead [`Cron::next_after`] looks; far enough for `29 2`, which
+/// only matches in leap years.
+const HORIZON_DAYS: i64 = 8 * 366;
+
+/// The times a cron expression matches, to the minute.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    /// Whether the day of month or the weekday field is `*`: cron matches
+    /// either restricted field, not both.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// The bits of the values `field` lists, e.g. `1-5`, `*/15` or `0,30`.
+fn field_bits(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut bits = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse().ok().filter(|&step| step > 0)),
+            None => (item, Some(1)),
+        };
+        let step = step.ok_or_else(|| anyhow!("Invalid step in {:?}", item))?;
+        let value = |text: &str| {
+            text.parse()
+                .ok()
+                .filter(|value| (min..=max).contains(value))
+                .ok_or_else(|| anyhow!("{:?} isn't a value from {} to {}", text, min, max))
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (value(first)?, value(last)?),
+            // `5/10` runs from 5 to the end of the field.
+            None if item.contains('/') => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if first > last {
+            bail!("Empty range {:?}", range);
+        }
+        for value in (first..=last).step_by(step) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl FromStr for Cron {
+    type Err = anyhow::Error;
+
+    /// Five fields: minute, hour, day of month, month and weekday (0 or 7 is
+    /// Sunday), or one of `@hourly`, `@daily`, `@weekly`, `@monthly` and
+    /// `@yearly`.
+    fn from_str(text: &str) -> Result<Self> {
+        let expanded = match text.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!("Invalid schedule {:?}: expected minute, hour, day, month and weekday fields", text);
+        };
+        let parse = |field: &str, name: &str, min: u32, max: u32| {
+            field_bits(field, min, max).with_context(|| format!("Invalid {} field in schedule {:?}", name, text))
+        };
+        let weekdays = parse(weekday, "weekday", 0, 7)?;
+        let cron = Cron {
+            minutes: parse(minute, "minute", 0, 59)?,
+            hours: parse(hour, "hour", 0, 23)? as u32,
+            days: parse(day, "day", 1, 31)? as u32,
+            months: parse(month, "month", 1, 12)? as u16,
+            weekdays: (weekdays | weekdays >> 7) as u8 & 0x7f,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        };
+        if cron.next_after(0).is_none() {
+            bail!("Schedule {:?} never matches", text);
+        }
+        Ok(cron)
+    }
+}
+
+/// The year, month and day of `days` since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    (era * 400 + year_of_era + i64::from(month <= 2), month, day)
+}
+
+/// `time` (Unix seconds) as `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format_utc(time: u64) -> String {
+    let (days, seconds) = ((time / 86_400) as i64, time % 86_400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+impl Cron {
+    fn matches_day(&self, days: i64) -> bool {
+        let (_, month, day) = civil_from_days(days);
+        // 1970-01-01 was a Thursday.
+        let weekday = (days + 4).rem_euclid(7);
+        let by_day = self.days & 1 << day != 0;
+        let by_weekday = self.weekdays & 1 << weekday != 0;
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => by_day || by_weekday,
+            _ => by_day && by_weekday,
+        };
+        self.months & 1 << month != 0 && day_matches
+    }
+
+    /// The first matching minute after `time`, in Unix seconds.
+    pub fn next_after(&self, time: u64) -> Option<u64> {
+        let start = time / 60 + 1;
+        let first_day = (start / 1440) as i64;
+        for days in first_day..first_day + HORIZON_DAYS {
+            if !self.matches_day(days) {
+                continue;
+            }
+            let from = if days == first_day { start % 1440 } else { 0 };
+            let minute = (from..1440).find(|minute| self.hours & 1 << (minute / 60) != 0 && self.minutes & 1 << (minute % 60) != 0);
+            if let Some(minute) = minute {
+                return Some((days as u64 * 1440 + minute) * 60);
+            }
+        }
+        None
+    }
+}
+
+/// What a schedule runs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScheduledJobs {
+    /// An input file, read anew at every run.
+    File(PathBuf),
+    /// `a b end` lines.
+    Inline(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    pub id: u32,
+    pub cron: String,
+    pub jobs: ScheduledJobs,
+    pub output: PathBuf,
+    pub output_format: String,
+}
+
+impl Schedule {
+    fn to_json(&self) -> String {
+        let jobs = match &self.jobs {
+            ScheduledJobs::File(path) => format!("\"input\":{}", json::quote(&path.to_string_lossy())),
+            ScheduledJobs::Inline(jobs) => format!("\"jobs\":{}", json::quote(jobs)),
+        };
+        format!(
+            "{{\"id\":{},\"cron\":{},{},\"output\":{},\"format\":{}}}",
+            self.id,
+            json::quote(&self.cron),
+            jobs,
+            json::quote(&self.output.to_string_lossy()),
+            json::quote(&self.output_format)
+        )
+    }
+
+    fn from_json(value: &Value) -> Result<Self> {
+        let text = |key: &str| value.get(key).and_then(Value::as_text);
+        let required = |key: &str| text(key).ok_or_else(|| anyhow!("Missing {}", key));
+        let jobs = match (text("input"), text("jobs")) {
+            (Some(path), None) => ScheduledJobs::File(PathBuf::from(path)),
+            (None, Some(jobs)) => ScheduledJobs::Inline(jobs.to_string()),
+            _ => bail!("Expected either input or jobs"),
+        };
+        let id = match value.get("id") {
+            Some(Value::Number(id)) => id.parse().map_err(|_| anyhow!("Invalid id {}", id))?,
+            _ => bail!("Missing id"),
+        };
+        Ok(Schedule {
+            id,
+            cron: required("cron")?.to_string(),
+            jobs,
+            output: PathBuf::from(required("output")?),
+            output_format: text("format").unwrap_or("text").to_string(),
+        })
+    }
+
+    /// Generates the records of the schedule's jobs into its output,
+    /// returning how many were written.
+    pub fn run(&self) -> Result<usize> {
+        let source = match &self.jobs {
+            ScheduledJobs::File(path) => pipeline::file_source(path, None)?,
+            ScheduledJobs::Inline(jobs) => {
+                let registry = InputParserRegistry::with_builtins();
+                registry.get("text")?.parse(Box::new(Cursor::new(jobs.clone().into_bytes())))
+            }
+        };
+        let generator = RecordGenerator::new(GenerateOptions::default());
+        let formatter = FormatterRegistry::with_builtins().create(&self.output_format)?;
+        let mut sink = FormatterSink::new(LazyFile::new(&self.output), formatter);
+        let mut records = 0;
+        Pipeline::builder(source)
+            .generator(move |job| Ok(generator.generate(job)))
+            .inspect(|_| records += 1)
+            .build()
+            .run(&mut sink)?;
+        Ok(records)
+    }
+}
+
+/// The outcome of one run of a schedule.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Run {
+    pub schedule: u32,
+    /// When it started, in Unix seconds.
+    pub started: u64,
+    pub duration: Duration,
+    /// The records written, or why the run failed.
+    pub outcome: Result<usize, String>,
+}
+
+impl Run {
+    fn to_json(&self) -> String {
+        let outcome = match &self.outcome {
+            Ok(records) => format!("\"outcome\":\"ok\",\"records\":{}", records),
+            Err(err) => format!("\"outcome\":\"failed\",\"error\":{}", json::quote(err)),
+        };
+        format!(
+            "{{\"schedule\":{},\"started\":{},\"duration_ms\":{},{}}}",
+            self.schedule,
+            self.started,
+            self.duration.as_millis(),
+            outcome
+        )
+    }
+
+    fn from_json(value: &Value) -> Result<Self> {
+        let number = |key: &str| match value.get(key) {
+            Some(Value::Number(number)) => number.parse::<u64>().map_err(|_| anyhow!("Invalid {} {}", key, number)),
+            _ => bail!("Missing {}", key),
+        };
+        let outcome = match value.get("outcome").and_then(Value::as_text) {
+            Some("ok") => Ok(number("records")? as usize),
+            Some("failed") => Err(value.get("error").and_then(Value::as_text).unwrap_or_default().to_string()),
+            _ => bail!("Missing outcome"),
+        };
+        Ok(Run {
+            schedule: number("schedule")? as u32,
+            started: number("started")?,
+            duration: Duration::from_millis(number("duration_ms")?),
+            outcome,
+        })
+    }
+}
+
+impl std::fmt::Display for Run {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} schedule {} ", format_utc(self.started), self.schedule)?;
+        match &self.outcome {
+            Ok(records) => write!(f, "ok: {} records in {} ms", records, self.duration.as_millis()),
+            Err(err) => write!(f, "failed after {} ms: {}", self.duration.as_millis(), err),
+        }
+    }
+}
+
+fn read_lines<T>(path: &Path, parse: impl Fn(&Value) -> Result<T>) -> Result<Vec<T>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {:?}", path)),
+    };
+    let lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    lines
+        .map(|(index, line)| json::parse(line).and_then(|value| parse(&value)).with_context(|| format!("{:?} line {}", path, index + 1)))
+        .collect()
+}
+
+/// The schedules and run history kept in a state directory.
+pub struct ScheduleStore {
+    dir: PathBuf,
+}
+
+impl ScheduleStore {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create state directory {:?}", dir))?;
+        Ok(ScheduleStore { dir: dir.to_path_buf() })
+    }
+
+    fn schedules_path(&self) -> PathBuf {
+        self.dir.join("schedules.ndjson")
+    }
+
+    fn history_path(&self) -> PathBuf {
+        self.dir.join("history.ndjson")
+    }
+
+    pub fn schedules(&self) -> Result<Vec<Schedule>> {
+        read_lines(&self.schedules_path(), Schedule::from_json)
+    }
+
+    fn save(&self, schedules: &[Schedule]) -> Result<()> {
+        let path = self.schedules_path();
+        let temp = self.dir.join("schedules.ndjson.tmp");
+        let text: String = schedules.iter().map(|schedule| schedule.to_json() + "\n").collect();
+        fs::write(&temp, text).with_context(|| format!("Failed to write {:?}", temp))?;
+        fs::rename(&temp, &path).with_context(|| format!("Failed to replace {:?}", path))
+    }
+
+    /// Adds a schedule, giving it the next free id, unless one with the same
+    /// cron expression, jobs and output exists already. Returns the stored
+    /// schedule and whether it is new.
+    pub fn add(&self, cron: &str, jobs: ScheduledJobs, output: &Path, output_format: &str) -> Result<(Schedule, bool)> {
+        cron.parse::<Cron>()?;
+        let mut schedules = self.schedules()?;
+        let same = |schedule: &&Schedule| schedule.cron == cron && schedule.jobs == jobs && schedule.output == output;
+        if let Some(existing) = schedules.iter().find(same) {
+            return Ok((existing.clone(), false));
+        }
+        let schedule = Schedule {
+            id: schedules.iter().map(|schedule| schedule.id).max().unwrap_or(0) + 1,
+            cron: cron.to_string(),
+            jobs,
+            output: output.to_path_buf(),
+            output_format: output_format.to_string(),
+        };
+        schedules.push(schedule.clone());
+        self.save(&schedules)?;
+        Ok((schedule, true))
+    }
+
+    /// Removes the schedule `id`, returning whether there was one.
+    pub fn remove(&self, id: u32) -> Result<bool> {
+        let mut schedules = self.schedules()?;
+        let before = schedules.len();
+        schedules.retain(|schedule| schedule.id != id);
+        if schedules.len() == before {
+            return Ok(false);
+        }
+        self.save(&schedules)?;
+        Ok(true)
+    }
+
+    pub fn record(&self, run: &Run) -> Result<()> {
+        let path = self.history_path();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {:?}", path))?;
+        writeln!(file, "{}", run.to_json()).with_context(|| format!("Failed to write {:?}", path))
+    }
+
+    pub fn history(&self) -> Result<Vec<Run>> {
+        read_lines(&self.history_path(), Run::from_json)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Runs the schedules of `store` as they come due until `stop` returns
+/// true, checked every second. Schedules are read anew every minute, so
+/// ones added while the daemon runs are picked up without a restart.
+pub fn run_daemon(store: &ScheduleStore, stop: impl Fn() -> bool, exporter: Option<&OtlpExporter>) -> Result<()> {
+    let mut checked = unix_now();
+    while !stop() {
+        let now = unix_now();
+        if now / 60 > checked / 60 {
+            run_due(store, checked, now, exporter)?;
+            checked = now;
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+    Ok(())
+}
+
+/// Runs the schedules of `store` due after `checked` and by `now`, both in
+/// Unix seconds, returning the runs.
+fn run_due(store: &ScheduleStore, checked: u64, now: u64, exporter: Option<&OtlpExporter>) -> Result<Vec<Run>> {
+    let mut runs = Vec::new();
+    for schedule in store.schedules()? {
+        // A schedule broken by hand is reported at every minute rather
+        // than stopping the others.
+        let due = schedule.cron.parse::<Cron>().map(|cron| cron.next_after(checked).is_some_and(|next| next <= now));
+        match due {
+            Ok(false) => {}
+            Ok(true) => {
+                let (start, started) = (SystemTime::now(), Instant::now());
+                let outcome = schedule.run().map_err(|err| format!("{:#}", err));
+                let run = Run {
+                    schedule: schedule.id,
+                    started: now,
+                    duration: started.elapsed(),
+                    outcome,
+                };
+                eprintln!("{}", run);
+                store.record(&run)?;
+                if let Some(exporter) = exporter {
+                    if let Err(err) = exporter.export_operation(&run_operation(&schedule, &run, start)) {
+                        eprintln!("Failed to export the span of schedule {}: {:#}", schedule.id, err);
+                    }
+                }
+                runs.push(run);
+            }
+            Err(err) => eprintln!("Skipping schedule {}: {:#}", schedule.id, err),
+        }
+    }
+    Ok(runs)
+}
+
+/// The span of `run`, a run of `schedule` that started at `start`.
+fn run_operation(schedule: &Schedule, run: &Run, start: SystemTime) -> Operation {
+    let mut attributes = vec![
+        ("schedule.id", Attribute::Int(schedule.id as u64)),
+        ("schedule.cron", Attribute::Text(schedule.cron.clone())),
+        ("schedule.output", Attribute::Text(schedule.output.to_string_lossy().into_owned())),
+    ];
+    if let Ok(records) = run.outcome {
+        attributes.push(("schedule.records", Attribute::Int(records as u64)));
+    }
+    Operation {
+        name: String::from("scheduled run"),
+        server: false,
+        start,
+        duration: run.duration,
+        attributes,
+        error: run.outcome.clone().err(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use super::{format_utc, run_due, Cron, Run, ScheduleStore, ScheduledJobs};
+    use crate::json::Value;
+    use crate::otlp::{spans_of, stub_collector, OtlpExporter};
+
+    /// Unix seconds of 2026-10-14 (a Wednesday) at `hour:minute` UTC.
+    fn at(hour: u64, minute: u64) -> u64 {
+        1_791_936_000 + hour * 3600 + minute * 60
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        assert_eq!(format_utc(at(0, 0)), "2026-10-14T00:00:00Z");
+        let nightly: Cron = "0 2 * * *".parse().unwrap();
+        assert_eq!(nightly.next_after(at(1, 30)), Some(at(2, 0)));
+        assert_eq!(nightly.next_after(at(2, 0)), Some(at(26, 0)));
+        let quarters: Cron = "*/15 9-17 * * 1-5".parse().unwrap();
+        assert_eq!(quarters.next_after(at(9, 7)), Some(at(9, 15)));
+        // Saturday and Sunday are skipped.
+        assert_eq!(quarters.next_after(at(3 * 24 + 17, 45)).map(format_utc).as_deref(), Some("2026-10-19T09:00:00Z"));
+        let sundays: Cron = "30 6 * * 7".parse().unwrap();
+        assert_eq!(sundays.next_after(at(0, 0)).map(format_utc).as_deref(), Some("2026-10-18T06:30:00Z"));
+        // With both day fields restricted, either one matching is enough.
+        let either: Cron = "0 0 1 * 1".parse().unwrap();
+        assert_eq!(either.next_after(at(0, 0)).map(format_utc).as_deref(), Some("2026-10-19T00:00:00Z"));
+        let leap: Cron = "0 0 29 2 *".parse().unwrap();
+        assert_eq!(leap.next_after(at(0, 0)).map(format_utc).as_deref(), Some("2028-02-29T00:00:00Z"));
+        assert_eq!("@daily".parse::<Cron>().unwrap(), "0 0 * * *".parse().unwrap());
+
+        assert!("0 2 * *".parse::<Cron>().is_err());
+        assert!("60 * * * *".parse::<Cron>().is_err());
+        assert!("*/0 * * * *".parse::<Cron>().is_err());
+        assert!("0 0 31 2 *".parse::<Cron>().is_err());
+    }
+
+    #[test]
+    fn test_schedule_store() {
+        let dir = std::env::temp_dir().join(format!("multiples_schedules_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = ScheduleStore::open(&dir).unwrap();
+        let output = dir.join("out.txt");
+        let inline = ScheduledJobs::Inline(String::from("2 3 10\n5 7 20"));
+        let (first, added) = store.add("0 2 * * *", inline.clone(), &output, "text").unwrap();
+        assert!(added);
+        assert!(!store.add("0 2 * * *", inline, &output, "text").unwrap().1);
+        let file = ScheduledJobs::File(PathBuf::from("jobs.csv"));
+        let (second, _) = store.add("@hourly", file, Path::new("hourly.json"), "json").unwrap();
+        assert_eq!((first.id, second.id), (1, 2));
+        assert!(store.add("0 25 * * *", ScheduledJobs::Inline(String::new()), &output, "text").is_err());
+
+        let reopened = ScheduleStore::open(&dir).unwrap();
+        assert_eq!(reopened.schedules().unwrap(), [first.clone(), second]);
+        assert!(reopened.remove(2).unwrap());
+        assert!(!reopened.remove(2).unwrap());
+        assert_eq!(reopened.schedules().unwrap().len(), 1);
+
+        assert_eq!(first.run().unwrap(), 2);
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "10:2 3 4 6 8 9 10\n20:5 7 10 14 15 20\n");
+        let runs = [
+            Run {
+                schedule: 1,
+                started: at(2, 0),
+                duration: Duration::from_millis(12),
+                outcome: Ok(2),
+            },
+            Run {
+                schedule: 1,
+                started: at(26, 0),
+                duration: Duration::from_millis(3),
+                outcome: Err(String::from("Input file does not exist")),
+            },
+        ];
+        for run in &runs {
+            store.record(run).unwrap();
+        }
+        assert_eq!(store.history().unwrap(), runs);
+        assert_eq!(runs[0].to_string(), "2026-10-14T02:00:00Z schedule 1 ok: 2 records in 12 ms");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_due_exports_spans() {
+        let dir = std::env::temp_dir().join(format!("multiples_due_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = ScheduleStore::open(&dir).unwrap();
+        let jobs = ScheduledJobs::Inline(String::from("2 3 10"));
+        store.add("0 2 * * *", jobs.clone(), &dir.join("nightly.txt"), "text").unwrap();
+        store.add("*/5 * * * *", jobs, &dir.join("missing/out.txt"), "text").unwrap();
+        let (endpoint, bodies) = stub_collector();
+        let exporter = OtlpExporter::new(&endpoint, 1.0).unwrap();
+        // Only the second schedule is due at 09:05, and it fails.
+        let runs = run_due(&store, at(9, 4), at(9, 5), Some(&exporter)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert!(runs[0].outcome.is_err());
+        let spans = spans_of(&bodies.recv_timeout(Duration::from_secs(5)).unwrap());
+        let text = |value: Option<&Value>| value.and_then(Value::as_text).map(String::from);
+        assert_eq!(text(spans[0].get("name")).as_deref(), Some("scheduled run"));
+        assert_eq!(text(spans[0].get("status").unwrap().get("code")).as_deref(), Some("2"));
+        let attributes = spans[0].get("attributes").unwrap().as_array().unwrap();
+        assert_eq!(text(attributes[0].get("value").unwrap().get("intValue")).as_deref(), Some("2"));
+        assert!(bodies.try_recv().is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! Randomized cross-checks of the generator, for the `selftest` command.
+//!
+//! Every case draws a job and compares what each strategy and kernel
+//! generates for it against two independent references: merging the
+//! arithmetic progressions of `a` and `b`, and sieving the range with them.
+//! It also checks the closed-form count and sum, that the numbers ascend
+//! within `1..=end`, and that `--descending` lists the same numbers.
+
+use std::fmt;
+
+use crate::formula::{self, Mode};
+use crate::random::{JobBounds, Rng};
+use crate::strategy::{GenerateOptions, RecordGenerator, Strategy};
+use crate::LineNumbers;
+
+/// A check that failed for one job.
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    pub job: LineNumbers,
+    pub mode: Mode,
+    pub check: String,
+    pub detail: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} ({}): {}: {}",
+            self.job.a, self.job.b, self.job.end, self.mode.name(), self.check, self.detail
+        )
+    }
+}
+
+/// The outcome of [`run`].
+#[derive(Debug, Default)]
+pub struct SelfTestReport {
+    pub cases: usize,
+    pub checks: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// The multiples of `d` in `1..=end`; 0 has none.
+fn progression(d: u32, end: u32) -> impl Iterator<Item = u32> {
+    let step = d as u64;
+    (1..)
+        .map(move |k| k * step)
+        .take_while(move |&n| d != 0 && n <= end as u64)
+        .map(|n| n as u32)
+}
+
+/// Merges the progressions of `a` and `b`, keeping a number found in both
+/// once, not at all in `xor` mode or twice in `multiset` mode.
+fn merge_reference(job: &LineNumbers, mode: Mode) -> Vec<u32> {
+    let (mut a, mut b) = (progression(job.a, job.end).peekable(), progression(job.b, job.end).peekable());
+    let mut numbers = Vec::new();
+    loop {
+        let (next, both) = match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return numbers,
+            (Some(x), Some(y)) if x == y => (x, true),
+            (Some(x), Some(y)) => (x.min(y), false),
+            (Some(x), None) => (x, false),
+            (None, Some(y)) => (y, false),
+        };
+        if a.peek() == Some(&next) {
+            a.next();
+        }
+        if b.peek() == Some(&next) {
+            b.next();
+        }
+        match mode {
+            Mode::Xor if both => {}
+            Mode::Multiset if both => numbers.extend([next, next]),
+            _ => numbers.push(next),
+        }
+    }
+}
+
+/// Marks the multiples of `a` and `b` in a table of the range.
+fn sieve_reference(job: &LineNumbers, mode: Mode) -> Vec<u32> {
+    let mut hits = vec![0u8; job.end as usize + 1];
+    for d in [job.a, job.b] {
+        for n in progression(d, job.end) {
+            hits[n as usize] += 1;
+        }
+    }
+    (1..=job.end)
+        .flat_map(|n| std::iter::repeat_n(n, mode.copies(hits[n as usize])))
+        .collect()
+}
+
+/// Runs every check on one job, adding failures to `report`.
+pub fn check_job(job: LineNumbers, mode: Mode, report: &mut SelfTestReport) {
+    let mut check = |name: &str, passed: bool, detail: &dyn Fn() -> String| {
+        report.checks += 1;
+        if !passed {
+            report.mismatches.push(Mismatch {
+                job,
+                mode,
+                check: name.to_string(),
+                detail: detail(),
+            });
+        }
+    };
+    let expected = sieve_reference(&job, mode);
+    let differ = |numbers: &[u32]| {
+        let first = numbers.iter().zip(&expected).position(|(x, y)| x != y);
+        let first = first.unwrap_or(numbers.len().min(expected.len()));
+        format!(
+            "{} numbers instead of {}, first difference at position {}",
+            numbers.len(),
+            expected.len(),
+            first
+        )
+    };
+    let merged = merge_reference(&job, mode);
+    check("merge vs sieve", merged == expected, &|| differ(&merged));
+
+    let variants = [
+        ("trial", GenerateOptions::default()),
+        ("trial (scalar)", GenerateOptions { force_scalar: true, ..Default::default() }),
+        ("wheel", GenerateOptions { strategy: Strategy::Wheel, ..Default::default() }),
+        ("trial, divisors as written", GenerateOptions { keep_divisors: true, ..Default::default() }),
+    ];
+    for (name, options) in variants {
+        let generator = RecordGenerator::new(GenerateOptions { mode, ..options });
+        let numbers = generator.generate(job).result.numbers;
+        check(&format!("{} vs sieve", name), numbers == expected, &|| differ(&numbers));
+    }
+
+    let descending = RecordGenerator::new(GenerateOptions {
+        mode,
+        strategy: Strategy::Wheel,
+        descending: true,
+        ..Default::default()
+    });
+    let mut reversed = descending.generate(job).result.numbers;
+    reversed.reverse();
+    check("descending vs sieve", reversed == expected, &|| differ(&reversed));
+
+    let generated = RecordGenerator::new(GenerateOptions { mode, ..Default::default() }).generate(job).result.numbers;
+    // Only a multiset lists a number twice.
+    let ascending = |pair: &[u32]| pair[0] < pair[1] || (mode == Mode::Multiset && pair[0] == pair[1]);
+    check("ascending", generated.windows(2).all(ascending), &|| String::from("numbers out of order"));
+    check(
+        "bounds",
+        generated.iter().all(|&n| (1..=job.end).contains(&n)),
+        &|| String::from("a number outside 1..=end"),
+    );
+    let count = formula::count(&job, mode);
+    check("closed-form count", count == expected.len() as u64, &|| {
+        format!("{} instead of {}", count, expected.len())
+    });
+    let sum = formula::sum(&job, mode);
+    let expected_sum: u128 = expected.iter().map(|&n| n as u128).sum();
+    check("closed-form sum", sum == expected_sum, &|| format!("{} instead of {}", sum, expected_sum));
+}
+
+/// Checks `cases` random jobs within `bounds` in every mode. About one
+/// divisor in twenty is 0 or equal to the other one, which the uniform draw
+/// would rarely hit.
+pub fn run(seed: u64, cases: usize, bounds: JobBounds) -> SelfTestReport {
+    let mut rng = Rng::new(seed);
+    let mut report = SelfTestReport::default();
+    for _ in 0..cases {
+        let a = rng.range(1, bounds.max_divisor as u64) as u32;
+        let b = match rng.range(0, 39) {
+            0 => 0,
+            1 => a,
+            _ => rng.range(1, bounds.max_divisor as u64) as u32,
+        };
+        let end = rng.range(0, bounds.max_end as u64) as u32;
+        let job = LineNumbers { a, b, end };
+        for mode in [Mode::Or, Mode::Xor, Mode::Multiset] {
+            check_job(job, mode, &mut report);
+        }
+        report.cases += 1;
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_reference, run, sieve_reference};
+    use crate::formula::Mode;
+    use crate::random::JobBounds;
+    use crate::LineNumbers;
+
+    #[test]
+    fn test_references() {
+        let job = LineNumbers { a: 4, b: 6, end: 24 };
+        assert_eq!(merge_reference(&job, Mode::Or), vec![4, 6, 8, 12, 16, 18, 20, 24]);
+        assert_eq!(sieve_reference(&job, Mode::Xor), vec![4, 6, 8, 16, 18, 20]);
+        assert_eq!(merge_reference(&job, Mode::Multiset), vec![4, 6, 8, 12, 12, 16, 18, 20, 24, 24]);
+        let zero = LineNumbers { a: 0, b: 0, end: 5 };
+        assert_eq!(merge_reference(&zero, Mode::Or), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn test_run_finds_no_mismatches() {
+        let bounds = JobBounds {
+            max_divisor: 50,
+            max_end: 5000,
+        };
+        let report = run(7, 50, bounds);
+        assert_eq!(report.cases, 50);
+        assert_eq!(report.checks, 50 * 3 * 10);
+        assert!(report.mismatches.is_empty(), "{:?}", report.mismatches);
+    }
+}
@@ -0,0 +1,457 @@
+//! Persisting and transmitting the public data types as JSON, with field
+//! names that stay the same from one release to the next.
+//!
+//! The crate doesn't depend on serde, so the types implement [`ToJson`] and
+//! [`FromJson`] instead, built on the [`json`](crate::json) reader.
+//! [`to_string`] wraps a value in a document stamped with
+//! [`SCHEMA_VERSION`], which [`from_str`] checks:
+//!
+//! ```text
+//! {"schema":1,"value":{"a":3,"b":5,"end":100}}
+//! ```
+//!
+//! Fields may be added to a schema version, and readers skip fields they
+//! don't know; renaming or removing one takes a new version.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::cancel::Status;
+use crate::formula::Mode;
+use crate::json::{self, Value};
+use crate::report::{LineError, RunReport, Timings, Warning};
+use crate::{LineNumbers, ResultNumbers, ResultRecord};
+
+/// The version of the documents [`to_string`] writes. [`from_str`] reads
+/// this and every earlier version.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Writes a value as JSON.
+pub trait ToJson {
+    fn write_json(&self, out: &mut String);
+
+    fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+}
+
+/// Reads a value from JSON written by [`ToJson`].
+pub trait FromJson: Sized {
+    fn from_json(value: &Value) -> Result<Self>;
+}
+
+/// `value` as a document of the current schema version.
+pub fn to_string<T: ToJson + ?Sized>(value: &T) -> String {
+    let mut out = format!("{{\"schema\":{},\"value\":", SCHEMA_VERSION);
+    value.write_json(&mut out);
+    out.push('}');
+    out
+}
+
+/// Reads a document written by [`to_string`] of this or an earlier version.
+pub fn from_str<T: FromJson>(text: &str) -> Result<T> {
+    let document = json::parse(text).context("Invalid JSON document")?;
+    let version: u32 = field(&document, "schema")?;
+    if version > SCHEMA_VERSION {
+        bail!("The document has schema version {}, newer than {} this build reads", version, SCHEMA_VERSION);
+    }
+    field(&document, "value")
+}
+
+/// The field `name` of `object`, with missing fields read as `null`.
+pub fn field<T: FromJson>(object: &Value, name: &str) -> Result<T> {
+    if !matches!(object, Value::Object(_)) {
+        bail!("Expected an object with field `{}`", name);
+    }
+    T::from_json(object.get(name).unwrap_or(&Value::Null)).with_context(|| format!("Invalid field `{}`", name))
+}
+
+/// Writes the fields of an object, see [`object`].
+pub struct Fields<'a> {
+    out: &'a mut String,
+    first: bool,
+}
+
+impl Fields<'_> {
+    pub fn field<T: ToJson + ?Sized>(&mut self, name: &str, value: &T) -> &mut Self {
+        if !self.first {
+            self.out.push(',');
+        }
+        self.first = false;
+        self.out.push_str(&json::quote(name));
+        self.out.push(':');
+        value.write_json(self.out);
+        self
+    }
+}
+
+/// Writes an object whose fields `fields` writes.
+pub fn object(out: &mut String, fields: impl FnOnce(&mut Fields)) {
+    out.push('{');
+    fields(&mut Fields { out, first: true });
+    out.push('}');
+}
+
+macro_rules! integer_json {
+    ($($ty:ty),*) => {$(
+        impl ToJson for $ty {
+            fn write_json(&self, out: &mut String) {
+                out.push_str(&self.to_string());
+            }
+        }
+
+        impl FromJson for $ty {
+            fn from_json(value: &Value) -> Result<Self> {
+                match value {
+                    Value::Number(text) => text.parse().map_err(|_| anyhow!("{} is not a {}", text, stringify!($ty))),
+                    _ => bail!("Expected a number"),
+                }
+            }
+        }
+    )*};
+}
+
+integer_json!(u32, u64, usize);
+
+impl ToJson for str {
+    fn write_json(&self, out: &mut String) {
+        out.push_str(&json::quote(self));
+    }
+}
+
+impl ToJson for String {
+    fn write_json(&self, out: &mut String) {
+        self.as_str().write_json(out);
+    }
+}
+
+impl FromJson for String {
+    fn from_json(value: &Value) -> Result<Self> {
+        match value {
+            Value::String(text) => Ok(text.clone()),
+            _ => bail!("Expected a string"),
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn write_json(&self, out: &mut String) {
+        out.push('[');
+        for (i, item) in self.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            item.write_json(out);
+        }
+        out.push(']');
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn write_json(&self, out: &mut String) {
+        self.as_slice().write_json(out);
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(value: &Value) -> Result<Self> {
+        let items = value.as_array().ok_or_else(|| anyhow!("Expected an array"))?;
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| T::from_json(item).with_context(|| format!("Invalid item {}", i)))
+            .collect()
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn write_json(&self, out: &mut String) {
+        match self {
+            Some(value) => value.write_json(out),
+            None => out.push_str("null"),
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(value: &Value) -> Result<Self> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_json(value).map(Some),
+        }
+    }
+}
+
+/// Durations are whole nanoseconds.
+impl ToJson for Duration {
+    fn write_json(&self, out: &mut String) {
+        out.push_str(&self.as_nanos().to_string());
+    }
+}
+
+impl FromJson for Duration {
+    fn from_json(value: &Value) -> Result<Self> {
+        u64::from_json(value).map(Duration::from_nanos)
+    }
+}
+
+/// Modes are their names, as given to `--mode`.
+impl ToJson for Mode {
+    fn write_json(&self, out: &mut String) {
+        self.name().write_json(out);
+    }
+}
+
+impl FromJson for Mode {
+    fn from_json(value: &Value) -> Result<Self> {
+        String::from_json(value)?.parse()
+    }
+}
+
+impl ToJson for Status {
+    fn write_json(&self, out: &mut String) {
+        match self {
+            Status::Complete => "complete",
+            Status::Cancelled => "cancelled",
+        }
+        .write_json(out);
+    }
+}
+
+impl FromJson for Status {
+    fn from_json(value: &Value) -> Result<Self> {
+        match String::from_json(value)?.as_str() {
+            "complete" => Ok(Status::Complete),
+            "cancelled" => Ok(Status::Cancelled),
+            status => bail!("Unknown status: {}", status),
+        }
+    }
+}
+
+impl ToJson for LineNumbers {
+    fn write_json(&self, out: &mut String) {
+        object(out, |fields| {
+            fields.field("a", &self.a).field("b", &self.b).field("end", &self.end);
+        });
+    }
+}
+
+impl FromJson for LineNumbers {
+    fn from_json(value: &Value) -> Result<Self> {
+        Ok(LineNumbers {
+            a: field(value, "a")?,
+            b: field(value, "b")?,
+            end: field(value, "end")?,
+        })
+    }
+}
+
+/// `count` is left out unless it is set.
+impl ToJson for ResultNumbers {
+    fn write_json(&self, out: &mut String) {
+        object(out, |fields| {
+            fields.field("end", &self.end).field("numbers", &self.numbers);
+            if let Some(count) = &self.count {
+                fields.field("count", count);
+            }
+        });
+    }
+}
+
+impl FromJson for ResultNumbers {
+    fn from_json(value: &Value) -> Result<Self> {
+        Ok(ResultNumbers {
+            end: field(value, "end")?,
+            numbers: field::<Option<_>>(value, "numbers")?.unwrap_or_default(),
+            count: field(value, "count")?,
+        })
+    }
+}
+
+impl ToJson for ResultRecord {
+    fn write_json(&self, out: &mut String) {
+        object(out, |fields| {
+            fields.field("index", &self.index).field("job", &self.job).field("result", &self.result);
+        });
+    }
+}
+
+impl FromJson for ResultRecord {
+    fn from_json(value: &Value) -> Result<Self> {
+        Ok(ResultRecord {
+            index: field(value, "index")?,
+            job: field(value, "job")?,
+            result: field(value, "result")?,
+        })
+    }
+}
+
+impl ToJson for Warning {
+    fn write_json(&self, out: &mut String) {
+        object(out, |fields| {
+            fields.field("line", &self.line).field("message", &self.message);
+        });
+    }
+}
+
+impl FromJson for Warning {
+    fn from_json(value: &Value) -> Result<Self> {
+        Ok(Warning {
+            line: field(value, "line")?,
+            message: field(value, "message")?,
+        })
+    }
+}
+
+/// Errors are written as their message with its causes, and read back as
+/// an error of that message.
+impl ToJson for LineError {
+    fn write_json(&self, out: &mut String) {
+        object(out, |fields| {
+            fields.field("index", &self.index).field("message", &self.to_string());
+        });
+    }
+}
+
+impl FromJson for LineError {
+    fn from_json(value: &Value) -> Result<Self> {
+        Ok(LineError {
+            index: field(value, "index")?,
+            error: anyhow!(field::<String>(value, "message")?),
+        })
+    }
+}
+
+impl ToJson for Timings {
+    fn write_json(&self, out: &mut String) {
+        object(out, |fields| {
+            fields.field("elapsed", &self.elapsed).field("generating", &self.generating).field("writing", &self.writing);
+        });
+    }
+}
+
+impl FromJson for Timings {
+    fn from_json(value: &Value) -> Result<Self> {
+        Ok(Timings {
+            elapsed: field(value, "elapsed")?,
+            generating: field(value, "generating")?,
+            writing: field(value, "writing")?,
+        })
+    }
+}
+
+impl ToJson for RunReport {
+    fn write_json(&self, out: &mut String) {
+        object(out, |fields| {
+            fields
+                .field("records", &self.records)
+                .field("warnings", &self.warnings)
+                .field("errors", &self.errors)
+                .field("timings", &self.timings)
+                .field("status", &self.status);
+        });
+    }
+}
+
+impl FromJson for RunReport {
+    fn from_json(value: &Value) -> Result<Self> {
+        Ok(RunReport {
+            records: field(value, "records")?,
+            warnings: field(value, "warnings")?,
+            errors: field(value, "errors")?,
+            timings: field(value, "timings")?,
+            status: field(value, "status")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{from_str, to_string, FromJson, ToJson};
+    use crate::cancel::Status;
+    use crate::formula::Mode;
+    use crate::json;
+    use crate::report::{LineError, RunReport, Timings, Warning};
+    use crate::{LineNumbers, ResultNumbers, ResultRecord};
+
+    fn round_trip<T: ToJson + FromJson>(value: &T) -> T {
+        from_str(&to_string(value)).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let record = ResultRecord {
+            index: 2,
+            job: LineNumbers { a: 3, b: 5, end: 10 },
+            result: ResultNumbers {
+                end: 10,
+                numbers: vec![3, 5, 6, 9, 10],
+                count: None,
+            },
+        };
+        assert_eq!(round_trip(&record), record);
+        let counted = ResultNumbers {
+            end: 4_000_000_000,
+            numbers: Vec::new(),
+            count: Some(1_866_666_667),
+        };
+        assert_eq!(round_trip(&counted), counted);
+        assert_eq!(round_trip(&vec![Mode::Xor, Mode::Multiset]), [Mode::Xor, Mode::Multiset]);
+
+        let report = RunReport {
+            records: 1,
+            warnings: vec![Warning {
+                line: Some(2),
+                message: String::from("both divisors are \"4\""),
+            }],
+            errors: vec![LineError {
+                index: 3,
+                error: anyhow::anyhow!("Line 4 does not contain exactly 3 numbers"),
+            }],
+            timings: Timings {
+                elapsed: Duration::from_millis(3),
+                generating: Duration::from_nanos(1500),
+                writing: Duration::ZERO,
+            },
+            status: Status::Cancelled,
+        };
+        let read = round_trip(&report);
+        assert_eq!((read.records, &read.warnings, read.timings, read.status), (1, &report.warnings, report.timings, Status::Cancelled));
+        assert_eq!((read.errors[0].index, read.errors[0].to_string()), (3, report.errors[0].to_string()));
+    }
+
+    /// Documents of schema version 1 as they were first written, which
+    /// every later build must read the same.
+    #[test]
+    fn test_version_1_documents() {
+        let job: LineNumbers = from_str(r#"{"schema":1,"value":{"a":3,"b":5,"end":100}}"#).unwrap();
+        assert_eq!(job, LineNumbers { a: 3, b: 5, end: 100 });
+        assert_eq!(job.to_json(), r#"{"a":3,"b":5,"end":100}"#);
+        let record: ResultRecord = from_str(
+            r#"{"schema":1,"value":{"index":0,"job":{"a":2,"b":3,"end":4},"result":{"end":4,"numbers":[2,3,4]}}}"#,
+        )
+        .unwrap();
+        assert_eq!(record.result.numbers, [2, 3, 4]);
+        assert_eq!(
+            to_string(&record),
+            r#"{"schema":1,"value":{"index":0,"job":{"a":2,"b":3,"end":4},"result":{"end":4,"numbers":[2,3,4]}}}"#
+        );
+        let report: RunReport = from_str(
+            r#"{"schema":1,"value":{"records":0,"warnings":[{"line":null,"message":"m"}],"errors":[],
+                "timings":{"elapsed":10,"generating":5,"writing":1},"status":"complete","future":true}}"#,
+        )
+        .unwrap();
+        assert_eq!((report.warnings[0].line, report.timings.elapsed, report.status), (None, Duration::from_nanos(10), Status::Complete));
+
+        let err = from_str::<LineNumbers>(r#"{"schema":2,"value":{}}"#).unwrap_err();
+        assert_eq!(err.to_string(), "The document has schema version 2, newer than 1 this build reads");
+        let err = from_str::<LineNumbers>(r#"{"schema":1,"value":{"a":3,"b":-5,"end":100}}"#).unwrap_err();
+        assert_eq!(format!("{:#}", err), "Invalid field `value`: Invalid field `b`: -5 is not a u32");
+        assert!(LineNumbers::from_json(&json::parse("[3, 5, 100]").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,1581 @@
+//! The HTTP mode started by `serve`.
+//!
+//! `GET /multiples?a=2&b=3&end=100` answers with the multiples of one job in
+//! any output format. Requests are checked before any work is done: every
+//! client gets a token bucket of requests, `end` is capped and the expected
+//! size of the response is weighed against a limit, so that an instance can
+//! be exposed beyond localhost. Rejections are `application/problem+json`
+//! documents as described in RFC 9457.
+//!
+//! With API keys or JWT keys configured, every endpoint but `/health` needs
+//! credentials (see [`crate::auth`]), and `/metrics` counts the requests of
+//! each identity in the Prometheus text format. `/openapi.json` describes
+//! the endpoints, built from the same parameter table requests are checked
+//! against.
+//!
+//! `POST /batch` runs all jobs of an uploaded input file through the
+//! pipeline. With `async=true` it answers at once with a job to poll at
+//! `/jobs/{id}`, which holds the results once the batch is done. Uploads
+//! are held against the [limits](crate::limits) of the input before any of
+//! it is parsed.
+//!
+//! Results can be fetched in pieces, for clients on connections that drop:
+//! `offset` and `limit` page through the multiples of `/multiples` and the
+//! records of `/jobs/{id}`, and both answer `Range` requests for the bytes
+//! of the answer, with an `ETag` to resume against.
+//!
+//! With an [exporter](crate::otlp) every request is sent to a collector as
+//! a span once it is answered.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{self, BufReader, Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::auth::Authenticator;
+use crate::cache::{LruCache, SingleFlight};
+use crate::errors::{self, ErrorCode};
+use crate::formatter::FormatterRegistry;
+use crate::formula::{self, Mode};
+use crate::http::{self, byte_range, multipart_boundary, multipart_parts, ByteRange, Request, Response};
+use crate::input::InputParserRegistry;
+use crate::json;
+use crate::jobfilter::JobFilter;
+use crate::limits::InputLimits;
+use crate::number;
+use crate::otlp::{Attribute, Operation, OtlpExporter};
+use crate::pipeline::{jobs_source, CollectSink, Pipeline};
+use crate::random::Rng;
+use crate::sha256::{hex, sha256};
+use crate::strategy::{GenerateOptions, RecordGenerator};
+use crate::throttle;
+use crate::{LineNumbers, ResultNumbers, ResultRecord};
+
+/// Batches started with `async=true` that may run at once.
+const MAX_RUNNING_BATCHES: usize = 16;
+
+/// Finished batches kept for polling; the oldest are dropped first.
+const MAX_FINISHED_BATCHES: usize = 100;
+
+/// Invalid input lines listed in one problem document.
+const MAX_REPORTED_ERRORS: usize = 20;
+
+/// Token buckets kept before idle ones are dropped.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long `serve_until` waits between looking for new connections.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long writing the `503` of a connection beyond `max_connections` may take.
+const REFUSAL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// `requests` per `period` for each client, e.g. `60/min`. A client may
+/// use up its whole allowance at once; it then refills evenly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+impl FromStr for RateLimit {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let (requests, unit) = text
+            .split_once('/')
+            .ok_or_else(|| anyhow!("Invalid rate limit: {} (expected e.g. 60/min)", text))?;
+        let requests = number::parse_u32(requests)?;
+        if requests == 0 {
+            bail!("Invalid rate limit: {} (must allow at least one request)", text);
+        }
+        let period = match unit {
+            "s" | "sec" | "second" => Duration::from_secs(1),
+            "min" | "minute" => Duration::from_secs(60),
+            "h" | "hour" => Duration::from_secs(3600),
+            _ => bail!("Unknown rate limit period: {} (available: s, min, h)", unit),
+        };
+        Ok(RateLimit { requests, period })
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// The token buckets of the clients seen.
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: HashMap<IpAddr, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Takes a token from `client`'s bucket, or returns how long until
+    /// the next one is available.
+    pub fn check(&mut self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let capacity = self.limit.requests as f64;
+        let per_token = self.limit.period.as_secs_f64() / capacity;
+        if self.buckets.len() >= MAX_TRACKED_CLIENTS {
+            // Buckets that have refilled completely are the same as new ones.
+            let full = |bucket: &Bucket| now.duration_since(bucket.updated).as_secs_f64() / per_token + bucket.tokens >= capacity;
+            self.buckets.retain(|_, bucket| !full(bucket));
+        }
+        let bucket = self.buckets.entry(client).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let refill = now.duration_since(bucket.updated).as_secs_f64() / per_token;
+        bucket.tokens = (bucket.tokens + refill).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) * per_token))
+        }
+    }
+}
+
+/// What `serve` accepts.
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// Largest `end` a request may ask for.
+    pub max_end: u32,
+    /// Largest response body, in bytes.
+    pub max_response_size: u64,
+    pub rate_limit: Option<RateLimit>,
+    pub auth: Authenticator,
+    /// Largest request body, in bytes, which bounds the input of a batch.
+    pub max_request_size: usize,
+    /// The limits of the input of a batch. JSON arrays, which have no lines,
+    /// hold their number of jobs against `max_lines`.
+    pub limits: InputLimits,
+    /// Generator threads of each batch.
+    pub threads: usize,
+    /// Bytes of `/multiples` answers kept in memory for repeated queries;
+    /// 0 turns the cache off.
+    pub cache_size: u64,
+    /// How long a cached answer is served for, or as long as it isn't
+    /// pushed out by newer ones.
+    pub cache_ttl: Option<Duration>,
+    /// Where the spans of requests are exported, if anywhere.
+    pub otlp: Option<OtlpExporter>,
+    /// Connections answered at once; the ones beyond get `503`.
+    pub max_connections: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            max_end: 10_000_000,
+            max_response_size: 16_000_000,
+            rate_limit: None,
+            auth: Authenticator::default(),
+            max_request_size: 1_000_000,
+            limits: InputLimits::untrusted(),
+            threads: throttle::default_threads(),
+            cache_size: 64_000_000,
+            cache_ttl: None,
+            otlp: None,
+            max_connections: 256,
+        }
+    }
+}
+
+/// An RFC 9457 problem document.
+#[derive(Clone, Debug)]
+pub struct Problem {
+    pub status: u16,
+    pub title: &'static str,
+    pub detail: String,
+    /// The parameters at fault and what is wrong with each.
+    pub errors: Vec<(String, String)>,
+    /// The code of the error, see [`errors`](crate::errors).
+    pub code: Option<&'static ErrorCode>,
+}
+
+impl Problem {
+    pub fn new(status: u16, title: &'static str, detail: impl Into<String>) -> Self {
+        Problem {
+            status,
+            title,
+            detail: detail.into(),
+            errors: Vec::new(),
+            code: None,
+        }
+    }
+
+    /// The problem of `err`, with its causes as the detail and its code.
+    pub fn from_error(status: u16, title: &'static str, err: &anyhow::Error) -> Self {
+        Problem {
+            code: errors::code_of(err),
+            ..Problem::new(status, title, format!("{:#}", err))
+        }
+    }
+
+    pub fn into_response(self) -> Response {
+        let mut body = format!(
+            "{{\"type\":\"about:blank\",\"title\":{},\"status\":{},\"detail\":{}",
+            json::quote(self.title),
+            self.status,
+            json::quote(&self.detail)
+        );
+        if let Some(code) = self.code {
+            body.push_str(&format!(",\"code\":\"{}\"", code));
+        }
+        if !self.errors.is_empty() {
+            let errors: Vec<String> = self
+                .errors
+                .iter()
+                .map(|(parameter, detail)| format!("{{\"parameter\":{},\"detail\":{}}}", json::quote(parameter), json::quote(detail)))
+                .collect();
+            body.push_str(&format!(",\"errors\":[{}]", errors.join(",")));
+        }
+        body.push('}');
+        Response::new(self.status, "application/problem+json", body)
+    }
+}
+
+/// How an answer lists its records.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct AnswerOptions {
+    mode: Mode,
+    count_only: bool,
+    format: String,
+}
+
+/// A query parameter of an endpoint.
+pub struct Parameter {
+    pub name: &'static str,
+    pub required: bool,
+    /// Its JSON schema; the list of formats is added to that of `format`.
+    pub schema: &'static str,
+    pub description: &'static str,
+}
+
+const MODE_PARAMETER: Parameter = Parameter {
+    name: "mode",
+    required: false,
+    schema: r#"{"type":"string","enum":["or","xor","multiset"],"default":"or"}"#,
+    description: "Which multiples are selected",
+};
+
+const COUNT_ONLY_PARAMETER: Parameter = Parameter {
+    name: "count_only",
+    required: false,
+    schema: r#"{"type":"boolean","default":false}"#,
+    description: "Answer with the number of multiples only",
+};
+
+const FORMAT_PARAMETER: Parameter = Parameter {
+    name: "format",
+    required: false,
+    schema: r#"{"type":"string","default":"json"}"#,
+    description: "The output format of the answer",
+};
+
+const OFFSET_PARAMETER: Parameter = Parameter {
+    name: "offset",
+    required: false,
+    schema: r#"{"type":"integer","minimum":0,"default":0}"#,
+    description: "Items to skip: multiples for /multiples, records for /jobs/{id}",
+};
+
+const LIMIT_PARAMETER: Parameter = Parameter {
+    name: "limit",
+    required: false,
+    schema: r#"{"type":"integer","minimum":0}"#,
+    description: "Most items to list; by default all from offset on",
+};
+
+pub const MULTIPLES_PARAMETERS: [Parameter; 8] = [
+    Parameter {
+        name: "a",
+        required: true,
+        schema: r#"{"type":"integer","minimum":1,"maximum":4294967295}"#,
+        description: "The first divisor",
+    },
+    Parameter {
+        name: "b",
+        required: true,
+        schema: r#"{"type":"integer","minimum":1,"maximum":4294967295}"#,
+        description: "The second divisor",
+    },
+    Parameter {
+        name: "end",
+        required: true,
+        schema: r#"{"type":"integer","minimum":0}"#,
+        description: "The last number tested; capped by --max-end",
+    },
+    MODE_PARAMETER,
+    COUNT_ONLY_PARAMETER,
+    FORMAT_PARAMETER,
+    OFFSET_PARAMETER,
+    LIMIT_PARAMETER,
+];
+
+pub const JOB_PARAMETERS: [Parameter; 2] = [OFFSET_PARAMETER, LIMIT_PARAMETER];
+
+pub const BATCH_PARAMETERS: [Parameter; 6] = [
+    Parameter {
+        name: "input_format",
+        required: false,
+        schema: r#"{"type":"string","enum":["text","csv","json","ndjson"]}"#,
+        description: "The format of the uploaded input; by default taken from its Content-Type or file name",
+    },
+    MODE_PARAMETER,
+    COUNT_ONLY_PARAMETER,
+    FORMAT_PARAMETER,
+    Parameter {
+        name: "async",
+        required: false,
+        schema: r#"{"type":"boolean","default":false}"#,
+        description: "Answer at once with a job to poll at /jobs/{id} instead of waiting for the results",
+    },
+    Parameter {
+        name: "require",
+        required: false,
+        schema: r#"{"type":"string","example":"coprime,a<b"}"#,
+        description: "Comma separated requirements (coprime, a-prime, b-prime, a<b) a job must meet to be run",
+    },
+];
+
+/// The output formats answers can be written in.
+fn response_formats() -> Vec<String> {
+    let registry = FormatterRegistry::with_builtins();
+    registry.names().filter(|&name| name != "store").map(String::from).collect()
+}
+
+const PROBLEM_SCHEMA: &str = r#"{"type":"object","required":["type","title","status"],"properties":{"type":{"type":"string"},"title":{"type":"string"},"status":{"type":"integer"},"detail":{"type":"string"},"code":{"type":"string","pattern":"^MP[0-9]{4}$"},"errors":{"type":"array","items":{"type":"object","properties":{"parameter":{"type":"string"},"detail":{"type":"string"}}}}}}"#;
+
+const RECORD_SCHEMA: &str = r#"{"type":"object","required":["schema","index","a","b","end","count"],"properties":{"schema":{"type":"integer"},"index":{"type":"integer"},"a":{"type":"integer"},"b":{"type":"integer"},"end":{"type":"integer"},"count":{"type":"integer"},"numbers":{"type":"array","items":{"type":"integer"}}}}"#;
+
+const JOB_SCHEMA: &str = r#"{"type":"object","required":["id","status"],"properties":{"id":{"type":"string"},"status":{"type":"string","enum":["running"]},"location":{"type":"string"}}}"#;
+
+fn parameters_json(parameters: &[Parameter]) -> String {
+    let quoted_formats: Vec<String> = response_formats().iter().map(|name| json::quote(name)).collect();
+    let parameters: Vec<String> = parameters
+        .iter()
+        .map(|parameter| {
+            let schema = match parameter.name {
+                "format" => format!("{},\"enum\":[{}]}}", parameter.schema.trim_end_matches('}'), quoted_formats.join(",")),
+                _ => parameter.schema.to_string(),
+            };
+            format!(
+                "{{\"name\":{},\"in\":\"query\",\"required\":{},\"description\":{},\"schema\":{}}}",
+                json::quote(parameter.name),
+                parameter.required,
+                json::quote(parameter.description),
+                schema
+            )
+        })
+        .collect();
+    format!("[{}]", parameters.join(","))
+}
+
+/// `"status":{…}` for a response whose body has `schema`.
+fn response_json(status: u16, description: &str, content_type: &str, schema: &str) -> String {
+    format!(
+        "\"{}\":{{\"description\":{},\"content\":{{{}:{{\"schema\":{}}}}}}}",
+        status,
+        json::quote(description),
+        json::quote(content_type),
+        schema
+    )
+}
+
+/// The OpenAPI 3 description of the endpoints, with the security schemes
+/// `auth` accepts.
+pub fn openapi(auth: &Authenticator) -> String {
+    let problem = |status: u16, description: &str| {
+        response_json(status, description, "application/problem+json", r##"{"$ref":"#/components/schemas/Problem"}"##)
+    };
+    let records = r##"{"type":"array","items":{"$ref":"#/components/schemas/Record"}}"##;
+    let job = r##"{"$ref":"#/components/schemas/Job"}"##;
+    let (mut schemes, mut security) = (Vec::new(), Vec::new());
+    if auth.accepts_api_keys() {
+        schemes.push(r#""apiKey":{"type":"apiKey","in":"header","name":"X-API-Key"}"#);
+        security.push(r#"{"apiKey":[]}"#);
+    }
+    if auth.accepts_bearer_tokens() {
+        schemes.push(r#""bearer":{"type":"http","scheme":"bearer","bearerFormat":"JWT"}"#);
+        security.push(r#"{"bearer":[]}"#);
+    }
+    // The answers every authenticated endpoint may give.
+    let mut refused = Vec::new();
+    if auth.is_enabled() {
+        refused.push(problem(401, "Missing or invalid credentials"));
+    }
+    refused.push(problem(429, "Rate limit exceeded; see Retry-After"));
+    let responses = |mut responses: Vec<String>| {
+        responses.extend(refused.iter().cloned());
+        format!("{{{}}}", responses.join(","))
+    };
+
+    let multiples = format!(
+        r#"{{"get":{{"operationId":"generate","summary":"The multiples of a or b up to end","parameters":{},"responses":{}}}}}"#,
+        parameters_json(&MULTIPLES_PARAMETERS),
+        responses(vec![
+            response_json(200, "The record of the job, in the requested format", "application/json", records),
+            response_json(206, "The requested bytes of the answer", "application/json", records),
+            problem(400, "Invalid parameters"),
+            problem(413, "The response would exceed --max-response-size"),
+            problem(416, "The range lies beyond the answer"),
+        ])
+    );
+    let batch = format!(
+        r#"{{"post":{{"operationId":"batch","summary":"The records of every job of an input file","parameters":{},"requestBody":{{"required":true,"content":{{"text/plain":{{"schema":{{"type":"string"}}}},"text/csv":{{"schema":{{"type":"string"}}}},"application/json":{{"schema":{{"type":"array"}}}},"application/x-ndjson":{{"schema":{{"type":"string"}}}},"multipart/form-data":{{"schema":{{"type":"object","properties":{{"input":{{"type":"string","format":"binary"}}}}}}}}}}}},"responses":{}}}}}"#,
+        parameters_json(&BATCH_PARAMETERS),
+        responses(vec![
+            response_json(200, "The records of the jobs in input order, in the requested format", "application/json", records),
+            response_json(202, "The batch is running, see Location", "application/json", job),
+            problem(400, "Invalid parameters or input"),
+            problem(413, "The request, its input or the response is too large"),
+            problem(503, "Too many batches are running"),
+        ])
+    );
+    let jobs = format!(
+        r#"{{"get":{{"operationId":"job","summary":"The results of a batch started with async=true","parameters":[{{"name":"id","in":"path","required":true,"schema":{{"type":"string"}}}},{}],"responses":{}}}}}"#,
+        parameters_json(&JOB_PARAMETERS).trim_start_matches('[').trim_end_matches(']'),
+        responses(vec![
+            response_json(200, "The records of the finished batch, in the requested format", "application/json", records),
+            response_json(202, "The batch is still running", "application/json", job),
+            response_json(206, "The requested bytes of the answer", "application/json", records),
+            problem(400, "Invalid parameters"),
+            problem(404, "No such job"),
+            problem(413, "The response would exceed --max-response-size"),
+            problem(416, "The range lies beyond the answer"),
+        ])
+    );
+    let metrics = format!(
+        r#"{{"get":{{"operationId":"metrics","summary":"Requests answered per identity, in the Prometheus text format","responses":{}}}}}"#,
+        responses(vec![response_json(200, "The counters", "text/plain", r#"{"type":"string"}"#)])
+    );
+    let health = r#"{"get":{"operationId":"health","summary":"Whether the server is up","responses":{"200":{"description":"ok"}},"security":[]}}"#;
+    let spec = r#"{"get":{"operationId":"openapi","summary":"This document","responses":{"200":{"description":"The OpenAPI document"}},"security":[]}}"#;
+    format!(
+        concat!(
+            r#"{{"openapi":"3.0.3","info":{{"title":"multiple_of_a_and_b","version":"{}"}},"#,
+            r#""paths":{{"/multiples":{},"/batch":{},"/jobs/{{id}}":{},"/metrics":{},"/health":{},"/openapi.json":{}}},"#,
+            r#""components":{{"schemas":{{"Record":{},"Job":{},"Problem":{}}},"securitySchemes":{{{}}}}},"security":[{}]}}"#
+        ),
+        env!("CARGO_PKG_VERSION"),
+        multiples,
+        batch,
+        jobs,
+        metrics,
+        health,
+        spec,
+        RECORD_SCHEMA,
+        JOB_SCHEMA,
+        PROBLEM_SCHEMA,
+        schemes.join(","),
+        security.join(",")
+    )
+}
+
+fn content_type(format: &str) -> &'static str {
+    match format {
+        "json" => "application/json",
+        "ndjson" => "application/x-ndjson",
+        "csv" => "text/csv",
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+/// The input format named by a `Content-Type`, or the text format.
+fn input_format_of(content_type: Option<&str>) -> &'static str {
+    let essence = content_type.unwrap_or_default().split(';').next().unwrap_or_default().trim();
+    match essence.to_ascii_lowercase().as_str() {
+        "text/csv" => "csv",
+        "application/json" => "json",
+        "application/x-ndjson" | "application/ndjson" | "application/jsonl" => "ndjson",
+        _ => "text",
+    }
+}
+
+fn format_records(format: &str, records: &[ResultRecord]) -> Result<Vec<u8>, Problem> {
+    let mut body = Vec::new();
+    let formatted = FormatterRegistry::with_builtins().create(format).and_then(|mut formatter| {
+        formatter.begin(&mut body)?;
+        for record in records {
+            formatter.write_record(&mut body, record)?;
+        }
+        formatter.finish(&mut body)
+    });
+    match formatted {
+        Ok(()) => Ok(body),
+        Err(err) => Err(Problem::from_error(500, "Internal error", &err)),
+    }
+}
+
+/// Generates the records of `jobs` on `threads` workers, in input order.
+fn run_batch(jobs: Vec<LineNumbers>, options: &AnswerOptions, threads: usize) -> Result<Vec<ResultRecord>, Problem> {
+    let generator = RecordGenerator::new(GenerateOptions {
+        mode: options.mode,
+        count_only: options.count_only,
+        ..Default::default()
+    });
+    let mut records = CollectSink::default();
+    let run = Pipeline::builder(jobs_source(jobs))
+        .workers(threads)
+        .generator(move |job| Ok(generator.generate(job)))
+        .build()
+        .run(&mut records);
+    match run {
+        Ok(_) => Ok(records.records),
+        Err(err) => Err(Problem::from_error(500, "Internal error", &err)),
+    }
+}
+
+/// `records` in the format of `options`, unless they take more than
+/// `max_response_size` bytes.
+fn answer(records: &[ResultRecord], options: &AnswerOptions, max_response_size: u64) -> Response {
+    match format_records(&options.format, records) {
+        Ok(body) if body.len() as u64 > max_response_size => {
+            let detail = format!(
+                "The results exceed the {} byte response limit; ask for count_only=true or fewer records with limit",
+                max_response_size
+            );
+            Problem::new(413, "Response too large", detail).into_response()
+        }
+        Ok(body) => Response::new(200, content_type(&options.format), body),
+        Err(problem) => problem.into_response(),
+    }
+}
+
+/// The slice of the items of an answer that `offset` and `limit` select.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+struct Page {
+    offset: u64,
+    limit: Option<u64>,
+}
+
+impl Page {
+    fn is_whole(&self) -> bool {
+        *self == Page::default()
+    }
+
+    /// How many of `total` items the page holds.
+    fn len(&self, total: u64) -> u64 {
+        total.saturating_sub(self.offset).min(self.limit.unwrap_or(u64::MAX))
+    }
+
+    fn range(&self, total: usize) -> std::ops::Range<usize> {
+        let start = (self.offset as usize).min(total);
+        start..start + self.len(total as u64) as usize
+    }
+}
+
+/// Serves the bytes a `Range` header asks for of a complete answer, and
+/// tells clients that they may ask.
+fn ranged(request: &Request, response: Response) -> Response {
+    if response.status != 200 {
+        return response;
+    }
+    let etag = format!("\"{}\"", hex(&sha256(&response.body)[..16]));
+    let response = response.with_header("Accept-Ranges", "bytes").with_header("ETag", &etag);
+    let Some(range) = request.header("range") else {
+        return response;
+    };
+    // A resumed download whose answer changed starts over.
+    if request.header("if-range").is_some_and(|tag| tag != etag) {
+        return response;
+    }
+    let len = response.body.len();
+    match byte_range(range, len) {
+        ByteRange::Whole => response,
+        ByteRange::Part(start, end) => {
+            let mut partial = response;
+            partial.status = 206;
+            partial.body = partial.body[start..=end].to_vec();
+            partial.with_header("Content-Range", format!("bytes {}-{}/{}", start, end, len))
+        }
+        ByteRange::Unsatisfiable => {
+            let detail = format!("The answer has {} bytes", len);
+            let problem = Problem::new(416, "Range not satisfiable", detail).into_response();
+            problem.with_header("Content-Range", format!("bytes */{}", len))
+        }
+    }
+}
+
+/// A batch started with `async=true`.
+struct BatchJob {
+    /// The identity that started it, the only one that may poll it.
+    owner: String,
+    options: AnswerOptions,
+    /// Set once the batch is done: its records, or the error it stopped at.
+    result: Option<Result<Vec<ResultRecord>, Response>>,
+}
+
+#[derive(Default)]
+struct BatchJobs {
+    jobs: HashMap<String, BatchJob>,
+    /// Finished jobs, oldest first, dropped beyond [`MAX_FINISHED_BATCHES`].
+    finished: VecDeque<String>,
+}
+
+impl BatchJobs {
+    fn running(&self) -> usize {
+        self.jobs.len() - self.finished.len()
+    }
+
+    fn finish(&mut self, id: &str, result: Result<Vec<ResultRecord>, Response>) {
+        if let Some(job) = self.jobs.get_mut(id) {
+            job.result = Some(result);
+            self.finished.push_back(id.to_string());
+        }
+        while self.finished.len() > MAX_FINISHED_BATCHES {
+            if let Some(oldest) = self.finished.pop_front() {
+                self.jobs.remove(&oldest);
+            }
+        }
+    }
+}
+
+fn running_job(id: &str) -> Response {
+    let location = format!("/jobs/{}", id);
+    let body = format!(
+        "{{\"id\":{},\"status\":\"running\",\"location\":{}}}",
+        json::quote(id),
+        json::quote(&location)
+    );
+    Response::new(202, "application/json", body).with_header("Location", location).with_header("Retry-After", 1)
+}
+
+/// A `/multiples` query, as the cache knows it.
+type CacheKey = (LineNumbers, AnswerOptions, Page);
+
+/// Answers requests; shared by the connection threads.
+pub struct Server {
+    config: ServerConfig,
+    limiter: Option<Mutex<RateLimiter>>,
+    /// Recent `/multiples` answers, unless the cache is off.
+    cache: Option<Mutex<LruCache<CacheKey, Response>>>,
+    /// `/multiples` answers being generated, for requests asking the same.
+    flights: SingleFlight<CacheKey, Result<Response, Problem>>,
+    /// Requests answered, by identity and status.
+    usage: Mutex<BTreeMap<(String, u16), u64>>,
+    openapi: String,
+    batches: Arc<Mutex<BatchJobs>>,
+    ids: Mutex<Rng>,
+}
+
+impl Server {
+    pub fn new(config: ServerConfig) -> Self {
+        let limiter = config.rate_limit.map(|limit| Mutex::new(RateLimiter::new(limit)));
+        let openapi = openapi(&config.auth);
+        let cache = (config.cache_size > 0).then(|| Mutex::new(LruCache::new(config.cache_size, config.cache_ttl)));
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+        Server {
+            config,
+            limiter,
+            cache,
+            flights: SingleFlight::default(),
+            usage: Mutex::default(),
+            openapi,
+            batches: Arc::default(),
+            ids: Mutex::new(Rng::new(seed ^ std::process::id() as u64)),
+        }
+    }
+
+    /// Answers `request` from `client`.
+    pub fn handle(&self, request: &Request, client: IpAddr) -> Response {
+        let (identity, response) = self.route(request, client);
+        let mut usage = self.usage.lock().unwrap_or_else(|err| err.into_inner());
+        *usage.entry((identity, response.status)).or_default() += 1;
+        response
+    }
+
+    fn route(&self, request: &Request, client: IpAddr) -> (String, Response) {
+        if let Some(limiter) = &self.limiter {
+            let checked = limiter.lock().unwrap_or_else(|err| err.into_inner()).check(client, Instant::now());
+            if let Err(wait) = checked {
+                let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+                let detail = format!("Rate limit exceeded; retry in {} s", seconds);
+                let response = Problem::new(429, "Too many requests", detail).into_response();
+                return (String::from("unauthenticated"), response.with_header("Retry-After", seconds));
+            }
+        }
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/health") => {
+                return (String::from("anonymous"), Response::new(200, "text/plain; charset=utf-8", "ok\n"));
+            }
+            ("GET", "/openapi.json") => {
+                return (String::from("anonymous"), Response::new(200, "application/json", self.openapi.clone()));
+            }
+            _ => {}
+        }
+        let identity = match self.config.auth.authenticate(request) {
+            Ok(identity) => identity,
+            Err(err) => {
+                let response = Problem::new(401, "Unauthorized", err.0).into_response();
+                return (String::from("unauthenticated"), response.with_header("WWW-Authenticate", "Bearer"));
+            }
+        };
+        let method = match request.path.as_str() {
+            "/batch" => "POST",
+            "/health" | "/metrics" | "/multiples" | "/openapi.json" => "GET",
+            path if path.starts_with("/jobs/") => "GET",
+            _ => {
+                let response = Problem::new(404, "Not found", format!("No endpoint at {}", request.path)).into_response();
+                return (identity, response);
+            }
+        };
+        if request.method != method {
+            let detail = format!("{} only answers {}", request.path, method);
+            let response = Problem::new(405, "Method not allowed", detail).into_response();
+            return (identity, response.with_header("Allow", method));
+        }
+        let response = match request.path.as_str() {
+            "/metrics" => Ok(Response::new(200, "text/plain; version=0.0.4", self.metrics())),
+            "/batch" => self.batch(request, &identity),
+            "/multiples" => self.multiples(request).map(|response| ranged(request, response)),
+            path => self.job(request, &path["/jobs/".len()..], &identity).map(|response| ranged(request, response)),
+        };
+        (identity, response.unwrap_or_else(Problem::into_response))
+    }
+
+    /// The usage counters in the Prometheus text format.
+    pub fn metrics(&self) -> String {
+        let mut out = String::from(
+            "# HELP multiples_requests_total Requests answered, by API key or token subject and status.\n\
+             # TYPE multiples_requests_total counter\n",
+        );
+        let usage = self.usage.lock().unwrap_or_else(|err| err.into_inner());
+        for ((identity, status), count) in usage.iter() {
+            let identity = identity.replace('\\', "\\\\").replace('"', "\\\"");
+            out.push_str(&format!("multiples_requests_total{{key=\"{}\",status=\"{}\"}} {}\n", identity, status, count));
+        }
+        let flights = self.flights.stats();
+        out.push_str(&format!(
+            "# HELP multiples_coalesced_requests_total Requests answered by the generation of an identical concurrent request.\n\
+             # TYPE multiples_coalesced_requests_total counter\n\
+             multiples_coalesced_requests_total {}\n\
+             # HELP multiples_generations_in_flight Answers being generated right now.\n\
+             # TYPE multiples_generations_in_flight gauge\n\
+             multiples_generations_in_flight {}\n",
+            flights.coalesced, flights.in_flight
+        ));
+        if let Some(cache) = &self.cache {
+            let stats = cache.lock().unwrap_or_else(|err| err.into_inner()).stats();
+            let metrics = [
+                ("cache_hits_total", "counter", "Answers served from the cache.", stats.hits),
+                ("cache_misses_total", "counter", "Answers that had to be generated.", stats.misses),
+                ("cache_evictions_total", "counter", "Cached answers dropped to make room.", stats.evictions),
+                ("cache_entries", "gauge", "Answers in the cache.", stats.entries as u64),
+                ("cache_bytes", "gauge", "Bytes of the answers in the cache.", stats.size),
+            ];
+            for (name, kind, help, value) in metrics {
+                out.push_str(&format!(
+                    "# HELP multiples_{0} {1}\n# TYPE multiples_{0} {2}\nmultiples_{0} {3}\n",
+                    name, help, kind, value
+                ));
+            }
+        }
+        out
+    }
+
+    /// Answers `/multiples` from the cache if the same query was answered
+    /// recently, and keeps successful answers for the next time. Identical
+    /// queries arriving while one is generated wait for its answer.
+    fn multiples(&self, request: &Request) -> Result<Response, Problem> {
+        let key = self.validate(request)?;
+        let Some(cache) = &self.cache else {
+            return self.flights.run(&key, || self.answer(&key));
+        };
+        let lock = || cache.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(response) = lock().get(&key, Instant::now()) {
+            return Ok(response);
+        }
+        self.flights.run(&key, || {
+            let response = self.answer(&key)?;
+            // Cached before the flight lands, so that no request in between
+            // generates it again.
+            let headers: usize = response.headers.iter().map(|(name, value)| name.len() + value.len()).sum();
+            let size = (response.body.len() + headers) as u64;
+            lock().insert(key.clone(), response.clone(), size, Instant::now());
+            Ok(response)
+        })
+    }
+
+    fn answer(&self, (job, options, page): &CacheKey) -> Result<Response, Problem> {
+        let (job, page) = (*job, *page);
+        let total = formula::count(&job, options.mode);
+        let expected = page.len(total);
+        // Every listed multiple takes at least a digit and a separator.
+        if !options.count_only && expected.saturating_mul(2) > self.config.max_response_size {
+            return Err(self.too_large(expected));
+        }
+        let record = if page.is_whole() {
+            let generator = RecordGenerator::new(GenerateOptions {
+                mode: options.mode,
+                count_only: options.count_only,
+                ..Default::default()
+            });
+            generator.generate(job)
+        } else {
+            let numbers = formula::page(&job, options.mode, page.offset, expected);
+            let result = ResultNumbers {
+                end: job.end,
+                numbers,
+                count: None,
+            };
+            ResultRecord { index: 0, job, result }
+        };
+        let body = format_records(&options.format, &[record])?;
+        if body.len() as u64 > self.config.max_response_size {
+            return Err(self.too_large(expected));
+        }
+        let response = Response::new(200, content_type(&options.format), body);
+        Ok(if page.is_whole() { response } else { response.with_header("X-Total-Count", total) })
+    }
+
+    fn too_large(&self, count: u64) -> Problem {
+        let detail = format!(
+            "The {} multiples would exceed the {} byte response limit; ask for count_only=true, \
+             fewer of them with offset and limit, or a smaller end",
+            count, self.config.max_response_size
+        );
+        Problem::new(413, "Response too large", detail)
+    }
+
+    /// Checks the query of a `/multiples` request, collecting every problem
+    /// with it rather than stopping at the first.
+    fn validate(&self, request: &Request) -> Result<(LineNumbers, AnswerOptions, Page), Problem> {
+        let mut errors = Vec::new();
+        let mut error = |parameter: &str, detail: String| errors.push((parameter.to_string(), detail));
+        let param = |name: &str| query_parameter(request, name);
+        check_parameters(request, &MULTIPLES_PARAMETERS, &mut error);
+        let mut numbers = [0; 3];
+        for (value, name) in numbers.iter_mut().zip(["a", "b", "end"]) {
+            match param(name).map(number::parse_u32) {
+                None => error(name, String::from("Missing")),
+                Some(Err(err)) => error(name, format!("{:#}", err)),
+                Some(Ok(0)) if name != "end" => error(name, String::from("Must be at least 1")),
+                Some(Ok(end)) if name == "end" && end > self.config.max_end => {
+                    error(name, format!("Must be at most {}", self.config.max_end))
+                }
+                Some(Ok(number)) => *value = number,
+            }
+        }
+        let options = answer_options(request, &mut error);
+        let page = page(request, &mut error);
+        if options.count_only && !page.is_whole() {
+            error("count_only", String::from("Can't be combined with offset or limit"));
+        }
+        invalid_if_any(errors, "The query has invalid parameters")?;
+        let [a, b, end] = numbers;
+        Ok((LineNumbers { a, b, end }, options, page))
+    }
+
+    /// Runs the jobs of the uploaded input, or starts doing so for
+    /// `async=true`.
+    fn batch(&self, request: &Request, identity: &str) -> Result<Response, Problem> {
+        let mut errors = Vec::new();
+        let mut error = |parameter: &str, detail: String| errors.push((parameter.to_string(), detail));
+        check_parameters(request, &BATCH_PARAMETERS, &mut error);
+        let options = answer_options(request, &mut error);
+        let background = match query_parameter(request, "async") {
+            None | Some("false") | Some("0") => false,
+            Some("true") | Some("1") | Some("") => true,
+            Some(value) => {
+                error("async", format!("Expected true or false, got {:?}", value));
+                false
+            }
+        };
+        let registry = InputParserRegistry::with_builtins();
+        let explicit = query_parameter(request, "input_format");
+        if let Some(format) = explicit.filter(|format| registry.get(format).is_err()) {
+            let names: Vec<&str> = registry.names().collect();
+            error("input_format", format!("Unknown format {:?} (available: {})", format, names.join(", ")));
+        }
+        let filter = match query_parameter(request, "require").map(JobFilter::from_str).transpose() {
+            Ok(filter) => filter.unwrap_or_default(),
+            Err(err) => {
+                error("require", err.to_string());
+                JobFilter::default()
+            }
+        };
+        invalid_if_any(errors, "The query has invalid parameters")?;
+
+        let parts;
+        let (input, detected) = match request.header("content-type").and_then(multipart_boundary) {
+            Some(boundary) => {
+                parts = multipart_parts(&request.body, &boundary)
+                    .map_err(|err| Problem::from_error(400, "Invalid input", &err))?;
+                let part = parts.iter().find(|part| part.name.as_deref() == Some("input"));
+                let part = part.or_else(|| parts.iter().find(|part| part.filename.is_some()));
+                let part = part.ok_or_else(|| Problem::new(400, "Invalid input", "The form has no input file"))?;
+                let by_name = part.filename.as_deref().and_then(|name| registry.format_for(Path::new(name)));
+                let format = by_name.unwrap_or_else(|| input_format_of(part.content_type.as_deref()));
+                (&part.data[..], format.to_string())
+            }
+            None => (&request.body[..], input_format_of(request.header("content-type")).to_string()),
+        };
+        let format = explicit.unwrap_or(&detected);
+        let parser = registry.get(format).map_err(|err| Problem::from_error(400, "Invalid input", &err))?;
+        let limits = &self.config.limits;
+        if format != "json" {
+            limits.check_text(input).map_err(|err| Problem::from_error(413, "Input too large", &err))?;
+        }
+
+        let mut jobs = Vec::new();
+        let mut errors = Vec::new();
+        for (index, job) in parser.parse(Box::new(input)).enumerate() {
+            match job {
+                Ok(job) if !filter.matches(&job) => {}
+                Ok(job) if job.end > self.config.max_end => {
+                    errors.push((format!("line {}", index + 1), format!("end must be at most {}", self.config.max_end)));
+                }
+                Ok(job) => jobs.push(job),
+                Err(err) => errors.push((String::from("input"), format!("{:#}", err))),
+            }
+            if errors.len() == MAX_REPORTED_ERRORS {
+                break;
+            }
+        }
+        invalid_if_any(errors, "The input has invalid lines")?;
+        if jobs.is_empty() && !filter.is_empty() {
+            return Err(Problem::new(400, "Invalid input", format!("No job of the input meets require={}", filter)));
+        }
+        if jobs.is_empty() {
+            return Err(Problem::new(400, "Invalid input", "The input holds no jobs"));
+        }
+        if let Some(max) = limits.max_lines.filter(|&max| format == "json" && jobs.len() > max) {
+            return Err(Problem::new(413, "Input too large", format!("The input has more than {} jobs", max)));
+        }
+        let counts = jobs.iter().map(|job| formula::count(job, options.mode));
+        limits.check_multiples(counts).map_err(|err| Problem::from_error(413, "Input too large", &err))?;
+        if !options.count_only {
+            let expected = jobs.iter().map(|job| formula::count(job, options.mode)).fold(0u64, u64::saturating_add);
+            if expected.saturating_mul(2) > self.config.max_response_size {
+                return Err(self.too_large(expected));
+            }
+        }
+
+        let (threads, max_response_size) = (self.config.threads, self.config.max_response_size);
+        if !background {
+            return Ok(answer(&run_batch(jobs, &options, threads)?, &options, max_response_size));
+        }
+        let id = format!("{:016x}", self.ids.lock().unwrap_or_else(|err| err.into_inner()).next_u64());
+        {
+            let mut batches = self.batches.lock().unwrap_or_else(|err| err.into_inner());
+            if batches.running() >= MAX_RUNNING_BATCHES {
+                let detail = format!("{} batches are running already; retry later", MAX_RUNNING_BATCHES);
+                return Err(Problem::new(503, "Too many batches", detail));
+            }
+            let job = BatchJob {
+                owner: identity.to_string(),
+                options: options.clone(),
+                result: None,
+            };
+            batches.jobs.insert(id.clone(), job);
+        }
+        let batches = self.batches.clone();
+        let job_id = id.clone();
+        thread::spawn(move || {
+            let result = run_batch(jobs, &options, threads).map_err(Problem::into_response);
+            batches.lock().unwrap_or_else(|err| err.into_inner()).finish(&job_id, result);
+        });
+        Ok(running_job(&id))
+    }
+
+    /// The state of the batch `id`, which only its owner can see, with the
+    /// records `offset` and `limit` select once it is done.
+    fn job(&self, request: &Request, id: &str, identity: &str) -> Result<Response, Problem> {
+        let mut errors = Vec::new();
+        let mut error = |parameter: &str, detail: String| errors.push((parameter.to_string(), detail));
+        check_parameters(request, &JOB_PARAMETERS, &mut error);
+        let page = page(request, &mut error);
+        invalid_if_any(errors, "The query has invalid parameters")?;
+        let batches = self.batches.lock().unwrap_or_else(|err| err.into_inner());
+        let job = batches.jobs.get(id).filter(|job| job.owner == identity);
+        let job = job.ok_or_else(|| Problem::new(404, "Not found", format!("No job {}", id)))?;
+        match &job.result {
+            None => Ok(running_job(id)),
+            Some(Err(response)) => Ok(response.clone()),
+            Some(Ok(records)) if page.is_whole() => Ok(answer(records, &job.options, self.config.max_response_size)),
+            Some(Ok(records)) => {
+                let response = answer(&records[page.range(records.len())], &job.options, self.config.max_response_size);
+                Ok(response.with_header("X-Total-Count", records.len()))
+            }
+        }
+    }
+
+    fn respond(&self, stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let client = stream.peer_addr()?.ip();
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let (start, clock) = (SystemTime::now(), Instant::now());
+        let (request, response) = match Request::read(&mut reader, self.config.max_request_size) {
+            Ok(Some(request)) => {
+                let response = self.handle(&request, client);
+                (Some(request), response)
+            }
+            Ok(None) => return Ok(()),
+            Err(err) => (None, Problem::from_error(400, "Bad request", &err).into_response()),
+        };
+        let mut out = &stream;
+        let written = response.write_to(&mut out).and_then(|()| out.flush());
+        if let Some(exporter) = &self.config.otlp {
+            let operation = request_operation(request.as_ref(), response.status, client, start, clock.elapsed());
+            if let Err(err) = exporter.export_operation(&operation) {
+                eprintln!("Failed to export the span of a request: {:#}", err);
+            }
+        }
+        written?;
+        Ok(())
+    }
+
+    /// Answers connections on `listener` until the process ends, each on a
+    /// thread of its own, up to `max_connections` at once.
+    pub fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        self.serve_until(listener, || false)
+    }
+
+    /// Answers connections on `listener` until `stop` returns true, then
+    /// stops accepting and waits for the connections already accepted to be
+    /// answered.
+    pub fn serve_until(self: Arc<Self>, listener: TcpListener, stop: impl Fn() -> bool) -> Result<()> {
+        // Accepting without blocking, so that `stop` is checked while no one connects.
+        listener.set_nonblocking(true).context("Failed to set up the listener")?;
+        let mut connections: Vec<thread::JoinHandle<()>> = Vec::new();
+        while !stop() {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::Interrupted => {
+                    thread::sleep(ACCEPT_INTERVAL);
+                    continue;
+                }
+                Err(err) => return Err(err).context("Failed to accept a connection"),
+            };
+            stream.set_nonblocking(false).context("Failed to set up a connection")?;
+            connections.retain(|connection| !connection.is_finished());
+            if connections.len() >= self.config.max_connections {
+                if let Err(err) = refuse(stream) {
+                    eprintln!("Failed to refuse a connection: {:#}", err);
+                }
+                continue;
+            }
+            let server = self.clone();
+            connections.push(thread::spawn(move || {
+                if let Err(err) = server.respond(stream) {
+                    eprintln!("Failed to answer a request: {:#}", err);
+                }
+            }));
+        }
+        for connection in connections {
+            let _ = connection.join();
+        }
+        Ok(())
+    }
+}
+
+/// Answers a connection beyond `max_connections` with `503` on the accepting
+/// thread, which a slow client may hold up for `REFUSAL_TIMEOUT` at most.
+fn refuse(stream: TcpStream) -> Result<()> {
+    stream.set_write_timeout(Some(REFUSAL_TIMEOUT))?;
+    let detail = "The server is answering as many connections as it may; try again shortly";
+    let response = Problem::new(503, "Too many connections", detail).into_response().with_header("Retry-After", 1);
+    let mut out = &stream;
+    response.write_to(&mut out).and_then(|()| out.flush())?;
+    // Reading what the client has sent so far, so that closing the connection
+    // doesn't reset it before the client has read the answer.
+    stream.set_nonblocking(true)?;
+    let mut buffer = [0; 4096];
+    while matches!((&stream).read(&mut buffer), Ok(n) if n > 0) {}
+    Ok(())
+}
+
+/// The span of a request answered with `status`; `request` is `None` when it
+/// couldn't be read.
+fn request_operation(
+    request: Option<&Request>,
+    status: u16,
+    client: IpAddr,
+    start: SystemTime,
+    duration: Duration,
+) -> Operation {
+    let mut attributes = vec![
+        ("http.response.status_code", Attribute::Int(status as u64)),
+        ("client.address", Attribute::Text(client.to_string())),
+    ];
+    // Spans are named after the route rather than the path, which holds ids.
+    let name = match request {
+        Some(request) => {
+            attributes.push(("http.request.method", Attribute::Text(request.method.clone())));
+            attributes.push(("url.path", Attribute::Text(request.path.clone())));
+            let route = match request.path.as_str() {
+                path @ ("/batch" | "/health" | "/metrics" | "/multiples" | "/openapi.json") => Some(path),
+                path if path.starts_with("/jobs/") => Some("/jobs/{id}"),
+                _ => None,
+            };
+            match route {
+                Some(route) => format!("{} {}", request.method, route),
+                None => request.method.clone(),
+            }
+        }
+        None => String::from("HTTP"),
+    };
+    Operation {
+        name,
+        server: true,
+        start,
+        duration,
+        attributes,
+        error: (status >= 500).then(|| http::reason(status).to_string()),
+    }
+}
+
+/// The last value given for the query parameter `name`.
+fn query_parameter<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request.query.iter().rev().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+}
+
+/// Reports the query parameters that aren't in `parameters`.
+fn check_parameters(request: &Request, parameters: &[Parameter], error: &mut impl FnMut(&str, String)) {
+    for (name, _) in &request.query {
+        if !parameters.iter().any(|parameter| parameter.name == name) {
+            let names: Vec<&str> = parameters.iter().map(|parameter| parameter.name).collect();
+            error(name, format!("Unknown parameter (available: {})", names.join(", ")));
+        }
+    }
+}
+
+/// Reads `mode`, `count_only` and `format`, reporting invalid values.
+fn answer_options(request: &Request, error: &mut impl FnMut(&str, String)) -> AnswerOptions {
+    let mode = match query_parameter(request, "mode").map(str::parse) {
+        None => Mode::Or,
+        Some(Ok(mode)) => mode,
+        Some(Err(err)) => {
+            error("mode", format!("{:#}", err));
+            Mode::Or
+        }
+    };
+    let count_only = match query_parameter(request, "count_only") {
+        None | Some("false") | Some("0") => false,
+        Some("true") | Some("1") | Some("") => true,
+        Some(value) => {
+            error("count_only", format!("Expected true or false, got {:?}", value));
+            false
+        }
+    };
+    let format = query_parameter(request, "format").unwrap_or("json").to_string();
+    let formats = response_formats();
+    if !formats.contains(&format) {
+        error("format", format!("Unknown format {:?} (available: {})", format, formats.join(", ")));
+    }
+    AnswerOptions {
+        mode,
+        count_only,
+        format,
+    }
+}
+
+/// Reads `offset` and `limit`, reporting invalid values.
+fn page(request: &Request, error: &mut impl FnMut(&str, String)) -> Page {
+    let mut page = Page::default();
+    for name in ["offset", "limit"] {
+        match query_parameter(request, name).map(number::parse_number) {
+            None => {}
+            Some(Ok(value)) if name == "offset" => page.offset = value,
+            Some(Ok(value)) => page.limit = Some(value),
+            Some(Err(err)) => error(name, format!("{:#}", err)),
+        }
+    }
+    page
+}
+
+fn invalid_if_any(errors: Vec<(String, String)>, detail: &str) -> Result<(), Problem> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let mut problem = Problem::new(400, "Invalid request", detail);
+    problem.errors = errors;
+    Err(problem)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{IpAddr, Ipv4Addr, TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{openapi, Problem, RateLimit, RateLimiter, Server, ServerConfig, ACCEPT_INTERVAL, MULTIPLES_PARAMETERS};
+    use crate::auth::{read_api_keys, Authenticator};
+    use crate::errors::MALFORMED_LINE;
+    use crate::http::{self, parse_query, HttpUrl, Request};
+    use crate::json;
+    use crate::otlp::{spans_of, stub_collector, OtlpExporter};
+
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn get(target: &str) -> Request {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        Request {
+            method: String::from("GET"),
+            path: path.to_string(),
+            query: parse_query(query).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        assert_eq!(
+            "2/s".parse::<RateLimit>().unwrap(),
+            RateLimit {
+                requests: 2,
+                period: Duration::from_secs(1)
+            }
+        );
+        assert!("0/s".parse::<RateLimit>().is_err());
+        assert!("5/day".parse::<RateLimit>().is_err());
+
+        let mut limiter = RateLimiter::new("2/s".parse().unwrap());
+        let start = Instant::now();
+        assert!(limiter.check(CLIENT, start).is_ok());
+        assert!(limiter.check(CLIENT, start).is_ok());
+        assert_eq!(limiter.check(CLIENT, start), Err(Duration::from_millis(500)));
+        assert!(limiter.check(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), start).is_ok());
+        assert!(limiter.check(CLIENT, start + Duration::from_millis(500)).is_ok());
+        assert!(limiter.check(CLIENT, start + Duration::from_millis(500)).is_err());
+    }
+
+    #[test]
+    fn test_multiples_endpoint() {
+        let server = Server::new(ServerConfig {
+            max_end: 1000,
+            max_response_size: 100,
+            rate_limit: Some("2/min".parse().unwrap()),
+            ..Default::default()
+        });
+        let response = server.handle(&get("/multiples?a=2&b=3&end=10&format=text"), CLIENT);
+        assert_eq!((response.status, response.body.as_slice()), (200, &b"10:2 3 4 6 8 9 10\n"[..]));
+        let response = server.handle(&get("/multiples?a=2&b=3&end=1000&count_only=true"), CLIENT);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("content-type"), Some("application/json"));
+
+        let response = server.handle(&get("/health"), CLIENT);
+        assert_eq!(response.status, 429);
+        assert_eq!(response.header("retry-after"), Some("30"));
+        assert_eq!(response.header("content-type"), Some("application/problem+json"));
+    }
+
+    #[test]
+    fn test_invalid_requests_are_problems() {
+        let server = Server::new(ServerConfig {
+            max_end: 1000,
+            max_response_size: 100,
+            ..Default::default()
+        });
+        let response = server.handle(&get("/multiples?a=0&end=5000&mode=and&colour=red"), CLIENT);
+        assert_eq!(response.status, 400);
+        let problem = json::parse(std::str::from_utf8(&response.body).unwrap()).unwrap();
+        assert_eq!(problem.get("title").and_then(|title| title.as_text()), Some("Invalid request"));
+        let parameters: Vec<_> = problem
+            .get("errors")
+            .and_then(|errors| errors.as_array())
+            .unwrap()
+            .iter()
+            .map(|error| error.get("parameter").and_then(|name| name.as_text()).unwrap().to_string())
+            .collect();
+        assert_eq!(parameters, ["colour", "a", "b", "end", "mode"]);
+
+        assert_eq!(server.handle(&get("/multiples?a=1&b=1&end=1000"), CLIENT).status, 413);
+        assert_eq!(server.handle(&get("/multiples?a=1&b=1&end=1000&count_only=1"), CLIENT).status, 200);
+        assert_eq!(server.handle(&get("/nothing"), CLIENT).status, 404);
+        let mut post = get("/multiples");
+        post.method = String::from("POST");
+        let response = server.handle(&post, CLIENT);
+        assert_eq!((response.status, response.header("allow")), (405, Some("GET")));
+
+        let err = MALFORMED_LINE.error("Line 1 does not contain exactly 3 numbers");
+        let response = Problem::from_error(400, "Invalid input", &err).into_response();
+        let problem = json::parse(std::str::from_utf8(&response.body).unwrap()).unwrap();
+        assert_eq!(problem.get("code").and_then(|code| code.as_text()), Some("MP0001"));
+        assert!(!String::from_utf8_lossy(&Problem::new(404, "Not found", "").into_response().body).contains("code"));
+    }
+
+    fn with_header(mut request: Request, name: &str, value: &str) -> Request {
+        request.headers.push((name.to_string(), value.to_string()));
+        request
+    }
+
+    #[test]
+    fn test_paged_and_ranged_answers() {
+        let server = Server::new(ServerConfig {
+            max_response_size: 100,
+            ..Default::default()
+        });
+        let response = server.handle(&get("/multiples?a=2&b=3&end=10&format=text&offset=2&limit=3"), CLIENT);
+        assert_eq!((response.status, response.body.as_slice()), (200, &b"10:4 6 8\n"[..]));
+        assert_eq!(response.header("x-total-count"), Some("7"));
+        // Far too many multiples for one response, but a page of them fits.
+        let response = server.handle(&get("/multiples?a=1&b=1&end=10M&format=text&offset=9999998"), CLIENT);
+        assert_eq!((response.status, response.body.as_slice()), (200, &b"10000000:9999999 10000000\n"[..]));
+        assert_eq!(server.handle(&get("/multiples?a=2&b=3&end=10&count_only=1&limit=1"), CLIENT).status, 400);
+        assert_eq!(server.handle(&get("/multiples?a=2&b=3&end=10&offset=-1"), CLIENT).status, 400);
+
+        let target = "/multiples?a=2&b=3&end=10&format=text";
+        let whole = server.handle(&get(target), CLIENT);
+        assert_eq!(whole.header("accept-ranges"), Some("bytes"));
+        let etag = whole.header("etag").unwrap().to_string();
+        let response = server.handle(&with_header(get(target), "Range", "bytes=3-"), CLIENT);
+        assert_eq!((response.status, response.body.as_slice()), (206, &whole.body[3..]));
+        assert_eq!(response.header("content-range"), Some("bytes 3-17/18"));
+        let resumed = with_header(with_header(get(target), "Range", "bytes=0-1"), "If-Range", &etag);
+        assert_eq!(server.handle(&resumed, CLIENT).status, 206);
+        let stale = with_header(with_header(get(target), "Range", "bytes=0-1"), "If-Range", "\"old\"");
+        assert_eq!(server.handle(&stale, CLIENT).status, 200);
+        let response = server.handle(&with_header(get(target), "Range", "bytes=18-"), CLIENT);
+        assert_eq!((response.status, response.header("content-range")), (416, Some("bytes */18")));
+    }
+
+    fn post(target: &str, content_type: &str, body: &str) -> Request {
+        let mut request = get(target);
+        request.method = String::from("POST");
+        request.headers.push((String::from("Content-Type"), content_type.to_string()));
+        request.body = body.as_bytes().to_vec();
+        request
+    }
+
+    #[test]
+    fn test_batch_endpoint() {
+        let server = Server::new(ServerConfig {
+            max_end: 1000,
+            threads: 2,
+            ..Default::default()
+        });
+        let response = server.handle(&post("/batch?format=text", "text/plain", "2 3 10\n5 7 20\n"), CLIENT);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"10:2 3 4 6 8 9 10\n20:5 7 10 14 15 20\n");
+
+        let form = "--b0\r\nContent-Disposition: form-data; name=\"input\"; filename=\"jobs.csv\"\r\n\r\n\
+                    a,b,end\n2,3,10\r\n--b0--\r\n";
+        let response = server.handle(&post("/batch?count_only=true", "multipart/form-data; boundary=b0", form), CLIENT);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("content-type"), Some("application/json"));
+
+        let response = server.handle(&post("/batch", "text/plain", "2 3 10\n2 3 5000\n"), CLIENT);
+        assert_eq!(response.status, 400);
+        assert!(String::from_utf8_lossy(&response.body).contains("line 2"));
+        assert_eq!(server.handle(&post("/batch?input_format=xml", "text/plain", "2 3 10\n"), CLIENT).status, 400);
+        assert_eq!(server.handle(&post("/batch", "text/plain", ""), CLIENT).status, 400);
+        let response = server.handle(&post("/batch?format=text&require=coprime", "text/plain", "2 3 10\n4 6 10\n4 6 5000\n"), CLIENT);
+        assert_eq!((response.status, response.body.as_slice()), (200, &b"10:2 3 4 6 8 9 10\n"[..]));
+        let response = server.handle(&post("/batch?require=a-prime", "text/plain", "4 6 10\n"), CLIENT);
+        assert!(String::from_utf8_lossy(&response.body).contains("No job of the input meets require=a-prime"));
+        assert_eq!(server.handle(&post("/batch?require=odd", "text/plain", "2 3 10\n"), CLIENT).status, 400);
+        let response = server.handle(&post("/batch", "text/plain", &format!("2 3 10 {}\n", "x ".repeat(100))), CLIENT);
+        assert_eq!(response.status, 413);
+        assert!(String::from_utf8_lossy(&response.body).contains("Line 1 has more than 64 tokens"));
+        let jobs = format!("[{}]", vec!["[2,3,10]"; 100_001].join(","));
+        let response = server.handle(&post("/batch", "application/json", &jobs), CLIENT);
+        assert_eq!(response.status, 413);
+        let response = server.handle(&get("/batch"), CLIENT);
+        assert_eq!((response.status, response.header("allow")), (405, Some("POST")));
+    }
+
+    #[test]
+    fn test_async_batch() {
+        let path = std::env::temp_dir().join(format!("multiples_batch_keys_{}.txt", std::process::id()));
+        std::fs::write(&path, "team-a key-a\nteam-b key-b\n").unwrap();
+        let keys = read_api_keys(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let server = Server::new(ServerConfig {
+            auth: Authenticator::new(keys, None),
+            ..Default::default()
+        });
+        let with_key = |mut request: Request, key: &str| {
+            request.headers.push((String::from("X-API-Key"), key.to_string()));
+            request
+        };
+        let upload = post("/batch?async=true&format=text", "text/plain", "2 3 10\n");
+        let response = server.handle(&with_key(upload, "key-a"), CLIENT);
+        assert_eq!(response.status, 202);
+        let location = response.header("location").unwrap().to_string();
+        assert!(location.starts_with("/jobs/"));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let response = loop {
+            let response = server.handle(&with_key(get(&location), "key-a"), CLIENT);
+            if response.status != 202 || Instant::now() > deadline {
+                break response;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!((response.status, response.body.as_slice()), (200, &b"10:2 3 4 6 8 9 10\n"[..]));
+        assert_eq!(server.handle(&with_key(get(&location), "key-b"), CLIENT).status, 404);
+        let response = server.handle(&with_key(get(&format!("{}?offset=0&limit=1", location)), "key-a"), CLIENT);
+        assert_eq!((response.status, response.header("x-total-count")), (200, Some("1")));
+        let response = server.handle(&with_key(get(&format!("{}?offset=5", location)), "key-a"), CLIENT);
+        assert_eq!((response.status, response.body.as_slice()), (200, &b""[..]));
+        let response = server.handle(&with_key(get(&format!("{}?colour=red", location)), "key-a"), CLIENT);
+        assert_eq!(response.status, 400);
+        assert_eq!(server.handle(&with_key(get("/jobs/0123456789abcdef"), "key-a"), CLIENT).status, 404);
+    }
+
+    #[test]
+    fn test_openapi() {
+        let server = Server::new(ServerConfig::default());
+        let response = server.handle(&get("/openapi.json"), CLIENT);
+        assert_eq!(response.status, 200);
+        let spec = json::parse(std::str::from_utf8(&response.body).unwrap()).unwrap();
+        let operation = spec.get("paths").and_then(|paths| paths.get("/multiples")).and_then(|path| path.get("get")).unwrap();
+        let parameters = operation.get("parameters").and_then(|parameters| parameters.as_array()).unwrap();
+        assert_eq!(parameters.len(), MULTIPLES_PARAMETERS.len());
+        let formats = parameters[5].get("schema").and_then(|schema| schema.get("enum")).unwrap();
+        assert!(formats.as_array().unwrap().iter().any(|format| format.as_text() == Some("ndjson")));
+        assert!(operation.get("responses").and_then(|responses| responses.get("401")).is_none());
+        let batch = spec.get("paths").and_then(|paths| paths.get("/batch")).and_then(|path| path.get("post"));
+        assert!(batch.and_then(|batch| batch.get("requestBody")).is_some());
+        let schemes = spec.get("components").and_then(|components| components.get("securitySchemes")).unwrap();
+        assert_eq!(schemes, &json::Value::Object(Default::default()));
+
+        let keys = Authenticator::new(Vec::new(), Some(crate::auth::JwtConfig { keys: vec![b"k".to_vec()], issuer: None }));
+        let spec = json::parse(&openapi(&keys)).unwrap();
+        let schemes = spec.get("components").and_then(|components| components.get("securitySchemes")).unwrap();
+        assert!(schemes.get("bearer").is_some() && schemes.get("apiKey").is_none());
+    }
+
+    #[test]
+    fn test_authentication_and_metrics() {
+        let path = std::env::temp_dir().join(format!("multiples_server_keys_{}.txt", std::process::id()));
+        std::fs::write(&path, "team-a key-a\n").unwrap();
+        let keys = read_api_keys(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let server = Server::new(ServerConfig {
+            auth: Authenticator::new(keys, None),
+            ..Default::default()
+        });
+        let mut request = get("/multiples?a=2&b=3&end=10");
+        let response = server.handle(&request, CLIENT);
+        assert_eq!((response.status, response.header("www-authenticate")), (401, Some("Bearer")));
+        assert_eq!(server.handle(&get("/health"), CLIENT).status, 200);
+        request.headers.push((String::from("X-API-Key"), String::from("key-a")));
+        assert_eq!(server.handle(&request, CLIENT).status, 200);
+        assert_eq!(server.handle(&request, CLIENT).status, 200);
+        assert_eq!(server.handle(&get("/metrics"), CLIENT).status, 401);
+        let metrics = server.metrics();
+        assert!(metrics.contains("multiples_requests_total{key=\"team-a\",status=\"200\"} 2\n"));
+        assert!(metrics.contains("multiples_requests_total{key=\"unauthenticated\",status=\"401\"} 2\n"));
+        assert!(metrics.contains("multiples_requests_total{key=\"anonymous\",status=\"200\"} 1\n"));
+    }
+
+    #[test]
+    fn test_cached_answers() {
+        let server = Server::new(ServerConfig {
+            cache_size: 60,
+            ..Default::default()
+        });
+        let first = server.handle(&get("/multiples?a=2&b=3&end=10&format=text"), CLIENT);
+        let again = server.handle(&get("/multiples?format=text&end=10&b=3&a=2"), CLIENT);
+        assert_eq!((again.status, again.body), (200, first.body));
+        // A different format is a different answer, and larger than the cache.
+        assert_eq!(server.handle(&get("/multiples?a=2&b=3&end=10"), CLIENT).status, 200);
+        assert_eq!(server.handle(&get("/multiples?a=0&b=3&end=10"), CLIENT).status, 400);
+        let metrics = server.metrics();
+        assert!(metrics.contains("multiples_cache_hits_total 1\n"), "{}", metrics);
+        assert!(metrics.contains("multiples_cache_misses_total 2\n"));
+        assert!(metrics.contains("multiples_cache_entries 1\n"));
+        assert!(metrics.contains("multiples_coalesced_requests_total 0\n"));
+
+        let server = Server::new(ServerConfig {
+            cache_size: 0,
+            ..Default::default()
+        });
+        assert_eq!(server.handle(&get("/multiples?a=2&b=3&end=10"), CLIENT).status, 200);
+        assert!(!server.metrics().contains("multiples_cache"));
+    }
+
+    #[test]
+    fn test_request_spans() {
+        let (endpoint, bodies) = stub_collector();
+        let server = Arc::new(Server::new(ServerConfig {
+            otlp: Some(OtlpExporter::new(&endpoint, 1.0).unwrap()),
+            ..Default::default()
+        }));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || server.serve(listener));
+        let text = |value: Option<&json::Value>| value.and_then(json::Value::as_text).map(String::from);
+        for (target, name, status) in [("/health", "GET /health", "200"), ("/jobs/abc", "GET /jobs/{id}", "404"), ("/nowhere", "GET", "404")] {
+            let url = HttpUrl::parse(&format!("http://{}{}", address, target)).unwrap();
+            http::get(&url, &[], Duration::from_secs(5)).unwrap();
+            let spans = spans_of(&bodies.recv_timeout(Duration::from_secs(5)).unwrap());
+            assert_eq!(text(spans[0].get("name")).as_deref(), Some(name));
+            let attributes = spans[0].get("attributes").unwrap().as_array().unwrap();
+            assert_eq!(text(attributes[0].get("value").unwrap().get("intValue")).as_deref(), Some(status));
+        }
+    }
+
+    #[test]
+    fn test_serve_until_finishes_accepted_connections() {
+        let server = Arc::new(Server::new(ServerConfig::default()));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let serving = thread::spawn(move || server.serve_until(listener, || stopped.load(Ordering::SeqCst)));
+
+        let mut connection = TcpStream::connect(address).unwrap();
+        connection.write_all(b"GET /health HTTP/1.1\r\n").unwrap();
+        thread::sleep(ACCEPT_INTERVAL * 5);
+        stop.store(true, Ordering::SeqCst);
+        thread::sleep(ACCEPT_INTERVAL * 5);
+        assert!(!serving.is_finished());
+        connection.write_all(b"Host: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        connection.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        serving.join().unwrap().unwrap();
+        assert!(TcpStream::connect(address).is_err());
+    }
+
+    #[test]
+    fn test_serve_until_refuses_connections_beyond_the_limit() {
+        let server = Arc::new(Server::new(ServerConfig { max_connections: 1, ..ServerConfig::default() }));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let serving = thread::spawn(move || server.serve_until(listener, || stopped.load(Ordering::SeqCst)));
+
+        let mut held = TcpStream::connect(address).unwrap();
+        held.write_all(b"GET /health HTTP/1.1\r\n").unwrap();
+        thread::sleep(ACCEPT_INTERVAL * 5);
+        let mut refused = TcpStream::connect(address).unwrap();
+        refused.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        refused.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(response.contains("Retry-After: 1\r\n") && response.contains("\"title\":\"Too many connections\""));
+
+        held.write_all(b"Host: localhost\r\n\r\n").unwrap();
+        response.clear();
+        held.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        stop.store(true, Ordering::SeqCst);
+        serving.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+//! Running under a service manager such as systemd, for `--service` and
+//! `--pid-file`.
+//!
+//! Readiness and watchdog pings follow the `sd_notify` protocol: datagrams
+//! such as `READY=1` sent to the socket named by `$NOTIFY_SOCKET`, which is
+//! only set for `Type=notify` units, so outside systemd they are no-ops.
+//! SIGTERM and SIGINT are turned into a flag that job sources poll, so a
+//! stopping service finishes the jobs already in flight instead of dying
+//! halfway through an output file.
+
+use std::env;
+use std::fs;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+const SIGINT: i32 = 2;
+const SIGTERM: i32 = 15;
+
+extern "C" {
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    #[cfg(unix)]
+    fn kill(pid: i32, signum: i32) -> i32;
+}
+
+static TERMINATED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_terminate(_signum: i32) {
+    TERMINATED.store(true, Ordering::SeqCst);
+}
+
+/// Makes SIGTERM and SIGINT set [`terminated`] instead of ending the process.
+pub fn handle_termination() {
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+    unsafe {
+        signal(SIGTERM, on_terminate);
+        signal(SIGINT, on_terminate);
+    }
+}
+
+/// Whether a termination signal arrived since [`handle_termination`].
+pub fn terminated() -> bool {
+    TERMINATED.load(Ordering::SeqCst)
+}
+
+/// Like [`terminated`], telling the service manager that the process is
+/// stopping the first time it returns true.
+pub fn stop_requested() -> bool {
+    static NOTIFIED: AtomicBool = AtomicBool::new(false);
+    if !terminated() {
+        return false;
+    }
+    if !NOTIFIED.swap(true, Ordering::SeqCst) {
+        let _ = notify("STOPPING=1");
+    }
+    true
+}
+
+/// Sends `state`, e.g. `READY=1`, to the service manager. Returns whether
+/// there is one to tell.
+pub fn notify(state: &str) -> io::Result<bool> {
+    match env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => notify_to(Path::new(&socket), state).map(|()| true),
+        None => Ok(false),
+    }
+}
+
+#[cfg(not(unix))]
+fn notify_to(_socket: &Path, _state: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "sd_notify needs Unix domain sockets"))
+}
+
+#[cfg(unix)]
+fn notify_to(socket: &Path, state: &str) -> io::Result<()> {
+    let sender = UnixDatagram::unbound()?;
+    #[cfg(target_os = "linux")]
+    if let Some(name) = socket.to_str().and_then(|socket| socket.strip_prefix('@')) {
+        use std::os::linux::net::SocketAddrExt;
+        let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        return sender.send_to_addr(state.as_bytes(), &address).map(|_| ());
+    }
+    sender.send_to(state.as_bytes(), socket).map(|_| ())
+}
+
+/// The interval to send watchdog pings at: half the timeout systemd passes
+/// in `WATCHDOG_USEC`, when it is meant for this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec = env::var("WATCHDOG_USEC").ok();
+    let pid = env::var("WATCHDOG_PID").ok();
+    watchdog_interval_from(usec.as_deref(), pid.as_deref(), std::process::id())
+}
+
+fn watchdog_interval_from(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.parse() != Ok(own_pid)) {
+        return None;
+    }
+    let usec: u64 = usec?.parse().ok().filter(|&usec| usec > 0)?;
+    Some(Duration::from_micros(usec / 2))
+}
+
+/// Pings the service manager's watchdog from its own thread until stopped.
+pub struct Watchdog {
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl Watchdog {
+    pub fn spawn(interval: Duration) -> Watchdog {
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                // A failed ping shows up as a watchdog timeout, which is
+                // the service manager's to report.
+                let _ = notify("WATCHDOG=1");
+            }
+        });
+        Watchdog { stop, thread }
+    }
+
+    pub fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = self.thread.join();
+    }
+}
+
+/// A file holding the process id, removed again when dropped.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes the pid to `path`, refusing to replace the file of a process
+    /// that is still running.
+    pub fn create(path: &Path) -> Result<PidFile> {
+        if let Ok(existing) = fs::read_to_string(path) {
+            if let Ok(pid) = existing.trim().parse::<u32>() {
+                if pid != std::process::id() && is_running(pid) {
+                    bail!("{:?} belongs to the running process {}", path, pid);
+                }
+            }
+        }
+        fs::write(path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Failed to write pid file {:?}", path))?;
+        Ok(PidFile { path: path.to_path_buf() })
+    }
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    const EPERM: i32 = 1;
+    let Ok(pid) = i32::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks whether the process exists.
+    unsafe { kill(pid, 0) == 0 || io::Error::last_os_error().raw_os_error() == Some(EPERM) }
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    false
+}
+
+/// What `--service` and `--pid-file` keep up for the length of a run.
+pub struct Service {
+    managed: bool,
+    pid_file: Option<PidFile>,
+    watchdog: Option<Watchdog>,
+}
+
+impl Service {
+    /// Writes the pid file, if any, and when `managed` starts handling
+    /// termination signals and pinging the watchdog systemd asks for.
+    pub fn start(pid_file: Option<&Path>, managed: bool) -> Result<Service> {
+        let pid_file = pid_file.map(PidFile::create).transpose()?;
+        if managed {
+            handle_termination();
+        }
+        let watchdog = managed.then(watchdog_interval).flatten().map(Watchdog::spawn);
+        Ok(Service {
+            managed,
+            pid_file,
+            watchdog,
+        })
+    }
+
+    /// Tells the service manager that setup is done.
+    pub fn ready(&self) -> Result<()> {
+        if self.managed {
+            notify("READY=1").context("Failed to notify the service manager")?;
+        }
+        Ok(())
+    }
+
+    /// Stops the watchdog and removes the pid file.
+    pub fn stop(self) {
+        if let Some(watchdog) = self.watchdog {
+            watchdog.stop();
+        }
+        drop(self.pid_file);
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{notify_to, watchdog_interval_from, PidFile};
+
+    #[cfg(unix)]
+    #[test]
+    fn test_notify_and_watchdog_interval() {
+        use std::os::unix::net::UnixDatagram;
+
+        let path = std::env::temp_dir().join(format!("multiples_notify_{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let manager = UnixDatagram::bind(&path).unwrap();
+        notify_to(&path, "READY=1").unwrap();
+        let mut buf = [0; 16];
+        let len = manager.recv(&mut buf).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+
+        assert_eq!(watchdog_interval_from(Some("3000000"), None, 7), Some(Duration::from_millis(1500)));
+        assert_eq!(watchdog_interval_from(Some("3000000"), Some("7"), 7), Some(Duration::from_millis(1500)));
+        assert_eq!(watchdog_interval_from(Some("3000000"), Some("8"), 7), None);
+        assert_eq!(watchdog_interval_from(Some("0"), None, 7), None);
+        assert_eq!(watchdog_interval_from(None, None, 7), None);
+    }
+
+    #[test]
+    fn test_pid_file_is_removed_on_drop() {
+        let path = std::env::temp_dir().join(format!("multiples_{}.pid", std::process::id()));
+        let pid_file = PidFile::create(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), format!("{}\n", std::process::id()));
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,144 @@
+//! Set operations between the multiples of two divisor pairs.
+//!
+//! Whether `n` is a multiple of `a` or `b` depends only on `n` modulo
+//! `lcm(a, b)`, so the result of any operation between two pairs repeats with
+//! the period `lcm(a1, b1, a2, b2)`. The members of one period are computed
+//! once and then shifted across the range instead of testing every number.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SetOp {
+    /// Multiples of both pairs.
+    Intersection,
+    /// Multiples of either pair.
+    Union,
+    /// Multiples of the first pair that aren't multiples of the second.
+    Difference,
+}
+
+impl FromStr for SetOp {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "intersection" => Ok(SetOp::Intersection),
+            "union" => Ok(SetOp::Union),
+            "difference" => Ok(SetOp::Difference),
+            _ => Err(anyhow!(
+                "Unknown set operation: {} (available: intersection, union, difference)",
+                name
+            )),
+        }
+    }
+}
+
+/// Longest period whose members are tabulated; beyond it every number is
+/// tested instead.
+const MAX_PERIOD: u64 = 1 << 20;
+
+fn gcd(mut x: u64, mut y: u64) -> u64 {
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    x
+}
+
+/// An operation between the multiples of `(a1, b1)` and `(a2, b2)`. A divisor
+/// of 0 has no multiples in the range, matching the generate command.
+#[derive(Clone, Debug)]
+pub struct SetOperation {
+    op: SetOp,
+    first: (u32, u32),
+    second: (u32, u32),
+    /// Shared period and the members of `1..=period`, unless the period
+    /// exceeds `MAX_PERIOD`.
+    cycle: Option<(u64, Vec<u64>)>,
+}
+
+impl SetOperation {
+    pub fn new(op: SetOp, first: (u32, u32), second: (u32, u32)) -> Self {
+        let mut operation = SetOperation {
+            op,
+            first,
+            second,
+            cycle: None,
+        };
+        let divisors = [first.0, first.1, second.0, second.1];
+        let period = divisors
+            .iter()
+            .filter(|&&d| d != 0)
+            .try_fold(1u64, |lcm, &d| (lcm / gcd(lcm, d as u64)).checked_mul(d as u64));
+        operation.cycle = period
+            .filter(|&period| period <= MAX_PERIOD)
+            .map(|period| (period, (1..=period).filter(|&n| operation.contains(n)).collect()));
+        operation
+    }
+
+    fn contains(&self, n: u64) -> bool {
+        let of = |(a, b): (u32, u32)| n.is_multiple_of(a as u64) || n.is_multiple_of(b as u64);
+        match self.op {
+            SetOp::Intersection => of(self.first) && of(self.second),
+            SetOp::Union => of(self.first) || of(self.second),
+            SetOp::Difference => of(self.first) && !of(self.second),
+        }
+    }
+
+    /// The members in `1..=end`, ascending.
+    pub fn members(&self, end: u32) -> Box<dyn Iterator<Item = u32> + '_> {
+        match &self.cycle {
+            Some((period, residues)) => Box::new(
+                (0..=end as u64 / period)
+                    .flat_map(move |cycle| residues.iter().map(move |r| cycle * period + r))
+                    .take_while(move |&n| n <= end as u64)
+                    .map(|n| n as u32),
+            ),
+            None => Box::new((1..=end).filter(|&n| self.contains(n as u64))),
+        }
+    }
+
+    /// The number of members in `1..=end`.
+    pub fn count(&self, end: u32) -> u64 {
+        match &self.cycle {
+            Some((period, residues)) => {
+                let end = end as u64;
+                end / period * residues.len() as u64 + residues.partition_point(|&r| r <= end % period) as u64
+            }
+            None => self.members(end).count() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SetOp, SetOperation};
+
+    #[test]
+    fn test_set_operations_match_brute_force() {
+        let pairs = [((2, 3), (5, 7)), ((4, 6), (3, 0)), ((12, 18), (8, 9)), ((0, 0), (1, 5))];
+        for op in [SetOp::Intersection, SetOp::Union, SetOp::Difference] {
+            for (first, second) in pairs {
+                let operation = SetOperation::new(op, first, second);
+                let expected: Vec<u32> = (1..=500).filter(|&n| operation.contains(n as u64)).collect();
+                assert_eq!(operation.members(500).collect::<Vec<_>>(), expected, "{:?}", (op, first, second));
+                assert_eq!(operation.count(500), expected.len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_intersection() {
+        let operation = SetOperation::new(SetOp::Intersection, (2, 3), (5, 7));
+        assert_eq!(operation.members(30).collect::<Vec<_>>(), vec![10, 14, 15, 20, 21, 28, 30]);
+        assert_eq!(operation.count(u32::MAX), operation.count(u32::MAX - 210) + 44);
+    }
+
+    #[test]
+    fn test_long_period_falls_back_to_testing() {
+        let operation = SetOperation::new(SetOp::Union, (65_521, 65_519), (65_497, 65_479));
+        assert!(operation.cycle.is_none());
+        assert_eq!(operation.count(200_000), 12);
+    }
+}
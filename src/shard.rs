@@ -0,0 +1,174 @@
+//! Splitting the range of every job into contiguous shards, so that one
+//! gigantic range can be generated on several machines, and merging the
+//! shards' results back together.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::formula::{self, Mode};
+use crate::{LineNumbers, ResultRecord};
+
+/// The `index`-th of `count` contiguous chunks of a range, counting from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shard {
+    pub index: u32,
+    pub count: u32,
+}
+
+impl Shard {
+    /// The first and last value of this shard of `1..=end`. Shard `i`
+    /// covers `(i - 1) * end / n + 1..=i * end / n`, so consecutive shards
+    /// meet exactly and together cover the range. A shard of a range
+    /// shorter than `count` may be empty, in which case `first > last`.
+    pub fn bounds(&self, end: u32) -> (u32, u32) {
+        let split = |i: u32| (i as u64 * end as u64 / self.count as u64) as u32;
+        (split(self.index - 1) + 1, split(self.index))
+    }
+}
+
+impl FromStr for Shard {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid shard: {} (expected i/n with 1 <= i <= n)", text);
+        let (index, count) = text.split_once('/').ok_or_else(invalid)?;
+        let index: u32 = index.trim().parse().map_err(|_| invalid())?;
+        let count: u32 = count.trim().parse().map_err(|_| invalid())?;
+        if index == 0 || index > count {
+            return Err(invalid());
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// Merges the results of the shards of one run, one list of records per
+/// shard. Records are paired by their job and how often that job occurred
+/// before in the same shard, so every shard must hold the same jobs.
+///
+/// The multiples of paired records are joined in ascending order, or
+/// descending with `descending`, and their counts added up. With
+/// `verify`, each merged count is checked against the closed form for that
+/// mode to catch a missing or repeated shard; this only holds without digit
+/// filters and for records that kept their divisors. The merged records
+/// are sorted by count like the output of `generate`.
+pub fn merge_shards(shards: Vec<Vec<ResultRecord>>, verify: Option<Mode>, descending: bool) -> Result<Vec<ResultRecord>> {
+    let mut shards = shards.into_iter();
+    let Some(first) = shards.next() else {
+        return Ok(Vec::new());
+    };
+    let mut positions: HashMap<(LineNumbers, usize), usize> = HashMap::new();
+    let mut occurrences: HashMap<LineNumbers, usize> = HashMap::new();
+    for (position, record) in first.iter().enumerate() {
+        let occurrence = occurrences.entry(record.job).or_default();
+        positions.insert((record.job, *occurrence), position);
+        *occurrence += 1;
+    }
+    let mut merged = first;
+    for (shard, records) in shards.enumerate() {
+        if records.len() != merged.len() {
+            bail!("Shard {} holds {} records, the first one {}", shard + 2, records.len(), merged.len());
+        }
+        let mut occurrences: HashMap<LineNumbers, usize> = HashMap::new();
+        for record in records {
+            let occurrence = occurrences.entry(record.job).or_default();
+            let Some(&position) = positions.get(&(record.job, *occurrence)) else {
+                let job = record.job;
+                bail!("Shard {} holds a:{} b:{} end:{} more often than the first one", shard + 2, job.a, job.b, job.end);
+            };
+            *occurrence += 1;
+            let result = &mut merged[position].result;
+            result.numbers.extend(record.result.numbers);
+            result.count = match (result.count, record.result.count) {
+                (None, None) => None,
+                (count, other) => Some(count.unwrap_or_default() + other.unwrap_or_default()),
+            };
+        }
+    }
+    for record in &mut merged {
+        let numbers = &mut record.result.numbers;
+        numbers.sort_unstable();
+        if descending {
+            numbers.reverse();
+        }
+        let job = record.job;
+        // Text results don't name their divisors, so there is nothing to check.
+        if let Some(mode) = verify.filter(|_| job.a != 0 || job.b != 0) {
+            let (count, expected) = (record.result.count(), formula::count(&job, mode));
+            if count != expected {
+                bail!(
+                    "a:{} b:{} end:{} has {} multiples instead of {}; is a shard missing or given twice?",
+                    job.a,
+                    job.b,
+                    job.end,
+                    count,
+                    expected
+                );
+            }
+        }
+    }
+    merged.sort_by_key(|record| record.result.count());
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_shards, Shard};
+    use crate::formula::Mode;
+    use crate::strategy::{GenerateOptions, RecordGenerator};
+    use crate::LineNumbers;
+
+    #[test]
+    fn test_shard_bounds() {
+        assert_eq!("2/3".parse::<Shard>().unwrap(), Shard { index: 2, count: 3 });
+        for text in ["0/3", "4/3", "3", "a/b", "1/0"] {
+            assert!(text.parse::<Shard>().is_err(), "{}", text);
+        }
+        for (end, count) in [(10, 3), (u32::MAX, 7), (2, 5), (0, 2), (100, 100)] {
+            let mut next = 1u64;
+            for index in 1..=count {
+                let (first, last) = Shard { index, count }.bounds(end);
+                assert_eq!(first as u64, next, "{}/{} of {}", index, count, end);
+                assert!(first as u64 <= last as u64 + 1);
+                next = last as u64 + 1;
+            }
+            assert_eq!(next, end as u64 + 1);
+        }
+        assert_eq!(Shard { index: 1, count: 5 }.bounds(2), (1, 0));
+    }
+
+    #[test]
+    fn test_merge_shards() {
+        let jobs = [LineNumbers { a: 3, b: 5, end: 100 }, LineNumbers { a: 2, b: 7, end: 3 }];
+        for mode in [Mode::Or, Mode::Multiset] {
+            for count_only in [false, true] {
+                let generate = |shard| {
+                    let generator = RecordGenerator::new(GenerateOptions {
+                        mode,
+                        count_only,
+                        shard,
+                        ..Default::default()
+                    });
+                    let mut records: Vec<_> = jobs.iter().map(|&job| generator.generate(job)).collect();
+                    records.sort_by_key(|record| record.result.count());
+                    records
+                };
+                let shards: Vec<_> = (1..=4).map(|index| generate(Some(Shard { index, count: 4 }))).collect();
+                let merged = merge_shards(shards.clone(), Some(mode), false).unwrap();
+                assert_eq!(merged, generate(None), "{:?} {}", mode, count_only);
+                assert!(merge_shards(shards[1..].to_vec(), Some(mode), false).is_err());
+                assert!(merge_shards(shards[1..].to_vec(), None, false).is_ok());
+            }
+        }
+        let shards = vec![vec![RecordGenerator::new(GenerateOptions::default()).generate(jobs[0])], Vec::new()];
+        assert!(merge_shards(shards, None, false).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! Sorting existing result sets, spilling to temporary files when they don't
+//! fit in the given memory budget.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::formatter::{NdjsonFormatter, OutputFormatter};
+use crate::reader::{NdjsonReader, RecordIter, ResultReader};
+use crate::ResultRecord;
+
+/// Default memory budget of an external sort, in bytes.
+pub const DEFAULT_MAX_MEMORY: u64 = 1 << 30;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    /// By the end of the range.
+    End,
+    /// By the number of multiples found.
+    Count,
+    /// By the position of the job in the original input.
+    Input,
+}
+
+impl SortKey {
+    pub fn key(self, record: &ResultRecord) -> u64 {
+        match self {
+            SortKey::End => record.result.end as u64,
+            SortKey::Count => record.result.count(),
+            SortKey::Input => record.index as u64,
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "end" => Ok(SortKey::End),
+            "count" => Ok(SortKey::Count),
+            "input" => Ok(SortKey::Input),
+            _ => Err(anyhow!("Unknown sort key: {} (available: end, count, input)", name)),
+        }
+    }
+}
+
+/// Stable in-memory sort of `records` by `key`.
+pub fn sort_records(records: &mut [ResultRecord], key: SortKey) {
+    records.sort_by_key(|record| key.key(record));
+}
+
+/// Rough number of bytes a record occupies in memory.
+fn estimated_size(record: &ResultRecord) -> u64 {
+    (std::mem::size_of::<ResultRecord>() + record.result.numbers.len() * std::mem::size_of::<u32>()) as u64
+}
+
+static NEXT_CHUNK: AtomicUsize = AtomicUsize::new(0);
+
+/// Sorted runs written to a temporary directory, removed when dropped.
+struct Chunks {
+    paths: Vec<PathBuf>,
+}
+
+impl Chunks {
+    fn write(&mut self, temp_dir: &Path, records: &[ResultRecord]) -> Result<()> {
+        let path = temp_dir.join(format!(
+            "multiples-sort-{}-{}.ndjson",
+            std::process::id(),
+            NEXT_CHUNK.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = File::create(&path).with_context(|| format!("Failed to create sort chunk {:?}", path))?;
+        self.paths.push(path);
+        let mut out = BufWriter::new(file);
+        let mut formatter = NdjsonFormatter::default();
+        for record in records {
+            formatter.write_record(&mut out, record)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for Chunks {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Sorts `records` by `key` and passes them to `emit` in order. The sort is
+/// stable. Once the buffered records exceed `max_memory` bytes they are
+/// sorted and written to `temp_dir` as a run, and the runs are merged at the
+/// end.
+pub fn sort_external(
+    records: RecordIter,
+    key: SortKey,
+    max_memory: u64,
+    temp_dir: &Path,
+    emit: &mut dyn FnMut(ResultRecord) -> Result<()>,
+) -> Result<()> {
+    let mut chunks = Chunks { paths: Vec::new() };
+    let mut buffer = Vec::new();
+    let mut buffered = 0;
+    for record in records {
+        let record = record?;
+        buffered += estimated_size(&record);
+        buffer.push(record);
+        if buffered > max_memory {
+            sort_records(&mut buffer, key);
+            chunks.write(temp_dir, &buffer)?;
+            buffer.clear();
+            buffered = 0;
+        }
+    }
+    sort_records(&mut buffer, key);
+    if chunks.paths.is_empty() {
+        return buffer.into_iter().try_for_each(emit);
+    }
+    if !buffer.is_empty() {
+        chunks.write(temp_dir, &buffer)?;
+    }
+    drop(buffer);
+
+    let mut runs = chunks
+        .paths
+        .iter()
+        .map(|path| {
+            let file = File::open(path).with_context(|| format!("Failed to open sort chunk {:?}", path))?;
+            Ok(NdjsonReader.read(Box::new(BufReader::new(file))))
+        })
+        .collect::<Result<Vec<RecordIter>>>()?;
+    let mut heads = Vec::with_capacity(runs.len());
+    // Ties go to the earlier run, which keeps the merge stable.
+    let mut heap = BinaryHeap::new();
+    for (run, records) in runs.iter_mut().enumerate() {
+        let head = records.next().transpose()?;
+        if let Some(record) = &head {
+            heap.push(Reverse((key.key(record), run)));
+        }
+        heads.push(head);
+    }
+    while let Some(Reverse((_, run))) = heap.pop() {
+        let record = heads[run].take().unwrap();
+        heads[run] = runs[run].next().transpose()?;
+        if let Some(next) = &heads[run] {
+            heap.push(Reverse((key.key(next), run)));
+        }
+        emit(record)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sort_external, SortKey};
+    use crate::{generate_record, LineNumbers, ResultRecord};
+
+    fn records() -> Vec<ResultRecord> {
+        [(2, 3, 30), (5, 7, 10), (2, 2, 20), (3, 3, 10), (2, 5, 40), (4, 4, 5)]
+            .iter()
+            .enumerate()
+            .map(|(index, &(a, b, end))| ResultRecord {
+                index,
+                ..generate_record(LineNumbers { a, b, end })
+            })
+            .collect()
+    }
+
+    fn sorted(key: SortKey, max_memory: u64) -> Vec<usize> {
+        let dir = std::env::temp_dir();
+        let mut indices = Vec::new();
+        let input = Box::new(records().into_iter().rev().map(Ok));
+        sort_external(input, key, max_memory, &dir, &mut |record| {
+            indices.push(record.index);
+            Ok(())
+        })
+        .unwrap();
+        indices
+    }
+
+    #[test]
+    fn test_sort_in_memory() {
+        assert_eq!(sorted(SortKey::Input, u64::MAX), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(sorted(SortKey::End, u64::MAX), vec![5, 3, 1, 2, 0, 4]);
+    }
+
+    #[test]
+    fn test_external_sort_matches_in_memory_sort() {
+        for key in [SortKey::End, SortKey::Count, SortKey::Input] {
+            assert_eq!(sorted(key, 1), sorted(key, u64::MAX), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn test_parse_sort_key() {
+        assert_eq!("count".parse::<SortKey>().unwrap(), SortKey::Count);
+        assert!("size".parse::<SortKey>().is_err());
+    }
+}
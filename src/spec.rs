@@ -0,0 +1,196 @@
+//! Checked building blocks of a job for library users who want invalid
+//! jobs rejected where they are made: a [`NonZeroDivisor`], the
+//! [`DivisorSet`] of a line and a [`RangeSpec`] whose start isn't above its
+//! end. [`RecordGenerator::generate_range`](crate::strategy::RecordGenerator::generate_range)
+//! takes them instead of a [`LineNumbers`].
+//!
+//! [`LineNumbers::new`] checks its divisors the same way, while the input
+//! parsers keep a divisor of 0 or other degenerate lines on purpose, see
+//! [`degenerate`](crate::degenerate).
+
+use std::fmt;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::number;
+use crate::LineNumbers;
+
+/// A divisor of at least 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NonZeroDivisor(NonZeroU32);
+
+impl NonZeroDivisor {
+    pub fn new(divisor: u32) -> Result<Self> {
+        NonZeroU32::new(divisor).map(NonZeroDivisor).ok_or_else(|| anyhow!("A divisor can't be 0"))
+    }
+
+    pub fn get(self) -> u32 {
+        self.0.get()
+    }
+}
+
+impl fmt::Display for NonZeroDivisor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Reads any literal the input accepts, such as `0x10` or `1_000`.
+impl FromStr for NonZeroDivisor {
+    type Err = anyhow::Error;
+
+    fn from_str(literal: &str) -> Result<Self> {
+        NonZeroDivisor::new(number::parse_u32(literal)?)
+    }
+}
+
+impl TryFrom<u32> for NonZeroDivisor {
+    type Error = anyhow::Error;
+
+    fn try_from(divisor: u32) -> Result<Self> {
+        NonZeroDivisor::new(divisor)
+    }
+}
+
+/// The two divisors of a line, in the order they were given.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DivisorSet {
+    pub a: NonZeroDivisor,
+    pub b: NonZeroDivisor,
+}
+
+impl DivisorSet {
+    pub fn new(a: u32, b: u32) -> Result<Self> {
+        Ok(DivisorSet {
+            a: NonZeroDivisor::new(a)?,
+            b: NonZeroDivisor::new(b)?,
+        })
+    }
+
+    /// The job selecting the multiples of the divisors from 1 to `end`.
+    pub fn up_to(self, end: u32) -> LineNumbers {
+        LineNumbers {
+            a: self.a.get(),
+            b: self.b.get(),
+            end,
+        }
+    }
+}
+
+/// The divisors of `job`, if neither is 0.
+impl TryFrom<LineNumbers> for DivisorSet {
+    type Error = anyhow::Error;
+
+    fn try_from(job: LineNumbers) -> Result<Self> {
+        DivisorSet::new(job.a, job.b)
+    }
+}
+
+/// The values `start..=end`, with `start <= end`, or none for the `1..=0`
+/// of [`RangeSpec::up_to`]`(0)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RangeSpec {
+    start: u32,
+    end: u32,
+}
+
+impl RangeSpec {
+    pub fn new(start: u32, end: u32) -> Result<Self> {
+        if start > end {
+            bail!("The range starts at {}, above its end {}", start, end);
+        }
+        Ok(RangeSpec { start, end })
+    }
+
+    /// The values `1..=end`, the range of a line of the input, which is
+    /// empty for an end of 0.
+    pub fn up_to(end: u32) -> Self {
+        RangeSpec { start: 1, end }
+    }
+
+    pub fn start(self) -> u32 {
+        self.start
+    }
+
+    pub fn end(self) -> u32 {
+        self.end
+    }
+
+    /// The number of values in the range.
+    pub fn count(self) -> u64 {
+        (self.end as u64 + 1).saturating_sub(self.start as u64)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.count() == 0
+    }
+
+    pub fn contains(self, n: u32) -> bool {
+        (self.start..=self.end).contains(&n)
+    }
+}
+
+/// Reads `start..=end`, or a lone `end` for `1..=end`.
+impl FromStr for RangeSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text.split_once("..=") {
+            Some((start, end)) => RangeSpec::new(number::parse_u32(start.trim())?, number::parse_u32(end.trim())?),
+            None => Ok(RangeSpec::up_to(number::parse_u32(text.trim())?)),
+        }
+    }
+}
+
+/// Writes `start..=end`, or `0` for the empty range, as it is read.
+impl fmt::Display for RangeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "{}", self.end);
+        }
+        write!(f, "{}..={}", self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DivisorSet, NonZeroDivisor, RangeSpec};
+    use crate::strategy::{GenerateOptions, RecordGenerator};
+    use crate::LineNumbers;
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(NonZeroDivisor::new(0).unwrap_err().to_string(), "A divisor can't be 0");
+        assert_eq!("0x10".parse::<NonZeroDivisor>().unwrap().get(), 16);
+        assert!(DivisorSet::try_from(LineNumbers { a: 3, b: 0, end: 10 }).is_err());
+        assert_eq!(DivisorSet::new(3, 5).unwrap().up_to(10), LineNumbers { a: 3, b: 5, end: 10 });
+        assert_eq!(LineNumbers::new(3, 5, 10).unwrap(), LineNumbers { a: 3, b: 5, end: 10 });
+        assert_eq!(LineNumbers::new(0, 5, 10).unwrap_err().to_string(), "A divisor can't be 0");
+
+        assert_eq!(RangeSpec::new(10, 5).unwrap_err().to_string(), "The range starts at 10, above its end 5");
+        let range: RangeSpec = "20..=30".parse().unwrap();
+        assert_eq!((range.start(), range.end(), range.count()), (20, 30, 11));
+        assert!("20..30".parse::<RangeSpec>().is_err());
+        assert_eq!("100".parse::<RangeSpec>().unwrap(), RangeSpec::new(1, 100).unwrap());
+        let empty = RangeSpec::up_to(0);
+        assert!(empty.is_empty() && !empty.contains(0) && !empty.contains(1));
+        assert_eq!(empty.to_string().parse::<RangeSpec>().unwrap(), empty);
+        assert_eq!(RangeSpec::new(0, 0).unwrap().count(), 1);
+        assert!(range.contains(30) && !range.contains(31));
+        assert_eq!(range.to_string(), "20..=30");
+    }
+
+    #[test]
+    fn test_generate_range() {
+        let generator = RecordGenerator::new(GenerateOptions::default());
+        let divisors = DivisorSet::new(3, 5).unwrap();
+        let record = generator.generate_range(divisors, "20..=30".parse().unwrap());
+        assert_eq!(record.job, LineNumbers { a: 3, b: 5, end: 30 });
+        assert_eq!(record.result.numbers, [20, 21, 24, 25, 27, 30]);
+        let whole = generator.generate_range(divisors, RangeSpec::up_to(30));
+        assert_eq!(whole, generator.generate(divisors.up_to(30)));
+        assert!(generator.generate_range(divisors, RangeSpec::up_to(0)).result.numbers.is_empty());
+    }
+}
@@ -0,0 +1,343 @@
+//! A small embedded result store: an append-only log of records keyed by
+//! `(a, b, end)`, written by the `store` output format and queried by the
+//! `get` and `list` commands.
+//!
+//! The file starts with [`MAGIC`], followed by one entry per record:
+//!
+//! ```text
+//! a, b, end      u32, little endian
+//! index          varint
+//! kind           u8: 0 lists the numbers, 1 only counts them
+//! count          varint
+//! payload length varint
+//! payload        varints of the zigzag encoded differences between
+//!                consecutive numbers, starting from 0
+//! ```
+//!
+//! Storing differences keeps the numbers of a line down to a byte or two
+//! each. A key written again replaces the earlier entry, so a store can be
+//! extended with `--append` and rewritten lines win.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::formatter::OutputFormatter;
+use crate::reader::{RecordIter, ResultReader};
+use crate::{LineNumbers, ResultNumbers, ResultRecord};
+
+/// The first bytes of every store.
+pub const MAGIC: &[u8; 8] = b"MULTKV1\n";
+
+const KIND_NUMBERS: u8 = 0;
+const KIND_COUNT: u8 = 1;
+
+pub(crate) fn write_varint(w: &mut dyn Write, mut value: u64) -> io::Result<()> {
+    let mut bytes = [0u8; 10];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes[len] = byte;
+            len += 1;
+            break;
+        }
+        bytes[len] = byte | 0x80;
+        len += 1;
+    }
+    w.write_all(&bytes[..len])
+}
+
+pub(crate) fn read_varint(r: &mut dyn Read) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        r.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("Varint too long")
+}
+
+pub(crate) fn zigzag(delta: i64) -> u64 {
+    ((delta << 1) ^ (delta >> 63)) as u64
+}
+
+pub(crate) fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+/// Writes records as store entries. Appending to an existing store is
+/// supported, since later entries replace earlier ones.
+#[derive(Default)]
+pub struct StoreFormatter;
+
+impl OutputFormatter for StoreFormatter {
+    fn begin(&mut self, w: &mut dyn Write) -> Result<()> {
+        w.write_all(MAGIC)?;
+        Ok(())
+    }
+
+    fn write_record(&mut self, w: &mut dyn Write, r: &ResultRecord) -> Result<()> {
+        let mut entry = Vec::with_capacity(16 + r.result.numbers.len());
+        for value in [r.job.a, r.job.b, r.job.end] {
+            entry.extend_from_slice(&value.to_le_bytes());
+        }
+        write_varint(&mut entry, r.index as u64)?;
+        let mut payload = Vec::with_capacity(r.result.numbers.len());
+        let mut previous = 0i64;
+        for &n in &r.result.numbers {
+            write_varint(&mut payload, zigzag(n as i64 - previous))?;
+            previous = n as i64;
+        }
+        entry.push(if r.result.count.is_some() { KIND_COUNT } else { KIND_NUMBERS });
+        write_varint(&mut entry, r.result.count())?;
+        write_varint(&mut entry, payload.len() as u64)?;
+        entry.extend_from_slice(&payload);
+        w.write_all(&entry)?;
+        Ok(())
+    }
+}
+
+fn read_magic(r: &mut dyn Read) -> Result<()> {
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic).context("Not a result store: file too short")?;
+    if &magic != MAGIC {
+        bail!("Not a result store: unexpected header");
+    }
+    Ok(())
+}
+
+/// The fixed part of an entry, before its payload.
+struct EntryHeader {
+    job: LineNumbers,
+    index: usize,
+    kind: u8,
+    count: u64,
+    payload_len: usize,
+}
+
+/// Reads the next entry header, or `None` at the end of the store.
+fn read_header(r: &mut dyn BufRead) -> Result<Option<EntryHeader>> {
+    if r.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let mut fixed = [0u8; 12];
+    r.read_exact(&mut fixed)?;
+    let field = |i: usize| u32::from_le_bytes(fixed[i * 4..i * 4 + 4].try_into().unwrap());
+    let index = read_varint(r)? as usize;
+    let mut kind = [0u8];
+    r.read_exact(&mut kind)?;
+    if kind[0] > KIND_COUNT {
+        bail!("Unknown entry kind {}", kind[0]);
+    }
+    Ok(Some(EntryHeader {
+        job: LineNumbers {
+            a: field(0),
+            b: field(1),
+            end: field(2),
+        },
+        index,
+        kind: kind[0],
+        count: read_varint(r)?,
+        payload_len: read_varint(r)? as usize,
+    }))
+}
+
+fn read_record(header: EntryHeader, payload: &[u8]) -> Result<ResultRecord> {
+    let mut numbers = Vec::new();
+    let mut count = None;
+    if header.kind == KIND_COUNT {
+        count = Some(header.count);
+    } else {
+        let mut payload = payload;
+        let mut previous = 0i64;
+        while !payload.is_empty() {
+            let n = previous + unzigzag(read_varint(&mut payload)?);
+            numbers.push(u32::try_from(n).context("Number out of range")?);
+            previous = n;
+        }
+        if numbers.len() as u64 != header.count {
+            bail!("Entry holds {} numbers instead of {}", numbers.len(), header.count);
+        }
+    }
+    Ok(ResultRecord {
+        index: header.index,
+        job: header.job,
+        result: ResultNumbers {
+            end: header.job.end,
+            numbers,
+            count,
+        },
+    })
+}
+
+/// Reads every record of a store in key order, latest entry per key.
+pub struct StoreReader;
+
+impl ResultReader for StoreReader {
+    fn read<'a>(&self, mut reader: Box<dyn BufRead + Send + 'a>) -> RecordIter<'a> {
+        let mut read_all = || -> Result<BTreeMap<(u32, u32, u32), ResultRecord>> {
+            read_magic(&mut reader)?;
+            let mut records = BTreeMap::new();
+            let mut entry = 0usize;
+            while let Some(header) = read_header(&mut reader).with_context(|| format!("Entry {}: truncated", entry + 1))? {
+                let mut payload = vec![0u8; header.payload_len];
+                reader
+                    .read_exact(&mut payload)
+                    .with_context(|| format!("Entry {}: truncated", entry + 1))?;
+                let record = read_record(header, &payload).with_context(|| format!("Entry {}", entry + 1))?;
+                records.insert((record.job.a, record.job.b, record.job.end), record);
+                entry += 1;
+            }
+            Ok(records)
+        };
+        match read_all() {
+            Ok(records) => Box::new(records.into_values().map(Ok)),
+            Err(err) => Box::new(std::iter::once(Err(err))),
+        }
+    }
+}
+
+/// Where the latest entry of a key starts, and how many numbers it has.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StoreEntry {
+    pub job: LineNumbers,
+    pub count: u64,
+    offset: u64,
+}
+
+/// An opened store, indexed by key for repeated lookups. Only the entry
+/// headers are read when opening; numbers are decoded on [`Store::get`].
+pub struct Store {
+    path: PathBuf,
+    file: BufReader<File>,
+    entries: BTreeMap<(u32, u32, u32), StoreEntry>,
+}
+
+impl Store {
+    pub fn open(path: &Path) -> Result<Store> {
+        let file = File::open(path).with_context(|| format!("Failed to open store: {:?}", path))?;
+        let mut file = BufReader::new(file);
+        read_magic(&mut file).with_context(|| format!("Failed to open store: {:?}", path))?;
+        let mut entries = BTreeMap::new();
+        loop {
+            let offset = file.stream_position()?;
+            let header = read_header(&mut file).with_context(|| format!("Store {:?}: truncated entry at byte {}", path, offset))?;
+            let Some(header) = header else {
+                break;
+            };
+            let key = (header.job.a, header.job.b, header.job.end);
+            entries.insert(
+                key,
+                StoreEntry {
+                    job: header.job,
+                    count: header.count,
+                    offset,
+                },
+            );
+            file.seek_relative(header.payload_len as i64)?;
+        }
+        Ok(Store {
+            path: path.to_path_buf(),
+            file,
+            entries,
+        })
+    }
+
+    /// The latest entry of every key, in key order.
+    pub fn entries(&self) -> impl Iterator<Item = &StoreEntry> {
+        self.entries.values()
+    }
+
+    /// Reads the record stored for `job`, if any.
+    pub fn get(&mut self, job: LineNumbers) -> Result<Option<ResultRecord>> {
+        let Some(entry) = self.entries.get(&(job.a, job.b, job.end)) else {
+            return Ok(None);
+        };
+        let path = &self.path;
+        self.file.seek(SeekFrom::Start(entry.offset))?;
+        let header = read_header(&mut self.file)?.with_context(|| format!("Store {:?} changed while open", path))?;
+        let mut payload = vec![0u8; header.payload_len];
+        self.file
+            .read_exact(&mut payload)
+            .with_context(|| format!("Store {:?}: truncated entry at byte {}", path, entry.offset))?;
+        read_record(header, &payload).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unzigzag, zigzag, Store, StoreFormatter, StoreReader};
+    use crate::formatter::OutputFormatter;
+    use crate::predicate::Divisible;
+    use crate::reader::ResultReader;
+    use crate::{count_record, formula::Mode, generate_record, generate_record_descending, LineNumbers, ResultRecord};
+
+    fn write_store(records: &[ResultRecord]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut formatter = StoreFormatter;
+        formatter.begin(&mut out).unwrap();
+        for record in records {
+            formatter.write_record(&mut out, record).unwrap();
+        }
+        formatter.finish(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_zigzag() {
+        for delta in [0, 1, -1, 2, -4_294_967_295, 4_294_967_295] {
+            assert_eq!(unzigzag(zigzag(delta)), delta);
+        }
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+    }
+
+    #[test]
+    fn test_store_round_trip_and_lookup() {
+        let job = |a, b, end| LineNumbers { a, b, end };
+        let mut replaced = generate_record(job(3, 5, 100));
+        replaced.index = 7;
+        let records = vec![
+            generate_record(job(3, 5, 100)),
+            generate_record_descending(job(2, 7, 30), &Divisible),
+            count_record(job(4, 6, 1000), Mode::Or),
+            replaced.clone(),
+        ];
+        let bytes = write_store(&records);
+        // Differences of 1 to 3 take a byte each.
+        assert!(bytes.len() < 8 + 4 * (47 + 15 + 47));
+
+        let read: Vec<ResultRecord> = StoreReader
+            .read(Box::new(&bytes[..]))
+            .collect::<anyhow::Result<_>>()
+            .unwrap();
+        assert_eq!(read, vec![records[1].clone(), replaced.clone(), records[2].clone()]);
+
+        let path = std::env::temp_dir().join(format!("multiples_store_{}.db", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        let mut store = Store::open(&path).unwrap();
+        let counts: Vec<u64> = store.entries().map(|entry| entry.count).collect();
+        assert_eq!(counts, vec![records[1].result.count(), 47, 333]);
+        assert_eq!(store.get(job(3, 5, 100)).unwrap(), Some(replaced));
+        assert_eq!(store.get(job(4, 6, 1000)).unwrap(), Some(records[2].clone()));
+        assert_eq!(store.get(job(4, 6, 999)).unwrap(), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_store_rejects_damaged_files() {
+        let bytes = write_store(&[generate_record(LineNumbers { a: 3, b: 5, end: 100 })]);
+        let read = |bytes: &[u8]| StoreReader.read(Box::new(bytes)).collect::<anyhow::Result<Vec<_>>>();
+        assert!(read(&bytes[..bytes.len() - 1]).is_err());
+        assert!(read(b"MULTKV0\n").is_err());
+        assert_eq!(read(&bytes[..8]).unwrap(), vec![]);
+    }
+}
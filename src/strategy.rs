@@ -0,0 +1,941 @@
+//! The default record generator and the strategies it can enumerate
+//! multiples with.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use anyhow::Result;
+
+use crate::cancel::CancellationToken;
+use crate::chunk::{Backpressure, ChunkSizer, MIN_CHUNK};
+use crate::errors::{CROSS_CHECK_FAILED, UNKNOWN_NAME};
+use crate::formula::{self, Mode};
+use crate::kernel::Kernel;
+use crate::observer::{Flow, LineStats, Observer};
+use crate::predicate::Predicate;
+use crate::random::Rng;
+use crate::shard::Shard;
+use crate::spec::{DivisorSet, RangeSpec};
+use crate::throttle::Throttle;
+use crate::wheel::{Wheel, WheelCache};
+use crate::{LineNumbers, ResultNumbers, ResultRecord};
+
+/// Windows of each range compared by [`RecordGenerator::cross_check`].
+const CROSS_CHECK_WINDOWS: u64 = 4;
+/// Values in each cross-checked window.
+const CROSS_CHECK_WINDOW: u64 = 4096;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Strategy {
+    /// Tests every number of the range for divisibility, with the fastest
+    /// [`Kernel`] the CPU supports.
+    #[default]
+    Trial,
+    /// Tiles the precomputed residues of each divisor pair across the
+    /// range, falling back to `Trial` for pairs with very long periods.
+    Wheel,
+}
+
+impl FromStr for Strategy {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "trial" => Ok(Strategy::Trial),
+            "wheel" => Ok(Strategy::Wheel),
+            _ => Err(UNKNOWN_NAME.error(format!("Unknown strategy: {} (available: trial, wheel)", name))),
+        }
+    }
+}
+
+/// The work of listing and writing a selected value, in tests of a number
+/// by trial division, roughly as `explain` calibrates it.
+pub const SELECTED_COST: u64 = 4;
+
+/// What the generator selects for each job and how it lists it.
+#[derive(Default)]
+pub struct GenerateOptions {
+    pub strategy: Strategy,
+    pub mode: Mode,
+    /// A further condition checked after divisibility, such as a digit filter.
+    pub filter: Option<Box<dyn Predicate>>,
+    pub descending: bool,
+    /// Only count the selected numbers. Without a filter the count is
+    /// computed in closed form whatever the strategy.
+    pub count_only: bool,
+    /// Use the scalar trial division kernel even when SIMD ones are available.
+    pub force_scalar: bool,
+    /// Generate with the divisors as written instead of their simplified
+    /// form, see [`formula::simplify`].
+    pub keep_divisors: bool,
+    /// Generate only this shard of each range. Records keep the whole
+    /// range as their `end`.
+    pub shard: Option<Shard>,
+    /// Sleep between chunks so that each generating thread takes at most
+    /// this share of a CPU.
+    pub throttle: Option<Throttle>,
+    /// Start every range at 0 rather than 1. 0 is a multiple of every
+    /// divisor, 0 included, so it is selected as often as a multiple of
+    /// both: once with `or`, never with `xor` and twice with `multiset`.
+    /// Of the shards of a range only the first holds it, and empty ranges
+    /// don't.
+    pub include_zero: bool,
+    /// Called on every line generated, see [`Observer`].
+    pub observers: Vec<Arc<dyn Observer>>,
+    /// List at most this many multiples of each record, the first ones in
+    /// listing order, and count at most as many. Without a filter only the
+    /// part of the range holding them is generated.
+    pub stop_after: Option<u64>,
+    /// Generate no values above this one, as if it were the end of every
+    /// range that reaches beyond it. Records keep their `end`.
+    pub stop_above: Option<u32>,
+    /// Stop generating once the token is cancelled. Lines generated in
+    /// chunks, by [`RecordGenerator::generate_chunked`] and
+    /// [`RecordGenerator::chunks`], stop before their next chunk and keep
+    /// the start of their range, as when an [`Observer`] stops them.
+    pub cancellation: Option<CancellationToken>,
+}
+
+/// Generates records according to [`GenerateOptions`], sharing wheels
+/// between jobs with the same divisors.
+pub struct RecordGenerator {
+    options: GenerateOptions,
+    kernel: Kernel,
+    wheels: WheelCache,
+}
+
+impl RecordGenerator {
+    pub fn new(options: GenerateOptions) -> Self {
+        let kernel = if options.force_scalar { Kernel::Scalar } else { Kernel::detect() };
+        RecordGenerator {
+            options,
+            kernel,
+            wheels: WheelCache::default(),
+        }
+    }
+
+    pub fn options(&self) -> &GenerateOptions {
+        &self.options
+    }
+
+    /// The job actually generated for `job`: the simplified one, unless
+    /// [`GenerateOptions::keep_divisors`] is set.
+    pub fn effective_job(&self, job: &LineNumbers) -> LineNumbers {
+        if self.options.keep_divisors {
+            return *job;
+        }
+        formula::simplify(job, self.options.mode).0
+    }
+
+    /// Whether `job` is counted in closed form rather than enumerated.
+    fn closed_form(&self) -> bool {
+        self.options.count_only && self.options.filter.is_none()
+    }
+
+    /// Whether generating `job` tests every number of its range, rather
+    /// than counting in closed form or tiling a wheel.
+    pub fn tests_every_number(&self, job: &LineNumbers) -> bool {
+        let job = self.effective_job(job);
+        match self.options.strategy {
+            _ if self.closed_form() => false,
+            Strategy::Trial => true,
+            Strategy::Wheel => Wheel::new(job.a, job.b, self.options.mode).is_none(),
+        }
+    }
+
+    /// Describes how `job` will be generated, e.g. `wheel (period 15, 7 residues)`.
+    pub fn describe(&self, job: &LineNumbers) -> String {
+        let options = &self.options;
+        if self.closed_form() {
+            return String::from("closed form");
+        }
+        let job = self.effective_job(job);
+        let trial = format!("trial ({})", self.kernel.name());
+        match options.strategy {
+            Strategy::Trial => trial,
+            Strategy::Wheel => match Wheel::new(job.a, job.b, options.mode) {
+                Some(wheel) => format!("wheel (period {}, {} residues)", wheel.period(), wheel.count(wheel.period())),
+                None => format!("{}, period too long for a wheel", trial),
+            },
+        }
+    }
+
+    /// The first value of `job`'s range generated, and the last: those of
+    /// its shard, cut to what [`GenerateOptions::stop_above`] and
+    /// [`GenerateOptions::stop_after`] leave.
+    fn bounds(&self, job: &LineNumbers) -> (u32, u32) {
+        self.bounds_from(job, 0)
+    }
+
+    /// Like [`RecordGenerator::bounds`], starting at `start` at the least.
+    fn bounds_from(&self, job: &LineNumbers, start: u32) -> (u32, u32) {
+        let (first, last) = match self.options.shard {
+            Some(shard) => shard.bounds(job.end),
+            None => (1, job.end),
+        };
+        let first_shard = self.options.shard.is_none_or(|shard| shard.index == 1);
+        // A range left empty, by an end of 0 or an exclusive end of 1 or less, stays empty.
+        let first = if self.options.include_zero && first_shard && first <= last { 0 } else { first };
+        let first = first.max(start);
+        let last = self.options.stop_above.map_or(last, |above| last.min(above));
+        match self.options.stop_after {
+            Some(limit) if self.options.filter.is_none() => self.first_multiples(job, first, last, limit),
+            _ => (first, last),
+        }
+    }
+
+    /// `first..=last` narrowed down to the values holding the first `limit`
+    /// multiples in listing order. A value listed twice at the cut, or 0,
+    /// may leave a few more, which [`RecordGenerator::truncate`] drops.
+    fn first_multiples(&self, job: &LineNumbers, first: u32, last: u32, limit: u64) -> (u32, u32) {
+        let job = self.effective_job(job);
+        let mode = self.options.mode;
+        let upto = |end: u32| formula::count(&LineNumbers { end, ..job }, mode);
+        if first > last {
+            return (first, last);
+        }
+        let (before, total) = (upto(first.max(1) - 1), upto(last));
+        if total - before <= limit {
+            return (first, last);
+        }
+        let range = LineNumbers { end: last, ..job };
+        match self.options.descending {
+            false => (first, formula::nth(&range, mode, before + limit).unwrap_or(last)),
+            true => (formula::nth(&range, mode, total - limit + 1).unwrap_or(first).max(first), last),
+        }
+    }
+
+    /// Cuts `result` to [`GenerateOptions::stop_after`] multiples.
+    fn truncate(&self, result: &mut ResultNumbers) {
+        let Some(limit) = self.options.stop_after else {
+            return;
+        };
+        result.numbers.truncate(usize::try_from(limit).unwrap_or(usize::MAX));
+        if let Some(count) = &mut result.count {
+            *count = (*count).min(limit);
+        }
+    }
+
+    /// How often 0 is selected for `job`, see [`GenerateOptions::include_zero`].
+    fn zero_copies(&self, job: &LineNumbers) -> usize {
+        match self.options.filter.as_ref().is_none_or(|filter| filter.matches(job, 0)) {
+            true => self.options.mode.copies(2),
+            false => 0,
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        self.options.cancellation.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+
+    fn line_started(&self, job: &LineNumbers) {
+        self.options.observers.iter().for_each(|observer| observer.on_line_start(job));
+    }
+
+    /// Passes a chunk to every observer, stopping if any of them does.
+    fn chunk_done(&self, job: &LineNumbers, numbers: &[u32]) -> Flow {
+        let flows = self.options.observers.iter().map(|observer| observer.on_chunk(job, numbers));
+        match flows.fold(false, |stop, flow| stop | (flow == Flow::Stop)) {
+            true => Flow::Stop,
+            false => Flow::Continue,
+        }
+    }
+
+    fn line_done(&self, job: &LineNumbers, count: u64, started: Instant, stopped: bool) {
+        let stats = LineStats {
+            count,
+            elapsed: started.elapsed(),
+            stopped,
+        };
+        self.options.observers.iter().for_each(|observer| observer.on_line_done(job, &stats));
+    }
+
+    fn record_done(&self, record: &ResultRecord, started: Instant, stopped: bool) {
+        let count = record.result.count.unwrap_or(record.result.numbers.len() as u64);
+        self.line_done(&record.job, count, started, stopped);
+    }
+
+    /// Generates the record of `job`. The record keeps `job` as written,
+    /// even when a simplified job was generated.
+    pub fn generate(&self, job: LineNumbers) -> ResultRecord {
+        self.generate_reporting(job, &mut |_| {})
+    }
+
+    /// Like [`RecordGenerator::generate`], calling `progress` with the
+    /// number of values of the range dealt with as generation goes on; the
+    /// calls add up to `job.end`.
+    pub fn generate_reporting(&self, job: LineNumbers, progress: &mut dyn FnMut(u64)) -> ResultRecord {
+        self.generate_from(job, 0, progress)
+    }
+
+    /// Generates the multiples of `divisors` in `range`, as a record of the
+    /// job ending at the end of the range. The other options apply as to
+    /// [`RecordGenerator::generate`].
+    pub fn generate_range(&self, divisors: DivisorSet, range: RangeSpec) -> ResultRecord {
+        self.generate_from(divisors.up_to(range.end()), range.start(), &mut |_| {})
+    }
+
+    fn generate_from(&self, job: LineNumbers, start: u32, progress: &mut dyn FnMut(u64)) -> ResultRecord {
+        let started = Instant::now();
+        self.line_started(&job);
+        let effective = self.effective_job(&job);
+        let mut reported = 0;
+        let (first, last) = self.bounds_from(&job, start);
+        let mut stopped = false;
+        let mut result = if self.closed_form() {
+            let result = ResultNumbers {
+                end: job.end,
+                numbers: Vec::new(),
+                count: Some(self.closed_count(&effective, first, last)),
+            };
+            // There is nothing left to stop.
+            self.chunk_done(&job, &result.numbers);
+            result
+        } else {
+            let mut result = ResultNumbers {
+                end: job.end,
+                numbers: Vec::new(),
+                count: self.options.count_only.then_some(0),
+            };
+            // Without observers to pass them to, the range is one chunk.
+            let size = if self.options.observers.is_empty() { u64::MAX } else { MIN_CHUNK as u64 };
+            let mut chunks = chunk_bounds(first, last, size);
+            if self.options.descending {
+                chunks.reverse();
+            }
+            for (low, high) in chunks {
+                let part = self.enumerate(effective, low, high, &mut |n| {
+                    reported += n;
+                    progress(n);
+                });
+                result.numbers.extend_from_slice(&part.numbers);
+                if let (Some(count), Some(part)) = (&mut result.count, part.count) {
+                    *count += part;
+                }
+                if self.chunk_done(&job, &part.numbers) == Flow::Stop {
+                    stopped = true;
+                    break;
+                }
+            }
+            result
+        };
+        self.truncate(&mut result);
+        if reported < job.end as u64 {
+            progress(job.end as u64 - reported);
+        }
+        let record = ResultRecord { index: 0, job, result };
+        self.record_done(&record, started, stopped);
+        record
+    }
+
+    /// Like [`RecordGenerator::generate_reporting`], splitting the range into
+    /// chunks generated on `threads` threads, each sized by the thread's
+    /// [`ChunkSizer`]. Jobs counted in closed form, ranges of less than two
+    /// chunks and a single thread are generated as a whole. The record is
+    /// the same as [`RecordGenerator::generate`] gives.
+    pub fn generate_chunked(
+        &self,
+        job: LineNumbers,
+        threads: usize,
+        backpressure: Option<&Backpressure>,
+        progress: &(dyn Fn(u64) + Sync),
+    ) -> ResultRecord {
+        let (first, last) = self.bounds(&job);
+        let values = (last as u64 + 1).saturating_sub(first as u64);
+        let throttle = self.options.throttle.as_ref();
+        // A throttled or cancellable line is generated in chunks even on one
+        // thread, to pause or stop between them.
+        let whole = threads < 2 && throttle.is_none() && self.options.cancellation.is_none();
+        if whole || self.closed_form() || values < 2 * MIN_CHUNK as u64 {
+            let started = Instant::now();
+            let record = self.generate_reporting(job, &mut |n| progress(n));
+            if let Some(throttle) = throttle {
+                throttle.pace(started.elapsed());
+            }
+            return record;
+        }
+        let started = Instant::now();
+        self.line_started(&job);
+        let effective = self.effective_job(&job);
+        let next = Mutex::new(first as u64);
+        let parts = Mutex::new(Vec::new());
+        let stopped = AtomicBool::new(false);
+        let reported = AtomicU64::new(0);
+        let report = |n| {
+            reported.fetch_add(n, Ordering::Relaxed);
+            progress(n);
+        };
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    let mut sizer = ChunkSizer::default();
+                    while !stopped.load(Ordering::Relaxed) && !self.cancelled() {
+                        let start = {
+                            let mut next = next.lock().unwrap();
+                            let start = *next;
+                            *next = (start + sizer.size() as u64).min(last as u64 + 1);
+                            start
+                        };
+                        if start > last as u64 {
+                            break;
+                        }
+                        let end = (start + sizer.size() as u64 - 1).min(last as u64);
+                        let started = Instant::now();
+                        let part = self.enumerate(effective, start as u32, end as u32, &mut |n| report(n));
+                        let behind = backpressure.is_some_and(Backpressure::is_behind);
+                        sizer.observe(end - start + 1, started.elapsed(), behind);
+                        if self.chunk_done(&job, &part.numbers) == Flow::Stop {
+                            stopped.store(true, Ordering::Relaxed);
+                        }
+                        parts.lock().unwrap().push((start, end, part));
+                        if let Some(throttle) = throttle {
+                            throttle.pace(started.elapsed());
+                        }
+                    }
+                });
+            }
+        });
+        let mut parts = parts.into_inner().unwrap();
+        parts.sort_by_key(|(start, _, _)| *start);
+        let stopped = stopped.into_inner() || self.cancelled();
+        if stopped {
+            // Chunks after a gap left by the stop are dropped, so that the
+            // record covers the start of the range.
+            let mut next = first as u64;
+            let contiguous = parts.iter().take_while(|(start, end, _)| {
+                let follows = *start == next;
+                next = end + 1;
+                follows
+            });
+            parts.truncate(contiguous.count());
+        }
+        if self.options.descending {
+            parts.reverse();
+        }
+        let mut result = ResultNumbers {
+            end: job.end,
+            numbers: Vec::new(),
+            count: self.options.count_only.then_some(0),
+        };
+        for (_, _, part) in parts {
+            result.numbers.extend(part.numbers);
+            if let (Some(count), Some(part)) = (&mut result.count, part.count) {
+                *count += part;
+            }
+        }
+        self.truncate(&mut result);
+        let reported = reported.into_inner();
+        if reported < job.end as u64 {
+            progress(job.end as u64 - reported);
+        }
+        let record = ResultRecord { index: 0, job, result };
+        self.record_done(&record, started, stopped);
+        record
+    }
+
+    /// A relative estimate of the work of generating `job`: the numbers of
+    /// its range tested one by one, plus [`SELECTED_COST`] for each value
+    /// selected.
+    pub fn estimated_cost(&self, job: &LineNumbers) -> u64 {
+        if self.closed_form() {
+            return 1;
+        }
+        let (first, last) = self.bounds(job);
+        let tested = match self.tests_every_number(job) {
+            true => (last as u64 + 1).saturating_sub(first as u64),
+            false => 0,
+        };
+        tested + self.predicted_count(job) * SELECTED_COST
+    }
+
+    /// The number of values generating `job` selects before any filter,
+    /// which bounds how many numbers its record holds.
+    pub fn predicted_count(&self, job: &LineNumbers) -> u64 {
+        let (first, last) = self.bounds(job);
+        let count = self.closed_count(&self.effective_job(job), first, last);
+        self.options.stop_after.map_or(count, |limit| count.min(limit))
+    }
+
+    /// The number of values generating `job` selects, when it is known
+    /// without generating them, that is without a filter.
+    pub fn known_count(&self, job: &LineNumbers) -> Option<u64> {
+        self.options.filter.is_none().then(|| self.predicted_count(job))
+    }
+
+    /// The numbers of `job`'s record in order, in chunks of the numbers of
+    /// at most `size` values of the range each, so that they can be written
+    /// without holding all of them. The chunks end early when an
+    /// [`Observer`] stops the line.
+    pub fn chunks(&self, job: LineNumbers, size: u32) -> impl Iterator<Item = Vec<u32>> + '_ {
+        let started = Instant::now();
+        self.line_started(&job);
+        let effective = self.effective_job(&job);
+        let (first, last) = self.bounds(&job);
+        let size = size.max(1) as u64;
+        let chunks = (last as u64 + 1).saturating_sub(first as u64).div_ceil(size);
+        let mut order: Box<dyn Iterator<Item = u64>> = Box::new(0..chunks);
+        if self.options.descending {
+            order = Box::new((0..chunks).rev());
+        }
+        let (mut count, mut stopped, mut done) = (0, false, false);
+        let limit = self.options.stop_after.unwrap_or(u64::MAX);
+        std::iter::from_fn(move || {
+            let chunk = match order.next() {
+                Some(chunk) if !stopped && count < limit && !self.cancelled() => chunk,
+                _ => {
+                    if !done {
+                        done = true;
+                        self.line_done(&job, count, started, stopped || self.cancelled());
+                    }
+                    return None;
+                }
+            };
+            let start = first as u64 + chunk * size;
+            let end = (start + size - 1).min(last as u64);
+            let mut numbers = self.enumerate(effective, start as u32, end as u32, &mut |_| {}).numbers;
+            numbers.truncate(usize::try_from(limit - count).unwrap_or(usize::MAX));
+            count += numbers.len() as u64;
+            stopped = self.chunk_done(&job, &numbers) == Flow::Stop;
+            Some(numbers)
+        })
+    }
+
+    /// The number of values in `first..=last` selected for `job`, in closed form.
+    fn closed_count(&self, job: &LineNumbers, first: u32, last: u32) -> u64 {
+        let upto = |end: u32| formula::count(&LineNumbers { end, ..*job }, self.options.mode);
+        if first > last {
+            0
+        } else if first == 0 {
+            self.options.mode.copies(2) as u64 + self.closed_count(job, 1, last)
+        } else {
+            upto(last) - upto(first - 1)
+        }
+    }
+
+    /// Selects the numbers of `first..=last` for `job`. The result keeps
+    /// `job.end` as its end.
+    fn enumerate(&self, job: LineNumbers, first: u32, last: u32, progress: &mut dyn FnMut(u64)) -> ResultNumbers {
+        let options = &self.options;
+        // The kernels and wheels start at 1.
+        if first == 0 {
+            let zeros = std::iter::repeat_n(0, self.zero_copies(&job));
+            let mut rest = self.enumerate(job, 1, last, progress);
+            match &mut rest.count {
+                Some(count) => *count += zeros.len() as u64,
+                None if options.descending => rest.numbers.extend(zeros),
+                None => {
+                    rest.numbers.splice(0..0, zeros);
+                }
+            }
+            return rest;
+        }
+        let wheel = match options.strategy {
+            Strategy::Trial => None,
+            Strategy::Wheel => self.wheels.get(job.a, job.b, options.mode),
+        };
+        let range = LineNumbers { end: last, ..job };
+        let mut numbers: Box<dyn DoubleEndedIterator<Item = u32> + '_> = match &wheel {
+            _ if first > last => Box::new(std::iter::empty()),
+            Some(wheel) => Box::new(wheel.numbers_from(first, last)),
+            None => Box::new(self.kernel.select_from(&range, first, options.mode, progress).into_iter()),
+        };
+        if let Some(filter) = &options.filter {
+            numbers = Box::new(numbers.filter(move |&n| filter.matches(&job, n)));
+        }
+        if options.descending {
+            numbers = Box::new(numbers.rev());
+        }
+        if options.count_only {
+            return ResultNumbers {
+                end: job.end,
+                numbers: Vec::new(),
+                count: Some(numbers.count() as u64),
+            };
+        }
+        ResultNumbers {
+            end: job.end,
+            numbers: numbers.collect(),
+            count: None,
+        }
+    }
+
+    /// Compares `record`, as generated from its job, with plain trial
+    /// division on a few windows of the range: its start and end and two
+    /// drawn at random, seeded with the job so that reruns check the same
+    /// windows. Count-only records hold a count of the whole range, so for
+    /// them the window is counted the same way the record was instead.
+    pub fn cross_check(&self, record: &ResultRecord) -> Result<()> {
+        let job = record.job;
+        let (first, last) = self.bounds(&job);
+        if first > last {
+            return Ok(());
+        }
+        let effective = self.effective_job(&job);
+        for (low, high) in sample_windows(&job, first, last) {
+            let expected = self.reference(&job, &effective, low, high);
+            let fail = |detail: String| {
+                Err(CROSS_CHECK_FAILED.error(format!(
+                    "Cross-check failed for a:{} b:{} end:{} in {}..={}: {} {}",
+                    job.a,
+                    job.b,
+                    job.end,
+                    low,
+                    high,
+                    self.describe(&job),
+                    detail
+                )))
+            };
+            if record.result.count.is_some() {
+                let count = match self.closed_form() {
+                    true => self.closed_count(&effective, low, high),
+                    false => self.enumerate(effective, low, high, &mut |_| {}).count(),
+                };
+                if count != expected.len() as u64 {
+                    return fail(format!("counts {} where trial division counts {}", count, expected.len()));
+                }
+                continue;
+            }
+            let mut listed: Vec<u32> = record.result.numbers.iter().copied().filter(|n| (low..=high).contains(n)).collect();
+            listed.sort_unstable();
+            if listed != expected {
+                let at = listed.iter().zip(&expected).take_while(|(x, y)| x == y).count();
+                return fail(match (listed.get(at), expected.get(at)) {
+                    (Some(n), Some(m)) if n < m => format!("lists {}, which trial division doesn't select", n),
+                    (Some(_), Some(m)) | (None, Some(m)) => format!("misses {}, which trial division selects", m),
+                    (Some(n), None) => format!("lists {}, which trial division doesn't select", n),
+                    (None, None) => unreachable!(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The numbers of `low..=high` selected for `job` by testing each one
+    /// against its divisors as written, without any of the faster paths.
+    /// Filters see `effective` like they do when generating.
+    fn reference(&self, job: &LineNumbers, effective: &LineNumbers, low: u32, high: u32) -> Vec<u32> {
+        let options = &self.options;
+        let mut numbers = Vec::new();
+        for n in low..=high {
+            let hits = n.is_multiple_of(job.a) as u8 + n.is_multiple_of(job.b) as u8;
+            if options.filter.as_ref().is_none_or(|filter| filter.matches(effective, n)) {
+                numbers.extend(std::iter::repeat_n(n, options.mode.copies(hits)));
+            }
+        }
+        numbers
+    }
+}
+
+/// `first..=last` split into chunks of `size` values, the last one shorter;
+/// an empty range is one empty chunk.
+fn chunk_bounds(first: u32, last: u32, size: u64) -> Vec<(u32, u32)> {
+    let mut chunks = Vec::new();
+    let mut low = first as u64;
+    loop {
+        let high = low.saturating_add(size - 1).min(last as u64);
+        chunks.push((low as u32, high as u32));
+        if high >= last as u64 {
+            return chunks;
+        }
+        low = high + 1;
+    }
+}
+
+/// The windows of `first..=last` checked by [`RecordGenerator::cross_check`].
+fn sample_windows(job: &LineNumbers, first: u32, last: u32) -> Vec<(u32, u32)> {
+    let (first, last) = (first as u64, last as u64);
+    let window = |low: u64| (low as u32, (low + CROSS_CHECK_WINDOW - 1).min(last) as u32);
+    if last - first < CROSS_CHECK_WINDOWS * CROSS_CHECK_WINDOW {
+        return (first..=last).step_by(CROSS_CHECK_WINDOW as usize).map(window).collect();
+    }
+    let mut rng = Rng::new((job.a as u64) << 32 ^ job.b as u64 ^ (job.end as u64).rotate_left(17));
+    let mut starts = vec![first, last + 1 - CROSS_CHECK_WINDOW];
+    for _ in 2..CROSS_CHECK_WINDOWS {
+        starts.push(rng.range(first, last + 1 - CROSS_CHECK_WINDOW));
+    }
+    starts.into_iter().map(window).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::{GenerateOptions, RecordGenerator, Strategy};
+    use crate::formula::Mode;
+    use crate::input::Bound;
+    use crate::predicate::Palindrome;
+    use crate::shard::Shard;
+    use crate::LineNumbers;
+
+    fn generate(options: GenerateOptions, job: LineNumbers) -> Vec<u32> {
+        RecordGenerator::new(options).generate(job).result.numbers
+    }
+
+    #[test]
+    fn test_strategies_agree() {
+        let jobs = [(3, 5, 1000), (4, 6, 99), (65_521, 65_519, 200_000), (0, 7, 50)];
+        for (a, b, end) in jobs {
+            let job = LineNumbers { a, b, end };
+            for mode in [Mode::Or, Mode::Xor, Mode::Multiset] {
+                let trial = generate(GenerateOptions { mode, ..Default::default() }, job);
+                let scalar = GenerateOptions {
+                    mode,
+                    force_scalar: true,
+                    ..Default::default()
+                };
+                assert_eq!(generate(scalar, job), trial, "{:?} {:?}", job, mode);
+                let wheel = GenerateOptions {
+                    strategy: Strategy::Wheel,
+                    mode,
+                    ..Default::default()
+                };
+                assert_eq!(generate(wheel, job), trial, "{:?} {:?}", job, mode);
+            }
+        }
+    }
+
+    #[test]
+    fn test_filter_descending_and_count() {
+        let job = LineNumbers { a: 3, b: 5, end: 200 };
+        let options = || GenerateOptions {
+            strategy: Strategy::Wheel,
+            filter: Some(Box::new(Palindrome)),
+            descending: true,
+            ..Default::default()
+        };
+        assert_eq!(generate(options(), job), vec![171, 141, 111, 99, 66, 55, 33, 9, 6, 5, 3]);
+        let counted = RecordGenerator::new(GenerateOptions {
+            count_only: true,
+            ..options()
+        })
+        .generate(job);
+        assert_eq!(counted.result.count, Some(11));
+    }
+
+    #[test]
+    fn test_simplified_jobs_keep_their_record() {
+        let job = LineNumbers { a: 9, b: 3, end: 100 };
+        let generator = RecordGenerator::new(GenerateOptions {
+            strategy: Strategy::Wheel,
+            ..Default::default()
+        });
+        assert_eq!(generator.describe(&job), "wheel (period 3, 1 residues)");
+        let record = generator.generate(job);
+        assert_eq!(record.job, job);
+        let kept = GenerateOptions {
+            keep_divisors: true,
+            ..Default::default()
+        };
+        assert_eq!(record.result.numbers, generate(kept, job));
+    }
+
+    #[test]
+    fn test_generate_reports_progress() {
+        for strategy in [Strategy::Trial, Strategy::Wheel] {
+            let generator = RecordGenerator::new(GenerateOptions {
+                strategy,
+                ..Default::default()
+            });
+            let mut calls = Vec::new();
+            generator.generate_reporting(LineNumbers { a: 3, b: 5, end: 10_000 }, &mut |n| calls.push(n));
+            assert_eq!(calls.iter().sum::<u64>(), 10_000);
+            assert_eq!(calls.len() > 1, strategy == Strategy::Trial);
+        }
+    }
+
+    #[test]
+    fn test_generate_chunked() {
+        let job = LineNumbers { a: 6, b: 10, end: 1_000_000 };
+        let options = || {
+            [
+                GenerateOptions::default(),
+                GenerateOptions {
+                    strategy: Strategy::Wheel,
+                    descending: true,
+                    ..Default::default()
+                },
+                GenerateOptions {
+                    mode: Mode::Multiset,
+                    filter: Some(Box::new(Palindrome)),
+                    count_only: true,
+                    ..Default::default()
+                },
+                GenerateOptions {
+                    shard: Some(Shard { index: 2, count: 3 }),
+                    ..Default::default()
+                },
+            ]
+        };
+        for (options, again) in options().into_iter().zip(options()) {
+            let whole = RecordGenerator::new(options).generate(job);
+            let reported = AtomicU64::new(0);
+            let chunked = RecordGenerator::new(again).generate_chunked(job, 3, None, &|n| {
+                reported.fetch_add(n, Ordering::Relaxed);
+            });
+            assert_eq!(chunked, whole);
+            assert_eq!(reported.into_inner(), 1_000_000);
+        }
+    }
+
+    #[test]
+    fn test_chunks() {
+        let job = LineNumbers { a: 6, b: 10, end: 1000 };
+        let options = || {
+            [
+                GenerateOptions::default(),
+                GenerateOptions {
+                    strategy: Strategy::Wheel,
+                    descending: true,
+                    ..Default::default()
+                },
+                GenerateOptions {
+                    filter: Some(Box::new(Palindrome)),
+                    shard: Some(Shard { index: 1, count: 3 }),
+                    ..Default::default()
+                },
+            ]
+        };
+        for (options, again) in options().into_iter().zip(options()) {
+            let whole = RecordGenerator::new(options).generate(job);
+            let generator = RecordGenerator::new(again);
+            let chunks: Vec<_> = generator.chunks(job, 64).collect();
+            assert_eq!(chunks.concat(), whole.result.numbers);
+            assert!(chunks.len() > 1);
+            assert!(generator.predicted_count(&job) >= whole.result.count());
+            match generator.options().filter {
+                None => assert_eq!(generator.known_count(&job), Some(whole.result.count())),
+                Some(_) => assert_eq!(generator.known_count(&job), None),
+            }
+        }
+    }
+
+    #[test]
+    fn test_include_zero() {
+        let job = LineNumbers { a: 3, b: 5, end: 10 };
+        let options = |mode, descending| GenerateOptions {
+            mode,
+            descending,
+            include_zero: true,
+            ..Default::default()
+        };
+        assert_eq!(generate(GenerateOptions::default(), LineNumbers { a: 0, b: 0, end: 10 }), Vec::<u32>::new());
+        assert_eq!(generate(options(Mode::Or, false), job), vec![0, 3, 5, 6, 9, 10]);
+        assert_eq!(generate(options(Mode::Xor, false), job), vec![3, 5, 6, 9, 10]);
+        assert_eq!(generate(options(Mode::Multiset, true), job), vec![10, 9, 6, 5, 3, 0, 0]);
+        assert_eq!(generate(options(Mode::Or, false), LineNumbers { a: 0, b: 0, end: 0 }), Vec::<u32>::new());
+        for end in [0, 1] {
+            let below = Bound::Exclusive.resolve(LineNumbers { end, ..job });
+            assert_eq!(generate(options(Mode::Or, false), below), Vec::<u32>::new());
+            let counted = RecordGenerator::new(GenerateOptions { count_only: true, ..options(Mode::Or, false) });
+            assert_eq!(counted.generate(below).result.count, Some(0));
+        }
+        assert_eq!(generate(options(Mode::Or, false), Bound::Exclusive.resolve(LineNumbers { end: 4, ..job })), vec![0, 3]);
+        for mode in [Mode::Or, Mode::Xor, Mode::Multiset] {
+            let job = LineNumbers { a: 6, b: 10, end: 100_000 };
+            let listed = RecordGenerator::new(options(mode, false));
+            let record = listed.generate(job);
+            listed.cross_check(&record).unwrap();
+            assert_eq!(listed.generate_chunked(job, 3, None, &|_| {}), record);
+            assert_eq!(listed.chunks(job, 64).collect::<Vec<_>>().concat(), record.result.numbers);
+            let counted = RecordGenerator::new(GenerateOptions { count_only: true, ..options(mode, false) });
+            assert_eq!(counted.generate(job).result.count, Some(record.result.count()));
+        }
+        let shard = |index| GenerateOptions { shard: Some(Shard { index, count: 2 }), ..options(Mode::Or, false) };
+        assert_eq!(generate(shard(1), job), vec![0, 3, 5]);
+        assert_eq!(generate(shard(2), job), vec![6, 9, 10]);
+        assert_eq!(generate(shard(2), LineNumbers { end: 0, ..job }), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn test_stop_after() {
+        let job = LineNumbers { a: 6, b: 10, end: 100_000 };
+        for mode in [Mode::Or, Mode::Xor, Mode::Multiset] {
+            for (descending, include_zero) in [(false, false), (true, false), (false, true), (true, true)] {
+                let options = || GenerateOptions {
+                    mode,
+                    descending,
+                    include_zero,
+                    ..Default::default()
+                };
+                let whole = RecordGenerator::new(options()).generate(job).result.numbers;
+                for limit in [1, 2, 7, 3000, 1_000_000] {
+                    let stopped = RecordGenerator::new(GenerateOptions { stop_after: Some(limit), ..options() });
+                    let expected = &whole[..whole.len().min(limit as usize)];
+                    assert_eq!(stopped.generate(job).result.numbers, expected, "{:?} {} {}", mode, descending, limit);
+                    assert_eq!(stopped.generate_chunked(job, 3, None, &|_| {}).result.numbers, expected);
+                    assert_eq!(stopped.chunks(job, 64).collect::<Vec<_>>().concat(), expected);
+                    let counted = RecordGenerator::new(GenerateOptions { count_only: true, stop_after: Some(limit), ..options() });
+                    assert_eq!(counted.generate(job).result.count, Some(expected.len() as u64));
+                }
+            }
+        }
+        let palindromes = RecordGenerator::new(GenerateOptions {
+            filter: Some(Box::new(Palindrome)),
+            stop_after: Some(3),
+            ..Default::default()
+        });
+        assert_eq!(palindromes.generate(job).result.numbers, [6, 66, 222]);
+        let above = RecordGenerator::new(GenerateOptions {
+            stop_above: Some(20),
+            ..Default::default()
+        });
+        let record = above.generate(job);
+        assert_eq!((record.result.end, record.result.numbers), (100_000, vec![6, 10, 12, 18, 20]));
+    }
+
+    #[test]
+    fn test_describe() {
+        let job = LineNumbers { a: 3, b: 5, end: 100 };
+        let wheel = RecordGenerator::new(GenerateOptions {
+            strategy: Strategy::Wheel,
+            ..Default::default()
+        });
+        assert_eq!(wheel.describe(&job), "wheel (period 15, 7 residues)");
+        let long = LineNumbers { a: 65_521, b: 65_519, end: 100 };
+        assert!(wheel.describe(&long).ends_with("period too long for a wheel"));
+        let scalar = RecordGenerator::new(GenerateOptions {
+            force_scalar: true,
+            ..Default::default()
+        });
+        assert_eq!(scalar.describe(&job), "trial (scalar)");
+        let counted = RecordGenerator::new(GenerateOptions {
+            count_only: true,
+            ..Default::default()
+        });
+        assert_eq!(counted.describe(&job), "closed form");
+    }
+
+    #[test]
+    fn test_cross_check() {
+        let jobs = [(3, 5, 100_000), (9, 3, 50), (65_521, 65_519, 300_000), (0, 7, 50), (4, 4, 0)];
+        for (a, b, end) in jobs {
+            let job = LineNumbers { a, b, end };
+            for mode in [Mode::Or, Mode::Xor, Mode::Multiset] {
+                for (strategy, descending, count_only) in [(Strategy::Trial, false, false), (Strategy::Wheel, true, false), (Strategy::Wheel, false, true)] {
+                    let generator = RecordGenerator::new(GenerateOptions {
+                        strategy,
+                        mode,
+                        descending,
+                        count_only,
+                        shard: Some(Shard { index: 2, count: 3 }),
+                        ..Default::default()
+                    });
+                    let record = generator.generate(job);
+                    generator.cross_check(&record).unwrap_or_else(|error| panic!("{:?} {:?}: {}", job, mode, error));
+                }
+            }
+        }
+
+        let generator = RecordGenerator::new(GenerateOptions::default());
+        let mut record = generator.generate(LineNumbers { a: 3, b: 5, end: 100_000 });
+        record.result.numbers.retain(|&n| n != 99_990);
+        let error = generator.cross_check(&record).unwrap_err().to_string();
+        assert!(error.contains("misses 99990"), "{}", error);
+        record.result.numbers.extend([99_990, 99_991]);
+        let error = generator.cross_check(&record).unwrap_err().to_string();
+        assert!(error.contains("lists 99991"), "{}", error);
+    }
+}
@@ -0,0 +1,367 @@
+//! Parameter sweeps for systematic experiments over divisor pairs: the
+//! values of `a`, `b` and `end` are each given as a value, a list or a
+//! range, and every combination of them that meets the constraints, such
+//! as `a<b` or `gcd(a,b)==1`, becomes a job. The jobs of a sweep are run
+//! as one input whose lines each name their own output, laid out by their
+//! parameters as `a=<a>/b=<b>/end=<end>.<extension>`, see [`Sweep::output`].
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::jobfilter::JobFilter;
+use crate::number::parse_number;
+use crate::LineNumbers;
+
+/// Most combinations a sweep may have before its constraints are applied,
+/// so that a typo such as `--end 1..1e9` is refused rather than written
+/// out as a billion lines.
+pub const MAX_COMBINATIONS: u64 = 1_000_000;
+
+/// The values one parameter takes, in the order given: comma separated
+/// numbers and inclusive ranges `lo..hi`, optionally with a step,
+/// `lo..hi:step`, e.g. `2..10`, `3,5,7` or `1e6..1e7:1e6`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Values(pub Vec<u32>);
+
+impl FromStr for Values {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let number = |literal: &str| -> Result<u32> {
+            let value = parse_number(literal.trim())?;
+            u32::try_from(value).map_err(|_| anyhow!("{} is larger than {}", literal.trim(), u32::MAX))
+        };
+        let mut values = Vec::new();
+        for item in text.split(',') {
+            let Some((lo, rest)) = item.split_once("..") else {
+                values.push(number(item)?);
+                continue;
+            };
+            let (hi, step) = match rest.split_once(':') {
+                Some((hi, step)) => (number(hi)?, number(step)?),
+                None => (number(rest)?, 1),
+            };
+            let lo = number(lo)?;
+            if lo > hi || step == 0 {
+                bail!("Invalid range {}: expected lo..hi or lo..hi:step with lo <= hi and a positive step", item.trim());
+            }
+            // Counted before collecting, so that a huge range fails at once.
+            if (hi - lo) as u64 / step as u64 >= MAX_COMBINATIONS {
+                bail!("The range {} has more than {} values", item.trim(), MAX_COMBINATIONS);
+            }
+            values.extend((lo..=hi).step_by(step as usize));
+        }
+        Ok(Values(values))
+    }
+}
+
+/// A parameter of a job, as named in constraints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Param {
+    A,
+    B,
+    End,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Number(i128),
+    Param(Param),
+    /// `gcd(x, y)` or `lcm(x, y)`.
+    Call(&'static str, Box<Expr>, Box<Expr>),
+    /// An arithmetic operation, `+`, `-`, `*`, `/` or `%`.
+    Op(Box<Expr>, char, Box<Expr>),
+}
+
+impl Expr {
+    /// The value of the expression for `job`, `None` on a division by zero.
+    fn eval(&self, job: &LineNumbers) -> Option<i128> {
+        Some(match self {
+            Expr::Number(n) => *n,
+            Expr::Param(Param::A) => job.a as i128,
+            Expr::Param(Param::B) => job.b as i128,
+            Expr::Param(Param::End) => job.end as i128,
+            Expr::Call(name, x, y) => {
+                let (x, y) = (x.eval(job)?.unsigned_abs(), y.eval(job)?.unsigned_abs());
+                let gcd = gcd(x, y);
+                match *name {
+                    "gcd" => gcd as i128,
+                    _ if gcd == 0 => 0,
+                    _ => (x / gcd).checked_mul(y).and_then(|lcm| i128::try_from(lcm).ok())?,
+                }
+            }
+            Expr::Op(x, op, y) => {
+                let (x, y) = (x.eval(job)?, y.eval(job)?);
+                match op {
+                    '+' => x.checked_add(y)?,
+                    '-' => x.checked_sub(y)?,
+                    '*' => x.checked_mul(y)?,
+                    '/' => x.checked_div(y)?,
+                    _ => x.checked_rem(y)?,
+                }
+            }
+        })
+    }
+}
+
+fn gcd(mut x: u128, mut y: u128) -> u128 {
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    x
+}
+
+/// A condition on the parameters of a job: two expressions compared with
+/// `<`, `<=`, `>`, `>=`, `==` or `!=`. Expressions are built from `a`,
+/// `b`, `end`, numbers, `+ - * / %`, parentheses and `gcd(x, y)` and
+/// `lcm(x, y)`, e.g. `a<b`, `gcd(a,b)==1` or `end%lcm(a,b)==0`. Jobs for
+/// which a side divides by zero don't meet the constraint.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Constraint {
+    text: String,
+    left: Expr,
+    comparison: &'static str,
+    right: Expr,
+}
+
+impl Constraint {
+    pub fn holds(&self, job: &LineNumbers) -> bool {
+        let (Some(left), Some(right)) = (self.left.eval(job), self.right.eval(job)) else {
+            return false;
+        };
+        match self.comparison {
+            "<" => left < right,
+            "<=" => left <= right,
+            ">" => left > right,
+            ">=" => left >= right,
+            "==" => left == right,
+            _ => left != right,
+        }
+    }
+}
+
+impl FromStr for Constraint {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let invalid = |what: &str| anyhow!("Invalid constraint {:?}: {}", text, what);
+        let mut parser = Parser { text, pos: 0 };
+        let left = parser.sum().map_err(|err| invalid(&err))?;
+        let comparison = ["<=", ">=", "==", "!=", "<", ">"]
+            .into_iter()
+            .find(|comparison| parser.eat(comparison))
+            .ok_or_else(|| invalid("expected a comparison such as a<b"))?;
+        let right = parser.sum().map_err(|err| invalid(&err))?;
+        parser.skip_whitespace();
+        if parser.pos < text.len() {
+            return Err(invalid(&format!("unexpected {:?}", &text[parser.pos..])));
+        }
+        Ok(Constraint {
+            text: text.trim().to_string(),
+            left,
+            comparison,
+            right,
+        })
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// A recursive descent parser of the expressions of a constraint.
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes `token` if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.text[self.pos..].starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(op) = ['+', '-'].into_iter().find(|op| self.eat(&op.to_string())) {
+            expr = Expr::Op(Box::new(expr), op, Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.atom()?;
+        while let Some(op) = ['*', '/', '%'].into_iter().find(|op| self.eat(&op.to_string())) {
+            expr = Expr::Op(Box::new(expr), op, Box::new(self.atom()?));
+        }
+        Ok(expr)
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        if self.eat("(") {
+            let expr = self.sum()?;
+            return match self.eat(")") {
+                true => Ok(expr),
+                false => Err("expected )".to_string()),
+            };
+        }
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+        let word = &rest[..len];
+        self.pos += len;
+        match word {
+            "" => Err(match rest.is_empty() {
+                true => "expected a value at the end".to_string(),
+                false => format!("expected a value before {:?}", rest),
+            }),
+            "a" => Ok(Expr::Param(Param::A)),
+            "b" => Ok(Expr::Param(Param::B)),
+            "end" => Ok(Expr::Param(Param::End)),
+            "gcd" | "lcm" => {
+                let name = if word == "gcd" { "gcd" } else { "lcm" };
+                if !self.eat("(") {
+                    return Err(format!("expected ( after {}", name));
+                }
+                let x = self.sum()?;
+                if !self.eat(",") {
+                    return Err(format!("{} takes two values", name));
+                }
+                let y = self.sum()?;
+                if !self.eat(")") {
+                    return Err("expected )".to_string());
+                }
+                Ok(Expr::Call(name, Box::new(x), Box::new(y)))
+            }
+            _ => match parse_number(word) {
+                Ok(n) => Ok(Expr::Number(n as i128)),
+                Err(_) => Err(format!("unknown name {:?}; constraints use a, b and end", word)),
+            },
+        }
+    }
+}
+
+/// The jobs of a sweep: the combinations of the values of `a`, `b` and
+/// `end` meeting all constraints and the requirements of `filter`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sweep {
+    pub a: Values,
+    pub b: Values,
+    pub end: Values,
+    pub constraints: Vec<Constraint>,
+    pub filter: JobFilter,
+}
+
+impl Sweep {
+    /// The jobs, for every `a` every `b` and for every `b` every `end`, in
+    /// the order their values are given.
+    pub fn jobs(&self) -> Result<Vec<LineNumbers>> {
+        let combinations = [&self.a, &self.b, &self.end].iter().fold(1u64, |n, values| n.saturating_mul(values.0.len() as u64));
+        if combinations > MAX_COMBINATIONS {
+            bail!("The sweep has {} combinations, more than the {} a sweep may have", combinations, MAX_COMBINATIONS);
+        }
+        let mut jobs = Vec::new();
+        for &a in &self.a.0 {
+            for &b in &self.b.0 {
+                for &end in &self.end.0 {
+                    let job = LineNumbers { a, b, end };
+                    if self.filter.matches(&job) && self.constraints.iter().all(|constraint| constraint.holds(&job)) {
+                        jobs.push(job);
+                    }
+                }
+            }
+        }
+        Ok(jobs)
+    }
+
+    /// Where the record of `job` is written in `dir`.
+    pub fn output(dir: &Path, job: &LineNumbers, extension: &str) -> PathBuf {
+        dir.join(format!("a={}", job.a)).join(format!("b={}", job.b)).join(format!("end={}.{}", job.end, extension))
+    }
+
+    /// The input running `jobs`: a line per job naming its
+    /// [output](Sweep::output).
+    pub fn input(dir: &Path, jobs: &[LineNumbers], extension: &str) -> String {
+        jobs.iter()
+            .map(|job| format!("{} {} {} > {}\n", job.a, job.b, job.end, Sweep::output(dir, job, extension).display()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Constraint, Sweep, Values};
+    use crate::LineNumbers;
+
+    fn holds(constraint: &str, a: u32, b: u32, end: u32) -> bool {
+        constraint.parse::<Constraint>().unwrap().holds(&LineNumbers { a, b, end })
+    }
+
+    #[test]
+    fn test_values() {
+        assert_eq!("2..5".parse::<Values>().unwrap().0, [2, 3, 4, 5]);
+        assert_eq!("3, 5,7".parse::<Values>().unwrap().0, [3, 5, 7]);
+        assert_eq!("1e6..5e6:2e6,1e7".parse::<Values>().unwrap().0, [1_000_000, 3_000_000, 5_000_000, 10_000_000]);
+        assert_eq!("1e6".parse::<Values>().unwrap().0, [1_000_000]);
+        assert!("5..2".parse::<Values>().is_err());
+        assert!("1..10:0".parse::<Values>().is_err());
+        assert!("1..4e9".parse::<Values>().is_err());
+        assert!("x".parse::<Values>().is_err());
+    }
+
+    #[test]
+    fn test_constraints() {
+        assert!(holds("a<b", 2, 3, 10) && !holds("a < b", 3, 3, 10));
+        assert!(holds("gcd(a,b)==1", 4, 9, 10) && !holds("gcd(a, b) == 1", 4, 6, 10));
+        assert!(holds("end % lcm(a,b) == 0", 4, 6, 24));
+        assert!(holds("(a + b) * 2 >= 10", 2, 3, 0) && !holds("a + b * 2 >= 10", 3, 3, 0));
+        assert!(holds("b - a * 2 != 0", 2, 3, 0));
+        assert!(holds("end >= 1e6", 2, 3, 1_000_000));
+        // A division by zero meets no constraint.
+        assert!(!holds("end / a >= 0", 0, 3, 10));
+        for invalid in ["a", "a <", "a < c", "gcd(a) == 1", "(a < b", "a < b )"] {
+            assert!(invalid.parse::<Constraint>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_sweep_jobs() {
+        let sweep = Sweep {
+            a: "2..4".parse().unwrap(),
+            b: "3..5".parse().unwrap(),
+            end: "100,1000".parse().unwrap(),
+            constraints: vec!["a<b".parse().unwrap(), "gcd(a,b)==1".parse().unwrap()],
+            filter: Default::default(),
+        };
+        let jobs = sweep.jobs().unwrap();
+        let pairs: Vec<_> = jobs.iter().step_by(2).map(|job| (job.a, job.b)).collect();
+        assert_eq!(pairs, [(2, 3), (2, 5), (3, 4), (3, 5), (4, 5)]);
+        assert_eq!((jobs[0].end, jobs[1].end), (100, 1000));
+        let input = Sweep::input(Path::new("out"), &jobs[..1], "csv");
+        assert_eq!(input, format!("2 3 100 > {}\n", Path::new("out/a=2/b=3/end=100.csv").display()));
+
+        let primes = Sweep { filter: "a-prime,b-prime".parse().unwrap(), ..sweep.clone() };
+        let pairs: Vec<_> = primes.jobs().unwrap().iter().step_by(2).map(|job| (job.a, job.b)).collect();
+        assert_eq!(pairs, [(2, 3), (2, 5), (3, 5)]);
+
+        let huge = Sweep { end: "1..999999".parse().unwrap(), ..sweep };
+        assert!(huge.jobs().is_err());
+    }
+}
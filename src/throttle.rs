@@ -0,0 +1,133 @@
+//! Running on shared machines without starving their other work, for
+//! `--nice`, `--throttle` and `--threads auto`.
+
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use crate::audit;
+
+/// The share of a CPU each generator thread may take: after working for a
+/// while, a thread sleeps long enough that its work takes that share of the
+/// time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Throttle {
+    share: f64,
+}
+
+impl Throttle {
+    /// How long to sleep after working for `worked`.
+    pub fn pause(&self, worked: Duration) -> Duration {
+        worked.mul_f64((1.0 - self.share) / self.share)
+    }
+
+    /// Sleeps for [`Throttle::pause`] of `worked`.
+    pub fn pace(&self, worked: Duration) {
+        let pause = self.pause(worked);
+        if !pause.is_zero() {
+            thread::sleep(pause);
+        }
+    }
+}
+
+impl FromStr for Throttle {
+    type Err = anyhow::Error;
+
+    /// Parses a percentage from 1 to 100 such as `50%`; the `%` is optional.
+    fn from_str(s: &str) -> Result<Self> {
+        let percent = s.strip_suffix('%').unwrap_or(s);
+        match percent.parse::<u32>() {
+            Ok(percent @ 1..=100) => Ok(Throttle {
+                share: percent as f64 / 100.0,
+            }),
+            _ => Err(anyhow!("Invalid throttle: {} (expected a percentage from 1% to 100%)", s)),
+        }
+    }
+}
+
+#[cfg(unix)]
+extern "C" {
+    fn setpriority(which: i32, who: u32, prio: i32) -> i32;
+}
+
+/// The niceness `--nice` runs at, the default of nice(1).
+pub const NICENESS: i32 = 10;
+
+/// Lowers the scheduling priority of the calling thread and of the threads
+/// it starts from then on to `niceness`, from 0 to 19.
+#[cfg(unix)]
+pub fn lower_priority(niceness: i32) -> Result<()> {
+    const PRIO_PROCESS: i32 = 0;
+    // SAFETY: setpriority only reads its arguments.
+    if unsafe { setpriority(PRIO_PROCESS, 0, niceness) } != 0 {
+        return Err(anyhow!(std::io::Error::last_os_error()).context(format!("Failed to set niceness {}", niceness)));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn lower_priority(_niceness: i32) -> Result<()> {
+    anyhow::bail!("--nice is not supported on this platform")
+}
+
+/// The CPUs the cgroup of the process may use, from `cpu.max` of cgroup v2
+/// or the CFS quota of cgroup v1, rounded up. `None` without a limit.
+pub fn cgroup_cpu_limit() -> Option<usize> {
+    let v2 = || {
+        let text = audit::read_to_string(Path::new("/sys/fs/cgroup/cpu.max")).ok()?;
+        let (quota, period) = text.trim().split_once(' ')?;
+        cpu_limit(quota, period)
+    };
+    let v1 = || {
+        let quota = audit::read_to_string(Path::new("/sys/fs/cgroup/cpu/cpu.cfs_quota_us")).ok()?;
+        let period = audit::read_to_string(Path::new("/sys/fs/cgroup/cpu/cpu.cfs_period_us")).ok()?;
+        cpu_limit(quota.trim(), period.trim())
+    };
+    v2().or_else(v1)
+}
+
+/// The CPUs a quota of `quota` microseconds every `period` allows. A quota
+/// of `max` or `-1` is no limit.
+fn cpu_limit(quota: &str, period: &str) -> Option<usize> {
+    let quota: u64 = quota.parse().ok()?;
+    let period: u64 = period.parse().ok().filter(|&period| period > 0)?;
+    Some(quota.div_ceil(period).max(1) as usize)
+}
+
+/// The threads to run for `--threads auto`: one per CPU the process may
+/// use, within the CPUs of the machine and the limit of its cgroup.
+pub fn default_threads() -> usize {
+    let cpus = thread::available_parallelism().map_or(1, |cpus| cpus.get());
+    cgroup_cpu_limit().map_or(cpus, |limit| limit.min(cpus))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{cpu_limit, Throttle};
+
+    #[test]
+    fn test_throttle() {
+        let half: Throttle = "50%".parse().unwrap();
+        assert_eq!(half.pause(Duration::from_millis(20)), Duration::from_millis(20));
+        let quarter: Throttle = "25".parse().unwrap();
+        assert_eq!(quarter.pause(Duration::from_millis(20)), Duration::from_millis(60));
+        assert_eq!("100%".parse::<Throttle>().unwrap().pause(Duration::from_secs(1)), Duration::ZERO);
+        for invalid in ["0%", "101%", "half", "-5%"] {
+            assert!(invalid.parse::<Throttle>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_cpu_limit() {
+        assert_eq!(cpu_limit("200000", "100000"), Some(2));
+        assert_eq!(cpu_limit("150000", "100000"), Some(2));
+        assert_eq!(cpu_limit("10000", "100000"), Some(1));
+        assert_eq!(cpu_limit("max", "100000"), None);
+        assert_eq!(cpu_limit("-1", "100000"), None);
+    }
+}
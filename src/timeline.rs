@@ -0,0 +1,249 @@
+//! Multiples on a time axis for `--time`: `a` and `b` are intervals such as
+//! `90s` and `7m`, `end` is a duration or an end timestamp, and the
+//! multiples are the timestamps after a start time that fall on either
+//! interval.
+//!
+//! Everything is counted in whole seconds from the start, so a line is
+//! generated like any other and only written as timestamps.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::formatter::{self, FormatterRegistry, Render};
+use crate::input::{self, InputParserRegistry};
+use crate::schedule::format_utc;
+
+/// Seconds per duration unit.
+const UNITS: [(char, u64); 5] = [('w', 604_800), ('d', 86_400), ('h', 3600), ('m', 60), ('s', 1)];
+
+/// Parses a duration in seconds: a plain number, or numbers with units
+/// such as `90s`, `7m` or `1h30m` (`w`, `d`, `h`, `m` and `s`).
+pub fn parse_duration(text: &str) -> Result<u32> {
+    let invalid = || anyhow!("Invalid duration: {:?} (expected e.g. 90s, 7m or 1h30m)", text);
+    if text.is_empty() {
+        return Err(invalid());
+    }
+    if text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return text.parse().map_err(|_| anyhow!("Duration too long: {:?}", text));
+    }
+    let mut seconds = 0u64;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let unit = rest[digits..].chars().next().ok_or_else(invalid)?;
+        let (_, size) = UNITS.iter().find(|(name, _)| *name == unit).ok_or_else(invalid)?;
+        let count: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        seconds = count.checked_mul(*size).and_then(|part| seconds.checked_add(part)).ok_or_else(invalid)?;
+        rest = &rest[digits + 1..];
+    }
+    u32::try_from(seconds).map_err(|_| anyhow!("Duration too long: {:?} (at most about 136 years)", text))
+}
+
+/// Writes `seconds` with the largest units that divide it, e.g. `1m30s`.
+pub fn format_duration(seconds: u32) -> String {
+    if seconds == 0 {
+        return String::from("0s");
+    }
+    let mut rest = seconds as u64;
+    let mut text = String::new();
+    // Weeks are written as days, which reads better for short spans.
+    for (name, size) in &UNITS[1..] {
+        if rest >= *size {
+            text.push_str(&format!("{}{}", rest / size, name));
+            rest %= size;
+        }
+    }
+    text
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parses a UTC timestamp into Unix seconds: `YYYY-MM-DD`, optionally
+/// followed by `THH:MM` or `THH:MM:SS` and `Z` or an offset such as
+/// `+02:00`. Timestamps before 1970 aren't supported.
+pub fn parse_timestamp(text: &str) -> Result<u64> {
+    let invalid = || anyhow!("Invalid timestamp: {:?} (expected e.g. 2026-01-31T12:00:00Z)", text);
+    let number = |digits: &str| -> Result<u32> {
+        match digits.bytes().all(|byte| byte.is_ascii_digit()) && !digits.is_empty() {
+            true => digits.parse().map_err(|_| invalid()),
+            false => Err(invalid()),
+        }
+    };
+    let (date, time) = text.split_once('T').unwrap_or((text, ""));
+    let [year, month, day] = date.split('-').collect::<Vec<_>>()[..] else {
+        return Err(invalid());
+    };
+    let (year, month, day) = (number(year)?, number(month)?, number(day)?);
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    let month_days = [31, if leap { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    if year < 1970 || !(1..=12).contains(&month) || day == 0 || day > month_days[month as usize - 1] {
+        return Err(invalid());
+    }
+    let (clock, offset) = match time.find(['Z', '+', '-']) {
+        Some(at) => time.split_at(at),
+        None => (time, ""),
+    };
+    let mut seconds = 0;
+    if !time.is_empty() {
+        let parts: Vec<u32> = clock.split(':').map(number).collect::<Result<_>>()?;
+        let (hours, minutes, secs) = match parts[..] {
+            [hours, minutes] => (hours, minutes, 0),
+            [hours, minutes, secs] => (hours, minutes, secs),
+            _ => return Err(invalid()),
+        };
+        if hours > 23 || minutes > 59 || secs > 59 {
+            return Err(invalid());
+        }
+        seconds = (hours * 3600 + minutes * 60 + secs) as i64;
+    }
+    let offset = match offset {
+        "" | "Z" => 0,
+        _ => {
+            let (sign, rest) = offset.split_at(1);
+            let (hours, minutes) = rest.split_once(':').ok_or_else(invalid)?;
+            let (hours, minutes) = (number(hours)?, number(minutes)?);
+            if hours > 23 || minutes > 59 {
+                return Err(invalid());
+            }
+            let offset = (hours * 3600 + minutes * 60) as i64;
+            if sign == "-" {
+                -offset
+            } else {
+                offset
+            }
+        }
+    };
+    let time = days_from_civil(year as i64, month, day) * 86_400 + seconds - offset;
+    u64::try_from(time).map_err(|_| anyhow!("Timestamp before 1970: {:?}", text))
+}
+
+/// The seconds from `start` to the `end` field of a line: a duration, or a
+/// timestamp no earlier than `start`.
+fn parse_end(text: &str, start: u64) -> Result<u32> {
+    // Durations never contain a `-`, dates always do.
+    if !text.contains('-') {
+        return parse_duration(text);
+    }
+    let end = parse_timestamp(text)?;
+    let Some(seconds) = end.checked_sub(start) else {
+        bail!("{} is before the start, {}", text, format_utc(start));
+    };
+    u32::try_from(seconds).with_context(|| format!("{} is more than about 136 years after the start", text))
+}
+
+/// The parsers of `--time` input: the `text` and `csv` layouts with two
+/// intervals and a duration or end timestamp, counted from `start`.
+pub fn parsers(start: u64) -> InputParserRegistry {
+    input::field_parsers(Arc::new(move |field, text| match field {
+        2 => parse_end(text, start),
+        _ => parse_duration(text),
+    }))
+}
+
+/// The formatters of `--time` output: `text`, `json`, `ndjson` and `csv`
+/// with the intervals as durations and the end and multiples as
+/// timestamps.
+pub fn formatters(start: u64) -> FormatterRegistry {
+    formatter::rendered_formatters(render(start))
+}
+
+/// Writes intervals as durations and values as timestamps from `start`, as
+/// the `--time` formatters do.
+pub fn render(start: u64) -> Arc<dyn Render> {
+    Arc::new(Timeline { start })
+}
+
+/// Writes seconds from `start` as timestamps.
+struct Timeline {
+    start: u64,
+}
+
+impl Render for Timeline {
+    fn divisor(&self, n: u32) -> String {
+        format_duration(n)
+    }
+
+    fn value(&self, n: u32) -> String {
+        format_utc(self.start + n as u64)
+    }
+
+    fn quoted(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{formatters, parse_duration, parse_timestamp, parsers, render};
+    use crate::formatter::rendered_text;
+    use crate::schedule::format_utc;
+    use crate::{generate_record, LineNumbers};
+
+    #[test]
+    fn test_durations_and_timestamps() {
+        assert_eq!(parse_duration("90s").unwrap(), 90);
+        assert_eq!(parse_duration("7m").unwrap(), 420);
+        assert_eq!(parse_duration("1h30m").unwrap(), 5400);
+        assert_eq!(parse_duration("2w1d").unwrap(), 15 * 86_400);
+        assert_eq!(parse_duration("45").unwrap(), 45);
+        for text in ["", "m", "1x", "1.5h", "-1s", "8000w"] {
+            assert!(parse_duration(text).is_err(), "{}", text);
+        }
+        assert_eq!(super::format_duration(5400), "1h30m");
+        assert_eq!(super::format_duration(90), "1m30s");
+        assert_eq!(super::format_duration(14 * 86_400 + 1), "14d1s");
+
+        assert_eq!(parse_timestamp("1970-01-01").unwrap(), 0);
+        assert_eq!(parse_timestamp("2026-10-14T12:30:00Z").unwrap(), 1_791_981_000);
+        assert_eq!(parse_timestamp("2026-10-14T14:30+02:00").unwrap(), 1_791_981_000);
+        assert_eq!(parse_timestamp("2024-02-29T00:00:00Z").unwrap(), 1_709_164_800);
+        for text in ["2023-02-29", "2026-13-01", "2026-10-14T24:00", "1969-12-31", "2026-10-14T12", "14.10.2026"] {
+            assert!(parse_timestamp(text).is_err(), "{}", text);
+        }
+        for time in [0, 951_782_400, 1_791_981_000, 4_102_444_799] {
+            assert_eq!(parse_timestamp(&format_utc(time)).unwrap(), time);
+        }
+    }
+
+    #[test]
+    fn test_timeline_round_trip() {
+        let start = parse_timestamp("2026-10-14T12:00:00Z").unwrap();
+        let parsers = parsers(start);
+        let text = "90s 7m 15m\n1h 1d 2026-10-15T00:00:00Z\n";
+        let jobs: Vec<LineNumbers> = parsers
+            .get("text")
+            .unwrap()
+            .parse(Box::new(Cursor::new(text)))
+            .collect::<anyhow::Result<_>>()
+            .unwrap();
+        assert_eq!(jobs, vec![LineNumbers { a: 90, b: 420, end: 900 }, LineNumbers { a: 3600, b: 86_400, end: 43_200 }]);
+        let before = parsers.get("text").unwrap().parse(Box::new(Cursor::new("1m 2m 2026-10-13\n"))).next().unwrap();
+        assert!(format!("{:#}", before.unwrap_err()).contains("before the start"));
+
+        let record = generate_record(jobs[0]);
+        let echoed = rendered_text(&*render(start), &record.result);
+        assert!(echoed.starts_with("2026-10-14T12:15:00Z:2026-10-14T12:01:30Z 2026-10-14T12:03:00Z "), "{}", echoed);
+        let mut formatter = formatters(start).create("ndjson").unwrap();
+        let mut out = Vec::new();
+        formatter.write_record(&mut out, &record).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"schema\":1,\"index\":0,\"a\":\"1m30s\",\"b\":\"7m\",\"end\":\"2026-10-14T12:15:00Z\",\"count\":12,\"numbers\":[\
+             \"2026-10-14T12:01:30Z\",\"2026-10-14T12:03:00Z\",\"2026-10-14T12:04:30Z\",\"2026-10-14T12:06:00Z\",\
+             \"2026-10-14T12:07:00Z\",\"2026-10-14T12:07:30Z\",\"2026-10-14T12:09:00Z\",\"2026-10-14T12:10:30Z\",\
+             \"2026-10-14T12:12:00Z\",\"2026-10-14T12:13:30Z\",\"2026-10-14T12:14:00Z\",\"2026-10-14T12:15:00Z\"]}\n"
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Precomputed residue patterns of divisor pairs.
+//!
+//! Whether `n` is selected for a pair `(a, b)` depends only on `n` modulo
+//! `lcm(a, b)`. A [`Wheel`] holds the selected residues of one such period
+//! and tiles them across a range, so lines sharing a pair share the work.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::formula::Mode;
+
+fn gcd(mut x: u64, mut y: u64) -> u64 {
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    x
+}
+
+/// The selected residues of one period of a divisor pair.
+#[derive(Clone, Debug)]
+pub struct Wheel {
+    period: u32,
+    /// Selected values in `1..=period`, ascending; a value selected twice
+    /// appears twice.
+    residues: Vec<u32>,
+}
+
+impl Wheel {
+    /// Longest period a wheel is built for; longer ones would cost more to
+    /// build and hold than testing every number.
+    pub const MAX_PERIOD: u32 = 1 << 20;
+
+    /// Builds the wheel of `(a, b)` in `mode`, or returns `None` when its
+    /// period exceeds [`Wheel::MAX_PERIOD`]. A divisor of 0 has no multiples.
+    pub fn new(a: u32, b: u32, mode: Mode) -> Option<Wheel> {
+        let period = [a, b]
+            .iter()
+            .filter(|&&d| d != 0)
+            .fold(1u64, |lcm, &d| lcm / gcd(lcm, d as u64) * d as u64);
+        if period > Wheel::MAX_PERIOD as u64 {
+            return None;
+        }
+        let period = period as u32;
+        let residues = (1..=period)
+            .flat_map(|n: u32| {
+                let hits = n.is_multiple_of(a) as u8 + n.is_multiple_of(b) as u8;
+                std::iter::repeat_n(n, mode.copies(hits))
+            })
+            .collect();
+        Some(Wheel { period, residues })
+    }
+
+    pub fn period(&self) -> u32 {
+        self.period
+    }
+
+    /// The selected numbers in `1..=end`, ascending; `.rev()` walks them
+    /// from `end` downward.
+    pub fn numbers(&self, end: u32) -> impl DoubleEndedIterator<Item = u32> + '_ {
+        let period = self.period as u64;
+        let full = end as u64 / period;
+        let partial = self.residues.partition_point(|&r| r as u64 <= end as u64 % period);
+        (0..full)
+            .flat_map(move |cycle| self.residues.iter().map(move |&r| (cycle * period + r as u64) as u32))
+            .chain(self.residues[..partial].iter().map(move |&r| (full * period + r as u64) as u32))
+    }
+
+    /// The selected numbers in `first..=end`, ascending, starting from the
+    /// period holding `first` rather than walking up to it.
+    pub fn numbers_from(&self, first: u32, end: u32) -> impl DoubleEndedIterator<Item = u32> + '_ {
+        let period = self.period as u64;
+        let (first, end) = (first as u64, end as u64);
+        (first.saturating_sub(1) / period..=end / period)
+            .flat_map(move |cycle| self.residues.iter().map(move |&r| cycle * period + r as u64))
+            .filter(move |&n| first <= n && n <= end)
+            .map(|n| n as u32)
+    }
+
+    /// The number of selected numbers in `1..=end`.
+    pub fn count(&self, end: u32) -> u64 {
+        let period = self.period as u64;
+        let partial = self.residues.partition_point(|&r| r as u64 <= end as u64 % period);
+        end as u64 / period * self.residues.len() as u64 + partial as u64
+    }
+}
+
+/// A divisor pair and mode, together identifying a wheel.
+type WheelKey = (u32, u32, Mode);
+
+/// Wheels built so far, shared between the lines and worker threads of a run.
+/// Pairs without a wheel are remembered as `None`.
+#[derive(Default)]
+pub struct WheelCache {
+    wheels: Mutex<HashMap<WheelKey, Option<Arc<Wheel>>>>,
+}
+
+impl WheelCache {
+    /// Returns the wheel of `(a, b)` in `mode`, building it on first use.
+    pub fn get(&self, a: u32, b: u32, mode: Mode) -> Option<Arc<Wheel>> {
+        let mut wheels = self.wheels.lock().unwrap();
+        wheels
+            .entry((a, b, mode))
+            .or_insert_with(|| Wheel::new(a, b, mode).map(Arc::new))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Wheel, WheelCache};
+    use crate::formula::Mode;
+
+    #[test]
+    fn test_wheel_matches_trial_division() {
+        for (a, b) in [(3, 5), (4, 6), (7, 7), (0, 3), (1, 2), (0, 0), (12, 18)] {
+            for mode in [Mode::Or, Mode::Xor, Mode::Multiset] {
+                let wheel = Wheel::new(a, b, mode).unwrap();
+                for end in [0, 1, 29, 30, 31, 100] {
+                    let expected: Vec<u32> = (1..=end)
+                        .flat_map(|n: u32| {
+                            let hits = n.is_multiple_of(a) as u8 + n.is_multiple_of(b) as u8;
+                            std::iter::repeat_n(n, mode.copies(hits))
+                        })
+                        .collect();
+                    assert_eq!(wheel.numbers(end).collect::<Vec<_>>(), expected, "{} {} {:?} {}", a, b, mode, end);
+                    assert_eq!(wheel.count(end), expected.len() as u64);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_wheel_reversed_and_near_u32_max() {
+        let wheel = Wheel::new(3, 5, Mode::Or).unwrap();
+        assert_eq!(wheel.numbers(20).rev().collect::<Vec<_>>(), vec![20, 18, 15, 12, 10, 9, 6, 5, 3]);
+        assert_eq!(wheel.numbers(u32::MAX).next_back(), Some(u32::MAX));
+        assert_eq!(wheel.numbers_from(10, 20).collect::<Vec<_>>(), vec![10, 12, 15, 18, 20]);
+        assert_eq!(wheel.numbers_from(16, 20).rev().collect::<Vec<_>>(), vec![20, 18]);
+        assert_eq!(wheel.numbers_from(0, 6).collect::<Vec<_>>(), wheel.numbers(6).collect::<Vec<_>>());
+        assert_eq!(wheel.numbers_from(u32::MAX - 1, u32::MAX).collect::<Vec<_>>(), vec![u32::MAX]);
+        assert_eq!(wheel.numbers_from(21, 20).count(), 0);
+    }
+
+    #[test]
+    fn test_wheel_cache_shares_wheels() {
+        let cache = WheelCache::default();
+        let first = cache.get(3, 5, Mode::Or).unwrap();
+        assert!(std::sync::Arc::ptr_eq(&first, &cache.get(3, 5, Mode::Or).unwrap()));
+        assert!(cache.get(65_521, 65_519, Mode::Or).is_none());
+    }
+}
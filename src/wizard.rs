@@ -0,0 +1,229 @@
+//! The `wizard` command: builds an input file by asking for the mode, the
+//! lines and the output format one question at a time, checking every
+//! answer and previewing every line, and tells the command generating it.
+
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::degenerate;
+use crate::formatter::{self, FormatterRegistry};
+use crate::formula::{self, Mode};
+use crate::number;
+use crate::LineNumbers;
+
+/// How many multiples the preview of a line shows at either end.
+const PREVIEW_SIZE: u64 = 5;
+
+/// What the wizard was told.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Answers {
+    pub mode: Mode,
+    pub jobs: Vec<LineNumbers>,
+    pub output_format: String,
+    /// The input file to write.
+    pub input: PathBuf,
+    /// The file the command generates.
+    pub output: PathBuf,
+}
+
+impl Answers {
+    /// The contents of the input file, one `a b end` line per job.
+    pub fn input_file(&self) -> String {
+        self.jobs.iter().map(|job| format!("{} {} {}\n", job.a, job.b, job.end)).collect()
+    }
+
+    /// The command line generating the input file as `program`, leaving out
+    /// options at their defaults.
+    pub fn command_line(&self, program: &str) -> String {
+        let mut line = String::from(program);
+        if self.mode != Mode::Or {
+            let _ = write!(line, " --mode {}", self.mode.name());
+        }
+        if self.output_format != "text" {
+            let _ = write!(line, " --output-format {}", self.output_format);
+        }
+        let _ = write!(line, " {} {}", shell_word(&self.input), shell_word(&self.output));
+        line
+    }
+}
+
+/// `path` as one word of a POSIX shell command line.
+fn shell_word(path: &Path) -> String {
+    let text = path.display().to_string();
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./+,:=@%".contains(c);
+    if !text.is_empty() && text.chars().all(plain) {
+        return text;
+    }
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// The multiples of `job` at both ends of its range and their count, such
+/// as `47 multiples: 3 5 6 9 10 … 93 95 96 99 100`.
+pub fn preview(job: &LineNumbers, mode: Mode) -> String {
+    let count = formula::count(job, mode);
+    let join = |numbers: Vec<u32>| numbers.iter().map(u32::to_string).collect::<Vec<_>>().join(" ");
+    let noun = if count == 1 { "multiple" } else { "multiples" };
+    match count {
+        0 => String::from("no multiples"),
+        _ if count <= 2 * PREVIEW_SIZE => format!("{} {}: {}", count, noun, join(formula::page(job, mode, 0, count))),
+        _ => format!(
+            "{} {}: {} … {}",
+            count,
+            noun,
+            join(formula::page(job, mode, 0, PREVIEW_SIZE)),
+            join(formula::page(job, mode, count - PREVIEW_SIZE, PREVIEW_SIZE))
+        ),
+    }
+}
+
+fn positive(answer: &str) -> Result<u32> {
+    match number::parse_u32(answer)? {
+        0 => bail!("Enter a number of at least 1"),
+        n => Ok(n),
+    }
+}
+
+fn yes(answer: &str) -> Result<bool> {
+    match answer.to_ascii_lowercase().as_str() {
+        "y" | "yes" => Ok(true),
+        "n" | "no" => Ok(false),
+        _ => bail!("Answer y or n"),
+    }
+}
+
+/// Asks the questions on `out` and reads the answers from `input`.
+pub struct Wizard<R, W> {
+    input: R,
+    out: W,
+}
+
+impl<R: BufRead, W: Write> Wizard<R, W> {
+    pub fn new(input: R, out: W) -> Self {
+        Wizard { input, out }
+    }
+
+    /// Asks `question` until `parse` accepts the answer, which is `default`
+    /// when it is left empty.
+    fn ask<T>(&mut self, question: &str, default: Option<&str>, parse: impl Fn(&str) -> Result<T>) -> Result<T> {
+        loop {
+            match default {
+                Some(default) => write!(self.out, "{} [{}]: ", question, default)?,
+                None => write!(self.out, "{}: ", question)?,
+            }
+            self.out.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line).context("Failed to read the answer")? == 0 {
+                bail!("The wizard was ended before it was done");
+            }
+            let answer = match line.trim() {
+                "" => default.unwrap_or(""),
+                answer => answer,
+            };
+            match parse(answer) {
+                Ok(value) => return Ok(value),
+                Err(err) => writeln!(self.out, "  {}, try again", err)?,
+            }
+        }
+    }
+
+    /// Asks for everything an input file and its command line need. The
+    /// path of the input file is only asked for without `input`.
+    pub fn run(&mut self, formats: &FormatterRegistry, input: Option<PathBuf>) -> Result<Answers> {
+        writeln!(self.out, "Building an input file. Leave an answer empty to take the default in brackets.\n")?;
+        let mode = self.ask("Mode: or (multiples of a or b), xor (of exactly one) or multiset (of both twice)", Some("or"), |answer| {
+            answer.parse::<Mode>()
+        })?;
+        let mut jobs = Vec::new();
+        loop {
+            writeln!(self.out, "\nLine {}", jobs.len() + 1)?;
+            let a = self.ask("  Divisor a", None, positive)?;
+            let b = self.ask("  Divisor b", None, positive)?;
+            let end = self.ask("  End of the range", None, positive)?;
+            let job = LineNumbers { a, b, end };
+            if let Some(reason) = degenerate::check(&job, mode) {
+                writeln!(self.out, "  Note: {}", reason)?;
+            }
+            writeln!(self.out, "  {}", preview(&job, mode))?;
+            jobs.push(job);
+            if !self.ask("Add another line? (y/n)", Some("n"), yes)? {
+                break;
+            }
+        }
+
+        let names: Vec<_> = formats.names().collect();
+        let question = format!("\nOutput format: {}", names.join(", "));
+        let output_format = self.ask(&question, Some("text"), |answer| {
+            formats.create(answer)?;
+            Ok(answer.to_string())
+        })?;
+        let input = match input {
+            Some(input) => input,
+            None => loop {
+                let path = self.ask("Input file to write", Some("jobs.txt"), |answer| Ok(PathBuf::from(answer)))?;
+                if !path.exists() || self.ask(&format!("{} exists, overwrite it? (y/n)", path.display()), Some("n"), yes)? {
+                    break path;
+                }
+            },
+        };
+        let default_output = format!("results.{}", formatter::extension(&output_format));
+        let output = self.ask("Results file the command writes", Some(&default_output), |answer| match answer {
+            _ if Path::new(answer) == input => Err(anyhow!("The results can't overwrite the input")),
+            _ => Ok(PathBuf::from(answer)),
+        })?;
+        Ok(Answers {
+            mode,
+            jobs,
+            output_format,
+            input,
+            output,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::path::{Path, PathBuf};
+
+    use super::{preview, shell_word, Answers, Wizard};
+    use crate::formatter::FormatterRegistry;
+    use crate::formula::Mode;
+    use crate::LineNumbers;
+
+    fn run(answers: &str, input: Option<PathBuf>) -> (anyhow::Result<Answers>, String) {
+        let mut out = Vec::new();
+        let answers = Wizard::new(Cursor::new(answers), &mut out).run(&FormatterRegistry::with_builtins(), input);
+        (answers, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_wizard() {
+        let (answers, out) = run("xor\n3\nzero\n0\n5\n100\ny\n7\n7\n50\n\nyaml\njson\n\n", Some(PathBuf::from("jobs.txt")));
+        let answers = answers.unwrap();
+        assert_eq!(answers.mode, Mode::Xor);
+        assert_eq!(answers.jobs, [LineNumbers { a: 3, b: 5, end: 100 }, LineNumbers { a: 7, b: 7, end: 50 }]);
+        assert_eq!(answers.output, PathBuf::from("results.json"));
+        assert_eq!(answers.input_file(), "3 5 100\n7 7 50\n");
+        assert_eq!(answers.command_line("multiples"), "multiples --mode xor --output-format json jobs.txt results.json");
+        assert!(out.contains("  Invalid number literal: \"zero\", try again\n"));
+        assert!(out.contains("  Enter a number of at least 1, try again\n"));
+        assert!(out.contains("  Note: both divisors are 7\n  no multiples\n"));
+        assert!(out.contains("  Unknown output format: yaml"));
+
+        let (answers, _) = run("\n3\n5\n100\n\n\nresults.txt\nout.txt\n", Some(PathBuf::from("results.txt")));
+        assert_eq!(answers.unwrap().command_line("multiples"), "multiples results.txt out.txt");
+        assert!(run("or\n3\n", None).0.is_err());
+    }
+
+    #[test]
+    fn test_preview() {
+        assert_eq!(preview(&LineNumbers { a: 3, b: 5, end: 100 }, Mode::Or), "47 multiples: 3 5 6 9 10 … 93 95 96 99 100");
+        assert_eq!(preview(&LineNumbers { a: 3, b: 5, end: 10 }, Mode::Or), "5 multiples: 3 5 6 9 10");
+        assert_eq!(preview(&LineNumbers { a: 3, b: 5, end: 4 }, Mode::Or), "1 multiple: 3");
+        assert_eq!(shell_word(Path::new("my jobs.txt")), "'my jobs.txt'");
+        assert_eq!(shell_word(Path::new("data/jobs.txt")), "data/jobs.txt");
+    }
+}